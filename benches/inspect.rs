@@ -0,0 +1,159 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use ethers::types::{Trace, TxHash};
+use mev_inspect::{
+    inspectors::{
+        Aave, AaveV2, Balancer, BalancerV2, Compound, Curve, EventLogs, FlashLoans, OneInch,
+        Seaport, UniswapV2, UniswapV3, UniswapV3Positions, WethInspector, ZeroEx, ERC1155, ERC20,
+        ERC721,
+    },
+    reducers::{ArbitrageReducer, JitReducer, LiquidationReducer, TradeReducer},
+    types::Inspection,
+    BatchInspector,
+};
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+const TXS: usize = 200;
+
+/// Counts the allocations, so that the benchmarks can report how many an
+/// inspection makes
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn inspector() -> BatchInspector {
+    BatchInspector::new(
+        vec![
+            Box::new(ERC721::new()),
+            Box::new(ERC1155::new()),
+            Box::new(Seaport::new()),
+            Box::new(ZeroEx::new()),
+            Box::new(WethInspector::new()),
+            Box::new(ERC20::new()),
+            Box::new(FlashLoans::new()),
+            Box::new(OneInch::new()),
+            Box::new(Balancer::new()),
+            Box::new(BalancerV2::new()),
+            Box::new(UniswapV2::new()),
+            Box::new(UniswapV3::new()),
+            Box::new(UniswapV3Positions::new()),
+            Box::new(Curve::new(vec![])),
+            Box::new(Aave::new()),
+            Box::new(AaveV2::new()),
+            Box::new(Compound::new(vec![])),
+            Box::new(EventLogs::new()),
+        ],
+        vec![
+            Box::new(LiquidationReducer::new()),
+            Box::new(TradeReducer::new()),
+            Box::new(ArbitrageReducer::new()),
+            Box::new(JitReducer::new()),
+        ],
+    )
+}
+
+/// The unclassified inspections of a block, whose txs are repeated until
+/// there are `TXS` of them
+fn block() -> Vec<Inspection> {
+    let traces: Vec<Trace> =
+        serde_json::from_str(include_str!("../res/11017338.trace.json")).unwrap();
+    let inspections = BatchInspector::new(vec![], vec![]).inspect_many(traces);
+    inspections
+        .iter()
+        .cycle()
+        .take(TXS)
+        .enumerate()
+        .map(|(i, inspection)| Inspection {
+            hash: TxHash::from_low_u64_be(i as u64),
+            ..inspection.clone()
+        })
+        .collect()
+}
+
+fn inspect(c: &mut Criterion) {
+    let inspector = inspector();
+    let block = block();
+
+    let mut group = c.benchmark_group("inspect_200_txs");
+    group.bench_function("serial", |b| {
+        b.iter_batched(
+            || block.clone(),
+            |mut inspections| {
+                for inspection in inspections.iter_mut() {
+                    inspector.inspect(inspection);
+                    inspector.reduce(inspection);
+                    inspection.prune();
+                }
+                inspections
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("parallel", |b| {
+        b.iter_batched(
+            || block.clone(),
+            |mut inspections| {
+                inspector.par_inspect(&mut inspections);
+                inspections
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+/// The unclassified inspection of a Curve arbitrage with 351 calls, which go
+/// up to 13 levels deep
+fn deep_trace() -> Inspection {
+    let traces: Vec<Trace> =
+        serde_json::from_str(include_str!("../res/simple_curve_arb.json")).unwrap();
+    BatchInspector::new(vec![], vec![])
+        .inspect_one(traces)
+        .unwrap()
+}
+
+fn inspect_deep_trace(c: &mut Criterion) {
+    let inspector = inspector();
+    let trace = deep_trace();
+    let run = |mut inspection: Inspection| {
+        inspector.inspect(&mut inspection);
+        inspector.reduce(&mut inspection);
+        inspection.prune();
+        inspection
+    };
+
+    let inspection = trace.clone();
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let inspection = run(inspection);
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    println!(
+        "inspecting {} calls made {} allocations",
+        trace.actions.len(),
+        allocations
+    );
+    drop(inspection);
+
+    c.bench_function("inspect_deep_trace", |b| {
+        b.iter_batched(|| trace.clone(), run, BatchSize::SmallInput)
+    });
+}
+
+criterion_group!(benches, inspect, inspect_deep_trace);
+criterion_main!(benches);
@@ -0,0 +1,42 @@
+//! Builds a minimal inspector for a Uniswap V2 fork from the ABI of its pairs,
+//! and prints the trades it finds in a transaction which sells tokens for ETH.
+//!
+//! Run with `cargo run --example custom_inspector`
+use ethers::types::Trace;
+use mev_inspect::{
+    inspectors::{InspectorBuilder, TradeMatching, TransferIn, ERC20},
+    reducers::TradeReducer,
+    types::Protocol,
+    BatchInspector,
+};
+
+fn main() -> anyhow::Result<()> {
+    // The pairs are sent the tokens sold before `swap` is called, and send the
+    // tokens bought to its third argument
+    let pairs = InspectorBuilder::new("myswap", include_str!("../abi/unipair.json"))?
+        .protocol(Protocol::Uniswappy)
+        .trade(
+            "swap(uint256,uint256,address,bytes)",
+            TradeMatching {
+                transfer_in: TransferIn::Before,
+                recipient: Some(2),
+            },
+        )?
+        .build();
+
+    // the transfers must be classified before they can be matched into trades
+    let inspector = BatchInspector::new(
+        vec![Box::new(ERC20::new()), pairs],
+        vec![Box::new(TradeReducer::new())],
+    );
+
+    let traces: Vec<Trace> =
+        serde_json::from_str(include_str!("../res/exact_tokens_for_eth.json"))?;
+    for inspection in inspector.inspect_many(traces) {
+        println!("{:?} on {:?}", inspection.hash, inspection.protocols);
+        for trade in inspection.trades() {
+            println!("{:#?}", trade);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,56 @@
+-- Tables modeled on flashbots' mev-inspect schema. The actions of a tx are
+-- keyed on its hash, their trace address and their position among the actions
+-- which share that trace address, so that writing a block again upserts rows.
+
+CREATE TABLE IF NOT EXISTS mev_inspections (
+    hash text PRIMARY KEY,
+    block_number bigint NOT NULL,
+    status text NOT NULL,
+    from_address text NOT NULL,
+    contract text NOT NULL,
+    proxy_impl text,
+    protocols text[] NOT NULL,
+    gas_used numeric,
+    gas_price numeric,
+    coinbase_payment numeric NOT NULL,
+    inserted_at timestamp with time zone DEFAULT now()
+);
+
+CREATE TABLE IF NOT EXISTS classifications (
+    hash text NOT NULL,
+    trace_address integer[] NOT NULL,
+    idx integer NOT NULL,
+    block_number bigint NOT NULL,
+    -- the `SpecificAction` variant, or `Unknown` for unclassified calls
+    kind text NOT NULL,
+    action jsonb NOT NULL,
+    PRIMARY KEY (hash, trace_address, idx)
+);
+
+CREATE TABLE IF NOT EXISTS transfers (
+    hash text NOT NULL,
+    trace_address integer[] NOT NULL,
+    idx integer NOT NULL,
+    block_number bigint NOT NULL,
+    token text NOT NULL,
+    from_address text NOT NULL,
+    to_address text NOT NULL,
+    amount numeric NOT NULL,
+    PRIMARY KEY (hash, trace_address, idx)
+);
+
+CREATE TABLE IF NOT EXISTS arbitrages (
+    hash text NOT NULL,
+    trace_address integer[] NOT NULL,
+    idx integer NOT NULL,
+    block_number bigint NOT NULL,
+    token text NOT NULL,
+    to_address text NOT NULL,
+    profit numeric NOT NULL,
+    capital numeric NOT NULL,
+    hops integer NOT NULL,
+    PRIMARY KEY (hash, trace_address, idx)
+);
+
+CREATE INDEX IF NOT EXISTS mev_inspections_block_number ON mev_inspections (block_number);
+CREATE INDEX IF NOT EXISTS arbitrages_block_number ON arbitrages (block_number);
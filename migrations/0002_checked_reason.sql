@@ -0,0 +1,3 @@
+-- Why a tx was marked `Checked`, which is kept out of its `status`
+
+ALTER TABLE mev_inspections ADD COLUMN IF NOT EXISTS checked_reason text;
@@ -1,6 +1,6 @@
 use super::types::Protocol;
 
-use ethers::types::Address;
+use ethers::types::{Address, H160};
 
 use once_cell::sync::Lazy;
 use std::collections::{HashMap, HashSet};
@@ -89,12 +89,79 @@ pub static PROTOCOLS: Lazy<HashMap<Address, Protocol>> = Lazy::new(|| {
         Protocol::Uniswap,
     );
 
+    // uni v3 router
+    map.insert(
+        parse_address("E592427A0AEce92De3Edee1F18E0157C05861564"),
+        Protocol::UniswapV3,
+    );
+
+    // uni v3 router 02
+    map.insert(
+        parse_address("68b3465833fb72A70ecDF485E0e4C7bD8665Fc45"),
+        Protocol::UniswapV3,
+    );
+
+    // uni v3 positions nft
+    map.insert(
+        parse_address("C36442b4a4522E871399CD717aBDD847Ab11FE88"),
+        Protocol::UniswapV3,
+    );
+
     // sushi router
     map.insert(
         "d9e1cE17f2641f24aE83637ab66a2cca9C378B9F".parse().unwrap(),
         Protocol::Sushiswap,
     );
 
+    // balancer v1 exchange proxy and v2 vault
+    map.insert(*BALANCER_PROXY, Protocol::Balancer);
+    map.insert(*BALANCER_VAULT, Protocol::Balancer);
+
+    // 1inch v3 / v4 routers
+    map.insert(
+        parse_address("11111112542d85b3ef69ae05771c2dccff4faa26"),
+        Protocol::OneInch,
+    );
+    map.insert(
+        parse_address("1111111254fb6c44bAC0beD2854e76F90643097d"),
+        Protocol::OneInch,
+    );
+
+    // kyber network proxies
+    map.insert(
+        parse_address("818E6FECD516Ecc3849DAf6845e3EC868087B755"),
+        Protocol::Kyber,
+    );
+    map.insert(
+        parse_address("9AAb3f75489902f3a48495025729a0AF77d4b11e"),
+        Protocol::Kyber,
+    );
+
+    // bancor v2 / v3 networks
+    map.insert(
+        parse_address("2F9EC37d6CcFFf1caB21733BdaDEdE11c823cCB0"),
+        Protocol::Bancor,
+    );
+    map.insert(
+        parse_address("eEF417e1D5CC832e619ae18D2F140De2999dD4fB"),
+        Protocol::Bancor,
+    );
+
+    // synthetix proxy
+    map.insert(
+        parse_address("C011a73ee8576Fb46F5E1c5751cA3B9Fe0af2a6F"),
+        Protocol::Synthetix,
+    );
+
+    for addr in SEAPORT.iter() {
+        map.insert(*addr, Protocol::Seaport);
+    }
+    map.insert(*LOOKSRARE, Protocol::LooksRare);
+    map.insert(*X2Y2, Protocol::X2Y2);
+    for comet in COMETS.keys() {
+        map.insert(*comet, Protocol::CompoundV3);
+    }
+
     insert_many(
         map,
         &["0xfe01821Ca163844203220cd08E4f2B2FB43aE4E4"], // 0x: BalancerBridge
@@ -109,8 +176,6 @@ pub static FILTER: Lazy<HashSet<Address>> = Lazy::new(|| {
     set.insert(parse_address("0x11111254369792b2ca5d084ab5eea397ca8fa48b"));
     // 1inch v2
     set.insert(parse_address("0x111111125434b319222cdbf8c261674adb56f3ae"));
-    // 1inch v3 router
-    set.insert(parse_address("0x11111112542d85b3ef69ae05771c2dccff4faa26"));
     // paraswap
     set.insert(parse_address("0x9509665d015bfe3c77aa5ad6ca20c8afa1d98989"));
     // paraswap v2
@@ -140,15 +205,79 @@ pub static FILTER: Lazy<HashSet<Address>> = Lazy::new(|| {
     set
 });
 
+/// Tokens whose balances rebase, so that the amounts of them which are sent
+/// and received back can differ without a trade, e.g. for stETH and AMPL
+pub static REBASING: Lazy<HashSet<Address>> = Lazy::new(|| {
+    let mut set = HashSet::new();
+    // Lido stETH
+    set.insert(parse_address("0xae7ab96520de3a18e5e111b5eaab095312d7fe84"));
+    // Ampleforth
+    set.insert(parse_address("0xd46ba6d942050d489dbd938a2c909a5d5039a161"));
+    set
+});
+
+/// Contracts which are only called for quotes, and the protocol which they
+/// quote. Uniswap V3's quoters simulate the swaps on the pools and revert with
+/// their amounts, so the transfers made under them never happen.
+pub static QUOTERS: Lazy<HashMap<Address, Protocol>> = Lazy::new(|| {
+    [
+        // Uniswap V3: Quoter
+        "0xb27308f9F90D607463bb33eA1BeBb41C27CE5AB6",
+        // Uniswap V3: QuoterV2
+        "0x61fFE014bA17989E743c5F6cB21bF9697530B21e",
+    ]
+    .iter()
+    .map(|addr| (parse_address(addr), Protocol::UniswapV3))
+    .collect()
+});
+
 pub static ZEROX: Lazy<Address> =
     Lazy::new(|| parse_address("0x61935cbdd02287b511119ddb11aeb42f1593b7ef"));
 
+/// Seaport 1.1, 1.4 and 1.5
+pub static SEAPORT: Lazy<HashSet<Address>> = Lazy::new(|| {
+    [
+        "0x00000000006c3852cbEf3e08E8dF289169EdE581",
+        "0x00000000000001ad428e4906aE43D8F9852d0dD6",
+        "0x00000000000000ADc04C56Bf30aC9d3c0aAF14dC",
+    ]
+    .iter()
+    .map(|addr| parse_address(addr))
+    .collect()
+});
+
+/// Uniswap's Universal Routers on mainnet, the first one and its successor
+pub static UNIVERSAL_ROUTER: Lazy<HashSet<Address>> = Lazy::new(|| {
+    [
+        "0xEf1c6E67703c7BD7107eed8303Fbe6EC2554BF6B",
+        "0x3fC91A3afd70395Cd496C647d5a6CC9D4B2b7FAD",
+    ]
+    .iter()
+    .map(|addr| parse_address(addr))
+    .collect()
+});
+
+/// Uniswap's Permit2, which is deployed at the same address on all chains
+pub static PERMIT2: Lazy<Address> =
+    Lazy::new(|| parse_address("0x000000000022D473030F116dDEE9F6B43aC78BA3"));
+
+/// LooksRare's exchange
+pub static LOOKSRARE: Lazy<Address> =
+    Lazy::new(|| parse_address("0x59728544B08AB483533076417FbBB2fD0B17CE3a"));
+
+/// X2Y2's exchange
+pub static X2Y2: Lazy<Address> =
+    Lazy::new(|| parse_address("0x74312363e45DCaBA76c59ec49a7Aa8A65a67EeD3"));
+
 pub static DYDX: Lazy<Address> =
     Lazy::new(|| parse_address("0x1e0447b19bb6ecfdae1e4ae1694b0c3659614e4e"));
 
 pub static BALANCER_PROXY: Lazy<Address> =
     Lazy::new(|| parse_address("0x3E66B66Fd1d0b02fDa6C811Da9E0547970DB2f21"));
 
+pub static BALANCER_VAULT: Lazy<Address> =
+    Lazy::new(|| parse_address("0xBA12222222228d8Ba445958a75a0704d566BF2C8"));
+
 pub static CURVE_REGISTRY: Lazy<Address> =
     Lazy::new(|| parse_address("0x7D86446dDb609eD0F5f8684AcF30380a356b2B4c"));
 
@@ -161,17 +290,249 @@ pub static COMPTROLLER: Lazy<Address> =
 pub static COMP_ORACLE: Lazy<Address> =
     Lazy::new(|| parse_address("922018674c12a7F0D394ebEEf9B58F186CdE13c1"));
 
+#[derive(Debug, Clone, PartialEq)]
+/// A lending protocol forked from Compound V2, whose markets are listed by its
+/// comptroller
+pub struct CompoundFork {
+    pub protocol: Protocol,
+    pub comptroller: Address,
+    /// The oracle which liquidators query before liquidating, if it is known
+    pub oracle: Option<Address>,
+    /// The market of ETH, which has no `underlying` and is credited as WETH
+    pub native_market: Option<Address>,
+}
+
+/// Compound V2 and its forks
+pub static COMPOUND_FORKS: Lazy<Vec<CompoundFork>> = Lazy::new(|| {
+    vec![
+        CompoundFork {
+            protocol: Protocol::Compound,
+            comptroller: *COMPTROLLER,
+            oracle: Some(*COMP_ORACLE),
+            native_market: Some(*CETH),
+        },
+        CompoundFork {
+            protocol: Protocol::Cream,
+            comptroller: parse_address("3d5BC3c8d13dcB8bF317092d84783c2697AE9258"),
+            oracle: None,
+            native_market: Some(parse_address("D06527D5e56A3495252A528C4987003b712860eE")),
+        },
+        // its WETH market wraps ETH itself
+        CompoundFork {
+            protocol: Protocol::IronBank,
+            comptroller: parse_address("AB1c342C7bf5Ec5F02ADEA1c2270670bCa144CbB"),
+            oracle: None,
+            native_market: None,
+        },
+    ]
+});
+
+/// The markets of Compound V3, by the base token which they lend
+pub static COMETS: Lazy<HashMap<Address, Address>> = Lazy::new(|| {
+    [
+        // cUSDCv3
+        (
+            "c3d688B66703497DAA19211EEdff47f25384cdc3",
+            "a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48",
+        ),
+        // cWETHv3
+        (
+            "A17581A9E3356d9A858b789D68B4d866e593aE94",
+            "c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2",
+        ),
+    ]
+    .iter()
+    .map(|(comet, base)| (parse_address(comet), parse_address(base)))
+    .collect()
+});
+
 pub static AAVE_LENDING_POOL: Lazy<Address> =
     Lazy::new(|| parse_address("398eC7346DcD622eDc5ae82352F02bE94C62d119"));
 
 pub static AAVE_LENDING_POOL_CORE: Lazy<Address> =
     Lazy::new(|| parse_address("3dfd23a6c5e8bbcfc9581d2e864a68feb6a076d3"));
 
+pub static AAVE_V2_LENDING_POOL: Lazy<Address> =
+    Lazy::new(|| parse_address("7d2768dE32b0b80b7a3454c06BdAc94A69DDc7A9"));
+
+/// Maker's DAI flash minters (`MCD_FLASH`), the current one and the legacy one
+pub static DSS_FLASH: Lazy<HashSet<Address>> = Lazy::new(|| {
+    [
+        "0x60744434d6339a6B27d73d9Eda62b6F66a0a04FA",
+        "0x1EB4CF3A948E7D72A198fe073cCb8C7a948cD853",
+    ]
+    .iter()
+    .map(|addr| parse_address(addr))
+    .collect()
+});
+
+/// 1inch's gas token, which refunded gas when burned before London
+pub static CHI: Lazy<Address> =
+    Lazy::new(|| parse_address("0x0000000000004946c0e9F43F4Dee607b0eF1fA1c"));
+
+/// GasToken.io's gas token, which refunded gas when burned before London
+pub static GST2: Lazy<Address> =
+    Lazy::new(|| parse_address("0x0000000000b3F879cb30FE243b4Dfee438691c04"));
+
+/// The stablecoin which USD values are priced with
+pub static USDC: Lazy<Address> =
+    Lazy::new(|| parse_address("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48"));
+
 pub static WETH: Lazy<Address> =
     Lazy::new(|| parse_address("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2"));
 
-pub static ETH: Lazy<Address> =
-    Lazy::new(|| parse_address("0xeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeee"));
+/// Market makers which trade on both centralized and decentralized exchanges,
+/// as labelled on Etherscan
+pub static MARKET_MAKERS: Lazy<HashSet<Address>> = Lazy::new(|| {
+    [
+        // Wintermute
+        "0x0000006daea1723962647b7e189d311d757Fb793",
+        // Jump Trading
+        "0xf584F8728B874a6a5c7A8d4d387C9aae9172D621",
+    ]
+    .iter()
+    .map(|addr| parse_address(addr))
+    .collect()
+});
+
+/// The placeholder which protocols such as 1inch, Curve and Balancer use for
+/// native ETH, and which ETH transfers are classified with
+pub const NATIVE_ETH: Address = H160([0xee; 20]);
+
+pub static ETH: Lazy<Address> = Lazy::new(|| NATIVE_ETH);
+
+/// Maps native ETH to `weth`, the chain's wrapped ETH, so that the two can be
+/// netted against each other. Other tokens are left as they are.
+pub fn canonical_token(token: Address, weth: Address) -> Address {
+    if token == NATIVE_ETH {
+        weth
+    } else {
+        token
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// The addresses which differ between the chains that can be inspected, so
+/// that the inspectors and reducers can be used beyond Ethereum mainnet
+pub struct ChainConfig {
+    pub chain_id: u64,
+    /// The chain's wrapped native token, e.g. WETH on mainnet and the rollups
+    pub weth: Address,
+    /// The placeholder which transfers of the chain's native token are
+    /// classified with
+    pub native: Address,
+    /// The protocols of the chain's known routers, factories and pools
+    pub protocols: HashMap<Address, Protocol>,
+    /// The chain's rebasing tokens, see `REBASING`
+    pub rebasing: HashSet<Address>,
+    /// The chain's Universal Routers, see `UNIVERSAL_ROUTER`
+    pub universal_routers: HashSet<Address>,
+}
+
+impl Default for ChainConfig {
+    fn default() -> Self {
+        Self::mainnet()
+    }
+}
+
+impl ChainConfig {
+    /// Instantiates a chain without any known protocols
+    pub fn new(chain_id: u64, weth: Address) -> Self {
+        Self {
+            chain_id,
+            weth,
+            native: NATIVE_ETH,
+            protocols: HashMap::new(),
+            rebasing: HashSet::new(),
+            universal_routers: HashSet::new(),
+        }
+    }
+
+    /// Adds a router, factory or pool of `protocol`
+    pub fn with_protocol(mut self, address: Address, protocol: Protocol) -> Self {
+        self.protocols.insert(address, protocol);
+        self
+    }
+
+    /// Adds a Universal Router
+    pub fn with_universal_router(mut self, address: Address) -> Self {
+        self.universal_routers.insert(address);
+        self
+    }
+
+    /// The chain's known contracts of `protocol`
+    pub fn contracts_of(&self, protocol: Protocol) -> HashSet<Address> {
+        self.protocols
+            .iter()
+            .filter(|(_, p)| **p == protocol)
+            .map(|(address, _)| *address)
+            .collect()
+    }
+
+    /// Ethereum mainnet
+    pub fn mainnet() -> Self {
+        Self {
+            protocols: PROTOCOLS.clone(),
+            rebasing: REBASING.clone(),
+            universal_routers: UNIVERSAL_ROUTER.clone(),
+            ..Self::new(1, *WETH)
+        }
+    }
+
+    /// Arbitrum One
+    pub fn arbitrum() -> Self {
+        Self::new(
+            42161,
+            parse_address("0x82aF49447D8a07e3bd95BD0d56f35241523fBab1"),
+        )
+        .with_uniswap_v3()
+        .with_protocol(
+            parse_address("0x1b02dA8Cb0d097eB8D57A175b88c7D8b47997506"),
+            Protocol::Sushiswap,
+        )
+        .with_protocol(
+            parse_address("0xc35DADB65012eC5796536bD9864eD8773aBc74C4"),
+            Protocol::Sushiswap,
+        )
+        .with_protocol(*BALANCER_VAULT, Protocol::Balancer)
+        .with_universal_router(parse_address("0x5E325eDA8064b456f4781070C0738d849c824258"))
+    }
+
+    /// Optimism
+    pub fn optimism() -> Self {
+        Self::new(
+            10,
+            parse_address("0x4200000000000000000000000000000000000006"),
+        )
+        .with_uniswap_v3()
+        .with_protocol(*BALANCER_VAULT, Protocol::Balancer)
+        .with_universal_router(parse_address("0xCb1355ff08Ab38bBCE60111F1bb2B784bE25D7e8"))
+    }
+
+    /// The preset of the chain with id `chain_id`, if there is one
+    pub fn from_chain_id(chain_id: u64) -> Option<Self> {
+        match chain_id {
+            1 => Some(Self::mainnet()),
+            10 => Some(Self::optimism()),
+            42161 => Some(Self::arbitrum()),
+            _ => None,
+        }
+    }
+
+    /// Uniswap V3's factory and routers, which are deployed at the same
+    /// addresses on the rollups as on mainnet
+    fn with_uniswap_v3(self) -> Self {
+        [
+            "0x1F98431c8aD98523631AE4a59f267346ea31F984",
+            "0xE592427A0AEce92De3Edee1F18E0157C05861564",
+            "0x68b3465833fb72A70ecDF485E0e4C7bD8665Fc45",
+        ]
+        .iter()
+        .fold(self, |chain, addr| {
+            chain.with_protocol(parse_address(addr), Protocol::UniswapV3)
+        })
+    }
+}
 
 pub static ADDRESSBOOK: Lazy<HashMap<Address, String>> = Lazy::new(|| {
     // TODO: Read these from a CSV?
@@ -181,6 +542,10 @@ pub static ADDRESSBOOK: Lazy<HashMap<Address, String>> = Lazy::new(|| {
             "0xdef1c0ded9bec7f1a1670819833240f027b25eff",
             "0x: ExchangeProxy",
         ),
+        (
+            "0x61935cbdd02287b511119ddb11aeb42f1593b7ef",
+            "0x: Exchange V3",
+        ),
         (
             "0xfe01821Ca163844203220cd08E4f2B2FB43aE4E4",
             "0x: BalancerBridge",
@@ -206,6 +571,22 @@ pub static ADDRESSBOOK: Lazy<HashMap<Address, String>> = Lazy::new(|| {
             "0x7a250d5630b4cf539739df2c5dacb4c659f2488d",
             "Uniswap Router V2",
         ),
+        (
+            "0xE592427A0AEce92De3Edee1F18E0157C05861564",
+            "Uniswap V3: Router",
+        ),
+        (
+            "0xC36442b4a4522E871399CD717aBDD847Ab11FE88",
+            "Uniswap V3: Positions NFT",
+        ),
+        (
+            "0xb27308f9F90D607463bb33eA1BeBb41C27CE5AB6",
+            "Uniswap V3: Quoter",
+        ),
+        (
+            "0x61fFE014bA17989E743c5F6cB21bF9697530B21e",
+            "Uniswap V3: QuoterV2",
+        ),
         (
             "0x088ee5007C98a9677165D78dD2109AE4a3D04d0C",
             "Sushiswap: YFI",
@@ -222,6 +603,10 @@ pub static ADDRESSBOOK: Lazy<HashMap<Address, String>> = Lazy::new(|| {
             "0x3dfd23a6c5e8bbcfc9581d2e864a68feb6a076d3",
             "AAVE: Lending Pool Core",
         ),
+        (
+            "0x7d2768dE32b0b80b7a3454c06BdAc94A69DDc7A9",
+            "AAVE: Lending Pool V2",
+        ),
         (
             "0xb6ad5fd2698a68917e39216304d4845625da2f57",
             "Balancer: YFI/yyDAI+yUSDC+yUSDT+yTUSD 50/50",
@@ -230,6 +615,33 @@ pub static ADDRESSBOOK: Lazy<HashMap<Address, String>> = Lazy::new(|| {
             "0xd44082f25f8002c5d03165c5d74b520fbc6d342d",
             "Balancer: Pool 293 (YFI / LEND / MKR / WETH / LINK)",
         ),
+        (
+            "0xBA12222222228d8Ba445958a75a0704d566BF2C8",
+            "Balancer V2: Vault",
+        ),
+        (
+            "0x11111112542d85b3ef69ae05771c2dccff4faa26",
+            "1inch: Aggregation Router V3",
+        ),
+        (
+            "0x1111111254fb6c44bAC0beD2854e76F90643097d",
+            "1inch: Aggregation Router V4",
+        ),
+        ("0x00000000006c3852cbEf3e08E8dF289169EdE581", "Seaport 1.1"),
+        ("0x00000000000001ad428e4906aE43D8F9852d0dD6", "Seaport 1.4"),
+        ("0x00000000000000ADc04C56Bf30aC9d3c0aAF14dC", "Seaport 1.5"),
+        (
+            "0x59728544B08AB483533076417FbBB2fD0B17CE3a",
+            "LooksRare: Exchange",
+        ),
+        (
+            "0x74312363e45DCaBA76c59ec49a7Aa8A65a67EeD3",
+            "X2Y2: Exchange",
+        ),
+        ("0xc3d688B66703497DAA19211EEdff47f25384cdc3", "cUSDCv3"),
+        ("0xA17581A9E3356d9A858b789D68B4d866e593aE94", "cWETHv3"),
+        ("0x0000006daea1723962647b7e189d311d757Fb793", "Wintermute"),
+        ("0xf584F8728B874a6a5c7A8d4d387C9aae9172D621", "Jump Trading"),
         // Tokens
         ("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48", "USDC"),
         ("0x0000000000000000000000000000000000000000", "ETH"),
@@ -250,6 +662,7 @@ pub static ADDRESSBOOK: Lazy<HashMap<Address, String>> = Lazy::new(|| {
             "yyDAI+yUSDC+yUSDT+yTUSD",
         ),
         ("0x0000000000b3f879cb30fe243b4dfee438691c04", "GST2"),
+        ("0x0000000000004946c0e9f43f4dee607b0ef1fa1c", "CHI"),
     ]
     .iter()
     .map(|(addr, token)| (parse_address(addr), token.to_string()))
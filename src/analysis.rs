@@ -0,0 +1,490 @@
+use crate::{
+    prices::{net_profit, PriceOracle},
+    types::{
+        actions::{SpecificAction, Trade},
+        Block,
+    },
+};
+use ethers::types::{Address, TxHash, I256};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// The activity of a searcher, i.e. of the bot contracts and the EOAs which
+/// call them, across many blocks
+pub struct SearcherProfile {
+    /// The searcher's bot contract which made the most arbitrages and
+    /// sandwiches
+    pub address: Address,
+    /// All of the searcher's bot contracts, most active first
+    pub contracts: Vec<Address>,
+    /// The EOAs which sent the searcher's transactions
+    pub eoas: Vec<Address>,
+    pub num_arbs: usize,
+    pub num_sandwiches: usize,
+    /// The ETH made by the arbitrages and sandwiches, before gas. The profits
+    /// in tokens which cannot be priced are left out.
+    pub total_profit_eth: I256,
+}
+
+/// The searcher which is being clustered
+#[derive(Default)]
+struct Cluster {
+    /// The number of MEV actions made through each contract, in the order
+    /// they were first seen
+    contracts: Vec<(Address, usize)>,
+    eoas: Vec<Address>,
+    num_arbs: usize,
+    num_sandwiches: usize,
+    total_profit_eth: I256,
+}
+
+impl Cluster {
+    fn merge(&mut self, other: Cluster) {
+        for (contract, count) in other.contracts {
+            self.add_contract(contract, count);
+        }
+        for eoa in other.eoas {
+            if !self.eoas.contains(&eoa) {
+                self.eoas.push(eoa);
+            }
+        }
+        self.num_arbs += other.num_arbs;
+        self.num_sandwiches += other.num_sandwiches;
+        self.total_profit_eth = self.total_profit_eth.saturating_add(other.total_profit_eth);
+    }
+
+    fn add_contract(&mut self, contract: Address, count: usize) {
+        match self.contracts.iter_mut().find(|(c, _)| *c == contract) {
+            Some((_, total)) => *total += count,
+            None => self.contracts.push((contract, count)),
+        }
+    }
+
+    fn into_profile(mut self) -> SearcherProfile {
+        // stable, so that ties keep the first contract seen
+        self.contracts
+            .sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        let contracts = self
+            .contracts
+            .into_iter()
+            .map(|(c, _)| c)
+            .collect::<Vec<_>>();
+        SearcherProfile {
+            address: contracts[0],
+            contracts,
+            eoas: self.eoas,
+            num_arbs: self.num_arbs,
+            num_sandwiches: self.num_sandwiches,
+            total_profit_eth: self.total_profit_eth,
+        }
+    }
+}
+
+/// Clusters the transactions of `blocks` which made arbitrages or sandwiches
+/// into searchers, most profitable first. Transactions belong to the same
+/// searcher if they were sent to the same bot contract or by the same EOA, so
+/// a searcher which rotates its EOAs or its contracts, but not both at once,
+/// is still attributed all of its activity. Transactions without any MEV do
+/// not link addresses together.
+pub fn searcher_profiles<O: PriceOracle>(blocks: &[Block], oracle: &O) -> Vec<SearcherProfile> {
+    // the cluster of each address, and the clusters which were merged into
+    // another one, whose index is then redirected
+    let mut index: HashMap<Address, usize> = HashMap::new();
+    let mut clusters: Vec<Option<Cluster>> = Vec::new();
+    let mut redirects: Vec<usize> = Vec::new();
+    let resolve = |redirects: &[usize], mut i: usize| {
+        while redirects[i] != i {
+            i = redirects[i];
+        }
+        i
+    };
+
+    for block in blocks {
+        for inspection in &block.inspections {
+            let mut activity = Cluster::default();
+            for action in inspection.known_actions() {
+                let profit = match action {
+                    SpecificAction::Arbitrage(arb) => {
+                        activity.num_arbs += 1;
+                        arb.eth_profit(oracle, block.number)
+                    }
+                    SpecificAction::Sandwich(sandwich) => {
+                        activity.num_sandwiches += 1;
+                        net_profit(
+                            oracle,
+                            vec![(sandwich.token, sandwich.profit)],
+                            block.number,
+                        )
+                    }
+                    _ => continue,
+                };
+                if let Some(profit) = profit {
+                    activity.total_profit_eth = activity.total_profit_eth.saturating_add(profit);
+                }
+            }
+            let count = activity.num_arbs + activity.num_sandwiches;
+            if count == 0 {
+                continue;
+            }
+            activity.add_contract(inspection.contract, count);
+            activity.eoas.push(inspection.from);
+
+            // join the clusters of the contract and the EOA, if any
+            let mut joined = [inspection.contract, inspection.from]
+                .iter()
+                .filter_map(|address| index.get(address))
+                .map(|i| resolve(&redirects, *i))
+                .collect::<Vec<_>>();
+            joined.sort_unstable();
+            joined.dedup();
+            let target = match joined.first() {
+                Some(target) => *target,
+                None => {
+                    clusters.push(None);
+                    redirects.push(clusters.len() - 1);
+                    clusters.len() - 1
+                }
+            };
+            for other in joined.iter().skip(1) {
+                let other_cluster = clusters[*other]
+                    .take()
+                    .expect("merged clusters are redirected");
+                activity.merge(other_cluster);
+                redirects[*other] = target;
+            }
+            match clusters[target] {
+                Some(ref mut cluster) => cluster.merge(activity),
+                None => clusters[target] = Some(activity),
+            }
+            index.insert(inspection.contract, target);
+            index.insert(inspection.from, target);
+        }
+    }
+
+    let mut profiles = clusters
+        .into_iter()
+        .flatten()
+        .map(Cluster::into_profile)
+        .collect::<Vec<_>>();
+    profiles.sort_by_key(|p| std::cmp::Reverse(p.total_profit_eth));
+    profiles
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// An arbitrage or a sandwich of an uncled block which was captured by another
+/// searcher in the canonical block at the same height, e.g. by a miner which
+/// reorged the block to take it for itself
+pub struct ReorgMev {
+    /// The bot contract which made the MEV in the uncled block
+    pub original_searcher: Address,
+    pub original_tx: TxHash,
+    /// The bot contract which made the same MEV in the canonical block
+    pub capturing_searcher: Address,
+    pub capturing_tx: TxHash,
+    /// The capturing searcher's profit in ETH, before gas. `None` if its token
+    /// cannot be priced.
+    pub profit: Option<I256>,
+}
+
+/// The opportunity which an arbitrage or a sandwich took, which is the same
+/// across both forks no matter who took it
+#[derive(PartialEq)]
+enum Opportunity {
+    /// The arbitrage's token and the pools of its hops, in order
+    Arbitrage(Address, Vec<Address>),
+    /// The sandwiched pool and its victims' trades
+    Sandwich(Address, Vec<Trade>),
+}
+
+/// The opportunities taken by the transactions of `block`, along with their
+/// searcher, tx and profit in ETH
+fn opportunities<O: PriceOracle>(
+    block: &Block,
+    oracle: &O,
+) -> Vec<(Opportunity, Address, TxHash, Option<I256>)> {
+    let mut found = Vec::new();
+    for inspection in &block.inspections {
+        for action in inspection.known_actions() {
+            let (opportunity, profit) = match action {
+                SpecificAction::Arbitrage(arb) => (
+                    Opportunity::Arbitrage(
+                        arb.token,
+                        arb.trades.iter().map(|trade| trade.t1.to).collect(),
+                    ),
+                    arb.eth_profit(oracle, block.number),
+                ),
+                SpecificAction::Sandwich(sandwich) => (
+                    Opportunity::Sandwich(
+                        sandwich.pool,
+                        sandwich.victims.iter().map(|v| v.trade.clone()).collect(),
+                    ),
+                    net_profit(
+                        oracle,
+                        vec![(sandwich.token, sandwich.profit)],
+                        block.number,
+                    ),
+                ),
+                _ => continue,
+            };
+            found.push((opportunity, inspection.contract, inspection.hash, profit));
+        }
+    }
+    found
+}
+
+/// Finds the arbitrages and sandwiches of `uncle`, a block at the same height
+/// as `canonical` which lost the fork choice, which were made by another
+/// searcher in `canonical`. Both blocks must have been inspected with the same
+/// block reducers, so that their sandwiches are found alike. The uncle's
+/// traces are not served by the canonical chain's nodes, so they are usually
+/// recorded by a node which followed the other fork and inspected with
+/// `Block::from_traces`.
+pub fn reorg_mev<O: PriceOracle>(canonical: &Block, uncle: &Block, oracle: &O) -> Vec<ReorgMev> {
+    let captured = opportunities(canonical, oracle);
+    opportunities(uncle, oracle)
+        .into_iter()
+        .filter_map(|(opportunity, original_searcher, original_tx, _)| {
+            let (_, capturing_searcher, capturing_tx, profit) = captured
+                .iter()
+                .find(|(other, _, _, _)| *other == opportunity)?;
+            if *capturing_searcher == original_searcher {
+                return None;
+            }
+            Some(ReorgMev {
+                original_searcher,
+                original_tx,
+                capturing_searcher: *capturing_searcher,
+                capturing_tx: *capturing_tx,
+                profit: *profit,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        addresses::WETH,
+        test_helpers::*,
+        types::{
+            actions::{Arbitrage, Sandwich, Trade, Transfer},
+            Classification, Inspection,
+        },
+        UniswapV2Oracle,
+    };
+    use ethers::types::U256;
+
+    fn tx(from: Address, contract: Address, action: Option<SpecificAction>) -> Inspection {
+        let mut inspection = mk_inspection(
+            action
+                .into_iter()
+                .map(|a| Classification::new(a, vec![]))
+                .collect(),
+        );
+        inspection.from = from;
+        inspection.contract = contract;
+        inspection
+    }
+
+    fn arb(profit: u64) -> Option<SpecificAction> {
+        Some(
+            Arbitrage {
+                profit: profit.into(),
+                token: *WETH,
+                to: Address::zero(),
+                capital: 0.into(),
+                trades: vec![],
+                recipient: Address::zero(),
+                searcher: Default::default(),
+            }
+            .into(),
+        )
+    }
+
+    /// An arbitrage whose hops go through `pools`
+    fn arb_through(profit: u64, pools: &[Address]) -> Option<SpecificAction> {
+        let hop = |pool: Address| {
+            let transfer = |from, to| Transfer {
+                from,
+                to,
+                amount: 0.into(),
+                token: *WETH,
+            };
+            Trade::new(
+                transfer(Address::zero(), pool),
+                transfer(pool, Address::zero()),
+            )
+        };
+        let mut arb = arb(profit);
+        if let Some(SpecificAction::Arbitrage(ref mut inner)) = arb {
+            inner.trades = pools.iter().copied().map(hop).collect();
+        }
+        arb
+    }
+
+    fn sandwich(profit: i64) -> Option<SpecificAction> {
+        let trade = Trade {
+            t1: Transfer {
+                from: Address::zero(),
+                to: Address::zero(),
+                amount: 0.into(),
+                token: *WETH,
+            },
+            t2: Transfer {
+                from: Address::zero(),
+                to: Address::zero(),
+                amount: 0.into(),
+                token: *WETH,
+            },
+        };
+        Some(
+            Sandwich {
+                pool: Address::zero(),
+                frontrun_tx: TxHash::zero(),
+                backrun_tx: TxHash::zero(),
+                frontrun: trade.clone(),
+                victims: vec![],
+                backrun: trade,
+                token: *WETH,
+                profit: profit.into(),
+                capital: U256::zero(),
+                searcher: Default::default(),
+            }
+            .into(),
+        )
+    }
+
+    fn block(number: u64, inspections: Vec<Inspection>) -> Block {
+        Block {
+            number,
+            miner: Address::zero(),
+            base_fee: None,
+            timestamp: None,
+            inspections,
+            reorged: false,
+        }
+    }
+
+    #[test]
+    fn clusters_bots_and_their_eoas() {
+        let addrs = addrs();
+        let (eoa1, eoa2, eoa3, bot1, bot2, bot3) =
+            (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4], addrs[5]);
+
+        let blocks = vec![
+            block(1, vec![tx(eoa1, bot1, arb(10)), tx(eoa3, bot3, arb(100))]),
+            block(
+                2,
+                vec![
+                    // another EOA calls the same bot
+                    tx(eoa2, bot1, arb(20)),
+                    // which then calls another bot
+                    tx(eoa2, bot2, sandwich(5)),
+                    tx(eoa2, bot2, sandwich(-1)),
+                    tx(eoa2, bot2, sandwich(2)),
+                    // the other searcher calls the first bot, without any MEV
+                    tx(eoa3, bot1, None),
+                ],
+            ),
+        ];
+        let profiles = searcher_profiles(&blocks, &UniswapV2Oracle::new());
+
+        assert_eq!(
+            profiles,
+            vec![
+                SearcherProfile {
+                    address: bot3,
+                    contracts: vec![bot3],
+                    eoas: vec![eoa3],
+                    num_arbs: 1,
+                    num_sandwiches: 0,
+                    total_profit_eth: 100.into(),
+                },
+                SearcherProfile {
+                    // made the most sandwiches
+                    address: bot2,
+                    contracts: vec![bot2, bot1],
+                    eoas: vec![eoa1, eoa2],
+                    num_arbs: 2,
+                    num_sandwiches: 3,
+                    total_profit_eth: 36.into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn merges_clusters_linked_later() {
+        let addrs = addrs();
+        let (eoa1, eoa2, bot1, bot2) = (addrs[0], addrs[1], addrs[2], addrs[3]);
+
+        // 2 separate searchers, until a tx links them
+        let blocks = vec![block(
+            1,
+            vec![
+                tx(eoa1, bot1, arb(1)),
+                tx(eoa2, bot2, arb(2)),
+                tx(eoa1, bot2, arb(3)),
+            ],
+        )];
+        let profiles = searcher_profiles(&blocks, &UniswapV2Oracle::new());
+
+        assert_eq!(profiles.len(), 1);
+        assert_eq!(profiles[0].address, bot2);
+        assert_eq!(profiles[0].contracts, vec![bot2, bot1]);
+        assert_eq!(profiles[0].eoas, vec![eoa1, eoa2]);
+        assert_eq!(profiles[0].num_arbs, 3);
+        assert_eq!(profiles[0].total_profit_eth, 6.into());
+    }
+
+    #[test]
+    fn finds_the_mev_stolen_by_a_reorg() {
+        let addrs = addrs();
+        let (eoa, bot1, bot2, bot3, pool1, pool2, pool3) = (
+            addrs[0], addrs[1], addrs[2], addrs[3], addrs[4], addrs[5], addrs[6],
+        );
+        let mut uncle = block(
+            10,
+            vec![
+                tx(eoa, bot1, arb_through(100, &[pool1, pool2])),
+                // made by the same searcher on both forks
+                tx(eoa, bot3, sandwich(5)),
+                // only found in the uncle
+                tx(eoa, bot1, arb_through(100, &[pool2, pool3])),
+            ],
+        );
+        let mut canonical = block(
+            10,
+            vec![
+                tx(eoa, bot3, sandwich(5)),
+                // the same cycle, taken for less by another bot
+                tx(eoa, bot2, arb_through(90, &[pool1, pool2])),
+                // the pools in another order are another cycle
+                tx(eoa, bot2, arb_through(1, &[pool3, pool2])),
+            ],
+        );
+        for (i, inspection) in uncle.inspections.iter_mut().enumerate() {
+            inspection.hash = TxHash::from_low_u64_be(i as u64);
+        }
+        for (i, inspection) in canonical.inspections.iter_mut().enumerate() {
+            inspection.hash = TxHash::from_low_u64_be(100 + i as u64);
+        }
+
+        let stolen = reorg_mev(&canonical, &uncle, &UniswapV2Oracle::new());
+        assert_eq!(
+            stolen,
+            vec![ReorgMev {
+                original_searcher: bot1,
+                original_tx: TxHash::from_low_u64_be(0),
+                capturing_searcher: bot2,
+                capturing_tx: TxHash::from_low_u64_be(101),
+                profit: Some(90.into()),
+            }]
+        );
+    }
+}
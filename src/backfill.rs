@@ -0,0 +1,277 @@
+//! Backfills a range of blocks, writing their inspections to a sink in block
+//! order and checkpointing the last block written after each one, so that a
+//! run which is interrupted resumes where it stopped.
+//!
+//! The blocks of a window are traced and inspected concurrently, but they are
+//! only written once all the blocks before them are. A crash can therefore
+//! only interrupt the write of the block right after the checkpoint, which is
+//! discarded from the sink before it is written again by the next run.
+use crate::{types::Block, BatchInspector, TraceProvider};
+use async_trait::async_trait;
+use futures::stream::{self, StreamExt};
+use std::{
+    fs, io,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+/// Where the backfilled blocks are written
+#[async_trait]
+pub trait BlockSink: Send {
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// Writes the inspections of the block. The blocks are written in order.
+    async fn write(&mut self, block: &Block) -> Result<(), Self::Error>;
+
+    /// Removes whatever got written of block `number` by a run which was
+    /// interrupted before checkpointing it. Sinks whose writes are idempotent
+    /// have nothing to remove.
+    async fn discard(&mut self, _number: u64) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// The progress of a backfill, i.e. the last block which was written
+#[async_trait]
+pub trait Checkpoint: Send {
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// The last block written, if any
+    async fn load(&mut self) -> Result<Option<u64>, Self::Error>;
+
+    /// Records that `number` and all the blocks before it were written
+    async fn save(&mut self, number: u64) -> Result<(), Self::Error>;
+}
+
+#[derive(Debug, Clone)]
+/// A checkpoint kept in a small file, which holds the number of the last
+/// block written. The file is replaced atomically, so a crash while saving
+/// leaves the previous checkpoint.
+pub struct StateFile {
+    path: PathBuf,
+}
+
+impl StateFile {
+    /// Instantiates the checkpoint. The file is created on the first save.
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_owned(),
+        }
+    }
+}
+
+#[async_trait]
+impl Checkpoint for StateFile {
+    type Error = io::Error;
+
+    async fn load(&mut self) -> Result<Option<u64>, Self::Error> {
+        let state = match fs::read_to_string(&self.path) {
+            Ok(inner) => inner,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        state
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    async fn save(&mut self, number: u64) -> Result<(), Self::Error> {
+        // written next to the state file, so that the rename does not cross
+        // file systems
+        let tmp = self.path.with_extension("tmp");
+        {
+            let mut file = fs::File::create(&tmp)?;
+            io::Write::write_all(&mut file, format!("{}\n", number).as_bytes())?;
+            file.sync_all()?;
+        }
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+#[derive(Debug, Error)]
+/// An error while backfilling a block. The blocks before it are written and
+/// checkpointed.
+pub enum BackfillError<P, S, C>
+where
+    P: std::error::Error + 'static,
+    S: std::error::Error + 'static,
+    C: std::error::Error + 'static,
+{
+    /// The block could not be traced
+    #[error("could not trace block {0}: {1}")]
+    Trace(u64, #[source] P),
+    /// The block could not be written
+    #[error("could not write block {0}: {1}")]
+    Sink(u64, #[source] S),
+    /// The progress could not be loaded or saved
+    #[error(transparent)]
+    Checkpoint(C),
+}
+
+/// Backfills the inspections of a range of blocks
+pub struct Backfiller<'a, P> {
+    inspector: &'a BatchInspector,
+    provider: &'a P,
+    window: usize,
+}
+
+impl<'a, P: TraceProvider> Backfiller<'a, P> {
+    /// Instantiates the backfiller, which inspects 10 blocks at a time
+    pub fn new(inspector: &'a BatchInspector, provider: &'a P) -> Self {
+        Self {
+            inspector,
+            provider,
+            window: 10,
+        }
+    }
+
+    /// Sets how many blocks are traced and inspected concurrently
+    pub fn with_window(mut self, window: usize) -> Self {
+        self.window = window.max(1);
+        self
+    }
+
+    /// Writes the blocks of the range to `sink`, starting after the last block
+    /// of `checkpoint`, and returns how many were written
+    pub async fn run<S: BlockSink, C: Checkpoint>(
+        &self,
+        blocks: Range<u64>,
+        sink: &mut S,
+        checkpoint: &mut C,
+    ) -> Result<u64, BackfillError<P::Error, S::Error, C::Error>> {
+        let start = match checkpoint.load().await.map_err(BackfillError::Checkpoint)? {
+            Some(last) => blocks.start.max(last + 1),
+            None => blocks.start,
+        };
+        if start >= blocks.end {
+            return Ok(0);
+        }
+        // the previous run may have been interrupted while writing it
+        sink.discard(start)
+            .await
+            .map_err(|err| BackfillError::Sink(start, err))?;
+
+        let mut inspected = stream::iter(start..blocks.end)
+            .map(|number| async move {
+                let block = Block::from_provider(self.inspector, self.provider, number).await;
+                (number, block)
+            })
+            .buffered(self.window);
+        let mut written = 0;
+        while let Some((number, block)) = inspected.next().await {
+            let block = block.map_err(|err| BackfillError::Trace(number, err))?;
+            sink.write(&block)
+                .await
+                .map_err(|err| BackfillError::Sink(number, err))?;
+            checkpoint
+                .save(number)
+                .await
+                .map_err(BackfillError::Checkpoint)?;
+            written += 1;
+        }
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Unsupported;
+    use crate::{inspectors::ERC20, reducers::TransferReducer};
+    use ethers::types::{Trace, TxHash};
+    use std::time::Duration;
+
+    /// A node whose earlier blocks take longer to trace, so that the blocks of
+    /// a window are inspected out of order
+    struct SlowNode {
+        end: u64,
+    }
+
+    #[async_trait]
+    impl TraceProvider for SlowNode {
+        type Error = io::Error;
+
+        async fn trace_transaction(&self, _: TxHash) -> Result<Vec<Trace>, Self::Error> {
+            Err(Unsupported("trace_transaction").into())
+        }
+
+        async fn trace_block(&self, number: u64) -> Result<Vec<Trace>, Self::Error> {
+            tokio::time::sleep(Duration::from_millis(self.end - number)).await;
+            Ok(vec![])
+        }
+
+        async fn block_transactions(&self, _: u64) -> Result<Vec<TxHash>, Self::Error> {
+            Ok(vec![])
+        }
+    }
+
+    /// A sink which crashes when writing `crash_at`, after it wrote the block
+    #[derive(Default)]
+    struct Blocks {
+        written: Vec<u64>,
+        crash_at: Option<u64>,
+    }
+
+    #[async_trait]
+    impl BlockSink for Blocks {
+        type Error = io::Error;
+
+        async fn write(&mut self, block: &Block) -> Result<(), Self::Error> {
+            self.written.push(block.number);
+            if self.crash_at == Some(block.number) {
+                return Err(io::Error::other("crashed"));
+            }
+            Ok(())
+        }
+
+        async fn discard(&mut self, number: u64) -> Result<(), Self::Error> {
+            self.written.retain(|written| *written != number);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn resumes_an_interrupted_run() {
+        let path = std::env::temp_dir().join(format!("backfill-{}.state", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let node = SlowNode { end: 130 };
+        let inspector = BatchInspector::new(
+            vec![Box::new(ERC20::new())],
+            vec![Box::new(TransferReducer::new())],
+        );
+        let backfiller = Backfiller::new(&inspector, &node).with_window(4);
+
+        // crashes after writing block 107, but before checkpointing it
+        let mut sink = Blocks {
+            crash_at: Some(107),
+            ..Default::default()
+        };
+        let err = backfiller
+            .run(100..130, &mut sink, &mut StateFile::new(&path))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, BackfillError::Sink(107, _)));
+        assert_eq!(StateFile::new(&path).load().await.unwrap(), Some(106));
+
+        // the next run starts over from 107, which is written only once
+        sink.crash_at = None;
+        let written = backfiller
+            .run(100..130, &mut sink, &mut StateFile::new(&path))
+            .await
+            .unwrap();
+        assert_eq!(written, 23);
+        assert_eq!(sink.written, (100..130).collect::<Vec<_>>());
+        assert_eq!(StateFile::new(&path).load().await.unwrap(), Some(129));
+
+        // and there is nothing left to do
+        let written = backfiller
+            .run(100..130, &mut sink, &mut StateFile::new(&path))
+            .await
+            .unwrap();
+        assert_eq!(written, 0);
+        fs::remove_file(&path).unwrap();
+    }
+}
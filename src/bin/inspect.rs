@@ -0,0 +1,268 @@
+//! Inspects a transaction or a range of blocks and prints the results as JSON,
+//! without a database.
+//!
+//! Exits with 1 on invalid arguments and with 2 if the node could not be queried.
+use mev_inspect::{
+    addresses::ChainConfig,
+    pipeline::{Pipeline, PipelineOptions, INSPECTORS},
+    types::{
+        gas::{BlockFees, BlockWithFees, TxFees},
+        Block, GasInfo, Inspection,
+    },
+    BatchInspector, EthersTraceProvider, TraceProvider,
+};
+
+use ethers::{
+    providers::{Http, JsonRpcClient, Middleware, Provider, ProviderError},
+    types::{TxHash, U64},
+};
+
+use gumdrop::Options;
+use std::{collections::HashMap, convert::TryFrom, ops::Range, process, sync::Arc};
+
+#[derive(Debug, Options, Clone)]
+struct Opts {
+    help: bool,
+
+    #[options(
+        no_short,
+        default = "http://localhost:8545",
+        help = "The tracing / archival node's URL"
+    )]
+    rpc_url: String,
+
+    #[options(
+        no_short,
+        default = "1",
+        help = "the id of the chain to inspect: 1 (mainnet), 10 (Optimism) or 42161 (Arbitrum)"
+    )]
+    chain: u64,
+
+    #[options(no_short, help = "inspect a transaction")]
+    tx: Option<TxHash>,
+
+    #[options(no_short, help = "inspect a block")]
+    block: Option<u64>,
+
+    #[options(no_short, help = "the first block of a range to inspect")]
+    from: Option<u64>,
+
+    #[options(no_short, help = "the block to stop inspecting at, exclusive")]
+    to: Option<u64>,
+
+    #[options(
+        no_short,
+        help = "comma separated inspectors to run, e.g. uniswap,curve,aave (default: all). ERC20 always runs, since the others need its transfers"
+    )]
+    inspectors: Option<String>,
+
+    #[options(
+        no_short,
+        help = "flag the large one-way trades of market makers as candidate CeFi-DeFi arbitrages, which has false positives"
+    )]
+    statistical_arbs: bool,
+
+    #[options(
+        no_short,
+        help = "flag the tokens pulled with an allowance from accounts which took no part in their tx as candidate exploits, which has false positives"
+    )]
+    allowance_exploits: bool,
+
+    #[options(
+        no_short,
+        help = "flag the chains of trades made by bots which end in another token worth more than they sold as open arbitrages, priced with Uniswap V2's WETH pairs"
+    )]
+    open_arbitrages: bool,
+
+    #[options(
+        no_short,
+        help = "drop the arbitrages and sandwiches which made less than this many USD net of gas, e.g. 0.01, priced with Uniswap V2's WETH pairs"
+    )]
+    min_profit: Option<f64>,
+}
+
+/// What to inspect
+enum Target {
+    Tx(TxHash),
+    Blocks(Range<u64>),
+}
+
+/// Why the inspection failed
+enum Failure {
+    Usage(String),
+    Rpc(anyhow::Error),
+}
+
+impl<E: Into<anyhow::Error>> From<E> for Failure {
+    fn from(err: E) -> Self {
+        Failure::Rpc(err.into())
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    pretty_env_logger::init();
+    let opts = Opts::parse_args_default_or_exit();
+
+    let code = match run(opts).await {
+        Ok(()) => 0,
+        Err(Failure::Usage(msg)) => {
+            eprintln!("{}", msg);
+            1
+        }
+        Err(Failure::Rpc(err)) => {
+            eprintln!("RPC failure: {:?}", err);
+            2
+        }
+    };
+    process::exit(code);
+}
+
+async fn run(opts: Opts) -> Result<(), Failure> {
+    let target = match (opts.tx, opts.block, opts.from, opts.to) {
+        (Some(hash), None, None, None) => Target::Tx(hash),
+        (None, Some(number), None, None) => Target::Blocks(number..number + 1),
+        (None, None, Some(from), Some(to)) if from < to => Target::Blocks(from..to),
+        _ => {
+            return Err(Failure::Usage(
+                "expected either --tx <hash>, --block <n> or --from <n> --to <m> with n < m"
+                    .to_owned(),
+            ))
+        }
+    };
+    let provider = Provider::<Http>::try_from(opts.rpc_url.as_str())
+        .map_err(|err| Failure::Usage(format!("invalid RPC URL: {}", err)))?;
+    // blocks are traced one tx at a time if the node does not serve `trace_block`
+    let tracer = EthersTraceProvider::new(provider.as_ref().clone());
+    let provider = Arc::new(provider);
+    let chain = ChainConfig::from_chain_id(opts.chain)
+        .ok_or_else(|| Failure::Usage(format!("unknown chain {}", opts.chain)))?;
+
+    let selected = match opts.inspectors {
+        Some(ref names) => {
+            let names = names.split(',').map(str::trim).collect::<Vec<_>>();
+            if let Some(name) = names.iter().find(|name| !INSPECTORS.contains(name)) {
+                return Err(Failure::Usage(format!(
+                    "unknown inspector {}, expected one of {}",
+                    name,
+                    INSPECTORS.join(",")
+                )));
+            }
+            names.into_iter().chain(Some("erc20")).collect()
+        }
+        None => INSPECTORS.to_vec(),
+    };
+    let options = PipelineOptions {
+        statistical_arbs: opts.statistical_arbs,
+        allowance_exploits: opts.allowance_exploits,
+        open_arbitrages: opts.open_arbitrages,
+        min_profit: opts.min_profit,
+    };
+    let pipeline = Pipeline::with_inspectors(provider.clone(), &chain, &selected, options)
+        .await
+        .map_err(|err| Failure::Rpc(anyhow::anyhow!("{:?}", err)))?;
+    let processor = pipeline.processor();
+
+    match target {
+        Target::Tx(hash) => {
+            let mut inspection = inspect_tx(&provider, processor, hash).await?;
+            let inspections = std::slice::from_mut(&mut inspection);
+            pipeline
+                .reduce_with_gas(provider.clone(), inspections)
+                .await;
+            print(&inspection);
+        }
+        Target::Blocks(blocks) => {
+            // print each block as soon as it has been inspected
+            for number in blocks {
+                let mut block = inspect_block(&provider, &tracer, processor, number).await?;
+                pipeline
+                    .reduce_with_gas(provider.clone(), &mut block.inspections)
+                    .await;
+                print(&block);
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn inspect_tx<M: Middleware + 'static>(
+    provider: &M,
+    processor: &BatchInspector,
+    hash: TxHash,
+) -> Result<Inspection, Failure> {
+    let traces = provider.trace_transaction(hash).await?;
+    let fees: Option<TxFees> = request(provider, "eth_getTransactionByHash", [hash]).await?;
+    let receipt = provider.get_transaction_receipt(hash).await?;
+    let (fees, receipt) = match (fees, receipt) {
+        (Some(fees), Some(receipt)) => (fees, receipt),
+        _ => return Err(Failure::Usage(format!("tx {:?} not found", hash))),
+    };
+    let block = fees
+        .block_number
+        .ok_or_else(|| Failure::Usage(format!("tx {:?} is still pending", hash)))?;
+    let block_fees: BlockFees = request(provider, "eth_getBlockByNumber", (block, false)).await?;
+
+    let mut logs = HashMap::new();
+    logs.insert(hash, receipt.logs.clone());
+    let mut inspection = processor
+        .inspect_block_with_logs(traces, None, &logs)
+        .pop()
+        .ok_or_else(|| Failure::Usage(format!("no traces found for tx {:?}", hash)))?;
+    inspection.gas = Some(GasInfo::from_fees(&receipt, &fees, &block_fees));
+    Ok(inspection)
+}
+
+async fn inspect_block<M: Middleware + 'static, P: TraceProvider<Error = ProviderError>>(
+    provider: &M,
+    tracer: &P,
+    processor: &BatchInspector,
+    number: u64,
+) -> Result<Block, Failure> {
+    let fees: Option<BlockWithFees> =
+        request(provider, "eth_getBlockByNumber", (U64::from(number), true)).await?;
+    let fees = fees.ok_or_else(|| Failure::Usage(format!("block {} not found", number)))?;
+    let hashes = fees
+        .transactions
+        .iter()
+        .map(|tx| tx.hash)
+        .collect::<Vec<_>>();
+    let traces = tracer.trace_block_or_transactions(number, &hashes).await?;
+    let receipts = provider.parity_block_receipts(number).await?;
+
+    let mut block =
+        Block::from_traces(processor, number, traces, &receipts).with_header(&fees.header);
+    let receipts = receipts
+        .iter()
+        .map(|receipt| (receipt.transaction_hash, receipt))
+        .collect::<HashMap<_, _>>();
+    for tx in fees.transactions.iter() {
+        if let Some(receipt) = receipts.get(&tx.hash) {
+            if let Some(inspection) = block.inspections.iter_mut().find(|i| i.hash == tx.hash) {
+                inspection.gas = Some(GasInfo::from_fees(receipt, tx, &fees.header));
+            }
+        }
+    }
+    Ok(block)
+}
+
+/// Sends a request whose response is parsed into one of our types
+async fn request<M, T, R>(provider: &M, method: &str, params: T) -> Result<R, Failure>
+where
+    M: Middleware,
+    T: std::fmt::Debug + serde::Serialize + Send + Sync,
+    R: serde::de::DeserializeOwned,
+{
+    let response: serde_json::Value = provider
+        .provider()
+        .as_ref()
+        .request(method, params)
+        .await
+        .map_err(Into::<ProviderError>::into)?;
+    Ok(serde_json::from_value(response)?)
+}
+
+fn print<T: serde::Serialize>(value: &T) {
+    let json = serde_json::to_string_pretty(value).expect("inspections are serializable");
+    println!("{}", json);
+}
@@ -0,0 +1,229 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    hash::Hash,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// How many entries the caches keep by default, which bounds their memory
+/// over long backfills
+pub const DEFAULT_MAX_ENTRIES: usize = 1_000_000;
+
+/// A cache which is shared by all its clones, so that the threads of a batch
+/// inspection can fill and read it concurrently. It keeps up to `max_entries`,
+/// evicting the least recently used ones, and its entries can also expire after
+/// a TTL, e.g. for values which may change over time. Values which are keyed by
+/// block, like prices, never change and do not need one.
+///
+/// Cloning the cache does not copy its entries: all the clones are handles to
+/// the same entries, so inserting into any of them, or changing its
+/// `max_entries`, changes the cache of all of them. Instantiate a new cache
+/// for one which is independent.
+pub struct SharedCache<K, V> {
+    inner: Arc<Mutex<Entries<K, V>>>,
+}
+
+struct Entries<K, V> {
+    values: HashMap<K, Entry<V>>,
+    /// The keys by when they were last used, oldest first
+    recency: BTreeMap<u64, K>,
+    /// Incremented on every use, to order the entries
+    clock: u64,
+    max_entries: usize,
+    ttl: Option<Duration>,
+}
+
+struct Entry<V> {
+    value: V,
+    used: u64,
+    inserted: Instant,
+}
+
+impl<K, V> Clone for SharedCache<K, V> {
+    /// Returns another handle to the same entries
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<K, V> fmt::Debug for SharedCache<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let entries = self.inner.lock().unwrap();
+        f.debug_struct("SharedCache")
+            .field("len", &entries.values.len())
+            .field("max_entries", &entries.max_entries)
+            .field("ttl", &entries.ttl)
+            .finish()
+    }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> Default for SharedCache<K, V> {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_ENTRIES)
+    }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> SharedCache<K, V> {
+    /// Instantiates an empty cache which keeps up to `max_entries`
+    pub fn new(max_entries: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Entries {
+                values: HashMap::new(),
+                recency: BTreeMap::new(),
+                clock: 0,
+                max_entries,
+                ttl: None,
+            })),
+        }
+    }
+
+    /// Expires the entries `ttl` after they were inserted
+    pub fn with_ttl(self, ttl: Duration) -> Self {
+        self.inner.lock().unwrap().ttl = Some(ttl);
+        self
+    }
+
+    /// Sets how many entries are kept, evicting the least recently used ones
+    /// which do not fit anymore
+    pub fn set_max_entries(&self, max_entries: usize) {
+        let mut entries = self.inner.lock().unwrap();
+        entries.max_entries = max_entries;
+        entries.evict();
+    }
+
+    /// The value of `key`, unless it was never inserted, got evicted or expired
+    pub fn get(&self, key: &K) -> Option<V> {
+        let mut entries = self.inner.lock().unwrap();
+        let expired = match (entries.values.get(key), entries.ttl) {
+            (None, _) => return None,
+            (Some(entry), Some(ttl)) => entry.inserted.elapsed() > ttl,
+            (Some(_), None) => false,
+        };
+        if expired {
+            entries.remove(key);
+            return None;
+        }
+
+        let used = entries.tick();
+        let entry = entries.values.get_mut(key)?;
+        let last_used = std::mem::replace(&mut entry.used, used);
+        let value = entry.value.clone();
+        entries.recency.remove(&last_used);
+        entries.recency.insert(used, key.clone());
+        Some(value)
+    }
+
+    /// Caches `value` for `key`, replacing the previous one
+    pub fn insert(&self, key: K, value: V) {
+        let mut entries = self.inner.lock().unwrap();
+        entries.remove(&key);
+        let used = entries.tick();
+        entries.recency.insert(used, key.clone());
+        entries.values.insert(
+            key,
+            Entry {
+                value,
+                used,
+                inserted: Instant::now(),
+            },
+        );
+        entries.evict();
+    }
+
+    /// The number of cached entries, including the expired ones which were not
+    /// looked up since they expired
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<K: Eq + Hash + Clone, V> Entries<K, V> {
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn remove(&mut self, key: &K) {
+        if let Some(entry) = self.values.remove(key) {
+            self.recency.remove(&entry.used);
+        }
+    }
+
+    /// Evicts the least recently used entries above `max_entries`
+    fn evict(&mut self) {
+        while self.values.len() > self.max_entries {
+            let oldest = match self.recency.keys().next() {
+                Some(used) => *used,
+                None => return,
+            };
+            if let Some(key) = self.recency.remove(&oldest) {
+                self.values.remove(&key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_the_least_recently_used_entries() {
+        let cache = SharedCache::new(2);
+        cache.insert(1, "a");
+        cache.insert(2, "b");
+        // 1 is used again, so that 2 is the one evicted
+        assert_eq!(cache.get(&1), Some("a"));
+        cache.insert(3, "c");
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&1), Some("a"));
+        assert_eq!(cache.get(&3), Some("c"));
+
+        // replacing an entry does not grow the cache
+        cache.insert(3, "d");
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&3), Some("d"));
+
+        cache.set_max_entries(1);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get(&3), Some("d"));
+    }
+
+    #[test]
+    fn is_shared_by_its_clones_across_threads() {
+        let cache = SharedCache::new(100);
+        let handles = (0..4)
+            .map(|thread| {
+                let cache = cache.clone();
+                std::thread::spawn(move || {
+                    for i in 0..10 {
+                        cache.insert((thread, i), thread * i);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(cache.len(), 40);
+        assert_eq!(cache.get(&(3, 7)), Some(21));
+    }
+
+    #[test]
+    fn expires_entries_after_their_ttl() {
+        let cache = SharedCache::new(10).with_ttl(Duration::from_millis(10));
+        cache.insert("token", 1);
+        assert_eq!(cache.get(&"token"), Some(1));
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(cache.get(&"token"), None);
+        assert!(cache.is_empty());
+    }
+}
@@ -0,0 +1,347 @@
+//! Writes inspections into Postgres tables modeled on flashbots' mev-inspect
+//! schema, whose migrations are in `migrations/`.
+//!
+//! This uses `tokio-postgres` rather than `sqlx`: it is the client which
+//! `MevDB` already depends on, so the crate does not pull in a second driver,
+//! and callers can share their connection between both. The caller supplies
+//! the `Client`, e.g. one checked out of its own pool. Note that `MevDB`'s
+//! default table is also called `mev_inspections`, so it must be given another
+//! name when both write to the same schema.
+use crate::{
+    types::{actions::SpecificAction, Classification, Inspection},
+    DbError,
+};
+use ethers::types::Address;
+use std::collections::HashMap;
+use tokio_postgres::{types::ToSql, Client, Transaction};
+
+/// The schema of the tables, in the order its migrations are applied
+const MIGRATIONS: &[&str] = &[
+    include_str!("../migrations/0001_mev_inspections.sql"),
+    include_str!("../migrations/0002_checked_reason.sql"),
+];
+
+/// The most rows inserted by a single statement, which keeps the statements
+/// below Postgres' limit of 65535 parameters
+const BATCH_SIZE: usize = 1000;
+
+type Row = Vec<Box<dyn ToSql + Sync>>;
+
+/// A table's columns, along with the cast applied to each parameter. Numbers
+/// are sent as text and cast, since `U256`s do not fit in a `Decimal`.
+struct Table {
+    name: &'static str,
+    columns: &'static [(&'static str, &'static str)],
+    key: &'static str,
+}
+
+const INSPECTIONS: Table = Table {
+    name: "mev_inspections",
+    columns: &[
+        ("hash", ""),
+        ("block_number", ""),
+        ("status", ""),
+        ("checked_reason", ""),
+        ("from_address", ""),
+        ("contract", ""),
+        ("proxy_impl", ""),
+        ("protocols", ""),
+        ("gas_used", "::text::numeric"),
+        ("gas_price", "::text::numeric"),
+        ("coinbase_payment", "::text::numeric"),
+    ],
+    key: "hash",
+};
+
+const CLASSIFICATIONS: Table = Table {
+    name: "classifications",
+    columns: &[
+        ("hash", ""),
+        ("trace_address", ""),
+        ("idx", ""),
+        ("block_number", ""),
+        ("kind", ""),
+        ("action", "::text::jsonb"),
+    ],
+    key: "hash, trace_address, idx",
+};
+
+const TRANSFERS: Table = Table {
+    name: "transfers",
+    columns: &[
+        ("hash", ""),
+        ("trace_address", ""),
+        ("idx", ""),
+        ("block_number", ""),
+        ("token", ""),
+        ("from_address", ""),
+        ("to_address", ""),
+        ("amount", "::text::numeric"),
+    ],
+    key: "hash, trace_address, idx",
+};
+
+const ARBITRAGES: Table = Table {
+    name: "arbitrages",
+    columns: &[
+        ("hash", ""),
+        ("trace_address", ""),
+        ("idx", ""),
+        ("block_number", ""),
+        ("token", ""),
+        ("to_address", ""),
+        ("profit", "::text::numeric"),
+        ("capital", "::text::numeric"),
+        ("hops", ""),
+    ],
+    key: "hash, trace_address, idx",
+};
+
+/// Creates the tables if they do not exist yet
+pub async fn migrate(client: &Client) -> Result<(), DbError> {
+    for migration in MIGRATIONS {
+        client.batch_execute(migration).await?;
+    }
+    Ok(())
+}
+
+/// Writes the inspections along with their classified actions, transfers and
+/// arbitrages in a single transaction. Rows which already exist are updated,
+/// so writing the inspections of a block again is idempotent.
+pub async fn write_inspections(
+    client: &mut Client,
+    inspections: &[Inspection],
+) -> Result<(), DbError> {
+    let mut inspection_rows: Vec<Row> = Vec::new();
+    let mut classification_rows: Vec<Row> = Vec::new();
+    let mut transfer_rows: Vec<Row> = Vec::new();
+    let mut arbitrage_rows: Vec<Row> = Vec::new();
+
+    for inspection in inspections {
+        let hash = format!("{:?}", inspection.hash);
+        let block_number = inspection.block_number as i64;
+        let gas = inspection.gas.as_ref();
+        let row: Row = vec![
+            Box::new(hash.clone()),
+            Box::new(block_number),
+            Box::new(format!("{:?}", inspection.status)),
+            Box::new(
+                inspection
+                    .checked_reason
+                    .as_ref()
+                    .map(|reason| format!("{:?}", reason)),
+            ),
+            Box::new(address(inspection.from)),
+            Box::new(address(inspection.contract)),
+            Box::new(inspection.proxy_impl.map(address)),
+            Box::new(
+                inspection
+                    .protocols
+                    .iter()
+                    .map(|protocol| format!("{:?}", protocol))
+                    .collect::<Vec<_>>(),
+            ),
+            Box::new(gas.map(|gas| gas.gas_used.to_string())),
+            Box::new(gas.map(|gas| gas.effective_gas_price().to_string())),
+            Box::new(inspection.coinbase_payment().to_string()),
+        ];
+        inspection_rows.push(row);
+
+        // the position of each action among the ones with the same trace address
+        let mut positions = HashMap::new();
+        for classification in &inspection.actions {
+            let (kind, action) = match classification {
+                Classification::Known(action) => (
+                    action.action.kind().to_owned(),
+                    serde_json::to_string(&action.action),
+                ),
+                Classification::Unknown(call) => {
+                    ("Unknown".to_owned(), serde_json::to_string(&call.call))
+                }
+                Classification::Prune => continue,
+            };
+            let trace_address = classification
+                .trace_address()
+                .iter()
+                .map(|i| *i as i32)
+                .collect::<Vec<_>>();
+            let idx = positions.entry(trace_address.clone()).or_insert(-1i32);
+            *idx += 1;
+            let key = |rest: Row| -> Row {
+                let mut row: Row = vec![
+                    Box::new(hash.clone()),
+                    Box::new(trace_address.clone()),
+                    Box::new(*idx),
+                    Box::new(block_number),
+                ];
+                row.extend(rest);
+                row
+            };
+
+            match classification.as_action() {
+                Some(SpecificAction::Transfer(transfer)) => transfer_rows.push(key(vec![
+                    Box::new(address(transfer.token)),
+                    Box::new(address(transfer.from)),
+                    Box::new(address(transfer.to)),
+                    Box::new(transfer.amount.to_string()),
+                ])),
+                Some(SpecificAction::Arbitrage(arb)) => arbitrage_rows.push(key(vec![
+                    Box::new(address(arb.token)),
+                    Box::new(address(arb.to)),
+                    Box::new(arb.profit.to_string()),
+                    Box::new(arb.capital.to_string()),
+                    Box::new(arb.trades.len() as i32),
+                ])),
+                _ => {}
+            }
+            let action = action.expect("actions are always serializable");
+            classification_rows.push(key(vec![Box::new(kind), Box::new(action)]));
+        }
+    }
+
+    let transaction = client.transaction().await?;
+    let tables = [
+        (INSPECTIONS, inspection_rows),
+        (CLASSIFICATIONS, classification_rows),
+        (TRANSFERS, transfer_rows),
+        (ARBITRAGES, arbitrage_rows),
+    ];
+    for (table, rows) in tables.iter() {
+        for batch in rows.chunks(BATCH_SIZE) {
+            upsert(&transaction, table, batch).await?;
+        }
+    }
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// Inserts the rows with a single statement, overwriting the ones with the
+/// same key
+async fn upsert(transaction: &Transaction<'_>, table: &Table, rows: &[Row]) -> Result<(), DbError> {
+    if rows.is_empty() {
+        return Ok(());
+    }
+
+    let width = table.columns.len();
+    let values = (0..rows.len())
+        .map(|i| {
+            let params = table
+                .columns
+                .iter()
+                .enumerate()
+                .map(|(j, (_, cast))| format!("${}{}", i * width + j + 1, cast))
+                .collect::<Vec<_>>();
+            format!("({})", params.join(", "))
+        })
+        .collect::<Vec<_>>();
+    let columns = table
+        .columns
+        .iter()
+        .map(|(column, _)| *column)
+        .collect::<Vec<_>>();
+    let updates = columns
+        .iter()
+        .map(|column| format!("{} = excluded.{}", column, column))
+        .collect::<Vec<_>>();
+    let statement = format!(
+        "INSERT INTO {} ({}) VALUES {} ON CONFLICT ({}) DO UPDATE SET {}",
+        table.name,
+        columns.join(", "),
+        values.join(", "),
+        table.key,
+        updates.join(", ")
+    );
+
+    let params = rows
+        .iter()
+        .flat_map(|row| row.iter().map(|param| param.as_ref()))
+        .collect::<Vec<_>>();
+    transaction.execute(statement.as_str(), &params).await?;
+    Ok(())
+}
+
+fn address(address: Address) -> String {
+    format!("{:?}", address)
+}
+
+#[cfg(all(test, feature = "postgres-tests"))]
+mod tests {
+    use super::*;
+    use crate::{inspectors::*, reducers::*, test_helpers::*, BatchInspector};
+    use tokio_postgres::{config::Config, NoTls};
+
+    /// The number of rows of each table
+    async fn counts(client: &Client) -> [i64; 4] {
+        let mut counts = [0; 4];
+        for (count, table) in counts.iter_mut().zip(&[
+            "mev_inspections",
+            "classifications",
+            "transfers",
+            "arbitrages",
+        ]) {
+            let row = client
+                .query_one(format!("SELECT count(*) FROM {}", table).as_str(), &[])
+                .await
+                .unwrap();
+            *count = row.get(0);
+        }
+        counts
+    }
+
+    /// This expects a throwaway postgres running on localhost:5432 with user
+    /// `mev_rs_user` and database `mev_inspections_test`, e.g. from
+    /// `docker run -p 5432:5432 -e POSTGRES_USER=mev_rs_user -e POSTGRES_DB=mev_inspections_test -e POSTGRES_HOST_AUTH_METHOD=trust postgres`
+    #[tokio::test]
+    async fn writes_inspections_idempotently() {
+        let mut config = Config::default();
+        config
+            .host("localhost")
+            .user("mev_rs_user")
+            .dbname("mev_inspections_test");
+        let (mut client, connection) = config.connect(NoTls).await.unwrap();
+        tokio::spawn(connection);
+        // a schema of its own keeps the tables apart from the `MevDB` tests,
+        // whose default table is also called `mev_inspections`
+        client
+            .batch_execute(
+                "DROP SCHEMA IF EXISTS db_tests CASCADE;
+                CREATE SCHEMA db_tests;
+                SET search_path TO db_tests;",
+            )
+            .await
+            .unwrap();
+        migrate(&client).await.unwrap();
+
+        let inspector = BatchInspector::new(
+            vec![
+                Box::new(ERC20::new()),
+                Box::new(UniswapV2::new()),
+                Box::new(Balancer::new()),
+            ],
+            vec![
+                Box::new(TradeReducer::new()),
+                Box::new(ArbitrageReducer::new()),
+            ],
+        );
+        let inspections = inspector.inspect_many(TRACES.clone());
+
+        write_inspections(&mut client, &inspections).await.unwrap();
+        let written = counts(&client).await;
+        assert_eq!(written[0], inspections.len() as i64);
+        assert!(written[1] > written[2] && written[2] > 0);
+        assert_eq!(written[3], 4);
+
+        // writing the block again updates the same rows
+        write_inspections(&mut client, &inspections).await.unwrap();
+        assert_eq!(counts(&client).await, written);
+
+        let row = client
+            .query_one(
+                "SELECT profit::text FROM arbitrages WHERE hash = $1",
+                &[&"0x46f4a4d409b44d85e64b1722b8b0f70e9713eb16d2c89da13cffd91486442627"],
+            )
+            .await
+            .unwrap();
+        assert_eq!(row.get::<_, String>(0), "41108016724856778");
+    }
+}
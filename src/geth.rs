@@ -0,0 +1,339 @@
+//! Geth's `debug_traceTransaction` with the `callTracer` returns the calls of
+//! a tx as a tree, which is flattened here into the Parity-style traces the
+//! inspectors expect. The trace address of each call is the path of indices
+//! from the root call to it, and calls are listed depth first, so that the
+//! traces are identical to the ones `trace_transaction` would return, apart
+//! from the output of failed calls which Parity does not report.
+use ethers::types::{
+    Action, ActionType, Address, Bytes, Call, CallResult, CallType, Create, CreateResult, Res,
+    Suicide, Trace, TxHash, H256, U256,
+};
+use serde::Deserialize;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+/// The kind of a frame, as named by Geth after the opcode which created it
+pub enum FrameType {
+    Call,
+    StaticCall,
+    DelegateCall,
+    CallCode,
+    Create,
+    Create2,
+    SelfDestruct,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+/// A frame of Geth's `callTracer` output, along with the frames it created
+pub struct CallFrame {
+    #[serde(rename = "type")]
+    pub kind: FrameType,
+    pub from: Address,
+    /// The callee, the created contract, or the beneficiary of a self-destruct
+    #[serde(default)]
+    pub to: Address,
+    /// Omitted for static calls
+    #[serde(default)]
+    pub value: U256,
+    #[serde(default)]
+    pub gas: U256,
+    #[serde(default)]
+    pub gas_used: U256,
+    #[serde(default)]
+    pub input: Bytes,
+    #[serde(default)]
+    pub output: Bytes,
+    pub error: Option<String>,
+    #[serde(default)]
+    pub calls: Vec<CallFrame>,
+}
+
+impl CallFrame {
+    /// Flattens the frames of tx `hash`, mined in block `block_number`, into
+    /// Parity-style traces
+    pub fn into_traces(self, hash: TxHash, block_number: u64) -> Vec<Trace> {
+        let mut traces = Vec::new();
+        self.flatten(hash, block_number, Vec::new(), &mut traces);
+        traces
+    }
+
+    fn flatten(
+        self,
+        hash: TxHash,
+        block_number: u64,
+        address: Vec<usize>,
+        traces: &mut Vec<Trace>,
+    ) {
+        let subtraces = self.calls.len();
+        let (action, action_type) = self.action();
+        let error = self.error.as_deref().map(parity_error);
+        // unlike Parity, the output of failed calls is kept since it is their
+        // revert reason
+        let result = match (&error, &self.kind) {
+            (_, FrameType::SelfDestruct) => None,
+            (Some(_), FrameType::Create) | (Some(_), FrameType::Create2) => None,
+            (None, FrameType::Create) | (None, FrameType::Create2) => {
+                Some(Res::Create(CreateResult {
+                    gas_used: self.gas_used,
+                    code: self.output.clone(),
+                    address: self.to,
+                }))
+            }
+            _ => Some(Res::Call(CallResult {
+                gas_used: self.gas_used,
+                output: self.output.clone(),
+            })),
+        };
+
+        traces.push(Trace {
+            action,
+            result,
+            trace_address: address.clone(),
+            subtraces,
+            transaction_position: None,
+            transaction_hash: Some(hash),
+            block_number,
+            block_hash: H256::zero(),
+            action_type,
+            error,
+        });
+
+        for (i, call) in self.calls.into_iter().enumerate() {
+            let mut address = address.clone();
+            address.push(i);
+            call.flatten(hash, block_number, address, traces);
+        }
+    }
+
+    fn action(&self) -> (Action, ActionType) {
+        let call_type = match self.kind {
+            FrameType::Call => CallType::Call,
+            FrameType::StaticCall => CallType::StaticCall,
+            FrameType::DelegateCall => CallType::DelegateCall,
+            FrameType::CallCode => CallType::CallCode,
+            FrameType::Create | FrameType::Create2 => {
+                let create = Create {
+                    from: self.from,
+                    value: self.value,
+                    gas: self.gas,
+                    init: self.input.clone(),
+                };
+                return (Action::Create(create), ActionType::Create);
+            }
+            FrameType::SelfDestruct => {
+                let suicide = Suicide {
+                    address: self.from,
+                    refund_address: self.to,
+                    balance: self.value,
+                };
+                return (Action::Suicide(suicide), ActionType::Suicide);
+            }
+        };
+        let value = match call_type {
+            // Geth reports the value of the caller's frame for delegate calls,
+            // but no ETH is sent by them
+            CallType::DelegateCall | CallType::StaticCall => U256::zero(),
+            _ => self.value,
+        };
+        let call = Call {
+            from: self.from,
+            to: self.to,
+            value,
+            gas: self.gas,
+            input: self.input.clone(),
+            call_type,
+        };
+        (Action::Call(call), ActionType::Call)
+    }
+}
+
+/// Renames Geth's most common errors to Parity's
+fn parity_error(error: &str) -> String {
+    match error {
+        "execution reverted" => "Reverted".to_owned(),
+        "out of gas" => "Out of gas".to_owned(),
+        "invalid jump destination" => "Bad jump destination".to_owned(),
+        error => error.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        inspectors::*,
+        reducers::*,
+        types::{inspection::TraceWrapper, Classification, Inspection, RevertReason, Status},
+        BatchInspector,
+    };
+    use std::convert::TryInto;
+
+    #[test]
+    // https://etherscan.io/tx/0x1c85df1fa4c2e9fe7acc7bf204681aa0072b5df05e06bbc8e593777c0dfa5c1c
+    fn classifies_geth_traces_like_parity_traces() {
+        let parity: Vec<Trace> =
+            serde_json::from_str(&std::fs::read_to_string("res/bot_selfdestruct.json").unwrap())
+                .unwrap();
+        let frame: CallFrame = serde_json::from_str(
+            &std::fs::read_to_string("res/bot_selfdestruct.geth.json").unwrap(),
+        )
+        .unwrap();
+        let geth = frame.into_traces(parity[0].transaction_hash.unwrap(), parity[0].block_number);
+
+        assert_eq!(geth.len(), parity.len());
+        for (geth, parity) in geth.iter().zip(&parity) {
+            assert_eq!(geth.trace_address, parity.trace_address);
+            assert_eq!(geth.subtraces, parity.subtraces);
+            assert_eq!(geth.action, parity.action);
+            assert_eq!(geth.error, parity.error);
+            if geth.error.is_none() {
+                assert_eq!(geth.result, parity.result);
+            }
+        }
+
+        let inspector = BatchInspector::new(
+            vec![
+                Box::new(ERC20::new()),
+                Box::new(Aave::new()),
+                Box::new(UniswapV2::new()),
+                Box::new(Balancer::new()),
+                Box::new(ZeroEx::new()),
+                Box::new(Curve::new(vec![])),
+            ],
+            vec![
+                Box::new(LiquidationReducer::new()),
+                Box::new(TradeReducer::new()),
+                Box::new(ArbitrageReducer::new()),
+            ],
+        );
+        let inspect = |traces: Vec<Trace>| {
+            let mut inspection: Inspection = TraceWrapper(traces).try_into().unwrap();
+            inspector.inspect(&mut inspection);
+            inspector.reduce(&mut inspection);
+            inspection.prune();
+            inspection
+        };
+        let geth = inspect(geth);
+        // the first failed call reverted without any data, which Parity does
+        // not report
+        assert_eq!(geth.revert_reason, Some(RevertReason::Empty));
+        let parity = inspect(parity);
+        assert_eq!(parity.revert_reason, None);
+        // nor does Geth tell the position of the tx in its block
+        assert_eq!(geth.tx_index, None);
+        assert_eq!(
+            Inspection {
+                revert_reason: None,
+                tx_index: parity.tx_index,
+                ..geth.clone()
+            },
+            parity
+        );
+        // the bot's trades were within a call which reverted, after which the
+        // tx went on
+        assert_eq!(geth.status, Status::Success);
+        assert!(geth.protocols.is_empty());
+    }
+
+    #[test]
+    fn parses_call_tracer_frames() {
+        let frame: CallFrame = serde_json::from_str(
+            r#"{
+                "type": "CALL",
+                "from": "0x0000000000000000000000000000000000000001",
+                "to": "0x0000000000000000000000000000000000000002",
+                "value": "0x10",
+                "gas": "0x100",
+                "gasUsed": "0x50",
+                "input": "0x",
+                "calls": [
+                    {
+                        "type": "DELEGATECALL",
+                        "from": "0x0000000000000000000000000000000000000002",
+                        "to": "0x0000000000000000000000000000000000000003",
+                        "value": "0x10",
+                        "gas": "0x80",
+                        "gasUsed": "0x20",
+                        "input": "0x12345678",
+                        "calls": [
+                            {
+                                "type": "STATICCALL",
+                                "from": "0x0000000000000000000000000000000000000002",
+                                "to": "0x0000000000000000000000000000000000000004",
+                                "gas": "0x40",
+                                "gasUsed": "0x10",
+                                "input": "0x",
+                                "output": "0x01"
+                            }
+                        ]
+                    },
+                    {
+                        "type": "CREATE2",
+                        "from": "0x0000000000000000000000000000000000000002",
+                        "to": "0x0000000000000000000000000000000000000005",
+                        "value": "0x0",
+                        "gas": "0x20",
+                        "gasUsed": "0x20",
+                        "input": "0x6000",
+                        "error": "out of gas"
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let traces = frame.into_traces(TxHash::zero(), 1);
+
+        let addresses = traces
+            .iter()
+            .map(|trace| trace.trace_address.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(addresses, vec![vec![], vec![0], vec![0, 0], vec![1]]);
+        assert_eq!(traces[0].subtraces, 2);
+        match traces[1].action {
+            Action::Call(ref call) => {
+                assert_eq!(call.call_type, CallType::DelegateCall);
+                assert!(call.value.is_zero());
+            }
+            _ => panic!("expected a delegate call"),
+        }
+        match traces[2].result {
+            Some(Res::Call(ref res)) => assert_eq!(res.output.as_ref(), &[1]),
+            _ => panic!("expected a call result"),
+        }
+        assert_eq!(traces[3].action_type, ActionType::Create);
+        assert_eq!(traces[3].error.as_deref(), Some("Out of gas"));
+        assert_eq!(traces[3].result, None);
+    }
+
+    #[test]
+    fn keeps_the_revert_reason_of_failed_calls() {
+        let mut output = vec![0x08, 0xc3, 0x79, 0xa0];
+        output.extend(ethers::abi::encode(&[ethers::abi::Token::String(
+            "UniswapV2Router: INSUFFICIENT_OUTPUT_AMOUNT".to_owned(),
+        )]));
+        let frame: CallFrame = serde_json::from_value(serde_json::json!({
+            "type": "CALL",
+            "from": "0x0000000000000000000000000000000000000001",
+            "to": "0x0000000000000000000000000000000000000002",
+            "input": "0x",
+            "error": "execution reverted",
+            "output": format!("0x{}", hex::encode(&output)),
+        }))
+        .unwrap();
+
+        let inspection: Inspection = TraceWrapper(frame.into_traces(TxHash::zero(), 1))
+            .try_into()
+            .unwrap();
+        assert_eq!(inspection.status, Status::Reverted);
+        assert_eq!(
+            inspection.revert_reason.unwrap().to_string(),
+            "UniswapV2Router: INSUFFICIENT_OUTPUT_AMOUNT"
+        );
+        // the revert data is not mistaken for the call's return value
+        match inspection.actions[0] {
+            Classification::Unknown(ref call) => assert!(call.output.as_ref().is_empty()),
+            _ => panic!("expected an unknown call"),
+        }
+    }
+}
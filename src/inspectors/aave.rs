@@ -8,26 +8,32 @@ use ethers::{
     contract::BaseContract,
     types::{Address, U256},
 };
+use once_cell::sync::Lazy;
+
+static POOL_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    serde_json::from_str::<Abi>(include_str!("../../abi/aavepool.json"))
+        .expect("could not parse aave abi")
+        .into()
+});
 
 type LiquidationCall = (Address, Address, Address, U256, bool);
 
 #[derive(Clone, Debug)]
 pub struct Aave {
-    pub pool: BaseContract,
+    pub pool: &'static BaseContract,
 }
 
 impl Aave {
     pub fn new() -> Self {
-        Aave {
-            pool: BaseContract::from({
-                serde_json::from_str::<Abi>(include_str!("../../abi/aavepool.json"))
-                    .expect("could not parse aave abi")
-            }),
-        }
+        Aave { pool: &POOL_ABI }
     }
 }
 
 impl Inspector for Aave {
+    fn name(&self) -> &'static str {
+        "aave"
+    }
+
     fn inspect(&self, inspection: &mut Inspection) {
         for action in inspection.actions.iter_mut() {
             match action {
@@ -51,6 +57,7 @@ impl Inspector for Aave {
                                     received_amount: U256::zero(),
                                     from: call.from,
                                     liquidated_user: user,
+                                    auction: None,
                                 },
                                 calltrace.trace_address.clone(),
                             );
@@ -67,8 +74,10 @@ impl Inspector for Aave {
 mod tests {
     use super::*;
     use crate::{
-        inspectors::ERC20, reducers::LiquidationReducer, test_helpers::read_trace, Reducer,
+        inspectors::ERC20, reducers::LiquidationReducer, test_helpers::read_trace, BatchInspector,
+        Reducer,
     };
+    use ethers::types::CallType;
 
     struct MyInspector {
         aave: Aave,
@@ -115,4 +124,62 @@ mod tests {
             "1100830609991235507621"
         );
     }
+
+    #[test]
+    // The lending pool is a proxy which delegates to its implementation, which
+    // in turn delegates liquidations to the `LendingPoolLiquidationManager`
+    fn liquidation_with_folded_delegatecalls() {
+        let inspector = BatchInspector::new(
+            vec![Box::new(Aave::new()), Box::new(ERC20::new())],
+            vec![Box::new(LiquidationReducer::new())],
+        )
+        .fold_delegatecalls(true);
+        let mut inspection = read_trace("simple_liquidation.json");
+        let mut expected = inspection.clone();
+        MyInspector::new().inspect(&mut expected);
+        inspector.inspect(&mut inspection);
+        inspector.reduce(&mut inspection);
+        inspection.prune();
+
+        assert!(inspection.protocols.contains(&Protocol::Aave));
+        assert!(inspection
+            .unknown()
+            .iter()
+            .all(|call| { call.as_ref().call_type != ethers::types::CallType::DelegateCall }));
+        let liquidation = |inspection: &Inspection| {
+            inspection
+                .known()
+                .iter()
+                .find_map(|x| x.as_ref().liquidation().cloned())
+        };
+        assert_eq!(liquidation(&inspection), liquidation(&expected));
+        // only the `DELEGATECALL`s made by the lending pool are gone
+        let pool_calls = |inspection: &Inspection, call_type| {
+            inspection
+                .unknown()
+                .iter()
+                .filter(|call| call.as_ref().from == *AAVE_LENDING_POOL)
+                .filter(|call| call.as_ref().call_type == call_type)
+                .count()
+        };
+        assert!(pool_calls(&expected, CallType::DelegateCall) > 0);
+        for call_type in [CallType::Call, CallType::StaticCall] {
+            assert_eq!(
+                pool_calls(&inspection, call_type.clone()),
+                pool_calls(&expected, call_type)
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    // https://etherscan.io/tx/0x93690c02fc4d58734225d898ea4091df104040450c0f204b6bf6f6850ac4602f
+    fn liquidation_snapshot() {
+        crate::test_helpers::assert_inspection_matches(
+            "0x93690c02fc4d58734225d898ea4091df104040450c0f204b6bf6f6850ac4602f",
+            include_str!(
+                "../../res/snapshots/0x93690c02fc4d58734225d898ea4091df104040450c0f204b6bf6f6850ac4602f.json"
+            ),
+        );
+    }
 }
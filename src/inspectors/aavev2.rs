@@ -0,0 +1,329 @@
+use crate::{
+    addresses::AAVE_V2_LENDING_POOL,
+    inspectors::find_matching,
+    is_nested,
+    types::{
+        actions::{Liquidation, Transfer},
+        Classification, Inspection, Protocol, Provenance,
+    },
+    Inspector,
+};
+use ethers::{
+    abi::parse_abi,
+    contract::BaseContract,
+    types::{Address, U256},
+};
+use once_cell::sync::Lazy;
+
+static POOL_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    parse_abi(&[
+        "function liquidationCall(address collateralAsset, address debtAsset, address user, uint256 debtToCover, bool receiveAToken) external",
+    ])
+    .expect("could not parse aave v2 abi")
+    .into()
+});
+
+static ATOKEN_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    parse_abi(&["function transferOnLiquidation(address from, address to, uint256 value) external"])
+        .expect("could not parse aave v2 atoken abi")
+        .into()
+});
+
+type LiquidationCall = (Address, Address, Address, U256, bool);
+type TransferOnLiquidation = (Address, Address, U256);
+
+#[derive(Clone, Debug)]
+/// An inspector for Aave V2's `LendingPool`
+pub struct AaveV2 {
+    pool: &'static BaseContract,
+    atoken: &'static BaseContract,
+}
+
+impl Default for AaveV2 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AaveV2 {
+    pub fn new() -> Self {
+        AaveV2 {
+            pool: &POOL_ABI,
+            atoken: &ATOKEN_ABI,
+        }
+    }
+}
+
+impl Inspector for AaveV2 {
+    fn name(&self) -> &'static str {
+        "aavev2"
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        // The seized aTokens are moved with `transferOnLiquidation` instead
+        // of a regular transfer, so we classify these as transfers first
+        for action in inspection.actions.iter_mut() {
+            let calltrace = match action.as_call() {
+                Some(inner) => inner,
+                None => continue,
+            };
+            let call = calltrace.as_ref();
+            if call.from != *AAVE_V2_LENDING_POOL {
+                continue;
+            }
+            if let Ok((from, to, amount)) = self
+                .atoken
+                .decode::<TransferOnLiquidation, _>("transferOnLiquidation", &call.input)
+            {
+                *action = Classification::new(
+                    Transfer {
+                        from,
+                        to,
+                        amount,
+                        token: call.to,
+                    },
+                    calltrace.trace_address.clone(),
+                );
+            }
+        }
+
+        let actions = &inspection.actions;
+        let mut liquidations = Vec::new();
+        for (i, action) in actions.iter().enumerate() {
+            let calltrace = match action.as_call() {
+                Some(inner) if inner.as_ref().to == *AAVE_V2_LENDING_POOL => inner,
+                _ => continue,
+            };
+            let call = calltrace.as_ref();
+
+            // https://github.com/aave/protocol-v2/blob/master/contracts/protocol/lendingpool/LendingPoolCollateralManager.sol
+            let (collateral, debt, user, debt_to_cover, _) = match self
+                .pool
+                .decode::<LiquidationCall, _>("liquidationCall", &call.input)
+            {
+                Ok(inner) => inner,
+                Err(_) => continue,
+            };
+            inspection.protocols.insert(Protocol::Aave);
+            let liquidator = call.from;
+            let subtraces = || {
+                actions
+                    .iter()
+                    .enumerate()
+                    .skip(i + 1)
+                    .filter(|(_, a)| is_nested(&calltrace.trace_address, a.trace_address()))
+            };
+
+            // `debtToCover` is capped by the close factor, so the amount which
+            // was actually repaid is the one transferred to the debt's aToken
+            let sent_amount = find_matching(
+                subtraces(),
+                |t| t.transfer(),
+                |t| t.from == liquidator && t.token == debt,
+                true,
+            )
+            .map(|(_, t)| t.amount)
+            .unwrap_or(debt_to_cover);
+
+            // The seized collateral includes the liquidation bonus, so it can
+            // only be read from the transfer to the liquidator. It's either the
+            // underlying or, if `receiveAToken` was set, the collateral's aToken.
+            let received = find_matching(
+                subtraces(),
+                |t| t.transfer(),
+                |t| t.to == liquidator && t.token == collateral,
+                true,
+            )
+            .or_else(|| {
+                find_matching(
+                    subtraces(),
+                    |t| t.transfer(),
+                    |t| t.to == liquidator && t.from == user,
+                    true,
+                )
+            });
+            let (received_token, received_amount) = match received {
+                Some((_, t)) => (t.token, t.amount),
+                // The amount will be set at the reducer
+                None => (collateral, U256::zero()),
+            };
+
+            let liquidation = Classification::new(
+                Liquidation {
+                    sent_token: debt,
+                    sent_amount,
+
+                    received_token,
+                    received_amount,
+                    from: liquidator,
+                    liquidated_user: user,
+                    auction: None,
+                },
+                calltrace.trace_address.clone(),
+            )
+            .with_provenance(Provenance::TransferInferred);
+            liquidations.push((i, liquidation));
+        }
+
+        for (i, liquidation) in liquidations {
+            inspection.actions[i] = liquidation;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        addresses::WETH, inspectors::ERC20, reducers::LiquidationReducer, test_helpers::*, Reducer,
+    };
+    use ethers::abi::Token;
+
+    struct MyInspector {
+        erc20: ERC20,
+        aave: AaveV2,
+        reducer: LiquidationReducer,
+    }
+
+    impl MyInspector {
+        fn inspect(&self, inspection: &mut Inspection) {
+            self.erc20.inspect(inspection);
+            self.aave.inspect(inspection);
+            self.reducer.reduce(inspection);
+            inspection.prune();
+        }
+
+        fn new() -> Self {
+            Self {
+                erc20: ERC20::new(),
+                aave: AaveV2::new(),
+                reducer: LiquidationReducer::new(),
+            }
+        }
+    }
+
+    fn liquidation_call(
+        collateral: Address,
+        debt: Address,
+        user: Address,
+        receive_atoken: bool,
+    ) -> ethers::types::Bytes {
+        calldata(
+            "liquidationCall(address,address,address,uint256,bool)",
+            &[
+                Token::Address(collateral),
+                Token::Address(debt),
+                Token::Address(user),
+                // repay as much as possible
+                Token::Uint(U256::MAX),
+                Token::Bool(receive_atoken),
+            ],
+        )
+    }
+
+    fn liquidation(inspection: &Inspection) -> Liquidation {
+        inspection
+            .known()
+            .iter()
+            .find_map(|x| x.as_ref().liquidation())
+            .cloned()
+            .unwrap()
+    }
+
+    #[test]
+    fn liquidation_for_underlying() {
+        let addrs = addrs();
+        let (liquidator, user, dai, adai, aweth, manager) =
+            (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4], addrs[5]);
+        let pool = *AAVE_V2_LENDING_POOL;
+
+        let input = liquidation_call(*WETH, dai, user, false);
+        let mut inspection = mk_inspection(vec![
+            mk_call(liquidator, pool, input.clone(), vec![]),
+            // the collateral manager
+            mk_call(pool, manager, input, vec![0]),
+            mk_call(aweth, *WETH, transfer(liquidator, 105.into()), vec![0, 0]),
+            mk_call(
+                pool,
+                dai,
+                transfer_from(liquidator, adai, 100.into()),
+                vec![0, 1],
+            ),
+        ]);
+        MyInspector::new().inspect(&mut inspection);
+
+        let liquidation = liquidation(&inspection);
+        assert_eq!(liquidation.sent_token, dai);
+        assert_eq!(liquidation.sent_amount, 100.into());
+        assert_eq!(liquidation.received_token, *WETH);
+        assert_eq!(liquidation.received_amount, 105.into());
+        assert_eq!(liquidation.from, liquidator);
+        assert_eq!(liquidation.liquidated_user, user);
+        assert_eq!(inspection.protocols, crate::set![Protocol::Aave]);
+        // the amounts are the ones of the transfers made by the liquidation
+        let provenance = inspection
+            .known()
+            .iter()
+            .find(|x| x.as_ref().liquidation().is_some())
+            .map(|x| x.provenance);
+        assert_eq!(provenance, Some(Provenance::TransferInferred));
+    }
+
+    #[test]
+    fn liquidation_for_atokens() {
+        let addrs = addrs();
+        let (liquidator, user, dai, adai, aweth, manager) =
+            (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4], addrs[5]);
+        let pool = *AAVE_V2_LENDING_POOL;
+
+        let input = liquidation_call(*WETH, dai, user, true);
+        let mut inspection = mk_inspection(vec![
+            mk_call(liquidator, pool, input.clone(), vec![]),
+            mk_call(pool, manager, input, vec![0]),
+            mk_call(
+                pool,
+                aweth,
+                calldata(
+                    "transferOnLiquidation(address,address,uint256)",
+                    &[
+                        Token::Address(user),
+                        Token::Address(liquidator),
+                        Token::Uint(105.into()),
+                    ],
+                ),
+                vec![0, 0],
+            ),
+            mk_call(
+                pool,
+                dai,
+                transfer_from(liquidator, adai, 100.into()),
+                vec![0, 1],
+            ),
+        ]);
+        MyInspector::new().inspect(&mut inspection);
+
+        let liquidation = liquidation(&inspection);
+        assert_eq!(liquidation.sent_amount, 100.into());
+        assert_eq!(liquidation.received_token, aweth);
+        assert_eq!(liquidation.received_amount, 105.into());
+    }
+
+    #[test]
+    fn other_pool_calls_are_not_liquidations() {
+        let addrs = addrs();
+        let (user, dai) = (addrs[0], addrs[1]);
+        let deposit = calldata(
+            "deposit(address,uint256,address,uint16)",
+            &[
+                Token::Address(dai),
+                Token::Uint(100.into()),
+                Token::Address(user),
+                Token::Uint(0.into()),
+            ],
+        );
+        let mut inspection =
+            mk_inspection(vec![mk_call(user, *AAVE_V2_LENDING_POOL, deposit, vec![])]);
+        MyInspector::new().inspect(&mut inspection);
+        assert!(inspection.protocols.is_empty());
+    }
+}
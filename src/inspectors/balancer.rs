@@ -1,8 +1,8 @@
 use crate::{
-    addresses::BALANCER_PROXY,
+    addresses::{ChainConfig, BALANCER_PROXY},
     inspectors::find_matching,
     traits::Inspector,
-    types::{actions::Trade, Classification, Inspection, Protocol},
+    types::{actions::Trade, Classification, Inspection, Protocol, Provenance},
 };
 
 use ethers::{
@@ -10,23 +10,41 @@ use ethers::{
     contract::BaseContract,
     types::{Address, Call as TraceCall, U256},
 };
+use once_cell::sync::Lazy;
+
+static BPOOL_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    serde_json::from_str::<Abi>(include_str!("../../abi/bpool.json"))
+        .expect("could not parse uniswap abi")
+        .into()
+});
+
+static BPROXY_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    serde_json::from_str::<Abi>(include_str!("../../abi/bproxy.json"))
+        .expect("could not parse uniswap abi")
+        .into()
+});
 
 #[derive(Debug, Clone)]
 /// An inspector for Uniswap
 pub struct Balancer {
-    bpool: BaseContract,
-    bproxy: BaseContract,
+    bpool: &'static BaseContract,
+    bproxy: &'static BaseContract,
+    /// The exchange proxy, which was only deployed on mainnet
+    proxy: Option<Address>,
 }
 
 type Swap = (Address, U256, Address, U256, U256);
 
 impl Inspector for Balancer {
+    fn name(&self) -> &'static str {
+        "balancer"
+    }
+
     fn inspect(&self, inspection: &mut Inspection) {
-        let actions = inspection.actions.to_vec();
+        let actions = &inspection.actions;
         let mut prune = Vec::new();
-        for i in 0..inspection.actions.len() {
-            let action = &mut inspection.actions[i];
-
+        let mut trades = Vec::new();
+        for (i, action) in actions.iter().enumerate() {
             if let Some(calltrace) = action.as_call() {
                 let call = calltrace.as_ref();
                 let (token_in, _, token_out, _, _) = if let Ok(inner) = self
@@ -69,8 +87,11 @@ impl Inspector for Balancer {
                             continue;
                         }
 
-                        *action =
-                            Classification::new(Trade::new(t1.clone(), t2.clone()), Vec::new());
+                        let trade = Classification::new(
+                            Trade::new(t1.clone(), t2.clone()),
+                            calltrace.trace_address.clone(),
+                        );
+                        trades.push((i, trade.with_provenance(Provenance::TransferInferred)));
                         prune.push(j);
                         prune.push(k);
 
@@ -81,6 +102,9 @@ impl Inspector for Balancer {
             }
         }
 
+        for (i, trade) in trades {
+            inspection.actions[i] = trade;
+        }
         prune
             .iter()
             .for_each(|p| inspection.actions[*p] = Classification::Prune);
@@ -91,20 +115,25 @@ impl Inspector for Balancer {
 impl Balancer {
     fn check(&self, call: &TraceCall) -> bool {
         // TODO: Adjust for exchange proxy calls
-        call.to == *BALANCER_PROXY
+        Some(call.to) == self.proxy
     }
 
     /// Constructor
     pub fn new() -> Self {
         Self {
-            bpool: BaseContract::from({
-                serde_json::from_str::<Abi>(include_str!("../../abi/bpool.json"))
-                    .expect("could not parse uniswap abi")
-            }),
-            bproxy: BaseContract::from({
-                serde_json::from_str::<Abi>(include_str!("../../abi/bproxy.json"))
-                    .expect("could not parse uniswap abi")
-            }),
+            bpool: &BPOOL_ABI,
+            bproxy: &BPROXY_ABI,
+            proxy: Some(*BALANCER_PROXY),
+        }
+    }
+
+    /// Instantiates an inspector for `chain`, which only knows of the exchange
+    /// proxy if the chain lists it
+    pub fn with_chain(chain: &ChainConfig) -> Self {
+        Self {
+            proxy: (chain.protocols.get(&BALANCER_PROXY) == Some(&Protocol::Balancer))
+                .then(|| *BALANCER_PROXY),
+            ..Self::new()
         }
     }
 }
@@ -155,19 +184,23 @@ mod tests {
 
         let known = inspection.known();
 
-        assert_eq!(known.len(), 4);
+        assert_eq!(known.len(), 5);
         let t1 = known[0].as_ref().transfer().unwrap();
         assert_eq!(
             t1.amount,
             U256::from_dec_str("134194492674651541324").unwrap()
         );
-        let trade = known[1].as_ref().trade().unwrap();
+        // the bot approves the token it sells before trading it
+        let approval = known[1].as_ref().approval().unwrap();
+        let trade = known[2].as_ref().trade().unwrap();
+        assert_eq!(known[2].provenance, Provenance::TransferInferred);
+        assert_eq!(approval.token, trade.t1.token);
         assert_eq!(
             trade.t1.amount,
             U256::from_dec_str("7459963749616500736").unwrap()
         );
-        let _t2 = known[2].as_ref().transfer().unwrap();
-        let _t3 = known[3].as_ref().transfer().unwrap();
+        let _t2 = known[3].as_ref().transfer().unwrap();
+        let _t3 = known[4].as_ref().transfer().unwrap();
     }
 
     #[test]
@@ -197,4 +230,16 @@ mod tests {
         let t2 = known[2].as_ref().transfer().unwrap();
         assert_eq!(ADDRESSBOOK.get(&t2.token).unwrap(), "COMP",);
     }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    // https://etherscan.io/tx/0x46f4a4d409b44d85e64b1722b8b0f70e9713eb16d2c89da13cffd91486442627
+    fn balancer_uni_arb_snapshot() {
+        assert_inspection_matches(
+            "0x46f4a4d409b44d85e64b1722b8b0f70e9713eb16d2c89da13cffd91486442627",
+            include_str!(
+                "../../res/snapshots/0x46f4a4d409b44d85e64b1722b8b0f70e9713eb16d2c89da13cffd91486442627.json"
+            ),
+        );
+    }
 }
@@ -0,0 +1,471 @@
+use crate::{
+    addresses::{ChainConfig, BALANCER_VAULT, NATIVE_ETH},
+    is_nested,
+    traits::Inspector,
+    types::{
+        actions::{Trade, Transfer},
+        classification::CallTrace,
+        Classification, Inspection, Protocol, TraceAddress,
+    },
+};
+
+use ethers::{
+    abi::Abi,
+    contract::{decode_function_data, BaseContract},
+    types::{Address, Bytes, H256, I256, U256},
+    utils::id,
+};
+use once_cell::sync::Lazy;
+
+static VAULT_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    serde_json::from_str::<Abi>(include_str!("../../abi/balancervault.json"))
+        .expect("could not parse balancer v2 vault abi")
+        .into()
+});
+
+// Type aliases for the Vault's params structs
+type SingleSwap = (H256, u8, Address, Address, U256, Bytes);
+type BatchSwapStep = (H256, U256, U256, U256, Vec<u8>);
+type FundManagement = (Address, bool, Address, bool);
+type Swap = (SingleSwap, FundManagement, U256, U256);
+type BatchSwap = (
+    u8,
+    Vec<BatchSwapStep>,
+    Vec<Address>,
+    FundManagement,
+    Vec<I256>,
+    U256,
+);
+
+/// `SwapKind.GIVEN_IN`, the amount of a swap is its input. Otherwise it is
+/// `GIVEN_OUT` and the amount is its output.
+const GIVEN_IN: u8 = 0;
+
+/// The `onSwap` hooks of the general and the minimal swap info pools
+const ON_SWAP: [&str; 2] = [
+    "onSwap((uint8,address,address,uint256,bytes32,uint256,address,address,bytes),uint256[],uint256,uint256)",
+    "onSwap((uint8,address,address,uint256,bytes32,uint256,address,address,bytes),uint256,uint256)",
+];
+
+/// A single hop through a pool
+struct Hop {
+    pool: Address,
+    token_in: Address,
+    token_out: Address,
+    amount_in: U256,
+    amount_out: U256,
+}
+
+#[derive(Debug, Clone)]
+/// An inspector for the Balancer V2 Vault
+pub struct BalancerV2 {
+    vault: &'static BaseContract,
+    /// The Vault, which is deployed at the same address on all the chains
+    /// which have it
+    address: Option<Address>,
+    /// The placeholder which the Vault's native asset is classified with
+    native: Address,
+}
+
+impl Inspector for BalancerV2 {
+    fn name(&self) -> &'static str {
+        "balancerv2"
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        let actions = &inspection.actions;
+        let mut prune = Vec::new();
+        let mut trades = Vec::new();
+        for (i, action) in actions.iter().enumerate() {
+            let calltrace = match action.as_call() {
+                Some(inner) if Some(inner.as_ref().to) == self.address => inner,
+                _ => continue,
+            };
+            let trace_address = &calltrace.trace_address;
+            // the Vault asks each pool for the calculated amount of its hop
+            let quotes = actions
+                .iter()
+                .skip(i + 1)
+                .filter(|a| is_nested(trace_address, a.trace_address()))
+                .filter(|a| a.trace_address().len() == trace_address.len() + 1)
+                .filter_map(|a| a.as_call())
+                .filter(|c| is_on_swap(&c.as_ref().input))
+                .map(|c| decode_amount(&c.output))
+                .collect::<Option<Vec<_>>>();
+
+            let res = match quotes {
+                Some(quotes) => self.decode_swaps(calltrace, &quotes),
+                None => None,
+            };
+            if let Some((sender, recipient, hops)) = res {
+                inspection.protocols.insert(Protocol::Balancer);
+
+                // The trades are built from the call and the pools' quotes rather
+                // than from the transfers, since swaps from / to internal
+                // balances do not transfer any tokens. Batch swaps only transfer
+                // the net amounts, so there are no transfers per hop either.
+                let hops = hops
+                    .into_iter()
+                    .map(|hop| Trade {
+                        t1: Transfer {
+                            from: sender,
+                            to: hop.pool,
+                            amount: hop.amount_in,
+                            token: hop.token_in,
+                        },
+                        t2: Transfer {
+                            from: hop.pool,
+                            to: recipient,
+                            amount: hop.amount_out,
+                            token: hop.token_out,
+                        },
+                    })
+                    .collect::<Vec<_>>();
+                trades.push((i, hops));
+                prune.push(trace_address.clone());
+            }
+        }
+
+        // remove the pool calls and the transfers of the swaps
+        for trace_address in prune {
+            inspection
+                .actions
+                .iter_mut()
+                .filter(|a| is_nested(&trace_address, a.trace_address()))
+                .for_each(|a| *a = Classification::Prune);
+        }
+
+        // replace each Vault call with its trades, in reverse so that the
+        // indices of the earlier calls stay valid
+        for (i, hops) in trades.into_iter().rev() {
+            let trace_address = TraceAddress::from(inspection.actions[i].trace_address());
+            inspection.actions.splice(
+                i..=i,
+                hops.into_iter()
+                    .map(|trade| Classification::new(trade, trace_address.clone())),
+            );
+        }
+    }
+}
+
+impl Default for BalancerV2 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BalancerV2 {
+    /// Constructor
+    pub fn new() -> Self {
+        Self {
+            vault: &VAULT_ABI,
+            address: Some(*BALANCER_VAULT),
+            native: NATIVE_ETH,
+        }
+    }
+
+    /// Instantiates an inspector for `chain`, which only knows of the Vault if
+    /// the chain lists it
+    pub fn with_chain(chain: &ChainConfig) -> Self {
+        Self {
+            address: (chain.protocols.get(&BALANCER_VAULT) == Some(&Protocol::Balancer))
+                .then(|| *BALANCER_VAULT),
+            native: chain.native,
+            ..Self::new()
+        }
+    }
+
+    /// The Vault uses the zero address for ETH
+    fn asset(&self, asset: Address) -> Address {
+        if asset.is_zero() {
+            self.native
+        } else {
+            asset
+        }
+    }
+
+    /// Decodes `swap` and `batchSwap` into their sender, recipient and hops
+    fn decode_swaps(
+        &self,
+        calltrace: &CallTrace,
+        quotes: &[U256],
+    ) -> Option<(Address, Address, Vec<Hop>)> {
+        let input = &calltrace.as_ref().input;
+        if let Ok(((pool_id, kind, asset_in, asset_out, amount, _), funds, _, _)) =
+            self.vault.decode::<Swap, _>("swap", input)
+        {
+            let function = self.vault.abi().function("swap").ok()?;
+            let calculated = decode_function_data(function, &calltrace.output, false).ok()?;
+            let (amount_in, amount_out) = amounts(kind, amount, calculated);
+            let hop = Hop {
+                pool: pool_address(pool_id),
+                token_in: self.asset(asset_in),
+                token_out: self.asset(asset_out),
+                amount_in,
+                amount_out,
+            };
+            Some((funds.0, funds.2, vec![hop]))
+        } else if let Ok((kind, steps, assets, funds, _, _)) =
+            self.vault.decode::<BatchSwap, _>("batchSwap", input)
+        {
+            if steps.len() != quotes.len() {
+                return None;
+            }
+
+            let mut hops = Vec::new();
+            let mut previous = U256::zero();
+            for ((pool_id, asset_in, asset_out, amount, _), calculated) in
+                steps.into_iter().zip(quotes)
+            {
+                // a zero amount means that the previous hop's calculated amount
+                // is used, which is how multi-hop swaps are chained
+                let given = if amount.is_zero() { previous } else { amount };
+                let (amount_in, amount_out) = amounts(kind, given, *calculated);
+                hops.push(Hop {
+                    pool: pool_address(pool_id),
+                    token_in: self.asset(*assets.get(asset_in.as_usize())?),
+                    token_out: self.asset(*assets.get(asset_out.as_usize())?),
+                    amount_in,
+                    amount_out,
+                });
+                previous = *calculated;
+            }
+            Some((funds.0, funds.2, hops))
+        } else {
+            None
+        }
+    }
+}
+
+/// Returns the `(amount_in, amount_out)` of a swap given its kind
+fn amounts(kind: u8, given: U256, calculated: U256) -> (U256, U256) {
+    if kind == GIVEN_IN {
+        (given, calculated)
+    } else {
+        (calculated, given)
+    }
+}
+
+/// The first 20 bytes of a pool id are the pool's address
+fn pool_address(pool_id: H256) -> Address {
+    Address::from_slice(&pool_id[..20])
+}
+
+fn is_on_swap(input: &Bytes) -> bool {
+    ON_SWAP
+        .iter()
+        .any(|signature| input.as_ref().get(..4) == Some(&id(signature)[..]))
+}
+
+fn decode_amount(output: &Bytes) -> Option<U256> {
+    output.as_ref().get(..32).map(U256::from_big_endian)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        addresses::{ETH, WETH},
+        inspectors::ERC20,
+        test_helpers::*,
+    };
+    use ethers::abi::Token;
+
+    struct MyInspector {
+        erc20: ERC20,
+        balancer: BalancerV2,
+    }
+
+    impl MyInspector {
+        fn inspect(&self, inspection: &mut Inspection) {
+            self.erc20.inspect(inspection);
+            self.balancer.inspect(inspection);
+            inspection.prune();
+        }
+
+        fn new() -> Self {
+            Self {
+                erc20: ERC20::new(),
+                balancer: BalancerV2::new(),
+            }
+        }
+    }
+
+    fn pool_id(pool: Address) -> Token {
+        let mut id = pool.as_bytes().to_vec();
+        id.extend_from_slice(&[0; 12]);
+        Token::FixedBytes(id)
+    }
+
+    fn funds(sender: Address, recipient: Address, to_internal: bool) -> Token {
+        Token::Tuple(vec![
+            Token::Address(sender),
+            Token::Bool(false),
+            Token::Address(recipient),
+            Token::Bool(to_internal),
+        ])
+    }
+
+    fn on_swap(
+        vault: Address,
+        pool: Address,
+        amount: u64,
+        trace_address: Vec<usize>,
+    ) -> Classification {
+        with_output(
+            mk_call(vault, pool, calldata(ON_SWAP[1], &[]), trace_address),
+            &[Token::Uint(amount.into())],
+        )
+    }
+
+    #[test]
+    fn batch_swap() {
+        let addrs = addrs();
+        let (usr, pool1, pool2, dai, usdc) = (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4]);
+        let vault = *BALANCER_VAULT;
+
+        let step = |pool, asset_in: u64, asset_out: u64, amount: u64| {
+            Token::Tuple(vec![
+                pool_id(pool),
+                Token::Uint(asset_in.into()),
+                Token::Uint(asset_out.into()),
+                Token::Uint(amount.into()),
+                Token::Bytes(vec![]),
+            ])
+        };
+        let input = calldata(
+            "batchSwap(uint8,(bytes32,uint256,uint256,uint256,bytes)[],address[],(address,bool,address,bool),int256[],uint256)",
+            &[
+                Token::Uint(GIVEN_IN.into()),
+                Token::Array(vec![step(pool1, 0, 1, 100), step(pool2, 1, 2, 0)]),
+                Token::Array(vec![
+                    Token::Address(*WETH),
+                    Token::Address(dai),
+                    Token::Address(usdc),
+                ]),
+                // the output stays in the Vault
+                funds(usr, usr, true),
+                Token::Array(vec![]),
+                Token::Uint(0.into()),
+            ],
+        );
+
+        let mut inspection = mk_inspection(vec![
+            mk_call(usr, vault, input, vec![]),
+            on_swap(vault, pool1, 250, vec![0]),
+            on_swap(vault, pool2, 249, vec![1]),
+            mk_call(vault, *WETH, transfer_from(usr, vault, 100.into()), vec![2]),
+        ]);
+        MyInspector::new().inspect(&mut inspection);
+
+        let trades = inspection
+            .known()
+            .iter()
+            .map(|a| a.as_ref().trade().cloned().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(trades.len(), 2);
+        assert_eq!(trades[0].t1.token, *WETH);
+        assert_eq!(trades[0].t1.amount, 100.into());
+        assert_eq!(trades[0].t1.to, pool1);
+        assert_eq!(trades[0].t2.token, dai);
+        assert_eq!(trades[0].t2.amount, 250.into());
+        assert_eq!(trades[1].t1.token, dai);
+        assert_eq!(trades[1].t1.amount, 250.into());
+        assert_eq!(trades[1].t2.token, usdc);
+        assert_eq!(trades[1].t2.amount, 249.into());
+        assert_eq!(trades[1].t2.from, pool2);
+
+        assert!(inspection.unknown().is_empty());
+        assert_eq!(inspection.protocols, crate::set![Protocol::Balancer]);
+    }
+
+    #[test]
+    fn swap_given_out() {
+        let addrs = addrs();
+        let (usr, pool, dai) = (addrs[0], addrs[1], addrs[2]);
+        let vault = *BALANCER_VAULT;
+
+        let input = calldata(
+            "swap((bytes32,uint8,address,address,uint256,bytes),(address,bool,address,bool),uint256,uint256)",
+            &[
+                Token::Tuple(vec![
+                    pool_id(pool),
+                    Token::Uint(1.into()),
+                    // ETH
+                    Token::Address(Address::zero()),
+                    Token::Address(dai),
+                    Token::Uint(1000.into()),
+                    Token::Bytes(vec![]),
+                ]),
+                funds(usr, usr, false),
+                Token::Uint(0.into()),
+                Token::Uint(0.into()),
+            ],
+        );
+
+        let mut inspection = mk_inspection(vec![
+            // the calculated amount in
+            with_output(mk_call(usr, vault, input, vec![]), &[Token::Uint(3.into())]),
+            on_swap(vault, pool, 3, vec![0]),
+            mk_call(vault, dai, transfer(usr, 1000.into()), vec![1]),
+        ]);
+        MyInspector::new().inspect(&mut inspection);
+
+        let known = inspection.known();
+        assert_eq!(known.len(), 1);
+        let trade = known[0].as_ref().trade().unwrap();
+        assert_eq!(trade.t1.token, *ETH);
+        assert_eq!(trade.t1.amount, 3.into());
+        assert_eq!(trade.t2.token, dai);
+        assert_eq!(trade.t2.amount, 1000.into());
+    }
+
+    #[test]
+    fn only_inspects_the_vault_of_the_chain() {
+        let addrs = addrs();
+        let (usr, pool, dai, native) = (addrs[0], addrs[1], addrs[2], addrs[3]);
+        let vault = *BALANCER_VAULT;
+
+        let input = calldata(
+            "swap((bytes32,uint8,address,address,uint256,bytes),(address,bool,address,bool),uint256,uint256)",
+            &[
+                Token::Tuple(vec![
+                    pool_id(pool),
+                    Token::Uint(GIVEN_IN.into()),
+                    Token::Address(dai),
+                    Token::Address(Address::zero()),
+                    Token::Uint(1000.into()),
+                    Token::Bytes(vec![]),
+                ]),
+                funds(usr, usr, false),
+                Token::Uint(0.into()),
+                Token::Uint(0.into()),
+            ],
+        );
+        let swap = || {
+            mk_inspection(vec![
+                with_output(
+                    mk_call(usr, vault, input.clone(), vec![]),
+                    &[Token::Uint(3.into())],
+                ),
+                on_swap(vault, pool, 3, vec![0]),
+                mk_call(vault, dai, transfer_from(usr, vault, 1000.into()), vec![1]),
+            ])
+        };
+
+        // the Vault is on Optimism, whose native token is classified as `native`
+        let mut optimism = ChainConfig::optimism();
+        optimism.native = native;
+        let mut inspection = swap();
+        BalancerV2::with_chain(&optimism).inspect(&mut inspection);
+        let trades = inspection.trades().collect::<Vec<_>>();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].t2.token, native);
+        assert_eq!(trades[0].t2.amount, 3.into());
+
+        // but not on a chain which does not list it
+        let mut inspection = swap();
+        BalancerV2::with_chain(&ChainConfig::new(137, optimism.weth)).inspect(&mut inspection);
+        assert_eq!(inspection.trades().count(), 0);
+    }
+}
@@ -0,0 +1,265 @@
+use crate::{
+    addresses::{ChainConfig, ETH, WETH},
+    inspectors::{inspect_routed_trades, RoutedTrade, ERC20},
+    traits::Inspector,
+    types::{Inspection, Protocol},
+};
+
+use ethers::{
+    abi::parse_abi,
+    contract::BaseContract,
+    types::{Address, Call as TraceCall, U256},
+};
+use once_cell::sync::Lazy;
+
+static NETWORK_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    parse_abi(&[
+        "function convertByPath(address[], uint256, uint256, address, address, uint256) returns (uint256)",
+        "function convertByPath2(address[], uint256, uint256, address) returns (uint256)",
+        "function tradeBySourceAmount(address, address, uint256, uint256, uint256, address) returns (uint256)",
+    ])
+    .expect("could not parse bancor abi")
+    .into()
+});
+
+// Type aliases for the BancorNetwork's conversion functions
+type ConvertByPath = (Vec<Address>, U256, U256, Address, Address, U256);
+type ConvertByPath2 = (Vec<Address>, U256, U256, Address);
+type TradeBySourceAmount = (Address, Address, U256, U256, U256, Address);
+
+#[derive(Debug, Clone)]
+/// An inspector for the conversions of Bancor V2's and V3's BancorNetwork.
+///
+/// V2 converts along a path which alternates tokens and the anchors of the
+/// pools between them, e.g. `[DAI, DAI/BNT, BNT, BNT/ETH, ETH]`, while V3 trades
+/// from a source to a target token through BNT. Either way the pools' calls get
+/// collapsed into a single trade between the caller and the network. Like
+/// Kyber, Bancor denotes ETH with `0xeee...eee`, which is replaced with WETH,
+/// and must run before `ERC20` classifies the ETH paid to it as a transfer.
+pub struct Bancor {
+    network: &'static BaseContract,
+    erc20: ERC20,
+    weth: Address,
+}
+
+impl Default for Bancor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bancor {
+    /// Constructor
+    pub fn new() -> Self {
+        Self::with_weth(*WETH)
+    }
+
+    /// Instantiates an inspector for a chain whose wrapped ETH is `weth`
+    pub fn with_weth(weth: Address) -> Self {
+        Self {
+            network: &NETWORK_ABI,
+            erc20: ERC20::new(),
+            weth,
+        }
+    }
+
+    /// Instantiates an inspector for `chain`
+    pub fn with_chain(chain: &ChainConfig) -> Self {
+        Self {
+            erc20: ERC20::with_chain(chain),
+            ..Self::with_weth(chain.weth)
+        }
+    }
+
+    fn decode(&self, call: &TraceCall) -> Option<RoutedTrade> {
+        let (src_token, src_amount, dst_token, beneficiary) =
+            if let Some((path, amount, beneficiary)) = self.decode_path(call) {
+                // the anchors are between the tokens, so a path has an odd length
+                if path.len() < 3 || path.len() % 2 == 0 {
+                    return None;
+                }
+                (path[0], amount, path[path.len() - 1], beneficiary)
+            } else if let Ok((src, dst, amount, _, _, beneficiary)) =
+                self.network
+                    .decode::<TradeBySourceAmount, _>("tradeBySourceAmount", &call.input)
+            {
+                (src, amount, dst, beneficiary)
+            } else {
+                return None;
+            };
+
+        Some(RoutedTrade {
+            // the ETH actually paid
+            src_amount: if src_token == *ETH && !call.value.is_zero() {
+                call.value
+            } else {
+                src_amount
+            },
+            src_token,
+            dst_token,
+            // the beneficiary defaults to the caller
+            receiver: if beneficiary.is_zero() {
+                call.from
+            } else {
+                beneficiary
+            },
+        })
+    }
+
+    /// Decodes the `(path, amount, beneficiary)` of a V2 conversion
+    fn decode_path(&self, call: &TraceCall) -> Option<(Vec<Address>, U256, Address)> {
+        if let Ok((path, amount, _, beneficiary, _, _)) = self
+            .network
+            .decode::<ConvertByPath, _>("convertByPath", &call.input)
+        {
+            Some((path, amount, beneficiary))
+        } else if let Ok((path, amount, _, beneficiary)) = self
+            .network
+            .decode::<ConvertByPath2, _>("convertByPath2", &call.input)
+        {
+            Some((path, amount, beneficiary))
+        } else {
+            None
+        }
+    }
+}
+
+impl Inspector for Bancor {
+    fn name(&self) -> &'static str {
+        "bancor"
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        inspect_routed_trades(
+            inspection,
+            &self.erc20,
+            self.weth,
+            Protocol::Bancor,
+            |call| self.decode(call),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+    use crate::types::Provenance;
+    use ethers::{abi::Token, types::Bytes};
+
+    fn inspect(inspection: &mut Inspection) {
+        Bancor::new().inspect(inspection);
+        ERC20::new().inspect(inspection);
+        inspection.prune();
+    }
+
+    #[test]
+    fn convert_by_path_through_bnt() {
+        let addrs = addrs();
+        let (usr, network, dai, dai_bnt, bnt, bnt_eth, pool1, pool2) = (
+            addrs[0], addrs[1], addrs[2], addrs[3], addrs[4], addrs[5], addrs[6], addrs[7],
+        );
+
+        let input = calldata(
+            "convertByPath(address[],uint256,uint256,address,address,uint256)",
+            &[
+                Token::Array(
+                    [dai, dai_bnt, bnt, bnt_eth, *ETH]
+                        .iter()
+                        .map(|a| Token::Address(*a))
+                        .collect(),
+                ),
+                Token::Uint(3000.into()),
+                Token::Uint(1.into()),
+                Token::Address(Address::zero()),
+                Token::Address(Address::zero()),
+                Token::Uint(0.into()),
+            ],
+        );
+        let mut inspection = mk_inspection(vec![
+            mk_call(usr, network, input, vec![]),
+            mk_call(
+                network,
+                dai,
+                transfer_from(usr, pool1, 3000.into()),
+                vec![0],
+            ),
+            mk_call(network, pool1, Bytes::default(), vec![1]),
+            mk_call(pool1, bnt, transfer(pool2, 1000.into()), vec![1, 0]),
+            mk_call(network, pool2, Bytes::default(), vec![2]),
+            with_value(
+                mk_call(pool2, network, Bytes::default(), vec![2, 0]),
+                2.into(),
+            ),
+            with_value(mk_call(network, usr, Bytes::default(), vec![3]), 2.into()),
+        ]);
+        inspect(&mut inspection);
+
+        let known = inspection.known();
+        assert_eq!(known.len(), 1);
+        let trade = known[0].as_ref().trade().unwrap();
+        assert_eq!(
+            (trade.t1.from, trade.t1.token, trade.t1.amount),
+            (usr, dai, 3000.into())
+        );
+        assert_eq!(
+            (trade.t2.to, trade.t2.token, trade.t2.amount),
+            (usr, *WETH, 2.into())
+        );
+        assert_eq!(known[0].provenance, Provenance::TransferInferred);
+        assert!(inspection.unknown().is_empty());
+        assert_eq!(inspection.protocols, crate::set![Protocol::Bancor]);
+    }
+
+    #[test]
+    fn trade_by_source_amount_from_eth() {
+        let addrs = addrs();
+        let (usr, network, beneficiary, dai) = (addrs[0], addrs[1], addrs[2], addrs[3]);
+
+        let input = calldata(
+            "tradeBySourceAmount(address,address,uint256,uint256,uint256,address)",
+            &[
+                Token::Address(*ETH),
+                Token::Address(dai),
+                Token::Uint(2.into()),
+                Token::Uint(1.into()),
+                Token::Uint(0.into()),
+                Token::Address(beneficiary),
+            ],
+        );
+        let mut inspection = mk_inspection(vec![with_output(
+            with_value(mk_call(usr, network, input, vec![]), 2.into()),
+            &[Token::Uint(6000.into())],
+        )]);
+        inspect(&mut inspection);
+
+        let trade = inspection.known()[0].as_ref().trade().cloned().unwrap();
+        assert_eq!((trade.t1.token, trade.t1.amount), (*WETH, 2.into()));
+        assert_eq!(
+            (trade.t2.to, trade.t2.token, trade.t2.amount),
+            (beneficiary, dai, 6000.into())
+        );
+    }
+
+    #[test]
+    fn even_paths_are_not_conversions() {
+        let addrs = addrs();
+        let (usr, network, dai, bnt) = (addrs[0], addrs[1], addrs[2], addrs[3]);
+
+        let input = calldata(
+            "convertByPath2(address[],uint256,uint256,address)",
+            &[
+                Token::Array(vec![Token::Address(dai), Token::Address(bnt)]),
+                Token::Uint(3000.into()),
+                Token::Uint(1.into()),
+                Token::Address(usr),
+            ],
+        );
+        let mut inspection = mk_inspection(vec![with_output(
+            mk_call(usr, network, input, vec![]),
+            &[Token::Uint(1000.into())],
+        )]);
+        inspect(&mut inspection);
+        assert!(inspection.known().is_empty());
+    }
+}
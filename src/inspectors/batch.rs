@@ -5,26 +5,38 @@ use std::pin::Pin;
 use std::task::{Context, Poll};
 
 use ethers::prelude::Middleware;
-use ethers::types::{Block, BlockNumber, Trace, Transaction, TransactionReceipt, TxHash, U256};
+use ethers::types::{
+    Action, Address, Block, BlockNumber, Log, RewardType, Trace, Transaction, TransactionReceipt,
+    TransactionRequest, TxHash, U256,
+};
 use futures::{
     stream::{self, FuturesUnordered},
     Stream, StreamExt, TryFutureExt,
 };
-use itertools::Itertools;
+use indexmap::IndexMap;
+use rayon::prelude::*;
 use thiserror::Error;
 
+use crate::addresses::FILTER;
 use crate::mevdb::BatchInserts;
-use crate::types::{EvalError, Evaluation};
+use crate::types::{EvalError, Evaluation, GasInfo, Status};
 use crate::{
+    geth::CallFrame,
+    inspectors::InspectorRegistry,
+    reducers::FrontrunReducer,
+    simulation::{PendingBlock, SimulationError},
     types::inspection::{Inspection, TraceWrapper},
-    HistoricalPrice, Inspector, MevDB, Reducer,
+    BlockReducer, HistoricalPrice, Inspector, MevDB, Reducer, TraceProvider,
 };
 use std::sync::Arc;
 
 /// Classifies traces according to the provided inspectors
 pub struct BatchInspector {
-    inspectors: Vec<Box<dyn Inspector + Send + Sync>>,
+    inspectors: InspectorRegistry,
     reducers: Vec<Box<dyn Reducer + Send + Sync>>,
+    block_reducers: Vec<Box<dyn BlockReducer + Send + Sync>>,
+    fold_delegatecalls: bool,
+    max_frames: Option<usize>,
 }
 
 impl BatchInspector {
@@ -32,52 +44,286 @@ impl BatchInspector {
     pub fn new(
         inspectors: Vec<Box<dyn Inspector + Send + Sync>>,
         reducers: Vec<Box<dyn Reducer + Send + Sync>>,
+    ) -> Self {
+        Self::from_registry(InspectorRegistry::new(inspectors), reducers)
+    }
+
+    /// Instantiates a batch inspector which runs the inspectors of `registry`
+    pub fn from_registry(
+        inspectors: InspectorRegistry,
+        reducers: Vec<Box<dyn Reducer + Send + Sync>>,
     ) -> Self {
         Self {
             inspectors,
             reducers,
+            block_reducers: Vec::new(),
+            fold_delegatecalls: false,
+            max_frames: None,
         }
     }
 
+    /// Sets the reducers which get run over all the inspections of a block
+    pub fn with_block_reducers(
+        mut self,
+        block_reducers: Vec<Box<dyn BlockReducer + Send + Sync>>,
+    ) -> Self {
+        self.block_reducers = block_reducers;
+        self
+    }
+
+    /// Folds the `DELEGATECALL` frames of each inspection into their callers
+    /// before inspecting it, so that the calls made by an implementation look
+    /// like they were made by its proxy. Disabled by default.
+    pub fn fold_delegatecalls(mut self, fold: bool) -> Self {
+        self.fold_delegatecalls = fold;
+        self
+    }
+
+    /// Leaves the transactions with more than `max_frames` calls unclassified,
+    /// and marks them as `Status::TooLarge` unless they reverted. Only their
+    /// top level call is converted from their traces. The inspectors and the
+    /// reducers scan the actions of a transaction for each one they classify,
+    /// which makes adversarially large traces take quadratic time and memory,
+    /// e.g. in long-running backfills. Unlimited by default.
+    pub fn with_max_frames(mut self, max_frames: usize) -> Self {
+        self.max_frames = Some(max_frames);
+        self
+    }
+
     /// Given a trace iterator, it groups all traces for the same tx hash
     /// and then inspects them and all of their subtraces
     pub fn inspect_many(&self, traces: impl IntoIterator<Item = Trace>) -> Vec<Inspection> {
-        // group traces in a block by tx hash
-        let traces = traces.into_iter().group_by(|t| t.transaction_hash);
+        self.inspect_block(traces, None)
+    }
+
+    /// Inspects all the traces of a block whose miner is `coinbase`. If it is not
+    /// provided, it is taken from the block's reward trace, if any.
+    pub fn inspect_block(
+        &self,
+        traces: impl IntoIterator<Item = Trace>,
+        coinbase: Option<Address>,
+    ) -> Vec<Inspection> {
+        self.inspect_block_with_logs(traces, coinbase, &HashMap::new())
+    }
+
+    /// Same as `inspect_block`, but the inspectors also get the logs of each
+    /// transaction, e.g. from its receipt
+    pub fn inspect_block_with_logs(
+        &self,
+        traces: impl IntoIterator<Item = Trace>,
+        coinbase: Option<Address>,
+        logs: &HashMap<TxHash, Vec<Log>>,
+    ) -> Vec<Inspection> {
+        let traces = traces.into_iter().collect::<Vec<_>>();
+        let coinbase = coinbase.or_else(|| {
+            traces.iter().find_map(|trace| match trace.action {
+                Action::Reward(ref reward) if reward.reward_type == RewardType::Block => {
+                    Some(reward.author)
+                }
+                _ => None,
+            })
+        });
+
+        // group traces in a block by tx hash, in block order. The block's reward
+        // traces, and the pseudo-traces which some nodes return e.g. for the
+        // genesis allocations, do not belong to any tx
+        let mut txs: IndexMap<TxHash, Vec<Trace>> = IndexMap::new();
+        for trace in traces {
+            if let Some(hash) = trace.transaction_hash {
+                txs.entry(hash).or_default().push(trace);
+            }
+        }
 
-        // inspects everything
-        traces
+        // Convert the traces to inspections, and inspect them all in parallel
+        let mut inspections = txs
             .into_iter()
-            // Convert the traces to inspections
-            .filter_map(|(_, traces)| self.inspect_one(traces))
-            .collect::<Vec<_>>()
+            .filter_map(|(_, traces)| self.prepare(traces, coinbase, logs))
+            .collect::<Vec<_>>();
+        self.par_inspect(&mut inspections);
+
+        if !self.block_reducers.is_empty() {
+            for reducer in self.block_reducers.iter() {
+                reducer.reduce_block(&mut inspections);
+            }
+            inspections.iter_mut().for_each(|i| i.prune());
+        }
+
+        inspections
+    }
+
+    /// Runs the inspectors and the reducers over each of the inspections across
+    /// rayon's thread pool, keeping their order. The inspectors are shared by
+    /// all the threads, so their ABIs are only parsed once.
+    pub fn par_inspect(&self, inspections: &mut [Inspection]) {
+        inspections.par_iter_mut().for_each(|inspection| {
+            self.inspect(inspection);
+            self.reduce(inspection);
+            inspection.prune();
+        });
+    }
+
+    /// Inspects the transactions of a block from their receipts only, for
+    /// providers which do not serve traces. Only the actions which can be
+    /// told from the logs are classified.
+    pub fn inspect_receipts<'a>(
+        &self,
+        txs: impl IntoIterator<Item = (&'a Transaction, &'a TransactionReceipt)>,
+    ) -> Vec<Inspection> {
+        let mut inspections = txs
+            .into_iter()
+            .filter(|(tx, _)| matches!(tx.to, Some(to) if FILTER.get(&to).is_none()))
+            .filter_map(|(tx, receipt)| {
+                let mut i = Inspection::from_receipt(tx, receipt);
+                self.inspect(&mut i);
+                self.reduce(&mut i);
+                i.prune();
+                if i.actions.is_empty() {
+                    None
+                } else {
+                    Some(i)
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if !self.block_reducers.is_empty() {
+            for reducer in self.block_reducers.iter() {
+                reducer.reduce_block(&mut inspections);
+            }
+            inspections.iter_mut().for_each(|i| i.prune());
+        }
+
+        inspections
     }
 
     pub fn inspect_one<T>(&self, traces: T) -> Option<Inspection>
+    where
+        T: IntoIterator<Item = Trace>,
+    {
+        self.inspect_in_block(traces, None, &HashMap::new())
+    }
+
+    fn inspect_in_block<T>(
+        &self,
+        traces: T,
+        coinbase: Option<Address>,
+        logs: &HashMap<TxHash, Vec<Log>>,
+    ) -> Option<Inspection>
+    where
+        T: IntoIterator<Item = Trace>,
+    {
+        let mut inspection = self.prepare(traces, coinbase, logs)?;
+        self.inspect(&mut inspection);
+        self.reduce(&mut inspection);
+        inspection.prune();
+        Some(inspection)
+    }
+
+    /// Inspects a pending transaction by simulating it in `block` with Geth's
+    /// `debug_traceCall`, e.g. to check a bundle or a trade for MEV before it
+    /// is sent. The transaction may be unsigned, so its inspection's hash is
+    /// zero. Its sender should be set, since the simulation runs as the zero
+    /// address otherwise.
+    pub async fn inspect_pending<P: TraceProvider>(
+        &self,
+        provider: &P,
+        tx: &TransactionRequest,
+        block: &PendingBlock,
+    ) -> Result<Inspection, SimulationError<P::Error>> {
+        let frame = provider
+            .debug_trace_call(tx, BlockNumber::Pending, block.options())
+            .await
+            .map_err(SimulationError::Provider)?;
+        let frame: CallFrame = serde_json::from_value(frame)?;
+        let gas_used = frame.gas_used;
+        let traces = frame.into_traces(TxHash::zero(), block.number);
+
+        let mut inspection = self
+            .prepare(traces, Some(block.coinbase), &HashMap::new())
+            .ok_or(SimulationError::NoActions)?;
+        inspection.gas = Some(GasInfo {
+            gas_used,
+            gas_price: tx.gas_price.unwrap_or_default(),
+            base_fee: block.base_fee,
+            ..Default::default()
+        });
+        self.inspect(&mut inspection);
+        self.reduce(&mut inspection);
+        inspection.prune();
+        Ok(inspection)
+    }
+
+    /// Converts a tx's traces to an inspection which is ready to be inspected,
+    /// unless it has nothing to classify. The traces of a tx with too many
+    /// frames are not converted past its top level call.
+    fn prepare<T>(
+        &self,
+        traces: T,
+        coinbase: Option<Address>,
+        logs: &HashMap<TxHash, Vec<Log>>,
+    ) -> Option<Inspection>
     where
         T: IntoIterator<Item = Trace>,
     {
         use std::convert::TryFrom;
-        let mut res = None;
-        if let Ok(mut i) = Inspection::try_from(TraceWrapper(traces)) {
-            if !i.actions.is_empty() {
-                self.inspect(&mut i);
-                self.reduce(&mut i);
-                i.prune();
-                res = Some(i);
-            }
+        let traces = traces.into_iter().collect::<Vec<_>>();
+        if self.is_too_large(traces.len()) {
+            let frames = traces.len();
+            let mut i = Inspection::try_from(TraceWrapper(traces.into_iter().take(1))).ok()?;
+            self.mark_too_large(&mut i, frames);
+            return Some(i);
+        }
+        let mut i = Inspection::try_from(TraceWrapper(traces)).ok()?;
+        if i.actions.is_empty() {
+            return None;
+        }
+        if let Some(logs) = logs.get(&i.hash) {
+            i.logs = logs.clone();
+        }
+        // the payments must be classified before they get turned into
+        // ETH transfers
+        if let Some(coinbase) = coinbase {
+            i.classify_miner_payments(coinbase);
+        }
+        Some(i)
+    }
+
+    fn is_too_large(&self, frames: usize) -> bool {
+        matches!(self.max_frames, Some(max) if frames > max)
+    }
+
+    /// Marks an inspection which is left uninspected, unless its tx reverted,
+    /// which it is still reported as
+    fn mark_too_large(&self, inspection: &mut Inspection, frames: usize) {
+        log::warn!(
+            "not inspecting tx {:?}, which has {} frames",
+            inspection.hash,
+            frames
+        );
+        if inspection.status != Status::Reverted {
+            inspection.status = Status::TooLarge;
         }
-        res
     }
 
     /// Decodes the inspection's actions
     pub fn inspect(&self, inspection: &mut Inspection) {
-        for inspector in self.inspectors.iter() {
-            inspector.inspect(inspection);
+        if inspection.status == Status::TooLarge {
+            return;
+        }
+        if self.is_too_large(inspection.actions.len()) {
+            let frames = inspection.actions.len();
+            self.mark_too_large(inspection, frames);
+            return;
         }
+        if self.fold_delegatecalls {
+            inspection.fold_delegatecalls();
+        }
+        self.inspectors.inspect_all(inspection);
     }
 
     pub fn reduce(&self, inspection: &mut Inspection) {
+        if inspection.status == Status::TooLarge || self.is_too_large(inspection.actions.len()) {
+            return;
+        }
         for reducer in self.reducers.iter() {
             reducer.reduce(inspection);
         }
@@ -234,31 +480,39 @@ impl<M: Middleware + Unpin + 'static> Stream for BatchEvaluator<M> {
             match this.block_infos.as_mut().poll_next(cx) {
                 Poll::Ready(Some(Ok((traces, block, receipts)))) => {
                     log::trace!("fetched block infos for block {:?}", block.number);
-                    let gas_price_txs = block
+                    let receipts = receipts
+                        .iter()
+                        .map(|receipt| (receipt.transaction_hash, receipt))
+                        .collect::<HashMap<TxHash, &TransactionReceipt>>();
+
+                    let gas_txs = block
                         .transactions
                         .iter()
-                        .map(|tx| (tx.hash, tx.gas_price))
-                        .collect::<HashMap<TxHash, U256>>();
-
-                    let gas_used_txs = receipts
-                        .into_iter()
-                        .map(|receipt| {
-                            (
-                                receipt.transaction_hash,
-                                receipt.gas_used.unwrap_or_default(),
-                            )
+                        .filter_map(|tx| {
+                            let receipt = receipts.get(&tx.hash)?;
+                            Some((tx.hash, GasInfo::new(tx, receipt)))
                         })
-                        .collect::<HashMap<TxHash, U256>>();
-
-                    for inspection in this.inspector.inspect_many(traces) {
-                        let gas_used = gas_used_txs
-                            .get(&inspection.hash)
-                            .cloned()
-                            .unwrap_or_default();
+                        .collect::<HashMap<TxHash, GasInfo>>();
 
-                        let gas_price = gas_price_txs
-                            .get(&inspection.hash)
-                            .cloned()
+                    let logs = receipts
+                        .iter()
+                        .map(|(hash, receipt)| (*hash, receipt.logs.clone()))
+                        .collect();
+
+                    let mut inspections =
+                        this.inspector
+                            .inspect_block_with_logs(traces, Some(block.author), &logs);
+                    for inspection in inspections.iter_mut() {
+                        inspection.gas = gas_txs.get(&inspection.hash).cloned();
+                    }
+                    // frontruns are told apart by their gas price, which is only known now
+                    FrontrunReducer::new().reduce_block(&mut inspections);
+
+                    for inspection in inspections {
+                        let (gas_used, gas_price) = inspection
+                            .gas
+                            .as_ref()
+                            .map(|gas| (gas.gas_used, gas.effective_gas_price()))
                             .unwrap_or_default();
 
                         if this.evaluations_queue.len() < this.max {
@@ -343,15 +597,22 @@ pub enum BatchEvaluationError<M: Middleware + 'static> {
 
 #[cfg(test)]
 mod tests {
-    use ethers::types::U256;
+    use ethers::{
+        abi::Token,
+        types::{Bytes, I256, U256},
+    };
 
     use crate::{
-        addresses::{ADDRESSBOOK, WETH},
+        addresses::{parse_address, ADDRESSBOOK, ETH, WETH},
         inspectors::*,
+        is_subtrace,
         reducers::*,
         set,
         test_helpers::*,
-        types::{Protocol, Status},
+        types::{
+            actions::{Searcher, Transfer},
+            Classification, Protocol,
+        },
     };
 
     use super::*;
@@ -370,7 +631,7 @@ mod tests {
         let inspector = BatchInspector::new(
             vec![
                 Box::new(ERC20::new()),
-                Box::new(Uniswap::new()),
+                Box::new(UniswapV2::new()),
                 Box::new(Aave::new()),
                 Box::new(Curve::new(vec![])), // even though the Curve inspector is on, there's no Curve in the found protocols
             ],
@@ -420,7 +681,7 @@ mod tests {
         let inspector = BatchInspector::new(
             vec![
                 Box::new(ERC20::new()),
-                Box::new(Uniswap::new()),
+                Box::new(UniswapV2::new()),
                 Box::new(Curve::new(vec![])),
                 Box::new(Balancer::new()),
             ],
@@ -455,7 +716,7 @@ mod tests {
         let inspector = BatchInspector::new(
             vec![
                 Box::new(ERC20::new()),
-                Box::new(Uniswap::new()),
+                Box::new(UniswapV2::new()),
                 Box::new(Curve::new(vec![])),
                 Box::new(Balancer::new()),
             ],
@@ -488,7 +749,7 @@ mod tests {
         let inspector = BatchInspector::new(
             vec![
                 Box::new(ERC20::new()),
-                Box::new(Uniswap::new()),
+                Box::new(UniswapV2::new()),
                 Box::new(Curve::new(vec![])),
             ],
             vec![
@@ -508,6 +769,15 @@ mod tests {
             .unwrap();
         assert_eq!(arb.profit, U256::from_dec_str("14397525374450478").unwrap());
         assert_eq!(arb.token, *WETH);
+        // the bot contract which made the arbitrage, and the EOA which called it
+        assert_eq!(
+            arb.searcher,
+            Searcher {
+                eoa: parse_address("0xff28319a7cd2136ea7283e7cdb0675b50ac29dd2"),
+                contract: parse_address("0x0000000000007f150bd6f54c40a34d7c3d5e9f56"),
+            }
+        );
+        assert_eq!(arb.searcher, inspection.searcher());
         assert_eq!(
             inspection.protocols,
             set![Protocol::Sushiswap, Protocol::Curve, Protocol::ZeroEx]
@@ -523,7 +793,7 @@ mod tests {
             vec![
                 Box::new(ERC20::new()),
                 Box::new(Aave::new()),
-                Box::new(Uniswap::new()),
+                Box::new(UniswapV2::new()),
                 Box::new(Balancer::new()),
                 Box::new(ZeroEx::new()),
                 Box::new(Curve::new(vec![])),
@@ -538,10 +808,13 @@ mod tests {
         inspector.reduce(&mut inspection);
         inspection.prune();
 
-        let known = inspection.known();
-
-        assert_eq!(inspection.status, Status::Reverted);
-        assert_eq!(inspection.protocols, set![Protocol::Uniswap])
+        // the bot's trades were within a call which reverted, after which it
+        // freed its gas tokens
+        assert_eq!(inspection.status, Status::Success);
+        assert!(inspection.protocols.is_empty());
+        assert!(inspection
+            .known_actions()
+            .all(|action| action.self_destruct().is_some()));
     }
 
     #[test]
@@ -555,7 +828,7 @@ mod tests {
                 Box::new(Aave::new()),
                 Box::new(ZeroEx::new()),
                 Box::new(Balancer::new()),
-                Box::new(Uniswap::new()),
+                Box::new(UniswapV2::new()),
                 Box::new(Curve::new(vec![])),
             ],
             vec![
@@ -596,7 +869,7 @@ mod tests {
                 Box::new(Aave::new()),
                 Box::new(ZeroEx::new()),
                 Box::new(Balancer::new()),
-                Box::new(Uniswap::new()),
+                Box::new(UniswapV2::new()),
                 Box::new(Curve::new(vec![])),
             ],
             vec![
@@ -631,11 +904,11 @@ mod tests {
     // 3. trades zHEGIC for HEGIC
     // 4. trades HEGIC for 3.685 WETH whcih stays at 0xa084
     // 5. send the remaining 111 WETH back to known bot
-    async fn reverted_arb_positive_revenue() {
+    async fn reverted_arb_is_pruned() {
         let mut inspection = read_trace("reverted_arb.json");
 
         let inspector = BatchInspector::new(
-            vec![Box::new(ERC20::new()), Box::new(Uniswap::new())],
+            vec![Box::new(ERC20::new()), Box::new(UniswapV2::new())],
             vec![
                 Box::new(TradeReducer::new()),
                 Box::new(ArbitrageReducer::new()),
@@ -645,14 +918,14 @@ mod tests {
         inspector.reduce(&mut inspection);
         inspection.prune();
 
-        let arb = inspection
-            .known()
+        // the bot caught the revert of its arb, whose trades never happened
+        assert_eq!(inspection.arbitrages().count(), 0);
+        assert_eq!(inspection.trades().count(), 0);
+        assert_eq!(inspection.status, Status::Success);
+        assert!(inspection
+            .actions
             .iter()
-            .find_map(|x| x.as_ref().arbitrage())
-            .cloned()
-            .unwrap();
-        assert_eq!(arb.profit.to_string(), "101664758086906735");
-        assert_eq!(inspection.status, Status::Reverted);
+            .all(|action| !is_subtrace(&[0, 0], action.trace_address())));
     }
 
     #[tokio::test]
@@ -662,7 +935,7 @@ mod tests {
         let mut inspection = read_trace("zapper1.json");
 
         let inspector = BatchInspector::new(
-            vec![Box::new(ERC20::new()), Box::new(Uniswap::new())],
+            vec![Box::new(ERC20::new()), Box::new(UniswapV2::new())],
             vec![
                 Box::new(TradeReducer::new()),
                 Box::new(ArbitrageReducer::new()),
@@ -697,4 +970,301 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn miner_payments() {
+        let tx = "0x8305be032131db3aebf16c12be7745b121e1c12d772aa84962a4ba63282fdcd8";
+        let coinbase = "0xea674fdde714fd979de3edf0f56aa9716b898ec8";
+        let bot = "0x0ccf97df578af5a6dce3ada9bf60316e3662741d";
+        let call = |to: &str, value: &str, trace_address: &str| {
+            format!(
+                r#"{{"action": {{"callType": "call", "from": "{}", "gas": "0x7148", "input": "0x", "to": "{}", "value": "{}"}}, "blockHash": "0x6a191156050aab2babfd8985ec263d5d25b972a3852f27bc6aacb5354595c908", "blockNumber": 1, "result": {{"gasUsed": "0x33", "output": "0x"}}, "subtraces": 0, "traceAddress": {}, "transactionHash": "{}", "transactionPosition": 0, "type": "call"}}"#,
+                bot, to, value, trace_address, tx
+            )
+        };
+        let traces = format!(
+            "[{}, {}, {}, {}, {}]",
+            call(bot, "0x0", "[]"),
+            call(coinbase, "0x64", "[0]"),
+            call(coinbase, "0xc8", "[1]"),
+            format!(
+                r#"{{"action": {{"address": "{}", "refundAddress": "{}", "balance": "0x12c"}}, "blockHash": "0x6a191156050aab2babfd8985ec263d5d25b972a3852f27bc6aacb5354595c908", "blockNumber": 1, "result": null, "subtraces": 0, "traceAddress": [2], "transactionHash": "{}", "transactionPosition": 0, "type": "suicide"}}"#,
+                bot, coinbase, tx
+            ),
+            format!(
+                r#"{{"action": {{"author": "{}", "rewardType": "block", "value": "0x1bc16d674ec80000"}}, "blockHash": "0x6a191156050aab2babfd8985ec263d5d25b972a3852f27bc6aacb5354595c908", "blockNumber": 1, "result": null, "subtraces": 0, "traceAddress": [], "transactionHash": null, "transactionPosition": null, "type": "reward"}}"#,
+                coinbase
+            ),
+        );
+        let traces: Vec<Trace> = serde_json::from_str(&traces).unwrap();
+
+        let inspector = BatchInspector::new(vec![Box::new(ERC20::new())], vec![]);
+        let inspections = inspector.inspect_many(traces);
+        assert_eq!(inspections.len(), 1);
+
+        let payments = inspections[0]
+            .known()
+            .iter()
+            .filter_map(|a| a.as_ref().miner_payment().cloned())
+            .collect::<Vec<_>>();
+        assert_eq!(payments.len(), 3);
+        assert_eq!(inspections[0].coinbase_payment(), 600.into());
+    }
+
+    #[test]
+    fn skips_the_traces_of_no_tx() {
+        let inspector = BatchInspector::new(vec![Box::new(ERC20::new())], vec![]);
+        let hashes =
+            |inspections: Vec<Inspection>| inspections.iter().map(|i| i.hash).collect::<Vec<_>>();
+        let expected = hashes(inspector.inspect_block(TRACES.clone(), None));
+
+        // the block's reward first, and a pseudo-trace which is not part of any
+        // tx, as some nodes return for the genesis allocations
+        let mut traces = TRACES.clone();
+        let reward = traces.pop().unwrap();
+        assert!(reward.transaction_hash.is_none());
+        let mut allocation = traces[0].clone();
+        allocation.transaction_hash = None;
+        allocation.transaction_position = None;
+        traces.insert(0, reward);
+        traces.insert(traces.len() / 2, allocation);
+
+        assert_eq!(hashes(inspector.inspect_block(traces, None)), expected);
+        assert!(!expected.contains(&TxHash::zero()));
+    }
+
+    #[test]
+    fn ephemeral_bot_deployment() {
+        let addrs = addrs();
+        let (eoa, factory, bot, pair, dai, coinbase) =
+            (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4], addrs[5]);
+        let tx = TxHash::random();
+        let trace = |action: String, result: &str, kind: &str, trace_address: &str| {
+            format!(
+                r#"{{"action": {}, "blockHash": "0x6a191156050aab2babfd8985ec263d5d25b972a3852f27bc6aacb5354595c908", "blockNumber": 1, "result": {}, "subtraces": 0, "traceAddress": {}, "transactionHash": "{:?}", "transactionPosition": 0, "type": "{}"}}"#,
+                action, result, trace_address, tx, kind
+            )
+        };
+        let call = |from: Address, to: Address, input: Bytes, trace_address: &str| {
+            trace(
+                format!(
+                    r#"{{"callType": "call", "from": "{:?}", "gas": "0x7148", "input": "0x{}", "to": "{:?}", "value": "0x0"}}"#,
+                    from,
+                    hex::encode(input.as_ref()),
+                    to
+                ),
+                r#"{"gasUsed": "0x33", "output": "0x"}"#,
+                "call",
+                trace_address,
+            )
+        };
+        let swap = calldata(
+            "swap(uint256,uint256,address,bytes)",
+            &[
+                Token::Uint(0.into()),
+                Token::Uint(200.into()),
+                Token::Address(bot),
+                Token::Bytes(vec![]),
+            ],
+        );
+
+        // the bot is deployed, trades and self-destructs to the miner, all
+        // within the same transaction
+        let traces = [
+            call(eoa, factory, Bytes::default(), "[]"),
+            trace(
+                format!(
+                    r#"{{"from": "{:?}", "gas": "0x7148", "init": "0x", "value": "0x0"}}"#,
+                    factory
+                ),
+                &format!(
+                    r#"{{"address": "{:?}", "code": "0x", "gasUsed": "0x33"}}"#,
+                    bot
+                ),
+                "create",
+                "[0]",
+            ),
+            call(factory, bot, Bytes::default(), "[1]"),
+            call(bot, *WETH, transfer(pair, 100.into()), "[1, 0]"),
+            call(bot, pair, swap, "[1, 1]"),
+            call(pair, dai, transfer(bot, 200.into()), "[1, 1, 0]"),
+            trace(
+                format!(
+                    r#"{{"address": "{:?}", "refundAddress": "{:?}", "balance": "0x12c"}}"#,
+                    bot, coinbase
+                ),
+                "null",
+                "suicide",
+                "[1, 2]",
+            ),
+        ];
+        let traces: Vec<Trace> = serde_json::from_str(&format!("[{}]", traces.join(", "))).unwrap();
+
+        let inspector = BatchInspector::new(
+            vec![Box::new(ERC20::new()), Box::new(UniswapV2::new())],
+            vec![Box::new(TradeReducer::new())],
+        );
+        let mut inspections = inspector.inspect_block(traces.clone(), Some(coinbase));
+        let inspection = &mut inspections[0];
+        inspector.reduce(inspection);
+        inspection.prune();
+
+        let known = inspection.known();
+        assert_eq!(known.len(), 4);
+        let deployment = known[0].as_ref().deployment().unwrap();
+        assert_eq!((deployment.deployer, deployment.contract), (factory, bot));
+        let trade = known[1].as_ref().trade().unwrap();
+        assert_eq!((trade.t1.to, trade.t2.token), (pair, dai));
+        assert!(known[2].as_ref().self_destruct().is_some());
+        // the bot's ETH goes to the miner
+        let payment = known[3].as_ref().miner_payment().unwrap();
+        assert_eq!((payment.from, payment.amount), (bot, 300.into()));
+        assert_eq!(known[3].trace_address, known[2].trace_address);
+        assert_eq!(inspection.coinbase_payment(), 300.into());
+
+        // to anyone else, it is a self-destruct which sent its ETH
+        let inspection = &inspector.inspect_block(traces, Some(eoa))[0];
+        let destruct = inspection
+            .known()
+            .iter()
+            .find_map(|a| a.as_ref().self_destruct().cloned())
+            .unwrap();
+        assert_eq!(
+            (destruct.contract, destruct.beneficiary, destruct.balance),
+            (bot, coinbase, 300.into())
+        );
+        assert!(inspection
+            .transfers()
+            .any(|t| *t == Transfer::new(bot, coinbase, 300.into(), *ETH)));
+        assert_eq!(
+            inspection.balance_deltas()[&(coinbase, *ETH)],
+            I256::from(300)
+        );
+        assert_eq!(inspection.coinbase_payment(), 0.into());
+    }
+
+    #[test]
+    fn parallel_inspection_keeps_the_serial_results() {
+        let inspector = BatchInspector::new(
+            vec![
+                Box::new(ERC20::new()),
+                Box::new(UniswapV2::new()),
+                Box::new(Aave::new()),
+            ],
+            vec![
+                Box::new(LiquidationReducer::new()),
+                Box::new(TradeReducer::new()),
+                Box::new(ArbitrageReducer::new()),
+            ],
+        );
+        let parallel = inspector.inspect_many(TRACES.clone());
+
+        let mut serial = BatchInspector::new(vec![], vec![]).inspect_many(TRACES.clone());
+        for inspection in serial.iter_mut() {
+            inspector.inspect(inspection);
+            inspector.reduce(inspection);
+            inspection.prune();
+        }
+
+        let results = |inspections: &[Inspection]| {
+            inspections
+                .iter()
+                .map(|i| (i.hash, i.actions.clone()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(results(&parallel), results(&serial));
+    }
+
+    #[test]
+    fn leaves_too_large_traces_uninspected() {
+        let addrs = addrs();
+        let (bot, token) = (addrs[0], addrs[1]);
+        let inspector = BatchInspector::new(
+            vec![Box::new(ERC20::new())],
+            vec![Box::new(TransferReducer::new())],
+        )
+        .with_max_frames(1000);
+        // a bot which recurses into itself, and transfers at the bottom
+        let deep = |depth: usize| {
+            let mut calls = (0..depth)
+                .map(|i| mk_call(bot, bot, Bytes::default(), vec![0; i]))
+                .collect::<Vec<_>>();
+            calls.push(mk_call(
+                bot,
+                token,
+                transfer(addrs[2], 1.into()),
+                vec![0; depth],
+            ));
+            mk_inspection(calls)
+        };
+
+        let mut inspections = vec![deep(5000), deep(100)];
+        inspector.par_inspect(&mut inspections);
+        assert_eq!(inspections[0].status, Status::TooLarge);
+        assert_eq!(inspections[0].actions.len(), 5001);
+        assert!(inspections[0]
+            .actions
+            .iter()
+            .all(|a| matches!(a, Classification::Unknown(_))));
+        // the ones under the limit are inspected as usual
+        assert_eq!(inspections[1].status, Status::Success);
+        assert_eq!(inspections[1].known_actions().count(), 1);
+    }
+
+    #[test]
+    fn counts_the_frames_before_converting_the_traces() {
+        let addrs = addrs();
+        let (bot, token) = (addrs[0], addrs[1]);
+        let inspector =
+            BatchInspector::new(vec![Box::new(ERC20::new())], vec![]).with_max_frames(3);
+        let trace = |to: Address, input: Bytes, trace_address: Vec<usize>, reverted: bool| {
+            let mut trace = serde_json::json!({
+                "action": {
+                    "callType": "call",
+                    "from": format!("{:?}", bot),
+                    "gas": "0x7148",
+                    "input": format!("0x{}", hex::encode(input.as_ref())),
+                    "to": format!("{:?}", to),
+                    "value": "0x0",
+                },
+                "blockHash": format!("{:?}", TxHash::zero()),
+                "blockNumber": 1,
+                "result": { "gasUsed": "0x33", "output": "0x" },
+                "subtraces": 0,
+                "traceAddress": trace_address,
+                "transactionHash": format!("{:?}", TxHash::zero()),
+                "transactionPosition": 0,
+                "type": "call",
+            });
+            if reverted {
+                trace["error"] = "Reverted".into();
+                trace["result"] = serde_json::Value::Null;
+            }
+            serde_json::from_value::<Trace>(trace).unwrap()
+        };
+        let traces = |reverted: bool| {
+            let mut traces = (0..4)
+                .map(|i| trace(bot, Bytes::default(), vec![0; i], reverted && i == 0))
+                .collect::<Vec<_>>();
+            traces.push(trace(
+                token,
+                transfer(addrs[2], 1.into()),
+                vec![0; 4],
+                false,
+            ));
+            traces
+        };
+
+        // only the top level call is converted
+        let inspection = inspector.inspect_one(traces(false)).unwrap();
+        assert_eq!(inspection.status, Status::TooLarge);
+        assert_eq!(inspection.actions.len(), 1);
+        assert_eq!(inspection.known_actions().count(), 0);
+
+        // a reverted tx is still reported as such
+        let inspection = inspector.inspect_one(traces(true)).unwrap();
+        assert_eq!(inspection.status, Status::Reverted);
+        assert_eq!(inspection.actions.len(), 1);
+        assert_eq!(inspection.known_actions().count(), 0);
+    }
 }
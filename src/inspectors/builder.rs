@@ -0,0 +1,456 @@
+use crate::{
+    inspectors::{find_all_matching, find_matching},
+    is_nested,
+    types::{
+        actions::{SpecificAction, Trade, Transfer},
+        Classification, Inspection, Protocol,
+    },
+    Inspector,
+};
+use ethers::{
+    abi::{Abi, Function, ParamType, Token},
+    types::{Address, Call},
+    utils::id,
+};
+use std::fmt;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum InspectorBuilderError {
+    #[error("Could not parse the ABI: {0}")]
+    Abi(#[from] serde_json::Error),
+    #[error("The ABI does not have a function {0}")]
+    UnknownFunction(String),
+    #[error("Argument {index} of {function} is not an address")]
+    NotAnAddress { function: String, index: usize },
+}
+
+/// A call to one of the functions of a user-defined inspector
+pub struct DecodedCall<'a> {
+    pub call: &'a Call,
+    pub trace_address: &'a [usize],
+    /// The decoded arguments of the call
+    pub args: Vec<Token>,
+    /// All the actions of the inspection, the call is the one at `index`
+    pub actions: &'a [Classification],
+    pub index: usize,
+}
+
+impl DecodedCall<'_> {
+    /// Returns the argument at `index` if it is an address
+    pub fn address(&self, index: usize) -> Option<Address> {
+        match self.args.get(index) {
+            Some(Token::Address(address)) => Some(*address),
+            _ => None,
+        }
+    }
+}
+
+/// Turns a call into an action, along with the indices of the actions which it
+/// consists of, e.g. the transfers of a trade, which then get pruned
+pub type ActionConstructor =
+    Box<dyn Fn(&DecodedCall) -> Option<(SpecificAction, Vec<usize>)> + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// Where the tokens sold in a trade are sent to the called contract
+pub enum TransferIn {
+    /// Before the call, which is how Uniswap V2 pairs are traded with
+    Before,
+    /// By the call itself, e.g. with a `transferFrom` of the caller's tokens
+    Within,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// How the transfers of a trade are found around the call which makes it. The
+/// tokens bought are always sent by the called contract, within the call.
+pub struct TradeMatching {
+    pub transfer_in: TransferIn,
+    /// The argument which the tokens bought are sent to. If `None`, they are
+    /// sent to the caller.
+    pub recipient: Option<usize>,
+}
+
+/// Builds inspectors for protocols which this crate does not support, from
+/// their ABI and a constructor of the action made by each of their functions.
+///
+/// ```
+/// use mev_inspect::{
+///     inspectors::{InspectorBuilder, TradeMatching, TransferIn},
+///     Inspector,
+/// };
+///
+/// // `swap(uint256,uint256,address,bytes)` of a Uniswap V2 fork, whose
+/// // pairs must be sent the tokens sold before being called
+/// let inspector = InspectorBuilder::new("myswap", include_str!("../../abi/unipair.json"))
+///     .unwrap()
+///     .trade(
+///         "swap",
+///         TradeMatching {
+///             transfer_in: TransferIn::Before,
+///             recipient: Some(2),
+///         },
+///     )
+///     .unwrap()
+///     .build();
+/// assert_eq!(inspector.name(), "myswap");
+/// ```
+pub struct InspectorBuilder {
+    name: &'static str,
+    abi: Abi,
+    protocol: Option<Protocol>,
+    functions: Vec<([u8; 4], Function, ActionConstructor)>,
+}
+
+impl InspectorBuilder {
+    /// Instantiates a builder of the inspector `name` of a contract with the
+    /// JSON `abi`
+    pub fn new(name: &'static str, abi: &str) -> Result<Self, InspectorBuilderError> {
+        Ok(Self {
+            name,
+            abi: serde_json::from_str(abi)?,
+            protocol: None,
+            functions: Vec::new(),
+        })
+    }
+
+    /// Sets the protocol which is added to the inspections this inspector
+    /// classifies actions of
+    pub fn protocol(mut self, protocol: Protocol) -> Self {
+        self.protocol = Some(protocol);
+        self
+    }
+
+    /// Classifies the calls to `function` with `constructor`. The function is
+    /// either a name, in which case all of its overloads are matched, or a
+    /// signature such as `swap(uint256,uint256,address,bytes)`.
+    pub fn action<F>(
+        mut self,
+        function: &str,
+        constructor: F,
+    ) -> Result<Self, InspectorBuilderError>
+    where
+        F: Fn(&DecodedCall) -> Option<(SpecificAction, Vec<usize>)> + Send + Sync + Clone + 'static,
+    {
+        for function in self.functions_of(function)? {
+            self.functions
+                .push((selector(&function), function, Box::new(constructor.clone())));
+        }
+        Ok(self)
+    }
+
+    /// Classifies the calls to `function` as trades with the called contract,
+    /// whose transfers are matched as in `matching`
+    pub fn trade(
+        self,
+        function: &str,
+        matching: TradeMatching,
+    ) -> Result<Self, InspectorBuilderError> {
+        if let Some(index) = matching.recipient {
+            for f in self.functions_of(function)? {
+                if !matches!(f.inputs.get(index), Some(param) if param.kind == ParamType::Address) {
+                    return Err(InspectorBuilderError::NotAnAddress {
+                        function: f.name,
+                        index,
+                    });
+                }
+            }
+        }
+        self.action(function, move |call| trade(call, matching))
+    }
+
+    /// Returns the inspector
+    pub fn build(self) -> Box<dyn Inspector + Send + Sync> {
+        Box::new(BuiltInspector {
+            name: self.name,
+            protocol: self.protocol,
+            functions: self.functions,
+        })
+    }
+
+    fn functions_of(&self, function: &str) -> Result<Vec<Function>, InspectorBuilderError> {
+        let functions = self
+            .abi
+            .functions()
+            .filter(|f| f.name == function || signature(f) == function)
+            .cloned()
+            .collect::<Vec<_>>();
+        if functions.is_empty() {
+            return Err(InspectorBuilderError::UnknownFunction(function.to_owned()));
+        }
+        Ok(functions)
+    }
+}
+
+/// An inspector made by an `InspectorBuilder`
+struct BuiltInspector {
+    name: &'static str,
+    protocol: Option<Protocol>,
+    functions: Vec<([u8; 4], Function, ActionConstructor)>,
+}
+
+impl fmt::Debug for BuiltInspector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BuiltInspector")
+            .field("name", &self.name)
+            .field("protocol", &self.protocol)
+            .field(
+                "functions",
+                &self
+                    .functions
+                    .iter()
+                    .map(|(_, function, _)| function.signature())
+                    .collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl Inspector for BuiltInspector {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        let actions = &inspection.actions;
+        let mut classified = Vec::new();
+        let mut prune = Vec::new();
+        for (i, action) in actions.iter().enumerate() {
+            let calltrace = match action.as_call() {
+                Some(calltrace) => calltrace,
+                None => continue,
+            };
+            let call = calltrace.as_ref();
+            if call.input.as_ref().len() < 4 {
+                continue;
+            }
+            let (selector, input) = call.input.as_ref().split_at(4);
+
+            for (expected, function, constructor) in self.functions.iter() {
+                if expected != selector {
+                    continue;
+                }
+                let args = match function.decode_input(input) {
+                    Ok(args) => args,
+                    Err(_) => continue,
+                };
+                let decoded = DecodedCall {
+                    call,
+                    trace_address: &calltrace.trace_address,
+                    args,
+                    actions,
+                    index: i,
+                };
+                if let Some((action, consumed)) = constructor(&decoded) {
+                    classified.push((
+                        i,
+                        Classification::new(action, calltrace.trace_address.clone()),
+                    ));
+                    prune.extend(consumed);
+                    if let Some(protocol) = self.protocol {
+                        inspection.protocols.insert(protocol);
+                    }
+                    break;
+                }
+            }
+        }
+
+        for (i, action) in classified {
+            inspection.actions[i] = action;
+        }
+        for i in prune {
+            inspection.actions[i] = Classification::Prune;
+        }
+    }
+}
+
+/// The signature of a function without its outputs, e.g. `transfer(address,uint256)`
+fn signature(function: &Function) -> String {
+    let signature = function.signature();
+    match signature.split_once(':') {
+        Some((signature, _)) => signature.to_owned(),
+        None => signature,
+    }
+}
+
+fn selector(function: &Function) -> [u8; 4] {
+    id(signature(function))
+}
+
+/// Finds the transfers of a trade with the called contract
+fn trade(call: &DecodedCall, matching: TradeMatching) -> Option<(SpecificAction, Vec<usize>)> {
+    let pool = call.call.to;
+    let recipient = match matching.recipient {
+        Some(index) => call.address(index)?,
+        None => call.call.from,
+    };
+    // the actions made by the call, which come right after it
+    let subtraces = || {
+        call.actions
+            .iter()
+            .enumerate()
+            .skip(call.index + 1)
+            .filter(|(_, action)| !matches!(action, Classification::Prune))
+            .take_while(|(_, action)| is_nested(call.trace_address, action.trace_address()))
+    };
+
+    let transfer_in = match matching.transfer_in {
+        TransferIn::Before => find_matching(
+            call.actions.iter().enumerate().take(call.index).rev(),
+            |action| action.transfer(),
+            |transfer| transfer.to == pool,
+            true,
+        ),
+        TransferIn::Within => find_matching(
+            subtraces(),
+            |action| action.transfer(),
+            |transfer| transfer.to == pool,
+            true,
+        ),
+    }?;
+    let transfers_out = find_all_matching(
+        subtraces(),
+        |action| action.transfer(),
+        |transfer: &Transfer| transfer.from == pool,
+        true,
+    );
+    let transfer_out = transfers_out
+        .iter()
+        .find(|(_, transfer)| transfer.to == recipient)
+        .or_else(|| transfers_out.first())
+        .copied()?;
+
+    // the tokens may be sold by a router on behalf of the recipient, so the
+    // transfers do not have to be between the same addresses
+    let trade = Trade {
+        t1: transfer_in.1.clone(),
+        t2: transfer_out.1.clone(),
+    };
+    Some((trade.into(), vec![transfer_in.0, transfer_out.0]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        inspectors::{UniswapV2, ERC20},
+        reducers::TradeReducer,
+        test_helpers::*,
+        Reducer,
+    };
+
+    const PAIR: &str = include_str!("../../abi/unipair.json");
+
+    fn v2_like() -> Box<dyn Inspector + Send + Sync> {
+        InspectorBuilder::new("myswap", PAIR)
+            .unwrap()
+            .protocol(Protocol::Uniswappy)
+            .trade(
+                "swap(uint256,uint256,address,bytes)",
+                TradeMatching {
+                    transfer_in: TransferIn::Before,
+                    recipient: Some(2),
+                },
+            )
+            .unwrap()
+            .build()
+    }
+
+    #[test]
+    fn builds_a_uniswap_v2_like_inspector() {
+        let inspect = |inspector: &dyn Inspector| {
+            let mut inspection = read_trace("exact_tokens_for_eth.json");
+            ERC20::new().inspect(&mut inspection);
+            inspector.inspect(&mut inspection);
+            TradeReducer::new().reduce(&mut inspection);
+            inspection.prune();
+            inspection
+        };
+        let built = inspect(v2_like().as_ref());
+        let uniswap = inspect(&UniswapV2::new());
+
+        assert_eq!(built.trades().count(), 1);
+        assert_eq!(
+            built.trades().collect::<Vec<_>>(),
+            uniswap.trades().collect::<Vec<_>>()
+        );
+        assert_eq!(built.known().len(), uniswap.known().len());
+        assert_eq!(built.protocols, crate::set![Protocol::Uniswappy]);
+    }
+
+    #[test]
+    fn classifies_calls_with_custom_constructors() {
+        // `skim(address)` sends the pair's excess reserves to its argument
+        let skims = InspectorBuilder::new("skims", PAIR)
+            .unwrap()
+            .action("skim", |call| {
+                let to = call.address(0)?;
+                let (i, transfer) = find_matching(
+                    call.actions.iter().enumerate().skip(call.index + 1),
+                    |action| action.transfer(),
+                    |transfer: &Transfer| transfer.to == to,
+                    false,
+                )?;
+                Some((transfer.clone().into(), vec![i]))
+            })
+            .unwrap()
+            .build();
+        assert_eq!(skims.name(), "skims");
+
+        let addrs = addrs();
+        let (usr, pair, token) = (addrs[0], addrs[1], addrs[2]);
+        let mut inspection = mk_inspection(vec![
+            mk_call(
+                usr,
+                pair,
+                calldata("skim(address)", &[Token::Address(usr)]),
+                vec![0],
+            ),
+            mk_call(pair, token, transfer(usr, 5.into()), vec![0, 0]),
+        ]);
+        ERC20::new().inspect(&mut inspection);
+        skims.inspect(&mut inspection);
+        inspection.prune();
+
+        assert_eq!(
+            inspection.transfers().collect::<Vec<_>>(),
+            vec![&Transfer {
+                from: pair,
+                to: usr,
+                amount: 5.into(),
+                token,
+            }]
+        );
+        assert_eq!(inspection.known()[0].trace_address[..], [0]);
+    }
+
+    #[test]
+    fn rejects_unknown_functions() {
+        assert!(matches!(
+            InspectorBuilder::new("myswap", "[}"),
+            Err(InspectorBuilderError::Abi(_))
+        ));
+        let builder = || InspectorBuilder::new("myswap", PAIR).unwrap();
+        let matching = TradeMatching {
+            transfer_in: TransferIn::Within,
+            recipient: None,
+        };
+        assert!(matches!(
+            builder().trade("exchange", matching),
+            Err(InspectorBuilderError::UnknownFunction(_))
+        ));
+        assert!(matches!(
+            builder().trade("swap(uint256,uint256)", matching),
+            Err(InspectorBuilderError::UnknownFunction(_))
+        ));
+        assert!(matches!(
+            builder().trade(
+                "swap",
+                TradeMatching {
+                    recipient: Some(0),
+                    ..matching
+                }
+            ),
+            Err(InspectorBuilderError::NotAnAddress { index: 0, .. })
+        ));
+    }
+}
@@ -1,10 +1,11 @@
 use crate::{
     actions_after,
-    addresses::{CETH, COMPTROLLER, COMP_ORACLE, WETH},
+    addresses::{CompoundFork, COMPOUND_FORKS, COMPTROLLER, COMP_ORACLE, WETH},
+    is_nested,
     traits::Inspector,
     types::{
         actions::{Liquidation, SpecificAction},
-        Classification, Inspection, Protocol, Status,
+        CheckedReason, Classification, Inspection, Protocol, Status, TraceAddress,
     },
 };
 use ethers::{
@@ -14,8 +15,27 @@ use ethers::{
     types::{Address, Call, CallType, U256},
 };
 
+use once_cell::sync::Lazy;
 use std::collections::HashMap;
 
+static CTOKEN_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    serde_json::from_str::<Abi>(include_str!("../../abi/ctoken.json"))
+        .expect("could not parse ctoken abi")
+        .into()
+});
+
+static CETHER_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    serde_json::from_str::<Abi>(include_str!("../../abi/cether.json"))
+        .expect("could not parse ctoken abi")
+        .into()
+});
+
+static COMPTROLLER_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    serde_json::from_str::<Abi>(include_str!("../../abi/comptroller.json"))
+        .expect("could not parse ctoken abi")
+        .into()
+});
+
 type LiquidateBorrow = (Address, U256, Address);
 type LiquidateBorrowEth = (Address, Address);
 type SeizeInternal = (Address, Address, Address, U256);
@@ -34,15 +54,25 @@ abigen!(
 abigen!(CToken, "abi/ctoken.json",);
 
 #[derive(Debug, Clone)]
-/// An inspector for Compound liquidations
+/// An inspector for Compound liquidations, and for the ones of its forks whose
+/// markets it was given, e.g. Cream and Iron Bank
 pub struct Compound {
-    ctoken: BaseContract,
-    cether: BaseContract,
-    comptroller: BaseContract,
+    ctoken: &'static BaseContract,
+    cether: &'static BaseContract,
+    comptroller: &'static BaseContract,
     ctoken_to_token: HashMap<Address, Address>,
+    /// The fork of each market which is not Compound's
+    forks: HashMap<Address, Protocol>,
+    /// The comptrollers and oracles which liquidators query before liquidating
+    comptrollers: Vec<Address>,
+    oracles: Vec<Address>,
 }
 
 impl Inspector for Compound {
+    fn name(&self) -> &'static str {
+        "compound"
+    }
+
     fn inspect(&self, inspection: &mut Inspection) {
         let mut found = false;
         for i in 0..inspection.actions.len() {
@@ -50,8 +80,8 @@ impl Inspector for Compound {
             let (action, subtraces) = actions_after(&mut inspection.actions, i);
 
             // if the provided action is a liquidation, start parsing all the subtraces
-            if let Some((mut liquidation, trace)) = self.try_as_liquidation(&action) {
-                inspection.protocols.insert(Protocol::Compound);
+            if let Some((mut liquidation, trace, protocol)) = self.try_as_liquidation(&action) {
+                inspection.protocols.insert(protocol);
 
                 // omit the double-counted Dcall
                 if let Some(ref call_type) = action.as_call().map(|call| &call.call.call_type) {
@@ -60,13 +90,33 @@ impl Inspector for Compound {
                     }
                 }
 
-                // once we find the `seize` call, parse it
-                if let Some(seized) = subtraces.iter().find_map(|seize| self.try_as_seize(seize)) {
-                    liquidation.received_amount = seized.2;
+                // once we find the `seize` call of this liquidation, parse it
+                let seized = subtraces
+                    .iter()
+                    .filter_map(|seize| self.try_as_seize(seize))
+                    .find(|(_, borrower, liquidator, _)| {
+                        *borrower == liquidation.liquidated_user && *liquidator == liquidation.from
+                    });
+                if let Some((ctoken, _, _, seized_tokens)) = seized {
+                    // the cTokens are worth their underlying at the exchange rate
+                    // which the comptroller computed them with
+                    let rate = self.exchange_rate(&inspection.actions[i + 1..], &trace, ctoken);
+                    let underlying = rate.and_then(|rate| seized_tokens.checked_mul(rate));
+                    match (self.ctoken_to_token.get(&ctoken), underlying) {
+                        (Some(token), Some(amount)) => {
+                            liquidation.received_token = *token;
+                            liquidation.received_amount = amount / U256::exp10(18);
+                        }
+                        _ => {
+                            liquidation.received_token = ctoken;
+                            liquidation.received_amount = seized_tokens;
+                        }
+                    }
 
-                    *action = Classification::new(liquidation, trace);
+                    inspection.actions[i] = Classification::new(liquidation, trace);
                     if inspection.status != Status::Reverted {
                         inspection.status = Status::Success;
+                        inspection.checked_reason = None;
                     }
                     found = true;
                 }
@@ -75,7 +125,9 @@ impl Inspector for Compound {
                 *action = Classification::new(SpecificAction::LiquidationCheck, Vec::new());
                 // a pre-flight is only marked as "Checked" if a successful
                 // liquidation was not already found before it
-                inspection.status = Status::Checked;
+                let reason =
+                    CheckedReason::new(inspection.revert_reason.as_ref(), CheckedReason::Unknown);
+                inspection.mark_checked(reason);
             }
         }
     }
@@ -85,23 +137,41 @@ impl Compound {
     /// Constructor
     pub fn new<T: IntoIterator<Item = (Address, Address)>>(ctoken_to_token: T) -> Self {
         Self {
-            ctoken: BaseContract::from({
-                serde_json::from_str::<Abi>(include_str!("../../abi/ctoken.json"))
-                    .expect("could not parse ctoken abi")
-            }),
-            cether: BaseContract::from({
-                serde_json::from_str::<Abi>(include_str!("../../abi/cether.json"))
-                    .expect("could not parse ctoken abi")
-            }),
-            comptroller: BaseContract::from({
-                serde_json::from_str::<Abi>(include_str!("../../abi/comptroller.json"))
-                    .expect("could not parse ctoken abi")
-            }),
+            ctoken: &CTOKEN_ABI,
+            cether: &CETHER_ABI,
+            comptroller: &COMPTROLLER_ABI,
             ctoken_to_token: ctoken_to_token.into_iter().collect(),
+            forks: HashMap::new(),
+            comptrollers: vec![*COMPTROLLER],
+            oracles: vec![*COMP_ORACLE],
+        }
+    }
+
+    /// Adds the markets of a Compound fork, whose liquidations are tagged with
+    /// its protocol
+    pub fn with_fork<T: IntoIterator<Item = (Address, Address)>>(
+        mut self,
+        fork: &CompoundFork,
+        ctoken_to_token: T,
+    ) -> Self {
+        for (ctoken, token) in ctoken_to_token {
+            self.ctoken_to_token.insert(ctoken, token);
+            if fork.protocol != Protocol::Compound {
+                self.forks.insert(ctoken, fork.protocol);
+            }
+        }
+        if !self.comptrollers.contains(&fork.comptroller) {
+            self.comptrollers.push(fork.comptroller);
         }
+        if let Some(oracle) = fork.oracle {
+            if !self.oracles.contains(&oracle) {
+                self.oracles.push(oracle);
+            }
+        }
+        self
     }
 
-    /// Instantiates Compound with all live markets
+    /// Instantiates Compound and its forks with all their live markets
     ///
     /// # Panics
     ///
@@ -109,7 +179,28 @@ impl Compound {
     pub async fn create<M: Middleware>(
         provider: std::sync::Arc<M>,
     ) -> Result<Self, ContractError<M>> {
-        let comptroller = Comptroller::new(*COMPTROLLER, provider.clone());
+        let mut compound = Compound::new(Vec::new());
+        for fork in COMPOUND_FORKS.iter() {
+            // a fork whose markets cannot be fetched is not inspected, rather
+            // than failing the other ones
+            match Self::markets(provider.clone(), fork).await {
+                Ok(markets) => compound = compound.with_fork(fork, markets),
+                Err(err) => log::warn!(
+                    "skipping {:?}, could not get its markets: {}",
+                    fork.protocol,
+                    err
+                ),
+            }
+        }
+        Ok(compound)
+    }
+
+    /// Fetches the live markets of the fork along with their underlying
+    async fn markets<M: Middleware>(
+        provider: std::sync::Arc<M>,
+        fork: &CompoundFork,
+    ) -> Result<Vec<(Address, Address)>, ContractError<M>> {
+        let comptroller = Comptroller::new(fork.comptroller, provider.clone());
 
         let markets = comptroller.get_all_markets().call().await?;
         let futs = markets
@@ -117,31 +208,32 @@ impl Compound {
             .map(|market| {
                 let provider = provider.clone();
                 async move {
-                    if market != *CETH {
-                        (
-                            market,
-                            CToken::new(market, provider)
-                                .underlying()
-                                .call()
-                                .await
-                                .expect("could not get underlying"),
-                        )
+                    if Some(market) != fork.native_market {
+                        let underlying = CToken::new(market, provider).underlying().call().await?;
+                        Ok((market, underlying))
                     } else {
-                        (market, *WETH)
+                        Ok((market, *WETH))
                     }
                 }
             })
             .collect::<Vec<_>>();
-        let res = futures::future::join_all(futs).await;
-
-        Ok(Compound::new(res))
+        futures::future::try_join_all(futs).await
     }
 
-    /// Find the liquidation action
-    fn try_as_liquidation(&self, action: &Classification) -> Option<(Liquidation, Vec<usize>)> {
+    /// Find the liquidation action, along with the fork whose market it
+    /// repaid
+    fn try_as_liquidation(
+        &self,
+        action: &Classification,
+    ) -> Option<(Liquidation, TraceAddress, Protocol)> {
         match action {
             Classification::Unknown(ref calltrace) => {
                 let call = calltrace.as_ref();
+                let protocol = self
+                    .forks
+                    .get(&call.to)
+                    .cloned()
+                    .unwrap_or(Protocol::Compound);
                 if let Ok((liquidated_user, repaid_amount, ctoken_collateral)) =
                     self.ctoken
                         .decode::<LiquidateBorrow, _>("liquidateBorrow", &call.input)
@@ -156,8 +248,10 @@ impl Compound {
 
                             from: call.from,
                             liquidated_user,
+                            auction: None,
                         },
                         calltrace.trace_address.clone(),
+                        protocol,
                     ))
                 } else if let Ok((liquidated_user, ctoken_collateral)) =
                     self.cether
@@ -173,8 +267,10 @@ impl Compound {
 
                             from: call.from,
                             liquidated_user,
+                            auction: None,
                         },
                         calltrace.trace_address.clone(),
+                        protocol,
                     ))
                 } else {
                     None
@@ -184,18 +280,44 @@ impl Compound {
         }
     }
 
-    // Parses a subtrace
-    fn try_as_seize(&self, call: &Call) -> Option<(Address, Address, U256)> {
+    // Parses a subtrace into the seized cToken, the borrower, the liquidator
+    // and the amount of cTokens seized
+    fn try_as_seize(&self, call: &Call) -> Option<(Address, Address, Address, U256)> {
         if let Ok((_seizertoken, liquidator, borrower, seizetokens)) = self
             .ctoken
             .decode::<SeizeInternal, _>("seizeInternal", &call.input)
         {
-            Some((borrower, liquidator, seizetokens))
+            Some((call.to, borrower, liquidator, seizetokens))
         } else if let Ok((liquidator, borrower, seizetokens)) = self
             .ctoken
             .decode::<(Address, Address, U256), _>("seize", &call.input)
         {
-            Some((borrower, liquidator, seizetokens))
+            Some((call.to, borrower, liquidator, seizetokens))
+        } else {
+            None
+        }
+    }
+
+    /// The exchange rate of `ctoken`, scaled by 1e18, which the comptroller
+    /// read when computing the cTokens seized by the liquidation at
+    /// `trace_address`
+    fn exchange_rate(
+        &self,
+        actions: &[Classification],
+        trace_address: &[usize],
+        ctoken: Address,
+    ) -> Option<U256> {
+        let selector = ethers::utils::id("exchangeRateStored()");
+        let calltrace = actions
+            .iter()
+            .filter(|a| is_nested(trace_address, a.trace_address()))
+            .filter_map(|a| a.as_call())
+            .find(|c| {
+                c.as_ref().to == ctoken && c.as_ref().input.as_ref().starts_with(&selector)
+            })?;
+        let output = calltrace.output.as_ref();
+        if output.len() == 32 {
+            Some(U256::from_big_endian(output))
         } else {
             None
         }
@@ -206,9 +328,9 @@ impl Compound {
             Classification::Unknown(ref calltrace) => {
                 let call = calltrace.as_ref();
                 // checks if liquidation is allowed
-                call.to == *COMPTROLLER && call.input.as_ref().starts_with(&self.comptroller.as_ref().function("liquidateBorrowAllowed").unwrap().selector()) ||
+                self.comptrollers.contains(&call.to) && call.input.as_ref().starts_with(&self.comptroller.as_ref().function("liquidateBorrowAllowed").unwrap().selector()) ||
                     // checks oracle price
-                    call.to == *COMP_ORACLE && call.input.as_ref().starts_with(&ethers::utils::id("getUnderlyingPrice(address)"))
+                    self.oracles.contains(&call.to) && call.input.as_ref().starts_with(&ethers::utils::id("getUnderlyingPrice(address)"))
             }
             _ => false,
         }
@@ -230,22 +352,25 @@ impl Compound {
 mod tests {
     use super::*;
     use crate::{
-        addresses::{parse_address, ADDRESSBOOK},
+        addresses::{parse_address, ADDRESSBOOK, CETH},
         test_helpers::*,
         types::Status,
         Inspector,
     };
-    use ethers::providers::Provider;
+    use ethers::{abi::Token, providers::Provider, types::Bytes};
     use std::convert::TryFrom;
 
     #[test]
     // https://etherscan.io/tx/0xb7ba825294f757f8b8b6303b2aef542bcaebc9cc0217ddfaf822200a00594ed9
     fn liquidate() {
         let mut inspection = read_trace("compound_liquidation.json");
-        let ctoken_to_token = vec![(
-            parse_address("0xb3319f5d18bc0d84dd1b4825dcde5d5f7266d407"),
-            parse_address("0xe41d2489571d322189246dafa5ebde1f4699f498"),
-        )];
+        let ctoken_to_token = vec![
+            (
+                parse_address("0xb3319f5d18bc0d84dd1b4825dcde5d5f7266d407"),
+                parse_address("0xe41d2489571d322189246dafa5ebde1f4699f498"),
+            ),
+            (*CETH, *WETH),
+        ];
         let compound = Compound::new(ctoken_to_token);
         compound.inspect(&mut inspection);
 
@@ -257,8 +382,10 @@ mod tests {
             .unwrap();
 
         assert_eq!(ADDRESSBOOK.get(&liquidation.sent_token).unwrap(), "ZRX");
-        // cETH has 8 decimals
-        assert_eq!(liquidation.received_amount, 5250648.into());
+        // the 5250648 seized cETH are worth their ETH at the exchange rate
+        // which the comptroller read
+        assert_eq!(liquidation.received_token, *WETH);
+        assert_eq!(liquidation.received_amount, 1050696858379599u64.into());
         // ZRX has 18 decimals
         assert_eq!(liquidation.sent_amount, 653800000000000000u64.into());
 
@@ -266,6 +393,178 @@ mod tests {
         assert_eq!(inspection.status, Status::Success);
     }
 
+    fn liquidate_eth(
+        liquidator: Address,
+        borrower: Address,
+        collateral: Address,
+        value: U256,
+        trace_address: Vec<usize>,
+    ) -> Classification {
+        let mut call = mk_call(
+            liquidator,
+            *CETH,
+            calldata(
+                "liquidateBorrow(address,address)",
+                &[Token::Address(borrower), Token::Address(collateral)],
+            ),
+            trace_address,
+        );
+        if let Classification::Unknown(ref mut calltrace) = call {
+            calltrace.call.value = value;
+        }
+        call
+    }
+
+    fn seize(
+        ctoken: Address,
+        liquidator: Address,
+        borrower: Address,
+        amount: u64,
+        trace_address: Vec<usize>,
+    ) -> Classification {
+        mk_call(
+            *CETH,
+            ctoken,
+            calldata(
+                "seize(address,address,uint256)",
+                &[
+                    Token::Address(liquidator),
+                    Token::Address(borrower),
+                    Token::Uint(amount.into()),
+                ],
+            ),
+            trace_address,
+        )
+    }
+
+    #[test]
+    // Each liquidation gets the collateral of the `seize` of its own borrower,
+    // in the collateral's market
+    fn liquidate_eth_for_other_markets() {
+        let addrs = addrs();
+        let (liquidator, alice, bob, cusdc, cdai) =
+            (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4]);
+
+        let mut inspection = mk_inspection(vec![
+            mk_call(liquidator, addrs[5], Bytes::default(), vec![]),
+            liquidate_eth(liquidator, alice, cusdc, 100.into(), vec![0]),
+            seize(cusdc, liquidator, alice, 500, vec![0, 0]),
+            liquidate_eth(liquidator, bob, cdai, 200.into(), vec![1]),
+            seize(cdai, liquidator, bob, 700, vec![1, 0]),
+        ]);
+        let compound = Compound::new(vec![(*CETH, *WETH)]);
+        compound.inspect(&mut inspection);
+
+        let liquidations = inspection
+            .known()
+            .iter()
+            .filter_map(|x| x.as_ref().liquidation().cloned())
+            .collect::<Vec<_>>();
+        assert_eq!(liquidations.len(), 2);
+        for (liquidation, (user, collateral, sent, received)) in liquidations
+            .iter()
+            .zip(&[(alice, cusdc, 100, 500), (bob, cdai, 200, 700)])
+        {
+            assert_eq!(liquidation.liquidated_user, *user);
+            // the repaid asset is reported as the underlying
+            assert_eq!(liquidation.sent_token, *WETH);
+            assert_eq!(liquidation.sent_amount, (*sent).into());
+            assert_eq!(liquidation.received_token, *collateral);
+            assert_eq!(liquidation.received_amount, (*received).into());
+        }
+        assert_eq!(inspection.protocols, crate::set![Protocol::Compound]);
+    }
+
+    #[test]
+    // The liquidations of a fork's markets are tagged with the fork, and its
+    // comptroller's checks are pre-flights
+    fn liquidate_on_forks() {
+        let addrs = addrs();
+        let (liquidator, borrower, crusdc, usdc, crdai, dai) =
+            (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4], addrs[6]);
+        for fork in &COMPOUND_FORKS[1..] {
+            let compound = Compound::new(vec![(*CETH, *WETH)])
+                .with_fork(fork, vec![(crusdc, usdc), (crdai, dai)]);
+            let check = mk_call(
+                crusdc,
+                fork.comptroller,
+                calldata(
+                    "liquidateBorrowAllowed(address,address,address,address,uint256)",
+                    &[
+                        Token::Address(crusdc),
+                        Token::Address(crdai),
+                        Token::Address(liquidator),
+                        Token::Address(borrower),
+                        Token::Uint(100.into()),
+                    ],
+                ),
+                vec![0, 0],
+            );
+            // 0.02 DAI per crDAI, scaled by 1e18
+            let rate = with_output(
+                mk_call(
+                    fork.comptroller,
+                    crdai,
+                    calldata("exchangeRateStored()", &[]),
+                    vec![0, 1, 0],
+                ),
+                &[Token::Uint(U256::exp10(16) * 2)],
+            );
+
+            let mut inspection = mk_inspection(vec![
+                mk_call(liquidator, addrs[5], Bytes::default(), vec![]),
+                mk_call(
+                    liquidator,
+                    crusdc,
+                    calldata(
+                        "liquidateBorrow(address,uint256,address)",
+                        &[
+                            Token::Address(borrower),
+                            Token::Uint(100.into()),
+                            Token::Address(crdai),
+                        ],
+                    ),
+                    vec![0],
+                ),
+                check.clone(),
+                mk_call(crusdc, fork.comptroller, Bytes::default(), vec![0, 1]),
+                rate,
+                mk_call(
+                    crusdc,
+                    crdai,
+                    calldata(
+                        "seize(address,address,uint256)",
+                        &[
+                            Token::Address(liquidator),
+                            Token::Address(borrower),
+                            Token::Uint(300.into()),
+                        ],
+                    ),
+                    vec![0, 2],
+                ),
+            ]);
+            compound.inspect(&mut inspection);
+
+            let liquidation = inspection
+                .known()
+                .iter()
+                .find_map(|x| x.as_ref().liquidation())
+                .cloned()
+                .unwrap();
+            assert_eq!(liquidation.sent_token, usdc);
+            assert_eq!(liquidation.received_token, dai);
+            assert_eq!(liquidation.received_amount, 6.into());
+            assert_eq!(inspection.protocols, crate::set![fork.protocol]);
+            assert_eq!(inspection.status, Status::Success);
+
+            // a check which was not followed by the liquidation
+            let mut checked = mk_inspection(vec![check]);
+            compound.inspect(&mut checked);
+            assert_eq!(checked.status, Status::Checked);
+            assert_eq!(checked.checked_reason, Some(CheckedReason::Unknown));
+        }
+    }
+
     #[tokio::test]
     async fn instantiate() {
         let provider =
@@ -0,0 +1,340 @@
+use crate::{
+    addresses::COMETS,
+    is_nested,
+    types::{
+        actions::{CollateralAbsorption, Liquidation},
+        Classification, Inspection, Protocol, Status,
+    },
+    Inspector,
+};
+use ethers::{
+    abi::parse_abi,
+    contract::BaseContract,
+    types::{Address, CallType, Log, H256, U256},
+    utils::keccak256,
+};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+
+static COMET_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    parse_abi(&[
+        "function absorb(address, address[])",
+        "function buyCollateral(address, uint256, uint256, address)",
+    ])
+    .expect("could not parse comet abi")
+    .into()
+});
+
+/// `AbsorbCollateral(address indexed absorber, address indexed borrower, address indexed asset, uint collateralAbsorbed, uint usdValue)`
+static ABSORB_COLLATERAL_EVENT: Lazy<H256> = Lazy::new(|| {
+    H256::from(keccak256(
+        "AbsorbCollateral(address,address,address,uint256,uint256)",
+    ))
+});
+
+/// `AbsorbDebt(address indexed absorber, address indexed borrower, uint basePaidOut, uint usdValue)`
+static ABSORB_DEBT_EVENT: Lazy<H256> =
+    Lazy::new(|| H256::from(keccak256("AbsorbDebt(address,address,uint256,uint256)")));
+
+type Absorb = (Address, Vec<Address>);
+type BuyCollateral = (Address, U256, U256, Address);
+
+#[derive(Debug, Clone)]
+/// An inspector for Compound V3 liquidations, which take two steps: an
+/// underwater account is absorbed by the market, which takes over its debt and
+/// collateral, and liquidators then buy the collateral from the market at a
+/// discount. Each absorbed account is classified as a `CollateralAbsorption`,
+/// whose collateral is only known from the logs, and each purchase as a
+/// `Liquidation` of an unknown borrower, which the `CompoundV3Reducer` links to
+/// the absorptions of the block.
+///
+/// It must run after `ERC20`, since the purchases are settled with transfers.
+pub struct CompoundV3 {
+    comet: &'static BaseContract,
+    /// The base token of each market
+    comets: HashMap<Address, Address>,
+}
+
+impl Default for CompoundV3 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Inspector for CompoundV3 {
+    fn name(&self) -> &'static str {
+        "compoundv3"
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        // the classifications of the market calls, by index
+        let mut found = HashMap::new();
+        let mut prune = Vec::new();
+        for (i, action) in inspection.actions.iter().enumerate() {
+            let calltrace = match action.as_call() {
+                // the proxy's delegate call to the implementation is not
+                // another liquidation
+                Some(calltrace) if calltrace.call.call_type == CallType::Call => calltrace,
+                _ => continue,
+            };
+            let call = calltrace.as_ref();
+            let base_token = match self.comets.get(&call.to) {
+                Some(inner) => *inner,
+                None => continue,
+            };
+            let trace_address = &calltrace.trace_address;
+
+            if let Ok((absorber, accounts)) = self.comet.decode::<Absorb, _>("absorb", &call.input)
+            {
+                let absorptions = accounts
+                    .into_iter()
+                    .map(|borrower| {
+                        let absorption =
+                            absorption(call.to, absorber, borrower, base_token, &inspection.logs);
+                        Classification::new(absorption, trace_address.clone())
+                    })
+                    .collect::<Vec<_>>();
+                found.insert(i, absorptions);
+            } else if let Ok((asset, _, base_amount, recipient)) = self
+                .comet
+                .decode::<BuyCollateral, _>("buyCollateral", &call.input)
+            {
+                let mut liquidation = Liquidation {
+                    sent_token: base_token,
+                    sent_amount: base_amount,
+                    received_token: asset,
+                    received_amount: 0.into(),
+                    from: call.from,
+                    liquidated_user: Address::zero(),
+                    auction: None,
+                };
+                // the base token paid in and the collateral paid out
+                let transfers = inspection
+                    .actions
+                    .iter()
+                    .enumerate()
+                    .skip(i + 1)
+                    .filter(|(_, a)| is_nested(trace_address, a.trace_address()))
+                    .filter_map(|(j, a)| Some((j, a.as_action()?.transfer()?)));
+                for (j, transfer) in transfers {
+                    if transfer.token == base_token
+                        && transfer.from == call.from
+                        && transfer.to == call.to
+                    {
+                        liquidation.sent_amount = transfer.amount;
+                        prune.push(j);
+                    } else if transfer.token == asset
+                        && transfer.from == call.to
+                        && transfer.to == recipient
+                    {
+                        liquidation.received_amount = transfer.amount;
+                        prune.push(j);
+                    }
+                }
+                found.insert(
+                    i,
+                    vec![Classification::new(liquidation, trace_address.clone())],
+                );
+            }
+        }
+
+        if found.is_empty() {
+            return;
+        }
+        for j in prune {
+            inspection.actions[j] = Classification::Prune;
+        }
+        let mut actions = Vec::with_capacity(inspection.actions.len());
+        for (i, action) in inspection.actions.drain(..).enumerate() {
+            match found.remove(&i) {
+                Some(classified) => actions.extend(classified),
+                None => actions.push(action),
+            }
+        }
+        inspection.actions = actions;
+        inspection.protocols.insert(Protocol::CompoundV3);
+        if inspection.status != Status::Reverted {
+            inspection.status = Status::Success;
+        }
+    }
+}
+
+impl CompoundV3 {
+    /// Constructor
+    pub fn new() -> Self {
+        Self {
+            comet: &COMET_ABI,
+            comets: COMETS.clone(),
+        }
+    }
+}
+
+/// The absorption of `borrower`, along with its collateral and debt if the
+/// market's logs are known
+fn absorption(
+    comet: Address,
+    absorber: Address,
+    borrower: Address,
+    base_token: Address,
+    logs: &[Log],
+) -> CollateralAbsorption {
+    let borrower_topic = H256::from(borrower);
+    let logs = logs
+        .iter()
+        .filter(|log| {
+            log.address == comet && log.topics.len() >= 3 && log.topics[2] == borrower_topic
+        })
+        .collect::<Vec<_>>();
+    let amount = |log: &Log| {
+        if log.data.as_ref().len() >= 32 {
+            U256::from_big_endian(&log.data.as_ref()[..32])
+        } else {
+            U256::zero()
+        }
+    };
+
+    CollateralAbsorption {
+        comet,
+        absorber,
+        borrower,
+        base_token,
+        debt: logs
+            .iter()
+            .find(|log| log.topics[0] == *ABSORB_DEBT_EVENT)
+            .map(|log| amount(log))
+            .unwrap_or_else(U256::zero),
+        collateral: logs
+            .iter()
+            .filter(|log| log.topics.len() == 4 && log.topics[0] == *ABSORB_COLLATERAL_EVENT)
+            .map(|log| (Address::from(log.topics[3]), amount(log)))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{inspectors::ERC20, test_helpers::*};
+    use ethers::{abi::Token, types::Bytes};
+
+    fn comet() -> (Address, Address) {
+        let comet = crate::addresses::parse_address("c3d688B66703497DAA19211EEdff47f25384cdc3");
+        (comet, COMETS[&comet])
+    }
+
+    fn uint(amount: u64) -> Vec<u8> {
+        let mut data = [0; 32];
+        U256::from(amount).to_big_endian(&mut data);
+        data.to_vec()
+    }
+
+    #[test]
+    fn absorbs_accounts() {
+        let addrs = addrs();
+        let (absorber, alice, bob, wbtc, weth) = (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4]);
+        let (comet, usdc) = comet();
+        let mut inspection = mk_inspection(vec![mk_call(
+            absorber,
+            comet,
+            calldata(
+                "absorb(address,address[])",
+                &[
+                    Token::Address(absorber),
+                    Token::Array(vec![Token::Address(alice), Token::Address(bob)]),
+                ],
+            ),
+            vec![],
+        )]);
+        let collateral = |borrower: Address, asset: Address, amount: u64| {
+            mk_log(
+                comet,
+                vec![
+                    *ABSORB_COLLATERAL_EVENT,
+                    H256::from(absorber),
+                    H256::from(borrower),
+                    H256::from(asset),
+                ],
+                [uint(amount), uint(0)].concat(),
+            )
+        };
+        inspection.logs = vec![
+            collateral(alice, wbtc, 10),
+            collateral(alice, weth, 20),
+            mk_log(
+                comet,
+                vec![*ABSORB_DEBT_EVENT, H256::from(absorber), H256::from(alice)],
+                [uint(1000), uint(0)].concat(),
+            ),
+        ];
+        CompoundV3::new().inspect(&mut inspection);
+
+        let absorptions = inspection
+            .known_actions()
+            .filter_map(|a| a.collateral_absorption())
+            .collect::<Vec<_>>();
+        assert_eq!(absorptions.len(), 2);
+        assert_eq!(
+            (absorptions[0].borrower, absorptions[0].base_token),
+            (alice, usdc)
+        );
+        assert_eq!(absorptions[0].debt, 1000.into());
+        assert_eq!(
+            absorptions[0].collateral,
+            vec![(wbtc, 10.into()), (weth, 20.into())]
+        );
+        // bob's logs were not fetched
+        assert_eq!(absorptions[1].borrower, bob);
+        assert!(absorptions[1].collateral.is_empty());
+        assert_eq!(inspection.protocols, crate::set![Protocol::CompoundV3]);
+    }
+
+    #[test]
+    fn buys_collateral() {
+        let addrs = addrs();
+        let (buyer, recipient, wbtc) = (addrs[0], addrs[1], addrs[2]);
+        let (comet, usdc) = comet();
+        let mut inspection = mk_inspection(vec![
+            mk_call(
+                buyer,
+                comet,
+                calldata(
+                    "buyCollateral(address,uint256,uint256,address)",
+                    &[
+                        Token::Address(wbtc),
+                        Token::Uint(9.into()),
+                        Token::Uint(3000.into()),
+                        Token::Address(recipient),
+                    ],
+                ),
+                vec![],
+            ),
+            mk_call(comet, addrs[3], Bytes::default(), vec![0]),
+            mk_call(
+                comet,
+                usdc,
+                transfer_from(buyer, comet, 3000.into()),
+                vec![0, 0],
+            ),
+            mk_call(comet, wbtc, transfer(recipient, 10.into()), vec![0, 1]),
+        ]);
+        ERC20::new().inspect(&mut inspection);
+        CompoundV3::new().inspect(&mut inspection);
+        inspection.prune();
+
+        let known = inspection.known();
+        assert_eq!(known.len(), 1);
+        assert_eq!(
+            known[0].as_ref().liquidation(),
+            Some(&Liquidation {
+                sent_token: usdc,
+                sent_amount: 3000.into(),
+                received_token: wbtc,
+                received_amount: 10.into(),
+                from: buyer,
+                liquidated_user: Address::zero(),
+                auction: None,
+            })
+        );
+        assert_eq!(inspection.status, Status::Success);
+    }
+}
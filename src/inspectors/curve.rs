@@ -1,29 +1,84 @@
 #![allow(clippy::too_many_arguments)]
 use crate::{
-    addresses::CURVE_REGISTRY,
+    addresses::{ChainConfig, CURVE_REGISTRY, NATIVE_ETH},
+    is_nested,
     traits::Inspector,
-    types::{actions::AddLiquidity, Classification, Inspection, Protocol},
+    types::{
+        actions::{AddLiquidity, RemoveLiquidity, SpecificAction, Trade, Transfer},
+        Classification, Inspection, Protocol, Provenance,
+    },
 };
 
+use ethers::{abi::Abi, contract::BaseContract};
 use ethers::{
-    abi::parse_abi,
+    abi::{parse_abi, FunctionExt},
     contract::decode_function_data,
     contract::{abigen, ContractError},
     providers::Middleware,
     types::{Address, Bytes, Call as TraceCall, U256},
 };
-use ethers::{abi::Abi, contract::BaseContract};
+use once_cell::sync::Lazy;
 use std::collections::HashMap;
 
+static POOL_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    serde_json::from_str::<Abi>(include_str!("../../abi/curvepool.json"))
+        .expect("could not parse Curve 2-pool abi")
+        .into()
+});
+
+static POOL3_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    parse_abi(&[
+        "function add_liquidity(uint256[3] calldata amounts, uint256 deadline) external",
+        "function remove_liquidity(uint256 amount, uint256[3] calldata min_amounts) external",
+        "function remove_liquidity_imbalance(uint256[3] calldata amounts, uint256 max_burn_amount) external",
+        "function remove_liquidity_one_coin(uint256 amount, int128 i, uint256 min_amount) external",
+    ])
+    .expect("could not parse curve 3-pool abi")
+    .into()
+});
+
+static POOL4_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    parse_abi(&[
+        "function add_liquidity(uint256[4] calldata amounts, uint256 deadline) external",
+        "function remove_liquidity(uint256 amount, uint256[4] calldata min_amounts) external",
+        "function remove_liquidity_imbalance(uint256[4] calldata amounts, uint256 max_burn_amount) external",
+        "function remove_liquidity_one_coin(uint256 amount, int128 i, uint256 min_amount) external",
+    ])
+    .expect("could not parse curve 4-pool abi")
+    .into()
+});
+
 // Type aliases for Curve
 type Exchange = (u128, u128, U256, U256);
 
+#[derive(Debug, Clone, Default, PartialEq)]
+/// The coins of a Curve pool, ordered by their index in the pool
+pub struct CurvePool {
+    /// The coins held by the pool, used by `exchange`
+    pub coins: Vec<Address>,
+    /// The coins which get wrapped to the pool's coins, used by
+    /// `exchange_underlying`. For plain pools these are the same as `coins`.
+    pub underlying_coins: Vec<Address>,
+}
+
+impl From<Vec<Address>> for CurvePool {
+    fn from(coins: Vec<Address>) -> Self {
+        Self {
+            underlying_coins: coins.clone(),
+            coins,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 /// An inspector for Curve
 pub struct Curve {
-    pool: BaseContract,
-    pool4: BaseContract,
-    pools: HashMap<Address, Vec<Address>>,
+    pool: &'static BaseContract,
+    pool3: &'static BaseContract,
+    pool4: &'static BaseContract,
+    pools: HashMap<Address, CurvePool>,
+    /// The placeholder which the pools' native coin is classified with
+    native: Address,
 }
 
 abigen!(
@@ -35,57 +90,110 @@ abigen!(
 );
 
 impl Inspector for Curve {
-    fn inspect(&self, inspection: &mut Inspection) {
-        let mut prune = Vec::new();
-        for i in 0..inspection.actions.len() {
-            let action = &mut inspection.actions[i];
+    fn name(&self) -> &'static str {
+        "curve"
+    }
 
+    fn inspect(&self, inspection: &mut Inspection) {
+        let actions = &inspection.actions;
+        let mut classified = Vec::new();
+        for (i, action) in actions.iter().enumerate() {
             if let Some(calltrace) = action.as_call() {
                 let call = calltrace.as_ref();
                 if self.check(call) {
                     inspection.protocols.insert(Protocol::Curve);
                 }
 
-                if let Some(liquidity) = self.as_add_liquidity(&call.to, &call.input) {
-                    *action = Classification::new(liquidity, calltrace.trace_address.clone());
-                    prune.push(i);
+                // the transfers which make up the action are nested under the call
+                let subtraces = actions
+                    .iter()
+                    .skip(i + 1)
+                    .filter(|a| is_nested(&calltrace.trace_address, a.trace_address()))
+                    .filter_map(|a| a.as_action())
+                    .filter_map(|a| a.transfer())
+                    .collect::<Vec<_>>();
+
+                // only the deposits are known from the calldata, the amounts of
+                // the removals and trades are the ones of their transfers
+                let res = self
+                    .as_add_liquidity(&call.to, &call.input)
+                    .map(|add| (SpecificAction::from(add), Provenance::CalldataDecoded))
+                    .or_else(|| {
+                        self.as_remove_liquidity(call, &subtraces)
+                            .map(|remove| (remove.into(), Provenance::TransferInferred))
+                    })
+                    .or_else(|| {
+                        self.as_trade(call, &subtraces)
+                            .map(|trade| (trade.into(), Provenance::TransferInferred))
+                    });
+
+                if let Some((res, provenance)) = res {
+                    inspection.protocols.insert(Protocol::Curve);
+                    let action =
+                        Classification::new::<SpecificAction>(res, calltrace.trace_address.clone())
+                            .with_provenance(provenance);
+                    classified.push((i, action));
                 }
             }
         }
 
-        let actions = inspection.actions.to_vec();
-        prune
-            .into_iter()
-            .for_each(|idx| actions[idx].prune_subcalls(&mut inspection.actions));
+        let prune = classified.iter().map(|(i, _)| *i).collect::<Vec<_>>();
+        for (i, action) in classified {
+            inspection.actions[i] = action;
+        }
+        // the subcalls come after their call
+        for i in prune {
+            let (calls, subcalls) = inspection.actions.split_at_mut(i + 1);
+            calls[i].prune_subcalls(subcalls);
+        }
         // TODO: Add checked calls
     }
 }
 
 impl Curve {
     /// Constructor
-    pub fn new<T: IntoIterator<Item = (Address, Vec<Address>)>>(pools: T) -> Self {
+    pub fn new<T: IntoIterator<Item = (Address, CurvePool)>>(pools: T) -> Self {
         Self {
-            pool: serde_json::from_str::<Abi>(include_str!("../../abi/curvepool.json"))
-                .expect("could not parse Curve 2-pool abi")
-                .into(),
-            pool4: parse_abi(&[
-                "function add_liquidity(uint256[4] calldata amounts, uint256 deadline) external",
-            ])
-            .expect("could not parse curve 4-pool abi")
-            .into(),
+            pool: &POOL_ABI,
+            pool3: &POOL3_ABI,
+            pool4: &POOL4_ABI,
             pools: pools.into_iter().collect(),
+            native: NATIVE_ETH,
+        }
+    }
+
+    /// Instantiates an inspector for the `pools` of `chain`
+    pub fn with_chain<T: IntoIterator<Item = (Address, CurvePool)>>(
+        pools: T,
+        chain: &ChainConfig,
+    ) -> Self {
+        Self {
+            native: chain.native,
+            ..Self::new(pools)
+        }
+    }
+
+    /// Curve denotes ETH with `0xeee...eee`
+    fn coin(&self, coin: Address) -> Address {
+        if coin == NATIVE_ETH {
+            self.native
+        } else {
+            coin
         }
     }
 
     fn as_add_liquidity(&self, to: &Address, data: &Bytes) -> Option<AddLiquidity> {
-        let tokens = self.pools.get(to)?;
+        let tokens = &self.pools.get(to)?.coins;
         // adapter for Curve's pool-specific abi decoding
-        // TODO: Do we need to add the tripool?
         let amounts = match tokens.len() {
             2 => self
                 .pool
                 .decode::<([U256; 2], U256), _>("add_liquidity", data)
                 .map(|x| x.0.to_vec()),
+            3 => self
+                .pool3
+                .decode::<([U256; 3], U256), _>("add_liquidity", data)
+                .map(|x| x.0.to_vec()),
             4 => self
                 .pool4
                 .decode::<([U256; 4], U256), _>("add_liquidity", data)
@@ -98,8 +206,100 @@ impl Curve {
         };
 
         Some(AddLiquidity {
-            tokens: tokens.clone(),
+            tokens: tokens.iter().map(|coin| self.coin(*coin)).collect(),
             amounts,
+            position: None,
+        })
+    }
+
+    /// Parses any of the `remove_liquidity` variants. The amounts in their
+    /// arguments are only bounds, so the amounts removed are read from the
+    /// transfers out of the pool.
+    fn as_remove_liquidity(
+        &self,
+        call: &TraceCall,
+        transfers: &[&Transfer],
+    ) -> Option<RemoveLiquidity> {
+        let coins = &self.pools.get(&call.to)?.coins;
+        let contract = match coins.len() {
+            2 => &self.pool,
+            3 => &self.pool3,
+            4 => &self.pool4,
+            _ => return None,
+        };
+
+        let tokens = if let Ok((_, i, _)) =
+            contract.decode::<(U256, u128, U256), _>("remove_liquidity_one_coin", &call.input)
+        {
+            vec![self.coin(*coins.get(i as usize)?)]
+        } else if contract
+            .abi()
+            .functions()
+            .filter(|f| f.name.starts_with("remove_liquidity"))
+            .any(|f| call.input.as_ref().get(..4) == Some(&f.selector()[..]))
+        {
+            coins.iter().map(|coin| self.coin(*coin)).collect()
+        } else {
+            return None;
+        };
+
+        let amounts = tokens
+            .iter()
+            .map(|token| {
+                transfers
+                    .iter()
+                    .filter(|t| t.token == *token && t.from == call.to)
+                    .fold(U256::zero(), |sum, t| sum.saturating_add(t.amount))
+            })
+            .collect();
+
+        Some(RemoveLiquidity {
+            tokens,
+            amounts,
+            fees: Vec::new(),
+            position: None,
+        })
+    }
+
+    /// Parses `exchange` and `exchange_underlying`, resolving their coin indices
+    /// against the pool's (underlying) coins
+    fn as_trade(&self, call: &TraceCall, transfers: &[&Transfer]) -> Option<Trade> {
+        let pool = self.pools.get(&call.to)?;
+        let (coins, (i, j, _, _)) =
+            if let Ok(args) = self.pool.decode::<Exchange, _>("exchange", &call.input) {
+                (&pool.coins, args)
+            } else if let Ok(args) = self
+                .pool
+                .decode::<Exchange, _>("exchange_underlying", &call.input)
+            {
+                (&pool.underlying_coins, args)
+            } else {
+                return None;
+            };
+        let token_in = self.coin(*coins.get(i as usize)?);
+        let token_out = self.coin(*coins.get(j as usize)?);
+
+        let t1 = if token_in == self.native && !call.value.is_zero() {
+            // ETH gets sent along with the call
+            Transfer {
+                from: call.from,
+                to: call.to,
+                amount: call.value,
+                token: token_in,
+            }
+        } else {
+            transfers
+                .iter()
+                .find(|t| t.token == token_in && t.to == call.to)
+                .map(|t| (*t).clone())?
+        };
+        let t2 = transfers
+            .iter()
+            .find(|t| t.token == token_out && t.from == call.to)?;
+
+        Some(Trade {
+            t1,
+            t2: (*t2).clone(),
         })
     }
 
@@ -109,12 +309,33 @@ impl Curve {
         let mut this = Self::new(vec![]);
         let registry = CurveRegistry::new(*CURVE_REGISTRY, provider);
 
+        // the registry returns fixed size arrays, padded with zeros
+        let trim = |coins: [Address; 8]| {
+            coins
+                .iter()
+                .cloned()
+                .take_while(|coin| !coin.is_zero())
+                .collect::<Vec<_>>()
+        };
+
         let pool_count = registry.pool_count().call().await?;
         // TODO: Cache these locally.
         for i in 0..pool_count.as_u64() {
             let pool = registry.pool_list(i.into()).call().await?;
-            let tokens = registry.get_underlying_coins(pool).call().await?;
-            this.pools.insert(pool, tokens.to_vec());
+            let coins = trim(registry.get_coins(pool).call().await?);
+            let underlying_coins = trim(registry.get_underlying_coins(pool).call().await?);
+            let underlying_coins = if underlying_coins.is_empty() {
+                coins.clone()
+            } else {
+                underlying_coins
+            };
+            this.pools.insert(
+                pool,
+                CurvePool {
+                    coins,
+                    underlying_coins,
+                },
+            );
         }
 
         Ok(this)
@@ -140,11 +361,13 @@ impl Curve {
 mod tests {
     use super::*;
     use crate::{
+        addresses::parse_address,
         inspectors::ERC20,
         reducers::{ArbitrageReducer, TradeReducer},
-        test_helpers::read_trace,
+        test_helpers::*,
         Reducer,
     };
+    use ethers::abi::Token;
     use ethers::providers::Provider;
     use std::convert::TryFrom;
 
@@ -198,4 +421,170 @@ mod tests {
             .unwrap();
         assert_eq!(arb.profit.to_string(), "45259140804");
     }
+
+    fn stablecoins() -> (Address, Address, Address) {
+        (
+            parse_address("0x6b175474e89094c44da98b954eedeac495271d0f"),
+            parse_address("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48"),
+            parse_address("0xdac17f958d2ee523a2206206994597c13d831ec7"),
+        )
+    }
+
+    /// The pools which the simple arb went through
+    fn stablecoin_pools() -> Curve {
+        let (dai, usdc, usdt) = stablecoins();
+        let coins = |addrs: &[&str]| addrs.iter().map(|a| parse_address(a)).collect();
+        let busd = parse_address("0x79a8c46dea5ada233abaffd40f3a0a2b1e5a4f27");
+        let ypool = parse_address("0x45f783cce6b7ff23b2ab2d70e416cdb7d6055f51");
+        Curve::new(vec![
+            (
+                busd,
+                CurvePool {
+                    coins: coins(&[
+                        "0xc2cb1040220768554cf699b0d863a3cd4324ce32",
+                        "0x26ea744e5b887e5205727f55dfbe8685e3b21951",
+                        "0xe6354ed5bc4b393a5aad09f21c46e101e692d447",
+                        "0x04bc0ab673d88ae9dbc9da2380cb6b79c4bca9ae",
+                    ]),
+                    underlying_coins: coins(&[
+                        "0x6b175474e89094c44da98b954eedeac495271d0f",
+                        "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48",
+                        "0xdac17f958d2ee523a2206206994597c13d831ec7",
+                        "0x4fabb145d64652a948d72533023f6e7a623c7c53",
+                    ]),
+                },
+            ),
+            (
+                ypool,
+                CurvePool {
+                    coins: coins(&[
+                        "0x16de59092dae5ccf4a1e6439d611fd0653f0bd01",
+                        "0xd6ad7a6750a7593e092a9b218d66c0a814a3436e",
+                        "0x83f798e925bcd4017eb265844fddabb448f1707d",
+                        "0x73a052500105205d34daf004eab301916da8190f",
+                    ]),
+                    underlying_coins: vec![
+                        dai,
+                        usdc,
+                        usdt,
+                        parse_address("0x0000000000085d4780b73119b644ae5ecd22b376"),
+                    ],
+                },
+            ),
+        ])
+    }
+
+    #[test]
+    fn exchange_underlying() {
+        let (_, usdc, usdt) = stablecoins();
+        let busd = parse_address("0x79a8c46dea5ada233abaffd40f3a0a2b1e5a4f27");
+        let ypool = parse_address("0x45f783cce6b7ff23b2ab2d70e416cdb7d6055f51");
+        let curve = stablecoin_pools();
+
+        let mut inspection = read_trace("simple_curve_arb.json");
+        ERC20::new().inspect(&mut inspection);
+        curve.inspect(&mut inspection);
+        inspection.prune();
+
+        let trades = inspection
+            .known()
+            .iter()
+            .filter_map(|x| x.as_ref().trade())
+            .cloned()
+            .collect::<Vec<_>>();
+        assert_eq!(trades.len(), 2);
+        assert_eq!(trades[0].t1.token, usdc);
+        assert_eq!(trades[0].t1.to, busd);
+        assert_eq!(trades[0].t1.amount, 4888682275029u64.into());
+        assert_eq!(trades[0].t2.token, usdt);
+        assert_eq!(trades[0].t2.from, busd);
+        assert_eq!(trades[1].t1.token, usdt);
+        assert_eq!(trades[1].t1.amount, trades[0].t2.amount);
+        assert_eq!(trades[1].t2.token, usdc);
+        assert_eq!(trades[1].t2.from, ypool);
+        assert!(inspection.protocols.contains(&Protocol::Curve));
+
+        // the 2 trades make up the arb
+        ArbitrageReducer::new().reduce(&mut inspection);
+        let arb = inspection
+            .known()
+            .iter()
+            .find_map(|x| x.as_ref().arbitrage())
+            .cloned()
+            .unwrap();
+        assert_eq!(arb.profit.to_string(), "45259140804");
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    // the same arb, classified with the pools it went through
+    fn exchange_underlying_snapshot() {
+        let mut inspection = read_trace("simple_curve_arb.json");
+        ERC20::new().inspect(&mut inspection);
+        stablecoin_pools().inspect(&mut inspection);
+        ArbitrageReducer::new().reduce(&mut inspection);
+        inspection.prune();
+        assert_snapshot_matches(
+            &inspection,
+            include_str!("../../res/snapshots/simple_curve_arb.json"),
+        );
+    }
+
+    #[test]
+    fn exchange() {
+        let (dai, usdc, usdt) = stablecoins();
+        let susd = parse_address("0x57ab1ec28d129707052df4df418d58a2d46d5f51");
+        let pool = parse_address("0xa5407eae9ba41422680e2e00537571bcc53efbfd");
+        let curve = Curve::new(vec![(pool, vec![dai, usdc, usdt, susd].into())]);
+
+        let mut inspection = read_trace("curve_arb.json");
+        ERC20::new().inspect(&mut inspection);
+        curve.inspect(&mut inspection);
+        inspection.prune();
+
+        let known = inspection.known();
+        let (trade, provenance) = known
+            .iter()
+            .find_map(|x| x.as_ref().trade().map(|trade| (trade, x.provenance)))
+            .unwrap();
+        assert_eq!(provenance, Provenance::TransferInferred);
+        assert_eq!(trade.t1.token, usdt);
+        assert_eq!(trade.t1.amount, 6116725297u64.into());
+        assert_eq!(trade.t2.token, susd);
+        assert_eq!(
+            trade.t2.amount,
+            U256::from_dec_str("6108981413754624256899").unwrap()
+        );
+    }
+
+    #[test]
+    fn remove_liquidity_one_coin() {
+        let (dai, usdc, usdt) = stablecoins();
+        let addrs = addrs();
+        let (usr, pool) = (addrs[0], addrs[1]);
+        let curve = Curve::new(vec![(pool, vec![dai, usdc, usdt].into())]);
+
+        let input = calldata(
+            "remove_liquidity_one_coin(uint256,int128,uint256)",
+            &[
+                Token::Uint(100.into()),
+                Token::Int(1.into()),
+                Token::Uint(0.into()),
+            ],
+        );
+        let mut inspection = mk_inspection(vec![
+            mk_call(usr, pool, input, vec![0]),
+            mk_call(pool, usdc, transfer(usr, 101.into()), vec![0, 0]),
+        ]);
+        ERC20::new().inspect(&mut inspection);
+        curve.inspect(&mut inspection);
+        inspection.prune();
+
+        let known = inspection.known();
+        assert_eq!(known.len(), 1);
+        let remove = known[0].as_ref().remove_liquidity().unwrap();
+        assert_eq!(remove.tokens, vec![usdc]);
+        assert_eq!(remove.amounts, vec![101.into()]);
+        assert_eq!(known[0].provenance, Provenance::TransferInferred);
+    }
 }
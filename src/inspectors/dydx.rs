@@ -0,0 +1,585 @@
+use crate::{
+    addresses::{parse_address, DYDX, WETH},
+    is_nested,
+    types::{
+        actions::{FlashLoan, Liquidation, Trade, Transfer},
+        Classification, Inspection, Protocol,
+    },
+    Inspector,
+};
+use ethers::{
+    abi::{self, ParamType, Tokenizable},
+    types::{Address, Call as TraceCall, CallType, Log, H256, U256},
+    utils::{id, keccak256},
+};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+
+/// `operate(Account.Info[] accounts, Actions.ActionArgs[] actions)`
+const OPERATE: &str = "operate((address,uint256)[],(uint8,uint256,(bool,uint8,uint8,uint256),uint256,uint256,address,uint256,bytes)[])";
+
+/// `LogLiquidate`, whose balance updates are the amounts exchanged by the
+/// liquidator
+static LOG_LIQUIDATE: Lazy<H256> = Lazy::new(|| {
+    H256::from(keccak256(
+        "LogLiquidate(address,uint256,address,uint256,uint256,uint256,((bool,uint256),(bool,uint128)),((bool,uint256),(bool,uint128)),((bool,uint256),(bool,uint128)),((bool,uint256),(bool,uint128)))",
+    ))
+});
+
+// Type aliases for the `operate` arguments
+type AccountInfo = (Address, U256);
+type AssetAmount = (bool, u8, u8, U256);
+type ActionArgs = (u8, U256, AssetAmount, U256, U256, Address, U256, Vec<u8>);
+
+/// The actions which can be batched in an `operate`
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ActionType {
+    Deposit,
+    Withdraw,
+    Transfer,
+    Buy,
+    Sell,
+    Trade,
+    Liquidate,
+    Vaporize,
+    Call,
+}
+
+impl ActionType {
+    fn from_u8(action: u8) -> Option<Self> {
+        use ActionType::*;
+        [
+            Deposit, Withdraw, Transfer, Buy, Sell, Trade, Liquidate, Vaporize, Call,
+        ]
+        .get(action as usize)
+        .copied()
+    }
+}
+
+/// An `ActionArgs`, with its accounts resolved to their owners and its markets
+/// to their tokens
+#[derive(Debug, Clone)]
+struct Action {
+    kind: ActionType,
+    owner: Address,
+    amount: U256,
+    primary: Option<Address>,
+    secondary: Option<Address>,
+    other_address: Address,
+    other_owner: Option<Address>,
+}
+
+#[derive(Debug, Clone)]
+/// An inspector for dYdX's SoloMargin.
+///
+/// dYdX batches the actions on its accounts in an `operate`. A `Withdraw`
+/// which is deposited back in the same `operate` is a flash loan, with the
+/// `Call` between them handing control to the borrower. `Buy` and `Sell`
+/// exchange tokens through an exchange wrapper, and `Liquidate` takes over the
+/// collateral of an undercollateralized account for repaying its debt.
+///
+/// The loans and trades replace the transfers which made them, so this must run
+/// after `ERC20`, and before `FlashLoans` which would otherwise classify the
+/// loans without knowing which of the transfers were trades.
+pub struct DyDx {
+    solo: Address,
+    operate: [u8; 4],
+    markets: HashMap<U256, Address>,
+}
+
+impl Default for DyDx {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DyDx {
+    /// Constructor
+    pub fn new() -> Self {
+        let mut markets = HashMap::new();
+        markets.insert(0.into(), *WETH);
+        // SAI
+        markets.insert(
+            1.into(),
+            parse_address("89d24A6b4CcB1B6fAA2625fE562bDD9a23260359"),
+        );
+        // USDC
+        markets.insert(
+            2.into(),
+            parse_address("A0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48"),
+        );
+        // DAI
+        markets.insert(
+            3.into(),
+            parse_address("6B175474E89094C44Da98b954EedeAC495271d0F"),
+        );
+        Self {
+            solo: *DYDX,
+            operate: id(OPERATE),
+            markets,
+        }
+    }
+
+    /// Sets the token of the market `id`, e.g. for markets which were added
+    /// after this crate was released
+    pub fn with_market(mut self, id: u64, token: Address) -> Self {
+        self.markets.insert(id.into(), token);
+        self
+    }
+
+    fn decode(&self, call: &TraceCall) -> Option<Vec<Action>> {
+        if call.to != self.solo || call.input.as_ref().get(..4) != Some(&self.operate[..]) {
+            return None;
+        }
+        let params = [
+            ParamType::Array(Box::new(ParamType::Tuple(vec![
+                ParamType::Address,
+                ParamType::Uint(256),
+            ]))),
+            ParamType::Array(Box::new(ParamType::Tuple(vec![
+                ParamType::Uint(8),
+                ParamType::Uint(256),
+                ParamType::Tuple(vec![
+                    ParamType::Bool,
+                    ParamType::Uint(8),
+                    ParamType::Uint(8),
+                    ParamType::Uint(256),
+                ]),
+                ParamType::Uint(256),
+                ParamType::Uint(256),
+                ParamType::Address,
+                ParamType::Uint(256),
+                ParamType::Bytes,
+            ]))),
+        ];
+        let mut tokens = abi::decode(&params, &call.input.as_ref()[4..])
+            .ok()?
+            .into_iter();
+        let accounts = Vec::<AccountInfo>::from_token(tokens.next()?).ok()?;
+        let actions = Vec::<ActionArgs>::from_token(tokens.next()?).ok()?;
+
+        let owner = |id: U256| {
+            if id >= accounts.len().into() {
+                return None;
+            }
+            Some(accounts[id.as_usize()].0)
+        };
+        actions
+            .into_iter()
+            .map(
+                |(kind, account, amount, primary, secondary, other, other_account, _)| {
+                    Some(Action {
+                        kind: ActionType::from_u8(kind)?,
+                        owner: owner(account)?,
+                        amount: amount.3,
+                        primary: self.markets.get(&primary).cloned(),
+                        secondary: self.markets.get(&secondary).cloned(),
+                        other_address: other,
+                        other_owner: owner(other_account),
+                    })
+                },
+            )
+            .collect()
+    }
+
+    /// The `(sent, received)` amounts of a liquidation, from the `LogLiquidate`
+    /// of the liquidator and the liquidated account
+    fn liquidated(
+        &self,
+        logs: &[Log],
+        liquidator: Address,
+        liquidated: Address,
+    ) -> Option<(U256, U256)> {
+        let log = logs.iter().find(|log| {
+            log.address == self.solo
+                && log.topics.len() == 3
+                && log.topics[0] == *LOG_LIQUIDATE
+                && log.topics[1] == H256::from(liquidator)
+                && log.topics[2] == H256::from(liquidated)
+        })?;
+        // the words of the accounts and markets precede the balance updates,
+        // whose first two words are the sign and the value of the change
+        let word = |i: usize| {
+            log.data
+                .as_ref()
+                .get(i * 32..(i + 1) * 32)
+                .map(U256::from_big_endian)
+        };
+        let owed = word(9)?;
+        let held = word(5)?;
+        Some((owed, held))
+    }
+}
+
+impl Inspector for DyDx {
+    fn name(&self) -> &'static str {
+        "dydx"
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        let actions = &inspection.actions;
+        let mut classified = Vec::new();
+        for (i, action) in actions.iter().enumerate() {
+            let calltrace = match action.as_call() {
+                Some(inner) if inner.as_ref().call_type == CallType::Call => inner,
+                _ => continue,
+            };
+            let operations = match self.decode(calltrace.as_ref()) {
+                Some(inner) => inner,
+                None => continue,
+            };
+
+            // the transfers made by the operation, which get consumed by the
+            // loans and trades they are part of
+            let mut transfers = actions
+                .iter()
+                .enumerate()
+                .skip(i + 1)
+                .filter(|(_, a)| is_nested(&calltrace.trace_address, a.trace_address()))
+                .filter_map(|(j, a)| Some((j, a.as_action()?.transfer()?.clone())))
+                .collect::<Vec<_>>();
+            let mut take = |check: &dyn Fn(&Transfer) -> bool, last: bool| {
+                let position = if last {
+                    transfers.iter().rposition(|(_, t)| check(t))
+                } else {
+                    transfers.iter().position(|(_, t)| check(t))
+                }?;
+                Some(transfers.remove(position))
+            };
+
+            let mut found = false;
+            let mut liquidation = None;
+            let mut repaid = vec![false; operations.len()];
+            for (k, operation) in operations.iter().enumerate() {
+                match operation.kind {
+                    ActionType::Withdraw => {
+                        // a loan is deposited back by the same account
+                        let deposit = operations.iter().enumerate().skip(k + 1).find(|(d, o)| {
+                            !repaid[*d]
+                                && o.kind == ActionType::Deposit
+                                && o.owner == operation.owner
+                                && o.primary == operation.primary
+                        });
+                        let (d, deposit) = match (deposit, operation.primary) {
+                            (Some(inner), Some(_)) => inner,
+                            _ => continue,
+                        };
+                        let token = operation.primary;
+                        let borrow = take(
+                            &|t| {
+                                Some(t.token) == token
+                                    && t.from == self.solo
+                                    && t.to == operation.other_address
+                            },
+                            false,
+                        );
+                        let repay = take(
+                            &|t| {
+                                Some(t.token) == token
+                                    && t.from == deposit.other_address
+                                    && t.to == self.solo
+                            },
+                            true,
+                        );
+                        if let (Some((j, borrow)), Some((r, repay))) = (borrow, repay) {
+                            let flash_loan = Classification::new(
+                                FlashLoan {
+                                    provider: Protocol::DyDx,
+                                    token: borrow.token,
+                                    amount: borrow.amount,
+                                    fee: repay.amount.saturating_sub(borrow.amount),
+                                },
+                                actions[j].trace_address(),
+                            );
+                            classified.push((j, flash_loan));
+                            classified.push((r, Classification::Prune));
+                            repaid[d] = true;
+                            found = true;
+                        }
+                    }
+                    ActionType::Buy | ActionType::Sell => {
+                        // buys pay with the secondary market, sells with the
+                        // primary one
+                        let (sold, bought) = match operation.kind {
+                            ActionType::Buy => (operation.secondary, operation.primary),
+                            _ => (operation.primary, operation.secondary),
+                        };
+                        let wrapper = operation.other_address;
+                        let paid = take(
+                            &|t| Some(t.token) == sold && t.from == self.solo && t.to == wrapper,
+                            false,
+                        );
+                        let (j, paid) = match paid {
+                            Some(inner) => inner,
+                            None => continue,
+                        };
+                        let got = take(
+                            &|t| Some(t.token) == bought && t.from == wrapper && t.to == self.solo,
+                            false,
+                        );
+                        let (r, got) = match got {
+                            Some(inner) => inner,
+                            None => continue,
+                        };
+                        let trade = Classification::new(
+                            Trade {
+                                t1: Transfer {
+                                    from: operation.owner,
+                                    to: wrapper,
+                                    amount: paid.amount,
+                                    token: paid.token,
+                                },
+                                t2: Transfer {
+                                    from: wrapper,
+                                    to: operation.owner,
+                                    amount: got.amount,
+                                    token: got.token,
+                                },
+                            },
+                            actions[j].trace_address(),
+                        );
+                        classified.push((j, trade));
+                        classified.push((r, Classification::Prune));
+                        found = true;
+                    }
+                    // there is a single slot for the liquidations, which
+                    // replace the `operate` since they make no transfers
+                    ActionType::Liquidate if liquidation.is_none() => {
+                        let (owed, held, liquidated) = match (
+                            operation.primary,
+                            operation.secondary,
+                            operation.other_owner,
+                        ) {
+                            (Some(owed), Some(held), Some(liquidated)) => (owed, held, liquidated),
+                            _ => continue,
+                        };
+                        let (sent, received) = self
+                            .liquidated(&inspection.logs, operation.owner, liquidated)
+                            .unwrap_or((operation.amount, U256::zero()));
+                        liquidation = Some(Liquidation {
+                            sent_token: owed,
+                            sent_amount: sent,
+                            received_token: held,
+                            received_amount: received,
+                            from: operation.owner,
+                            liquidated_user: liquidated,
+                            auction: None,
+                        });
+                        found = true;
+                    }
+                    _ => (),
+                }
+            }
+
+            if found {
+                inspection.protocols.insert(Protocol::DyDx);
+                let operate = match liquidation {
+                    Some(liquidation) => {
+                        Classification::new(liquidation, calltrace.trace_address.clone())
+                    }
+                    None => Classification::Prune,
+                };
+                classified.push((i, operate));
+            }
+        }
+
+        for (i, action) in classified {
+            inspection.actions[i] = action;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{inspectors::ERC20, test_helpers::*};
+    use ethers::{abi::Token, types::Bytes};
+
+    const DEPOSIT: u8 = 0;
+    const WITHDRAW: u8 = 1;
+    const SELL: u8 = 4;
+    const LIQUIDATE: u8 = 6;
+    const CALL: u8 = 8;
+
+    /// `(kind, account, amount, primary market, secondary market, other
+    /// address, other account)`
+    type Args = (u8, u64, u64, u64, u64, Address, u64);
+
+    fn operate(accounts: &[Address], actions: &[Args]) -> Bytes {
+        let accounts = accounts
+            .iter()
+            .map(|owner| Token::Tuple(vec![Token::Address(*owner), Token::Uint(0.into())]))
+            .collect();
+        let actions = actions
+            .iter()
+            .map(
+                |(kind, account, amount, primary, secondary, other, other_account)| {
+                    Token::Tuple(vec![
+                        Token::Uint((*kind).into()),
+                        Token::Uint((*account).into()),
+                        Token::Tuple(vec![
+                            Token::Bool(false),
+                            Token::Uint(0.into()),
+                            Token::Uint(0.into()),
+                            Token::Uint((*amount).into()),
+                        ]),
+                        Token::Uint((*primary).into()),
+                        Token::Uint((*secondary).into()),
+                        Token::Address(*other),
+                        Token::Uint((*other_account).into()),
+                        Token::Bytes(vec![]),
+                    ])
+                },
+            )
+            .collect();
+        calldata(OPERATE, &[Token::Array(accounts), Token::Array(actions)])
+    }
+
+    fn inspect(inspection: &mut Inspection, dydx: DyDx) {
+        ERC20::new().inspect(inspection);
+        dydx.inspect(inspection);
+        inspection.prune();
+    }
+
+    #[test]
+    fn flash_loan_and_sell() {
+        let addrs = addrs();
+        let (bot, wrapper, usdc) = (addrs[0], addrs[1], addrs[2]);
+        let solo = *DYDX;
+
+        // borrow 1000 WETH, sell 500 of them for USDC and repay the loan
+        let input = operate(
+            &[bot],
+            &[
+                (WITHDRAW, 0, 1000, 0, 0, bot, 0),
+                (SELL, 0, 500, 0, 2, wrapper, 0),
+                (CALL, 0, 0, 0, 0, bot, 0),
+                (DEPOSIT, 0, 1002, 0, 0, bot, 0),
+            ],
+        );
+        let mut inspection = mk_inspection(vec![
+            mk_call(bot, solo, input, vec![]),
+            mk_call(solo, *WETH, transfer(bot, 1000.into()), vec![0]),
+            mk_call(solo, *WETH, transfer(wrapper, 500.into()), vec![1]),
+            // exchange
+            mk_call(solo, wrapper, Bytes::default(), vec![2]),
+            mk_call(
+                solo,
+                usdc,
+                transfer_from(wrapper, solo, 1500.into()),
+                vec![3],
+            ),
+            // callFunction
+            mk_call(solo, bot, Bytes::default(), vec![4]),
+            mk_call(solo, *WETH, transfer_from(bot, solo, 1002.into()), vec![5]),
+        ]);
+        inspect(&mut inspection, DyDx::new().with_market(2, usdc));
+
+        let known = inspection.known();
+        assert_eq!(known.len(), 2);
+        assert_eq!(
+            known[0].as_ref().flash_loan().unwrap(),
+            &FlashLoan {
+                provider: Protocol::DyDx,
+                token: *WETH,
+                amount: 1000.into(),
+                fee: 2.into(),
+            }
+        );
+        let trade = known[1].as_ref().trade().unwrap();
+        assert_eq!(
+            (trade.t1.from, trade.t1.token, trade.t1.amount),
+            (bot, *WETH, 500.into())
+        );
+        assert_eq!(
+            (trade.t2.to, trade.t2.token, trade.t2.amount),
+            (bot, usdc, 1500.into())
+        );
+        // the callbacks are left for the other inspectors
+        assert_eq!(inspection.unknown().len(), 2);
+        assert_eq!(inspection.protocols, crate::set![Protocol::DyDx]);
+    }
+
+    #[test]
+    fn withdrawals_are_not_loans() {
+        let addrs = addrs();
+        let bot = addrs[0];
+        let solo = *DYDX;
+
+        let input = operate(&[bot], &[(WITHDRAW, 0, 1000, 0, 0, bot, 0)]);
+        let mut inspection = mk_inspection(vec![
+            mk_call(bot, solo, input, vec![]),
+            mk_call(solo, *WETH, transfer(bot, 1000.into()), vec![0]),
+        ]);
+        inspect(&mut inspection, DyDx::new());
+
+        assert!(inspection.known()[0].as_ref().transfer().is_some());
+        assert_eq!(inspection.unknown().len(), 1);
+        assert!(inspection.protocols.is_empty());
+    }
+
+    #[test]
+    fn liquidation() {
+        let addrs = addrs();
+        let (liquidator, user) = (addrs[0], addrs[1]);
+        let dai = parse_address("6B175474E89094C44Da98b954EedeAC495271d0F");
+        let solo = *DYDX;
+
+        // repay the user's DAI debt for their WETH
+        let input = operate(
+            &[liquidator, user],
+            &[(LIQUIDATE, 0, 3000, 3, 0, Address::zero(), 1)],
+        );
+        let mut inspection = mk_inspection(vec![mk_call(liquidator, solo, input, vec![])]);
+        let mut without_logs = inspection.clone();
+
+        let mut data = vec![U256::zero(); 20];
+        data[5] = 1.into();
+        data[9] = 2900.into();
+        inspection.logs.push(mk_log(
+            solo,
+            vec![*LOG_LIQUIDATE, H256::from(liquidator), H256::from(user)],
+            abi::encode(&data.into_iter().map(Token::Uint).collect::<Vec<_>>()),
+        ));
+        inspect(&mut inspection, DyDx::new());
+
+        let liquidation = inspection.liquidations().next().cloned().unwrap();
+        assert_eq!(
+            liquidation,
+            Liquidation {
+                sent_token: dai,
+                sent_amount: 2900.into(),
+                received_token: *WETH,
+                received_amount: 1.into(),
+                from: liquidator,
+                liquidated_user: user,
+                auction: None,
+            }
+        );
+
+        // the amount repaid defaults to the requested one
+        inspect(&mut without_logs, DyDx::new());
+        let liquidation = without_logs.liquidations().next().cloned().unwrap();
+        assert_eq!(liquidation.sent_amount, 3000.into());
+        assert!(liquidation.received_amount.is_zero());
+    }
+
+    #[test]
+    // http://etherscan.io/tx/0x0e0e7c690589d9b94c3fbc4bae8abb4c5cac5c965abbb5bf1533e9f546b10b92
+    fn flash_loan_funding_liquidation() {
+        let mut inspection = read_trace("dydx_loan.json");
+        inspect(&mut inspection, DyDx::new());
+
+        let loan = inspection
+            .known()
+            .iter()
+            .find_map(|a| a.as_ref().flash_loan().cloned())
+            .unwrap();
+        assert_eq!(loan.provider, Protocol::DyDx);
+        assert_eq!(
+            loan.token,
+            parse_address("6B175474E89094C44Da98b954EedeAC495271d0F")
+        );
+        assert_eq!(loan.fee, 2.into());
+    }
+}
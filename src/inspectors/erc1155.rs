@@ -0,0 +1,180 @@
+use crate::{
+    types::{actions::NftTransfer, Classification, Inspection},
+    Inspector,
+};
+use ethers::{
+    abi::parse_abi,
+    contract::BaseContract,
+    types::{Address, Bytes, Call as TraceCall, CallType, U256},
+};
+use once_cell::sync::Lazy;
+
+static ERC1155_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    parse_abi(&[
+        "function safeTransferFrom(address, address, uint256, uint256, bytes)",
+        "function safeBatchTransferFrom(address, address, uint256[], uint256[], bytes)",
+    ])
+    .expect("could not parse erc1155 abi")
+    .into()
+});
+
+type SafeTransferFrom = (Address, Address, U256, U256, Bytes);
+type SafeBatchTransferFrom = (Address, Address, Vec<U256>, Vec<U256>, Bytes);
+
+#[derive(Debug, Clone)]
+/// Decodes ERC1155 transfers. A batch transfer is expanded into one transfer
+/// per token id, which all share the batch's trace address.
+///
+/// Neither of ERC1155's functions shares its selector with ERC20 or ERC721,
+/// so they need no logs to be told apart.
+pub struct ERC1155(&'static BaseContract);
+
+impl Default for ERC1155 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ERC1155 {
+    pub fn new() -> Self {
+        Self(&ERC1155_ABI)
+    }
+
+    /// Parse a Call trace to discover the ERC1155 transfers it made
+    pub fn try_parse(&self, call: &TraceCall) -> Option<Vec<NftTransfer>> {
+        if call.call_type != CallType::Call {
+            return None;
+        }
+
+        let transfer = |(from, to, token_id, amount)| NftTransfer {
+            collection: call.to,
+            token_id,
+            amount,
+            from,
+            to,
+        };
+        if let Ok((from, to, id, amount, _)) = self
+            .0
+            .decode::<SafeTransferFrom, _>("safeTransferFrom", &call.input)
+        {
+            Some(vec![transfer((from, to, id, amount))])
+        } else if let Ok((from, to, ids, amounts, _)) = self
+            .0
+            .decode::<SafeBatchTransferFrom, _>("safeBatchTransferFrom", &call.input)
+        {
+            // the batch reverts unless there is an amount for each id
+            if ids.len() != amounts.len() {
+                return None;
+            }
+            let transfers = ids
+                .into_iter()
+                .zip(amounts)
+                .map(|(id, amount)| transfer((from, to, id, amount)))
+                .collect();
+            Some(transfers)
+        } else {
+            None
+        }
+    }
+}
+
+impl Inspector for ERC1155 {
+    fn name(&self) -> &'static str {
+        "erc1155"
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        let mut actions = Vec::with_capacity(inspection.actions.len());
+        for classification in inspection.actions.drain(..) {
+            let transfers = classification
+                .as_call()
+                .and_then(|calltrace| self.try_parse(calltrace.as_ref()));
+            match transfers {
+                Some(transfers) => {
+                    let trace_address = classification.trace_address();
+                    actions.extend(
+                        transfers
+                            .into_iter()
+                            .map(|transfer| Classification::new(transfer, trace_address)),
+                    );
+                }
+                None => actions.push(classification),
+            }
+        }
+        inspection.actions = actions;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{inspectors::ERC20, test_helpers::*};
+    use ethers::abi::Token;
+
+    fn uints(values: &[u64]) -> Token {
+        Token::Array(values.iter().map(|v| Token::Uint((*v).into())).collect())
+    }
+
+    #[test]
+    fn single_and_batch_transfers() {
+        let addrs = addrs();
+        let (alice, bob, items, dai) = (addrs[0], addrs[1], addrs[2], addrs[3]);
+
+        let single = calldata(
+            "safeTransferFrom(address,address,uint256,uint256,bytes)",
+            &[
+                Token::Address(alice),
+                Token::Address(bob),
+                Token::Uint(1.into()),
+                Token::Uint(10.into()),
+                Token::Bytes(vec![]),
+            ],
+        );
+        let batch = calldata(
+            "safeBatchTransferFrom(address,address,uint256[],uint256[],bytes)",
+            &[
+                Token::Address(bob),
+                Token::Address(alice),
+                uints(&[2, 3]),
+                uints(&[20, 30]),
+                Token::Bytes(vec![]),
+            ],
+        );
+        let mut inspection = mk_inspection(vec![
+            mk_call(alice, items, single, vec![0]),
+            mk_call(bob, items, batch, vec![1]),
+            mk_call(alice, dai, transfer(bob, 5.into()), vec![2]),
+        ]);
+        ERC1155::new().inspect(&mut inspection);
+        ERC20::new().inspect(&mut inspection);
+
+        let nft = |id: u64, amount: u64, from, to| NftTransfer {
+            collection: items,
+            token_id: id.into(),
+            amount: amount.into(),
+            from,
+            to,
+        };
+        let known = inspection.known();
+        let transfers = known
+            .iter()
+            .filter_map(|action| action.as_ref().nft_transfer().cloned())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            transfers,
+            vec![
+                nft(1, 10, alice, bob),
+                nft(2, 20, bob, alice),
+                nft(3, 30, bob, alice)
+            ]
+        );
+        // the batch's transfers share its trace address
+        let addresses = inspection
+            .actions
+            .iter()
+            .map(|action| action.trace_address())
+            .collect::<Vec<_>>();
+        assert_eq!(addresses, vec![vec![0], vec![1], vec![1], vec![2]]);
+        assert!(known[3].as_ref().transfer().is_some());
+    }
+}
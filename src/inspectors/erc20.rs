@@ -1,7 +1,8 @@
 use crate::{
-    addresses::{ETH, WETH},
+    addresses::{ChainConfig, NATIVE_ETH, WETH},
+    inspectors::logs::credit_logged_amounts,
     types::{
-        actions::{Deposit, SpecificAction, Transfer, Withdrawal},
+        actions::{Approval, Deposit, SpecificAction, Transfer, Withdrawal},
         Classification, Inspection,
     },
     Inspector,
@@ -11,12 +12,41 @@ use ethers::{
     contract::BaseContract,
     types::{Address, Call as TraceCall, CallType, U256},
 };
+use once_cell::sync::Lazy;
+
+static ERC20_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    parse_abi(&[
+        "function transferFrom(address, address, uint256)",
+        "function transfer(address, uint256)",
+        "function deposit()",
+        "function withdraw(uint256)",
+        "function mint(address, uint256)",
+        "function burnFrom(address, uint256)",
+        "function approve(address, uint256)",
+        "function increaseAllowance(address, uint256)",
+    ])
+    .expect("could not parse erc20 abi")
+    .into()
+});
 
 #[derive(Debug, Clone)]
-/// Decodes ERC20 calls
-pub struct ERC20(BaseContract);
+/// Decodes ERC20 calls. When the logs of the transaction are known, the
+/// transfers are credited with the amounts of their `Transfer` logs, which
+/// differ for fee-on-transfer tokens.
+pub struct ERC20 {
+    abi: &'static BaseContract,
+    /// The chain's wrapped native token, whose withdrawals send the native
+    /// token along with their own transfer
+    weth: Address,
+    /// The placeholder which native transfers are classified with
+    native: Address,
+}
 
 impl Inspector for ERC20 {
+    fn name(&self) -> &'static str {
+        "erc20"
+    }
+
     fn inspect(&self, inspection: &mut Inspection) {
         inspection.actions.iter_mut().for_each(|classification| {
             if let Some(calltrace) = classification.as_call() {
@@ -24,23 +54,27 @@ impl Inspector for ERC20 {
                     *classification = Classification::new(transfer, calltrace.trace_address.clone())
                 }
             }
-        })
+        });
+        credit_logged_amounts(&mut inspection.actions, &inspection.logs);
     }
 }
 
 impl ERC20 {
     pub fn new() -> Self {
-        Self(BaseContract::from(
-            parse_abi(&[
-                "function transferFrom(address, address, uint256)",
-                "function transfer(address, uint256)",
-                "function deposit()",
-                "function withdraw(uint256)",
-                "function mint(address, uint256)",
-                "function burnFrom(address, uint256)",
-            ])
-            .expect("could not parse erc20 abi"),
-        ))
+        Self {
+            abi: &ERC20_ABI,
+            weth: *WETH,
+            native: NATIVE_ETH,
+        }
+    }
+
+    /// Instantiates an inspector for `chain`
+    pub fn with_chain(chain: &ChainConfig) -> Self {
+        Self {
+            abi: &ERC20_ABI,
+            weth: chain.weth,
+            native: chain.native,
+        }
     }
 
     /// Parse a Call trace to discover a token action
@@ -56,7 +90,7 @@ impl ERC20 {
 
         let token = trace_call.to;
         if let Ok((from, to, amount)) = self
-            .0
+            .abi
             .decode::<(Address, Address, U256), _>("transferFrom", &trace_call.input)
         {
             Some(SpecificAction::Transfer(Transfer {
@@ -66,7 +100,7 @@ impl ERC20 {
                 token,
             }))
         } else if let Ok((from, amount)) = self
-            .0
+            .abi
             .decode::<(Address, U256), _>("burnFrom", &trace_call.input)
         {
             Some(SpecificAction::Transfer(Transfer {
@@ -77,7 +111,7 @@ impl ERC20 {
                 token,
             }))
         } else if let Ok((to, amount)) = self
-            .0
+            .abi
             .decode::<(Address, U256), _>("mint", &trace_call.input)
         {
             Some(SpecificAction::Transfer(Transfer {
@@ -88,7 +122,7 @@ impl ERC20 {
                 token,
             }))
         } else if let Ok((to, amount)) = self
-            .0
+            .abi
             .decode::<(Address, U256), _>("transfer", &trace_call.input)
         {
             Some(SpecificAction::Transfer(Transfer {
@@ -97,7 +131,21 @@ impl ERC20 {
                 amount,
                 token,
             }))
-        } else if let Ok(amount) = self.0.decode::<U256, _>("withdraw", &trace_call.input) {
+        } else if let Ok((spender, amount)) = self
+            .abi
+            .decode::<(Address, U256), _>("approve", &trace_call.input)
+            .or_else(|_| {
+                self.abi
+                    .decode::<(Address, U256), _>("increaseAllowance", &trace_call.input)
+            })
+        {
+            Some(SpecificAction::Approval(Approval {
+                owner: trace_call.from,
+                spender,
+                amount,
+                token,
+            }))
+        } else if let Ok(amount) = self.abi.decode::<U256, _>("withdraw", &trace_call.input) {
             Some(SpecificAction::WethWithdrawal(Withdrawal {
                 to: trace_call.from,
                 amount,
@@ -111,13 +159,13 @@ impl ERC20 {
                 from: trace_call.from,
                 amount: trace_call.value,
             }))
-        } else if trace_call.value > 0.into() && trace_call.from != *WETH {
+        } else if trace_call.value > 0.into() && trace_call.from != self.weth {
             // ETH transfer
             Some(SpecificAction::Transfer(Transfer {
                 from: trace_call.from,
                 to: trace_call.to,
                 amount: trace_call.value,
-                token: *ETH,
+                token: self.native,
             }))
         } else {
             None
@@ -0,0 +1,233 @@
+use crate::{
+    types::{actions::NftTransfer, Classification, Inspection},
+    Inspector,
+};
+use ethers::{
+    abi::{self, parse_abi, Detokenize, ParamType},
+    contract::BaseContract,
+    types::{Address, Call as TraceCall, CallType, Log, H256, U256},
+    utils::keccak256,
+};
+use once_cell::sync::Lazy;
+
+static ERC721_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    parse_abi(&["function transferFrom(address, address, uint256)"])
+        .expect("could not parse erc721 abi")
+        .into()
+});
+
+type TransferFrom = (Address, Address, U256);
+
+#[derive(Debug, Clone)]
+/// Decodes ERC721 transfers.
+///
+/// ERC721's `transferFrom` has the same selector as ERC20's, so it is told
+/// apart by the `Transfer` log it emitted: ERC721 indexes the token id, which
+/// makes it a 4-topic log, while ERC20 puts the amount in the log's data. This
+/// must run before `ERC20`, which would otherwise decode the call as a transfer
+/// of `tokenId` tokens.
+pub struct ERC721 {
+    erc721: &'static BaseContract,
+    /// The selectors of both `safeTransferFrom` overloads
+    safe_transfer_from: [[u8; 4]; 2],
+    transfer_topic: H256,
+}
+
+impl Default for ERC721 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ERC721 {
+    pub fn new() -> Self {
+        Self {
+            erc721: &ERC721_ABI,
+            safe_transfer_from: [
+                ethers::utils::id("safeTransferFrom(address,address,uint256)"),
+                ethers::utils::id("safeTransferFrom(address,address,uint256,bytes)"),
+            ],
+            transfer_topic: H256::from(keccak256("Transfer(address,address,uint256)")),
+        }
+    }
+
+    /// Parse a Call trace to discover an ERC721 transfer, using the tx's logs
+    /// to disambiguate `transferFrom`
+    pub fn try_parse(&self, call: &TraceCall, logs: &[Log]) -> Option<NftTransfer> {
+        if call.call_type != CallType::Call || call.input.as_ref().len() < 4 {
+            return None;
+        }
+
+        let (selector, args) = call.input.as_ref().split_at(4);
+        let (from, to, token_id) = if self.safe_transfer_from.iter().any(|s| s == selector) {
+            // both overloads start with the same static arguments, which get
+            // decoded by selector since decoding by name only picks one of them
+            let params = [ParamType::Address, ParamType::Address, ParamType::Uint(256)];
+            let tokens = abi::decode(&params, args).ok()?;
+            TransferFrom::from_tokens(tokens).ok()?
+        } else {
+            let transfer = self
+                .erc721
+                .decode::<TransferFrom, _>("transferFrom", &call.input)
+                .ok()?;
+            if !self.emitted_nft_transfer(call.to, transfer, logs) {
+                return None;
+            }
+            transfer
+        };
+
+        Some(NftTransfer {
+            collection: call.to,
+            token_id,
+            amount: 1.into(),
+            from,
+            to,
+        })
+    }
+
+    /// Whether `collection` emitted a `Transfer` with the token id indexed
+    fn emitted_nft_transfer(
+        &self,
+        collection: Address,
+        (from, to, token_id): TransferFrom,
+        logs: &[Log],
+    ) -> bool {
+        let mut id = [0; 32];
+        token_id.to_big_endian(&mut id);
+        let topics = [
+            self.transfer_topic,
+            H256::from(from),
+            H256::from(to),
+            H256::from(id),
+        ];
+        logs.iter()
+            .any(|log| log.address == collection && log.topics == topics)
+    }
+}
+
+impl Inspector for ERC721 {
+    fn name(&self) -> &'static str {
+        "erc721"
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        let logs = &inspection.logs;
+        inspection.actions.iter_mut().for_each(|classification| {
+            if let Some(calltrace) = classification.as_call() {
+                if let Some(transfer) = self.try_parse(calltrace.as_ref(), logs) {
+                    *classification = Classification::new(transfer, calltrace.trace_address.clone())
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{inspectors::ERC20, test_helpers::*};
+    use ethers::abi::Token;
+
+    fn inspect(inspection: &mut Inspection) {
+        ERC721::new().inspect(inspection);
+        ERC20::new().inspect(inspection);
+    }
+
+    #[test]
+    fn transfer_from_with_indexed_token_id() {
+        let addrs = addrs();
+        let (alice, bob, punks) = (addrs[0], addrs[1], addrs[2]);
+
+        let mut inspection = mk_inspection(vec![mk_call(
+            alice,
+            punks,
+            transfer_from(alice, bob, 42.into()),
+            vec![],
+        )]);
+        inspection.logs = vec![erc721_transfer_log(punks, alice, bob, 42.into())];
+        inspect(&mut inspection);
+
+        let known = inspection.known();
+        assert_eq!(known.len(), 1);
+        assert_eq!(
+            known[0].as_ref().nft_transfer(),
+            Some(&NftTransfer {
+                collection: punks,
+                token_id: 42.into(),
+                amount: 1.into(),
+                from: alice,
+                to: bob,
+            })
+        );
+    }
+
+    #[test]
+    fn transfer_from_with_amount_in_data() {
+        let addrs = addrs();
+        let (alice, bob, dai) = (addrs[0], addrs[1], addrs[2]);
+        let transfer = ERC721::new();
+
+        let mut inspection = mk_inspection(vec![mk_call(
+            alice,
+            dai,
+            transfer_from(alice, bob, 42.into()),
+            vec![],
+        )]);
+        let mut amount = vec![0; 32];
+        U256::from(42).to_big_endian(&mut amount);
+        inspection.logs = vec![mk_log(
+            dai,
+            vec![transfer.transfer_topic, H256::from(alice), H256::from(bob)],
+            amount,
+        )];
+        inspect(&mut inspection);
+
+        let known = inspection.known();
+        assert_eq!(known.len(), 1);
+        let transfer = known[0].as_ref().transfer().unwrap();
+        assert_eq!((transfer.token, transfer.amount), (dai, 42.into()));
+    }
+
+    #[test]
+    fn safe_transfer_from() {
+        let addrs = addrs();
+        let (alice, bob, punks) = (addrs[0], addrs[1], addrs[2]);
+        let args = [
+            Token::Address(alice),
+            Token::Address(bob),
+            Token::Uint(7.into()),
+        ];
+        let mut with_data = args.to_vec();
+        with_data.push(Token::Bytes(vec![1, 2, 3]));
+
+        // no logs are needed, since ERC20 has no `safeTransferFrom`
+        let mut inspection = mk_inspection(vec![
+            mk_call(
+                alice,
+                punks,
+                calldata("safeTransferFrom(address,address,uint256)", &args),
+                vec![0],
+            ),
+            mk_call(
+                alice,
+                punks,
+                calldata(
+                    "safeTransferFrom(address,address,uint256,bytes)",
+                    &with_data,
+                ),
+                vec![1],
+            ),
+        ]);
+        inspect(&mut inspection);
+
+        let known = inspection.known();
+        assert_eq!(known.len(), 2);
+        for action in known {
+            let transfer = action.as_ref().nft_transfer().unwrap();
+            assert_eq!(
+                (transfer.token_id, transfer.from, transfer.to),
+                (7.into(), alice, bob)
+            );
+        }
+    }
+}
@@ -0,0 +1,678 @@
+use crate::{
+    addresses::{
+        AAVE_LENDING_POOL, AAVE_LENDING_POOL_CORE, AAVE_V2_LENDING_POOL, BALANCER_VAULT, DSS_FLASH,
+        DYDX,
+    },
+    inspectors::{find_matching, uniswap::uniswappy},
+    is_nested,
+    types::{actions::FlashLoan, Classification, Inspection, Protocol},
+    Inspector,
+};
+use ethers::{
+    abi::parse_abi,
+    contract::BaseContract,
+    types::{Address, Bytes, Call as TraceCall, U256},
+    utils::id,
+};
+use itertools::Itertools;
+use once_cell::sync::Lazy;
+
+static AAVE_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    parse_abi(&[
+        "function flashLoan(address receiver, address reserve, uint256 amount, bytes params) external",
+    ])
+    .expect("could not parse aave flash loan abi")
+    .into()
+});
+
+static AAVE_V2_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    parse_abi(&[
+        "function flashLoan(address receiver, address[] assets, uint256[] amounts, uint256[] modes, address onBehalfOf, bytes params, uint16 referralCode) external",
+    ])
+    .expect("could not parse aave v2 flash loan abi")
+    .into()
+});
+
+static BALANCER_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    parse_abi(&[
+        "function flashLoan(address recipient, address[] tokens, uint256[] amounts, bytes userData) external",
+    ])
+    .expect("could not parse balancer flash loan abi")
+    .into()
+});
+
+static UNISWAP_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    parse_abi(&[
+        "function swap(uint256 amount0Out, uint256 amount1Out, address to, bytes data) external",
+        "function flash(address recipient, uint256 amount0, uint256 amount1, bytes data) external",
+    ])
+    .expect("could not parse uniswap flash loan abi")
+    .into()
+});
+
+type AaveFlashLoan = (Address, Address, U256, Bytes);
+type AaveV2FlashLoan = (
+    Address,
+    Vec<Address>,
+    Vec<U256>,
+    Vec<U256>,
+    Address,
+    Bytes,
+    u16,
+);
+type BalancerFlashLoan = (Address, Vec<Address>, Vec<U256>, Bytes);
+type PairSwap = (U256, U256, Address, Bytes);
+type PoolFlash = (Address, U256, U256, Bytes);
+
+#[derive(Clone, Debug)]
+/// An inspector for the flash loans of Aave, dYdX, Balancer, Maker and Uniswap,
+/// i.e. V2's flashswaps and V3's `flash`.
+///
+/// The lenders transfer the tokens out, call back into the borrower and then
+/// expect the tokens back (plus a fee) before the call returns, so a loan is
+/// made up of a transfer out of the lender and a later transfer back to it, both
+/// nested under the call which started the loan. The transfer out is replaced
+/// with the `FlashLoan`, which therefore precedes all the actions it funded.
+/// Maker is the exception, since it mints the DAI it lends and pulls it back
+/// before burning it.
+pub struct FlashLoans {
+    aave: &'static BaseContract,
+    aave_v2: &'static BaseContract,
+    balancer: &'static BaseContract,
+    uniswap: &'static BaseContract,
+    /// The selector of dYdX's `SoloMargin.operate`
+    operate: [u8; 4],
+}
+
+/// What the calldata of a flash loan tells us about its transfers
+struct LoanCall {
+    provider: Protocol,
+    /// The recipient of the borrowed tokens
+    receiver: Option<Address>,
+    /// The account which lends the tokens
+    lender: Option<Address>,
+    /// The account which the tokens are repaid to, if it is not the lender
+    repaid_to: Option<Address>,
+    /// The borrowed tokens
+    tokens: Option<Vec<Address>>,
+    /// The borrowed amounts
+    amounts: Option<Vec<U256>>,
+}
+
+impl Default for FlashLoans {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FlashLoans {
+    pub fn new() -> Self {
+        Self {
+            aave: &AAVE_ABI,
+            aave_v2: &AAVE_V2_ABI,
+            balancer: &BALANCER_ABI,
+            uniswap: &UNISWAP_ABI,
+            operate: id("operate((address,uint256)[],(uint8,uint256,(bool,uint8,uint8,uint256),uint256,uint256,address,uint256,bytes)[])"),
+        }
+    }
+
+    fn decode(&self, call: &TraceCall) -> Option<LoanCall> {
+        if call.to == *AAVE_LENDING_POOL {
+            // https://github.com/aave/aave-protocol/blob/master/contracts/lendingpool/LendingPool.sol#L843
+            let (receiver, reserve, _, _) = self
+                .aave
+                .decode::<AaveFlashLoan, _>("flashLoan", &call.input)
+                .ok()?;
+            Some(LoanCall {
+                provider: Protocol::Aave,
+                receiver: Some(receiver),
+                lender: Some(*AAVE_LENDING_POOL_CORE),
+                repaid_to: None,
+                tokens: Some(vec![reserve]),
+                amounts: None,
+            })
+        } else if call.to == *AAVE_V2_LENDING_POOL {
+            // The funds are sent from each asset's aToken. Assets with a
+            // non-zero `mode` are borrowed instead of repaid, so they will not
+            // have a repayment.
+            let (receiver, assets, _, _, _, _, _) = self
+                .aave_v2
+                .decode::<AaveV2FlashLoan, _>("flashLoan", &call.input)
+                .ok()?;
+            Some(LoanCall {
+                provider: Protocol::Aave,
+                receiver: Some(receiver),
+                lender: None,
+                repaid_to: None,
+                tokens: Some(assets),
+                amounts: None,
+            })
+        } else if call.to == *BALANCER_VAULT {
+            // The vault's loans are free unless governance sets a fee, which
+            // is repaid along with them
+            let (recipient, tokens, amounts, _) = self
+                .balancer
+                .decode::<BalancerFlashLoan, _>("flashLoan", &call.input)
+                .ok()?;
+            Some(LoanCall {
+                provider: Protocol::Balancer,
+                receiver: Some(recipient),
+                lender: Some(call.to),
+                repaid_to: None,
+                tokens: Some(tokens),
+                amounts: Some(amounts),
+            })
+        } else if DSS_FLASH.contains(&call.to) {
+            // ERC-3156's `flashLoan` has the same arguments as Aave's. The DAI
+            // is minted to the receiver, and pulled back by the flash minter.
+            let (receiver, token, amount, _) = self
+                .aave
+                .decode::<AaveFlashLoan, _>("flashLoan", &call.input)
+                .ok()?;
+            Some(LoanCall {
+                provider: Protocol::MakerDAO,
+                receiver: Some(receiver),
+                lender: Some(Address::zero()),
+                repaid_to: Some(call.to),
+                tokens: Some(vec![token]),
+                amounts: Some(vec![amount]),
+            })
+        } else if call.to == *DYDX && call.input.as_ref().get(..4) == Some(&self.operate[..]) {
+            // dYdX has no flash loan function. Instead, a `Withdraw`, a `Call`
+            // and a `Deposit` of the same market are batched in an `operate`.
+            Some(LoanCall {
+                provider: Protocol::DyDx,
+                receiver: None,
+                lender: Some(*DYDX),
+                repaid_to: None,
+                tokens: None,
+                amounts: None,
+            })
+        } else if let Ok((_, _, to, data)) = self.uniswap.decode::<PairSwap, _>("swap", &call.input)
+        {
+            // A flashswap which is repaid in the same token. Repaying in the
+            // other token is a trade, which the Uniswap inspector classifies.
+            if data.as_ref().is_empty() {
+                return None;
+            }
+            Some(LoanCall {
+                provider: uniswappy(call),
+                receiver: Some(to),
+                lender: Some(call.to),
+                repaid_to: None,
+                tokens: None,
+                amounts: None,
+            })
+        } else if let Ok((recipient, amount0, amount1, _)) =
+            self.uniswap.decode::<PoolFlash, _>("flash", &call.input)
+        {
+            // The pool sends exactly the requested amounts, which keeps the
+            // transfers of any swaps made by the callback from passing as loans
+            Some(LoanCall {
+                provider: Protocol::UniswapV3,
+                receiver: Some(recipient),
+                lender: Some(call.to),
+                repaid_to: None,
+                tokens: None,
+                amounts: Some(
+                    [amount0, amount1]
+                        .iter()
+                        .filter(|amount| !amount.is_zero())
+                        .cloned()
+                        .collect(),
+                ),
+            })
+        } else {
+            None
+        }
+    }
+}
+
+impl Inspector for FlashLoans {
+    fn name(&self) -> &'static str {
+        "flashloans"
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        let actions = &inspection.actions;
+        let mut classified = Vec::new();
+        for (i, action) in actions.iter().enumerate() {
+            let calltrace = match action.as_call() {
+                Some(inner) => inner,
+                None => continue,
+            };
+            let loan = match self.decode(calltrace.as_ref()) {
+                Some(inner) => inner,
+                None => continue,
+            };
+            // The calls which re-enter the called contract, e.g. swaps on the
+            // pool which lent the tokens. Their transfers are their own, and
+            // not part of the loan.
+            let reentrant = actions
+                .iter()
+                .skip(i + 1)
+                .filter_map(|a| a.as_call())
+                .filter(|c| {
+                    c.as_ref().to == calltrace.as_ref().to
+                        && is_nested(&calltrace.trace_address, &c.trace_address)
+                })
+                .map(|c| c.trace_address.clone())
+                .collect::<Vec<_>>();
+            let subtraces = || {
+                actions
+                    .iter()
+                    .enumerate()
+                    .skip(i + 1)
+                    .filter(|(_, a)| is_nested(&calltrace.trace_address, a.trace_address()))
+                    .filter(|(_, a)| {
+                        !reentrant
+                            .iter()
+                            .any(|call| is_nested(call, a.trace_address()))
+                    })
+            };
+
+            // The first transfer of each token out of the lender is the loan.
+            // Later ones are made by the actions which the loan funded.
+            let borrows = subtraces()
+                .filter_map(|(j, a)| Some((j, a.as_action()?.transfer()?)))
+                .filter(|(_, t)| {
+                    loan.receiver.iter().all(|to| t.to == *to)
+                        && loan.lender.iter().all(|from| t.from == *from)
+                        && loan.tokens.iter().all(|tokens| tokens.contains(&t.token))
+                        && loan
+                            .amounts
+                            .iter()
+                            .all(|amounts| amounts.contains(&t.amount))
+                })
+                .unique_by(|(_, t)| t.token)
+                .collect::<Vec<_>>();
+
+            let mut found = false;
+            for (j, borrow) in borrows {
+                // The repayment is the last transfer back to the lender, since
+                // the funded actions may also send it the same token
+                let repay = find_matching(
+                    subtraces().filter(|(k, _)| *k > j).rev(),
+                    |t| t.transfer(),
+                    |t| t.to == loan.repaid_to.unwrap_or(borrow.from) && t.token == borrow.token,
+                    true,
+                );
+                let (r, repay) = match repay {
+                    Some(inner) => inner,
+                    None => continue,
+                };
+
+                let flash_loan = Classification::new(
+                    FlashLoan {
+                        provider: loan.provider,
+                        token: borrow.token,
+                        amount: borrow.amount,
+                        fee: repay.amount.saturating_sub(borrow.amount),
+                    },
+                    actions[j].trace_address(),
+                );
+                classified.push((j, flash_loan));
+                classified.push((r, Classification::Prune));
+                found = true;
+            }
+
+            if found {
+                inspection.protocols.insert(loan.provider);
+                classified.push((i, Classification::Prune));
+            }
+        }
+
+        for (i, action) in classified {
+            inspection.actions[i] = action;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{inspectors::ERC20, test_helpers::*};
+    use ethers::abi::Token;
+
+    fn inspect(inspection: &mut Inspection) {
+        ERC20::new().inspect(inspection);
+        FlashLoans::new().inspect(inspection);
+        inspection.prune();
+    }
+
+    fn loans(inspection: &Inspection) -> Vec<FlashLoan> {
+        inspection
+            .known()
+            .iter()
+            .filter_map(|a| a.as_ref().flash_loan().cloned())
+            .collect()
+    }
+
+    fn aave_v2_flash_loan(receiver: Address, asset: Address, amount: u64) -> Bytes {
+        calldata(
+            "flashLoan(address,address[],uint256[],uint256[],address,bytes,uint16)",
+            &[
+                Token::Address(receiver),
+                Token::Array(vec![Token::Address(asset)]),
+                Token::Array(vec![Token::Uint(amount.into())]),
+                Token::Array(vec![Token::Uint(0.into())]),
+                Token::Address(receiver),
+                Token::Bytes(vec![]),
+                Token::Uint(0.into()),
+            ],
+        )
+    }
+
+    fn operate() -> Bytes {
+        calldata("operate((address,uint256)[],(uint8,uint256,(bool,uint8,uint8,uint256),uint256,uint256,address,uint256,bytes)[])", &[])
+    }
+
+    #[test]
+    fn nested_flash_loans() {
+        let addrs = addrs();
+        let (bot, weth, dai, aweth, other) = (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4]);
+        let (pool, dydx) = (*AAVE_V2_LENDING_POOL, *DYDX);
+
+        let mut inspection = mk_inspection(vec![
+            mk_call(bot, pool, aave_v2_flash_loan(bot, weth, 1000), vec![]),
+            mk_call(aweth, weth, transfer(bot, 1000.into()), vec![0]),
+            // executeOperation
+            mk_call(pool, bot, Bytes::default(), vec![1]),
+            // which also borrows from dYdX
+            mk_call(bot, dydx, operate(), vec![1, 0]),
+            mk_call(dydx, weth, transfer(bot, 500.into()), vec![1, 0, 0]),
+            // callFunction
+            mk_call(dydx, bot, Bytes::default(), vec![1, 0, 1]),
+            mk_call(bot, weth, transfer(other, 1500.into()), vec![1, 0, 1, 0]),
+            mk_call(other, dai, transfer(bot, 3000.into()), vec![1, 0, 1, 1]),
+            mk_call(
+                dydx,
+                weth,
+                transfer_from(bot, dydx, 502.into()),
+                vec![1, 0, 2],
+            ),
+            mk_call(pool, weth, transfer_from(bot, aweth, 1009.into()), vec![2]),
+        ]);
+        inspect(&mut inspection);
+
+        assert_eq!(
+            loans(&inspection),
+            vec![
+                FlashLoan {
+                    provider: Protocol::Aave,
+                    token: weth,
+                    amount: 1000.into(),
+                    fee: 9.into(),
+                },
+                FlashLoan {
+                    provider: Protocol::DyDx,
+                    token: weth,
+                    amount: 500.into(),
+                    fee: 2.into(),
+                },
+            ]
+        );
+        // the loan calls and the repayments are pruned, unlike the transfers
+        // made with the borrowed funds
+        assert_eq!(inspection.known().len(), 4);
+        assert_eq!(inspection.unknown().len(), 2);
+        assert_eq!(
+            inspection.protocols,
+            crate::set![Protocol::Aave, Protocol::DyDx]
+        );
+    }
+
+    #[test]
+    fn flashswap_repaid_in_the_same_token() {
+        let addrs = addrs();
+        let (bot, pair, weth, dai) = (addrs[0], addrs[1], addrs[2], addrs[3]);
+        let swap = calldata(
+            "swap(uint256,uint256,address,bytes)",
+            &[
+                Token::Uint(0.into()),
+                Token::Uint(100.into()),
+                Token::Address(bot),
+                Token::Bytes(vec![1]),
+            ],
+        );
+
+        for (repaid, expected) in [
+            (
+                weth,
+                vec![FlashLoan {
+                    provider: Protocol::Uniswappy,
+                    token: weth,
+                    amount: 100.into(),
+                    fee: 1.into(),
+                }],
+            ),
+            // repaying in the other token is a trade instead
+            (dai, vec![]),
+        ] {
+            let mut inspection = mk_inspection(vec![
+                mk_call(bot, pair, swap.clone(), vec![]),
+                mk_call(pair, weth, transfer(bot, 100.into()), vec![0]),
+                // uniswapV2Call
+                mk_call(pair, bot, Bytes::default(), vec![1]),
+                mk_call(bot, repaid, transfer(pair, 101.into()), vec![1, 0]),
+            ]);
+            inspect(&mut inspection);
+            assert_eq!(loans(&inspection), expected);
+        }
+    }
+
+    #[test]
+    fn borrowing_is_not_a_flash_loan() {
+        let addrs = addrs();
+        let (bot, weth, aweth) = (addrs[0], addrs[1], addrs[2]);
+        let pool = *AAVE_V2_LENDING_POOL;
+
+        // nothing is repaid, as when a non-zero `mode` opens a debt position
+        let mut inspection = mk_inspection(vec![
+            mk_call(bot, pool, aave_v2_flash_loan(bot, weth, 1000), vec![]),
+            mk_call(aweth, weth, transfer(bot, 1000.into()), vec![0]),
+            mk_call(pool, bot, Bytes::default(), vec![1]),
+        ]);
+        inspect(&mut inspection);
+        assert!(loans(&inspection).is_empty());
+        assert_eq!(inspection.unknown().len(), 2);
+    }
+
+    #[test]
+    fn balancer_flash_loan_of_many_tokens() {
+        let addrs = addrs();
+        let (bot, weth, dai, other) = (addrs[0], addrs[1], addrs[2], addrs[3]);
+        let vault = *BALANCER_VAULT;
+        let flash_loan = calldata(
+            "flashLoan(address,address[],uint256[],bytes)",
+            &[
+                Token::Address(bot),
+                Token::Array(vec![Token::Address(weth), Token::Address(dai)]),
+                Token::Array(vec![Token::Uint(1000.into()), Token::Uint(3000.into())]),
+                Token::Bytes(vec![]),
+            ],
+        );
+
+        let mut inspection = mk_inspection(vec![
+            mk_call(bot, vault, flash_loan, vec![]),
+            mk_call(vault, weth, transfer(bot, 1000.into()), vec![0]),
+            mk_call(vault, dai, transfer(bot, 3000.into()), vec![1]),
+            // receiveFlashLoan
+            mk_call(vault, bot, Bytes::default(), vec![2]),
+            mk_call(bot, weth, transfer(other, 1000.into()), vec![2, 0]),
+            mk_call(other, weth, transfer(bot, 1010.into()), vec![2, 1]),
+            mk_call(bot, weth, transfer(vault, 1000.into()), vec![2, 2]),
+            mk_call(bot, dai, transfer(vault, 3000.into()), vec![2, 3]),
+        ]);
+        inspect(&mut inspection);
+        assert_eq!(
+            loans(&inspection),
+            vec![
+                FlashLoan {
+                    provider: Protocol::Balancer,
+                    token: weth,
+                    amount: 1000.into(),
+                    fee: 0.into(),
+                },
+                FlashLoan {
+                    provider: Protocol::Balancer,
+                    token: dai,
+                    amount: 3000.into(),
+                    fee: 0.into(),
+                },
+            ]
+        );
+        // the loans and the funded transfers
+        assert_eq!(inspection.known().len(), 4);
+        assert_eq!(inspection.protocols, crate::set![Protocol::Balancer]);
+    }
+
+    #[test]
+    fn maker_flash_mint() {
+        let addrs = addrs();
+        let (bot, dai, dai_join, other) = (addrs[0], addrs[1], addrs[2], addrs[3]);
+        let flash = *DSS_FLASH.iter().next().unwrap();
+        let flash_loan = calldata(
+            "flashLoan(address,address,uint256,bytes)",
+            &[
+                Token::Address(bot),
+                Token::Address(dai),
+                Token::Uint(1000.into()),
+                Token::Bytes(vec![]),
+            ],
+        );
+        let mint = calldata(
+            "mint(address,uint256)",
+            &[Token::Address(bot), Token::Uint(1000.into())],
+        );
+        let approve = calldata(
+            "approve(address,uint256)",
+            &[Token::Address(flash), Token::Uint(1001.into())],
+        );
+        let burn = calldata(
+            "burn(address,uint256)",
+            &[Token::Address(flash), Token::Uint(1000.into())],
+        );
+
+        let mut inspection = mk_inspection(vec![
+            mk_call(bot, flash, flash_loan, vec![]),
+            // DaiJoin.exit
+            mk_call(flash, dai_join, Bytes::default(), vec![0]),
+            mk_call(dai_join, dai, mint, vec![0, 0]),
+            // onFlashLoan
+            mk_call(flash, bot, Bytes::default(), vec![1]),
+            mk_call(bot, dai, transfer(other, 1000.into()), vec![1, 0]),
+            mk_call(other, dai, transfer(bot, 1010.into()), vec![1, 1]),
+            mk_call(bot, dai, approve, vec![1, 2]),
+            mk_call(flash, dai, transfer_from(bot, flash, 1001.into()), vec![2]),
+            // DaiJoin.join
+            mk_call(flash, dai_join, Bytes::default(), vec![3]),
+            mk_call(dai_join, dai, burn, vec![3, 0]),
+        ]);
+        inspect(&mut inspection);
+        assert_eq!(
+            loans(&inspection),
+            vec![FlashLoan {
+                provider: Protocol::MakerDAO,
+                token: dai,
+                amount: 1000.into(),
+                fee: 1.into(),
+            }]
+        );
+        assert_eq!(inspection.protocols, crate::set![Protocol::MakerDAO]);
+    }
+
+    fn pool_flash(recipient: Address, amount0: u64, amount1: u64) -> Bytes {
+        calldata(
+            "flash(address,uint256,uint256,bytes)",
+            &[
+                Token::Address(recipient),
+                Token::Uint(amount0.into()),
+                Token::Uint(amount1.into()),
+                Token::Bytes(vec![]),
+            ],
+        )
+    }
+
+    #[test]
+    fn pool_flash_with_fee() {
+        let addrs = addrs();
+        let (bot, pool, weth, dai) = (addrs[0], addrs[1], addrs[2], addrs[3]);
+
+        let mut inspection = mk_inspection(vec![
+            mk_call(bot, pool, pool_flash(bot, 0, 1000), vec![]),
+            mk_call(pool, weth, transfer(bot, 1000.into()), vec![0]),
+            // uniswapV3FlashCallback
+            mk_call(pool, bot, Bytes::default(), vec![1]),
+            mk_call(bot, weth, transfer(addrs[4], 1000.into()), vec![1, 0]),
+            mk_call(addrs[4], dai, transfer(bot, 3000.into()), vec![1, 1]),
+            mk_call(bot, weth, transfer(pool, 1003.into()), vec![1, 2]),
+        ]);
+        inspect(&mut inspection);
+        assert_eq!(
+            loans(&inspection),
+            vec![FlashLoan {
+                provider: Protocol::UniswapV3,
+                token: weth,
+                amount: 1000.into(),
+                fee: 3.into(),
+            }]
+        );
+        // the funded transfers are kept
+        assert_eq!(inspection.known().len(), 3);
+    }
+
+    #[test]
+    fn pool_flash_swapping_on_the_same_pool() {
+        let addrs = addrs();
+        let (bot, pool, weth, dai) = (addrs[0], addrs[1], addrs[2], addrs[3]);
+        let swap = calldata(
+            "swap(address,bool,int256,uint160,bytes)",
+            &[
+                Token::Address(bot),
+                Token::Bool(true),
+                Token::Int(500.into()),
+                Token::Uint(0.into()),
+                Token::Bytes(vec![]),
+            ],
+        );
+
+        let mut inspection = mk_inspection(vec![
+            mk_call(bot, pool, pool_flash(bot, 0, 1000), vec![]),
+            mk_call(pool, weth, transfer(bot, 1000.into()), vec![0]),
+            mk_call(pool, bot, Bytes::default(), vec![1]),
+            mk_call(bot, weth, transfer(pool, 1003.into()), vec![1, 0]),
+            // after repaying, the callback sells WETH on the same pool
+            mk_call(bot, pool, swap, vec![1, 1]),
+            mk_call(pool, dai, transfer(bot, 1000.into()), vec![1, 1, 0]),
+            // uniswapV3SwapCallback
+            mk_call(pool, bot, Bytes::default(), vec![1, 1, 1]),
+            mk_call(bot, weth, transfer(pool, 500.into()), vec![1, 1, 1, 0]),
+        ]);
+        inspect(&mut inspection);
+        assert_eq!(
+            loans(&inspection),
+            vec![FlashLoan {
+                provider: Protocol::UniswapV3,
+                token: weth,
+                amount: 1000.into(),
+                fee: 3.into(),
+            }]
+        );
+        // the swap's transfers are left for the trade
+        let transfers = inspection
+            .known()
+            .iter()
+            .filter_map(|a| a.as_ref().transfer().cloned())
+            .collect::<Vec<_>>();
+        assert_eq!(transfers.len(), 2);
+        assert_eq!(
+            (transfers[0].token, transfers[0].amount),
+            (dai, 1000.into())
+        );
+        assert_eq!(
+            (transfers[1].token, transfers[1].amount),
+            (weth, 500.into())
+        );
+    }
+}
@@ -0,0 +1,228 @@
+use crate::{
+    addresses::{CHI, GST2},
+    is_nested,
+    types::{
+        actions::{GasToken, GasTokenKind},
+        Classification, Inspection,
+    },
+    Inspector,
+};
+use ethers::{
+    abi::parse_abi,
+    contract::BaseContract,
+    types::{Address, Call as TraceCall, CallType, U256},
+};
+use once_cell::sync::Lazy;
+
+static GAS_TOKEN_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    parse_abi(&[
+        "function mint(uint256)",
+        "function free(uint256) returns (bool)",
+        "function freeUpTo(uint256) returns (uint256)",
+        "function freeFrom(address, uint256) returns (bool)",
+        "function freeFromUpTo(address, uint256) returns (uint256)",
+    ])
+    .expect("could not parse gas token abi")
+    .into()
+});
+
+#[derive(Debug, Clone)]
+/// Classifies the minting and burning of the CHI and GST2 gas tokens.
+///
+/// Burning a token self-destructs one of the contracts which minting it
+/// created, which refunded gas before London. The contracts' creations and
+/// self-destructs are part of the gas token's action, so they are pruned.
+pub struct GasTokens {
+    abi: &'static BaseContract,
+    tokens: Vec<Address>,
+}
+
+impl Default for GasTokens {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GasTokens {
+    /// Constructor
+    pub fn new() -> Self {
+        Self {
+            abi: &GAS_TOKEN_ABI,
+            tokens: vec![*CHI, *GST2],
+        }
+    }
+
+    /// Parses a mint or a burn of gas tokens. The `UpTo` burns free as many
+    /// tokens as the holder has, up to the requested amount, and return how many
+    /// they freed.
+    pub fn try_parse(&self, call: &TraceCall, output: &[u8]) -> Option<GasToken> {
+        if !self.tokens.contains(&call.to) || call.call_type != CallType::Call {
+            return None;
+        }
+        let freed = |requested: U256| {
+            if output.len() >= 32 {
+                U256::from_big_endian(&output[..32])
+            } else {
+                requested
+            }
+        };
+
+        let (account, amount, kind) =
+            if let Ok(amount) = self.abi.decode::<U256, _>("mint", &call.input) {
+                (call.from, amount, GasTokenKind::Mint)
+            } else if let Ok(amount) = self.abi.decode::<U256, _>("free", &call.input) {
+                (call.from, amount, GasTokenKind::Free)
+            } else if let Ok(amount) = self.abi.decode::<U256, _>("freeUpTo", &call.input) {
+                (call.from, freed(amount), GasTokenKind::Free)
+            } else if let Ok((account, amount)) = self
+                .abi
+                .decode::<(Address, U256), _>("freeFrom", &call.input)
+            {
+                (account, amount, GasTokenKind::Free)
+            } else if let Ok((account, amount)) = self
+                .abi
+                .decode::<(Address, U256), _>("freeFromUpTo", &call.input)
+            {
+                (account, freed(amount), GasTokenKind::Free)
+            } else {
+                return None;
+            };
+
+        Some(GasToken {
+            token: call.to,
+            account,
+            amount,
+            kind,
+        })
+    }
+}
+
+impl Inspector for GasTokens {
+    fn name(&self) -> &'static str {
+        "gastokens"
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        // only the trace address of each gas token call is copied, before its
+        // subcalls are pruned
+        for i in 0..inspection.actions.len() {
+            let calltrace = match inspection.actions[i].as_call() {
+                Some(inner) => inner,
+                None => continue,
+            };
+            let gas_token = match self.try_parse(calltrace.as_ref(), calltrace.output.as_ref()) {
+                Some(inner) => inner,
+                None => continue,
+            };
+            let trace_address = calltrace.trace_address.clone();
+
+            inspection
+                .actions
+                .iter_mut()
+                .filter(|a| is_nested(&trace_address, a.trace_address()))
+                .for_each(|a| *a = Classification::Prune);
+            inspection.actions[i] = Classification::new(gas_token, trace_address);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_helpers::*,
+        types::{actions::SpecificAction, GasInfo},
+        UniswapV2Oracle,
+    };
+    use ethers::{abi::Token, types::I256};
+
+    #[test]
+    fn burns_in_a_historical_arb() {
+        // a Curve arbitrage which burns 54 CHI
+        let mut inspection = read_trace("simple_curve_arb.json");
+        let num_actions = inspection.actions.len();
+        GasTokens::new().inspect(&mut inspection);
+        inspection.prune();
+
+        let burns = inspection
+            .known_actions()
+            .filter_map(|a| a.gas_token())
+            .cloned()
+            .collect::<Vec<_>>();
+        assert_eq!(burns.len(), 1);
+        assert_eq!(burns[0].token, *CHI);
+        assert_eq!(burns[0].amount, 54.into());
+        assert_eq!(burns[0].kind, GasTokenKind::Free);
+        // the calls to the token's contracts and their self-destructs
+        assert_eq!(inspection.actions.len(), num_actions - 2 * 54);
+        assert!(!inspection
+            .known_actions()
+            .any(|a| matches!(a, SpecificAction::SelfDestruct(_))));
+
+        // the refund is capped by the gas used
+        assert_eq!(inspection.gas_token_refund(), (54 * 24_000).into());
+        inspection.gas = Some(GasInfo {
+            gas_used: 1_000_000.into(),
+            gas_price: 2.into(),
+            ..Default::default()
+        });
+        assert_eq!(inspection.gas_token_refund(), 1_000_000.into());
+
+        // which is paid for along with the gas, as there is nothing else priced
+        // in the tx
+        let profit = inspection.net_profit_eth(&UniswapV2Oracle::new()).unwrap();
+        assert_eq!(profit, I256::from(-4_000_000));
+
+        // nothing is refunded since London
+        inspection.block_number = crate::types::gas::LONDON_BLOCK;
+        assert_eq!(inspection.gas_token_refund(), 0.into());
+    }
+
+    #[test]
+    fn mints_and_frees_from() {
+        let addrs = addrs();
+        let (bot, holder) = (addrs[0], addrs[1]);
+        let mut inspection = mk_inspection(vec![
+            mk_call(
+                bot,
+                *GST2,
+                calldata("mint(uint256)", &[Token::Uint(10.into())]),
+                vec![0],
+            ),
+            with_output(
+                mk_call(
+                    bot,
+                    *GST2,
+                    calldata(
+                        "freeFromUpTo(address,uint256)",
+                        &[Token::Address(holder), Token::Uint(10.into())],
+                    ),
+                    vec![1],
+                ),
+                &[Token::Uint(3.into())],
+            ),
+            // another token's mint
+            mk_call(
+                bot,
+                addrs[2],
+                calldata("mint(uint256)", &[Token::Uint(10.into())]),
+                vec![2],
+            ),
+        ]);
+        GasTokens::new().inspect(&mut inspection);
+
+        let actions = inspection
+            .known_actions()
+            .filter_map(|a| a.gas_token())
+            .map(|a| (a.account, a.amount, a.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            actions,
+            vec![
+                (bot, 10.into(), GasTokenKind::Mint),
+                (holder, 3.into(), GasTokenKind::Free)
+            ]
+        );
+        assert_eq!(inspection.gas_token_refund(), (3 * 24_000).into());
+    }
+}
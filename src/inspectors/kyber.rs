@@ -0,0 +1,264 @@
+use crate::{
+    addresses::{ChainConfig, ETH, WETH},
+    inspectors::{inspect_routed_trades, RoutedTrade, ERC20},
+    traits::Inspector,
+    types::{Inspection, Protocol},
+};
+
+use ethers::{
+    abi::parse_abi,
+    contract::BaseContract,
+    types::{Address, Bytes, Call as TraceCall, U256},
+};
+use once_cell::sync::Lazy;
+
+static PROXY_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    parse_abi(&[
+        "function trade(address, uint256, address, address, uint256, uint256, address) returns (uint256)",
+        "function tradeWithHint(address, uint256, address, address, uint256, uint256, address, bytes) returns (uint256)",
+        "function tradeWithHintAndFee(address, uint256, address, address, uint256, uint256, address, uint256, bytes) returns (uint256)",
+        "function swapEtherToToken(address, uint256) returns (uint256)",
+        "function swapTokenToEther(address, uint256, uint256) returns (uint256)",
+        "function swapTokenToToken(address, uint256, address, uint256) returns (uint256)",
+    ])
+    .expect("could not parse kyber abi")
+    .into()
+});
+
+// Type aliases for the KyberNetworkProxy's trade functions
+type KyberTrade = (Address, U256, Address, Address, U256, U256, Address);
+type TradeWithHint = (Address, U256, Address, Address, U256, U256, Address, Bytes);
+type TradeWithHintAndFee = (
+    Address,
+    U256,
+    Address,
+    Address,
+    U256,
+    U256,
+    Address,
+    U256,
+    Bytes,
+);
+type SwapEtherToToken = (Address, U256);
+type SwapTokenToEther = (Address, U256, U256);
+type SwapTokenToToken = (Address, U256, Address, U256);
+
+#[derive(Debug, Clone)]
+/// An inspector for the KyberNetworkProxy.
+///
+/// The proxy trades with the network's reserves, whose calls get collapsed into
+/// a single trade between the caller and the proxy. Kyber denotes ETH with
+/// `0xeee...eee`, which is replaced with WETH so that its trades can be netted
+/// against the WETH traded on other exchanges. Since the calls to the proxy pay
+/// it ETH, it must run before `ERC20` classifies them as ETH transfers.
+pub struct Kyber {
+    proxy: &'static BaseContract,
+    erc20: ERC20,
+    weth: Address,
+}
+
+impl Default for Kyber {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Kyber {
+    /// Constructor
+    pub fn new() -> Self {
+        Self::with_weth(*WETH)
+    }
+
+    /// Instantiates an inspector for a chain whose wrapped ETH is `weth`
+    pub fn with_weth(weth: Address) -> Self {
+        Self {
+            proxy: &PROXY_ABI,
+            erc20: ERC20::new(),
+            weth,
+        }
+    }
+
+    /// Instantiates an inspector for `chain`
+    pub fn with_chain(chain: &ChainConfig) -> Self {
+        Self {
+            erc20: ERC20::with_chain(chain),
+            ..Self::with_weth(chain.weth)
+        }
+    }
+
+    fn decode(&self, call: &TraceCall) -> Option<RoutedTrade> {
+        let (src_token, src_amount, dst_token, receiver) =
+            if let Ok(args) = self.proxy.decode::<KyberTrade, _>("trade", &call.input) {
+                (args.0, args.1, args.2, args.3)
+            } else if let Ok(args) = self
+                .proxy
+                .decode::<TradeWithHint, _>("tradeWithHint", &call.input)
+            {
+                (args.0, args.1, args.2, args.3)
+            } else if let Ok(args) = self
+                .proxy
+                .decode::<TradeWithHintAndFee, _>("tradeWithHintAndFee", &call.input)
+            {
+                (args.0, args.1, args.2, args.3)
+            } else if let Ok((token, _)) = self
+                .proxy
+                .decode::<SwapEtherToToken, _>("swapEtherToToken", &call.input)
+            {
+                (*ETH, call.value, token, call.from)
+            } else if let Ok((token, amount, _)) = self
+                .proxy
+                .decode::<SwapTokenToEther, _>("swapTokenToEther", &call.input)
+            {
+                (token, amount, *ETH, call.from)
+            } else if let Ok((src, amount, dst, _)) = self
+                .proxy
+                .decode::<SwapTokenToToken, _>("swapTokenToToken", &call.input)
+            {
+                (src, amount, dst, call.from)
+            } else {
+                return None;
+            };
+
+        Some(RoutedTrade {
+            // the ETH actually paid, since any excess of `srcAmount` is refunded
+            src_amount: if src_token == *ETH && !call.value.is_zero() {
+                call.value
+            } else {
+                src_amount
+            },
+            src_token,
+            dst_token,
+            receiver,
+        })
+    }
+}
+
+impl Inspector for Kyber {
+    fn name(&self) -> &'static str {
+        "kyber"
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        inspect_routed_trades(
+            inspection,
+            &self.erc20,
+            self.weth,
+            Protocol::Kyber,
+            |call| self.decode(call),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Provenance;
+    use crate::{
+        inspectors::UniswapV2,
+        reducers::{ArbitrageReducer, TradeReducer},
+        test_helpers::*,
+        Reducer,
+    };
+    use ethers::abi::Token;
+
+    fn inspect(inspection: &mut Inspection) {
+        Kyber::new().inspect(inspection);
+        ERC20::new().inspect(inspection);
+        UniswapV2::new().inspect(inspection);
+        TradeReducer::new().reduce(inspection);
+        ArbitrageReducer::new().reduce(inspection);
+        inspection.prune();
+    }
+
+    fn swap_ether_to_token(token: Address) -> Bytes {
+        calldata(
+            "swapEtherToToken(address,uint256)",
+            &[Token::Address(token), Token::Uint(1.into())],
+        )
+    }
+
+    #[test]
+    fn swap_ether_to_token_as_weth() {
+        let addrs = addrs();
+        let (usr, proxy, network, reserve, dai) =
+            (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4]);
+
+        let mut inspection = mk_inspection(vec![
+            with_output(
+                with_value(
+                    mk_call(usr, proxy, swap_ether_to_token(dai), vec![]),
+                    2.into(),
+                ),
+                &[Token::Uint(6000.into())],
+            ),
+            with_value(mk_call(proxy, network, Bytes::default(), vec![0]), 2.into()),
+            with_value(
+                mk_call(network, reserve, Bytes::default(), vec![0, 0]),
+                2.into(),
+            ),
+            mk_call(reserve, dai, transfer(network, 6000.into()), vec![0, 0, 0]),
+            mk_call(network, dai, transfer(usr, 6000.into()), vec![0, 1]),
+        ]);
+        inspect(&mut inspection);
+
+        let known = inspection.known();
+        assert_eq!(known.len(), 1);
+        let trade = known[0].as_ref().trade().unwrap();
+        assert_eq!(
+            (trade.t1.from, trade.t1.token, trade.t1.amount),
+            (usr, *WETH, 2.into())
+        );
+        assert_eq!(
+            (trade.t2.to, trade.t2.token, trade.t2.amount),
+            (usr, dai, 6000.into())
+        );
+        assert_eq!(known[0].provenance, Provenance::TransferInferred);
+        assert_eq!(inspection.protocols, crate::set![Protocol::Kyber]);
+    }
+
+    #[test]
+    fn arbitrage_against_uniswap() {
+        let addrs = addrs();
+        let (eoa, bot, proxy, reserve, pair, dai) =
+            (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4], addrs[5]);
+
+        // buy DAI with ETH on Kyber, whose output is not known, and sell it
+        // for more WETH on Uniswap
+        let mut inspection = mk_inspection(vec![
+            mk_call(eoa, bot, Bytes::default(), vec![]),
+            with_value(
+                mk_call(bot, proxy, swap_ether_to_token(dai), vec![0]),
+                2.into(),
+            ),
+            with_value(
+                mk_call(proxy, reserve, Bytes::default(), vec![0, 0]),
+                2.into(),
+            ),
+            mk_call(reserve, dai, transfer(bot, 6000.into()), vec![0, 0, 0]),
+            mk_call(bot, dai, transfer(pair, 6000.into()), vec![1]),
+            mk_call(
+                bot,
+                pair,
+                calldata(
+                    "swap(uint256,uint256,address,bytes)",
+                    &[
+                        Token::Uint(0.into()),
+                        Token::Uint(3.into()),
+                        Token::Address(bot),
+                        Token::Bytes(vec![]),
+                    ],
+                ),
+                vec![2],
+            ),
+            mk_call(pair, *WETH, transfer(bot, 3.into()), vec![2, 0]),
+        ]);
+        inspect(&mut inspection);
+
+        let known = inspection.known();
+        assert_eq!(known.len(), 1);
+        let arb = known[0].as_ref().arbitrage().unwrap();
+        assert_eq!(arb.profit, 1.into());
+        assert_eq!(arb.token, *WETH);
+        assert_eq!(arb.trades.len(), 2);
+    }
+}
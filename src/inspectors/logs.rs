@@ -0,0 +1,743 @@
+use crate::{
+    types::{
+        actions::{AddLiquidity, NftTransfer, RemoveLiquidity, SpecificAction, Trade, Transfer},
+        Classification, Inspection, Protocol, Provenance,
+    },
+    Inspector,
+};
+use ethers::{
+    abi::{self, ParamType},
+    types::{Address, Log, H256, I256, U256},
+    utils::keccak256,
+};
+
+#[derive(Debug, Clone)]
+/// Classifies a transaction's event logs, for providers whose traces are
+/// missing or unreliable.
+///
+/// ERC20 `Transfer`s are turned into transfers, which get paired into trades
+/// and liquidity changes by the Uniswap V2 and V3 `Swap` and the Uniswap V2
+/// `Mint` and `Burn` events which follow them. ERC1155's `TransferSingle` and
+/// `TransferBatch` are turned into NFT transfers, which ERC20's and ERC721's
+/// `Transfer` cannot be mistaken for since their topics differ.
+///
+/// If the transaction was already classified from its traces, only the trades
+/// which its traces missed are added, so that a swap seen in both is not
+/// counted twice. It must therefore run after all the trace inspectors.
+///
+/// By default a `Swap` is only paired with transfers of the exact amounts it
+/// emitted. With `with_swap_events`, the `Swap`s are the source of truth for
+/// the pool and the direction of the trades instead, and are paired with the
+/// transfers in and out of the pool whatever their amounts. The amounts are
+/// the ones of the `Transfer` logs either way, which is what the trace
+/// inspectors credit fee-on-transfer tokens with too (see
+/// `credit_logged_amounts`), so that both agree.
+pub struct EventLogs {
+    transfer: H256,
+    swap_v2: H256,
+    swap_v3: H256,
+    mint: H256,
+    burn: H256,
+    transfer_single: H256,
+    transfer_batch: H256,
+    swap_events: bool,
+}
+
+/// A swap, as emitted by a pool
+struct Swap {
+    pool: Address,
+    amount_in: U256,
+    amount_out: U256,
+    recipient: Address,
+}
+
+impl Default for EventLogs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventLogs {
+    pub fn new() -> Self {
+        let topic = |event: &str| H256::from(keccak256(event));
+        Self {
+            transfer: topic("Transfer(address,address,uint256)"),
+            swap_v2: topic("Swap(address,uint256,uint256,uint256,uint256,address)"),
+            swap_v3: topic("Swap(address,address,int256,int256,uint160,uint128,int24)"),
+            mint: topic("Mint(address,uint256,uint256)"),
+            burn: topic("Burn(address,uint256,uint256,address)"),
+            transfer_single: topic("TransferSingle(address,address,address,uint256,uint256)"),
+            transfer_batch: topic("TransferBatch(address,address,address,uint256[],uint256[])"),
+            swap_events: false,
+        }
+    }
+
+    /// Builds the trades from the pools' `Swap` events, without requiring the
+    /// transfers around them to be of the amounts they emitted, e.g. when the
+    /// pool sent a fee-on-transfer token whose recipient got less
+    pub fn with_swap_events(mut self) -> Self {
+        self.swap_events = true;
+        self
+    }
+
+    /// Classifies the logs, in the order in which they were emitted
+    pub fn classify(&self, logs: &[Log]) -> Vec<Classification> {
+        let mut actions: Vec<Classification> = Vec::new();
+        for log in logs {
+            let topic = match log.topics.first() {
+                Some(inner) => *inner,
+                None => continue,
+            };
+
+            if topic == self.transfer {
+                if let Some(transfer) = self.decode_transfer(log) {
+                    actions.push(Classification::new(transfer, Vec::new()));
+                }
+            } else if topic == self.transfer_single || topic == self.transfer_batch {
+                for transfer in self.decode_nft_transfers(log).unwrap_or_default() {
+                    actions.push(Classification::new(transfer, Vec::new()));
+                }
+            } else if topic == self.swap_v2 || topic == self.swap_v3 {
+                if let Some(swap) = self.decode_swap(log) {
+                    pair_swap(&mut actions, swap, !self.swap_events);
+                }
+            } else if topic == self.mint || topic == self.burn {
+                let amounts = match decode_uints(log.data.as_ref(), 2) {
+                    Some(inner) => inner,
+                    None => continue,
+                };
+                if topic == self.mint {
+                    // the tokens were sent to the pair before it minted
+                    let found = pair_liquidity(&mut actions, &amounts, |t| t.to == log.address);
+                    if let Some((i, tokens)) = found {
+                        actions[i] = Classification::new(
+                            AddLiquidity {
+                                tokens,
+                                amounts,
+                                position: None,
+                            },
+                            Vec::new(),
+                        );
+                    }
+                } else {
+                    let found = pair_liquidity(&mut actions, &amounts, |t| t.from == log.address);
+                    if let Some((i, tokens)) = found {
+                        actions[i] = Classification::new(
+                            RemoveLiquidity {
+                                tokens,
+                                amounts,
+                                fees: vec![U256::zero(); 2],
+                                position: None,
+                            },
+                            Vec::new(),
+                        );
+                    }
+                }
+            }
+        }
+
+        actions
+            .into_iter()
+            .filter(|a| a != &Classification::Prune)
+            .map(|a| a.with_provenance(Provenance::EventDecoded))
+            .collect()
+    }
+
+    fn decode_transfer(&self, log: &Log) -> Option<Transfer> {
+        // ERC721 transfers index their token id, which makes them 4 topics long
+        if log.topics.len() != 3 {
+            return None;
+        }
+        Some(Transfer {
+            from: Address::from(log.topics[1]),
+            to: Address::from(log.topics[2]),
+            amount: *decode_uints(log.data.as_ref(), 1)?.first()?,
+            token: log.address,
+        })
+    }
+
+    /// Decodes an ERC1155 `TransferSingle` or `TransferBatch` into a transfer
+    /// per token id
+    fn decode_nft_transfers(&self, log: &Log) -> Option<Vec<NftTransfer>> {
+        // the operator, sender and recipient are indexed
+        if log.topics.len() != 4 {
+            return None;
+        }
+        let (from, to) = (Address::from(log.topics[2]), Address::from(log.topics[3]));
+        let (ids, amounts) = if log.topics[0] == self.transfer_single {
+            let values = decode_uints(log.data.as_ref(), 2)?;
+            (vec![values[0]], vec![values[1]])
+        } else {
+            let array = ParamType::Array(Box::new(ParamType::Uint(256)));
+            let mut tokens = abi::decode(&[array.clone(), array], log.data.as_ref()).ok()?;
+            let amounts = tokens.pop()?.into_array()?;
+            let ids = tokens.pop()?.into_array()?;
+            let uints = |tokens: Vec<abi::Token>| -> Option<Vec<U256>> {
+                tokens.into_iter().map(|token| token.into_uint()).collect()
+            };
+            (uints(ids)?, uints(amounts)?)
+        };
+        if ids.len() != amounts.len() {
+            return None;
+        }
+
+        let transfers = ids
+            .into_iter()
+            .zip(amounts)
+            .map(|(token_id, amount)| NftTransfer {
+                collection: log.address,
+                token_id,
+                amount,
+                from,
+                to,
+            })
+            .collect();
+        Some(transfers)
+    }
+
+    fn decode_swap(&self, log: &Log) -> Option<Swap> {
+        let data = log.data.as_ref();
+        let (amount_in, amount_out, recipient) = if log.topics.first()? == &self.swap_v2 {
+            // amount0In, amount1In, amount0Out, amount1Out, and the recipient
+            let amounts = decode_uints(data, 4)?;
+            (
+                amounts[0].max(amounts[1]),
+                amounts[2].max(amounts[3]),
+                Address::from(*log.topics.get(2)?),
+            )
+        } else {
+            // the pool's balance changes, positive for the token it received
+            let amounts = decode_uints(data, 2)?;
+            let (amount0, amount1) = (I256::from_raw(amounts[0]), I256::from_raw(amounts[1]));
+            let (amount_in, amount_out) = if amount0.is_positive() {
+                (amount0, amount1)
+            } else {
+                (amount1, amount0)
+            };
+            (
+                amount_in.into_raw(),
+                (-amount_out).into_raw(),
+                Address::from(*log.topics.get(2)?),
+            )
+        };
+
+        Some(Swap {
+            pool: log.address,
+            amount_in,
+            amount_out,
+            recipient,
+        })
+    }
+}
+
+/// Decodes the first `n` words of a log's data
+fn decode_uints(data: &[u8], n: usize) -> Option<Vec<U256>> {
+    let params = vec![ParamType::Uint(256); n];
+    if data.len() < 32 * n {
+        return None;
+    }
+    abi::decode(&params, &data[..32 * n])
+        .ok()?
+        .into_iter()
+        .map(|token| token.into_uint())
+        .collect()
+}
+
+/// Credits the transfers which were decoded from calls with the amounts of
+/// their `Transfer` logs, which is what their recipients actually received.
+///
+/// Fee-on-transfer tokens, e.g. PAXG and the reflection tokens, take their fee
+/// within their own contract, so that the amount of the `transfer` call is more
+/// than what was credited. Only their logs tell, e.g. by emitting the net
+/// amount to the recipient and the fee to a collector. Each transfer is paired
+/// with the first log of the same token between the same accounts which was
+/// not paired yet, in the order in which they were made.
+pub fn credit_logged_amounts(actions: &mut [Classification], logs: &[Log]) {
+    let events = EventLogs::new();
+    let mut logged = logs
+        .iter()
+        .filter(|log| log.topics.first() == Some(&events.transfer))
+        .filter_map(|log| events.decode_transfer(log).map(Some))
+        .collect::<Vec<_>>();
+    if logged.is_empty() {
+        return;
+    }
+
+    for action in actions.iter_mut() {
+        let inner = match action {
+            Classification::Known(inner) => inner,
+            _ => continue,
+        };
+        let transfer = match inner.action {
+            SpecificAction::Transfer(ref mut transfer) => transfer,
+            _ => continue,
+        };
+        let found = logged
+            .iter_mut()
+            .find(|log| matches!(log, Some(log) if same_flow(log, transfer)));
+        if let Some(log) = found.and_then(Option::take) {
+            if log.amount != transfer.amount {
+                transfer.amount = log.amount;
+                inner.provenance = Provenance::EventDecoded;
+            }
+        }
+    }
+}
+
+/// Finds the latest unpaired transfer matching `filter`
+fn find_transfer(actions: &[Classification], filter: impl Fn(&Transfer) -> bool) -> Option<usize> {
+    actions.iter().rposition(
+        |action| matches!(action.as_action().and_then(|a| a.transfer()), Some(t) if filter(t)),
+    )
+}
+
+fn transfer_at(actions: &[Classification], i: usize) -> Transfer {
+    actions[i]
+        .as_action()
+        .and_then(|a| a.transfer())
+        .cloned()
+        .expect("found a transfer")
+}
+
+/// Replaces the transfers into and out of the pool which preceded its `Swap`
+/// with the trade they made up. Unless `check_amounts`, they may have moved
+/// other amounts than the `Swap`'s: the pool sent its output to the `Swap`'s
+/// recipient, and fee-on-transfer tokens may have sent a part of it to their
+/// fee collector as well.
+fn pair_swap(actions: &mut [Classification], swap: Swap, check_amounts: bool) {
+    let out = find_transfer(actions, |t| {
+        t.from == swap.pool
+            && t.to == swap.recipient
+            && (!check_amounts || t.amount == swap.amount_out)
+    });
+    let out = match out {
+        Some(inner) => inner,
+        None => return,
+    };
+    let t2 = transfer_at(actions, out);
+    let input = find_transfer(actions, |t| {
+        t.to == swap.pool && t.token != t2.token && (!check_amounts || t.amount == swap.amount_in)
+    });
+    if let Some(input) = input {
+        let t1 = transfer_at(actions, input);
+        let (first, second) = (input.min(out), input.max(out));
+        actions[first] = Classification::new(Trade::new(t1, t2), Vec::new());
+        actions[second] = Classification::Prune;
+    }
+}
+
+/// Finds the transfers of both of a pair's tokens which match the amounts of
+/// its `Mint` or `Burn`, and prunes all but the first one
+fn pair_liquidity(
+    actions: &mut [Classification],
+    amounts: &[U256],
+    filter: impl Fn(&Transfer) -> bool,
+) -> Option<(usize, Vec<Address>)> {
+    let i = find_transfer(actions, |t| filter(t) && t.amount == amounts[0])?;
+    let token0 = transfer_at(actions, i).token;
+    let j = find_transfer(actions, |t| {
+        filter(t) && t.amount == amounts[1] && t.token != token0
+    })?;
+    let token1 = transfer_at(actions, j).token;
+
+    actions[i.max(j)] = Classification::Prune;
+    Some((i.min(j), vec![token0, token1]))
+}
+
+/// Whether both transfers moved the same token between the same accounts,
+/// whatever their amounts
+fn same_flow(transfer: &Transfer, other: &Transfer) -> bool {
+    transfer.token == other.token && transfer.from == other.from && transfer.to == other.to
+}
+
+impl Inspector for EventLogs {
+    fn name(&self) -> &'static str {
+        "logs"
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        let from_logs = self.classify(&inspection.logs);
+        if from_logs.is_empty() {
+            return;
+        }
+
+        // substitute for the missing traces
+        if inspection.actions.is_empty() {
+            inspection.actions = from_logs;
+            return;
+        }
+
+        // Otherwise the traces already saw all the transfers, so only the
+        // trades are merged in. Each of the traces' actions can only be
+        // matched once, since a tx may repeat the same swap.
+        let mut matched = vec![false; inspection.actions.len()];
+        for trade in from_logs
+            .iter()
+            .filter_map(|a| a.as_action().and_then(|a| a.trade()))
+        {
+            let find = |actions: &[Classification],
+                        matched: &[bool],
+                        f: &dyn Fn(&SpecificAction) -> bool| {
+                actions
+                    .iter()
+                    .enumerate()
+                    .position(|(i, a)| !matched[i] && matches!(a.as_action(), Some(a) if f(a)))
+            };
+
+            // seen in both, so the event confirms the trade
+            if let Some(i) = find(&inspection.actions, &matched, &|a| a.trade() == Some(trade)) {
+                if let Classification::Known(ref mut inner) = inspection.actions[i] {
+                    // e.g. a trade decoded from calldata stays decoded from it
+                    inner.provenance = inner.provenance.min(Provenance::EventDecoded);
+                }
+                matched[i] = true;
+                continue;
+            }
+            // the traces saw the transfers but not the pool, e.g. an unknown fork
+            let t1 = find(&inspection.actions, &matched, &|a| {
+                a.transfer() == Some(&trade.t1)
+            });
+            let t2 = find(&inspection.actions, &matched, &|a| {
+                a.transfer() == Some(&trade.t2)
+            });
+            if let (Some(t1), Some(t2)) = (t1, t2) {
+                let trace_address = inspection.actions[t1].trace_address();
+                inspection.actions[t1] = Classification::new(trade.clone(), trace_address)
+                    .with_provenance(Provenance::EventDecoded);
+                inspection.actions[t2] = Classification::Prune;
+                matched[t1] = true;
+                matched[t2] = true;
+                inspection.protocols.insert(Protocol::Uniswappy);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        inspectors::{BatchInspector, UniswapV2, ERC20},
+        test_helpers::*,
+    };
+    use ethers::{
+        abi::Token,
+        types::{Bytes, Transaction, TransactionReceipt},
+    };
+
+    fn words(amounts: &[u64]) -> Vec<u8> {
+        abi::encode(
+            &amounts
+                .iter()
+                .map(|a| Token::Uint((*a).into()))
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    fn swap_log(pair: Address, sender: Address, to: Address, amounts: &[u64]) -> Log {
+        mk_log(
+            pair,
+            vec![EventLogs::new().swap_v2, H256::from(sender), H256::from(to)],
+            words(amounts),
+        )
+    }
+
+    #[test]
+    fn swap_from_logs() {
+        let addrs = addrs();
+        let (usr, pair, router, weth, dai) = (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4]);
+
+        let mut inspection = mk_inspection(vec![]);
+        inspection.logs = vec![
+            transfer_log(weth, usr, pair, 100),
+            transfer_log(dai, pair, usr, 3000),
+            // Sync
+            mk_log(pair, vec![H256::zero()], words(&[1, 2])),
+            swap_log(pair, router, usr, &[0, 100, 3000, 0]),
+            // an unrelated transfer
+            transfer_log(dai, usr, router, 5),
+        ];
+        EventLogs::new().inspect(&mut inspection);
+
+        let known = inspection.known();
+        assert_eq!(known.len(), 2);
+        let trade = known[0].as_ref().trade().unwrap();
+        assert_eq!(
+            (trade.t1.from, trade.t1.to, trade.t1.token),
+            (usr, pair, weth)
+        );
+        assert_eq!((trade.t2.to, trade.t2.amount), (usr, 3000.into()));
+        assert_eq!(known[1].as_ref().transfer().unwrap().amount, 5.into());
+    }
+
+    #[test]
+    fn v3_swap_and_v2_liquidity_from_logs() {
+        let addrs = addrs();
+        let (usr, pool, pair, weth, dai) = (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4]);
+
+        // the pool pays out before being paid in its callback
+        let amounts = [
+            Token::Int(I256::from(-3000).into_raw()),
+            Token::Int(100.into()),
+            Token::Uint(0.into()),
+            Token::Uint(0.into()),
+            Token::Int(0.into()),
+        ];
+        let mut inspection = mk_inspection(vec![]);
+        inspection.logs = vec![
+            transfer_log(dai, pool, usr, 3000),
+            transfer_log(weth, usr, pool, 100),
+            mk_log(
+                pool,
+                vec![EventLogs::new().swap_v3, H256::from(usr), H256::from(usr)],
+                abi::encode(&amounts),
+            ),
+            transfer_log(dai, usr, pair, 3000),
+            transfer_log(weth, usr, pair, 100),
+            // the LP tokens
+            transfer_log(pair, Address::zero(), usr, 10),
+            mk_log(
+                pair,
+                vec![EventLogs::new().mint, H256::from(usr)],
+                words(&[100, 3000]),
+            ),
+        ];
+        EventLogs::new().inspect(&mut inspection);
+
+        let known = inspection.known();
+        assert_eq!(known.len(), 3);
+        let trade = known[0].as_ref().trade().unwrap();
+        assert_eq!((trade.t1.token, trade.t1.amount), (weth, 100.into()));
+        assert_eq!((trade.t2.token, trade.t2.amount), (dai, 3000.into()));
+        assert_eq!(
+            known[1].as_ref().add_liquidity(),
+            Some(&AddLiquidity {
+                tokens: vec![weth, dai],
+                amounts: vec![100.into(), 3000.into()],
+                position: None,
+            })
+        );
+        assert_eq!(known[2].as_ref().transfer().unwrap().token, pair);
+    }
+
+    #[test]
+    fn inspects_receipts_without_traces() {
+        let addrs = addrs();
+        let (usr, pair, router, weth, dai) = (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4]);
+
+        let tx = Transaction {
+            from: usr,
+            to: Some(router),
+            ..Default::default()
+        };
+        let receipt = TransactionReceipt {
+            status: Some(1.into()),
+            logs: vec![
+                transfer_log(weth, usr, pair, 100),
+                transfer_log(dai, pair, usr, 3000),
+                swap_log(pair, router, usr, &[100, 0, 0, 3000]),
+            ],
+            ..Default::default()
+        };
+        // a tx without any logs is skipped
+        let empty = (tx.clone(), TransactionReceipt::default());
+
+        let inspector = BatchInspector::new(
+            vec![
+                Box::new(ERC20::new()),
+                Box::new(UniswapV2::new()),
+                Box::new(EventLogs::new()),
+            ],
+            vec![],
+        );
+        let inspections = inspector.inspect_receipts(vec![(&tx, &receipt), (&empty.0, &empty.1)]);
+
+        assert_eq!(inspections.len(), 1);
+        assert_eq!(
+            (inspections[0].from, inspections[0].contract),
+            (usr, router)
+        );
+        assert!(inspections[0].known()[0].as_ref().trade().is_some());
+    }
+
+    #[test]
+    fn merges_with_the_traces() {
+        let addrs = addrs();
+        let (usr, pair, fork, weth, dai) = (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4]);
+
+        let pair_swap = |amount1: u64, to: Address| {
+            calldata(
+                "swap(uint256,uint256,address,bytes)",
+                &[
+                    Token::Uint(0.into()),
+                    Token::Uint(amount1.into()),
+                    Token::Address(to),
+                    Token::Bytes(vec![]),
+                ],
+            )
+        };
+        // the same swap on a known pair and on a fork which the traces miss
+        let mut inspection = mk_inspection(vec![
+            mk_call(usr, weth, transfer(pair, 100.into()), vec![0]),
+            mk_call(usr, pair, pair_swap(3000, usr), vec![1]),
+            mk_call(pair, dai, transfer(usr, 3000.into()), vec![1, 0]),
+            mk_call(usr, weth, transfer(fork, 100.into()), vec![2]),
+            mk_call(usr, fork, Bytes::default(), vec![3]),
+            mk_call(fork, dai, transfer(usr, 3000.into()), vec![3, 0]),
+        ]);
+        inspection.logs = vec![
+            transfer_log(weth, usr, pair, 100),
+            transfer_log(dai, pair, usr, 3000),
+            swap_log(pair, usr, usr, &[100, 0, 0, 3000]),
+            transfer_log(weth, usr, fork, 100),
+            transfer_log(dai, fork, usr, 3000),
+            swap_log(fork, usr, usr, &[100, 0, 0, 3000]),
+        ];
+        ERC20::new().inspect(&mut inspection);
+        UniswapV2::new().inspect(&mut inspection);
+        EventLogs::new().inspect(&mut inspection);
+        inspection.prune();
+
+        let trades = inspection
+            .known()
+            .iter()
+            .filter_map(|a| a.as_ref().trade().cloned())
+            .map(|t| (t.t1.to, t.t2.from))
+            .collect::<Vec<_>>();
+        assert_eq!(trades, vec![(pair, pair), (fork, fork)]);
+        // the amounts of both trades come from their Swap events
+        assert!(inspection
+            .known()
+            .iter()
+            .filter(|a| a.as_ref().trade().is_some())
+            .all(|a| a.provenance == Provenance::EventDecoded));
+
+        // a trade decoded from the calldata, e.g. Balancer's, stays so
+        let trade = Trade::new(
+            Transfer::new(usr, pair, 100.into(), weth),
+            Transfer::new(pair, usr, 3000.into(), dai),
+        );
+        let mut inspection = mk_inspection(vec![Classification::new(trade, vec![0])]);
+        inspection.logs = vec![
+            transfer_log(weth, usr, pair, 100),
+            transfer_log(dai, pair, usr, 3000),
+            swap_log(pair, usr, usr, &[100, 0, 0, 3000]),
+        ];
+        EventLogs::new().inspect(&mut inspection);
+        inspection.prune();
+        let known = inspection.known();
+        assert_eq!(known.len(), 1);
+        assert_eq!(known[0].provenance, Provenance::CalldataDecoded);
+    }
+
+    #[test]
+    fn fee_on_transfer_swap_from_the_event() {
+        let addrs = addrs();
+        let (usr, pair, fot, dai, collector) = (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4]);
+
+        // a token which takes 2% of the amounts sent for its collector, so
+        // the user only received 2881 of the 2940 the pair sent
+        let traces = vec![
+            mk_call(usr, dai, transfer(pair, 100.into()), vec![0]),
+            mk_call(
+                usr,
+                pair,
+                calldata(
+                    "swap(uint256,uint256,address,bytes)",
+                    &[
+                        Token::Uint(0.into()),
+                        Token::Uint(2940.into()),
+                        Token::Address(usr),
+                        Token::Bytes(vec![]),
+                    ],
+                ),
+                vec![1],
+            ),
+            mk_call(pair, fot, transfer(usr, 2940.into()), vec![1, 0]),
+        ];
+        let logs = vec![
+            transfer_log(dai, usr, pair, 100),
+            transfer_log(fot, pair, usr, 2881),
+            transfer_log(fot, pair, collector, 59),
+            swap_log(pair, usr, usr, &[100, 0, 0, 2940]),
+        ];
+        let trades = |inspection: &Inspection| {
+            inspection
+                .known()
+                .iter()
+                .filter_map(|a| a.as_ref().trade().cloned())
+                .map(|t| (t.t1.token, t.t1.amount, t.t2.token, t.t2.amount))
+                .collect::<Vec<_>>()
+        };
+        let expected = vec![(dai, 100.into(), fot, 2881.into())];
+
+        // the traces are credited with the logged amounts, whether or not the
+        // `Swap`s are trusted
+        for events in [EventLogs::new(), EventLogs::new().with_swap_events()].iter() {
+            let mut inspection = mk_inspection(traces.clone());
+            inspection.logs = logs.clone();
+            ERC20::new().inspect(&mut inspection);
+            UniswapV2::new().inspect(&mut inspection);
+            events.inspect(&mut inspection);
+            inspection.prune();
+            assert_eq!(trades(&inspection), expected);
+            assert_eq!(inspection.known()[0].trace_address[..], [1]);
+        }
+
+        // without the traces, only the `Swap` pairs the transfers of the logs
+        // into a trade, since they do not add up to its amounts
+        let mut inspection = mk_inspection(vec![]);
+        inspection.logs = logs.clone();
+        EventLogs::new().inspect(&mut inspection);
+        assert!(trades(&inspection).is_empty());
+        let mut inspection = mk_inspection(vec![]);
+        inspection.logs = logs;
+        EventLogs::new().with_swap_events().inspect(&mut inspection);
+        assert_eq!(trades(&inspection), expected);
+    }
+
+    #[test]
+    fn erc1155_transfers_from_logs() {
+        let addrs = addrs();
+        let (operator, alice, bob, items) = (addrs[0], addrs[1], addrs[2], addrs[3]);
+        let topics = |event: &str| {
+            vec![
+                H256::from(keccak256(event)),
+                H256::from(operator),
+                H256::from(alice),
+                H256::from(bob),
+            ]
+        };
+        let batch = abi::encode(&[
+            Token::Array(vec![Token::Uint(2.into()), Token::Uint(3.into())]),
+            Token::Array(vec![Token::Uint(20.into()), Token::Uint(30.into())]),
+        ]);
+
+        let mut inspection = mk_inspection(vec![]);
+        inspection.logs = vec![
+            mk_log(
+                items,
+                topics("TransferSingle(address,address,address,uint256,uint256)"),
+                words(&[1, 10]),
+            ),
+            mk_log(
+                items,
+                topics("TransferBatch(address,address,address,uint256[],uint256[])"),
+                batch,
+            ),
+            // an ERC721 transfer, which indexes its token id
+            erc721_transfer_log(items, alice, bob, 4.into()),
+        ];
+        EventLogs::new().inspect(&mut inspection);
+
+        let transfers = inspection
+            .known()
+            .iter()
+            .map(|action| {
+                let transfer = action.as_ref().nft_transfer().unwrap();
+                (transfer.token_id.as_u64(), transfer.amount.as_u64())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(transfers, vec![(1, 10), (2, 20), (3, 30)]);
+    }
+}
@@ -0,0 +1,271 @@
+use crate::{
+    addresses::LOOKSRARE,
+    inspectors::{
+        marketplace::{self, into_tuple, Sale},
+        ERC20,
+    },
+    traits::Inspector,
+    types::{Inspection, Protocol},
+};
+
+use ethers::{
+    abi::{Abi, Token},
+    contract::BaseContract,
+    types::Bytes,
+};
+use once_cell::sync::Lazy;
+
+static LOOKSRARE_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    serde_json::from_str::<Abi>(include_str!("../../abi/looksrare.json"))
+        .expect("could not parse looksrare abi")
+        .into()
+});
+
+// The taker's order and the maker's order which it matches
+type MatchOrders = (Token, Token);
+
+#[derive(Debug, Clone)]
+/// An inspector for the LooksRare exchange.
+///
+/// A taker either buys a listing with `matchAskWithTakerBid`, or sells to a bid
+/// with `matchBidWithTakerAsk`. The NFT is classified at the price of the
+/// taker's order, which the exchange splits between the seller, the protocol's
+/// fee recipient and the creator's royalty recipient. The listings bought with
+/// ETH are paid in WETH, which the exchange wraps first. It must run after
+/// `ERC721`, but before `ERC20` so that the payments are not classified as
+/// transfers.
+pub struct LooksRare {
+    looksrare: &'static BaseContract,
+    erc20: ERC20,
+}
+
+impl Default for LooksRare {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LooksRare {
+    /// Constructor
+    pub fn new() -> Self {
+        Self {
+            looksrare: &LOOKSRARE_ABI,
+            erc20: ERC20::new(),
+        }
+    }
+
+    fn decode(&self, input: &Bytes) -> Option<Sale> {
+        let (taker_bids, (taker, maker)) = if let Ok(orders) = self
+            .looksrare
+            .decode::<MatchOrders, _>("matchAskWithTakerBid", input)
+        {
+            (true, orders)
+        } else if let Ok(orders) = self
+            .looksrare
+            .decode::<MatchOrders, _>("matchAskWithTakerBidUsingETHAndWETH", input)
+        {
+            (true, orders)
+        } else if let Ok(orders) = self
+            .looksrare
+            .decode::<MatchOrders, _>("matchBidWithTakerAsk", input)
+        {
+            (false, orders)
+        } else {
+            return None;
+        };
+
+        // the price and the token id are the taker's, since the maker's order
+        // may be for any token of the collection
+        let (taker, maker) = (into_tuple(taker)?, into_tuple(maker)?);
+        let taker_address = taker.get(1)?.clone().into_address()?;
+        let price = taker.get(2)?.clone().into_uint()?;
+        let token_id = taker.get(3)?.clone().into_uint()?;
+        let signer = maker.get(1)?.clone().into_address()?;
+        let collection = maker.get(2)?.clone().into_address()?;
+        let payment_token = maker.get(7)?.clone().into_address()?;
+
+        let (buyer, seller) = if taker_bids {
+            (taker_address, signer)
+        } else {
+            (signer, taker_address)
+        };
+        Some(Sale {
+            nfts: vec![(collection, token_id)],
+            buyer,
+            seller,
+            payment_token,
+            price,
+        })
+    }
+}
+
+impl Inspector for LooksRare {
+    fn name(&self) -> &'static str {
+        "looksrare"
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        for i in 0..inspection.actions.len() {
+            let sale = match inspection.actions[i].as_call() {
+                Some(calltrace) if calltrace.as_ref().to == *LOOKSRARE => {
+                    match self.decode(&calltrace.as_ref().input) {
+                        Some(inner) => inner,
+                        None => continue,
+                    }
+                }
+                _ => continue,
+            };
+            marketplace::settle(&self.erc20, inspection, i, &[sale], Protocol::LooksRare);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        addresses::WETH,
+        inspectors::ERC721,
+        test_helpers::*,
+        types::{actions::NftTrade, Classification},
+    };
+    use ethers::types::{Address, U256};
+
+    const TAKER: &str = "(bool,address,uint256,uint256,uint256,bytes)";
+    const MAKER: &str = "(bool,address,address,uint256,uint256,uint256,address,address,uint256,uint256,uint256,uint256,bytes,uint8,bytes32,bytes32)";
+
+    fn inspect(inspection: &mut Inspection) {
+        ERC721::new().inspect(inspection);
+        LooksRare::new().inspect(inspection);
+        ERC20::new().inspect(inspection);
+        inspection.prune();
+    }
+
+    fn taker(ask: bool, taker: Address, price: u64, id: u64) -> Token {
+        Token::Tuple(vec![
+            Token::Bool(ask),
+            Token::Address(taker),
+            Token::Uint(price.into()),
+            Token::Uint(id.into()),
+            Token::Uint(8500.into()),
+            Token::Bytes(vec![]),
+        ])
+    }
+
+    fn maker(ask: bool, signer: Address, collection: Address, price: u64, id: u64) -> Token {
+        Token::Tuple(vec![
+            Token::Bool(ask),
+            Token::Address(signer),
+            Token::Address(collection),
+            Token::Uint(price.into()),
+            Token::Uint(id.into()),
+            Token::Uint(1.into()),
+            Token::Address(Address::zero()),
+            Token::Address(*WETH),
+            Token::Uint(0.into()),
+            Token::Uint(0.into()),
+            Token::Uint(U256::MAX),
+            Token::Uint(8500.into()),
+            Token::Bytes(vec![]),
+            Token::Uint(27.into()),
+            Token::FixedBytes(vec![0; 32]),
+            Token::FixedBytes(vec![0; 32]),
+        ])
+    }
+
+    /// The calls of a sale of token 7, paid in WETH by the buyer with a
+    /// protocol fee and a royalty
+    fn sale(
+        input: Bytes,
+        (taker, buyer, seller): (Address, Address, Address),
+        collection: Address,
+    ) -> Vec<Classification> {
+        let addrs = addrs();
+        let (treasury, creator, transfer_manager) = (addrs[5], addrs[6], addrs[7]);
+        vec![
+            mk_call(taker, *LOOKSRARE, input, vec![]),
+            // the protocol fee, the royalty and the rest to the seller
+            mk_call(
+                *LOOKSRARE,
+                *WETH,
+                transfer_from(buyer, treasury, 20.into()),
+                vec![0],
+            ),
+            mk_call(
+                *LOOKSRARE,
+                *WETH,
+                transfer_from(buyer, creator, 50.into()),
+                vec![1],
+            ),
+            mk_call(
+                *LOOKSRARE,
+                *WETH,
+                transfer_from(buyer, seller, 930.into()),
+                vec![2],
+            ),
+            mk_call(*LOOKSRARE, transfer_manager, Bytes::default(), vec![3]),
+            mk_call(
+                transfer_manager,
+                collection,
+                transfer_from(seller, buyer, 7.into()),
+                vec![3, 0],
+            ),
+        ]
+    }
+
+    #[test]
+    fn buys_a_listing_with_fees() {
+        let addrs = addrs();
+        let (buyer, seller, collection) = (addrs[0], addrs[1], addrs[2]);
+        let input = calldata(
+            &format!("matchAskWithTakerBid({},{})", TAKER, MAKER),
+            &[
+                taker(false, buyer, 1000, 7),
+                maker(true, seller, collection, 1000, 7),
+            ],
+        );
+        let mut inspection = mk_inspection(sale(input, (buyer, buyer, seller), collection));
+        inspection.logs = vec![erc721_transfer_log(collection, seller, buyer, 7.into())];
+        inspect(&mut inspection);
+
+        let known = inspection.known();
+        assert_eq!(known.len(), 1);
+        assert_eq!(
+            known[0].as_ref().nft_trade(),
+            Some(&NftTrade {
+                collection,
+                token_id: 7.into(),
+                payment_token: *WETH,
+                price: 1000.into(),
+                buyer,
+                seller,
+            })
+        );
+        // the transfer manager's call is left unknown
+        assert_eq!(inspection.unknown().len(), 1);
+        assert_eq!(inspection.protocols, crate::set![Protocol::LooksRare]);
+    }
+
+    #[test]
+    fn sells_to_a_bid() {
+        let addrs = addrs();
+        let (bidder, seller, collection) = (addrs[0], addrs[1], addrs[2]);
+        let input = calldata(
+            &format!("matchBidWithTakerAsk({},{})", TAKER, MAKER),
+            &[
+                taker(true, seller, 1000, 7),
+                maker(false, bidder, collection, 1000, 0),
+            ],
+        );
+        let mut inspection = mk_inspection(sale(input, (seller, bidder, seller), collection));
+        inspection.logs = vec![erc721_transfer_log(collection, seller, bidder, 7.into())];
+        inspect(&mut inspection);
+
+        let known = inspection.known();
+        assert_eq!(known.len(), 1);
+        let trade = known[0].as_ref().nft_trade().unwrap();
+        // the token id is the taker's, since the bid is for the collection
+        assert_eq!((trade.token_id, trade.price), (7.into(), 1000.into()));
+        assert_eq!((trade.buyer, trade.seller), (bidder, seller));
+    }
+}
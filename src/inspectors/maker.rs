@@ -0,0 +1,512 @@
+use crate::{
+    addresses::{parse_address, WETH},
+    is_nested,
+    types::{
+        actions::{Auction, Liquidation},
+        classification::CallTrace,
+        Classification, Inspection, Protocol, Status,
+    },
+    Inspector,
+};
+use ethers::{
+    abi::parse_abi,
+    contract::BaseContract,
+    types::{Address, Bytes, Call as TraceCall, Log, H256, U256},
+    utils::keccak256,
+};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+
+static CAT_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    parse_abi(&["function bite(bytes32, address) returns (uint256)"])
+        .expect("could not parse cat abi")
+        .into()
+});
+
+static FLIP_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    parse_abi(&["function kick(address, address, uint256, uint256, uint256) returns (uint256)"])
+        .expect("could not parse flip abi")
+        .into()
+});
+
+static DOG_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    parse_abi(&["function bark(bytes32, address, address) returns (uint256)"])
+        .expect("could not parse dog abi")
+        .into()
+});
+
+static CLIP_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    parse_abi(&[
+        "function kick(uint256, uint256, address, address) returns (uint256)",
+        "function take(uint256, uint256, uint256, address, bytes)",
+    ])
+    .expect("could not parse clip abi")
+    .into()
+});
+
+static VAT_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    parse_abi(&[
+        "function flux(bytes32, address, address, uint256)",
+        "function move(address, address, uint256)",
+    ])
+    .expect("could not parse vat abi")
+    .into()
+});
+
+/// `Take(uint256 indexed id, uint256 max, uint256 price, uint256 owe, uint256 tab, uint256 lot, address indexed usr)`
+static TAKE_EVENT: Lazy<H256> = Lazy::new(|| {
+    H256::from(keccak256(
+        "Take(uint256,uint256,uint256,uint256,uint256,uint256,address)",
+    ))
+});
+
+static DAI: Lazy<Address> = Lazy::new(|| parse_address("6B175474E89094C44Da98b954EedeAC495271d0F"));
+
+/// The DAI balances of the Vat are `rad`s, which have 45 decimals instead of 18
+static RAY: Lazy<U256> = Lazy::new(|| U256::exp10(27));
+
+/// The tokens of the collateral types onboarded to the Vat, which Maker only
+/// refers to by their `bytes32` ilk names
+static ILKS: Lazy<HashMap<String, Address>> = Lazy::new(|| {
+    let tokens = [
+        ("BAT-A", "0D8775F648430679A709E98d2b0Cb6250d2887EF"),
+        ("USDC-A", "A0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48"),
+        ("USDC-B", "A0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48"),
+        ("WBTC-A", "2260FAC5E5542a773Aa44fBCfeDf7C193bc2C599"),
+        ("WBTC-B", "2260FAC5E5542a773Aa44fBCfeDf7C193bc2C599"),
+        ("WBTC-C", "2260FAC5E5542a773Aa44fBCfeDf7C193bc2C599"),
+        ("TUSD-A", "0000000000085d4780B73119b644AE5ecd22b376"),
+        ("ZRX-A", "E41d2489571d322189246DaFA5ebDe1F4699F498"),
+        ("KNC-A", "dd974D5C2e2928deA5F71b9825b8b646686BD200"),
+        ("MANA-A", "0F5D2fB29fb7d3CFeE444a200298f468908cC942"),
+        ("USDT-A", "dAC17F958D2ee523a2206206994597C13D831ec7"),
+        ("PAXUSD-A", "8E870D67F660D95d5be530380D0eC0bd388289E1"),
+        ("COMP-A", "c00e94Cb662C3520282E6f5717214004A7f26888"),
+        ("LRC-A", "BBbbCA6A901c926F240b89EacB641d8Aec7AEafD"),
+        ("LINK-A", "514910771AF9Ca656af840dff83E8264EcF986CA"),
+        ("BAL-A", "ba100000625a3754423978a60c9317c58a424e3D"),
+        ("YFI-A", "0bc529c00C6401aEF6D220BE8C6Ea1667F6Ad93e"),
+        ("GUSD-A", "056Fd409E1d7A124BD7017459dFEa2F387b6d5Cd"),
+        ("UNI-A", "1f9840a85d5aF5bf1D1762F925BDADdC4201F984"),
+        ("RENBTC-A", "EB4C2781e4ebA804CE9a9803C67d0893436bB27D"),
+        ("AAVE-A", "7Fc66500c84A76Ad7e9c93437bFc5Ac33E2DDaE9"),
+        ("MATIC-A", "7D1AfA7B718fb893dB30A3aBc0Cfc608AaCfeBB0"),
+        ("WSTETH-A", "7f39C581F595B53c5cb19bD0b3f8dA6c935E2Ca0"),
+        ("WSTETH-B", "7f39C581F595B53c5cb19bD0b3f8dA6c935E2Ca0"),
+        ("RETH-A", "ae78736Cd615f374D3085123A210448E74Fc6393"),
+        ("GNO-A", "6810e776880C02933D47DB1b9fc05908e5386b96"),
+    ];
+    let mut ilks: HashMap<_, _> = tokens
+        .iter()
+        .map(|(ilk, token)| (ilk.to_string(), parse_address(token)))
+        .collect();
+    for ilk in ["ETH-A", "ETH-B", "ETH-C"].iter() {
+        ilks.insert(ilk.to_string(), *WETH);
+    }
+    ilks
+});
+
+type Bite = ([u8; 32], Address);
+type FlipKick = (Address, Address, U256, U256, U256);
+type Bark = ([u8; 32], Address, Address);
+type ClipKick = (U256, U256, Address, Address);
+type Take = (U256, U256, U256, Address, Bytes);
+type Flux = ([u8; 32], Address, Address, U256);
+type Move = (Address, Address, U256);
+
+#[derive(Debug, Clone)]
+/// An inspector for Maker liquidations.
+///
+/// Liquidating a vault, i.e. the legacy `Cat.bite` or Liquidation 2.0's
+/// `Dog.bark`, kicks off an auction of its collateral. Since nothing has been
+/// sold yet, these liquidations have no amounts. Liquidation 2.0's Clipper
+/// auctions are then taken in parts, each `Clipper.take` being a liquidation
+/// of the DAI paid for the collateral bought by its caller. The vault of a take
+/// is only known from its logs, or from a bark in the same block after the
+/// `MakerAuctionReducer` aggregated the block's takes.
+pub struct MakerDAO {
+    cat: &'static BaseContract,
+    flip: &'static BaseContract,
+    dog: &'static BaseContract,
+    clip: &'static BaseContract,
+    vat: &'static BaseContract,
+    ilks: HashMap<String, Address>,
+}
+
+impl Inspector for MakerDAO {
+    fn name(&self) -> &'static str {
+        "maker"
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        let mut prune = Vec::new();
+        for i in 0..inspection.actions.len() {
+            let calltrace = match inspection.actions[i].as_call() {
+                Some(calltrace) => calltrace,
+                None => continue,
+            };
+            let call = calltrace.as_ref();
+            // the indices of the calls made by this one, along with them
+            let subcalls = inspection
+                .actions
+                .iter()
+                .enumerate()
+                .skip(i + 1)
+                .filter(|(_, action)| !matches!(action, Classification::Prune))
+                .take_while(|(_, action)| {
+                    is_nested(&calltrace.trace_address, action.trace_address())
+                })
+                .filter_map(|(j, action)| Some((j, action.as_call()?)))
+                .collect::<Vec<_>>();
+
+            let found = if let Ok((ilk, urn)) = self.cat.decode::<Bite, _>("bite", &call.input) {
+                self.kick(calltrace, ilk, urn, &subcalls, |kick| {
+                    self.flip.decode::<FlipKick, _>("kick", &kick.input).is_ok()
+                })
+            } else if let Ok((ilk, urn, _)) = self.dog.decode::<Bark, _>("bark", &call.input) {
+                self.kick(calltrace, ilk, urn, &subcalls, |kick| {
+                    self.clip.decode::<ClipKick, _>("kick", &kick.input).is_ok()
+                })
+            } else if let Ok((id, _, _, who, _)) = self.clip.decode::<Take, _>("take", &call.input)
+            {
+                self.take(call, id, who, &subcalls, &inspection.logs)
+            } else {
+                None
+            };
+
+            if let Some((liquidation, consumed)) = found {
+                let trace_address = calltrace.trace_address.clone();
+                inspection.actions[i] = Classification::new(liquidation, trace_address);
+                prune.extend(consumed);
+                inspection.protocols.insert(Protocol::MakerDAO);
+                if inspection.status != Status::Reverted {
+                    inspection.status = Status::Success;
+                }
+            }
+        }
+
+        for i in prune {
+            inspection.actions[i] = Classification::Prune;
+        }
+    }
+}
+
+impl Default for MakerDAO {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MakerDAO {
+    /// Constructor
+    pub fn new() -> Self {
+        Self {
+            cat: &CAT_ABI,
+            flip: &FLIP_ABI,
+            dog: &DOG_ABI,
+            clip: &CLIP_ABI,
+            vat: &VAT_ABI,
+            ilks: ILKS.clone(),
+        }
+    }
+
+    /// Sets the token of the collateral type `ilk`, e.g. for ilks which were
+    /// onboarded after this crate was released
+    pub fn with_ilk(mut self, ilk: &str, token: Address) -> Self {
+        self.ilks.insert(ilk.to_owned(), token);
+        self
+    }
+
+    /// Returns the name and the token of an ilk, if it is known
+    fn ilk(&self, ilk: &[u8; 32]) -> Option<(String, Address)> {
+        let len = ilk.iter().position(|b| *b == 0).unwrap_or(32);
+        let name = std::str::from_utf8(&ilk[..len]).ok()?;
+        let token = self.ilks.get(name)?;
+        Some((name.to_owned(), *token))
+    }
+
+    /// Parses a bite or a bark, which kick off an auction in one of their
+    /// subcalls. The auction's id is the output of the kick.
+    fn kick<F: Fn(&TraceCall) -> bool>(
+        &self,
+        calltrace: &CallTrace,
+        ilk: [u8; 32],
+        urn: Address,
+        subcalls: &[(usize, &CallTrace)],
+        is_kick: F,
+    ) -> Option<(Liquidation, Vec<usize>)> {
+        let call = calltrace.as_ref();
+        let (ilk, token) = self.ilk(&ilk)?;
+        let (j, kick) = subcalls
+            .iter()
+            .find(|(_, subcall)| is_kick(subcall.as_ref()))?;
+        // the id is returned by both the kick and its caller, but the outputs
+        // of calls are not known for all nodes
+        let id = [&kick.output, &calltrace.output]
+            .iter()
+            .find(|output| output.as_ref().len() >= 32)
+            .map(|output| U256::from_big_endian(&output.as_ref()[..32]))
+            .unwrap_or_default();
+        let kick = kick.as_ref();
+        Some((
+            Liquidation {
+                sent_token: *DAI,
+                sent_amount: U256::zero(),
+                received_token: token,
+                received_amount: U256::zero(),
+                from: call.from,
+                liquidated_user: urn,
+                auction: Some(Auction {
+                    house: kick.to,
+                    id,
+                    ilk,
+                }),
+            },
+            vec![*j],
+        ))
+    }
+
+    /// Parses a take of auction `id`, in which the caller pays DAI in the Vat
+    /// for collateral sent to `who`
+    fn take(
+        &self,
+        call: &TraceCall,
+        id: U256,
+        who: Address,
+        subcalls: &[(usize, &CallTrace)],
+        logs: &[Log],
+    ) -> Option<(Liquidation, Vec<usize>)> {
+        let (flux, ilk, slice) = subcalls.iter().find_map(|(j, subcall)| {
+            let (ilk, src, dst, wad) = self
+                .vat
+                .decode::<Flux, _>("flux", &subcall.call.input)
+                .ok()?;
+            if src == call.to && dst == who {
+                Some((*j, ilk, wad))
+            } else {
+                None
+            }
+        })?;
+        let (ilk, token) = self.ilk(&ilk)?;
+        let (movement, owe) = subcalls.iter().find_map(|(j, subcall)| {
+            let (src, _, rad) = self
+                .vat
+                .decode::<Move, _>("move", &subcall.call.input)
+                .ok()?;
+            if src == call.from {
+                Some((*j, rad))
+            } else {
+                None
+            }
+        })?;
+
+        let mut topic = [0; 32];
+        id.to_big_endian(&mut topic);
+        let urn = logs
+            .iter()
+            .find(|log| {
+                log.address == call.to
+                    && log.topics.len() == 3
+                    && log.topics[0] == *TAKE_EVENT
+                    && log.topics[1] == H256::from(topic)
+            })
+            .map(|log| Address::from(log.topics[2]))
+            .unwrap_or_else(Address::zero);
+
+        Some((
+            Liquidation {
+                sent_token: *DAI,
+                sent_amount: owe / *RAY,
+                received_token: token,
+                received_amount: slice,
+                from: call.from,
+                liquidated_user: urn,
+                auction: Some(Auction {
+                    house: call.to,
+                    id,
+                    ilk,
+                }),
+            },
+            vec![flux, movement],
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+    use ethers::abi::Token;
+
+    fn ilk(name: &str) -> Token {
+        let mut ilk = [0; 32];
+        ilk[..name.len()].copy_from_slice(name.as_bytes());
+        Token::FixedBytes(ilk.to_vec())
+    }
+
+    fn bark(dog: Address, clip: Address, keeper: Address, urn: Address, name: &str) -> Inspection {
+        mk_inspection(vec![
+            mk_call(
+                keeper,
+                dog,
+                calldata(
+                    "bark(bytes32,address,address)",
+                    &[ilk(name), Token::Address(urn), Token::Address(keeper)],
+                ),
+                vec![],
+            ),
+            with_output(
+                mk_call(
+                    dog,
+                    clip,
+                    calldata(
+                        "kick(uint256,uint256,address,address)",
+                        &[
+                            Token::Uint(100.into()),
+                            Token::Uint(10.into()),
+                            Token::Address(urn),
+                            Token::Address(keeper),
+                        ],
+                    ),
+                    vec![0],
+                ),
+                &[Token::Uint(42.into())],
+            ),
+        ])
+    }
+
+    fn liquidation(inspection: &Inspection) -> Liquidation {
+        inspection.liquidations().next().cloned().unwrap()
+    }
+
+    #[test]
+    fn bark_kicks_off_auction() {
+        let addrs = addrs();
+        let (dog, clip, keeper, urn) = (addrs[0], addrs[1], addrs[2], addrs[3]);
+        let mut inspection = bark(dog, clip, keeper, urn, "ETH-A");
+        MakerDAO::new().inspect(&mut inspection);
+
+        let liquidation = liquidation(&inspection);
+        assert_eq!(liquidation.from, keeper);
+        assert_eq!(liquidation.liquidated_user, urn);
+        assert_eq!(liquidation.received_token, *WETH);
+        assert!(liquidation.sent_amount.is_zero());
+        assert!(liquidation.received_amount.is_zero());
+        assert_eq!(
+            liquidation.auction,
+            Some(Auction {
+                house: clip,
+                id: 42.into(),
+                ilk: "ETH-A".to_owned()
+            })
+        );
+        assert_eq!(inspection.actions[1], Classification::Prune);
+        assert_eq!(inspection.protocols, crate::set![Protocol::MakerDAO]);
+    }
+
+    #[test]
+    fn unknown_ilks_are_not_classified() {
+        let addrs = addrs();
+        let (dog, clip, keeper, urn) = (addrs[0], addrs[1], addrs[2], addrs[3]);
+        let mut inspection = bark(dog, clip, keeper, urn, "FOO-A");
+        MakerDAO::new().inspect(&mut inspection);
+        assert_eq!(inspection.liquidations().count(), 0);
+
+        let token = addrs[4];
+        let mut inspection = bark(dog, clip, keeper, urn, "FOO-A");
+        MakerDAO::new()
+            .with_ilk("FOO-A", token)
+            .inspect(&mut inspection);
+        assert_eq!(liquidation(&inspection).received_token, token);
+
+        // only onboarded collateral types are known, e.g. there never was a BAT-C
+        let mut inspection = bark(dog, clip, keeper, urn, "BAT-C");
+        MakerDAO::new().inspect(&mut inspection);
+        assert_eq!(inspection.liquidations().count(), 0);
+        let mut inspection = bark(dog, clip, keeper, urn, "WSTETH-B");
+        MakerDAO::new().inspect(&mut inspection);
+        assert_eq!(
+            liquidation(&inspection).auction.unwrap().ilk,
+            "WSTETH-B".to_owned()
+        );
+    }
+
+    #[test]
+    fn take_buys_collateral_for_dai() {
+        let addrs = addrs();
+        let (clip, vat, keeper, who, urn, vow) =
+            (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4], addrs[5]);
+        let wad = U256::exp10(18);
+        let id = U256::from(42);
+        let mut inspection = mk_inspection(vec![
+            mk_call(
+                keeper,
+                clip,
+                calldata(
+                    "take(uint256,uint256,uint256,address,bytes)",
+                    &[
+                        Token::Uint(id),
+                        Token::Uint(wad),
+                        Token::Uint(U256::MAX),
+                        Token::Address(who),
+                        Token::Bytes(vec![]),
+                    ],
+                ),
+                vec![],
+            ),
+            mk_call(
+                clip,
+                vat,
+                calldata(
+                    "flux(bytes32,address,address,uint256)",
+                    &[
+                        ilk("WBTC-A"),
+                        Token::Address(clip),
+                        Token::Address(who),
+                        Token::Uint(wad),
+                    ],
+                ),
+                vec![0],
+            ),
+            mk_call(
+                clip,
+                vat,
+                calldata(
+                    "move(address,address,uint256)",
+                    &[
+                        Token::Address(keeper),
+                        Token::Address(vow),
+                        Token::Uint(wad * 3000 * *RAY),
+                    ],
+                ),
+                vec![1],
+            ),
+        ]);
+        // without the logs the vault is not known
+        let mut without_logs = inspection.clone();
+        MakerDAO::new().inspect(&mut without_logs);
+        assert_eq!(liquidation(&without_logs).liquidated_user, Address::zero());
+
+        let mut topic = [0; 32];
+        id.to_big_endian(&mut topic);
+        inspection.logs.push(mk_log(
+            clip,
+            vec![*TAKE_EVENT, H256::from(topic), H256::from(urn)],
+            vec![],
+        ));
+        MakerDAO::new().inspect(&mut inspection);
+
+        let liquidation = liquidation(&inspection);
+        assert_eq!(liquidation.sent_token, *DAI);
+        assert_eq!(liquidation.sent_amount, wad * 3000);
+        assert_eq!(
+            liquidation.received_token,
+            parse_address("2260FAC5E5542a773Aa44fBCfeDf7C193bc2C599")
+        );
+        assert_eq!(liquidation.received_amount, wad);
+        assert_eq!(liquidation.from, keeper);
+        assert_eq!(liquidation.liquidated_user, urn);
+        assert_eq!(inspection.actions[1], Classification::Prune);
+        assert_eq!(inspection.actions[2], Classification::Prune);
+    }
+}
@@ -0,0 +1,153 @@
+//! Settles the sales of the NFT marketplaces whose orders carry their price,
+//! e.g. LooksRare and X2Y2. The NFTs which the exchange transfers are
+//! classified as trades at the price of their order, and the payments it makes
+//! to the seller, the protocol's fee recipient and the creator's royalty
+//! recipient are the costs of the trade, which get pruned.
+use crate::{
+    inspectors::ERC20,
+    is_nested,
+    types::{
+        actions::{NftTrade, NftTransfer, SpecificAction},
+        Classification, Inspection, Protocol, TraceAddress,
+    },
+};
+use ethers::{
+    abi::Token,
+    types::{Address, U256},
+};
+
+/// A sale decoded from the calldata of a marketplace
+pub(crate) struct Sale {
+    /// The (collection, token id) of the NFTs sold, where a bundle has several
+    pub nfts: Vec<(Address, U256)>,
+    pub buyer: Address,
+    pub seller: Address,
+    /// ETH or the ERC20 which the NFTs are paid with
+    pub payment_token: Address,
+    /// The amount paid by the buyer, including the fees
+    pub price: U256,
+}
+
+impl Sale {
+    /// The price of the `k`th NFT of the sale. The price of a bundle is split
+    /// evenly between its NFTs, and the first one gets the remainder.
+    fn share(&self, k: usize) -> U256 {
+        let share = self.price / self.nfts.len();
+        if k == 0 {
+            self.price - share * (self.nfts.len() - 1)
+        } else {
+            share
+        }
+    }
+}
+
+pub(crate) fn into_tuple(token: Token) -> Option<Vec<Token>> {
+    match token {
+        Token::Tuple(inner) => Some(inner),
+        _ => None,
+    }
+}
+
+/// Classifies the sales of the exchange call at index `i`. Nothing is
+/// classified if none of the sales' NFTs got transferred.
+pub(crate) fn settle(
+    erc20: &ERC20,
+    inspection: &mut Inspection,
+    i: usize,
+    sales: &[Sale],
+    protocol: Protocol,
+) {
+    let actions = &inspection.actions;
+    let calltrace = match actions[i].as_call() {
+        Some(inner) => inner,
+        None => return,
+    };
+    let exchange = calltrace.as_ref().to;
+    let buyers = sales.iter().map(|sale| sale.buyer).collect::<Vec<_>>();
+
+    let mut trades: Vec<(usize, NftTrade)> = Vec::new();
+    let mut payments = Vec::new();
+    for (j, action) in actions
+        .iter()
+        .enumerate()
+        .skip(i + 1)
+        .filter(|(_, a)| is_nested(&calltrace.trace_address, a.trace_address()))
+    {
+        // the receiver of an NFT may do anything with it in its callback,
+        // which is not part of the sale
+        if trades
+            .iter()
+            .any(|(k, _)| is_nested(actions[*k].trace_address(), action.trace_address()))
+        {
+            continue;
+        }
+
+        match action {
+            Classification::Known(known) => {
+                if let Some(nft) = known.as_ref().nft_transfer() {
+                    if let Some(trade) = trade(sales, &trades, nft) {
+                        trades.push((j, trade));
+                    }
+                }
+            }
+            Classification::Unknown(calltrace) => {
+                // ETH is paid out by the exchange, while ERC20s are pulled
+                // from the buyer. Excess ETH is refunded to the buyer.
+                if let Some(SpecificAction::Transfer(transfer)) =
+                    erc20.try_parse(calltrace.as_ref())
+                {
+                    if sales
+                        .iter()
+                        .any(|sale| sale.payment_token == transfer.token)
+                        && (buyers.contains(&transfer.from) || transfer.from == exchange)
+                        && !buyers.contains(&transfer.to)
+                    {
+                        payments.push(j);
+                    }
+                }
+            }
+            Classification::Prune => (),
+        }
+    }
+
+    if trades.is_empty() {
+        return;
+    }
+    for (j, trade) in trades {
+        let trace_address = TraceAddress::from(inspection.actions[j].trace_address());
+        inspection.actions[j] = Classification::new(trade, trace_address);
+    }
+    for j in payments {
+        inspection.actions[j] = Classification::Prune;
+    }
+    inspection.actions[i] = Classification::Prune;
+    inspection.protocols.insert(protocol);
+}
+
+/// The trade of the NFT, if one of the sales sold it and it was not already
+/// traded
+fn trade(sales: &[Sale], trades: &[(usize, NftTrade)], nft: &NftTransfer) -> Option<NftTrade> {
+    sales.iter().find_map(|sale| {
+        if nft.from != sale.seller {
+            return None;
+        }
+        let k = sale
+            .nfts
+            .iter()
+            .position(|id| *id == (nft.collection, nft.token_id))?;
+        let traded = trades
+            .iter()
+            .any(|(_, t)| (t.collection, t.token_id) == (nft.collection, nft.token_id));
+        if traded {
+            return None;
+        }
+        Some(NftTrade {
+            collection: nft.collection,
+            token_id: nft.token_id,
+            payment_token: sale.payment_token,
+            price: sale.share(k),
+            buyer: nft.to,
+            seller: nft.from,
+        })
+    })
+}
@@ -5,36 +5,149 @@
 //! specific logic needs to be written.
 
 mod uniswap;
-/// A Uniswap inspector
-pub use uniswap::Uniswap;
+/// A Uniswap V2 inspector
+pub use uniswap::UniswapV2;
+
+mod uniswapv3;
+/// A Uniswap V3 inspector
+pub use uniswapv3::UniswapV3;
+
+mod uniswapv3_positions;
+/// A Uniswap V3 liquidity positions inspector
+pub use uniswapv3_positions::UniswapV3Positions;
+
+mod sushiswap;
+/// A SushiSwap inspector
+pub use sushiswap::SushiSwap;
 
 mod curve;
 /// A Curve inspector
-pub use curve::Curve;
+pub use curve::{Curve, CurvePool};
+
+mod synthetix;
+/// A Synthetix exchanges inspector
+pub use synthetix::Synthetix;
 
 mod balancer;
 /// A Balancer inspector
 pub use balancer::Balancer;
 
+mod balancerv2;
+/// A Balancer V2 inspector
+pub use balancerv2::BalancerV2;
+
 mod aave;
 /// An Aave inspector
 pub use aave::Aave;
 
+mod aavev2;
+/// An Aave V2 inspector
+pub use aavev2::AaveV2;
+
+mod flashloan;
+/// A flash loan inspector for Aave, dYdX, Balancer, Maker and Uniswap
+pub use flashloan::FlashLoans;
+
+mod dydx;
+/// A dYdX SoloMargin inspector
+pub use dydx::DyDx;
+
+mod oneinch;
+/// A 1inch AggregationRouter inspector
+pub use oneinch::OneInch;
+
+mod universal_router;
+/// A Uniswap Universal Router inspector
+pub use universal_router::UniversalRouter;
+
+mod kyber;
+/// A KyberNetworkProxy inspector
+pub use kyber::Kyber;
+
+mod bancor;
+/// A Bancor V2 and V3 conversions inspector
+pub use bancor::Bancor;
+
+mod seaport;
+/// A Seaport (OpenSea) inspector
+pub use seaport::Seaport;
+
+mod looksrare;
+/// A LooksRare inspector
+pub use looksrare::LooksRare;
+
+mod x2y2;
+/// An X2Y2 inspector
+pub use x2y2::X2Y2;
+
+mod marketplace;
+
 mod erc20;
 /// ERC20 Inspector, to be used for parsing subtraces involving transfer/transferFrom
 pub use erc20::ERC20;
 
+mod weth;
+/// WETH Inspector, which classifies wrapping and unwrapping ETH as transfers
+pub use weth::WethInspector;
+
+mod permit2;
+/// Permit2 Inspector, which classifies the transfers it makes for its spenders
+pub use permit2::Permit2;
+
+mod gastoken;
+/// A CHI and GST2 gas tokens inspector
+pub use gastoken::GasTokens;
+
+mod erc721;
+/// ERC721 Inspector, which tells NFT transfers apart from ERC20 ones by their logs
+pub use erc721::ERC721;
+
+mod erc1155;
+/// ERC1155 Inspector, which expands batch transfers into a transfer per token id
+pub use erc1155::ERC1155;
+
+mod logs;
+/// An inspector for event logs, merging them with what was classified from traces
+pub use logs::EventLogs;
+
 mod batch;
 /// Takes multiple inspectors
 pub use batch::{BatchEvaluationError, BatchInspector};
 
+mod builder;
+/// Builds inspectors for other protocols from their ABIs
+pub use builder::{
+    ActionConstructor, DecodedCall, InspectorBuilder, InspectorBuilderError, TradeMatching,
+    TransferIn,
+};
+
+mod registry;
+/// Runs a selection of inspectors in order
+pub use registry::InspectorRegistry;
+
 mod compound;
 pub use compound::Compound;
 
+mod compoundv3;
+/// A Compound V3 absorptions and collateral purchases inspector
+pub use compoundv3::CompoundV3;
+
+mod maker;
+/// A MakerDAO liquidations inspector
+pub use maker::MakerDAO;
+
 mod zeroex;
 pub use zeroex::ZeroEx;
 
-use crate::types::{actions::SpecificAction, Classification};
+use crate::{
+    addresses::canonical_token,
+    is_nested,
+    types::{
+        actions::{SpecificAction, Trade, Transfer},
+        Classification, Inspection, Protocol, Provenance,
+    },
+};
+use ethers::types::{Address, Call, CallType, U256};
 
 /// Given an iterator over index,Classification tuples, it will try to cast
 /// each classification to the given specific action (depending on the function given
@@ -42,9 +155,12 @@ use crate::types::{actions::SpecificAction, Classification};
 /// that classification and its index. If `check_all` is set to false, it will return
 /// None if the action we're looking for is not the first known action, e.g.
 /// given a [Deposit, Transfer, Trade], if we're looking for a Trade, `check_all` must
-/// be set to true, otherwise once the Transfer is hit, it will return None
+/// be set to true, otherwise once the Transfer is hit, it will return None.
+/// Only the known actions count: the unknown calls and the pruned actions in
+/// between are skipped, but a known action of the right type which fails
+/// `check_fn` stops the search as well.
 pub(crate) fn find_matching<'a, I, T, F1, F2>(
-    mut actions: I,
+    actions: I,
     cast: F1,
     check_fn: F2,
     check_all: bool,
@@ -54,18 +170,146 @@ where
     F1: Fn(&SpecificAction) -> Option<&T>,
     F2: Fn(&T) -> bool,
 {
-    let mut found_known = false;
-    actions.find_map(|(j, a)| {
-        if check_all || !found_known {
-            if let Some(action) = a.as_action() {
-                if let Some(t) = cast(&action) {
-                    if check_fn(t) {
-                        return Some((j, t));
-                    }
-                }
-                found_known = true;
-            }
+    matching(actions, cast, check_fn, check_all).next()
+}
+
+/// Like `find_matching`, but returns all the matching classifications. If
+/// `check_all` is set to false, these are the known actions which match until
+/// the first one which does not, e.g. given a [Transfer, Transfer, Trade, Transfer]
+/// only the first 2 transfers are returned.
+pub(crate) fn find_all_matching<'a, I, T, F1, F2>(
+    actions: I,
+    cast: F1,
+    check_fn: F2,
+    check_all: bool,
+) -> Vec<(usize, &'a T)>
+where
+    I: Iterator<Item = (usize, &'a Classification)>,
+    F1: Fn(&SpecificAction) -> Option<&T>,
+    F2: Fn(&T) -> bool,
+{
+    matching(actions, cast, check_fn, check_all).collect()
+}
+
+/// Lazily yields the matches of `find_all_matching`, so that `find_matching`
+/// can stop at the first one
+fn matching<'a, I, T, F1, F2>(
+    actions: I,
+    cast: F1,
+    check_fn: F2,
+    check_all: bool,
+) -> impl Iterator<Item = (usize, &'a T)>
+where
+    T: 'a,
+    I: Iterator<Item = (usize, &'a Classification)>,
+    F1: Fn(&SpecificAction) -> Option<&T>,
+    F2: Fn(&T) -> bool,
+{
+    actions
+        .filter_map(|(j, a)| a.as_action().map(|action| (j, action)))
+        .map(move |(j, action)| (j, cast(action).filter(|t| check_fn(t))))
+        .take_while(move |(_, t)| check_all || t.is_some())
+        .filter_map(|(j, t)| t.map(|t| (j, t)))
+}
+
+/// A trade made through a router which collapses the calls to its pools, like
+/// Kyber's proxy and Bancor's network, as far as it can be told from its calldata
+pub(crate) struct RoutedTrade {
+    pub(crate) src_token: Address,
+    pub(crate) src_amount: U256,
+    pub(crate) dst_token: Address,
+    /// The account which the router pays `dst_token` to
+    pub(crate) receiver: Address,
+}
+
+/// Collapses the calls which `decode` decodes as routed trades, along with all
+/// their subcalls, into a trade of `protocol` between the caller and the router.
+/// The amount received is returned by the router or else is its last payment
+/// to the receiver, which `erc20` parses if it is not classified yet. The
+/// routers denote ETH with `0xeee...eee`, which is replaced with `weth`.
+pub(crate) fn inspect_routed_trades<F>(
+    inspection: &mut Inspection,
+    erc20: &ERC20,
+    weth: Address,
+    protocol: Protocol,
+    decode: F,
+) where
+    F: Fn(&Call) -> Option<RoutedTrade>,
+{
+    // the transfer made by the action, whether it has been classified yet or not
+    let transfer = |action: &Classification| match action {
+        Classification::Known(_) => action.as_action()?.transfer().cloned(),
+        Classification::Unknown(calltrace) => {
+            erc20.try_parse(calltrace.as_ref())?.transfer().cloned()
+        }
+        Classification::Prune => None,
+    };
+
+    let actions = &inspection.actions;
+    let mut trades: Vec<(usize, Classification)> = Vec::new();
+    for (i, action) in actions.iter().enumerate() {
+        let calltrace = match action.as_call() {
+            Some(inner) if inner.as_ref().call_type == CallType::Call => inner,
+            _ => continue,
+        };
+        // already collapsed into an outer trade
+        if trades
+            .iter()
+            .any(|(_, outer)| is_nested(outer.trace_address(), &calltrace.trace_address))
+        {
+            continue;
         }
-        None
-    })
+        let call = calltrace.as_ref();
+        let routed = match decode(call) {
+            Some(inner) => inner,
+            None => continue,
+        };
+        let output = calltrace.output.as_ref();
+        let received = if output.len() >= 32 {
+            U256::from_big_endian(&output[..32])
+        } else {
+            let payment = actions
+                .iter()
+                .skip(i + 1)
+                .filter(|a| is_nested(&calltrace.trace_address, a.trace_address()))
+                .filter_map(&transfer)
+                .filter(|t| t.to == routed.receiver && t.token == routed.dst_token)
+                .last();
+            match payment {
+                Some(payment) => payment.amount,
+                None => continue,
+            }
+        };
+
+        inspection.protocols.insert(protocol);
+        let trade = Classification::new(
+            Trade {
+                t1: Transfer {
+                    from: call.from,
+                    to: call.to,
+                    amount: routed.src_amount,
+                    token: canonical_token(routed.src_token, weth),
+                },
+                t2: Transfer {
+                    from: call.to,
+                    to: routed.receiver,
+                    amount: received,
+                    token: canonical_token(routed.dst_token, weth),
+                },
+            },
+            calltrace.trace_address.clone(),
+        );
+        trades.push((i, trade.with_provenance(Provenance::TransferInferred)));
+    }
+
+    for (i, trade) in trades {
+        // the pools' calls and transfers are part of the trade
+        let trace_address = trade.trace_address();
+        inspection
+            .actions
+            .iter_mut()
+            .filter(|a| is_nested(trace_address, a.trace_address()))
+            .for_each(|a| *a = Classification::Prune);
+        inspection.actions[i] = trade;
+    }
 }
@@ -0,0 +1,368 @@
+use crate::{
+    addresses::{ChainConfig, NATIVE_ETH},
+    is_nested,
+    traits::Inspector,
+    types::{
+        actions::{Trade, Transfer},
+        Classification, Inspection, Protocol, Provenance,
+    },
+};
+
+use ethers::{
+    abi::Abi,
+    contract::BaseContract,
+    types::{Address, Bytes, Call as TraceCall, CallType, U256},
+};
+use once_cell::sync::Lazy;
+
+static ROUTER_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    serde_json::from_str::<Abi>(include_str!("../../abi/oneinch.json"))
+        .expect("could not parse 1inch abi")
+        .into()
+});
+
+// Type aliases for the AggregationRouter's swap functions
+type SwapDescription = (Address, Address, Address, Address, U256, U256, U256, Bytes);
+type Swap = (Address, SwapDescription, Bytes);
+type Unoswap = (Address, U256, U256, Vec<[u8; 32]>);
+type UniswapV3Swap = (U256, U256, Vec<U256>);
+
+#[derive(Debug, Clone)]
+/// An inspector for 1inch's AggregationRouter.
+///
+/// The router trades through many pools, whose calls get collapsed into a
+/// single trade between the caller and the router. It must therefore run before
+/// the inspectors of the pools it routes through, but after `ERC20`.
+pub struct OneInch {
+    router: &'static BaseContract,
+    /// The placeholder which the router's native token is classified with
+    native: Address,
+}
+
+/// A swap, as far as it can be told from its calldata
+struct SwapCall {
+    src_token: Option<Address>,
+    dst_token: Option<Address>,
+    amount: U256,
+    receiver: Address,
+}
+
+impl Default for OneInch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OneInch {
+    /// Constructor
+    pub fn new() -> Self {
+        Self {
+            router: &ROUTER_ABI,
+            native: NATIVE_ETH,
+        }
+    }
+
+    /// Instantiates an inspector for `chain`
+    pub fn with_chain(chain: &ChainConfig) -> Self {
+        Self {
+            native: chain.native,
+            ..Self::new()
+        }
+    }
+
+    fn decode(&self, call: &TraceCall) -> Option<SwapCall> {
+        if let Ok((_, desc, _)) = self.router.decode::<Swap, _>("swap", &call.input) {
+            let (src_token, dst_token, _, receiver, amount, _, _, _) = desc;
+            Some(SwapCall {
+                src_token: Some(self.eth_or(src_token)),
+                dst_token: Some(self.eth_or(dst_token)),
+                amount,
+                receiver: if receiver.is_zero() {
+                    call.from
+                } else {
+                    receiver
+                },
+            })
+        } else if let Ok((src_token, amount, _, _)) =
+            self.router.decode::<Unoswap, _>("unoswap", &call.input)
+        {
+            // the output token is only known to the last pool of the route
+            Some(SwapCall {
+                src_token: Some(self.eth_or(src_token)),
+                dst_token: None,
+                amount,
+                receiver: call.from,
+            })
+        } else if let Ok((amount, _, _)) = self
+            .router
+            .decode::<UniswapV3Swap, _>("uniswapV3Swap", &call.input)
+        {
+            Some(SwapCall {
+                src_token: None,
+                dst_token: None,
+                amount,
+                receiver: call.from,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// 1inch denotes ETH with either the zero address or `0xeee...eee`
+    fn eth_or(&self, token: Address) -> Address {
+        if token.is_zero() || token == NATIVE_ETH {
+            self.native
+        } else {
+            token
+        }
+    }
+}
+
+impl Inspector for OneInch {
+    fn name(&self) -> &'static str {
+        "oneinch"
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        let actions = &inspection.actions;
+        let mut swaps: Vec<(usize, Classification)> = Vec::new();
+        for (i, action) in actions.iter().enumerate() {
+            let calltrace = match action.as_call() {
+                Some(inner) if inner.as_ref().call_type == CallType::Call => inner,
+                _ => continue,
+            };
+            // already collapsed into an outer swap
+            if swaps
+                .iter()
+                .any(|(_, swap)| is_nested(swap.trace_address(), &calltrace.trace_address))
+            {
+                continue;
+            }
+            let call = calltrace.as_ref();
+            let swap = match self.decode(call) {
+                Some(inner) => inner,
+                None => continue,
+            };
+            let subtraces = || {
+                actions
+                    .iter()
+                    .skip(i + 1)
+                    .filter(|a| is_nested(&calltrace.trace_address, a.trace_address()))
+            };
+
+            // The input is paid by the caller, either in ETH or by having its
+            // tokens pulled by the router or the first pool
+            let src_token = swap.src_token.or_else(|| {
+                if !call.value.is_zero() {
+                    return Some(self.native);
+                }
+                subtraces()
+                    .filter_map(|a| a.as_action()?.transfer())
+                    .find(|t| t.from == call.from)
+                    .map(|t| t.token)
+            });
+            let src_token = match src_token {
+                Some(inner) => inner,
+                None => continue,
+            };
+
+            // The realized output is the last payment to the receiver, which is
+            // at least the calldata's `minReturn`
+            let received = subtraces().rev().find_map(|a| match a {
+                Classification::Known(_) => a
+                    .as_action()?
+                    .transfer()
+                    .filter(|t| {
+                        t.to == swap.receiver
+                            && swap
+                                .dst_token
+                                .map_or(t.token != src_token, |dst| t.token == dst)
+                    })
+                    .map(|t| (t.token, t.amount)),
+                Classification::Unknown(calltrace) => {
+                    let call = calltrace.as_ref();
+                    let is_eth = swap
+                        .dst_token
+                        .map_or(src_token != self.native, |dst| dst == self.native);
+                    if is_eth && call.to == swap.receiver && !call.value.is_zero() {
+                        Some((self.native, call.value))
+                    } else {
+                        None
+                    }
+                }
+                Classification::Prune => None,
+            });
+            let (dst_token, dst_amount) = match received {
+                Some(inner) => inner,
+                None => continue,
+            };
+
+            inspection.protocols.insert(Protocol::OneInch);
+            let trade = Classification::new(
+                Trade {
+                    t1: Transfer {
+                        from: call.from,
+                        to: call.to,
+                        amount: swap.amount,
+                        token: src_token,
+                    },
+                    t2: Transfer {
+                        from: call.to,
+                        to: swap.receiver,
+                        amount: dst_amount,
+                        token: dst_token,
+                    },
+                },
+                calltrace.trace_address.clone(),
+            )
+            .with_provenance(Provenance::TransferInferred);
+            swaps.push((i, trade));
+        }
+
+        for (i, trade) in swaps {
+            // the pools' calls and transfers are part of the aggregated trade
+            let trace_address = trade.trace_address();
+            inspection
+                .actions
+                .iter_mut()
+                .filter(|a| is_nested(trace_address, a.trace_address()))
+                .for_each(|a| *a = Classification::Prune);
+            inspection.actions[i] = trade;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        addresses::{ETH, WETH},
+        inspectors::{UniswapV2, ERC20},
+        test_helpers::*,
+    };
+    use ethers::abi::Token;
+
+    fn inspect(inspection: &mut Inspection) {
+        ERC20::new().inspect(inspection);
+        OneInch::new().inspect(inspection);
+        UniswapV2::new().inspect(inspection);
+        inspection.prune();
+    }
+
+    fn pair_swap(amount0: u64, amount1: u64, to: Address) -> Bytes {
+        calldata(
+            "swap(uint256,uint256,address,bytes)",
+            &[
+                Token::Uint(amount0.into()),
+                Token::Uint(amount1.into()),
+                Token::Address(to),
+                Token::Bytes(vec![]),
+            ],
+        )
+    }
+
+    #[test]
+    fn swap_through_the_executor() {
+        let addrs = addrs();
+        let (usr, router, executor, pair1, pair2, dai, usdc) = (
+            addrs[0], addrs[1], addrs[2], addrs[3], addrs[4], addrs[5], addrs[6],
+        );
+
+        let input = calldata(
+            "swap(address,(address,address,address,address,uint256,uint256,uint256,bytes),bytes)",
+            &[
+                Token::Address(executor),
+                Token::Tuple(vec![
+                    Token::Address(*WETH),
+                    Token::Address(dai),
+                    Token::Address(executor),
+                    Token::Address(Address::zero()),
+                    Token::Uint(100.into()),
+                    // the minimum return
+                    Token::Uint(2900.into()),
+                    Token::Uint(0.into()),
+                    Token::Bytes(vec![]),
+                ]),
+                Token::Bytes(vec![]),
+            ],
+        );
+        let mut inspection = mk_inspection(vec![
+            mk_call(usr, router, input, vec![]),
+            mk_call(
+                router,
+                *WETH,
+                transfer_from(usr, executor, 100.into()),
+                vec![0],
+            ),
+            // the executor routes through 2 pairs
+            mk_call(router, executor, Bytes::default(), vec![1]),
+            mk_call(executor, *WETH, transfer(pair1, 100.into()), vec![1, 0]),
+            mk_call(executor, pair1, pair_swap(0, 300, pair2), vec![1, 1]),
+            mk_call(pair1, usdc, transfer(pair2, 300.into()), vec![1, 1, 0]),
+            mk_call(executor, pair2, pair_swap(2950, 0, router), vec![1, 2]),
+            mk_call(pair2, dai, transfer(router, 2950.into()), vec![1, 2, 0]),
+            mk_call(router, dai, transfer(usr, 2950.into()), vec![2]),
+        ]);
+        inspect(&mut inspection);
+
+        let known = inspection.known();
+        assert_eq!(known.len(), 1);
+        let trade = known[0].as_ref().trade().unwrap();
+        assert_eq!(
+            (trade.t1.from, trade.t1.token, trade.t1.amount),
+            (usr, *WETH, 100.into())
+        );
+        assert_eq!(
+            (trade.t2.to, trade.t2.token, trade.t2.amount),
+            (usr, dai, 2950.into())
+        );
+        // the received amount is the one of the last transfer to the receiver
+        assert_eq!(known[0].provenance, Provenance::TransferInferred);
+        assert!(inspection.unknown().is_empty());
+        assert_eq!(inspection.protocols, crate::set![Protocol::OneInch]);
+    }
+
+    #[test]
+    fn unoswap_to_eth() {
+        let addrs = addrs();
+        let (usr, router, pair, dai) = (addrs[0], addrs[1], addrs[2], addrs[3]);
+
+        let input = calldata(
+            "unoswap(address,uint256,uint256,bytes32[])",
+            &[
+                Token::Address(dai),
+                Token::Uint(3000.into()),
+                Token::Uint(1.into()),
+                Token::Array(vec![Token::FixedBytes(pair.as_bytes().to_vec())]),
+            ],
+        );
+        let mut inspection = mk_inspection(vec![
+            mk_call(usr, router, input, vec![]),
+            mk_call(router, dai, transfer_from(usr, pair, 3000.into()), vec![0]),
+            mk_call(router, pair, pair_swap(0, 2, router), vec![1]),
+            mk_call(pair, *WETH, transfer(router, 2.into()), vec![1, 0]),
+            mk_call(
+                router,
+                *WETH,
+                calldata("withdraw(uint256)", &[Token::Uint(2.into())]),
+                vec![2],
+            ),
+            with_value(
+                mk_call(*WETH, router, Bytes::default(), vec![2, 0]),
+                2.into(),
+            ),
+            with_value(mk_call(router, usr, Bytes::default(), vec![3]), 2.into()),
+        ]);
+        inspect(&mut inspection);
+
+        let known = inspection.known();
+        assert_eq!(known.len(), 1);
+        let trade = known[0].as_ref().trade().unwrap();
+        assert_eq!((trade.t1.token, trade.t1.amount), (dai, 3000.into()));
+        assert_eq!(
+            (trade.t2.to, trade.t2.token, trade.t2.amount),
+            (usr, *ETH, 2.into())
+        );
+        assert_eq!(known[0].provenance, Provenance::TransferInferred);
+    }
+}
@@ -0,0 +1,297 @@
+use crate::{
+    addresses::PERMIT2,
+    is_nested,
+    types::{actions::Transfer, Classification, Inspection},
+    Inspector,
+};
+use ethers::{
+    abi::{self, ParamType, Token},
+    types::{Address, Call as TraceCall, CallType},
+    utils::id,
+};
+
+// The functions which transfer a single token to a spender's recipient
+const TRANSFER_FROM: &str = "transferFrom(address,address,uint160,address)";
+const PERMIT_TRANSFER_FROM: &str =
+    "permitTransferFrom(((address,uint256),uint256,uint256),(address,uint256),address,bytes)";
+const PERMIT_WITNESS_TRANSFER_FROM: &str = "permitWitnessTransferFrom(((address,uint256),uint256,uint256),(address,uint256),address,bytes32,string,bytes)";
+
+#[derive(Debug, Clone)]
+/// Classifies the transfers which Permit2 makes for its spenders, e.g.
+/// Uniswap's Universal Router and the aggregators. Owners approve their tokens
+/// to Permit2 once, and the spenders pull them through it with an allowance
+/// (`transferFrom`) or with a signature of the owner (`permitTransferFrom` and
+/// `permitWitnessTransferFrom`).
+///
+/// The transfer is classified at the spender's call to Permit2 rather than at
+/// Permit2's own `transferFrom` of the token, which is pruned, so that it is
+/// found where the spender paid for its trade. This therefore runs after
+/// `ERC20`. The batched transfers are left to `ERC20`, one per token.
+pub struct Permit2 {
+    permit2: Address,
+}
+
+impl Default for Permit2 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Inspector for Permit2 {
+    fn name(&self) -> &'static str {
+        "permit2"
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        let transfers = inspection
+            .actions
+            .iter()
+            .filter_map(|a| a.as_call())
+            .filter_map(|calltrace| {
+                let transfer = self.try_parse(calltrace.as_ref())?;
+                Some((calltrace.trace_address.clone(), transfer))
+            })
+            .collect::<Vec<_>>();
+
+        for (trace_address, transfer) in transfers {
+            for action in inspection.actions.iter_mut() {
+                if action.trace_address() == &trace_address[..] {
+                    *action = Classification::new(transfer.clone(), trace_address.clone());
+                } else if is_nested(&trace_address, action.trace_address()) {
+                    // the token's transfer
+                    *action = Classification::Prune;
+                }
+            }
+        }
+    }
+}
+
+impl Permit2 {
+    /// Instantiates the inspector for the canonical Permit2
+    pub fn new() -> Self {
+        Self { permit2: *PERMIT2 }
+    }
+
+    /// Parses a call to Permit2 which transfers a single token
+    pub fn try_parse(&self, call: &TraceCall) -> Option<Transfer> {
+        if call.to != self.permit2 || call.call_type != CallType::Call {
+            return None;
+        }
+        let input = call.input.as_ref();
+        let data = input.get(4..)?;
+
+        if input.starts_with(&id(TRANSFER_FROM)) {
+            let params = [
+                ParamType::Address,
+                ParamType::Address,
+                ParamType::Uint(160),
+                ParamType::Address,
+            ];
+            let mut tokens = abi::decode(&params, data).ok()?.into_iter();
+            return Some(Transfer {
+                from: tokens.next()?.into_address()?,
+                to: tokens.next()?.into_address()?,
+                amount: tokens.next()?.into_uint()?,
+                token: tokens.next()?.into_address()?,
+            });
+        }
+
+        // `((token, amount), nonce, deadline)`
+        let permit = ParamType::Tuple(vec![
+            ParamType::Tuple(vec![ParamType::Address, ParamType::Uint(256)]),
+            ParamType::Uint(256),
+            ParamType::Uint(256),
+        ]);
+        // `(to, requestedAmount)`
+        let details = ParamType::Tuple(vec![ParamType::Address, ParamType::Uint(256)]);
+        let params = if input.starts_with(&id(PERMIT_TRANSFER_FROM)) {
+            vec![permit, details, ParamType::Address, ParamType::Bytes]
+        } else if input.starts_with(&id(PERMIT_WITNESS_TRANSFER_FROM)) {
+            vec![
+                permit,
+                details,
+                ParamType::Address,
+                ParamType::FixedBytes(32),
+                ParamType::String,
+                ParamType::Bytes,
+            ]
+        } else {
+            return None;
+        };
+        let mut tokens = abi::decode(&params, data).ok()?.into_iter();
+        match (tokens.next()?, tokens.next()?, tokens.next()?) {
+            (Token::Tuple(permit), Token::Tuple(details), Token::Address(from)) => {
+                let token = match permit.into_iter().next()? {
+                    Token::Tuple(permitted) => permitted.into_iter().next()?.into_address()?,
+                    _ => return None,
+                };
+                let mut details = details.into_iter();
+                Some(Transfer {
+                    from,
+                    to: details.next()?.into_address()?,
+                    amount: details.next()?.into_uint()?,
+                    token,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        addresses::{parse_address, USDC, WETH},
+        inspectors::ERC20,
+        test_helpers::*,
+    };
+    use ethers::types::{Bytes, U256};
+
+    #[test]
+    fn funds_a_universal_router_swap() {
+        // A swap of 1000 USDC for ETH through the Universal Router, whose USDC
+        // is pulled from the user by Permit2
+        let addrs = addrs();
+        let (user, pair) = (addrs[0], addrs[1]);
+        let router = parse_address("0x3fc91a3afd70395cd496c647d5a6cc9d4b2b7fad");
+        let amount_in = U256::from(1000) * 1_000_000;
+        let amount_out = U256::from(520_000_000_000_000_000u64);
+
+        // V2_SWAP_EXACT_IN to the router, paid by the user, then UNWRAP_WETH
+        // to the user
+        let v2_swap = abi::encode(&[
+            Token::Address(Address::from_low_u64_be(2)),
+            Token::Uint(amount_in),
+            Token::Uint(amount_out),
+            Token::Array(vec![Token::Address(*USDC), Token::Address(*WETH)]),
+            Token::Bool(true),
+        ]);
+        let unwrap = abi::encode(&[
+            Token::Address(Address::from_low_u64_be(1)),
+            Token::Uint(amount_out),
+        ]);
+        let execute = calldata(
+            "execute(bytes,bytes[],uint256)",
+            &[
+                Token::Bytes(vec![0x08, 0x0c]),
+                Token::Array(vec![Token::Bytes(v2_swap), Token::Bytes(unwrap)]),
+                Token::Uint(U256::MAX),
+            ],
+        );
+        let permit2_transfer = calldata(
+            TRANSFER_FROM,
+            &[
+                Token::Address(user),
+                Token::Address(pair),
+                Token::Uint(amount_in),
+                Token::Address(*USDC),
+            ],
+        );
+        let pair_swap = calldata(
+            "swap(uint256,uint256,address,bytes)",
+            &[
+                Token::Uint(0.into()),
+                Token::Uint(amount_out),
+                Token::Address(router),
+                Token::Bytes(vec![]),
+            ],
+        );
+        let withdraw = calldata("withdraw(uint256)", &[Token::Uint(amount_out)]);
+        let swap = || {
+            mk_inspection(vec![
+                mk_call(user, router, execute.clone(), vec![]),
+                mk_call(router, *PERMIT2, permit2_transfer.clone(), vec![0]),
+                mk_call(
+                    *PERMIT2,
+                    *USDC,
+                    transfer_from(user, pair, amount_in),
+                    vec![0, 0],
+                ),
+                mk_call(router, pair, pair_swap.clone(), vec![1]),
+                mk_call(pair, *WETH, transfer(router, amount_out), vec![1, 0]),
+                mk_call(router, *WETH, withdraw.clone(), vec![2]),
+                with_value(
+                    mk_call(*WETH, router, Bytes::default(), vec![2, 0]),
+                    amount_out,
+                ),
+                with_value(mk_call(router, user, Bytes::default(), vec![3]), amount_out),
+            ])
+        };
+
+        let mut inspection = swap();
+        ERC20::new().inspect(&mut inspection);
+        Permit2::new().inspect(&mut inspection);
+        // at the router's call to Permit2, rather than at Permit2's call to USDC
+        let known = inspection.known();
+        assert_eq!(known[0].trace_address.to_vec(), vec![0]);
+        assert_eq!(
+            known[0].as_ref().transfer(),
+            Some(&Transfer::new(user, pair, amount_in, *USDC))
+        );
+        assert!(inspection
+            .known_actions()
+            .skip(1)
+            .filter_map(|a| a.transfer())
+            .all(|t| t.token != *USDC));
+
+        let mut inspection = swap();
+        let processor = processor();
+        processor.inspect(&mut inspection);
+        processor.reduce(&mut inspection);
+        let trades = inspection.trades().collect::<Vec<_>>();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].t1, Transfer::new(user, pair, amount_in, *USDC));
+        assert_eq!(trades[0].t2, Transfer::new(pair, router, amount_out, *WETH));
+    }
+
+    #[test]
+    fn signature_transfers() {
+        let addrs = addrs();
+        let (owner, spender, to, token) = (addrs[0], addrs[1], addrs[2], addrs[3]);
+        let permit = Token::Tuple(vec![
+            Token::Tuple(vec![Token::Address(token), Token::Uint(100.into())]),
+            Token::Uint(0.into()),
+            Token::Uint(U256::MAX),
+        ]);
+        // less than the permitted amount may be requested
+        let details = Token::Tuple(vec![Token::Address(to), Token::Uint(60.into())]);
+        let permit_transfer = calldata(
+            PERMIT_TRANSFER_FROM,
+            &[
+                permit.clone(),
+                details.clone(),
+                Token::Address(owner),
+                Token::Bytes(vec![1; 65]),
+            ],
+        );
+        let witness_transfer = calldata(
+            PERMIT_WITNESS_TRANSFER_FROM,
+            &[
+                permit,
+                details,
+                Token::Address(owner),
+                Token::FixedBytes(vec![0; 32]),
+                Token::String("Witness".to_owned()),
+                Token::Bytes(vec![1; 65]),
+            ],
+        );
+
+        for input in [permit_transfer, witness_transfer] {
+            let mut inspection = mk_inspection(vec![
+                mk_call(spender, *PERMIT2, input, vec![0]),
+                mk_call(
+                    *PERMIT2,
+                    token,
+                    transfer_from(owner, to, 60.into()),
+                    vec![0, 0],
+                ),
+            ]);
+            ERC20::new().inspect(&mut inspection);
+            Permit2::new().inspect(&mut inspection);
+            inspection.prune();
+            let transfers = inspection.transfers().cloned().collect::<Vec<_>>();
+            assert_eq!(transfers, vec![Transfer::new(owner, to, 60.into(), token)]);
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use crate::{types::Inspection, Inspector};
+
+#[derive(Debug, Default)]
+/// An ordered set of inspectors, for running a configurable selection of them,
+/// e.g. the ones named on the command line. Later inspectors see what the
+/// earlier ones have classified, so the ERC20 one must come before the
+/// protocols which pair transfers into trades.
+pub struct InspectorRegistry {
+    inspectors: Vec<Box<dyn Inspector + Send + Sync>>,
+}
+
+impl InspectorRegistry {
+    /// Constructor
+    pub fn new(inspectors: Vec<Box<dyn Inspector + Send + Sync>>) -> Self {
+        Self { inspectors }
+    }
+
+    /// Adds an inspector, which runs after the ones already registered
+    pub fn register(mut self, inspector: Box<dyn Inspector + Send + Sync>) -> Self {
+        self.inspectors.push(inspector);
+        self
+    }
+
+    /// The names of the registered inspectors, in the order they run in
+    pub fn names(&self) -> Vec<&'static str> {
+        self.inspectors
+            .iter()
+            .map(|inspector| inspector.name())
+            .collect()
+    }
+
+    /// Returns the registered inspector called `name`, if any
+    pub fn get(&self, name: &str) -> Option<&(dyn Inspector + Send + Sync)> {
+        self.inspectors
+            .iter()
+            .find(|inspector| inspector.name() == name)
+            .map(|inspector| inspector.as_ref())
+    }
+
+    /// Classifies the inspection's actions with each inspector in turn, then
+    /// resolves the preflight reads against the trades all of them found
+    pub fn inspect_all(&self, inspection: &mut Inspection) {
+        for inspector in self.inspectors.iter() {
+            inspector.inspect(inspection);
+        }
+        inspection.resolve_preflights();
+    }
+}
+
+impl From<Vec<Box<dyn Inspector + Send + Sync>>> for InspectorRegistry {
+    fn from(inspectors: Vec<Box<dyn Inspector + Send + Sync>>) -> Self {
+        Self::new(inspectors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        inspectors::{UniswapV2, ERC20},
+        test_helpers::read_trace,
+    };
+
+    #[test]
+    fn runs_inspectors_in_order() {
+        let registry = InspectorRegistry::default()
+            .register(Box::new(ERC20::new()))
+            .register(Box::new(UniswapV2::new()));
+        assert_eq!(registry.names(), vec!["erc20", "uniswap"]);
+        assert_eq!(registry.get("uniswap").unwrap().name(), "uniswap");
+        assert!(registry.get("curve").is_none());
+
+        let mut inspection = read_trace("exact_tokens_for_eth.json");
+        registry.inspect_all(&mut inspection);
+        assert_eq!(inspection.trades().count(), 1);
+
+        // UniswapV2 pairs the transfers which ERC20 classifies, running it
+        // first finds no trades
+        let reversed =
+            InspectorRegistry::new(vec![Box::new(UniswapV2::new()), Box::new(ERC20::new())]);
+        let mut inspection = read_trace("exact_tokens_for_eth.json");
+        reversed.inspect_all(&mut inspection);
+        assert_eq!(inspection.trades().count(), 0);
+    }
+}
@@ -0,0 +1,527 @@
+use crate::{
+    addresses::{ETH, SEAPORT},
+    inspectors::ERC20,
+    is_nested,
+    traits::Inspector,
+    types::{
+        actions::{NftTrade, NftTransfer, SpecificAction},
+        Classification, Inspection, Protocol,
+    },
+};
+
+use ethers::{
+    abi::{Abi, Token},
+    contract::BaseContract,
+    types::{Address, Bytes, U256},
+};
+use once_cell::sync::Lazy;
+
+static SEAPORT_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    serde_json::from_str::<Abi>(include_str!("../../abi/seaport.json"))
+        .expect("could not parse seaport abi")
+        .into()
+});
+
+// Type aliases for Seaport's fulfillment functions. The orders are too large
+// to be decoded into tuples, so their fields are read from their tokens.
+type FulfillOrder = (Token, [u8; 32]);
+type FulfillAdvancedOrder = (Token, Vec<Token>, [u8; 32], Address);
+
+// https://github.com/ProjectOpenSea/seaport/blob/main/contracts/lib/ConsiderationEnums.sol
+const NATIVE: u8 = 0;
+const ERC20_ITEM: u8 = 1;
+
+#[derive(Debug, Clone)]
+/// An inspector for OpenSea's Seaport.
+///
+/// The NFTs of a fulfilled order are classified with their price, which is
+/// read from the payments made to the seller and to the fee recipients. It must
+/// run after `ERC721`, but before `ERC20` so that the ETH sent to Seaport is
+/// not classified as a transfer.
+pub struct Seaport {
+    seaport: &'static BaseContract,
+    erc20: ERC20,
+}
+
+/// What is known of a fulfilled order before looking at its transfers
+struct Order {
+    offerer: Address,
+    /// ETH or the ERC20 which the NFTs are paid with
+    payment_token: Address,
+    /// Whether the offerer pays for the NFTs, i.e. a bid was accepted
+    offerer_pays: bool,
+}
+
+impl Default for Seaport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Seaport {
+    /// Constructor
+    pub fn new() -> Self {
+        Self {
+            seaport: &SEAPORT_ABI,
+            erc20: ERC20::new(),
+        }
+    }
+
+    fn decode(&self, input: &Bytes) -> Option<Order> {
+        let order = if let Ok(order) = self.seaport.decode::<Token, _>("fulfillBasicOrder", input) {
+            return basic_order(order);
+        } else if let Ok((order, _)) = self
+            .seaport
+            .decode::<FulfillOrder, _>("fulfillOrder", input)
+        {
+            order
+        } else if let Ok((order, _, _, _)) = self
+            .seaport
+            .decode::<FulfillAdvancedOrder, _>("fulfillAdvancedOrder", input)
+        {
+            order
+        } else {
+            return None;
+        };
+
+        // both orders start with their `OrderParameters`
+        let parameters = into_tuple(into_tuple(order)?.into_iter().next()?)?;
+        let offerer = parameters.first()?.clone().into_address()?;
+        let payment = |items: &Token| {
+            items
+                .clone()
+                .into_array()?
+                .into_iter()
+                .filter_map(|item| {
+                    let item = into_tuple(item)?;
+                    let kind = item.first()?.clone().into_uint()?.low_u32() as u8;
+                    let token = item.get(1)?.clone().into_address()?;
+                    match kind {
+                        NATIVE => Some(*ETH),
+                        ERC20_ITEM => Some(token),
+                        _ => None,
+                    }
+                })
+                .next()
+        };
+
+        // the offerer either lists NFTs or bids for them with ERC20s
+        if let Some(payment_token) = payment(parameters.get(2)?) {
+            Some(Order {
+                offerer,
+                payment_token,
+                offerer_pays: true,
+            })
+        } else {
+            Some(Order {
+                offerer,
+                payment_token: payment(parameters.get(3)?)?,
+                offerer_pays: false,
+            })
+        }
+    }
+}
+
+fn into_tuple(token: Token) -> Option<Vec<Token>> {
+    match token {
+        Token::Tuple(inner) => Some(inner),
+        _ => None,
+    }
+}
+
+/// Basic orders trade a single NFT, and their route is encoded in their
+/// `basicOrderType`
+fn basic_order(order: Token) -> Option<Order> {
+    let fields = into_tuple(order)?;
+    let address = |i: usize| fields.get(i)?.clone().into_address();
+    let (consideration_token, offerer, offer_token) = (address(0)?, address(3)?, address(5)?);
+    let order_type = fields.get(8)?.clone().into_uint()?.low_u32();
+
+    // ETH_TO_ERC721, ETH_TO_ERC1155, ERC20_TO_ERC721, ERC20_TO_ERC1155,
+    // ERC721_TO_ERC20 and ERC1155_TO_ERC20, each with 4 order types
+    let (payment_token, offerer_pays) = match order_type / 4 {
+        0 | 1 => (*ETH, false),
+        2 | 3 => (consideration_token, false),
+        4 | 5 => (offer_token, true),
+        _ => return None,
+    };
+    Some(Order {
+        offerer,
+        payment_token,
+        offerer_pays,
+    })
+}
+
+impl Inspector for Seaport {
+    fn name(&self) -> &'static str {
+        "seaport"
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        let actions = &inspection.actions;
+        let mut classified = Vec::new();
+        for (i, action) in actions.iter().enumerate() {
+            let calltrace = match action.as_call() {
+                Some(inner) if SEAPORT.contains(&inner.as_ref().to) => inner,
+                _ => continue,
+            };
+            let call = calltrace.as_ref();
+            let order = match self.decode(&call.input) {
+                Some(inner) => inner,
+                None => continue,
+            };
+            let payer = if order.offerer_pays {
+                order.offerer
+            } else {
+                call.from
+            };
+
+            let mut nfts: Vec<(usize, NftTransfer)> = Vec::new();
+            let mut payments = Vec::new();
+            let mut price = U256::zero();
+            for (j, action) in actions
+                .iter()
+                .enumerate()
+                .skip(i + 1)
+                .filter(|(_, a)| is_nested(&calltrace.trace_address, a.trace_address()))
+            {
+                // the receiver of an NFT may do anything with it in its
+                // callback, which is not part of the order
+                if nfts
+                    .iter()
+                    .any(|(k, _)| is_nested(actions[*k].trace_address(), action.trace_address()))
+                {
+                    continue;
+                }
+
+                match action {
+                    Classification::Known(known) => {
+                        if let Some(nft) = known.as_ref().nft_transfer() {
+                            if nft.from == order.offerer || nft.to == order.offerer {
+                                nfts.push((j, nft.clone()));
+                            }
+                        }
+                    }
+                    Classification::Unknown(calltrace) => {
+                        // ETH is paid out by Seaport, while ERC20s are pulled
+                        // from the payer. Excess ETH is refunded to the payer.
+                        if let Some(SpecificAction::Transfer(transfer)) =
+                            self.erc20.try_parse(calltrace.as_ref())
+                        {
+                            if transfer.token == order.payment_token
+                                && (transfer.from == payer || transfer.from == call.to)
+                                && transfer.to != payer
+                            {
+                                price += transfer.amount;
+                                payments.push(j);
+                            }
+                        }
+                    }
+                    Classification::Prune => (),
+                }
+            }
+
+            if nfts.is_empty() {
+                continue;
+            }
+
+            // the price of a bundle is split evenly between its NFTs
+            let share = price / nfts.len();
+            let remainder = price - share * nfts.len();
+            for (k, (j, nft)) in nfts.into_iter().enumerate() {
+                let trade = Classification::new(
+                    NftTrade {
+                        collection: nft.collection,
+                        token_id: nft.token_id,
+                        payment_token: order.payment_token,
+                        price: if k == 0 { share + remainder } else { share },
+                        buyer: nft.to,
+                        seller: nft.from,
+                    },
+                    actions[j].trace_address(),
+                );
+                classified.push((j, trade));
+            }
+            for j in payments {
+                classified.push((j, Classification::Prune));
+            }
+            classified.push((i, Classification::Prune));
+            inspection.protocols.insert(Protocol::Seaport);
+        }
+
+        for (i, action) in classified {
+            inspection.actions[i] = action;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{addresses::WETH, inspectors::ERC721, test_helpers::*};
+
+    fn inspect(inspection: &mut Inspection) {
+        ERC721::new().inspect(inspection);
+        Seaport::new().inspect(inspection);
+        ERC20::new().inspect(inspection);
+        inspection.prune();
+    }
+
+    fn seaport() -> Address {
+        *SEAPORT.iter().next().unwrap()
+    }
+
+    fn item(kind: u8, token: Address, id: u64, amount: u64) -> Vec<Token> {
+        vec![
+            Token::Uint(kind.into()),
+            Token::Address(token),
+            Token::Uint(id.into()),
+            Token::Uint(amount.into()),
+            Token::Uint(amount.into()),
+        ]
+    }
+
+    fn consideration(kind: u8, token: Address, amount: u64, recipient: Address) -> Token {
+        let mut item = item(kind, token, 0, amount);
+        item.push(Token::Address(recipient));
+        Token::Tuple(item)
+    }
+
+    fn parameters(offerer: Address, offer: Vec<Token>, consideration: Vec<Token>) -> Token {
+        Token::Tuple(vec![
+            Token::Address(offerer),
+            Token::Address(Address::zero()),
+            Token::Array(offer),
+            Token::Array(consideration),
+            Token::Uint(0.into()),
+            Token::Uint(0.into()),
+            Token::Uint(U256::MAX),
+            Token::FixedBytes(vec![0; 32]),
+            Token::Uint(0.into()),
+            Token::FixedBytes(vec![0; 32]),
+            Token::Uint(0.into()),
+        ])
+    }
+
+    fn basic_order(
+        offerer: Address,
+        collection: Address,
+        id: u64,
+        price: u64,
+        fee_recipient: Address,
+    ) -> Bytes {
+        calldata(
+            "fulfillBasicOrder((address,uint256,uint256,address,address,address,uint256,uint256,uint8,uint256,uint256,bytes32,uint256,bytes32,bytes32,uint256,(uint256,address)[],bytes))",
+            &[Token::Tuple(vec![
+                Token::Address(Address::zero()),
+                Token::Uint(0.into()),
+                Token::Uint(price.into()),
+                Token::Address(offerer),
+                Token::Address(Address::zero()),
+                Token::Address(collection),
+                Token::Uint(id.into()),
+                Token::Uint(1.into()),
+                // ETH_TO_ERC721_FULL_OPEN
+                Token::Uint(0.into()),
+                Token::Uint(0.into()),
+                Token::Uint(U256::MAX),
+                Token::FixedBytes(vec![0; 32]),
+                Token::Uint(0.into()),
+                Token::FixedBytes(vec![0; 32]),
+                Token::FixedBytes(vec![0; 32]),
+                Token::Uint(1.into()),
+                Token::Array(vec![Token::Tuple(vec![
+                    Token::Uint(25.into()),
+                    Token::Address(fee_recipient),
+                ])]),
+                Token::Bytes(vec![]),
+            ])],
+        )
+    }
+
+    #[test]
+    fn basic_order_paid_in_eth() {
+        let addrs = addrs();
+        let (buyer, seller, opensea, collection) = (addrs[0], addrs[1], addrs[2], addrs[3]);
+
+        let mut inspection = mk_inspection(vec![
+            with_value(
+                mk_call(
+                    buyer,
+                    seaport(),
+                    basic_order(seller, collection, 7, 975, opensea),
+                    vec![],
+                ),
+                1000.into(),
+            ),
+            mk_call(
+                seaport(),
+                collection,
+                transfer_from(seller, buyer, 7.into()),
+                vec![0],
+            ),
+            with_value(
+                mk_call(seaport(), seller, Bytes::default(), vec![1]),
+                975.into(),
+            ),
+            with_value(
+                mk_call(seaport(), opensea, Bytes::default(), vec![2]),
+                25.into(),
+            ),
+        ]);
+        inspection.logs = vec![erc721_transfer_log(collection, seller, buyer, 7.into())];
+        inspect(&mut inspection);
+
+        let known = inspection.known();
+        assert_eq!(known.len(), 1);
+        assert_eq!(
+            known[0].as_ref().nft_trade(),
+            Some(&NftTrade {
+                collection,
+                token_id: 7.into(),
+                payment_token: *ETH,
+                price: 1000.into(),
+                buyer,
+                seller,
+            })
+        );
+        assert_eq!(inspection.protocols, crate::set![Protocol::Seaport]);
+    }
+
+    #[test]
+    fn bundle_paid_in_weth() {
+        let addrs = addrs();
+        let (buyer, seller, opensea, collection, conduit) =
+            (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4]);
+
+        let order = Token::Tuple(vec![
+            parameters(
+                seller,
+                vec![
+                    Token::Tuple(item(2, collection, 1, 1)),
+                    Token::Tuple(item(2, collection, 2, 1)),
+                ],
+                vec![
+                    consideration(ERC20_ITEM, *WETH, 976, seller),
+                    consideration(ERC20_ITEM, *WETH, 25, opensea),
+                ],
+            ),
+            Token::Bytes(vec![]),
+        ]);
+        let input = calldata(
+            "fulfillOrder(((address,address,(uint8,address,uint256,uint256,uint256)[],(uint8,address,uint256,uint256,uint256,address)[],uint8,uint256,uint256,bytes32,uint256,bytes32,uint256),bytes),bytes32)",
+            &[order, Token::FixedBytes(vec![0; 32])],
+        );
+        let mut inspection = mk_inspection(vec![
+            mk_call(buyer, seaport(), input, vec![]),
+            mk_call(seaport(), conduit, Bytes::default(), vec![0]),
+            mk_call(
+                conduit,
+                collection,
+                transfer_from(seller, buyer, 1.into()),
+                vec![0, 0],
+            ),
+            mk_call(
+                conduit,
+                collection,
+                transfer_from(seller, buyer, 2.into()),
+                vec![0, 1],
+            ),
+            mk_call(
+                conduit,
+                *WETH,
+                transfer_from(buyer, seller, 976.into()),
+                vec![0, 2],
+            ),
+            mk_call(
+                conduit,
+                *WETH,
+                transfer_from(buyer, opensea, 25.into()),
+                vec![0, 3],
+            ),
+        ]);
+        inspection.logs = vec![
+            erc721_transfer_log(collection, seller, buyer, 1.into()),
+            erc721_transfer_log(collection, seller, buyer, 2.into()),
+        ];
+        inspect(&mut inspection);
+
+        let trades = inspection
+            .known()
+            .iter()
+            .map(|a| a.as_ref().nft_trade().cloned().unwrap())
+            .map(|t| (t.token_id, t.price, t.buyer, t.seller))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            trades,
+            vec![
+                (1.into(), 501.into(), buyer, seller),
+                (2.into(), 500.into(), buyer, seller),
+            ]
+        );
+        // the conduit's call is left unknown
+        assert_eq!(inspection.unknown().len(), 1);
+    }
+
+    #[test]
+    fn accepted_bid() {
+        let addrs = addrs();
+        let (bidder, seller, opensea, collection) = (addrs[0], addrs[1], addrs[2], addrs[3]);
+
+        let order = Token::Tuple(vec![
+            parameters(
+                bidder,
+                vec![Token::Tuple(item(ERC20_ITEM, *WETH, 0, 1000))],
+                vec![
+                    consideration(2, collection, 1, bidder),
+                    consideration(ERC20_ITEM, *WETH, 25, opensea),
+                ],
+            ),
+            Token::Uint(1.into()),
+            Token::Uint(1.into()),
+            Token::Bytes(vec![]),
+            Token::Bytes(vec![]),
+        ]);
+        let input = calldata(
+            "fulfillAdvancedOrder(((address,address,(uint8,address,uint256,uint256,uint256)[],(uint8,address,uint256,uint256,uint256,address)[],uint8,uint256,uint256,bytes32,uint256,bytes32,uint256),uint120,uint120,bytes,bytes),(uint256,uint8,uint256,uint256,bytes32[])[],bytes32,address)",
+            &[
+                order,
+                Token::Array(vec![]),
+                Token::FixedBytes(vec![0; 32]),
+                Token::Address(Address::zero()),
+            ],
+        );
+        let mut inspection = mk_inspection(vec![
+            mk_call(seller, seaport(), input, vec![]),
+            mk_call(
+                seaport(),
+                *WETH,
+                transfer_from(bidder, seller, 975.into()),
+                vec![0],
+            ),
+            mk_call(
+                seaport(),
+                *WETH,
+                transfer_from(bidder, opensea, 25.into()),
+                vec![1],
+            ),
+            mk_call(
+                seaport(),
+                collection,
+                transfer_from(seller, bidder, 9.into()),
+                vec![2],
+            ),
+        ]);
+        inspection.logs = vec![erc721_transfer_log(collection, seller, bidder, 9.into())];
+        inspect(&mut inspection);
+
+        let known = inspection.known();
+        assert_eq!(known.len(), 1);
+        let trade = known[0].as_ref().nft_trade().unwrap();
+        assert_eq!(
+            (trade.token_id, trade.payment_token, trade.price),
+            (9.into(), *WETH, 1000.into())
+        );
+        assert_eq!((trade.buyer, trade.seller), (bidder, seller));
+    }
+}
@@ -0,0 +1,144 @@
+use crate::{
+    addresses::ChainConfig,
+    inspectors::UniswapV2,
+    traits::Inspector,
+    types::{Inspection, Protocol},
+};
+
+#[derive(Debug, Clone)]
+/// An inspector for SushiSwap.
+///
+/// SushiSwap is a Uniswap V2 fork with the same ABIs, so this reuses the V2
+/// decoding and only classifies the calls to Sushi's router and pairs. Note that
+/// `UniswapV2` already classifies (and tags) Sushi's swaps, and it should be used
+/// instead when inspecting arbitrages across forks, since consecutive legs share
+/// their transfers.
+pub struct SushiSwap(UniswapV2);
+
+impl Inspector for SushiSwap {
+    fn name(&self) -> &'static str {
+        "sushiswap"
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        self.0.inspect(inspection)
+    }
+}
+
+impl Default for SushiSwap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SushiSwap {
+    /// Constructor
+    pub fn new() -> Self {
+        Self(UniswapV2::new().with_fork(Protocol::Sushiswap))
+    }
+
+    /// Instantiates an inspector for Sushi's deployment on `chain`
+    pub fn with_chain(chain: &ChainConfig) -> Self {
+        Self(UniswapV2::with_chain(chain).with_fork(Protocol::Sushiswap))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        addresses::{parse_address, WETH},
+        inspectors::ERC20,
+        reducers::{ArbitrageReducer, TradeReducer},
+        test_helpers::*,
+        Reducer,
+    };
+    use ethers::{
+        abi::Token,
+        types::{Address, U256},
+    };
+
+    fn swap(amount0: u64, amount1: u64, to: Address) -> ethers::types::Bytes {
+        calldata(
+            "swap(uint256,uint256,address,bytes)",
+            &[
+                Token::Uint(amount0.into()),
+                Token::Uint(amount1.into()),
+                Token::Address(to),
+                Token::Bytes(vec![]),
+            ],
+        )
+    }
+
+    /// Sells WETH for DAI on `pair1` and the DAI back for WETH on `pair2`
+    fn arb(pair1: Address, pair2: Address) -> Inspection {
+        let addrs = addrs();
+        let (usr, dai) = (addrs[0], addrs[1]);
+        mk_inspection(vec![
+            mk_call(usr, *WETH, transfer(pair1, 100.into()), vec![0]),
+            mk_call(usr, pair1, swap(0, 150, pair2), vec![1]),
+            mk_call(pair1, dai, transfer(pair2, 150.into()), vec![1, 0]),
+            mk_call(usr, pair2, swap(110, 0, usr), vec![2]),
+            mk_call(pair2, *WETH, transfer(usr, 110.into()), vec![2, 0]),
+        ])
+    }
+
+    fn uni_pair() -> Address {
+        // WETH / USDC
+        parse_address("0xb4e16d0168e52d35cacd2c6185b44281ec28c9dc")
+    }
+
+    fn sushi_pair() -> Address {
+        // YFI / WETH
+        parse_address("0x088ee5007c98a9677165d78dd2109ae4a3d04d0c")
+    }
+
+    #[test]
+    fn only_classifies_sushi() {
+        let mut inspection = arb(uni_pair(), sushi_pair());
+        ERC20::new().inspect(&mut inspection);
+        SushiSwap::new().inspect(&mut inspection);
+        inspection.prune();
+
+        let trades = inspection
+            .known()
+            .iter()
+            .filter_map(|a| a.as_ref().trade().cloned())
+            .collect::<Vec<_>>();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].t1.to, sushi_pair());
+        assert_eq!(trades[0].t2.token, *WETH);
+        assert_eq!(inspection.protocols, crate::set![Protocol::Sushiswap]);
+    }
+
+    #[test]
+    fn tags_each_leg_with_its_fork() {
+        let unknown = addrs()[9];
+        for (pair1, pair2, protocols) in [
+            (
+                uni_pair(),
+                sushi_pair(),
+                crate::set![Protocol::Uniswap, Protocol::Sushiswap],
+            ),
+            // a pair we do not know of is not misattributed to Uniswap or Sushi
+            (
+                unknown,
+                sushi_pair(),
+                crate::set![Protocol::Uniswappy, Protocol::Sushiswap],
+            ),
+        ] {
+            let mut inspection = arb(pair1, pair2);
+            ERC20::new().inspect(&mut inspection);
+            UniswapV2::new().inspect(&mut inspection);
+            TradeReducer::new().reduce(&mut inspection);
+            ArbitrageReducer::new().reduce(&mut inspection);
+            inspection.prune();
+
+            let known = inspection.known();
+            assert_eq!(known.len(), 1);
+            let arb = known[0].as_ref().arbitrage().unwrap();
+            assert_eq!(arb.profit, U256::from(10));
+            assert_eq!(inspection.protocols, protocols);
+        }
+    }
+}
@@ -0,0 +1,320 @@
+use crate::{
+    addresses::parse_address,
+    is_nested,
+    types::{
+        actions::{Trade, Transfer},
+        Classification, Inspection, Protocol, Provenance,
+    },
+    Inspector,
+};
+use ethers::{
+    abi::{self, parse_abi, ParamType, Token},
+    contract::BaseContract,
+    types::{Address, Call as TraceCall, CallType, Log, H256, U256},
+    utils::keccak256,
+};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+
+static SYNTHETIX_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    parse_abi(&[
+        "function exchange(bytes32, uint256, bytes32) returns (uint256)",
+        "function exchangeWithTracking(bytes32, uint256, bytes32, address, bytes32) returns (uint256)",
+        "function exchangeAtomically(bytes32, uint256, bytes32, bytes32, uint256) returns (uint256)",
+    ])
+    .expect("could not parse synthetix abi")
+    .into()
+});
+
+/// `SynthExchange(address indexed account, bytes32 fromCurrencyKey, uint256 fromAmount, bytes32 toCurrencyKey, uint256 toAmount, address toAddress)`
+static SYNTH_EXCHANGE: Lazy<H256> = Lazy::new(|| {
+    H256::from(keccak256(
+        "SynthExchange(address,bytes32,uint256,bytes32,uint256,address)",
+    ))
+});
+
+/// The synths' tokens, which Synthetix only refers to by their currency keys
+static SYNTHS: Lazy<HashMap<String, Address>> = Lazy::new(|| {
+    [
+        ("sUSD", "57Ab1ec28D129707052df4dF418D58a2D46d5f51"),
+        ("sETH", "5e74C9036fb86BD7eCdcb084a0673EFc32eA31cb"),
+        ("sBTC", "fE18be6b3Bd88A2D2A7f928d00292E7a9963CfC6"),
+        ("sEUR", "D71eCFF9342A5Ced620049e616c5035F1dB98620"),
+        ("sLINK", "bBC455cb4F1B9e4bFC4B73970d360c8f032EfEE6"),
+    ]
+    .iter()
+    .map(|(key, synth)| (key.to_string(), parse_address(synth)))
+    .collect()
+});
+
+type Exchange = ([u8; 32], U256, [u8; 32]);
+type ExchangeWithTracking = ([u8; 32], U256, [u8; 32], Address, [u8; 32]);
+type ExchangeAtomically = ([u8; 32], U256, [u8; 32], [u8; 32], U256);
+
+/// A `SynthExchange` event
+struct SynthExchange {
+    account: Address,
+    from_key: [u8; 32],
+    from_amount: U256,
+    to_key: [u8; 32],
+    to_amount: U256,
+    to_address: Address,
+}
+
+#[derive(Debug, Clone)]
+/// An inspector for the exchanges between synths on Synthetix.
+///
+/// Exchanging burns the source synth and issues the destination one, without
+/// transferring either, so the trades are read from the `SynthExchange` events
+/// emitted by the Synthetix proxy. The transaction's logs must therefore have
+/// been fetched for its exchanges to be classified.
+pub struct Synthetix {
+    synthetix: &'static BaseContract,
+    synths: HashMap<String, Address>,
+}
+
+impl Default for Synthetix {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Synthetix {
+    /// Constructor
+    pub fn new() -> Self {
+        Self {
+            synthetix: &SYNTHETIX_ABI,
+            synths: SYNTHS.clone(),
+        }
+    }
+
+    /// Sets the token of the synth with the currency key `key`, e.g. for synths
+    /// which were added after this crate was released
+    pub fn with_synth(mut self, key: &str, synth: Address) -> Self {
+        self.synths.insert(key.to_owned(), synth);
+        self
+    }
+
+    /// Returns the token of a currency key, if it is known
+    fn synth(&self, key: &[u8; 32]) -> Option<Address> {
+        let len = key.iter().position(|b| *b == 0).unwrap_or(32);
+        let key = std::str::from_utf8(&key[..len]).ok()?;
+        self.synths.get(key).cloned()
+    }
+
+    /// Decodes the `(source key, source amount, destination key)` of an
+    /// exchange
+    fn decode(&self, call: &TraceCall) -> Option<([u8; 32], U256, [u8; 32])> {
+        if let Ok(args) = self
+            .synthetix
+            .decode::<Exchange, _>("exchange", &call.input)
+        {
+            Some(args)
+        } else if let Ok((src, amount, dst, _, _)) = self
+            .synthetix
+            .decode::<ExchangeWithTracking, _>("exchangeWithTracking", &call.input)
+        {
+            Some((src, amount, dst))
+        } else if let Ok((src, amount, dst, _, _)) = self
+            .synthetix
+            .decode::<ExchangeAtomically, _>("exchangeAtomically", &call.input)
+        {
+            Some((src, amount, dst))
+        } else {
+            None
+        }
+    }
+}
+
+/// Decodes a `SynthExchange` event
+fn decode_exchange(log: &Log) -> Option<SynthExchange> {
+    if log.topics.len() != 2 || log.topics[0] != *SYNTH_EXCHANGE {
+        return None;
+    }
+    let tokens = abi::decode(
+        &[
+            ParamType::FixedBytes(32),
+            ParamType::Uint(256),
+            ParamType::FixedBytes(32),
+            ParamType::Uint(256),
+            ParamType::Address,
+        ],
+        log.data.as_ref(),
+    )
+    .ok()?;
+    let key = |token: &Token| {
+        let mut key = [0; 32];
+        key.copy_from_slice(token.clone().into_fixed_bytes()?.get(..32)?);
+        Some(key)
+    };
+    Some(SynthExchange {
+        account: Address::from(log.topics[1]),
+        from_key: key(&tokens[0])?,
+        from_amount: tokens[1].clone().into_uint()?,
+        to_key: key(&tokens[2])?,
+        to_amount: tokens[3].clone().into_uint()?,
+        to_address: tokens[4].clone().into_address()?,
+    })
+}
+
+impl Inspector for Synthetix {
+    fn name(&self) -> &'static str {
+        "synthetix"
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        let actions = &inspection.actions;
+        let mut used = vec![false; inspection.logs.len()];
+        let mut exchanges = Vec::new();
+        for (i, action) in actions.iter().enumerate() {
+            let calltrace = match action.as_call() {
+                Some(inner) if inner.as_ref().call_type == CallType::Call => inner,
+                _ => continue,
+            };
+            let call = calltrace.as_ref();
+            let (from_key, from_amount, to_key) = match self.decode(call) {
+                Some(inner) => inner,
+                None => continue,
+            };
+
+            // the proxy emits the event of the exchange which was made through it
+            let event = inspection.logs.iter().enumerate().find_map(|(l, log)| {
+                if used[l] || log.address != call.to {
+                    return None;
+                }
+                let event = decode_exchange(log)?;
+                if event.account == call.from
+                    && event.from_key == from_key
+                    && event.to_key == to_key
+                    && event.from_amount == from_amount
+                {
+                    Some((l, event))
+                } else {
+                    None
+                }
+            });
+            let (l, event) = match event {
+                Some(inner) => inner,
+                None => continue,
+            };
+            let (from_synth, to_synth) = match (self.synth(&from_key), self.synth(&to_key)) {
+                (Some(from), Some(to)) => (from, to),
+                _ => continue,
+            };
+            used[l] = true;
+
+            inspection.protocols.insert(Protocol::Synthetix);
+            let trade = Classification::new(
+                Trade {
+                    t1: Transfer {
+                        from: call.from,
+                        to: call.to,
+                        amount: event.from_amount,
+                        token: from_synth,
+                    },
+                    t2: Transfer {
+                        from: call.to,
+                        to: event.to_address,
+                        amount: event.to_amount,
+                        token: to_synth,
+                    },
+                },
+                calltrace.trace_address.clone(),
+            )
+            .with_provenance(Provenance::EventDecoded);
+            exchanges.push((i, trade));
+        }
+
+        for (i, trade) in exchanges {
+            // the burns and issues of the synths are part of the exchange
+            let trace_address = trade.trace_address();
+            inspection
+                .actions
+                .iter_mut()
+                .filter(|a| is_nested(trace_address, a.trace_address()))
+                .for_each(|a| *a = Classification::Prune);
+            inspection.actions[i] = trade;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+    use ethers::types::Bytes;
+
+    fn key(key: &str) -> [u8; 32] {
+        let mut bytes = [0; 32];
+        bytes[..key.len()].copy_from_slice(key.as_bytes());
+        bytes
+    }
+
+    fn exchange_log(proxy: Address, account: Address, from: u64, to: u64) -> Log {
+        mk_log(
+            proxy,
+            vec![*SYNTH_EXCHANGE, H256::from(account)],
+            abi::encode(&[
+                Token::FixedBytes(key("sUSD").to_vec()),
+                Token::Uint(from.into()),
+                Token::FixedBytes(key("sETH").to_vec()),
+                Token::Uint(to.into()),
+                Token::Address(account),
+            ]),
+        )
+    }
+
+    fn exchange(proxy: Address, usr: Address) -> Inspection {
+        let input = calldata(
+            "exchangeWithTracking(bytes32,uint256,bytes32,address,bytes32)",
+            &[
+                Token::FixedBytes(key("sUSD").to_vec()),
+                Token::Uint(3000.into()),
+                Token::FixedBytes(key("sETH").to_vec()),
+                Token::Address(usr),
+                Token::FixedBytes(key("TRACKING").to_vec()),
+            ],
+        );
+        mk_inspection(vec![
+            mk_call(usr, proxy, input, vec![]),
+            // the proxy's target, which burns and issues the synths
+            mk_call(proxy, proxy, Bytes::default(), vec![0]),
+        ])
+    }
+
+    #[test]
+    fn exchange_from_event() {
+        let addrs = addrs();
+        let (usr, proxy) = (addrs[0], addrs[1]);
+        let mut inspection = exchange(proxy, usr);
+        inspection.logs.push(exchange_log(proxy, usr, 3000, 1));
+        Synthetix::new().inspect(&mut inspection);
+        inspection.prune();
+
+        let known = inspection.known();
+        assert_eq!(known.len(), 1);
+        let trade = known[0].as_ref().trade().unwrap();
+        assert_eq!(
+            (trade.t1.from, trade.t1.token, trade.t1.amount),
+            (usr, SYNTHS["sUSD"], 3000.into())
+        );
+        assert_eq!(
+            (trade.t2.to, trade.t2.token, trade.t2.amount),
+            (usr, SYNTHS["sETH"], 1.into())
+        );
+        assert_eq!(known[0].provenance, Provenance::EventDecoded);
+        assert!(inspection.unknown().is_empty());
+        assert_eq!(inspection.protocols, crate::set![Protocol::Synthetix]);
+    }
+
+    #[test]
+    fn needs_the_event() {
+        let addrs = addrs();
+        let (usr, proxy, other) = (addrs[0], addrs[1], addrs[2]);
+        let mut inspection = exchange(proxy, usr);
+        // the event of an exchange by another account
+        inspection.logs.push(exchange_log(proxy, other, 3000, 1));
+        Synthetix::new().inspect(&mut inspection);
+        assert!(inspection.known().is_empty());
+    }
+}
@@ -1,18 +1,33 @@
 use crate::{
-    addresses::{AAVE_LENDING_POOL_CORE, PROTOCOLS},
-    inspectors::find_matching,
+    addresses::{ChainConfig, AAVE_LENDING_POOL_CORE, PROTOCOLS, QUOTERS},
+    inspectors::{find_all_matching, find_matching},
+    is_nested, is_subtrace,
     traits::Inspector,
     types::{
-        actions::{AddLiquidity as AddLiquidityAct, Trade},
-        Classification, Inspection, Protocol, Status,
+        actions::{AddLiquidity as AddLiquidityAct, Trade, Transfer},
+        CheckedReason, Classification, Inspection, Protocol, Provenance,
     },
 };
 
 use ethers::{abi::Abi, contract::BaseContract};
 use ethers::{
     contract::decode_function_data,
-    types::{Address, Bytes, Call as TraceCall, CallType, U256},
+    types::{Address, Bytes, Call as TraceCall, U256},
 };
+use once_cell::sync::Lazy;
+use std::{borrow::Cow, collections::HashMap};
+
+static ROUTER_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    serde_json::from_str::<Abi>(include_str!("../../abi/unirouterv2.json"))
+        .expect("could not parse uniswap abi")
+        .into()
+});
+
+static PAIR_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    serde_json::from_str::<Abi>(include_str!("../../abi/unipair.json"))
+        .expect("could not parse uniswap abi")
+        .into()
+});
 
 // Type aliases for Uniswap's `swap` return types
 type SwapTokensFor = (U256, U256, Vec<Address>, Address, U256);
@@ -21,24 +36,40 @@ type PairSwap = (U256, U256, Address, Bytes);
 type AddLiquidity = (Address, Address, U256, U256, U256, U256, Address, U256);
 
 #[derive(Debug, Clone)]
-/// An inspector for Uniswap
-pub struct Uniswap {
-    router: BaseContract,
-    pair: BaseContract,
+/// An inspector for Uniswap V2 (and its clones)
+pub struct UniswapV2 {
+    router: &'static BaseContract,
+    pair: &'static BaseContract,
+    /// If set, only the calls to this fork are classified
+    fork: Option<Protocol>,
+    /// The chain's known routers and pairs, which tag the calls with their fork
+    protocols: Cow<'static, HashMap<Address, Protocol>>,
 }
 
-impl Inspector for Uniswap {
+impl Inspector for UniswapV2 {
+    fn name(&self) -> &'static str {
+        "uniswap"
+    }
+
     fn inspect(&self, inspection: &mut Inspection) {
         let num_protocols = inspection.protocols.len();
-        let actions = inspection.actions.to_vec();
+        let actions = &inspection.actions;
 
         let mut prune: Vec<usize> = Vec::new();
+        // the reads of the pairs' reserves and of the routers' quotes
+        let mut reads = Vec::new();
+        // the calls to quoters, whose simulated swaps are pruned with them
+        let mut quotes = Vec::new();
         let mut has_trade = false;
-        for i in 0..inspection.actions.len() {
-            let action = &mut inspection.actions[i];
-
+        // the classified calls, which are only written once all the calls were
+        // read so that the swaps see the transfers they are made of
+        let mut classified = Vec::new();
+        for (i, action) in actions.iter().enumerate() {
             if let Some(calltrace) = action.as_call() {
                 let call = calltrace.as_ref();
+                if matches!(self.fork, Some(fork) if fork != self.protocol(call)) {
+                    continue;
+                }
                 let preflight = self.is_preflight(call);
 
                 // we classify AddLiquidity calls in order to find sandwich attacks
@@ -47,133 +78,280 @@ impl Inspector for Uniswap {
                     .router
                     .decode::<AddLiquidity, _>("addLiquidity", &call.input)
                 {
-                    let trace_address = calltrace.trace_address.clone();
-                    *action = Classification::new(
-                        AddLiquidityAct {
-                            tokens: vec![token0, token1],
-                            amounts: vec![amount0, amount1],
-                        },
-                        trace_address,
-                    );
-                } else if let Ok((_, _, _, bytes)) =
+                    classified.push((
+                        i,
+                        Classification::new(
+                            AddLiquidityAct {
+                                tokens: vec![token0, token1],
+                                amounts: vec![amount0, amount1],
+                                position: None,
+                            },
+                            calltrace.trace_address.clone(),
+                        ),
+                    ));
+                } else if let Ok((_, _, to, bytes)) =
                     self.pair.decode::<PairSwap, _>("swap", &call.input)
                 {
                     // add the protocol
-                    let protocol = uniswappy(&call);
+                    let protocol = self.protocol(call);
                     inspection.protocols.insert(protocol);
 
-                    // skip flashswaps -- TODO: Get an example tx.
-                    if !bytes.as_ref().is_empty() {
-                        eprintln!("Flashswaps are not supported. {:?}", inspection.hash);
-                        continue;
-                    }
-
-                    let res = find_matching(
-                        // Iterate backwards
-                        actions.iter().enumerate().rev().skip(actions.len() - i),
-                        // Get a transfer
-                        |t| t.transfer(),
-                        // We just want the first transfer, no need to filter for anything
-                        |_| true,
-                        // `check_all=true` because there might be other known calls
-                        // before that, due to the Uniswap V2 architecture.
-                        true,
-                    );
-
-                    if let Some((idx_in, transfer_in)) = res {
-                        let res = find_matching(
-                            actions.iter().enumerate().skip(i + 1),
-                            // Get a transfer
-                            |t| t.transfer(),
-                            // We just want the first transfer, no need to filter for anything
-                            |_| true,
-                            // `check_all = false` because the first known external call
-                            // after the `swap` must be a transfer out
-                            false,
+                    let res = if bytes.as_ref().is_empty() {
+                        swap_transfers(actions, i, call.to, to)
+                    } else {
+                        flashswap_transfers(actions, i, call.to, &calltrace.trace_address)
+                    };
+
+                    if let Some(((idx_in, transfer_in), (idx_out, transfer_out))) = res {
+                        // change the action to a trade, whose amounts are the
+                        // ones of the transfers around the swap
+                        let trade = Classification::new(
+                            Trade {
+                                t1: transfer_in.clone(),
+                                t2: transfer_out.clone(),
+                            },
+                            calltrace.trace_address.clone(),
                         );
-
-                        if let Some((idx_out, transfer_out)) = res {
-                            // change the action to a trade
-                            *action = Classification::new(
-                                Trade {
-                                    t1: transfer_in.clone(),
-                                    t2: transfer_out.clone(),
-                                },
-                                Vec::new(),
-                            );
-                            // if a trade has been made, then we will not try
-                            // to flag this as "checked"
-                            has_trade = true;
-                            // prune the 2 trades
-                            prune.push(idx_in);
-                            prune.push(idx_out);
-                        }
+                        classified.push((i, trade.with_provenance(Provenance::TransferInferred)));
+                        // if a trade has been made, then we will not try
+                        // to flag this as "checked"
+                        has_trade = true;
+                        // prune the 2 trades
+                        prune.push(idx_in);
+                        prune.push(idx_out);
                     }
-                } else if (call.call_type == CallType::StaticCall && preflight) || self.check(call)
-                {
-                    let protocol = uniswappy(&call);
+                } else if preflight {
+                    let protocol = match QUOTERS.get(&call.to) {
+                        Some(protocol) => {
+                            quotes.push(calltrace.trace_address.clone());
+                            *protocol
+                        }
+                        None => self.protocol(call),
+                    };
+                    reads.push((calltrace.trace_address.clone(), protocol));
+                    classified.push((i, Classification::Prune));
+                } else if self.check(call) {
+                    let protocol = self.protocol(call);
                     inspection.protocols.insert(protocol);
-                    *action = Classification::Prune;
+                    classified.push((i, Classification::Prune));
                 }
             }
         }
 
+        for (i, action) in classified {
+            inspection.actions[i] = action;
+        }
+
         prune
             .iter()
             .for_each(|p| inspection.actions[*p] = Classification::Prune);
+        for quote in quotes {
+            inspection
+                .actions
+                .iter_mut()
+                .filter(|a| is_nested(&quote, a.trace_address()))
+                .for_each(|a| *a = Classification::Prune);
+        }
+
+        // Bots read the reserves or the quotes before trading, to check whether
+        // their trade is profitable. The reads only tell us which protocols were
+        // traded on if a trade, on any protocol, followed them. The reads which
+        // no trade follows yet are resolved once all the inspectors ran, since
+        // the trade may be classified by another one.
+        if !reads.is_empty() {
+            for (read, protocol) in reads {
+                if inspection.trades_after(&read) {
+                    inspection.protocols.insert(protocol);
+                } else {
+                    inspection.preflights.push((read, protocol));
+                }
+            }
+            return;
+        }
 
         // If there are less than 2 classified actions (i.e. we didn't execute more
         // than 1 trade attempt, and if there were checked protocols
-        // in this transaction, then that means there was an arb check which reverted early
+        // in this transaction, then that means there was an arb check which reverted early.
+        // Approvals are not attempts, bots often make them along with their trades.
         if inspection.protocols.len() > num_protocols
             && inspection
-                .actions
-                .iter()
-                .filter_map(|x| x.as_action())
+                .known_actions()
+                .filter(|action| action.approval().is_none())
                 .count()
                 < 2
             && !has_trade
         {
-            inspection.status = Status::Checked;
+            let reason = CheckedReason::new(
+                inspection.revert_reason.as_ref(),
+                CheckedReason::ArbRevertedEarly,
+            );
+            inspection.mark_checked(reason);
         }
     }
 }
 
-fn uniswappy(call: &TraceCall) -> Protocol {
-    if let Some(protocol) = PROTOCOLS.get(&call.to) {
+/// The transfer in and transfer out of a swap along with their indices
+type SwapTransfers<'a> = ((usize, &'a Transfer), (usize, &'a Transfer));
+
+/// Finds the transfers of the regular `swap` at index `i` of `pair`, which
+/// sends its output to `recipient`
+fn swap_transfers(
+    actions: &[Classification],
+    i: usize,
+    pair: Address,
+    recipient: Address,
+) -> Option<SwapTransfers<'_>> {
+    let transfer_in = find_matching(
+        // Iterate backwards
+        actions.iter().enumerate().rev().skip(actions.len() - i),
+        // Get a transfer
+        |t| t.transfer(),
+        // We just want the first transfer, no need to filter for anything
+        |_| true,
+        // `check_all=true` because there might be other known calls
+        // before that, due to the Uniswap V2 architecture.
+        true,
+    )?;
+
+    let transfers_out = find_all_matching(
+        actions.iter().enumerate().skip(i + 1),
+        // Get a transfer
+        |t| t.transfer(),
+        // Sent by the pair
+        |t| t.from == pair,
+        // `check_all = false` because the first known external calls
+        // after the `swap` must be its transfers out
+        false,
+    );
+    // the pair may make other transfers out before paying the recipient, e.g.
+    // when it is called back into. Fee-on-transfer tokens only pay their fee
+    // collector within their own `transfer`, which `ERC20` credits from logs
+    let transfer_out = transfers_out
+        .iter()
+        .find(|(_, t)| t.to == recipient)
+        .or_else(|| transfers_out.first())
+        .copied()?;
+
+    Some((transfer_in, transfer_out))
+}
+
+/// Finds the transfers of the flashswap at index `i`.
+///
+/// Flashswaps transfer the tokens out optimistically and then call back into the
+/// recipient (`uniswapV2Call`), which must repay the pair before `swap` returns.
+/// The repayment is therefore nested inside the callback's subtrace instead of
+/// being a sibling of the `swap` call.
+fn flashswap_transfers<'a>(
+    actions: &'a [Classification],
+    i: usize,
+    pair: Address,
+    swap_trace: &[usize],
+) -> Option<SwapTransfers<'a>> {
+    let transfer_out = find_matching(
+        actions.iter().enumerate().skip(i + 1),
+        |t| t.transfer(),
+        |t| t.from == pair,
+        false,
+    )?;
+
+    let transfer_in = find_matching(
+        actions
+            .iter()
+            .enumerate()
+            .skip(i + 1)
+            .filter(|(_, a)| is_subtrace(swap_trace, a.trace_address())),
+        |t| t.transfer(),
+        |t| t.to == pair,
+        true,
+    )
+    // the pair may also have been paid before the swap
+    .or_else(|| {
+        find_matching(
+            actions.iter().enumerate().rev().skip(actions.len() - i),
+            |t| t.transfer(),
+            |t| t.to == pair,
+            true,
+        )
+    })?;
+
+    // Repaying in the same token is a flash loan, not a trade
+    if transfer_in.1.token == transfer_out.1.token {
+        return None;
+    }
+
+    Some((transfer_in, transfer_out))
+}
+
+/// Tags a call with the fork whose router or pair it touched, based on the
+/// addresses we know of. Pairs of unknown forks are `Uniswappy`.
+pub(crate) fn uniswappy(call: &TraceCall) -> Protocol {
+    tag(&PROTOCOLS, call)
+}
+
+fn tag(protocols: &HashMap<Address, Protocol>, call: &TraceCall) -> Protocol {
+    if let Some(protocol) = protocols.get(&call.to) {
         *protocol
-    } else if let Some(protocol) = PROTOCOLS.get(&call.from) {
+    } else if let Some(protocol) = protocols.get(&call.from) {
         *protocol
     } else {
         Protocol::Uniswappy
     }
 }
 
-impl Uniswap {
+impl Default for UniswapV2 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UniswapV2 {
     /// Constructor
     pub fn new() -> Self {
         Self {
-            router: BaseContract::from({
-                serde_json::from_str::<Abi>(include_str!("../../abi/unirouterv2.json"))
-                    .expect("could not parse uniswap abi")
-            }),
-            pair: BaseContract::from({
-                serde_json::from_str::<Abi>(include_str!("../../abi/unipair.json"))
-                    .expect("could not parse uniswap abi")
-            }),
+            router: &ROUTER_ABI,
+            pair: &PAIR_ABI,
+            fork: None,
+            protocols: Cow::Borrowed(&PROTOCOLS),
+        }
+    }
+
+    /// Instantiates an inspector for `chain`, whose known routers and pairs
+    /// the forks are told apart with
+    pub fn with_chain(chain: &ChainConfig) -> Self {
+        Self {
+            protocols: Cow::Owned(chain.protocols.clone()),
+            ..Self::new()
         }
     }
 
+    /// Only classifies the calls to `fork`
+    pub(crate) fn with_fork(mut self, fork: Protocol) -> Self {
+        self.fork = Some(fork);
+        self
+    }
+
+    /// The fork whose router or pair the call touched
+    fn protocol(&self, call: &TraceCall) -> Protocol {
+        tag(&self.protocols, call)
+    }
+
+    /// Whether the call reads a pair's reserves or a router's quote, which bots
+    /// do to check whether their trades are profitable, or asks a known quoter.
+    /// Other view calls, static or not, may be part of the protocols which make
+    /// them, so they are left alone.
     fn is_preflight(&self, call: &TraceCall) -> bool {
-        // There's a function selector clash here with Aave's getReserves
-        // function in the core, which we do not care about here
-        // https://github.com/aave/aave-protocol/search?q=%22function+getReserves%28%29%22
-        call.to != *AAVE_LENDING_POOL_CORE
-            && call.from != *AAVE_LENDING_POOL_CORE
-            && call
-                .input
-                .as_ref()
-                .starts_with(&ethers::utils::id("getReserves()"))
+        let input = call.input.as_ref();
+        if QUOTERS.contains_key(&call.to) {
+            true
+        } else if input.starts_with(&ethers::utils::id("getReserves()")) {
+            // There's a function selector clash here with Aave's getReserves
+            // function in the core, which we do not care about here
+            // https://github.com/aave/aave-protocol/search?q=%22function+getReserves%28%29%22
+            call.to != *AAVE_LENDING_POOL_CORE && call.from != *AAVE_LENDING_POOL_CORE
+        } else {
+            input.starts_with(&ethers::utils::id("getAmountsOut(uint256,address[])"))
+                || input.starts_with(&ethers::utils::id("getAmountsIn(uint256,address[])"))
+        }
     }
 
     // There MUST be 1 `swap` call in the traces either to the Pair directly
@@ -209,16 +387,21 @@ pub mod tests {
     use crate::{
         addresses::ADDRESSBOOK,
         reducers::{ArbitrageReducer, TradeReducer},
-        types::{Protocol, Status},
+        types::inspection::TraceWrapper,
+        types::{Protocol, RevertReason, Status},
         Reducer,
     };
     use crate::{inspectors::ERC20, types::Inspection, Inspector};
-    use ethers::types::U256;
+    use ethers::{
+        abi::Token,
+        types::{CallType, U256},
+    };
+    use std::convert::TryInto;
 
     // inspector that does all 3 transfer/trade/arb combos
     struct MyInspector {
         erc20: ERC20,
-        uni: Uniswap,
+        uni: UniswapV2,
         trade: TradeReducer,
         arb: ArbitrageReducer,
     }
@@ -227,6 +410,7 @@ pub mod tests {
         fn inspect(&self, inspection: &mut Inspection) {
             self.erc20.inspect(inspection);
             self.uni.inspect(inspection);
+            inspection.resolve_preflights();
 
             self.trade.reduce(inspection);
             self.arb.reduce(inspection);
@@ -237,11 +421,20 @@ pub mod tests {
         fn new() -> Self {
             Self {
                 erc20: ERC20::new(),
-                uni: Uniswap::new(),
+                uni: UniswapV2::new(),
                 trade: TradeReducer::new(),
                 arb: ArbitrageReducer::new(),
             }
         }
+
+        fn with_chain(chain: &ChainConfig) -> Self {
+            Self {
+                erc20: ERC20::with_chain(chain),
+                uni: UniswapV2::with_chain(chain),
+                trade: TradeReducer::with_chain(chain),
+                arb: ArbitrageReducer::with_chain(chain),
+            }
+        }
     }
 
     mod arbitrages {
@@ -270,6 +463,58 @@ pub mod tests {
             );
         }
 
+        #[test]
+        #[cfg(feature = "serde")]
+        // the same arb, classified by all the inspectors and reducers
+        fn uni_sushi_arb_snapshot() {
+            assert_inspection_matches(
+                "0xd9306dc8c1230cc0faef22a8442d0994b8fc9a8f4c9faeab94a9a7eac8e59710",
+                include_str!(
+                    "../../res/snapshots/0xd9306dc8c1230cc0faef22a8442d0994b8fc9a8f4c9faeab94a9a7eac8e59710.json"
+                ),
+            );
+        }
+
+        #[test]
+        fn same_arb_on_another_chain() {
+            let hash = "0xd9306dc8c1230cc0faef22a8442d0994b8fc9a8f4c9faeab94a9a7eac8e59710";
+            let arbitrum = ChainConfig::arbitrum();
+            // the arb, as if its pairs had been deployed on Arbitrum, where
+            // WETH has another address
+            let traces = TRACES
+                .iter()
+                .filter(|t| t.transaction_hash == Some(hash.parse().unwrap()))
+                .collect::<Vec<_>>();
+            let traces = serde_json::to_string(&traces).unwrap().replace(
+                &format!("{:?}", *crate::addresses::WETH),
+                &format!("{:?}", arbitrum.weth),
+            );
+            let traces: Vec<ethers::types::Trace> = serde_json::from_str(&traces).unwrap();
+
+            // the pairs are only known to be Sushi's and Uniswap's on mainnet
+            for (chain, mut inspection, protocols) in vec![
+                (
+                    ChainConfig::mainnet(),
+                    get_trace(hash),
+                    crate::set![Protocol::Sushiswap, Protocol::Uniswap],
+                ),
+                (
+                    arbitrum,
+                    TraceWrapper(traces).try_into().unwrap(),
+                    crate::set![Protocol::Uniswappy],
+                ),
+            ] {
+                MyInspector::with_chain(&chain).inspect(&mut inspection);
+                let arb = inspection.arbitrages().next().cloned().unwrap();
+                assert_eq!(arb.token, chain.weth);
+                assert_eq!(
+                    arb.profit,
+                    U256::from_dec_str("626678385524850545").unwrap()
+                );
+                assert_eq!(inspection.protocols, protocols);
+            }
+        }
+
         // https://etherscan.io/tx/0xdfeae07360e2d7695a498e57e2054c658d1d78bbcd3c763fc8888b5433b6c6d5
         #[test]
         fn xsp_xfi_eth_arb() {
@@ -312,62 +557,66 @@ pub mod tests {
             get_trace("123d03cef9ccd4230d111d01cf1785aed4242eb2e1e542bd792d025eb7e3cc84");
         let uni = MyInspector::new();
         uni.inspect(&mut inspection);
-        assert_eq!(inspection.status, Status::Checked); // This is a check
+        assert_eq!(inspection.status, Status::Checked);
+        assert_eq!(
+            inspection.checked_reason,
+            Some(CheckedReason::ArbRevertedEarly)
+        ); // This is a check
         let known = inspection.known();
         let transfer = known[0].as_ref().transfer().unwrap();
         assert_eq!(ADDRESSBOOK.get(&transfer.token).unwrap(), "ETH");
     }
 
     #[test]
-    // Traces which either reverted or returned early on purpose, after checking
-    // for an arb opportunity and seeing that it won't work.
-    fn checked() {
-        let both = crate::set![Protocol::Uniswap, Protocol::Sushiswap];
-        let uni = crate::set![Protocol::Uniswap];
-        for (trace, protocols) in &[
-            (
-                "0x2f85ce5bb5f7833e052897fa4a070615a4e21a247e1ccc2347a3882f0e73943d",
-                &both,
-            ),
-            (
-                // sakeswap is uniswappy
-                "0xd9df5ae2e9e18099913559f71473866758df3fd25919be605c71c300e64165fd",
-                &crate::set![Protocol::Uniswappy, Protocol::Uniswap],
-            ),
+    fn checked_reason_from_revert_reason() {
+        for (msg, reason) in &[
             (
-                "0xfd24e512dc90bd1ca8a4f7987be6122c1fa3221b261e8728212f2f4d980ee4cd",
-                &both,
+                "UniswapV2Router: INSUFFICIENT_OUTPUT_AMOUNT",
+                CheckedReason::InsufficientOutput,
             ),
+            ("Too little received", CheckedReason::InsufficientOutput),
+            ("no profit", CheckedReason::NoProfitableTrade),
             (
-                "0xf5f0b7e1c1761eff33956965f90b6d291fa2ff3c9907b450d483a58932c54598",
-                &both,
-            ),
-            (
-                "0x4cf1a912197c2542208f7c1b5624fa5ea75508fa45f41c28f7e6aaa443d14db2",
-                &both,
-            ),
-            (
-                "0x9b08b7c8efe5cfd40c012b956a6031f60c076bc07d5946888a0d55e5ed78b38a",
-                &uni,
-            ),
-            (
-                "0xe43734199366c665e341675e0f6ea280745d7d801924815b2c642dc83c8756d6",
-                &both,
-            ),
-            (
-                "0x243b4b5bf96d345f690f6b17e75031dc634d0e97c47d73cbecf2327250077591",
-                &both,
-            ),
-            (
-                "0x52311e6ec870f530e84f79bbb08dce05c95d80af5a3cb29ab85d128a15dbea8d",
-                &uni,
+                "Ownable: caller is not the owner",
+                CheckedReason::ArbRevertedEarly,
             ),
+        ] {
+            // Parity does not report the output of failed calls, so the
+            // reason is set as if the trace came from Geth
+            let mut inspection =
+                get_trace("123d03cef9ccd4230d111d01cf1785aed4242eb2e1e542bd792d025eb7e3cc84");
+            inspection.revert_reason = Some(RevertReason::Error(msg.to_string()));
+            MyInspector::new().inspect(&mut inspection);
+            assert_eq!(inspection.status, Status::Checked);
+            assert_eq!(inspection.checked_reason.as_ref(), Some(reason));
+        }
+    }
+
+    #[test]
+    // Traces which either reverted or returned early on purpose, after checking
+    // for an arb opportunity and seeing that it won't work.
+    fn checked() {
+        for trace in &[
+            "0x2f85ce5bb5f7833e052897fa4a070615a4e21a247e1ccc2347a3882f0e73943d",
+            "0xd9df5ae2e9e18099913559f71473866758df3fd25919be605c71c300e64165fd",
+            "0xfd24e512dc90bd1ca8a4f7987be6122c1fa3221b261e8728212f2f4d980ee4cd",
+            "0xf5f0b7e1c1761eff33956965f90b6d291fa2ff3c9907b450d483a58932c54598",
+            "0x4cf1a912197c2542208f7c1b5624fa5ea75508fa45f41c28f7e6aaa443d14db2",
+            "0x9b08b7c8efe5cfd40c012b956a6031f60c076bc07d5946888a0d55e5ed78b38a",
+            "0xe43734199366c665e341675e0f6ea280745d7d801924815b2c642dc83c8756d6",
+            "0x243b4b5bf96d345f690f6b17e75031dc634d0e97c47d73cbecf2327250077591",
+            "0x52311e6ec870f530e84f79bbb08dce05c95d80af5a3cb29ab85d128a15dbea8d",
         ] {
             let mut inspection = get_trace(trace);
             let uni = MyInspector::new();
             uni.inspect(&mut inspection);
             assert_eq!(inspection.status, Status::Checked);
-            assert_eq!(inspection.protocols, **protocols);
+            assert_eq!(
+                inspection.checked_reason,
+                Some(CheckedReason::ArbRevertedEarly)
+            );
+            // the reserves were read, but nothing was traded
+            assert!(inspection.protocols.is_empty());
         }
     }
 
@@ -379,20 +628,384 @@ pub mod tests {
         uni.inspect(&mut inspection);
 
         let known = inspection.known();
-
-        assert_eq!(known.len(), 4);
+        assert_eq!(known.len(), 5);
         let t1 = known[0].as_ref().transfer().unwrap();
         assert_eq!(
             t1.amount,
             U256::from_dec_str("155025667786800022191").unwrap()
         );
-        let trade = known[1].as_ref().trade().unwrap();
+        // the bot approves the token it sells before trading it
+        let approval = known[1].as_ref().approval().unwrap();
+        let trade = known[2].as_ref().trade().unwrap();
+        assert_eq!(approval.token, trade.t1.token);
         assert_eq!(
             trade.t1.amount,
             U256::from_dec_str("28831175112148480867").unwrap()
         );
-        let _t2 = known[2].as_ref().transfer().unwrap();
-        let _t3 = known[3].as_ref().transfer().unwrap();
+        let _t2 = known[3].as_ref().transfer().unwrap();
+        let _t3 = known[4].as_ref().transfer().unwrap();
+    }
+
+    #[test]
+    // https://etherscan.io/tx/0x97afae49a25201dbb34502d36a7903b51754362ceb231ff775c07db540f4a3d6
+    // Flashswaps WETH out of the DAI/WETH pair, trades it for BAT, liquidates
+    // an Aave position for DAI and repays the pair in DAI inside the callback
+    fn flashswap() {
+        let mut inspection = read_trace("liquidation_1.json");
+        let uni = MyInspector::new();
+        uni.inspect(&mut inspection);
+
+        let known = inspection.known();
+
+        // the flashswap is repaid in DAI for the WETH it lent out
+        let flashswap = known[0].as_ref().trade().unwrap();
+        assert_eq!(ADDRESSBOOK.get(&flashswap.t1.token).unwrap(), "DAI");
+        assert_eq!(
+            flashswap.t1.amount,
+            U256::from_dec_str("449264275113432407806").unwrap()
+        );
+        assert_eq!(ADDRESSBOOK.get(&flashswap.t2.token).unwrap(), "WETH");
+        assert_eq!(
+            flashswap.t2.amount,
+            U256::from_dec_str("1049109768528661108").unwrap()
+        );
+
+        // the borrowed WETH gets traded for BAT inside the callback
+        let trade = known[1].as_ref().trade().unwrap();
+        assert_eq!(trade.t1.amount, flashswap.t2.amount);
+        assert_eq!(ADDRESSBOOK.get(&trade.t2.token).unwrap(), "BAT");
+    }
+
+    #[test]
+    fn fee_on_transfer_swap() {
+        let addrs = addrs();
+        let (user, router, pair, token_in, token_out, collector) =
+            (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4], addrs[5]);
+        let swap = calldata(
+            "swap(uint256,uint256,address,bytes)",
+            &[
+                Token::Uint(0.into()),
+                Token::Uint(90.into()),
+                Token::Address(user),
+                Token::Bytes(vec![]),
+            ],
+        );
+        let mut inspection = mk_inspection(vec![
+            mk_call(
+                router,
+                token_in,
+                transfer_from(user, pair, 100.into()),
+                vec![0],
+            ),
+            mk_call(router, pair, swap, vec![1]),
+            mk_call(pair, token_out, transfer(user, 90.into()), vec![1, 0]),
+        ]);
+        // both tokens take a 10% fee within their `transfer`, which only their
+        // logs show
+        inspection.logs = vec![
+            transfer_log(token_in, user, pair, 90),
+            transfer_log(token_in, user, collector, 10),
+            transfer_log(token_out, pair, user, 81),
+            transfer_log(token_out, pair, collector, 9),
+        ];
+        let uni = MyInspector::new();
+        uni.inspect(&mut inspection);
+
+        let known = inspection.known();
+        let trade = known
+            .iter()
+            .find_map(|action| action.as_ref().trade())
+            .unwrap();
+        assert_eq!(trade.t1.amount, 90.into());
+        assert_eq!(trade.t2.to, user);
+        assert_eq!(trade.t2.amount, 81.into());
+        assert_eq!(inspection.transfers().count(), 0);
+    }
+
+    #[test]
+    fn swaps_paid_out_to_several_recipients() {
+        let addrs = addrs();
+        let (user, pair, token_in, token_out, other) =
+            (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4]);
+        let swap = calldata(
+            "swap(uint256,uint256,address,bytes)",
+            &[
+                Token::Uint(0.into()),
+                Token::Uint(90.into()),
+                Token::Address(user),
+                Token::Bytes(vec![]),
+            ],
+        );
+        // a pair whose token calls back into it, so that it makes another
+        // transfer before paying the swap's recipient
+        let mut inspection = mk_inspection(vec![
+            mk_call(user, token_in, transfer(pair, 100.into()), vec![0]),
+            mk_call(user, pair, swap, vec![1]),
+            mk_call(pair, token_out, transfer(other, 10.into()), vec![1, 0]),
+            mk_call(pair, token_out, transfer(user, 90.into()), vec![1, 1]),
+        ]);
+        MyInspector::new().inspect(&mut inspection);
+        let trade = inspection.trades().next().unwrap();
+        assert_eq!((trade.t2.to, trade.t2.amount), (user, 90.into()));
+
+        assert_eq!(inspection.transfers().next().unwrap().to, other);
+    }
+
+    #[test]
+    fn approvals_are_not_trade_attempts() {
+        let addrs = addrs();
+        let (bot, pair, token_in, token_out, router) =
+            (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4]);
+        let approve = calldata(
+            "approve(address,uint256)",
+            &[Token::Address(router), Token::Uint(U256::MAX)],
+        );
+        let swap = calldata(
+            "swap(uint256,uint256,address,bytes)",
+            &[
+                Token::Uint(0.into()),
+                Token::Uint(90.into()),
+                Token::Address(bot),
+                Token::Bytes(vec![]),
+            ],
+        );
+        let get_reserves = calldata("getReserves()", &[]);
+
+        // a bot which approves the router and then trades
+        let mut inspection = mk_inspection(vec![
+            mk_call(bot, token_in, approve.clone(), vec![0]),
+            mk_call(bot, token_in, transfer(pair, 100.into()), vec![1]),
+            mk_call(bot, pair, swap, vec![2]),
+            mk_call(pair, token_out, transfer(bot, 90.into()), vec![2, 0]),
+        ]);
+        MyInspector::new().inspect(&mut inspection);
+        assert_eq!(inspection.status, Status::Success);
+        assert_eq!(inspection.trades().count(), 1);
+        let approval = inspection
+            .known_actions()
+            .find_map(|action| action.approval())
+            .unwrap();
+        assert_eq!(approval.owner, bot);
+        assert_eq!(approval.spender, router);
+        assert_eq!(approval.amount, U256::MAX);
+        assert_eq!(approval.token, token_in);
+
+        // a bot which approves the router, sends its tokens and then bails
+        // out after checking the reserves
+        let mut inspection = mk_inspection(vec![
+            mk_call(bot, token_in, approve, vec![0]),
+            mk_call(bot, token_in, transfer(pair, 100.into()), vec![1]),
+            mk_call(bot, pair, get_reserves, vec![2]),
+        ]);
+        MyInspector::new().inspect(&mut inspection);
+        assert_eq!(inspection.status, Status::Checked);
+        assert_eq!(
+            inspection.checked_reason,
+            Some(CheckedReason::ArbRevertedEarly)
+        );
+    }
+
+    #[test]
+    fn stops_at_the_first_known_call_after_the_swap() {
+        let addrs = addrs();
+        let (bot, pair, token_in, token_out, other) =
+            (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4]);
+        let swap = calldata(
+            "swap(uint256,uint256,address,bytes)",
+            &[
+                Token::Uint(0.into()),
+                Token::Uint(90.into()),
+                Token::Address(bot),
+                Token::Bytes(vec![]),
+            ],
+        );
+        let approve = calldata(
+            "approve(address,uint256)",
+            &[Token::Address(other), Token::Uint(U256::MAX)],
+        );
+
+        // the swap does not pay out, and the pair's later transfer is not
+        // its output
+        let mut inspection = mk_inspection(vec![
+            mk_call(bot, token_in, transfer(pair, 100.into()), vec![0]),
+            mk_call(bot, pair, swap, vec![1]),
+            mk_call(pair, token_out, approve, vec![1, 0]),
+            mk_call(pair, token_out, transfer(other, 90.into()), vec![1, 1]),
+        ]);
+        MyInspector::new().inspect(&mut inspection);
+        assert_eq!(inspection.trades().count(), 0);
+    }
+
+    #[test]
+    fn quotes_before_a_trade() {
+        let addrs = addrs();
+        let (bot, router, pair, pool, weth, dai) =
+            (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4], addrs[5]);
+        let quote = calldata(
+            "getAmountsOut(uint256,address[])",
+            &[
+                Token::Uint(100.into()),
+                Token::Array(vec![Token::Address(weth), Token::Address(dai)]),
+            ],
+        );
+        let read = |call: Classification| match call {
+            Classification::Unknown(mut calltrace) => {
+                calltrace.call.call_type = CallType::StaticCall;
+                Classification::Unknown(calltrace)
+            }
+            call => call,
+        };
+        let swap = calldata(
+            "swap(uint256,uint256,address,bytes)",
+            &[
+                Token::Uint(0.into()),
+                Token::Uint(90.into()),
+                Token::Address(bot),
+                Token::Bytes(vec![]),
+            ],
+        );
+
+        // the bot quotes its trade on the router, which reads the reserves,
+        // and then trades on the pair
+        let mut inspection = mk_inspection(vec![
+            read(mk_call(bot, router, quote.clone(), vec![0])),
+            read(mk_call(
+                router,
+                pair,
+                calldata("getReserves()", &[]),
+                vec![0, 0],
+            )),
+            mk_call(bot, weth, transfer(pair, 100.into()), vec![1]),
+            mk_call(bot, pair, swap, vec![2]),
+            mk_call(pair, dai, transfer(bot, 90.into()), vec![2, 0]),
+        ]);
+        MyInspector::new().inspect(&mut inspection);
+        assert_eq!(inspection.status, Status::Success);
+        assert_eq!(inspection.trades().count(), 1);
+        assert!(inspection.unknown().is_empty());
+        assert_eq!(inspection.protocols, crate::set![Protocol::Uniswappy]);
+
+        // or trades on another protocol instead
+        let trade = Trade {
+            t1: Transfer {
+                from: bot,
+                to: pool,
+                amount: 100.into(),
+                token: weth,
+            },
+            t2: Transfer {
+                from: pool,
+                to: bot,
+                amount: 91.into(),
+                token: dai,
+            },
+        };
+        let mut inspection = mk_inspection(vec![
+            read(mk_call(bot, router, quote.clone(), vec![0])),
+            Classification::new(trade.clone(), vec![1]),
+        ]);
+        MyInspector::new().inspect(&mut inspection);
+        assert_eq!(inspection.status, Status::Success);
+        assert_eq!(inspection.protocols, crate::set![Protocol::Uniswappy]);
+
+        // which is classified by an inspector running after this one
+        #[derive(Debug)]
+        struct Pool(Trade);
+        impl Inspector for Pool {
+            fn name(&self) -> &'static str {
+                "pool"
+            }
+
+            fn inspect(&self, inspection: &mut Inspection) {
+                inspection.actions[1] = Classification::new(self.0.clone(), vec![1]);
+            }
+        }
+        let mut inspection = mk_inspection(vec![
+            read(mk_call(bot, router, quote.clone(), vec![0])),
+            mk_call(bot, pool, calldata("swap()", &[]), vec![1]),
+        ]);
+        let registry = crate::inspectors::InspectorRegistry::new(vec![
+            Box::new(UniswapV2::new()),
+            Box::new(Pool(trade)),
+        ]);
+        registry.inspect_all(&mut inspection);
+        assert_eq!(inspection.status, Status::Success);
+        assert_eq!(inspection.protocols, crate::set![Protocol::Uniswappy]);
+
+        // the bot bails out after the quote
+        let mut inspection = mk_inspection(vec![
+            read(mk_call(bot, router, quote, vec![0])),
+            mk_call(bot, weth, transfer(pair, 100.into()), vec![1]),
+        ]);
+        MyInspector::new().inspect(&mut inspection);
+        assert_eq!(inspection.status, Status::Checked);
+        assert_eq!(
+            inspection.checked_reason,
+            Some(CheckedReason::ArbRevertedEarly)
+        );
+        assert!(inspection.unknown().is_empty());
+        assert!(inspection.protocols.is_empty());
+    }
+
+    #[test]
+    fn quoter_probe_before_a_trade() {
+        let addrs = addrs();
+        let (bot, pool, pair, weth, dai) = (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4]);
+        let quoter = *QUOTERS.keys().next().unwrap();
+        let quote = calldata(
+            "quoteExactInputSingle(address,address,uint24,uint256,uint160)",
+            &[
+                Token::Address(weth),
+                Token::Address(dai),
+                Token::Uint(3000.into()),
+                Token::Uint(100.into()),
+                Token::Uint(0.into()),
+            ],
+        );
+        let probe = calldata(
+            "swap(address,bool,int256,uint160,bytes)",
+            &[
+                Token::Address(quoter),
+                Token::Bool(true),
+                Token::Int(100.into()),
+                Token::Uint(0.into()),
+                Token::Bytes(vec![]),
+            ],
+        );
+        let swap = calldata(
+            "swap(uint256,uint256,address,bytes)",
+            &[
+                Token::Uint(0.into()),
+                Token::Uint(90.into()),
+                Token::Address(bot),
+                Token::Bytes(vec![]),
+            ],
+        );
+
+        // the quoter simulates the swap on the V3 pool, whose callback reverts
+        // with the amounts, and the bot then trades on a V2 pair
+        let mut inspection = mk_inspection(vec![
+            mk_call(bot, quoter, quote, vec![0]),
+            mk_call(quoter, pool, probe, vec![0, 0]),
+            mk_call(pool, dai, transfer(quoter, 91.into()), vec![0, 0, 0]),
+            // uniswapV3SwapCallback
+            mk_call(pool, quoter, Bytes::default(), vec![0, 0, 1]),
+            mk_call(bot, weth, transfer(pair, 100.into()), vec![1]),
+            mk_call(bot, pair, swap, vec![2]),
+            mk_call(pair, dai, transfer(bot, 90.into()), vec![2, 0]),
+        ]);
+        MyInspector::new().inspect(&mut inspection);
+        assert_eq!(inspection.status, Status::Success);
+        let trades = inspection.trades().cloned().collect::<Vec<_>>();
+        assert_eq!(trades.len(), 1);
+        assert_eq!((trades[0].t1.from, trades[0].t2.amount), (bot, 90.into()));
+        // the simulated swap is pruned with the quote
+        assert_eq!(inspection.known().len(), 1);
+        assert!(inspection.unknown().is_empty());
+        assert_eq!(
+            inspection.protocols,
+            crate::set![Protocol::Uniswappy, Protocol::UniswapV3]
+        );
     }
 
     mod simple_transfers {
@@ -0,0 +1,627 @@
+use crate::{
+    addresses::ChainConfig,
+    inspectors::find_matching,
+    is_nested,
+    traits::Inspector,
+    types::{
+        actions::{Trade, Transfer},
+        Classification, Inspection, Protocol, Provenance, TraceAddress,
+    },
+};
+
+use ethers::{
+    abi::{Abi, FunctionExt},
+    contract::BaseContract,
+    types::{Address, Bytes, U256},
+};
+use once_cell::sync::Lazy;
+use std::collections::HashSet;
+
+static ROUTER_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    serde_json::from_str::<Abi>(include_str!("../../abi/unirouterv3.json"))
+        .expect("could not parse uniswap v3 abi")
+        .into()
+});
+
+// Type aliases for the SwapRouter's params structs. The `*Single` structs
+// share the same layout, as do the multi-hop ones.
+type SwapSingle = (Address, Address, u32, Address, U256, U256, U256, U256);
+type SwapPath = (Bytes, Address, U256, U256, U256);
+
+/// The size of an encoded address in a V3 path
+const ADDR_SIZE: usize = 20;
+/// The size of an encoded fee in a V3 path
+const FEE_SIZE: usize = 3;
+/// The size of a single `token | fee | token` hop in a V3 path
+const HOP_SIZE: usize = ADDR_SIZE + FEE_SIZE + ADDR_SIZE;
+
+/// The router's functions which settle the leftover balances of a multicall
+const PAYMENTS: [&str; 3] = ["refundETH", "unwrapWETH9", "sweepToken"];
+
+#[derive(Debug, Clone)]
+/// An inspector for Uniswap V3's SwapRouter
+pub struct UniswapV3 {
+    router: &'static BaseContract,
+    /// The routers whose calls are inspected, if only the chain's known ones
+    /// are. Otherwise all the calls which the router's ABI decodes are.
+    routers: Option<HashSet<Address>>,
+}
+
+impl Inspector for UniswapV3 {
+    fn name(&self) -> &'static str {
+        "uniswapv3"
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        let actions = &inspection.actions;
+
+        // The router batches calls via `multicall`, which delegatecalls itself
+        // with each item, so item `k` is the `k`th subtrace of the multicall
+        let mut calls: Vec<(Bytes, TraceAddress)> = Vec::new();
+        for calltrace in actions.iter().filter_map(|a| a.as_call()) {
+            if !self.is_router(calltrace.as_ref().to) {
+                continue;
+            }
+            let input = &calltrace.as_ref().input;
+            if let Ok(data) = self.router.decode::<Vec<Vec<u8>>, _>("multicall", input) {
+                for (k, data) in data.into_iter().enumerate() {
+                    let mut trace_address = calltrace.trace_address.clone();
+                    trace_address.push(k);
+                    calls.push((data.into(), trace_address));
+                }
+            } else if !calls.iter().any(|(_, t)| t == &calltrace.trace_address) {
+                calls.push((input.clone(), calltrace.trace_address.clone()));
+            }
+        }
+
+        let mut prune = Vec::new();
+        let mut classified = Vec::new();
+        for (input, trace_address) in calls {
+            let i = match actions
+                .iter()
+                .position(|a| a.trace_address() == &trace_address[..])
+            {
+                Some(i) => i,
+                None => continue,
+            };
+
+            // ETH refunds / WETH unwraps / token sweeps only send the
+            // leftovers of the swaps back to the caller, so they're not
+            // actions of their own
+            if PAYMENTS
+                .iter()
+                .any(|name| self.is_call(name, input.as_ref()))
+            {
+                classified.push((i, Classification::Prune));
+                prune.push(trace_address);
+                continue;
+            }
+
+            let (token_in, token_out, recipient) = match self.decode_swap(&input) {
+                Some(inner) => inner,
+                None => continue,
+            };
+            inspection.protocols.insert(Protocol::UniswapV3);
+
+            // The pools pay out to the recipient and get paid in their
+            // callback, so both transfers are nested under the router call
+            let subtraces = || {
+                actions
+                    .iter()
+                    .enumerate()
+                    .skip(i + 1)
+                    .filter(|(_, a)| is_nested(&trace_address, a.trace_address()))
+            };
+
+            // `exactOutput` swaps may pull more than they need from the payer
+            // and refund the rest, so their input is the amount they return
+            let amount_in = actions[i]
+                .as_call()
+                .and_then(|calltrace| self.amount_in(input.as_ref(), calltrace.output.as_ref()));
+            let t1 = find_matching(subtraces(), |t| t.transfer(), |t| t.token == token_in, true)
+                .map(|(_, t1)| Transfer {
+                    amount: amount_in.unwrap_or(t1.amount),
+                    ..t1.clone()
+                });
+            let t2 = find_matching(
+                subtraces(),
+                |t| t.transfer(),
+                |t| t.token == token_out && t.to == recipient,
+                true,
+            );
+
+            if let (Some(t1), Some((_, t2))) = (t1, t2) {
+                let trade =
+                    Classification::new(Trade { t1, t2: t2.clone() }, trace_address.clone());
+                classified.push((i, trade.with_provenance(Provenance::TransferInferred)));
+                prune.push(trace_address);
+            }
+        }
+
+        for (i, action) in classified {
+            inspection.actions[i] = action;
+        }
+        // remove the pool calls and transfers which make up the trades
+        for trace_address in prune {
+            inspection
+                .actions
+                .iter_mut()
+                .filter(|a| is_nested(&trace_address, a.trace_address()))
+                .for_each(|a| *a = Classification::Prune);
+        }
+    }
+}
+
+impl Default for UniswapV3 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UniswapV3 {
+    /// Constructor
+    pub fn new() -> Self {
+        Self {
+            router: &ROUTER_ABI,
+            routers: None,
+        }
+    }
+
+    /// Instantiates an inspector for `chain`, which only inspects the calls
+    /// to its known Uniswap V3 routers
+    pub fn with_chain(chain: &ChainConfig) -> Self {
+        Self {
+            routers: Some(chain.contracts_of(Protocol::UniswapV3)),
+            ..Self::new()
+        }
+    }
+
+    fn is_router(&self, address: Address) -> bool {
+        match self.routers {
+            Some(ref routers) => routers.contains(&address),
+            None => true,
+        }
+    }
+
+    /// Whether `input` is a call to the router's `name` function
+    fn is_call(&self, name: &str, input: &[u8]) -> bool {
+        self.router
+            .abi()
+            .function(name)
+            .map(|f| input.get(..4) == Some(&f.selector()[..]))
+            .unwrap_or(false)
+    }
+
+    /// The input which an `exactOutput` or `exactOutputSingle` call spent,
+    /// decoded from its `output`
+    fn amount_in(&self, input: &[u8], output: &[u8]) -> Option<U256> {
+        let name = ["exactOutputSingle", "exactOutput"]
+            .iter()
+            .find(|name| self.is_call(name, input))?;
+        let function = self.router.abi().function(name).ok()?;
+        function.decode_output(output).ok()?.pop()?.into_uint()
+    }
+
+    /// Decodes any of the SwapRouter's swap entrypoints into the
+    /// `(token_in, token_out, recipient)` of the swap
+    fn decode_swap(&self, input: &Bytes) -> Option<(Address, Address, Address)> {
+        if let Ok((token_in, token_out, _, recipient, _, _, _, _)) = self
+            .router
+            .decode::<SwapSingle, _>("exactInputSingle", input)
+            .or_else(|_| {
+                self.router
+                    .decode::<SwapSingle, _>("exactOutputSingle", input)
+            })
+        {
+            Some((token_in, token_out, recipient))
+        } else if let Ok((path, recipient, _, _, _)) =
+            self.router.decode::<SwapPath, _>("exactInput", input)
+        {
+            let hops = decode_path(path.as_ref())?;
+            Some((hops.first()?.0, hops.last()?.2, recipient))
+        } else if let Ok((path, recipient, _, _, _)) =
+            self.router.decode::<SwapPath, _>("exactOutput", input)
+        {
+            // exact output paths are encoded in reverse, starting from the
+            // token that's being bought
+            let hops = decode_path(path.as_ref())?;
+            Some((hops.last()?.2, hops.first()?.0, recipient))
+        } else {
+            None
+        }
+    }
+}
+
+/// Splits a V3 encoded path into its `(token_a, fee, token_b)` hops.
+///
+/// Paths are packed as `token | fee | token | fee | token ...`, with 20 byte
+/// addresses and 3 byte fees, so each hop is a 43 byte window which advances
+/// by 23 bytes. Returns `None` if the path is malformed.
+pub(crate) fn decode_path(path: &[u8]) -> Option<Vec<(Address, u32, Address)>> {
+    if path.len() < HOP_SIZE || path.len() % (ADDR_SIZE + FEE_SIZE) != ADDR_SIZE {
+        return None;
+    }
+
+    let hops = path
+        .windows(HOP_SIZE)
+        .step_by(ADDR_SIZE + FEE_SIZE)
+        .map(|hop| {
+            let token_a = Address::from_slice(&hop[..ADDR_SIZE]);
+            let fee = hop[ADDR_SIZE..ADDR_SIZE + FEE_SIZE]
+                .iter()
+                .fold(0u32, |fee, byte| (fee << 8) | *byte as u32);
+            let token_b = Address::from_slice(&hop[ADDR_SIZE + FEE_SIZE..]);
+            (token_a, fee, token_b)
+        })
+        .collect();
+
+    Some(hops)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        addresses::{parse_address, WETH},
+        inspectors::ERC20,
+        test_helpers::*,
+    };
+    use ethers::{abi::Token, types::CallType};
+
+    struct MyInspector {
+        erc20: ERC20,
+        uni: UniswapV3,
+    }
+
+    impl MyInspector {
+        fn inspect(&self, inspection: &mut Inspection) {
+            self.erc20.inspect(inspection);
+            self.uni.inspect(inspection);
+            inspection.prune();
+        }
+
+        fn new() -> Self {
+            Self {
+                erc20: ERC20::new(),
+                uni: UniswapV3::new(),
+            }
+        }
+    }
+
+    fn encode_path(tokens: &[Address], fees: &[u32]) -> Vec<u8> {
+        let mut path = tokens[0].as_bytes().to_vec();
+        for (token, fee) in tokens[1..].iter().zip(fees) {
+            path.extend_from_slice(&fee.to_be_bytes()[1..]);
+            path.extend_from_slice(token.as_bytes());
+        }
+        path
+    }
+
+    /// Sets the type and value of an unclassified call
+    fn with(mut call: Classification, call_type: CallType, value: U256) -> Classification {
+        if let Classification::Unknown(ref mut calltrace) = call {
+            calltrace.call.call_type = call_type;
+            calltrace.call.value = value;
+        }
+        call
+    }
+
+    #[test]
+    fn instances_share_the_abi() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<UniswapV3>();
+
+        let (a, b) = (UniswapV3::new(), UniswapV3::new());
+        assert!(std::ptr::eq(a.router, b.router));
+    }
+
+    #[test]
+    fn decodes_path() {
+        let usdc = parse_address("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48");
+        let dai = parse_address("0x6b175474e89094c44da98b954eedeac495271d0f");
+        let path = encode_path(&[*WETH, usdc, dai], &[3000, 500]);
+        assert_eq!(path.len(), 66);
+
+        let hops = decode_path(&path).unwrap();
+        assert_eq!(hops, vec![(*WETH, 3000, usdc), (usdc, 500, dai)]);
+
+        // truncated paths are rejected
+        assert!(decode_path(&path[..50]).is_none());
+        assert!(decode_path(&path[..20]).is_none());
+    }
+
+    #[test]
+    fn exact_input_single() {
+        let addrs = addrs();
+        let (usr, router, pool, token_out) = (addrs[0], addrs[1], addrs[2], addrs[3]);
+
+        let input = calldata(
+            "exactInputSingle((address,address,uint24,address,uint256,uint256,uint256,uint160))",
+            &[Token::Tuple(vec![
+                Token::Address(*WETH),
+                Token::Address(token_out),
+                Token::Uint(3000.into()),
+                Token::Address(usr),
+                Token::Uint(0.into()),
+                Token::Uint(100.into()),
+                Token::Uint(1.into()),
+                Token::Uint(0.into()),
+            ])],
+        );
+
+        let mut inspection = mk_inspection(vec![
+            mk_call(usr, router, input, vec![]),
+            // the pool pays out first
+            mk_call(router, pool, Bytes::default(), vec![0]),
+            mk_call(pool, token_out, transfer(usr, 250.into()), vec![0, 0]),
+            // and then gets paid in the router's callback
+            mk_call(pool, router, Bytes::default(), vec![0, 1]),
+            mk_call(
+                router,
+                *WETH,
+                transfer_from(usr, pool, 100.into()),
+                vec![0, 1, 0],
+            ),
+        ]);
+        MyInspector::new().inspect(&mut inspection);
+
+        let known = inspection.known();
+        assert_eq!(known.len(), 1);
+        let trade = known[0].as_ref().trade().unwrap();
+        assert_eq!(trade.t1.token, *WETH);
+        assert_eq!(trade.t1.amount, 100.into());
+        assert_eq!(trade.t2.token, token_out);
+        assert_eq!(trade.t2.amount, 250.into());
+        assert_eq!(trade.t2.to, usr);
+        assert_eq!(known[0].provenance, Provenance::TransferInferred);
+
+        assert!(inspection.unknown().is_empty());
+        assert_eq!(inspection.protocols, crate::set![Protocol::UniswapV3]);
+    }
+
+    #[test]
+    fn only_inspects_the_routers_of_the_chain() {
+        let addrs = addrs();
+        let (usr, router, pool, token_out) = (addrs[0], addrs[1], addrs[2], addrs[3]);
+
+        let input = calldata(
+            "exactInputSingle((address,address,uint24,address,uint256,uint256,uint256,uint160))",
+            &[Token::Tuple(vec![
+                Token::Address(*WETH),
+                Token::Address(token_out),
+                Token::Uint(3000.into()),
+                Token::Address(usr),
+                Token::Uint(0.into()),
+                Token::Uint(100.into()),
+                Token::Uint(1.into()),
+                Token::Uint(0.into()),
+            ])],
+        );
+        let swap = || {
+            mk_inspection(vec![
+                mk_call(usr, router, input.clone(), vec![]),
+                mk_call(router, pool, Bytes::default(), vec![0]),
+                mk_call(pool, token_out, transfer(usr, 250.into()), vec![0, 0]),
+                mk_call(pool, router, Bytes::default(), vec![0, 1]),
+                mk_call(
+                    router,
+                    *WETH,
+                    transfer_from(usr, pool, 100.into()),
+                    vec![0, 1, 0],
+                ),
+            ])
+        };
+
+        // the router is not one of Arbitrum's
+        let chain = ChainConfig::arbitrum();
+        for (chain, trades) in vec![
+            (chain.clone(), 0),
+            (chain.with_protocol(router, Protocol::UniswapV3), 1),
+        ] {
+            let mut inspection = swap();
+            MyInspector {
+                erc20: ERC20::with_chain(&chain),
+                uni: UniswapV3::with_chain(&chain),
+            }
+            .inspect(&mut inspection);
+            assert_eq!(inspection.trades().count(), trades);
+        }
+    }
+
+    /// A multihop `exactOutput` through 2 pools, by `addrs[0]` via `addrs[1]`
+    fn exact_output_multihop_trace(addrs: &[Address]) -> Inspection {
+        let (usr, router, pool1, pool2) = (addrs[0], addrs[1], addrs[2], addrs[3]);
+        let (mid, token_out) = (addrs[4], addrs[5]);
+
+        // exact output paths go from the output token to the input token
+        let path = encode_path(&[token_out, mid, *WETH], &[500, 3000]);
+        let input = calldata(
+            "exactOutput((bytes,address,uint256,uint256,uint256))",
+            &[Token::Tuple(vec![
+                Token::Bytes(path),
+                Token::Address(usr),
+                Token::Uint(0.into()),
+                Token::Uint(250.into()),
+                Token::Uint(1000.into()),
+            ])],
+        );
+
+        mk_inspection(vec![
+            mk_call(usr, router, input, vec![]),
+            mk_call(router, pool1, Bytes::default(), vec![0]),
+            mk_call(pool1, token_out, transfer(usr, 250.into()), vec![0, 0]),
+            mk_call(pool1, router, Bytes::default(), vec![0, 1]),
+            mk_call(router, pool2, Bytes::default(), vec![0, 1, 0]),
+            mk_call(pool2, mid, transfer(pool1, 40.into()), vec![0, 1, 0, 0]),
+            mk_call(pool2, router, Bytes::default(), vec![0, 1, 0, 1]),
+            mk_call(
+                router,
+                *WETH,
+                transfer_from(usr, pool2, 90.into()),
+                vec![0, 1, 0, 1, 0],
+            ),
+        ])
+    }
+
+    #[test]
+    fn exact_output_multihop() {
+        let addrs = addrs();
+        let token_out = addrs[5];
+        let mut inspection = exact_output_multihop_trace(&addrs);
+        MyInspector::new().inspect(&mut inspection);
+
+        let known = inspection.known();
+        assert_eq!(known.len(), 1);
+        let trade = known[0].as_ref().trade().unwrap();
+        assert_eq!(trade.t1.token, *WETH);
+        assert_eq!(trade.t1.amount, 90.into());
+        assert_eq!(trade.t2.token, token_out);
+        assert_eq!(trade.t2.amount, 250.into());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    // the same swap, classified by all the inspectors and reducers
+    fn exact_output_multihop_snapshot() {
+        let addrs = (1..=6).map(Address::repeat_byte).collect::<Vec<_>>();
+        assert_processed_matches(
+            exact_output_multihop_trace(&addrs),
+            include_str!("../../res/snapshots/uniswapv3_exact_output.json"),
+        );
+    }
+
+    #[test]
+    fn exact_output_single_spends_what_it_returns() {
+        let addrs = addrs();
+        let (usr, router, pool, token_out) = (addrs[0], addrs[1], addrs[2], addrs[3]);
+
+        let input = calldata(
+            "exactOutputSingle((address,address,uint24,address,uint256,uint256,uint256,uint160))",
+            &[Token::Tuple(vec![
+                Token::Address(*WETH),
+                Token::Address(token_out),
+                Token::Uint(3000.into()),
+                Token::Address(usr),
+                Token::Uint(0.into()),
+                Token::Uint(250.into()),
+                // amountInMaximum
+                Token::Uint(100.into()),
+                Token::Uint(0.into()),
+            ])],
+        );
+        let mut swap = mk_call(usr, router, input, vec![]);
+        if let Classification::Unknown(ref mut calltrace) = swap {
+            calltrace.output = ethers::abi::encode(&[Token::Uint(90.into())]).into();
+        }
+
+        // the maximum is pulled from the user, and the leftover refunded
+        let mut inspection = mk_inspection(vec![
+            swap,
+            mk_call(
+                router,
+                *WETH,
+                transfer_from(usr, router, 100.into()),
+                vec![0],
+            ),
+            mk_call(router, pool, Bytes::default(), vec![1]),
+            mk_call(pool, token_out, transfer(usr, 250.into()), vec![1, 0]),
+            mk_call(pool, router, Bytes::default(), vec![1, 1]),
+            mk_call(router, *WETH, transfer(pool, 90.into()), vec![1, 1, 0]),
+            mk_call(router, *WETH, transfer(usr, 10.into()), vec![2]),
+        ]);
+        MyInspector::new().inspect(&mut inspection);
+
+        let known = inspection.known();
+        assert_eq!(known.len(), 1);
+        let trade = known[0].as_ref().trade().unwrap();
+        assert_eq!(trade.t1.from, usr);
+        assert_eq!(trade.t1.token, *WETH);
+        assert_eq!(trade.t1.amount, 90.into());
+        assert_eq!(trade.t2.amount, 250.into());
+    }
+
+    #[test]
+    fn multicall() {
+        let addrs = addrs();
+        let (usr, router, pool, token_in) = (addrs[0], addrs[1], addrs[2], addrs[3]);
+
+        // sell `token_in` for WETH which the router then unwraps to the user
+        let swap = calldata(
+            "exactInputSingle((address,address,uint24,address,uint256,uint256,uint256,uint160))",
+            &[Token::Tuple(vec![
+                Token::Address(token_in),
+                Token::Address(*WETH),
+                Token::Uint(3000.into()),
+                Token::Address(router),
+                Token::Uint(0.into()),
+                Token::Uint(100.into()),
+                Token::Uint(1.into()),
+                Token::Uint(0.into()),
+            ])],
+        );
+        let unwrap = calldata(
+            "unwrapWETH9(uint256,address)",
+            &[Token::Uint(1.into()), Token::Address(usr)],
+        );
+        let input = calldata(
+            "multicall(bytes[])",
+            &[Token::Array(vec![
+                Token::Bytes(swap.to_vec()),
+                Token::Bytes(unwrap.to_vec()),
+            ])],
+        );
+
+        let delegatecall = |input: &Bytes, trace_address| {
+            with(
+                mk_call(router, router, input.clone(), trace_address),
+                CallType::DelegateCall,
+                0.into(),
+            )
+        };
+        let mut inspection = mk_inspection(vec![
+            mk_call(usr, router, input, vec![]),
+            delegatecall(&swap, vec![0]),
+            mk_call(router, pool, Bytes::default(), vec![0, 0]),
+            mk_call(pool, *WETH, transfer(router, 250.into()), vec![0, 0, 0]),
+            mk_call(pool, router, Bytes::default(), vec![0, 0, 1]),
+            mk_call(
+                router,
+                token_in,
+                transfer_from(usr, pool, 100.into()),
+                vec![0, 0, 1, 0],
+            ),
+            delegatecall(&unwrap, vec![1]),
+            mk_call(
+                router,
+                *WETH,
+                calldata("withdraw(uint256)", &[Token::Uint(250.into())]),
+                vec![1, 0],
+            ),
+            with(
+                mk_call(*WETH, router, Bytes::default(), vec![1, 0, 0]),
+                CallType::Call,
+                250.into(),
+            ),
+            with(
+                mk_call(router, usr, Bytes::default(), vec![1, 1]),
+                CallType::Call,
+                250.into(),
+            ),
+        ]);
+        MyInspector::new().inspect(&mut inspection);
+
+        let known = inspection.known();
+        assert_eq!(known.len(), 1);
+        assert_eq!(known[0].trace_address[..], [0]);
+        let trade = known[0].as_ref().trade().unwrap();
+        assert_eq!(trade.t1.token, token_in);
+        assert_eq!(trade.t1.amount, 100.into());
+        assert_eq!(trade.t2.token, *WETH);
+        assert_eq!(trade.t2.amount, 250.into());
+
+        // only the multicall itself is left
+        let unknown = inspection.unknown();
+        assert_eq!(unknown.len(), 1);
+        assert!(unknown[0].trace_address.is_empty());
+    }
+}
@@ -0,0 +1,536 @@
+use crate::{
+    is_nested,
+    traits::Inspector,
+    types::{
+        actions::{AddLiquidity, LiquidityPosition, RemoveLiquidity, SpecificAction},
+        classification::CallTrace,
+        Classification, Inspection, Protocol,
+    },
+};
+
+use ethers::{
+    abi::{parse_abi, Abi, Detokenize},
+    contract::{decode_function_data, BaseContract},
+    types::{Address, Bytes, U256},
+};
+
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+
+static MANAGER_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    serde_json::from_str::<Abi>(include_str!("../../abi/uniposmanagerv3.json"))
+        .expect("could not parse uniswap v3 position manager abi")
+        .into()
+});
+
+static POOL_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    parse_abi(&[
+        "function mint(address, int24, int24, uint128, bytes)",
+        "function burn(int24, int24, uint128)",
+        "function collect(address, int24, int24, uint128, uint128)",
+    ])
+    .expect("could not parse uniswap v3 pool abi")
+    .into()
+});
+
+// Type aliases for the NonfungiblePositionManager's params structs
+type Mint = (
+    Address,
+    Address,
+    u32,
+    i32,
+    i32,
+    U256,
+    U256,
+    U256,
+    U256,
+    Address,
+    U256,
+);
+type IncreaseLiquidity = (U256, U256, U256, U256, U256, U256);
+type DecreaseLiquidity = (U256, u128, U256, U256, U256);
+type Collect = (U256, Address, u128, u128);
+
+// Type aliases for the calls the manager makes to the pool
+type PoolMint = (Address, i32, i32, u128, Bytes);
+type PoolBurn = (i32, i32, u128);
+type PoolCollect = (Address, i32, i32, u128, u128);
+
+#[derive(Debug, Clone)]
+/// An inspector for Uniswap V3's NonfungiblePositionManager
+pub struct UniswapV3Positions {
+    manager: &'static BaseContract,
+    pool: &'static BaseContract,
+}
+
+impl Inspector for UniswapV3Positions {
+    fn name(&self) -> &'static str {
+        "uniswapv3positions"
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        let actions = &inspection.actions;
+        let mut prune = Vec::new();
+        let mut classified = Vec::new();
+        for (i, action) in actions.iter().enumerate() {
+            let calltrace = match action.as_call() {
+                Some(inner) => inner,
+                None => continue,
+            };
+
+            // Calls batched via `multicall` are delegatecalls, so they're
+            // in the trace along with their return data and can be decoded
+            // directly
+            let trace_address = &calltrace.trace_address;
+            let subtraces = actions
+                .iter()
+                .skip(i + 1)
+                .filter(|a| is_nested(trace_address, a.trace_address()))
+                .collect::<Vec<_>>();
+
+            if let Some(action) = self.classify(calltrace, &subtraces) {
+                inspection.protocols.insert(Protocol::UniswapV3);
+                classified.push((i, Classification::new(action, trace_address.clone())));
+                prune.push(trace_address.clone());
+            }
+        }
+
+        for (i, action) in classified {
+            inspection.actions[i] = action;
+        }
+
+        // remove the pool calls and transfers of each position change
+        for trace_address in prune {
+            inspection
+                .actions
+                .iter_mut()
+                .filter(|a| is_nested(&trace_address, a.trace_address()))
+                .for_each(|a| *a = Classification::Prune);
+        }
+
+        merge_positions(&mut inspection.actions);
+    }
+}
+
+impl Default for UniswapV3Positions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UniswapV3Positions {
+    /// Constructor
+    pub fn new() -> Self {
+        Self {
+            manager: &MANAGER_ABI,
+            pool: &POOL_ABI,
+        }
+    }
+
+    fn classify(
+        &self,
+        calltrace: &CallTrace,
+        subtraces: &[&Classification],
+    ) -> Option<SpecificAction> {
+        let input = &calltrace.as_ref().input;
+        let output = &calltrace.output;
+
+        // the manager only stores the position's token id, the pool and
+        // tick range are found in its call to the pool
+        let (pool, tick_lower, tick_upper) = self.pool_call(subtraces)?;
+        let position = |token_id| {
+            Some(LiquidityPosition {
+                pool,
+                token_id,
+                tick_lower,
+                tick_upper,
+            })
+        };
+
+        let action = if let Ok((token0, token1, ..)) = self.manager.decode::<Mint, _>("mint", input)
+        {
+            // the token id and the amounts actually deposited are only
+            // known after the call, so they must be read from its output
+            let (token_id, _, amount0, amount1) =
+                self.decode_output::<(U256, u128, U256, U256)>("mint", output)?;
+            AddLiquidity {
+                tokens: vec![token0, token1],
+                amounts: vec![amount0, amount1],
+                position: position(token_id),
+            }
+            .into()
+        } else if let Ok((token_id, ..)) = self
+            .manager
+            .decode::<IncreaseLiquidity, _>("increaseLiquidity", input)
+        {
+            let (_, amount0, amount1) =
+                self.decode_output::<(u128, U256, U256)>("increaseLiquidity", output)?;
+            AddLiquidity {
+                tokens: pool_tokens(subtraces, pool),
+                amounts: vec![amount0, amount1],
+                position: position(token_id),
+            }
+            .into()
+        } else if let Ok((token_id, ..)) = self
+            .manager
+            .decode::<DecreaseLiquidity, _>("decreaseLiquidity", input)
+        {
+            // decreasing only credits the tokens to the position, they
+            // get transferred out in a later `collect`
+            let (amount0, amount1) =
+                self.decode_output::<(U256, U256)>("decreaseLiquidity", output)?;
+            RemoveLiquidity {
+                tokens: pool_tokens(subtraces, pool),
+                amounts: vec![amount0, amount1],
+                fees: Vec::new(),
+                position: position(token_id),
+            }
+            .into()
+        } else if let Ok((token_id, ..)) = self.manager.decode::<Collect, _>("collect", input) {
+            // anything collected is treated as fees, `merge_positions` will
+            // subtract any liquidity that was removed in the same transaction
+            let (amount0, amount1) = self.decode_output::<(U256, U256)>("collect", output)?;
+            RemoveLiquidity {
+                tokens: pool_tokens(subtraces, pool),
+                amounts: vec![U256::zero(), U256::zero()],
+                fees: vec![amount0, amount1],
+                position: position(token_id),
+            }
+            .into()
+        } else {
+            return None;
+        };
+
+        Some(action)
+    }
+
+    /// Finds the pool and the tick range of the position being modified
+    fn pool_call(&self, subtraces: &[&Classification]) -> Option<(Address, i32, i32)> {
+        subtraces.iter().filter_map(|a| a.as_call()).find_map(|c| {
+            let call = c.as_ref();
+            let (tick_lower, tick_upper) = if let Ok((_, lower, upper, _, _)) =
+                self.pool.decode::<PoolMint, _>("mint", &call.input)
+            {
+                (lower, upper)
+            } else if let Ok((lower, upper, _)) =
+                self.pool.decode::<PoolBurn, _>("burn", &call.input)
+            {
+                (lower, upper)
+            } else if let Ok((_, lower, upper, _, _)) =
+                self.pool.decode::<PoolCollect, _>("collect", &call.input)
+            {
+                (lower, upper)
+            } else {
+                return None;
+            };
+            Some((call.to, tick_lower, tick_upper))
+        })
+    }
+
+    fn decode_output<D: Detokenize>(&self, name: &str, output: &Bytes) -> Option<D> {
+        let function = self.manager.abi().function(name).ok()?;
+        decode_function_data(function, output, false).ok()
+    }
+}
+
+/// Returns the pool's `[token0, token1]` if both were transferred to or from
+/// the pool, otherwise returns an empty vector.
+fn pool_tokens(subtraces: &[&Classification], pool: Address) -> Vec<Address> {
+    let mut tokens = subtraces
+        .iter()
+        .filter_map(|a| a.as_action())
+        .filter_map(|a| a.transfer())
+        .filter(|t| t.from == pool || t.to == pool)
+        .map(|t| t.token)
+        .collect::<Vec<_>>();
+    // the pools order their tokens by address
+    tokens.sort();
+    tokens.dedup();
+
+    if tokens.len() == 2 {
+        tokens
+    } else {
+        Vec::new()
+    }
+}
+
+fn position_tokens(action: &SpecificAction) -> Option<(&Vec<Address>, &LiquidityPosition)> {
+    match action {
+        SpecificAction::AddLiquidity(inner) => Some((&inner.tokens, inner.position.as_ref()?)),
+        SpecificAction::RemoveLiquidity(inner) => Some((&inner.tokens, inner.position.as_ref()?)),
+        _ => None,
+    }
+}
+
+/// Fills in the tokens of the positions which did not transfer both of them,
+/// and folds each `collect` into the liquidity removal that preceded it, so that
+/// only the fees earned are counted as such.
+fn merge_positions(actions: &mut [Classification]) {
+    let tokens = actions
+        .iter()
+        .filter_map(|a| a.as_action())
+        .filter_map(position_tokens)
+        .filter(|(tokens, _)| !tokens.is_empty())
+        .map(|(tokens, position)| (position.token_id, tokens.clone()))
+        .collect::<HashMap<_, _>>();
+
+    for i in 0..actions.len() {
+        let (removed, rest) = actions.split_at_mut(i + 1);
+        let removed = match removed[i].as_action_mut() {
+            Some(SpecificAction::RemoveLiquidity(inner)) if inner.fees.is_empty() => inner,
+            _ => continue,
+        };
+        let token_id = match removed.position {
+            Some(ref position) => position.token_id,
+            None => continue,
+        };
+
+        let collected = rest.iter_mut().find(|a| {
+            a.as_action()
+                .and_then(|a| a.remove_liquidity())
+                .filter(|r| !r.fees.is_empty())
+                .and_then(|r| r.position.as_ref())
+                .map(|p| p.token_id == token_id)
+                .unwrap_or(false)
+        });
+        if let Some(collected) = collected {
+            if let Some(fees) = collected
+                .as_action()
+                .and_then(|a| a.remove_liquidity())
+                .map(|r| &r.fees)
+            {
+                removed.fees = fees
+                    .iter()
+                    .zip(&removed.amounts)
+                    .map(|(fee, amount)| fee.saturating_sub(*amount))
+                    .collect();
+            }
+            *collected = Classification::Prune;
+        }
+    }
+
+    for action in actions.iter_mut() {
+        let (action_tokens, position) = match action.as_action_mut() {
+            Some(SpecificAction::AddLiquidity(inner)) => (&mut inner.tokens, &inner.position),
+            Some(SpecificAction::RemoveLiquidity(inner)) => (&mut inner.tokens, &inner.position),
+            _ => continue,
+        };
+        if let (true, Some(position)) = (action_tokens.is_empty(), position) {
+            if let Some(tokens) = tokens.get(&position.token_id) {
+                *action_tokens = tokens.clone();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{inspectors::ERC20, test_helpers::*};
+    use ethers::{abi::Token, types::CallType};
+
+    struct MyInspector {
+        erc20: ERC20,
+        positions: UniswapV3Positions,
+    }
+
+    impl MyInspector {
+        fn inspect(&self, inspection: &mut Inspection) {
+            self.erc20.inspect(inspection);
+            self.positions.inspect(inspection);
+            inspection.prune();
+        }
+
+        fn new() -> Self {
+            Self {
+                erc20: ERC20::new(),
+                positions: UniswapV3Positions::new(),
+            }
+        }
+    }
+
+    fn int(i: i32) -> Token {
+        Token::Int(if i < 0 {
+            U256::MAX - U256::from(-(i as i64) - 1)
+        } else {
+            i.into()
+        })
+    }
+
+    fn uint(i: u64) -> Token {
+        Token::Uint(i.into())
+    }
+
+    #[test]
+    fn mint() {
+        let addrs = addrs();
+        let (usr, manager, pool) = (addrs[0], addrs[1], addrs[2]);
+        let (token0, token1) = (addrs[3], addrs[4]);
+
+        let input = calldata(
+            "mint((address,address,uint24,int24,int24,uint256,uint256,uint256,uint256,address,uint256))",
+            &[Token::Tuple(vec![
+                Token::Address(token0),
+                Token::Address(token1),
+                uint(3000),
+                int(-600),
+                int(600),
+                uint(1000),
+                uint(1000),
+                uint(0),
+                uint(0),
+                Token::Address(usr),
+                uint(0),
+            ])],
+        );
+        let pool_mint = calldata(
+            "mint(address,int24,int24,uint128,bytes)",
+            &[
+                Token::Address(manager),
+                int(-600),
+                int(600),
+                uint(5),
+                Token::Bytes(vec![]),
+            ],
+        );
+
+        let mut inspection = mk_inspection(vec![
+            // only part of the desired amounts get deposited
+            with_output(
+                mk_call(usr, manager, input, vec![]),
+                &[uint(42), uint(5), uint(900), uint(1000)],
+            ),
+            mk_call(manager, pool, pool_mint, vec![0]),
+            mk_call(pool, manager, Bytes::default(), vec![0, 0]),
+            mk_call(
+                manager,
+                token0,
+                transfer_from(usr, pool, 900.into()),
+                vec![0, 0, 0],
+            ),
+            mk_call(
+                manager,
+                token1,
+                transfer_from(usr, pool, 1000.into()),
+                vec![0, 0, 1],
+            ),
+        ]);
+        MyInspector::new().inspect(&mut inspection);
+
+        let known = inspection.known();
+        assert_eq!(known.len(), 1);
+        let add = known[0].as_ref().add_liquidity().unwrap();
+        assert_eq!(add.tokens, vec![token0, token1]);
+        assert_eq!(add.amounts, vec![900.into(), 1000.into()]);
+        assert_eq!(
+            add.position,
+            Some(LiquidityPosition {
+                pool,
+                token_id: 42.into(),
+                tick_lower: -600,
+                tick_upper: 600,
+            })
+        );
+        assert!(inspection.unknown().is_empty());
+        assert_eq!(inspection.protocols, crate::set![Protocol::UniswapV3]);
+    }
+
+    #[test]
+    fn decrease_and_collect() {
+        let addrs = addrs();
+        let (usr, manager, pool) = (addrs[0], addrs[1], addrs[2]);
+        let (mut token0, mut token1) = (addrs[3], addrs[4]);
+        if token0 > token1 {
+            std::mem::swap(&mut token0, &mut token1);
+        }
+
+        let decrease = calldata(
+            "decreaseLiquidity((uint256,uint128,uint256,uint256,uint256))",
+            &[Token::Tuple(vec![
+                uint(42),
+                uint(5),
+                uint(0),
+                uint(0),
+                uint(0),
+            ])],
+        );
+        let collect = calldata(
+            "collect((uint256,address,uint128,uint128))",
+            &[Token::Tuple(vec![
+                uint(42),
+                Token::Address(usr),
+                Token::Uint(u128::MAX.into()),
+                Token::Uint(u128::MAX.into()),
+            ])],
+        );
+        let input = calldata(
+            "multicall(bytes[])",
+            &[Token::Array(vec![
+                Token::Bytes(decrease.to_vec()),
+                Token::Bytes(collect.to_vec()),
+            ])],
+        );
+        let burn = |amount| {
+            calldata(
+                "burn(int24,int24,uint128)",
+                &[int(-600), int(600), uint(amount)],
+            )
+        };
+        let pool_collect = calldata(
+            "collect(address,int24,int24,uint128,uint128)",
+            &[
+                Token::Address(usr),
+                int(-600),
+                int(600),
+                uint(910),
+                uint(1003),
+            ],
+        );
+        let delegatecall = |input: &Bytes, trace_address| {
+            let mut call = mk_call(manager, manager, input.clone(), trace_address);
+            if let Classification::Unknown(ref mut calltrace) = call {
+                calltrace.call.call_type = CallType::DelegateCall;
+            }
+            call
+        };
+
+        let mut inspection = mk_inspection(vec![
+            mk_call(usr, manager, input, vec![]),
+            with_output(delegatecall(&decrease, vec![0]), &[uint(900), uint(1000)]),
+            mk_call(manager, pool, burn(5), vec![0, 0]),
+            with_output(delegatecall(&collect, vec![1]), &[uint(910), uint(1003)]),
+            mk_call(manager, pool, burn(0), vec![1, 0]),
+            mk_call(manager, pool, pool_collect, vec![1, 1]),
+            mk_call(pool, token0, transfer(usr, 910.into()), vec![1, 1, 0]),
+            mk_call(pool, token1, transfer(usr, 1003.into()), vec![1, 1, 1]),
+        ]);
+        MyInspector::new().inspect(&mut inspection);
+
+        let known = inspection.known();
+        assert_eq!(known.len(), 1);
+        assert_eq!(known[0].trace_address[..], [0]);
+        let remove = known[0].as_ref().remove_liquidity().unwrap();
+        // the tokens are only transferred when collecting
+        assert_eq!(remove.tokens, vec![token0, token1]);
+        assert_eq!(remove.amounts, vec![900.into(), 1000.into()]);
+        assert_eq!(remove.fees, vec![10.into(), 3.into()]);
+        assert_eq!(remove.position.as_ref().unwrap().token_id, 42.into());
+        assert_eq!(remove.position.as_ref().unwrap().tick_lower, -600);
+
+        // only the multicall itself is left
+        assert_eq!(inspection.unknown().len(), 1);
+    }
+
+    #[test]
+    fn overlapping_positions() {
+        let position = |tick_lower, tick_upper| LiquidityPosition {
+            pool: Address::zero(),
+            token_id: U256::zero(),
+            tick_lower,
+            tick_upper,
+        };
+        assert!(position(-10, 10).overlaps(&position(0, 20)));
+        assert!(position(-10, 10).overlaps(&position(-5, 5)));
+        assert!(!position(-10, 10).overlaps(&position(10, 20)));
+    }
+}
@@ -0,0 +1,588 @@
+use crate::{
+    addresses::{ChainConfig, UNIVERSAL_ROUTER},
+    inspectors::{uniswap::uniswappy, uniswapv3::decode_path},
+    is_nested,
+    traits::Inspector,
+    types::{
+        actions::{Trade, Transfer},
+        classification::CallTrace,
+        Classification, Inspection, Protocol, Provenance,
+    },
+};
+
+use ethers::{
+    abi::{self, ParamType, Token},
+    types::{Address, Call as TraceCall, CallType},
+    utils::id,
+};
+use std::{borrow::Cow, collections::HashSet};
+
+// The router's commands which swap on the pools. The other ones wrap and
+// unwrap ETH or move tokens, whose calls are classified by `WethInspector` and
+// `ERC20` on their own.
+const V3_SWAP_EXACT_IN: u8 = 0x00;
+const V3_SWAP_EXACT_OUT: u8 = 0x01;
+const V2_SWAP_EXACT_IN: u8 = 0x08;
+const V2_SWAP_EXACT_OUT: u8 = 0x09;
+
+/// The bits of a command byte which select its command, the others are flags,
+/// e.g. whether the command is allowed to revert
+const COMMAND_TYPE_MASK: u8 = 0x3f;
+
+/// The recipient which stands for the router's caller
+const MSG_SENDER: u64 = 1;
+/// The recipient which stands for the router itself
+const ADDRESS_THIS: u64 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// The pools which a command swaps on
+enum Pool {
+    V2,
+    V3,
+}
+
+impl Pool {
+    /// The signature of the pools' `swap`
+    fn swap(&self) -> &'static str {
+        match self {
+            Pool::V2 => "swap(uint256,uint256,address,bytes)",
+            Pool::V3 => "swap(address,bool,int256,uint160,bytes)",
+        }
+    }
+}
+
+/// A swap command, decoded from its input
+struct SwapCommand {
+    pool: Pool,
+    token_in: Address,
+    token_out: Address,
+    recipient: Address,
+    /// How many of the pools' swaps the router calls itself
+    calls: usize,
+}
+
+#[derive(Debug, Clone)]
+/// An inspector for Uniswap's Universal Router.
+///
+/// The router's `execute` runs a list of commands, one byte each, with the
+/// inputs of each command ABI encoded in the same order. Each swap command is
+/// classified as a single trade from its payer to its recipient, at the
+/// router's first call for the command. Its pools' swaps and transfers are
+/// pruned, and so is `execute` once any of its swaps was classified.
+///
+/// Only the swap commands are decoded. The others make calls of their own,
+/// which are classified by their inspectors: e.g. `WRAP_ETH` deposits into WETH
+/// for `WethInspector`, `PERMIT2_TRANSFER_FROM` calls Permit2 for `Permit2`, and
+/// `TRANSFER`, `SWEEP` and `PAY_PORTION` transfer the tokens or ETH for `ERC20`.
+///
+/// The router calls the pools directly, so this must run before `UniswapV2`,
+/// which would otherwise classify their swaps on their own, and after `ERC20`.
+/// The swaps which the payer funds through Permit2 are paid with a transfer
+/// which `Permit2` classifies, or `ERC20` from the token's own call if it did
+/// not run.
+pub struct UniversalRouter {
+    /// The routers whose calls to `execute` are inspected
+    routers: Cow<'static, HashSet<Address>>,
+}
+
+impl Default for UniversalRouter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UniversalRouter {
+    /// Constructor
+    pub fn new() -> Self {
+        Self {
+            routers: Cow::Borrowed(&UNIVERSAL_ROUTER),
+        }
+    }
+
+    /// Instantiates an inspector for the Universal Routers of `chain`
+    pub fn with_chain(chain: &ChainConfig) -> Self {
+        Self {
+            routers: Cow::Owned(chain.universal_routers.clone()),
+        }
+    }
+}
+
+impl Inspector for UniversalRouter {
+    fn name(&self) -> &'static str {
+        "universalrouter"
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        let executes = inspection
+            .actions
+            .iter()
+            .filter_map(|a| a.as_call())
+            .filter(|calltrace| calltrace.as_ref().call_type == CallType::Call)
+            .filter(|calltrace| self.routers.contains(&calltrace.as_ref().to))
+            .filter_map(|calltrace| Some((calltrace.clone(), decode_swaps(calltrace.as_ref())?)))
+            .collect::<Vec<_>>();
+        for (calltrace, swaps) in executes {
+            inspect_execute(inspection, &calltrace, swaps);
+        }
+    }
+}
+
+/// Classifies the swaps of the `execute` call. Its commands run in order, so
+/// each swap is made of the next pools' swaps which the router calls, along
+/// with the call which paid the first pool before them.
+fn inspect_execute(inspection: &mut Inspection, execute: &CallTrace, swaps: Vec<SwapCommand>) {
+    let actions = &inspection.actions;
+    let trace_address = |i: usize| actions[i].trace_address();
+    // the router's own calls
+    let children = actions
+        .iter()
+        .enumerate()
+        .filter(|(_, a)| {
+            is_nested(&execute.trace_address, a.trace_address())
+                && a.trace_address().len() == execute.trace_address.len() + 1
+        })
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    // the last index of the subtrace of the `i`th action
+    let subtrace_end = |i: usize| {
+        (i + 1..actions.len())
+            .take_while(|j| is_nested(trace_address(i), trace_address(*j)))
+            .last()
+            .unwrap_or(i)
+    };
+    let transfers = |span: std::ops::RangeInclusive<usize>| {
+        span.filter_map(|j| actions[j].as_action()?.transfer())
+            .collect::<Vec<&Transfer>>()
+    };
+
+    // the spans of the swaps' calls, which do not overlap, along with their trade
+    let mut trades = Vec::new();
+    let mut next = 0;
+    for swap in swaps {
+        let selector = id(swap.pool.swap());
+        let is_swap = |i: &usize| match actions[*i].as_call() {
+            Some(calltrace) => calltrace.as_ref().input.as_ref().starts_with(&selector),
+            None => false,
+        };
+        let calls = children[next..]
+            .iter()
+            .enumerate()
+            .filter(|(_, i)| is_swap(i))
+            .take(swap.calls)
+            .map(|(k, _)| next + k)
+            .collect::<Vec<_>>();
+        // e.g. the command was allowed to revert and did, after which the
+        // calls of the next commands cannot be told apart
+        let (first, last) = match (calls.first(), calls.last()) {
+            (Some(first), Some(last)) if calls.len() == swap.calls => (*first, *last),
+            _ => break,
+        };
+        let previous = std::mem::replace(&mut next, last + 1);
+
+        // V2 pairs are paid before their swap, by the router or through Permit2,
+        // and V3 pools in their callback
+        let first_pool = actions[children[first]].as_call().map(|c| c.as_ref().to);
+        let funding = match swap.pool {
+            Pool::V2 => (previous..first)
+                .rev()
+                .find(|k| {
+                    transfers(children[*k]..=subtrace_end(children[*k]))
+                        .iter()
+                        .any(|t| t.token == swap.token_in && Some(t.to) == first_pool)
+                })
+                .unwrap_or(first),
+            Pool::V3 => first,
+        };
+        let span = children[funding]..=subtrace_end(children[last]);
+
+        let pools = span
+            .clone()
+            .filter(|j| is_swap(j))
+            .filter_map(|j| Some(actions[j].as_call()?.as_ref().to))
+            .collect::<HashSet<_>>();
+        let transfers = transfers(span.clone());
+        let t1 = transfers
+            .iter()
+            .find(|t| t.token == swap.token_in && pools.contains(&t.to));
+        let t2 = transfers.iter().rev().find(|t| {
+            t.token == swap.token_out && pools.contains(&t.from) && t.to == swap.recipient
+        });
+        let (t1, t2) = match (t1, t2) {
+            (Some(t1), Some(t2)) => ((*t1).clone(), (*t2).clone()),
+            _ => continue,
+        };
+
+        for j in span.clone().filter(|j| is_swap(j)) {
+            let protocol = match swap.pool {
+                Pool::V2 => actions[j].as_call().map(|c| uniswappy(c.as_ref())),
+                Pool::V3 => Some(Protocol::UniswapV3),
+            };
+            inspection.protocols.extend(protocol);
+        }
+        let trade = Classification::new(Trade { t1, t2 }, trace_address(*span.start()));
+        trades.push((span, trade.with_provenance(Provenance::TransferInferred)));
+    }
+    if trades.is_empty() {
+        return;
+    }
+
+    let execute = actions
+        .iter()
+        .position(|a| a.trace_address() == &execute.trace_address[..]);
+    for (span, trade) in trades {
+        let (start, end) = span.into_inner();
+        inspection.actions[start] = trade;
+        inspection.actions[start + 1..=end]
+            .iter_mut()
+            .for_each(|a| *a = Classification::Prune);
+    }
+    if let Some(i) = execute {
+        inspection.actions[i] = Classification::Prune;
+    }
+}
+
+/// Decodes the swap commands of a call to `execute`, with their recipients
+/// resolved
+fn decode_swaps(call: &TraceCall) -> Option<Vec<SwapCommand>> {
+    let input = call.input.as_ref();
+    let bytes = || ParamType::Bytes;
+    let params = if input.starts_with(&id("execute(bytes,bytes[],uint256)")) {
+        vec![
+            bytes(),
+            ParamType::Array(Box::new(bytes())),
+            ParamType::Uint(256),
+        ]
+    } else if input.starts_with(&id("execute(bytes,bytes[])")) {
+        vec![bytes(), ParamType::Array(Box::new(bytes()))]
+    } else {
+        return None;
+    };
+    let mut tokens = abi::decode(&params, &input[4..]).ok()?.into_iter();
+    let commands = tokens.next()?.into_bytes()?;
+    let inputs = tokens.next()?.into_array()?;
+
+    let swaps = commands
+        .iter()
+        .zip(inputs)
+        .filter_map(|(command, input)| {
+            decode_swap(command & COMMAND_TYPE_MASK, &input.into_bytes()?)
+        })
+        .map(|swap| SwapCommand {
+            recipient: if swap.recipient == Address::from_low_u64_be(MSG_SENDER) {
+                call.from
+            } else if swap.recipient == Address::from_low_u64_be(ADDRESS_THIS) {
+                call.to
+            } else {
+                swap.recipient
+            },
+            ..swap
+        })
+        .collect();
+    Some(swaps)
+}
+
+/// Decodes the input of a swap command, which is the `(recipient, amount,
+/// amountLimit, path, payerIsUser)` of the swap. `None` for the other commands.
+fn decode_swap(command: u8, input: &[u8]) -> Option<SwapCommand> {
+    let path = match command {
+        V3_SWAP_EXACT_IN | V3_SWAP_EXACT_OUT => ParamType::Bytes,
+        V2_SWAP_EXACT_IN | V2_SWAP_EXACT_OUT => ParamType::Array(Box::new(ParamType::Address)),
+        _ => return None,
+    };
+    let params = [
+        ParamType::Address,
+        ParamType::Uint(256),
+        ParamType::Uint(256),
+        path,
+        ParamType::Bool,
+    ];
+    let mut tokens = abi::decode(&params, input).ok()?.into_iter();
+    let recipient = tokens.next()?.into_address()?;
+    let path = tokens.nth(2)?;
+
+    if command == V2_SWAP_EXACT_IN || command == V2_SWAP_EXACT_OUT {
+        let path = path
+            .into_array()?
+            .into_iter()
+            .map(Token::into_address)
+            .collect::<Option<Vec<_>>>()?;
+        if path.len() < 2 {
+            return None;
+        }
+        return Some(SwapCommand {
+            pool: Pool::V2,
+            token_in: path[0],
+            token_out: *path.last()?,
+            recipient,
+            calls: path.len() - 1,
+        });
+    }
+
+    let hops = decode_path(path.into_bytes()?.as_ref())?;
+    let (token_in, token_out, calls) = if command == V3_SWAP_EXACT_IN {
+        (hops.first()?.0, hops.last()?.2, hops.len())
+    } else {
+        // exact output paths are encoded in reverse, and each pool's swap is
+        // made in the callback of the one after it in the path
+        (hops.last()?.2, hops.first()?.0, 1)
+    };
+    Some(SwapCommand {
+        pool: Pool::V3,
+        token_in,
+        token_out,
+        recipient,
+        calls,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{inspectors::ERC20, test_helpers::*};
+    use ethers::types::{Bytes, U256};
+
+    fn inspect(inspection: &mut Inspection) {
+        ERC20::new().inspect(inspection);
+        UniversalRouter::new().inspect(inspection);
+        inspection.prune();
+    }
+
+    fn execute(commands: Vec<(u8, Vec<Token>)>) -> Bytes {
+        let (commands, inputs): (Vec<_>, Vec<_>) = commands
+            .into_iter()
+            .map(|(command, input)| (command, Token::Bytes(abi::encode(&input))))
+            .unzip();
+        calldata(
+            "execute(bytes,bytes[],uint256)",
+            &[
+                Token::Bytes(commands),
+                Token::Array(inputs),
+                Token::Uint(U256::MAX),
+            ],
+        )
+    }
+
+    /// The input of a swap command
+    fn swap(recipient: u64, amount: u64, path: Token, payer_is_user: bool) -> Vec<Token> {
+        vec![
+            Token::Address(Address::from_low_u64_be(recipient)),
+            Token::Uint(amount.into()),
+            Token::Uint(0.into()),
+            path,
+            Token::Bool(payer_is_user),
+        ]
+    }
+
+    /// A V3 path through pools with a 0.05% fee
+    fn v3_path(tokens: &[Address]) -> Token {
+        let mut path = tokens[0].as_bytes().to_vec();
+        for token in &tokens[1..] {
+            path.extend_from_slice(&[0x00, 0x01, 0xf4]);
+            path.extend_from_slice(token.as_bytes());
+        }
+        Token::Bytes(path)
+    }
+
+    fn permit2_transfer_from(from: Address, to: Address, amount: u64, token: Address) -> Bytes {
+        calldata(
+            "transferFrom(address,address,uint160,address)",
+            &[
+                Token::Address(from),
+                Token::Address(to),
+                Token::Uint(amount.into()),
+                Token::Address(token),
+            ],
+        )
+    }
+
+    fn v3_swap(recipient: Address) -> Bytes {
+        calldata(
+            "swap(address,bool,int256,uint160,bytes)",
+            &[
+                Token::Address(recipient),
+                Token::Bool(true),
+                Token::Int(0.into()),
+                Token::Uint(0.into()),
+                Token::Bytes(vec![]),
+            ],
+        )
+    }
+
+    fn trades(inspection: &Inspection) -> Vec<(Vec<usize>, Trade)> {
+        inspection
+            .known()
+            .into_iter()
+            .filter_map(|a| Some((a.trace_address.to_vec(), a.as_ref().trade()?.clone())))
+            .collect()
+    }
+
+    #[test]
+    fn classifies_each_swap_command() {
+        let addrs = addrs();
+        let (usr, permit2, pair, pool, dai, weth, usdc) = (
+            addrs[0], addrs[1], addrs[2], addrs[3], addrs[4], addrs[5], addrs[6],
+        );
+        let router = *UNIVERSAL_ROUTER.iter().next().unwrap();
+
+        // DAI for WETH on a V2 pair, which the user funds through Permit2 and
+        // pays out to the router, then the WETH for USDC on a V3 pool
+        let input = execute(vec![
+            (
+                V2_SWAP_EXACT_IN,
+                swap(
+                    ADDRESS_THIS,
+                    100,
+                    Token::Array(vec![Token::Address(dai), Token::Address(weth)]),
+                    true,
+                ),
+            ),
+            (
+                V3_SWAP_EXACT_IN,
+                swap(MSG_SENDER, 5, v3_path(&[weth, usdc]), false),
+            ),
+        ]);
+        let pair_swap = calldata(
+            "swap(uint256,uint256,address,bytes)",
+            &[
+                Token::Uint(0.into()),
+                Token::Uint(5.into()),
+                Token::Address(router),
+                Token::Bytes(vec![]),
+            ],
+        );
+        let mut inspection = mk_inspection(vec![
+            mk_call(usr, router, input, vec![]),
+            mk_call(
+                router,
+                permit2,
+                permit2_transfer_from(usr, pair, 100, dai),
+                vec![0],
+            ),
+            mk_call(
+                permit2,
+                dai,
+                transfer_from(usr, pair, 100.into()),
+                vec![0, 0],
+            ),
+            mk_call(router, pair, calldata("getReserves()", &[]), vec![1]),
+            mk_call(router, pair, pair_swap, vec![2]),
+            mk_call(pair, weth, transfer(router, 5.into()), vec![2, 0]),
+            mk_call(router, pool, v3_swap(usr), vec![3]),
+            mk_call(pool, usdc, transfer(usr, 10.into()), vec![3, 0]),
+            mk_call(pool, router, Bytes::default(), vec![3, 1]),
+            mk_call(router, weth, transfer(pool, 5.into()), vec![3, 1, 0]),
+        ]);
+        inspect(&mut inspection);
+
+        let trades = trades(&inspection);
+        assert_eq!(trades.len(), 2);
+        assert!(inspection
+            .known()
+            .iter()
+            .all(|a| a.provenance == Provenance::TransferInferred));
+        // at the Permit2 call which funded the pair
+        assert_eq!(trades[0].0, vec![0]);
+        assert_eq!(
+            trades[0].1,
+            Trade {
+                t1: Transfer::new(usr, pair, 100.into(), dai),
+                t2: Transfer::new(pair, router, 5.into(), weth),
+            }
+        );
+        assert_eq!(trades[1].0, vec![3]);
+        assert_eq!(
+            trades[1].1,
+            Trade {
+                t1: Transfer::new(router, pool, 5.into(), weth),
+                t2: Transfer::new(pool, usr, 10.into(), usdc),
+            }
+        );
+        assert!(inspection.unknown().is_empty());
+        assert_eq!(
+            inspection.protocols,
+            crate::set![Protocol::Uniswappy, Protocol::UniswapV3]
+        );
+    }
+
+    #[test]
+    fn exact_output_swaps_are_nested() {
+        let addrs = addrs();
+        let (usr, permit2, pool1, pool2, dai, weth, usdc) = (
+            addrs[0], addrs[1], addrs[2], addrs[3], addrs[4], addrs[5], addrs[6],
+        );
+        let router = *UNIVERSAL_ROUTER.iter().next().unwrap();
+
+        // 10 USDC for DAI through WETH, whose path starts from the output.
+        // The flag to allow the command to revert is ignored.
+        let input = execute(vec![(
+            V3_SWAP_EXACT_OUT | 0x80,
+            swap(MSG_SENDER, 10, v3_path(&[usdc, weth, dai]), true),
+        )]);
+        let mut inspection = mk_inspection(vec![
+            mk_call(usr, router, input, vec![]),
+            mk_call(router, pool1, v3_swap(usr), vec![0]),
+            mk_call(pool1, usdc, transfer(usr, 10.into()), vec![0, 0]),
+            mk_call(pool1, router, Bytes::default(), vec![0, 1]),
+            // the WETH owed to the first pool is bought from the second one
+            mk_call(router, pool2, v3_swap(pool1), vec![0, 1, 0]),
+            mk_call(pool2, weth, transfer(pool1, 5.into()), vec![0, 1, 0, 0]),
+            mk_call(pool2, router, Bytes::default(), vec![0, 1, 0, 1]),
+            mk_call(
+                router,
+                permit2,
+                permit2_transfer_from(usr, pool2, 100, dai),
+                vec![0, 1, 0, 1, 0],
+            ),
+            mk_call(
+                permit2,
+                dai,
+                transfer_from(usr, pool2, 100.into()),
+                vec![0, 1, 0, 1, 0, 0],
+            ),
+        ]);
+        inspect(&mut inspection);
+
+        let trades = trades(&inspection);
+        assert_eq!(trades.len(), 1);
+        assert_eq!(
+            trades[0].1,
+            Trade {
+                t1: Transfer::new(usr, pool2, 100.into(), dai),
+                t2: Transfer::new(pool1, usr, 10.into(), usdc),
+            }
+        );
+        assert!(inspection.unknown().is_empty());
+    }
+
+    #[test]
+    fn only_inspects_the_routers_of_the_chain() {
+        let addrs = addrs();
+        let (usr, router, pool, weth, usdc) = (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4]);
+
+        let input = execute(vec![(
+            V3_SWAP_EXACT_IN,
+            swap(MSG_SENDER, 5, v3_path(&[weth, usdc]), false),
+        )]);
+        let swap = || {
+            mk_inspection(vec![
+                mk_call(usr, router, input.clone(), vec![]),
+                mk_call(router, pool, v3_swap(usr), vec![0]),
+                mk_call(pool, usdc, transfer(usr, 10.into()), vec![0, 0]),
+                mk_call(pool, router, Bytes::default(), vec![0, 1]),
+                mk_call(router, weth, transfer(pool, 5.into()), vec![0, 1, 0]),
+            ])
+        };
+
+        // the router is not one of mainnet's
+        let mut inspection = swap();
+        inspect(&mut inspection);
+        assert!(trades(&inspection).is_empty());
+
+        let chain = ChainConfig::arbitrum().with_universal_router(router);
+        let mut inspection = swap();
+        ERC20::with_chain(&chain).inspect(&mut inspection);
+        UniversalRouter::with_chain(&chain).inspect(&mut inspection);
+        inspection.prune();
+        assert_eq!(trades(&inspection).len(), 1);
+    }
+}
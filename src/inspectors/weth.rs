@@ -0,0 +1,160 @@
+use crate::{
+    addresses::{ChainConfig, NATIVE_ETH, WETH},
+    types::{actions::Transfer, Classification, Inspection},
+    Inspector,
+};
+use ethers::{
+    abi::parse_abi,
+    contract::BaseContract,
+    types::{Address, Call as TraceCall, CallType, U256},
+};
+use once_cell::sync::Lazy;
+
+static WETH_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    parse_abi(&["function deposit()", "function withdraw(uint256)"])
+        .expect("could not parse weth abi")
+        .into()
+});
+
+#[derive(Debug, Clone)]
+/// Classifies wrapping and unwrapping ETH as transfers to WETH, so that they
+/// can be matched with the transfers of the trades around them. A `deposit`
+/// is a transfer of ETH to WETH and a `withdraw` one of WETH to WETH.
+///
+/// The amounts are taken from the calls rather than from the `Deposit` and
+/// `Withdrawal` events: the value sent along a `deposit` and the argument of
+/// a `withdraw`. This must run before `ERC20`, which would otherwise classify
+/// them as `WethDeposit`s and `WethWithdrawal`s.
+pub struct WethInspector {
+    abi: &'static BaseContract,
+    weth: Address,
+    /// The placeholder which native transfers are classified with
+    native: Address,
+}
+
+impl Inspector for WethInspector {
+    fn name(&self) -> &'static str {
+        "weth"
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        inspection.actions.iter_mut().for_each(|classification| {
+            if let Some(calltrace) = classification.as_call() {
+                if let Some(transfer) = self.try_parse(calltrace.as_ref()) {
+                    *classification = Classification::new(transfer, calltrace.trace_address.clone())
+                }
+            }
+        })
+    }
+}
+
+impl Default for WethInspector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WethInspector {
+    pub fn new() -> Self {
+        Self {
+            abi: &WETH_ABI,
+            weth: *WETH,
+            native: NATIVE_ETH,
+        }
+    }
+
+    /// Instantiates an inspector for the wrapped native token of `chain`
+    pub fn with_chain(chain: &ChainConfig) -> Self {
+        Self {
+            abi: &WETH_ABI,
+            weth: chain.weth,
+            native: chain.native,
+        }
+    }
+
+    /// Parses a call to WETH which wraps or unwraps ETH
+    pub fn try_parse(&self, call: &TraceCall) -> Option<Transfer> {
+        if call.to != self.weth || call.call_type != CallType::Call {
+            return None;
+        }
+
+        let input = call.input.as_ref();
+        // WETH's fallback function deposits any ETH sent to it
+        let is_deposit = input.is_empty() || input.starts_with(&ethers::utils::id("deposit()"));
+        if is_deposit && !call.value.is_zero() {
+            Some(Transfer {
+                from: call.from,
+                to: self.weth,
+                amount: call.value,
+                token: self.native,
+            })
+        } else if let Ok(amount) = self.abi.decode::<U256, _>("withdraw", &call.input) {
+            Some(Transfer {
+                from: call.from,
+                to: self.weth,
+                amount,
+                token: self.weth,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{inspectors::ERC20, test_helpers::*};
+    use ethers::abi::Token;
+
+    #[test]
+    fn wraps_and_unwraps() {
+        let addrs = addrs();
+        let (bot, token) = (addrs[0], addrs[1]);
+        let deposit = calldata("deposit()", &[]);
+        let withdraw = calldata("withdraw(uint256)", &[Token::Uint(5.into())]);
+        let mut inspection = mk_inspection(vec![
+            with_value(mk_call(bot, *WETH, deposit.clone(), vec![0]), 10.into()),
+            with_value(mk_call(bot, *WETH, Default::default(), vec![1]), 3.into()),
+            mk_call(bot, *WETH, withdraw.clone(), vec![2]),
+            // WETH sends the ETH back, which is not a transfer of its own
+            with_value(
+                mk_call(*WETH, bot, Default::default(), vec![2, 0]),
+                5.into(),
+            ),
+            // only WETH's deposits are classified
+            with_value(mk_call(bot, token, deposit, vec![3]), 1.into()),
+        ]);
+        WethInspector::new().inspect(&mut inspection);
+        ERC20::new().inspect(&mut inspection);
+
+        let transfer = |amount: u64, token| {
+            Some(Transfer {
+                from: bot,
+                to: *WETH,
+                amount: amount.into(),
+                token,
+            })
+        };
+        let transfers = inspection
+            .actions
+            .iter()
+            .map(|action| action.as_action().and_then(|a| a.transfer()).cloned())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            transfers,
+            vec![
+                transfer(10, NATIVE_ETH),
+                transfer(3, NATIVE_ETH),
+                transfer(5, *WETH),
+                None,
+                None
+            ]
+        );
+        assert!(inspection.actions[3].as_call().is_some());
+        assert!(inspection.actions[4]
+            .as_action()
+            .and_then(|a| a.deposit())
+            .is_some());
+    }
+}
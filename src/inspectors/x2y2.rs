@@ -0,0 +1,389 @@
+use crate::{
+    addresses::{ETH, X2Y2},
+    inspectors::{
+        marketplace::{self, into_tuple, Sale},
+        ERC20,
+    },
+    traits::Inspector,
+    types::{Inspection, Protocol},
+};
+
+use ethers::{
+    abi::{self, Abi, ParamType, Token},
+    contract::BaseContract,
+    types::{Address, Bytes, U256},
+};
+use once_cell::sync::Lazy;
+
+static X2Y2_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    serde_json::from_str::<Abi>(include_str!("../../abi/x2y2.json"))
+        .expect("could not parse x2y2 abi")
+        .into()
+});
+
+// https://github.com/X2Y2-io/contracts/blob/main/contracts/MarketConsts.sol
+const COMPLETE_SELL_OFFER: u8 = 1;
+const COMPLETE_BUY_OFFER: u8 = 2;
+
+#[derive(Debug, Clone)]
+/// An inspector for X2Y2's exchange.
+///
+/// A `run` settles several orders at once, e.g. a sweep of listings, and each
+/// of its settlements either buys a listing or sells to a bid. The NFTs of an
+/// order's item are encoded in the item's data, and the offers on a whole
+/// collection fill in the token id with their `dataReplacement`. The NFTs are
+/// classified at the price of their settlement, which includes the fees paid
+/// to the protocol and to the creator. It must run after `ERC721`, but before
+/// `ERC20` so that the payments are not classified as transfers.
+pub struct X2Y2 {
+    x2y2: &'static BaseContract,
+    erc20: ERC20,
+}
+
+impl Default for X2Y2 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl X2Y2 {
+    /// Constructor
+    pub fn new() -> Self {
+        Self {
+            x2y2: &X2Y2_ABI,
+            erc20: ERC20::new(),
+        }
+    }
+
+    fn decode(&self, input: &Bytes) -> Option<Vec<Sale>> {
+        let input = into_tuple(self.x2y2.decode::<Token, _>("run", input).ok()?)?;
+        let orders = input.first()?.clone().into_array()?;
+        let details = input.get(1)?.clone().into_array()?;
+        let taker = into_tuple(input.get(2)?.clone())?
+            .get(4)?
+            .clone()
+            .into_address()?;
+
+        let sales = details
+            .into_iter()
+            .filter_map(|detail| {
+                let detail = into_tuple(detail)?;
+                let uint = |i: usize| detail.get(i)?.clone().into_uint();
+                let op = uint(0)?.low_u32() as u8;
+                let (order, item) = (uint(1)?.low_u64() as usize, uint(2)?.low_u64() as usize);
+                let price = uint(3)?;
+                let replacement = detail.get(6)?.clone().into_bytes()?;
+
+                let order = into_tuple(orders.get(order)?.clone())?;
+                let maker = order.get(1)?.clone().into_address()?;
+                let currency = order.get(6)?.clone().into_address()?;
+                let mask = order.get(7)?.clone().into_bytes()?;
+                let item = into_tuple(order.get(8)?.clone().into_array()?.get(item)?.clone())?;
+                let data = item.get(1)?.clone().into_bytes()?;
+
+                let (buyer, seller) = match op {
+                    COMPLETE_SELL_OFFER => (taker, maker),
+                    COMPLETE_BUY_OFFER => (maker, taker),
+                    _ => return None,
+                };
+                Some(Sale {
+                    nfts: nfts(&replace(data, &replacement, &mask))?,
+                    buyer,
+                    seller,
+                    payment_token: if currency == Address::zero() {
+                        *ETH
+                    } else {
+                        currency
+                    },
+                    price,
+                })
+            })
+            .collect::<Vec<_>>();
+        Some(sales)
+    }
+}
+
+/// Replaces the bytes of the item's data which are set in the order's mask, as
+/// X2Y2 does when the settlement has a replacement
+fn replace(mut data: Vec<u8>, replacement: &[u8], mask: &[u8]) -> Vec<u8> {
+    if replacement.is_empty() || replacement.len() != data.len() || mask.len() != data.len() {
+        return data;
+    }
+    for (i, byte) in data.iter_mut().enumerate() {
+        if mask[i] != 0 {
+            *byte = replacement[i];
+        }
+    }
+    data
+}
+
+/// The (collection, token id) pairs of an item's data
+fn nfts(data: &[u8]) -> Option<Vec<(Address, U256)>> {
+    let pair = ParamType::Tuple(vec![ParamType::Address, ParamType::Uint(256)]);
+    let tokens = abi::decode(&[ParamType::Array(Box::new(pair))], data).ok()?;
+    let nfts = tokens
+        .into_iter()
+        .next()?
+        .into_array()?
+        .into_iter()
+        .filter_map(|pair| {
+            let pair = into_tuple(pair)?;
+            Some((
+                pair.first()?.clone().into_address()?,
+                pair.get(1)?.clone().into_uint()?,
+            ))
+        })
+        .collect::<Vec<_>>();
+    if nfts.is_empty() {
+        None
+    } else {
+        Some(nfts)
+    }
+}
+
+impl Inspector for X2Y2 {
+    fn name(&self) -> &'static str {
+        "x2y2"
+    }
+
+    fn inspect(&self, inspection: &mut Inspection) {
+        for i in 0..inspection.actions.len() {
+            let sales = match inspection.actions[i].as_call() {
+                Some(calltrace) if calltrace.as_ref().to == *X2Y2 => {
+                    match self.decode(&calltrace.as_ref().input) {
+                        Some(inner) => inner,
+                        None => continue,
+                    }
+                }
+                _ => continue,
+            };
+            marketplace::settle(&self.erc20, inspection, i, &sales, Protocol::X2Y2);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{inspectors::ERC721, test_helpers::*, types::actions::NftTrade};
+
+    const RUN: &str = "run(((uint256,address,uint256,uint256,uint256,uint256,address,bytes,(uint256,bytes)[],bytes32,bytes32,uint8,uint8)[],(uint8,uint256,uint256,uint256,bytes32,address,bytes,uint256,uint256,uint256,(uint256,address)[])[],(uint256,uint256,uint256,uint256,address,bool),bytes32,bytes32,uint8))";
+
+    fn inspect(inspection: &mut Inspection) {
+        ERC721::new().inspect(inspection);
+        X2Y2::new().inspect(inspection);
+        ERC20::new().inspect(inspection);
+        inspection.prune();
+    }
+
+    fn pairs(nfts: &[(Address, u64)]) -> Vec<u8> {
+        let pairs = nfts
+            .iter()
+            .map(|(collection, id)| {
+                Token::Tuple(vec![Token::Address(*collection), Token::Uint((*id).into())])
+            })
+            .collect();
+        abi::encode(&[Token::Array(pairs)])
+    }
+
+    fn order(maker: Address, currency: Address, items: Vec<Vec<u8>>) -> Token {
+        Token::Tuple(vec![
+            Token::Uint(0.into()),
+            Token::Address(maker),
+            Token::Uint(1.into()),
+            Token::Uint(1.into()),
+            Token::Uint(1.into()),
+            Token::Uint(U256::MAX),
+            Token::Address(currency),
+            Token::Bytes(vec![]),
+            Token::Array(
+                items
+                    .into_iter()
+                    .map(|data| Token::Tuple(vec![Token::Uint(0.into()), Token::Bytes(data)]))
+                    .collect(),
+            ),
+            Token::FixedBytes(vec![0; 32]),
+            Token::FixedBytes(vec![0; 32]),
+            Token::Uint(27.into()),
+            Token::Uint(1.into()),
+        ])
+    }
+
+    fn detail(op: u8, order: u64, price: u64, fee_recipient: Address) -> Token {
+        Token::Tuple(vec![
+            Token::Uint(op.into()),
+            Token::Uint(order.into()),
+            Token::Uint(0.into()),
+            Token::Uint(price.into()),
+            Token::FixedBytes(vec![0; 32]),
+            Token::Address(Address::zero()),
+            Token::Bytes(vec![]),
+            Token::Uint(0.into()),
+            Token::Uint(0.into()),
+            Token::Uint(0.into()),
+            Token::Array(vec![Token::Tuple(vec![
+                Token::Uint(5000.into()),
+                Token::Address(fee_recipient),
+            ])]),
+        ])
+    }
+
+    fn run(orders: Vec<Token>, details: Vec<Token>, taker: Address) -> Bytes {
+        calldata(
+            RUN,
+            &[Token::Tuple(vec![
+                Token::Array(orders),
+                Token::Array(details),
+                Token::Tuple(vec![
+                    Token::Uint(0.into()),
+                    Token::Uint(U256::MAX),
+                    Token::Uint(0.into()),
+                    Token::Uint(0.into()),
+                    Token::Address(taker),
+                    Token::Bool(false),
+                ]),
+                Token::FixedBytes(vec![0; 32]),
+                Token::FixedBytes(vec![0; 32]),
+                Token::Uint(27.into()),
+            ])],
+        )
+    }
+
+    fn pay(to: Address, amount: u64, trace_address: Vec<usize>) -> crate::types::Classification {
+        with_value(
+            mk_call(*X2Y2, to, Bytes::default(), trace_address),
+            amount.into(),
+        )
+    }
+
+    #[test]
+    fn sweeps_listings_in_eth() {
+        let addrs = addrs();
+        let (buyer, seller1, seller2, x2y2, creator, collection, delegate) = (
+            addrs[0], addrs[1], addrs[2], addrs[3], addrs[4], addrs[5], addrs[6],
+        );
+        let input = run(
+            vec![
+                order(seller1, Address::zero(), vec![pairs(&[(collection, 1)])]),
+                // a bundle
+                order(
+                    seller2,
+                    Address::zero(),
+                    vec![pairs(&[(collection, 2), (collection, 3)])],
+                ),
+            ],
+            vec![
+                detail(COMPLETE_SELL_OFFER, 0, 100, x2y2),
+                detail(COMPLETE_SELL_OFFER, 1, 201, x2y2),
+            ],
+            buyer,
+        );
+        let nft = |seller: Address, id: u64, trace_address: Vec<usize>| {
+            mk_call(
+                delegate,
+                collection,
+                transfer_from(seller, buyer, id.into()),
+                trace_address,
+            )
+        };
+        let mut inspection = mk_inspection(vec![
+            with_value(mk_call(buyer, *X2Y2, input, vec![]), 400.into()),
+            // the protocol's fee, the creator's royalty and the rest to the
+            // seller
+            pay(x2y2, 1, vec![0]),
+            pay(creator, 5, vec![1]),
+            pay(seller1, 94, vec![2]),
+            mk_call(*X2Y2, delegate, Bytes::default(), vec![3]),
+            nft(seller1, 1, vec![3, 0]),
+            pay(x2y2, 2, vec![4]),
+            pay(seller2, 199, vec![5]),
+            mk_call(*X2Y2, delegate, Bytes::default(), vec![6]),
+            nft(seller2, 2, vec![6, 0]),
+            nft(seller2, 3, vec![6, 1]),
+            // the excess is refunded
+            pay(buyer, 99, vec![7]),
+        ]);
+        inspection.logs = vec![
+            erc721_transfer_log(collection, seller1, buyer, 1.into()),
+            erc721_transfer_log(collection, seller2, buyer, 2.into()),
+            erc721_transfer_log(collection, seller2, buyer, 3.into()),
+        ];
+        inspect(&mut inspection);
+
+        let trades = inspection
+            .known_actions()
+            .filter_map(|a| a.nft_trade())
+            .cloned()
+            .collect::<Vec<_>>();
+        let trade = |id: u64, price: u64, seller: Address| NftTrade {
+            collection,
+            token_id: id.into(),
+            payment_token: *ETH,
+            price: price.into(),
+            buyer,
+            seller,
+        };
+        assert_eq!(
+            trades,
+            vec![
+                trade(1, 100, seller1),
+                trade(2, 101, seller2),
+                trade(3, 100, seller2),
+            ]
+        );
+        // the fees are part of the prices, and only the refund is left
+        let refund = inspection
+            .known_actions()
+            .filter_map(|a| a.transfer())
+            .map(|t| (t.to, t.amount))
+            .collect::<Vec<_>>();
+        assert_eq!(refund, vec![(buyer, 99.into())]);
+        assert_eq!(inspection.protocols, crate::set![Protocol::X2Y2]);
+    }
+
+    #[test]
+    fn sells_to_a_collection_offer() {
+        let addrs = addrs();
+        let (bidder, seller, x2y2, collection, delegate, weth) =
+            (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4], addrs[5]);
+        // the offer is for any token, which the seller fills in
+        let mut mask = vec![0; 128];
+        mask[96..].copy_from_slice(&[1; 32]);
+        let mut orders = vec![order(bidder, weth, vec![pairs(&[(collection, 0)])])];
+        if let Token::Tuple(ref mut order) = orders[0] {
+            order[7] = Token::Bytes(mask);
+        }
+        let mut details = vec![detail(COMPLETE_BUY_OFFER, 0, 1000, x2y2)];
+        if let Token::Tuple(ref mut detail) = details[0] {
+            detail[6] = Token::Bytes(pairs(&[(collection, 42)]));
+        }
+        let mut inspection = mk_inspection(vec![
+            mk_call(seller, *X2Y2, run(orders, details, seller), vec![]),
+            mk_call(*X2Y2, weth, transfer_from(bidder, x2y2, 5.into()), vec![0]),
+            mk_call(
+                *X2Y2,
+                weth,
+                transfer_from(bidder, seller, 995.into()),
+                vec![1],
+            ),
+            mk_call(*X2Y2, delegate, Bytes::default(), vec![2]),
+            mk_call(
+                delegate,
+                collection,
+                transfer_from(seller, bidder, 42.into()),
+                vec![2, 0],
+            ),
+        ]);
+        inspection.logs = vec![erc721_transfer_log(collection, seller, bidder, 42.into())];
+        inspect(&mut inspection);
+
+        let known = inspection.known();
+        assert_eq!(known.len(), 1);
+        let trade = known[0].as_ref().nft_trade().unwrap();
+        assert_eq!(
+            (trade.token_id, trade.payment_token, trade.price),
+            (42.into(), weth, 1000.into())
+        );
+        assert_eq!((trade.buyer, trade.seller), (bidder, seller));
+    }
+}
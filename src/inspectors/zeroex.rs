@@ -1,41 +1,266 @@
 use crate::{
-    addresses::PROTOCOLS,
+    addresses::{ChainConfig, PROTOCOLS},
+    inspectors::ERC20,
+    is_nested,
     traits::Inspector,
-    types::{actions::Transfer, Classification, Inspection, Protocol},
+    types::{
+        actions::{Trade, Transfer},
+        classification::CallTrace,
+        Classification, Inspection, Protocol,
+    },
 };
 
 use ethers::{
-    abi::parse_abi,
-    contract::BaseContract,
-    types::{Address, Bytes, U256},
+    abi::{parse_abi, Abi, Token, Tokenizable},
+    contract::{decode_function_data, BaseContract},
+    types::{Address, Bytes, CallType, U256},
 };
+use once_cell::sync::Lazy;
+use std::{borrow::Cow, collections::HashMap};
+
+static BRIDGE_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    parse_abi(&[
+        "function bridgeTransferFrom(address tokenAddress, address from, address to, uint256 amount, bytes calldata bridgeData)",
+    ])
+    .expect("could not parse bridge abi")
+    .into()
+});
+
+static EXCHANGE_ABI: Lazy<BaseContract> = Lazy::new(|| {
+    serde_json::from_str::<Abi>(include_str!("../../abi/zeroex.json"))
+        .expect("could not parse 0x abi")
+        .into()
+});
 
 #[derive(Debug, Clone)]
-/// An inspector for ZeroEx Exchange Proxy transfers
+/// An inspector for ZeroEx Exchange Proxy transfers, and for the orders filled
+/// by the Exchange and the Exchange Proxy
 pub struct ZeroEx {
-    bridge: BaseContract,
+    bridge: &'static BaseContract,
+    exchange: &'static BaseContract,
+    erc20: ERC20,
+    /// The known contracts, which the bridges' sub-protocols are told from
+    protocols: Cow<'static, HashMap<Address, Protocol>>,
 }
 
 type BridgeTransfer = (Address, Address, Address, U256, Bytes);
 
+// Type aliases for the Exchange's order structs
+type Order = (
+    Address,
+    Address,
+    Address,
+    Address,
+    U256,
+    U256,
+    U256,
+    U256,
+    U256,
+    U256,
+    Vec<u8>,
+    Vec<u8>,
+    Vec<u8>,
+    Vec<u8>,
+);
+type FillOrder = (Order, U256, Bytes);
+// The orders are decoded one by one, since there are too many fields in them
+// for a `Vec<Order>`
+type BatchFillOrders = (Vec<Token>, Vec<U256>, Vec<Vec<u8>>);
+type RfqOrder = (
+    Address,
+    Address,
+    u128,
+    u128,
+    Address,
+    Address,
+    Address,
+    [u8; 32],
+    u64,
+    U256,
+);
+type FillRfqOrder = (RfqOrder, (u8, u8, [u8; 32], [u8; 32]), u128);
+type TransformERC20 = (Address, Address, U256, U256, Vec<(u32, Vec<u8>)>);
+
+/// The `assetData` prefix of the tokens which are moved by the `ERC20Proxy`
+const ERC20_PROXY_ID: [u8; 4] = [0xf4, 0x72, 0x61, 0xb0];
+/// The `assetData` prefix of the tokens which are moved by the `ERC20BridgeProxy`
+const ERC20_BRIDGE_PROXY_ID: [u8; 4] = [0xdc, 0x16, 0x00, 0xf3];
+
 impl ZeroEx {
     pub fn new() -> Self {
-        let bridge = BaseContract::from(
-            parse_abi(&[
-    "function bridgeTransferFrom(address tokenAddress, address from, address to, uint256 amount, bytes calldata bridgeData)"
-            ]).expect("could not parse bridge abi"));
+        Self {
+            bridge: &BRIDGE_ABI,
+            exchange: &EXCHANGE_ABI,
+            erc20: ERC20::new(),
+            protocols: Cow::Borrowed(&PROTOCOLS),
+        }
+    }
 
-        Self { bridge }
+    /// Instantiates an inspector for `chain`
+    pub fn with_chain(chain: &ChainConfig) -> Self {
+        Self {
+            erc20: ERC20::with_chain(chain),
+            protocols: Cow::Owned(chain.protocols.clone()),
+            ..Self::new()
+        }
+    }
+
+    /// Decodes the `(maker, maker_token, taker_token)` of each order filled by
+    /// the call. Orders of non-ERC20 assets are skipped.
+    fn decode_fills(&self, input: &Bytes) -> Option<Vec<(Address, Address, Address)>> {
+        let orders = if let Ok((order, _, _)) = self
+            .exchange
+            .decode::<FillOrder, _>("fillOrder", input)
+            .or_else(|_| {
+                self.exchange
+                    .decode::<FillOrder, _>("fillOrKillOrder", input)
+            }) {
+            vec![order]
+        } else if let Ok((orders, _, _)) = self
+            .exchange
+            .decode::<BatchFillOrders, _>("batchFillOrders", input)
+        {
+            orders
+                .into_iter()
+                .map(Order::from_token)
+                .collect::<Result<_, _>>()
+                .ok()?
+        } else if let Ok((order, _, _)) = self
+            .exchange
+            .decode::<FillRfqOrder, _>("fillRfqOrder", input)
+        {
+            // RFQ orders are only for ERC20s, so their tokens are not encoded
+            return Some(vec![(order.4, order.0, order.1)]);
+        } else {
+            return None;
+        };
+
+        Some(
+            orders
+                .into_iter()
+                .filter_map(|order| {
+                    Some((
+                        order.0,
+                        decode_asset_data(&order.10)?,
+                        decode_asset_data(&order.11)?,
+                    ))
+                })
+                .collect(),
+        )
+    }
+
+    /// `transformERC20` sells the caller's input token for the output token,
+    /// however the Exchange Proxy's transformers source the liquidity
+    fn decode_transform(&self, calltrace: &CallTrace) -> Option<Trade> {
+        let call = calltrace.as_ref();
+        let (input_token, output_token, input_amount, _, _) = self
+            .exchange
+            .decode::<TransformERC20, _>("transformERC20", &call.input)
+            .ok()?;
+        let function = self.exchange.abi().function("transformERC20").ok()?;
+        // reverted calls do not return the output amount
+        let output_amount: U256 = decode_function_data(function, &calltrace.output, false).ok()?;
+
+        Some(Trade::new(
+            Transfer {
+                from: call.from,
+                to: call.to,
+                amount: input_amount,
+                token: input_token,
+            },
+            Transfer {
+                from: call.to,
+                to: call.from,
+                amount: output_amount,
+                token: output_token,
+            },
+        ))
+    }
+
+    /// The transfer made by the action, whether it has been classified yet or not
+    fn transfer(&self, action: &Classification) -> Option<Transfer> {
+        match action {
+            Classification::Known(_) => action.as_action()?.transfer().cloned(),
+            Classification::Unknown(calltrace) => self
+                .erc20
+                .try_parse(calltrace.as_ref())?
+                .transfer()
+                .cloned(),
+            Classification::Prune => None,
+        }
+    }
+
+    /// Replaces the fill at index `i` with the trades between the makers of its
+    /// orders and the taker, which are read from the transfers under it. The
+    /// replacements are pushed to `classified`.
+    fn settle(
+        &self,
+        actions: &[Classification],
+        i: usize,
+        orders: Vec<(Address, Address, Address)>,
+        classified: &mut Vec<(usize, Classification)>,
+    ) {
+        let trace_address = actions[i].trace_address();
+        let transfers = actions
+            .iter()
+            .enumerate()
+            .skip(i + 1)
+            .filter(|(_, a)| is_nested(trace_address, a.trace_address()))
+            .filter_map(|(j, a)| Some((j, self.transfer(a)?)))
+            .collect::<Vec<_>>();
+
+        let mut used = Vec::new();
+        for (maker, maker_token, taker_token) in orders {
+            let taker_side = transfers
+                .iter()
+                .find(|(j, t)| !used.contains(j) && t.token == taker_token && t.to == maker);
+            let (j1, t1) = match taker_side {
+                Some(inner) => inner,
+                None => continue,
+            };
+            let maker_side = transfers.iter().find(|(j, t)| {
+                !used.contains(j) && t.token == maker_token && t.from == maker && t.to == t1.from
+            });
+            let (j2, t2) = match maker_side {
+                Some(inner) => inner,
+                None => continue,
+            };
+
+            let trade = Classification::new(
+                Trade::new(t1.clone(), t2.clone()),
+                actions[*j1].trace_address(),
+            );
+            classified.push((*j1, trade));
+            classified.push((*j2, Classification::Prune));
+            classified.push((i, Classification::Prune));
+            used.push(*j1);
+            used.push(*j2);
+        }
     }
 }
 
+/// Decodes the token of an ERC20 `assetData`
+fn decode_asset_data(data: &[u8]) -> Option<Address> {
+    let id = data.get(..4)?;
+    if id != ERC20_PROXY_ID && id != ERC20_BRIDGE_PROXY_ID {
+        return None;
+    }
+    // both are prefixed with the ABI encoded token address
+    data.get(16..36).map(Address::from_slice)
+}
+
 impl Inspector for ZeroEx {
+    fn name(&self) -> &'static str {
+        "zeroex"
+    }
+
     fn inspect(&self, inspection: &mut Inspection) {
-        let actions = inspection.actions.to_vec();
+        let actions = &inspection.actions;
         let mut prune = Vec::new();
-        for i in 0..inspection.actions.len() {
-            let action = &mut inspection.actions[i];
-
+        let mut fills = Vec::new();
+        let mut transforms = Vec::new();
+        let mut classified = Vec::new();
+        for (i, action) in actions.iter().enumerate() {
             if let Some(calltrace) = action.as_call() {
                 let call = calltrace.as_ref();
 
@@ -47,12 +272,12 @@ impl Inspector for ZeroEx {
                     inspection.protocols.insert(Protocol::ZeroEx);
 
                     // the bridge call will tell us which sub-protocol was used
-                    if let Some(protocol) = PROTOCOLS.get(&transfer.1) {
+                    if let Some(protocol) = self.protocols.get(&transfer.1) {
                         inspection.protocols.insert(*protocol);
                     }
 
                     // change this to a transfer
-                    *action = Classification::new(
+                    let transfer = Classification::new(
                         Transfer {
                             token: transfer.0,
                             from: transfer.1,
@@ -61,17 +286,49 @@ impl Inspector for ZeroEx {
                         },
                         calltrace.trace_address.clone(),
                     );
+                    classified.push((i, transfer));
 
                     // keep the index to prune all the subcalls
                     prune.push(i);
+                } else if call.call_type != CallType::Call {
+                    // the Exchange Proxy delegatecalls its features with the
+                    // same calldata, so these are skipped
+                    continue;
+                } else if let Some(trade) = self.decode_transform(calltrace) {
+                    inspection.protocols.insert(Protocol::ZeroEx);
+                    let trace_address = calltrace.trace_address.clone();
+                    classified.push((i, Classification::new(trade, trace_address.clone())));
+                    transforms.push(trace_address);
+                } else if let Some(orders) = self.decode_fills(&call.input) {
+                    inspection.protocols.insert(Protocol::ZeroEx);
+                    fills.push((i, orders));
                 }
             }
         }
 
-        // remove the subcalls from any of the classified calls
-        prune
-            .into_iter()
-            .for_each(|idx| actions[idx].prune_subcalls(&mut inspection.actions));
+        for (i, orders) in fills {
+            self.settle(actions, i, orders, &mut classified);
+        }
+        for (i, action) in classified {
+            inspection.actions[i] = action;
+        }
+
+        // remove the subcalls from any of the classified calls, which come
+        // after them
+        for i in prune {
+            let (calls, subcalls) = inspection.actions.split_at_mut(i + 1);
+            calls[i].prune_subcalls(subcalls);
+        }
+
+        // the liquidity sources of the transformations are part of their trade.
+        // These are usually called directly by EOAs, i.e. at the top level.
+        for trace_address in transforms {
+            inspection
+                .actions
+                .iter_mut()
+                .filter(|a| is_nested(&trace_address, a.trace_address()))
+                .for_each(|a| *a = Classification::Prune);
+        }
     }
 }
 
@@ -149,4 +406,241 @@ mod tests {
         // the trade required more than we put in (TODO: is this correct?)
         assert_ne!(t1.amount, balancer.t1.amount + uniswap.t1.amount);
     }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    // the same split trade, classified by all the inspectors and reducers
+    fn balancer_uni_zeroex_snapshot() {
+        assert_processed_matches(
+            read_trace("exchange_proxy.json"),
+            include_str!("../../res/snapshots/exchange_proxy.json"),
+        );
+    }
+
+    fn asset_data(token: Address) -> Token {
+        Token::Bytes(
+            [
+                &ERC20_PROXY_ID[..],
+                &ethers::abi::encode(&[Token::Address(token)]),
+            ]
+            .concat(),
+        )
+    }
+
+    fn order(maker: Address, maker_token: Address, taker_token: Address) -> Token {
+        let mut fields = vec![Token::Address(maker)];
+        fields.extend(vec![Token::Address(Address::zero()); 3]);
+        fields.extend(vec![Token::Uint(1.into()); 6]);
+        fields.push(asset_data(maker_token));
+        fields.push(asset_data(taker_token));
+        fields.extend(vec![Token::Bytes(vec![]); 2]);
+        Token::Tuple(fields)
+    }
+
+    fn delegatecall(mut call: Classification) -> Classification {
+        if let Classification::Unknown(ref mut calltrace) = call {
+            calltrace.call.call_type = CallType::DelegateCall;
+        }
+        call
+    }
+
+    #[test]
+    fn decodes_asset_data() {
+        let token = addrs()[0];
+        match asset_data(token) {
+            Token::Bytes(data) => assert_eq!(decode_asset_data(&data), Some(token)),
+            _ => unreachable!(),
+        }
+        // ERC721 assets are not supported
+        assert_eq!(decode_asset_data(&[0x02, 0x57, 0x17, 0x92]), None);
+    }
+
+    #[test]
+    fn rfq_order_arbitraged_against_an_amm() {
+        let addrs = addrs();
+        let (searcher, maker, proxy, feature, pool, weth, usdc) = (
+            addrs[0], addrs[1], addrs[2], addrs[3], addrs[4], addrs[5], addrs[6],
+        );
+
+        let input = calldata(
+            "fillRfqOrder((address,address,uint128,uint128,address,address,address,bytes32,uint64,uint256),(uint8,uint8,bytes32,bytes32),uint128)",
+            &[
+                Token::Tuple(vec![
+                    Token::Address(usdc),
+                    Token::Address(weth),
+                    Token::Uint(3000.into()),
+                    Token::Uint(1.into()),
+                    Token::Address(maker),
+                    Token::Address(searcher),
+                    Token::Address(searcher),
+                    Token::FixedBytes(vec![0; 32]),
+                    Token::Uint(0.into()),
+                    Token::Uint(0.into()),
+                ]),
+                Token::Tuple(vec![
+                    Token::Uint(2.into()),
+                    Token::Uint(27.into()),
+                    Token::FixedBytes(vec![0; 32]),
+                    Token::FixedBytes(vec![0; 32]),
+                ]),
+                Token::Uint(1.into()),
+            ],
+        );
+        let amm_trade = Trade::new(
+            Transfer {
+                from: searcher,
+                to: pool,
+                amount: 3000.into(),
+                token: usdc,
+            },
+            Transfer {
+                from: pool,
+                to: searcher,
+                amount: 2.into(),
+                token: weth,
+            },
+        );
+        let mut inspection = mk_inspection(vec![
+            mk_call(searcher, proxy, input.clone(), vec![0]),
+            delegatecall(mk_call(searcher, feature, input, vec![0, 0])),
+            mk_call(
+                proxy,
+                weth,
+                transfer_from(searcher, maker, 1.into()),
+                vec![0, 0, 0],
+            ),
+            mk_call(
+                proxy,
+                usdc,
+                transfer_from(maker, searcher, 3000.into()),
+                vec![0, 0, 1],
+            ),
+            Classification::new(amm_trade, vec![1]),
+        ]);
+        ZeroEx::new().inspect(&mut inspection);
+        ERC20::new().inspect(&mut inspection);
+        ArbitrageReducer::new().reduce(&mut inspection);
+        inspection.prune();
+
+        let known = inspection.known();
+        assert_eq!(known.len(), 1);
+        let arb = known[0].as_ref().arbitrage().unwrap();
+        assert_eq!(arb.profit, 1.into());
+        assert_eq!(arb.trades[0].t1.to, maker);
+        assert_eq!(arb.trades[0].t2.amount, 3000.into());
+        assert_eq!(inspection.protocols, crate::set![Protocol::ZeroEx]);
+    }
+
+    #[test]
+    fn batch_fill_orders() {
+        let addrs = addrs();
+        let (taker, maker1, maker2, exchange, erc20_proxy, dai, weth) = (
+            addrs[0], addrs[1], addrs[2], addrs[3], addrs[4], addrs[5], addrs[6],
+        );
+
+        let input = calldata(
+            "batchFillOrders((address,address,address,address,uint256,uint256,uint256,uint256,uint256,uint256,bytes,bytes,bytes,bytes)[],uint256[],bytes[])",
+            &[
+                Token::Array(vec![order(maker1, dai, weth), order(maker2, dai, weth)]),
+                Token::Array(vec![Token::Uint(1.into()), Token::Uint(2.into())]),
+                Token::Array(vec![Token::Bytes(vec![]), Token::Bytes(vec![])]),
+            ],
+        );
+        let mut inspection = mk_inspection(vec![
+            mk_call(taker, exchange, input, vec![]),
+            mk_call(
+                erc20_proxy,
+                weth,
+                transfer_from(taker, maker1, 1.into()),
+                vec![0],
+            ),
+            mk_call(
+                erc20_proxy,
+                dai,
+                transfer_from(maker1, taker, 3000.into()),
+                vec![1],
+            ),
+            mk_call(
+                erc20_proxy,
+                weth,
+                transfer_from(taker, maker2, 2.into()),
+                vec![2],
+            ),
+            mk_call(
+                erc20_proxy,
+                dai,
+                transfer_from(maker2, taker, 5000.into()),
+                vec![3],
+            ),
+        ]);
+        ZeroEx::new().inspect(&mut inspection);
+        inspection.prune();
+
+        let trades = inspection
+            .known()
+            .iter()
+            .filter_map(|a| a.as_ref().trade().cloned())
+            .collect::<Vec<_>>();
+        assert_eq!(trades.len(), 2);
+        assert_eq!(
+            (trades[0].t1.to, trades[0].t1.amount, trades[0].t2.amount),
+            (maker1, 1.into(), 3000.into())
+        );
+        assert_eq!(
+            (trades[1].t1.to, trades[1].t1.amount, trades[1].t2.amount),
+            (maker2, 2.into(), 5000.into())
+        );
+        assert!(inspection.unknown().is_empty());
+    }
+
+    #[test]
+    fn transform_erc20() {
+        let addrs = addrs();
+        let (usr, proxy, feature, wallet, pool, dai, weth) = (
+            addrs[0], addrs[1], addrs[2], addrs[3], addrs[4], addrs[5], addrs[6],
+        );
+
+        let input = calldata(
+            "transformERC20(address,address,uint256,uint256,(uint32,bytes)[])",
+            &[
+                Token::Address(dai),
+                Token::Address(weth),
+                Token::Uint(3000.into()),
+                Token::Uint(1.into()),
+                Token::Array(vec![]),
+            ],
+        );
+        let mut inspection = mk_inspection(vec![
+            with_output(
+                mk_call(usr, proxy, input.clone(), vec![]),
+                &[Token::Uint(2.into())],
+            ),
+            delegatecall(mk_call(usr, feature, input, vec![0])),
+            mk_call(
+                proxy,
+                dai,
+                transfer_from(usr, wallet, 3000.into()),
+                vec![0, 0],
+            ),
+            // the transformers' trades
+            mk_call(wallet, dai, transfer(pool, 3000.into()), vec![0, 1]),
+            mk_call(pool, weth, transfer(wallet, 2.into()), vec![0, 2]),
+            mk_call(wallet, weth, transfer(usr, 2.into()), vec![0, 3]),
+        ]);
+        ZeroEx::new().inspect(&mut inspection);
+        ERC20::new().inspect(&mut inspection);
+        inspection.prune();
+
+        let known = inspection.known();
+        assert_eq!(known.len(), 1);
+        let trade = known[0].as_ref().trade().unwrap();
+        assert_eq!(
+            (trade.t1.from, trade.t1.token, trade.t1.amount),
+            (usr, dai, 3000.into())
+        );
+        assert_eq!(
+            (trade.t2.to, trade.t2.token, trade.t2.amount),
+            (usr, weth, 2.into())
+        );
+    }
 }
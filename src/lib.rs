@@ -20,6 +20,9 @@ pub mod reducers;
 /// multiple inspectors
 pub use inspectors::BatchInspector;
 
+/// The inspectors and reducers which the binaries run
+pub mod pipeline;
+
 /// Types for MEV-INSPECT
 pub mod types;
 
@@ -29,6 +32,35 @@ pub mod addresses;
 mod cached_provider;
 pub use cached_provider::CachedProvider;
 
+/// Conversion of Geth's `callTracer` traces to Parity's format
+pub mod geth;
+
+/// Inspection of pending transactions by simulating them
+pub mod simulation;
+
+mod trace_provider;
+pub use trace_provider::{
+    is_transient, EthersTraceProvider, InternalOperation, OperationKind, TraceProvider, Unsupported,
+};
+
+/// Checkpointed backfills of block ranges
+pub mod backfill;
+
+/// Live inspection of the new blocks at the head of the chain
+pub mod stream;
+
+/// Analyses of the MEV found across many blocks
+pub mod analysis;
+
+/// Resolution of proxies to the contracts which implement them
+pub mod proxy;
+
+/// Caches which are shared across the threads of a batch inspection
+pub mod cache;
+
+/// The symbols and decimals of tokens, for displaying their amounts
+pub mod tokens;
+
 #[cfg(test)]
 mod test_helpers;
 
@@ -37,10 +69,14 @@ pub use traits::*;
 
 /// PostGres trait implementations
 mod mevdb;
-pub use mevdb::{BatchInserts, MevDB};
+pub use mevdb::{BatchInserts, DbError, MevDB};
+
+/// Postgres tables of the inspections and their actions
+#[cfg(feature = "db")]
+pub mod db;
 
 mod prices;
-pub use prices::HistoricalPrice;
+pub use prices::{net_profit, HistoricalPrice, PriceOracle, UniswapV2Oracle};
 
 /// Checks if `a2` is a subtrace of `a1`
 pub(crate) fn is_subtrace(a1: &[usize], a2: &[usize]) -> bool {
@@ -51,6 +87,12 @@ pub(crate) fn is_subtrace(a1: &[usize], a2: &[usize]) -> bool {
     a1 == &a2[..std::cmp::min(a1.len(), a2.len())]
 }
 
+/// Checks if `a2` is strictly nested under `a1`. Unlike `is_subtrace`, this also
+/// holds for the transaction's top level call, which has an empty trace address.
+pub(crate) fn is_nested(a1: &[usize], a2: &[usize]) -> bool {
+    a2.len() > a1.len() && (a1.is_empty() || is_subtrace(a1, a2))
+}
+
 use crate::types::Classification;
 use ethers::types::Call;
 pub(crate) fn actions_after(
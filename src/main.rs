@@ -1,19 +1,20 @@
 use mev_inspect::{
-    inspectors::{Aave, Balancer, Compound, Curve, Uniswap, ZeroEx, ERC20},
-    reducers::{ArbitrageReducer, LiquidationReducer, TradeReducer},
-    types::Evaluation,
-    BatchInserts, BatchInspector, CachedProvider, HistoricalPrice, Inspector, MevDB, Reducer,
+    addresses::ChainConfig,
+    pipeline::{Pipeline, PipelineOptions},
+    types::{Evaluation, GasInfo},
+    BatchInserts, BatchInspector, CachedProvider, EthersTraceProvider, HistoricalPrice, MevDB,
+    TraceProvider,
 };
 
 use ethers::{
-    providers::{Middleware, Provider, StreamExt},
-    types::{BlockNumber, TxHash, U256},
+    providers::{Http, Middleware, Provider, StreamExt},
+    types::{TxHash, U256},
 };
 
 use futures::SinkExt;
 use gumdrop::Options;
 use std::io::Write;
-use std::{collections::HashMap, convert::TryFrom, path::PathBuf, sync::Arc};
+use std::{collections::HashMap, convert::TryFrom, path::PathBuf, str::FromStr, sync::Arc};
 
 #[derive(Debug, Options, Clone)]
 struct Opts {
@@ -34,6 +35,25 @@ struct Opts {
     #[options(help = "Path to where traces will be cached")]
     cache: Option<PathBuf>,
 
+    #[options(
+        no_short,
+        default = "1",
+        help = "the id of the chain to inspect: 1 (mainnet), 10 (Optimism) or 42161 (Arbitrum)"
+    )]
+    chain: u64,
+
+    #[options(
+        no_short,
+        help = "flag the chains of trades made by bots which end in another token worth more than they sold as open arbitrages, priced with Uniswap V2's WETH pairs. Not supported by the blocks command"
+    )]
+    open_arbitrages: bool,
+
+    #[options(
+        no_short,
+        help = "drop the arbitrages and sandwiches which made less than this many USD net of gas, e.g. 0.01, priced with Uniswap V2's WETH pairs. Not supported by the blocks command"
+    )]
+    min_profit: Option<f64>,
+
     #[options(help = "Database config")]
     db_cfg: tokio_postgres::Config,
     #[options(default = "mev_inspections", help = "the table of the database")]
@@ -91,33 +111,33 @@ async fn main() -> anyhow::Result<()> {
 
 async fn run<M: Middleware + Clone + 'static>(provider: M, opts: Opts) -> anyhow::Result<()> {
     let provider = Arc::new(provider);
+    // watched blocks are not cached, so they are traced over the node directly
+    let tracer = EthersTraceProvider::new(Http::from_str(opts.url.as_str())?);
+    let chain = ChainConfig::from_chain_id(opts.chain)
+        .ok_or_else(|| anyhow::anyhow!("unknown chain {}", opts.chain))?;
+    // the blocks of the blocks command are evaluated as they stream in, before
+    // their tokens could be priced
+    if matches!(opts.cmd, Some(Command::Blocks(_))) {
+        if opts.open_arbitrages {
+            anyhow::bail!("--open-arbitrages is not supported by the blocks command");
+        }
+        if opts.min_profit.is_some() {
+            anyhow::bail!("--min-profit is not supported by the blocks command");
+        }
+    }
     // Instantiate the thing which will query historical prices
     let prices = HistoricalPrice::new(provider.clone());
 
-    let compound = Compound::create(provider.clone()).await?;
-    let curve = Curve::create(provider.clone()).await?;
-    let inspectors: Vec<Box<dyn Inspector + Send + Sync>> = vec![
-        // Classify Transfers
-        Box::new(ZeroEx::new()),
-        Box::new(ERC20::new()),
-        // Classify AMMs
-        Box::new(Balancer::new()),
-        Box::new(Uniswap::new()),
-        Box::new(curve),
-        // Classify Liquidations
-        Box::new(Aave::new()),
-        Box::new(compound),
-    ];
-
-    let reducers: Vec<Box<dyn Reducer + Send + Sync>> = vec![
-        Box::new(LiquidationReducer::new()),
-        Box::new(TradeReducer::new()),
-        Box::new(ArbitrageReducer::new()),
-    ];
-    let processor = BatchInspector::new(inspectors, reducers);
+    let options = PipelineOptions {
+        open_arbitrages: opts.open_arbitrages,
+        min_profit: opts.min_profit,
+        ..Default::default()
+    };
+    let pipeline = Pipeline::create(provider.clone(), &chain, options).await?;
+    let processor = pipeline.processor().clone();
 
     // TODO: Pass overwrite parameter
-    let mut db = MevDB::connect(opts.db_cfg, &opts.db_table).await?;
+    let mut db = MevDB::connect(opts.db_cfg.clone(), &opts.db_table).await?;
     db.create().await?;
     if opts.reset {
         db.clear().await?;
@@ -125,11 +145,11 @@ async fn run<M: Middleware + Clone + 'static>(provider: M, opts: Opts) -> anyhow
     }
     log::debug!("created mevdb table");
 
-    if let Some(cmd) = opts.cmd {
+    if let Some(cmd) = opts.cmd.clone() {
         match cmd {
-            Command::Tx(opts) => {
-                let traces = provider.trace_transaction(opts.tx).await?;
-                if let Some(inspection) = processor.inspect_one(traces) {
+            Command::Tx(tx_opts) => {
+                let traces = provider.trace_transaction(tx_opts.tx).await?;
+                if let Some(mut inspection) = processor.inspect_one(traces) {
                     let gas_used = provider
                         .get_transaction_receipt(inspection.hash)
                         .await?
@@ -143,6 +163,16 @@ async fn run<M: Middleware + Clone + 'static>(provider: M, opts: Opts) -> anyhow
                         .expect("tx not found")
                         .gas_price;
 
+                    inspection.gas = Some(GasInfo {
+                        gas_used,
+                        gas_price,
+                        ..Default::default()
+                    });
+                    let inspections = std::slice::from_mut(&mut inspection);
+                    pipeline
+                        .reduce_with_gas(provider.clone(), inspections)
+                        .await;
+
                     let evaluation =
                         Evaluation::new(inspection, &prices, gas_used, gas_price).await?;
                     println!("Found: {:?}", evaluation.as_ref().hash);
@@ -153,13 +183,12 @@ async fn run<M: Middleware + Clone + 'static>(provider: M, opts: Opts) -> anyhow
                     println!("Status: {:?}", evaluation.inspection.status);
                     db.insert(&evaluation).await?;
                 } else {
-                    eprintln!("No actions found for tx {:?}", opts.tx);
+                    eprintln!("No actions found for tx {:?}", tx_opts.tx);
                 }
             }
             Command::Blocks(inner) => {
                 log::debug!("command blocks {:?}", inner);
                 let provider = Arc::new(provider);
-                let processor = Arc::new(processor);
                 let prices = Arc::new(prices);
 
                 let (tx, rx) = futures::channel::mpsc::unbounded();
@@ -255,9 +284,11 @@ async fn run<M: Middleware + Clone + 'static>(provider: M, opts: Opts) -> anyhow
                 &mut lock,
                 block.as_u64(),
                 &provider,
+                &tracer,
                 &processor,
                 &mut db,
                 &prices,
+                &pipeline,
             )
             .await?;
         }
@@ -266,25 +297,30 @@ async fn run<M: Middleware + Clone + 'static>(provider: M, opts: Opts) -> anyhow
     Ok(())
 }
 
-async fn process_block<M: Middleware + 'static>(
+async fn process_block<M: Middleware + Clone + 'static>(
     lock: &mut std::io::StdoutLock<'_>,
     block_number: u64,
     provider: &M,
+    tracer: &EthersTraceProvider<Http>,
     processor: &BatchInspector,
     db: &mut MevDB,
     prices: &HistoricalPrice<M>,
+    pipeline: &Pipeline,
 ) -> anyhow::Result<()> {
-    let block_number = block_number.into();
-
-    // get all the traces
-    let traces = provider
-        .trace_block(BlockNumber::Number(block_number))
-        .await?;
     // get all the block txs
     let block = provider
         .get_block_with_txs(block_number)
         .await?
         .expect("block should exist");
+    // get all the traces, one tx at a time if the node does not serve `trace_block`
+    let hashes = block
+        .transactions
+        .iter()
+        .map(|tx| tx.hash)
+        .collect::<Vec<_>>();
+    let traces = tracer
+        .trace_block_or_transactions(block_number, &hashes)
+        .await?;
     let gas_price_txs = block
         .transactions
         .iter()
@@ -303,7 +339,19 @@ async fn process_block<M: Middleware + 'static>(
         })
         .collect::<HashMap<TxHash, U256>>();
 
-    let inspections = processor.inspect_many(traces);
+    let mut inspections = processor.inspect_many(traces);
+    for inspection in inspections.iter_mut() {
+        let gas_used = gas_used_txs.get(&inspection.hash).cloned();
+        let gas_price = gas_price_txs.get(&inspection.hash).cloned();
+        inspection.gas = Some(GasInfo {
+            gas_used: gas_used.unwrap_or_default(),
+            gas_price: gas_price.unwrap_or_default(),
+            ..Default::default()
+        });
+    }
+    pipeline
+        .reduce_with_gas(Arc::new(provider.clone()), &mut inspections)
+        .await;
 
     let t1 = std::time::Instant::now();
 
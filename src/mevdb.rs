@@ -44,6 +44,7 @@ impl MevDB {
                 "CREATE TABLE IF NOT EXISTS {} (
                     hash text PRIMARY KEY,
                     status text,
+                    checked_reason text,
 
                     block_number NUMERIC,
                     gas_price NUMERIC,
@@ -62,6 +63,13 @@ impl MevDB {
                 self.table_name
             ))
             .await?;
+        // the tables which were created before the reason was stored
+        self.client
+            .batch_execute(&format!(
+                "ALTER TABLE {} ADD COLUMN IF NOT EXISTS checked_reason text",
+                self.table_name
+            ))
+            .await?;
         Ok(())
     }
 
@@ -81,8 +89,9 @@ impl MevDB {
                         actions,
                         eoa,
                         contract,
-                        proxy_impl
-                    ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+                        proxy_impl,
+                        checked_reason
+                    ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
                     {}",
                     self.table_name, self.overwrite,
                 )
@@ -103,6 +112,11 @@ impl MevDB {
                         .proxy_impl
                         .map(|x| format!("{:?}", x))
                         .unwrap_or_else(|| "".to_owned()),
+                    &evaluation
+                        .inspection
+                        .checked_reason
+                        .as_ref()
+                        .map(|reason| format!("{:?}", reason)),
                 ],
             )
             .await?;
@@ -349,13 +363,19 @@ mod tests {
 
         let inspection = Inspection {
             status: crate::types::Status::Checked,
+            revert_reason: None,
+            checked_reason: Some(crate::types::CheckedReason::Unknown),
             actions: Vec::new(),
-            protocols: HashSet::new(),
+            protocols: Default::default(),
+            preflights: Default::default(),
             from: Address::zero(),
             contract: Address::zero(),
             proxy_impl: None,
             hash: TxHash::zero(),
             block_number: 9,
+            tx_index: None,
+            gas: None,
+            logs: Vec::new(),
         };
         let actions = [ActionType::Liquidation, ActionType::Arbitrage]
             .iter()
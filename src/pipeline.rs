@@ -0,0 +1,235 @@
+use crate::{
+    addresses::ChainConfig,
+    inspectors::{
+        Aave, AaveV2, Balancer, BalancerV2, Bancor, Compound, CompoundV3, Curve, DyDx, EventLogs,
+        FlashLoans, GasTokens, InspectorRegistry, Kyber, LooksRare, MakerDAO, OneInch, Permit2,
+        Seaport, Synthetix, UniswapV2, UniswapV3, UniswapV3Positions, UniversalRouter,
+        WethInspector, ZeroEx, ERC1155, ERC20, ERC721, X2Y2,
+    },
+    reducers::{
+        AggregatorReducer, AllowanceExploitReducer, ArbitrageReducer, CompoundV3Reducer,
+        DustReducer, FrontrunReducer, JitReducer, LiquidationCompetitionReducer,
+        LiquidationReducer, MakerAuctionReducer, NftArbitrageReducer, NftSweepReducer,
+        OpenArbitrageReducer, OracleManipulationReducer, SandwichReducer,
+        StatisticalArbitrageReducer, TradeReducer, TransferReducer,
+    },
+    types::Inspection,
+    BatchInspector, BlockReducer, Inspector, Reducer, UniswapV2Oracle,
+};
+use ethers::{contract::ContractError, providers::Middleware};
+use std::sync::Arc;
+
+/// The inspectors which can be selected, in the order they run in
+pub const INSPECTORS: [&str; 29] = [
+    "erc721",
+    "erc1155",
+    "seaport",
+    "looksrare",
+    "x2y2",
+    "zeroex",
+    "kyber",
+    "bancor",
+    "weth",
+    "erc20",
+    "permit2",
+    "gastokens",
+    "dydx",
+    "flashloans",
+    "oneinch",
+    "universalrouter",
+    "balancer",
+    "balancerv2",
+    "uniswap",
+    "uniswapv3",
+    "uniswapv3positions",
+    "curve",
+    "synthetix",
+    "aave",
+    "aavev2",
+    "compound",
+    "compoundv3",
+    "maker",
+    "logs",
+];
+
+#[derive(Debug, Clone, Default)]
+/// The reducers which only run when asked for, since they have false
+/// positives or need the prices of the inspected tokens
+pub struct PipelineOptions {
+    /// Flag the large one-way trades of market makers as candidate CeFi-DeFi
+    /// arbitrages
+    pub statistical_arbs: bool,
+    /// Flag the tokens pulled with an allowance from accounts which took no
+    /// part in their tx as candidate exploits
+    pub allowance_exploits: bool,
+    /// Flag the chains of trades made by bots which end in another token worth
+    /// more than they sold as open arbitrages
+    pub open_arbitrages: bool,
+    /// Drop the arbitrages and sandwiches which made less than this many USD
+    /// net of gas
+    pub min_profit: Option<f64>,
+}
+
+/// The inspectors and reducers which the binaries run on a chain, so that they
+/// report the same MEV for the same transactions.
+///
+/// Some reducers can only run once the inspections' gas is known, from their
+/// receipts, and once the prices of their tokens were fetched. They are run by
+/// `reduce_with_gas` after the `processor` inspected and reduced the traces.
+pub struct Pipeline {
+    processor: Arc<BatchInspector>,
+    chain: ChainConfig,
+    options: PipelineOptions,
+}
+
+impl Pipeline {
+    /// Instantiates all the inspectors, along with all the reducers and the
+    /// opt-in ones which were asked for
+    pub async fn create<M: Middleware + 'static>(
+        provider: Arc<M>,
+        chain: &ChainConfig,
+        options: PipelineOptions,
+    ) -> Result<Self, ContractError<M>> {
+        Self::with_inspectors(provider, chain, &INSPECTORS, options).await
+    }
+
+    /// Instantiates the `selected` inspectors, which must be in `INSPECTORS`,
+    /// along with all the reducers and the opt-in ones which were asked for
+    pub async fn with_inspectors<M: Middleware + 'static>(
+        provider: Arc<M>,
+        chain: &ChainConfig,
+        selected: &[&str],
+        options: PipelineOptions,
+    ) -> Result<Self, ContractError<M>> {
+        let mut registry = InspectorRegistry::default();
+        for name in INSPECTORS.iter().filter(|name| selected.contains(name)) {
+            let inspector: Box<dyn Inspector + Send + Sync> = match *name {
+                // Classify Transfers, NFTs first since they share ERC20's `transferFrom`
+                "erc721" => Box::new(ERC721::new()),
+                "erc1155" => Box::new(ERC1155::new()),
+                // Classify NFT Marketplaces, whose payments are not transfers yet
+                "seaport" => Box::new(Seaport::new()),
+                "looksrare" => Box::new(LooksRare::new()),
+                "x2y2" => Box::new(X2Y2::new()),
+                "zeroex" => Box::new(ZeroEx::with_chain(chain)),
+                // Kyber and Bancor are paid in ETH, which must not be classified
+                // as a transfer yet
+                "kyber" => Box::new(Kyber::with_chain(chain)),
+                "bancor" => Box::new(Bancor::with_chain(chain)),
+                // Classify wrapping ETH before ERC20 decodes it as a `WethDeposit`
+                "weth" => Box::new(WethInspector::with_chain(chain)),
+                "erc20" => Box::new(ERC20::with_chain(chain)),
+                // Classify the transfers which Permit2 makes for the routers
+                "permit2" => Box::new(Permit2::new()),
+                "gastokens" => Box::new(GasTokens::new()),
+                // Classify Flash Loans, dYdX's before the others since its loans
+                // are batched with its trades
+                "dydx" => Box::new(DyDx::new()),
+                "flashloans" => Box::new(FlashLoans::new()),
+                // Classify Aggregators, which collapse the AMM calls they route
+                // through, and Uniswap's Universal Router, which calls the pools
+                // directly
+                "oneinch" => Box::new(OneInch::with_chain(chain)),
+                "universalrouter" => Box::new(UniversalRouter::with_chain(chain)),
+                // Classify AMMs
+                "balancer" => Box::new(Balancer::with_chain(chain)),
+                "balancerv2" => Box::new(BalancerV2::with_chain(chain)),
+                "uniswap" => Box::new(UniswapV2::with_chain(chain)),
+                "uniswapv3" => Box::new(UniswapV3::with_chain(chain)),
+                "uniswapv3positions" => Box::new(UniswapV3Positions::new()),
+                // the registry of Curve's pools is only known on mainnet
+                "curve" if chain.chain_id == 1 => Box::new(Curve::create(provider.clone()).await?),
+                "curve" => Box::new(Curve::with_chain(Vec::new(), chain)),
+                "synthetix" => Box::new(Synthetix::new()),
+                // Classify Liquidations
+                "aave" => Box::new(Aave::new()),
+                "aavev2" => Box::new(AaveV2::new()),
+                "compound" => Box::new(Compound::create(provider.clone()).await?),
+                "compoundv3" => Box::new(CompoundV3::new()),
+                "maker" => Box::new(MakerDAO::new()),
+                // Merge in what the traces missed, or everything if there were none
+                "logs" => Box::new(EventLogs::new()),
+                _ => unreachable!("unknown inspector {}", name),
+            };
+            debug_assert_eq!(inspector.name(), *name);
+            registry = registry.register(inspector);
+        }
+
+        let mut reducers: Vec<Box<dyn Reducer + Send + Sync>> = vec![
+            Box::new(AggregatorReducer::with_chain(chain)),
+            Box::new(LiquidationReducer::with_chain(chain)),
+            Box::new(TradeReducer::with_chain(chain)),
+            Box::new(TransferReducer::new()),
+            Box::new(OracleManipulationReducer::new()),
+            Box::new(ArbitrageReducer::with_chain(chain)),
+            Box::new(JitReducer::new()),
+            Box::new(NftArbitrageReducer::with_chain(chain)),
+            Box::new(NftSweepReducer::with_chain(chain)),
+        ];
+        if options.statistical_arbs {
+            reducers.push(Box::new(StatisticalArbitrageReducer::new()));
+        }
+        if options.allowance_exploits {
+            reducers.push(Box::new(AllowanceExploitReducer::new()));
+        }
+        let block_reducers: Vec<Box<dyn BlockReducer + Send + Sync>> = vec![
+            Box::new(JitReducer::new()),
+            Box::new(SandwichReducer::with_chain(chain)),
+            Box::new(MakerAuctionReducer::new()),
+            Box::new(CompoundV3Reducer::new()),
+            Box::new(LiquidationCompetitionReducer::new()),
+        ];
+        let processor =
+            BatchInspector::from_registry(registry, reducers).with_block_reducers(block_reducers);
+
+        Ok(Self {
+            processor: Arc::new(processor),
+            chain: chain.clone(),
+            options,
+        })
+    }
+
+    /// The inspector of the traces, which runs all the reducers which do not
+    /// need the inspections' gas or prices
+    pub fn processor(&self) -> &Arc<BatchInspector> {
+        &self.processor
+    }
+
+    /// Whether `reduce_with_gas` needs the prices of the inspected tokens
+    pub fn needs_prices(&self) -> bool {
+        self.options.open_arbitrages || self.options.min_profit.is_some()
+    }
+
+    /// Runs the reducers which need the gas of the inspections, e.g. frontruns
+    /// are told apart by their gas price, and the opt-in ones which value
+    /// their actions, once the prices of their tokens were fetched at their
+    /// blocks. The inspections must be the ones of a block, in the order of
+    /// their transactions.
+    pub async fn reduce_with_gas<M: Middleware + 'static>(
+        &self,
+        provider: Arc<M>,
+        inspections: &mut [Inspection],
+    ) {
+        FrontrunReducer::new().reduce_block(inspections);
+        if !self.needs_prices() {
+            return;
+        }
+
+        let oracle = UniswapV2Oracle::new();
+        oracle.fetch_inspections(provider, inspections).await;
+        if self.options.open_arbitrages {
+            let reducer = OpenArbitrageReducer::new(oracle.clone()).with_chain(&self.chain);
+            for inspection in inspections.iter_mut() {
+                reducer.reduce(inspection);
+                inspection.prune();
+            }
+        }
+        // the dust is only told apart once all the other reducers ran
+        if let Some(min_profit) = self.options.min_profit {
+            DustReducer::new(oracle)
+                .with_min_profit(min_profit)
+                .reduce_block(inspections);
+            inspections.iter_mut().for_each(|i| i.prune());
+        }
+    }
+}
@@ -1,17 +1,305 @@
 #![allow(clippy::clippy::too_many_arguments)]
-use crate::addresses::{parse_address, ETH, WETH};
+use crate::{
+    addresses::{canonical_token, parse_address, ETH, USDC, WETH},
+    cache::SharedCache,
+    types::{actions::SpecificAction, Inspection},
+};
 use ethers::{
     contract::{abigen, ContractError},
     providers::Middleware,
-    types::{Address, BlockNumber, U256},
-    utils::WEI_IN_ETHER,
+    types::{Address, BlockNumber, I256, U256},
+    utils::{keccak256, WEI_IN_ETHER},
 };
 use once_cell::sync::Lazy;
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryFrom,
+    sync::Arc,
+};
 
 // Generate type-safe bindings to Uniswap's router
 abigen!(Uniswap, "abi/unirouterv2.json");
 
+// Generate type-safe bindings to Uniswap's pairs
+abigen!(UniswapPair, "abi/unipair.json");
+
+static UNISWAP_FACTORY: Lazy<Address> =
+    Lazy::new(|| parse_address("5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f"));
+
+const PAIR_INIT_CODE_HASH: &str =
+    "96e8ac4277198ff8b6f785478aa9a39f403cb768dd02cbee326c3e7da348845f";
+
+/// A source of historical token prices
+pub trait PriceOracle {
+    /// Returns the price of `token` in ETH at `block`, as the wei which
+    /// 1e18 of the token's base units are worth
+    fn price_at(&self, token: Address, block: u64) -> Option<U256>;
+
+    /// Returns the reserves of `token_in` and `token_out` in `pool` at `block`,
+    /// in this order, for oracles which price tokens from pools
+    fn reserves_at(
+        &self,
+        _pool: Address,
+        _token_in: Address,
+        _token_out: Address,
+        _block: u64,
+    ) -> Option<(U256, U256)> {
+        None
+    }
+}
+
+/// Converts `amount` to a float, losing the precision beyond 53 bits
+pub(crate) fn to_f64(amount: U256) -> f64 {
+    amount
+        .0
+        .iter()
+        .rev()
+        .fold(0.0, |total, limb| total * 2f64.powi(64) + *limb as f64)
+}
+
+/// The amount which a constant product pool with a 0.3% fee, e.g. a Uniswap V2
+/// pair, pays out for `amount_in` given its reserves before the trade. `None`
+/// if the pool is empty or the amounts overflow.
+pub fn amount_out(amount_in: U256, reserve_in: U256, reserve_out: U256) -> Option<U256> {
+    let amount_in = amount_in.checked_mul(997.into())?;
+    let denominator = reserve_in
+        .checked_mul(1000.into())?
+        .checked_add(amount_in)?;
+    if denominator.is_zero() || reserve_out.is_zero() {
+        return None;
+    }
+    Some(amount_in.checked_mul(reserve_out)? / denominator)
+}
+
+/// Converts an amount to a signed one, unless it does not fit in one, e.g. the
+/// `U256::MAX` which tokens pass for unlimited amounts
+pub fn signed(amount: U256) -> Option<I256> {
+    I256::try_from(amount).ok()
+}
+
+/// How much more was gained than spent, unless either is too large to be signed
+pub fn signed_delta(gained: U256, spent: U256) -> Option<I256> {
+    signed(gained)?.checked_sub(signed(spent)?)
+}
+
+/// Values each token delta in ETH and nets them out, so that a searcher which
+/// ends up with different tokens than the ones it started with is accounted for.
+/// Returns `None` if a token which has a non-zero delta cannot be priced.
+pub fn net_profit<O: PriceOracle, I: IntoIterator<Item = (Address, I256)>>(
+    oracle: &O,
+    deltas: I,
+    block: u64,
+) -> Option<I256> {
+    let mut netted = HashMap::new();
+    for (token, delta) in deltas {
+        // assume price parity of WETH / ETH
+        let token = canonical_token(token, *WETH);
+        let total = netted.entry(token).or_insert_with(I256::zero);
+        *total = total.checked_add(delta)?;
+    }
+
+    netted
+        .into_iter()
+        .filter(|(_, delta)| !delta.is_zero())
+        .try_fold(I256::zero(), |profit, (token, delta)| {
+            let price = oracle.price_at(token, block)?;
+            let value = delta.abs().into_raw().checked_mul(price)? / WEI_IN_ETHER;
+            let value = signed(value)?;
+            if delta.is_negative() {
+                profit.checked_sub(value)
+            } else {
+                profit.checked_add(value)
+            }
+        })
+}
+
+/// Converts an amount of wei to USD at the price of USDC at `block`. Returns
+/// `None` if USDC cannot be priced.
+pub fn usd_value<O: PriceOracle>(oracle: &O, wei: I256, block: u64) -> Option<f64> {
+    // the wei which 1e18 base units of USDC, i.e. 1e12 USD, are worth
+    let price = to_f64(oracle.price_at(*USDC, block)?);
+    if price == 0.0 {
+        return None;
+    }
+    let dollars = to_f64(wei.abs().into_raw()) * 1e12 / price;
+    Some(if wei.is_negative() { -dollars } else { dollars })
+}
+
+/// Prices tokens against WETH using the reserves of their Uniswap V2 pair.
+///
+/// The reserves must be fetched for each token and block before they can be
+/// priced, since `PriceOracle` is synchronous. They never expire, since they
+/// are keyed by block, but only the most recently used ones are kept.
+///
+/// Cloning the oracle does not copy its reserves: the clones share them, so
+/// that the oracle can be fetched into and priced from by many threads. The
+/// reserves fetched, and the `with_max_entries` set, through any clone apply to
+/// all of them. Instantiate a new oracle for one with its own reserves.
+/// **Fetching requires an archive node to work**
+#[derive(Debug, Clone, Default)]
+pub struct UniswapV2Oracle {
+    /// (token, block) -> (token reserve, WETH reserve)
+    reserves: SharedCache<(Address, u64), (U256, U256)>,
+}
+
+impl PriceOracle for UniswapV2Oracle {
+    fn price_at(&self, token: Address, block: u64) -> Option<U256> {
+        if token == *ETH || token == *WETH {
+            return Some(WEI_IN_ETHER);
+        }
+
+        let (reserve, weth_reserve) = self.reserves.get(&(token, block))?;
+        if reserve.is_zero() {
+            return None;
+        }
+        weth_reserve.checked_mul(WEI_IN_ETHER).map(|x| x / reserve)
+    }
+
+    /// The reserves are only known for the WETH pairs of the inserted tokens
+    fn reserves_at(
+        &self,
+        pool: Address,
+        token_in: Address,
+        token_out: Address,
+        block: u64,
+    ) -> Option<(U256, U256)> {
+        let (token, weth_in) = if token_in == *WETH {
+            (token_out, true)
+        } else if token_out == *WETH {
+            (token_in, false)
+        } else {
+            return None;
+        };
+        if pool != pair_for(token, *WETH) {
+            return None;
+        }
+
+        let (reserve, weth_reserve) = self.reserves.get(&(token, block))?;
+        if weth_in {
+            Some((weth_reserve, reserve))
+        } else {
+            Some((reserve, weth_reserve))
+        }
+    }
+}
+
+impl UniswapV2Oracle {
+    /// Instantiates an oracle without any reserves
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keeps the reserves of up to `max_entries` tokens and blocks, in this
+    /// oracle and all its clones
+    pub fn with_max_entries(self, max_entries: usize) -> Self {
+        self.reserves.set_max_entries(max_entries);
+        self
+    }
+
+    /// Sets the reserves of the token's WETH pair at `block`
+    pub fn insert(&self, token: Address, block: u64, reserve: U256, weth_reserve: U256) {
+        self.reserves
+            .insert((token, block), (reserve, weth_reserve));
+    }
+
+    /// Fetches the reserves of the token's WETH pair at `block`
+    pub async fn fetch<M: Middleware>(
+        &self,
+        provider: Arc<M>,
+        token: Address,
+        block: u64,
+    ) -> Result<(), ContractError<M>> {
+        let pair = UniswapPair::new(pair_for(token, *WETH), provider);
+        let (reserve0, reserve1, _) = pair.get_reserves().block(block).call().await?;
+        let (reserve0, reserve1) = (U256::from(reserve0), U256::from(reserve1));
+        let reserves = if token < *WETH {
+            (reserve0, reserve1)
+        } else {
+            (reserve1, reserve0)
+        };
+        self.reserves.insert((token, block), reserves);
+        Ok(())
+    }
+
+    /// Fetches the reserves of the WETH pairs of `tokens` at `block`. The ones
+    /// which cannot be fetched, e.g. because the token has no pair, are left
+    /// unpriced.
+    pub async fn fetch_all<M: Middleware, I: IntoIterator<Item = Address>>(
+        &self,
+        provider: Arc<M>,
+        tokens: I,
+        block: u64,
+    ) {
+        let tokens = tokens
+            .into_iter()
+            .filter(|token| *token != *ETH && *token != *WETH)
+            .collect::<HashSet<_>>();
+        let futs = tokens.into_iter().map(|token| {
+            let provider = provider.clone();
+            async move {
+                if let Err(err) = self.fetch(provider, token, block).await {
+                    log::debug!("could not fetch the reserves of {:?}: {}", token, err);
+                }
+            }
+        });
+        futures::future::join_all(futs).await;
+    }
+
+    /// Fetches the reserves which the actions of `inspections` are valued with
+    /// at their blocks: the WETH pairs of the tokens which they traded, made,
+    /// borrowed or liquidated, and USDC's to value them in USD
+    pub async fn fetch_inspections<M: Middleware>(
+        &self,
+        provider: Arc<M>,
+        inspections: &[Inspection],
+    ) {
+        let mut blocks: HashMap<u64, HashSet<Address>> = HashMap::new();
+        for inspection in inspections {
+            let tokens = blocks.entry(inspection.block_number).or_default();
+            tokens.insert(*USDC);
+            for action in inspection.known_actions() {
+                match action {
+                    SpecificAction::Trade(trade) => {
+                        tokens.extend(&[trade.t1.token, trade.t2.token])
+                    }
+                    SpecificAction::Arbitrage(arb) => tokens.extend(&[arb.token]),
+                    SpecificAction::Liquidation(liq) => {
+                        tokens.extend(&[liq.sent_token, liq.received_token])
+                    }
+                    SpecificAction::ProfitableLiquidation(liq) => tokens.extend(&[liq.token]),
+                    SpecificAction::FlashLoan(loan) => tokens.extend(&[loan.token]),
+                    SpecificAction::Sandwich(sandwich) => tokens.extend(&[sandwich.token]),
+                    _ => {}
+                }
+            }
+        }
+        for (block, tokens) in blocks {
+            self.fetch_all(provider.clone(), tokens, block).await;
+        }
+    }
+}
+
+/// Computes the CREATE2 address of the Uniswap V2 pair of 2 tokens
+fn pair_for(token_a: Address, token_b: Address) -> Address {
+    let (token0, token1) = if token_a < token_b {
+        (token_a, token_b)
+    } else {
+        (token_b, token_a)
+    };
+    let salt = keccak256([token0.as_bytes(), token1.as_bytes()].concat());
+    let init_code_hash = hex::decode(PAIR_INIT_CODE_HASH).expect("invalid init code hash");
+    let hash = keccak256(
+        [
+            &[0xff],
+            UNISWAP_FACTORY.as_bytes(),
+            &salt[..],
+            &init_code_hash[..],
+        ]
+        .concat(),
+    );
+    Address::from_slice(&hash[12..])
+}
+
 /// Gets historical prices in ETH for any token via Uniswap.
 /// **Requires an archive node to work**
 pub struct HistoricalPrice<M> {
@@ -77,6 +365,7 @@ impl<M: Middleware> HistoricalPrice<M> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::types::actions::{Trade, Transfer};
     use ethers::{
         providers::{Http, Provider},
         utils::WEI_IN_ETHER as WEI,
@@ -93,6 +382,287 @@ mod tests {
         provider
     });
 
+    fn usdc() -> Address {
+        parse_address("a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48")
+    }
+
+    fn dai() -> Address {
+        parse_address("6b175474e89094c44da98b954eedeac495271d0f")
+    }
+
+    fn oracle() -> UniswapV2Oracle {
+        let oracle = UniswapV2Oracle::new();
+        // 1 ETH = 2000 USDC = 2000 DAI
+        oracle.insert(usdc(), 1, (2000 * 1_000_000u64).into(), WEI);
+        oracle.insert(dai(), 1, WEI * 2000, WEI);
+        oracle
+    }
+
+    #[test]
+    fn computes_pair_address() {
+        assert_eq!(
+            pair_for(usdc(), *WETH),
+            parse_address("b4e16d0168e52d35cacd2c6185b44281ec28c9dc")
+        );
+        assert_eq!(pair_for(*WETH, usdc()), pair_for(usdc(), *WETH));
+    }
+
+    #[test]
+    fn unlimited_amounts_are_not_signed() {
+        assert_eq!(signed_delta(5.into(), 7.into()), Some(I256::from(-2)));
+        assert_eq!(signed(U256::MAX), None);
+        assert_eq!(signed_delta(U256::MAX, 1.into()), None);
+        // the largest signed amount
+        let max = U256::MAX >> 1;
+        assert_eq!(signed(max), Some(I256::MAX));
+        assert_eq!(signed_delta(max, 0.into()), Some(I256::MAX));
+        assert_eq!(signed_delta(0.into(), max), Some(-I256::MAX));
+    }
+
+    #[test]
+    fn prices_from_reserves() {
+        let oracle = oracle();
+        // 1e18 base units of USDC are 1e12 USDC
+        assert_eq!(
+            oracle.price_at(usdc(), 1).unwrap(),
+            U256::from(500_000_000_000_000_000_000_000_000u128)
+        );
+        assert_eq!(oracle.price_at(dai(), 1).unwrap(), WEI / 2000);
+        assert_eq!(oracle.price_at(*ETH, 1).unwrap(), WEI);
+        // not fetched
+        assert!(oracle.price_at(dai(), 2).is_none());
+    }
+
+    #[test]
+    fn clones_share_their_reserves() {
+        let oracle = UniswapV2Oracle::new().with_max_entries(2);
+        let clone = oracle.clone();
+        clone.insert(dai(), 1, WEI * 2000, WEI);
+        assert_eq!(oracle.price_at(dai(), 1).unwrap(), WEI / 2000);
+
+        // the least recently used reserves are evicted
+        oracle.insert(dai(), 2, WEI * 2000, WEI);
+        oracle.insert(dai(), 3, WEI * 2000, WEI);
+        assert!(clone.price_at(dai(), 1).is_none());
+        assert!(clone.price_at(dai(), 3).is_some());
+
+        // and so is the limit set through a clone
+        let _ = clone.clone().with_max_entries(1);
+        assert!(oracle.price_at(dai(), 2).is_none());
+        assert!(oracle.price_at(dai(), 3).is_some());
+    }
+
+    #[test]
+    fn trade_prices_and_impact() {
+        let oracle = oracle();
+        let pair = pair_for(usdc(), *WETH);
+        let usr = Address::random();
+        let trade = |sold: (Address, U256), bought: (Address, U256)| Trade {
+            t1: Transfer {
+                from: usr,
+                to: pair,
+                amount: sold.1,
+                token: sold.0,
+            },
+            t2: Transfer {
+                from: pair,
+                to: usr,
+                amount: bought.1,
+                token: bought.0,
+            },
+        };
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+
+        // sells 1 WETH for 1990 USDC, while the pool's spot price is 2000
+        let buy = trade((*WETH, WEI), (usdc(), (1990 * 1_000_000u64).into()));
+        assert!(close(buy.implied_price().unwrap(), 1990.0));
+        assert!(close(buy.price_impact(&oracle, 1).unwrap(), 0.005));
+
+        // the other way around, 2000 USDC for 0.99 WETH
+        let sell = trade(
+            (usdc(), (2000 * 1_000_000u64).into()),
+            (*WETH, WEI * 99 / 100),
+        );
+        assert!(close(sell.implied_price().unwrap(), 0.000495));
+        assert!(close(sell.price_impact(&oracle, 1).unwrap(), 0.01));
+
+        // the reserves are not known at another block or for another pool
+        assert!(buy.price_impact(&oracle, 2).is_none());
+        let mut elsewhere = buy.clone();
+        elsewhere.t1.to = Address::random();
+        assert!(elsewhere.price_impact(&oracle, 1).is_none());
+        // nor the decimals of an unknown token
+        let mut unknown = buy;
+        unknown.t2.token = Address::random();
+        assert!(unknown.implied_price().is_none());
+    }
+
+    #[tokio::test]
+    async fn fetches_the_reserves_of_the_inspected_tokens() {
+        use crate::{
+            test_helpers::mk_inspection,
+            types::{
+                actions::{Trade, Transfer},
+                Classification,
+            },
+        };
+        use ethers::{abi::Token, providers::MockProvider, types::Bytes};
+
+        let (usr, pool) = (Address::random(), Address::random());
+        let trade = Trade::new(
+            Transfer::new(usr, pool, WEI * 2000, dai()),
+            Transfer::new(pool, usr, WEI, *WETH),
+        );
+        let mut inspection = mk_inspection(vec![Classification::new(trade, vec![])]);
+        inspection.block_number = 1;
+
+        // the reserves of both DAI's pair and USDC's, which are their pairs'
+        // token0, are 2000 tokens for 1 WETH
+        let mock = MockProvider::new();
+        let reserves = ethers::abi::encode(&[
+            Token::Uint(WEI * 2000),
+            Token::Uint(WEI),
+            Token::Uint(0.into()),
+        ]);
+        for _ in 0..2 {
+            mock.push::<Bytes, Bytes>(reserves.clone().into()).unwrap();
+        }
+        let oracle = UniswapV2Oracle::new();
+        oracle
+            .fetch_inspections(Arc::new(Provider::new(mock)), &[inspection])
+            .await;
+
+        assert_eq!(oracle.price_at(dai(), 1), Some(WEI / 2000));
+        assert_eq!(oracle.price_at(usdc(), 1), Some(WEI / 2000));
+        assert_eq!(oracle.price_at(dai(), 2), None);
+    }
+
+    #[test]
+    fn nets_out_every_token() {
+        let oracle = oracle();
+        let eth = |x: i64| I256::from(x) * I256::from_raw(WEI);
+
+        // starts with 1 ETH of WETH and ends up with 1000 USDC and 1010 DAI
+        let deltas = vec![
+            (*WETH, eth(-1)),
+            (usdc(), I256::from(2000 * 1_000_000i64)),
+            (usdc(), I256::from(-1000 * 1_000_000i64)),
+            (dai(), eth(1010)),
+        ];
+        let profit = net_profit(&oracle, deltas, 1).unwrap();
+        assert_eq!(profit, I256::from_raw(WEI / 200));
+
+        // unpriced tokens are fine as long as they net out to zero
+        let token = parse_address("e41d2489571d322189246dafa5ebde1f4699f498");
+        let deltas = vec![(token, eth(1)), (token, eth(-1)), (*ETH, eth(1))];
+        assert_eq!(net_profit(&oracle, deltas, 1).unwrap(), eth(1));
+        assert!(net_profit(&oracle, vec![(token, eth(1))], 1).is_none());
+    }
+
+    #[test]
+    fn action_profits() {
+        use crate::types::actions::{Liquidation, Trade, Transfer};
+        let oracle = oracle();
+        let (usr, pool) = (Address::random(), Address::random());
+
+        // sells 1 ETH for 2100 DAI
+        let trade = Trade::new(
+            Transfer {
+                from: usr,
+                to: pool,
+                amount: WEI,
+                token: *WETH,
+            },
+            Transfer {
+                from: pool,
+                to: usr,
+                amount: WEI * 2100,
+                token: dai(),
+            },
+        );
+        assert_eq!(
+            trade.eth_profit(&oracle, 1).unwrap(),
+            I256::from_raw(WEI / 20)
+        );
+
+        // repays 2000 USDC for 1.05 ETH
+        let liquidation = Liquidation {
+            sent_token: usdc(),
+            sent_amount: (2000 * 1_000_000u64).into(),
+            received_token: *ETH,
+            received_amount: WEI * 105 / 100,
+            ..Default::default()
+        };
+        assert_eq!(
+            liquidation.eth_profit(&oracle, 1).unwrap(),
+            I256::from_raw(WEI / 20)
+        );
+    }
+
+    #[test]
+    fn values_in_usd() {
+        use crate::types::actions::Arbitrage;
+        let oracle = oracle();
+        let usd = |wei: I256| usd_value(&oracle, wei, 1).unwrap();
+        assert!((usd(I256::from_raw(WEI)) - 2000.0).abs() < 1e-6);
+        assert!((usd(-I256::from_raw(WEI / 4)) + 500.0).abs() < 1e-6);
+        // USDC is not priced before then
+        assert_eq!(usd_value(&oracle, I256::from_raw(WEI), 0), None);
+
+        let arb = Arbitrage {
+            profit: WEI * 200,
+            token: dai(),
+            to: Address::zero(),
+            capital: 0.into(),
+            trades: vec![],
+            recipient: Address::zero(),
+            searcher: Default::default(),
+        };
+        assert!((arb.usd_profit(&oracle, 1).unwrap() - 200.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn subtracts_gas() {
+        use crate::{
+            test_helpers::mk_inspection,
+            types::{actions::Arbitrage, Classification, GasInfo},
+        };
+        let oracle = oracle();
+
+        // an arb of 200 DAI which paid 0.02 ETH of gas
+        let mut inspection = mk_inspection(vec![Classification::new(
+            Arbitrage {
+                profit: WEI * 200,
+                token: dai(),
+                to: Address::zero(),
+                capital: 0.into(),
+                trades: vec![],
+                recipient: Address::zero(),
+                searcher: Default::default(),
+            },
+            vec![],
+        )]);
+        inspection.block_number = 1;
+        assert!(inspection.net_profit_eth(&oracle).is_none());
+
+        inspection.gas = Some(
+            GasInfo {
+                gas_used: 200_000.into(),
+                gas_price: 1.into(),
+                ..Default::default()
+            }
+            .with_fees(
+                (60 * 1_000_000_000u64).into(),
+                (200 * 1_000_000_000u64).into(),
+                (40 * 1_000_000_000u64).into(),
+            ),
+        );
+        assert_eq!(
+            inspection.net_profit_eth(&oracle).unwrap(),
+            I256::from_raw(WEI / 10 - WEI / 50)
+        );
+    }
+
     #[tokio::test]
     #[ignore] // This test can only run against an archive node
     async fn check_historical_price() {
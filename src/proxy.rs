@@ -0,0 +1,189 @@
+use ethers::{
+    providers::Middleware,
+    types::{Address, H256},
+};
+use once_cell::sync::Lazy;
+use std::{collections::HashMap, sync::Mutex};
+
+/// `keccak256("eip1967.proxy.implementation") - 1`
+static EIP1967_IMPLEMENTATION_SLOT: Lazy<H256> = Lazy::new(|| {
+    "360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc"
+        .parse()
+        .unwrap()
+});
+
+/// `keccak256("PROXIABLE")`, where EIP-1822 (UUPS) proxies store their implementation
+static EIP1822_IMPLEMENTATION_SLOT: Lazy<H256> = Lazy::new(|| {
+    "c5f16f0fcc639fa48a6947836d9850f504798523bf8c9a3a87d5876cf622bcf7"
+        .parse()
+        .unwrap()
+});
+
+/// The code of an EIP-1167 minimal proxy is its implementation's address
+/// between this prefix and suffix
+const EIP1167_PREFIX: [u8; 10] = [0x36, 0x3d, 0x3d, 0x37, 0x3d, 0x3d, 0x3d, 0x36, 0x3d, 0x73];
+const EIP1167_SUFFIX: [u8; 15] = [
+    0x5a, 0xf4, 0x3d, 0x82, 0x80, 0x3e, 0x90, 0x3d, 0x91, 0x60, 0x2b, 0x57, 0xfd, 0x5b, 0xf3,
+];
+
+/// The implementations which have already been resolved, `None` for contracts
+/// which are not proxies
+static IMPLEMENTATIONS: Lazy<Mutex<HashMap<Address, Option<Address>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Returns the implementation behind `address`, or `None` if it is not a proxy.
+///
+/// EIP-1167 clones have their implementation embedded in their code, while
+/// upgradeable proxies store it at the EIP-1967 or the EIP-1822 slot. The slot
+/// is read instead of calling `implementation()`, since transparent proxies
+/// only answer that call for their admin, whose address is kept at another slot.
+///
+/// Results are cached for the lifetime of the process, so upgrades which
+/// happen after a proxy got resolved are not picked up.
+pub async fn resolve_implementation<M: Middleware>(
+    address: Address,
+    provider: &M,
+) -> Result<Option<Address>, M::Error> {
+    if let Some(implementation) = IMPLEMENTATIONS.lock().unwrap().get(&address) {
+        return Ok(*implementation);
+    }
+
+    let code = provider.get_code(address, None).await?;
+    let mut implementation = minimal_proxy_implementation(code.as_ref());
+    if implementation.is_none() {
+        for slot in [*EIP1967_IMPLEMENTATION_SLOT, *EIP1822_IMPLEMENTATION_SLOT].iter() {
+            let value = provider.get_storage_at(address, *slot, None).await?;
+            if !value.is_zero() {
+                // the address is stored in the slot's lower 20 bytes
+                implementation = Some(Address::from(value));
+                break;
+            }
+        }
+    }
+
+    IMPLEMENTATIONS
+        .lock()
+        .unwrap()
+        .insert(address, implementation);
+    Ok(implementation)
+}
+
+/// Returns the implementation of an EIP-1167 minimal proxy from its code
+pub fn minimal_proxy_implementation(code: &[u8]) -> Option<Address> {
+    if code.len() != EIP1167_PREFIX.len() + 20 + EIP1167_SUFFIX.len()
+        || !code.starts_with(&EIP1167_PREFIX)
+        || !code.ends_with(&EIP1167_SUFFIX)
+    {
+        return None;
+    }
+
+    let start = EIP1167_PREFIX.len();
+    Some(Address::from_slice(&code[start..start + 20]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_helpers::{addrs, mk_call, mk_inspection},
+        types::Classification,
+    };
+    use ethers::{
+        providers::Provider,
+        types::{Bytes, CallType},
+    };
+
+    fn minimal_proxy(implementation: Address) -> Bytes {
+        [
+            &EIP1167_PREFIX[..],
+            implementation.as_bytes(),
+            &EIP1167_SUFFIX[..],
+        ]
+        .concat()
+        .into()
+    }
+
+    #[test]
+    fn decodes_minimal_proxies() {
+        let implementation = addrs()[0];
+        let code = minimal_proxy(implementation);
+        assert_eq!(
+            minimal_proxy_implementation(code.as_ref()),
+            Some(implementation)
+        );
+        // any other code is not a minimal proxy
+        assert_eq!(minimal_proxy_implementation(&code.as_ref()[1..]), None);
+        assert_eq!(minimal_proxy_implementation(&[]), None);
+    }
+
+    #[test]
+    fn follows_the_first_delegatecall() {
+        let addrs = addrs();
+        let (usr, proxy, implementation, library) = (addrs[0], addrs[1], addrs[2], addrs[3]);
+        let delegatecall = |from, to, trace_address| {
+            let mut call = mk_call(from, to, Bytes::default(), trace_address);
+            if let Classification::Unknown(ref mut calltrace) = call {
+                calltrace.call.call_type = CallType::DelegateCall;
+            }
+            call
+        };
+
+        let inspection = mk_inspection(vec![
+            mk_call(usr, proxy, Bytes::default(), vec![]),
+            delegatecall(proxy, implementation, vec![0]),
+            // the implementation's own delegatecalls run in the proxy's context
+            delegatecall(proxy, library, vec![0, 0]),
+        ]);
+        assert_eq!(inspection.implementation_of(proxy), Some(implementation));
+        assert_eq!(inspection.implementation_of(usr), None);
+    }
+
+    #[tokio::test]
+    async fn resolves_and_caches_implementations() {
+        let addrs = addrs();
+        let (clone, transparent, uups, contract) = (addrs[0], addrs[1], addrs[2], addrs[3]);
+        let (provider, mock) = Provider::mocked();
+        // the mock's responses are popped from the back
+        let slot = |address: Address| H256::from(address);
+        let responses = [
+            // a regular contract, whose slots are all empty
+            serde_json::to_value(H256::zero()).unwrap(),
+            serde_json::to_value(H256::zero()).unwrap(),
+            serde_json::to_value(Bytes::from(vec![0x60, 0x80])).unwrap(),
+            // an EIP-1822 proxy
+            serde_json::to_value(slot(addrs[6])).unwrap(),
+            serde_json::to_value(H256::zero()).unwrap(),
+            serde_json::to_value(Bytes::from(vec![0x60, 0x80])).unwrap(),
+            // a transparent EIP-1967 proxy
+            serde_json::to_value(slot(addrs[5])).unwrap(),
+            serde_json::to_value(Bytes::from(vec![0x60, 0x80])).unwrap(),
+            // a minimal proxy
+            serde_json::to_value(minimal_proxy(addrs[4])).unwrap(),
+        ];
+        for response in responses.iter() {
+            mock.push::<serde_json::Value, _>(response).unwrap();
+        }
+
+        for (address, expected) in [
+            (clone, Some(addrs[4])),
+            (transparent, Some(addrs[5])),
+            (uups, Some(addrs[6])),
+            (contract, None),
+        ]
+        .iter()
+        {
+            let implementation = resolve_implementation(*address, &provider).await.unwrap();
+            assert_eq!(implementation, *expected);
+        }
+
+        // all the responses were consumed, so these come from the cache
+        let implementation = resolve_implementation(transparent, &provider)
+            .await
+            .unwrap();
+        assert_eq!(implementation, Some(addrs[5]));
+        assert_eq!(
+            resolve_implementation(contract, &provider).await.unwrap(),
+            None
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use crate::{
+    addresses::{canonical_token, ChainConfig, WETH},
+    is_nested,
+    types::{Classification, Inspection},
+    Reducer,
+};
+use ethers::types::Address;
+
+#[derive(Clone, Debug)]
+/// Prunes the trades of the pools which an aggregator routed through, so that
+/// they are not counted along with the aggregator's own trade.
+///
+/// The aggregators' inspectors in this crate already prune the calls under
+/// them, but other inspectors may not, so this makes the result independent
+/// of how the aggregator's trade was classified. A trade is an
+/// aggregator's if trades are nested under its call, one of which sells its
+/// input token and one of which buys its output token. The trades made in
+/// the callback of a flash swap start from the borrowed token instead, and
+/// are kept. Without an aggregator the pools' trades are all kept.
+pub struct AggregatorReducer {
+    weth: Address,
+}
+
+impl Default for AggregatorReducer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AggregatorReducer {
+    /// Instantiates the reducer
+    pub fn new() -> Self {
+        Self::with_weth(*WETH)
+    }
+
+    /// Instantiates the reducer for a chain whose wrapped ETH is `weth`, which
+    /// aggregators may trade as ETH
+    pub fn with_weth(weth: Address) -> Self {
+        Self { weth }
+    }
+
+    /// Instantiates a reducer for `chain`
+    pub fn with_chain(chain: &ChainConfig) -> Self {
+        Self::with_weth(chain.weth)
+    }
+}
+
+impl Reducer for AggregatorReducer {
+    fn reduce(&self, inspection: &mut Inspection) {
+        let same =
+            |a: Address, b: Address| canonical_token(a, self.weth) == canonical_token(b, self.weth);
+        let actions = &inspection.actions;
+        let mut pruned = vec![false; actions.len()];
+        for (i, action) in actions.iter().enumerate() {
+            // already pruned under an outer aggregator
+            if pruned[i] {
+                continue;
+            }
+            let outer = match action.as_action().and_then(|a| a.trade()) {
+                Some(inner) => inner,
+                None => continue,
+            };
+            let trace_address = action.trace_address();
+            let inner = actions
+                .iter()
+                .enumerate()
+                .filter(|(_, a)| is_nested(trace_address, a.trace_address()))
+                .filter_map(|(j, a)| Some((j, a.as_action()?.trade()?)))
+                .collect::<Vec<_>>();
+
+            let sells = inner.iter().any(|(_, t)| same(t.t1.token, outer.t1.token));
+            let buys = inner.iter().any(|(_, t)| same(t.t2.token, outer.t2.token));
+            if sells && buys {
+                inner.iter().for_each(|(j, _)| pruned[*j] = true);
+            }
+        }
+
+        for (action, pruned) in inspection.actions.iter_mut().zip(pruned) {
+            if pruned {
+                *action = Classification::Prune;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        inspectors::{OneInch, UniswapV2, ERC20},
+        test_helpers::*,
+        types::actions::{Trade, Transfer},
+        Inspector,
+    };
+    use ethers::{abi::Token, types::Bytes};
+
+    fn pair_swap(amount0: u64, amount1: u64, to: Address) -> Bytes {
+        calldata(
+            "swap(uint256,uint256,address,bytes)",
+            &[
+                Token::Uint(amount0.into()),
+                Token::Uint(amount1.into()),
+                Token::Address(to),
+                Token::Bytes(vec![]),
+            ],
+        )
+    }
+
+    #[test]
+    fn keeps_the_aggregated_trade() {
+        let addrs = addrs();
+        let (usr, router, executor, pair1, pair2, dai, usdc) = (
+            addrs[0], addrs[1], addrs[2], addrs[3], addrs[4], addrs[5], addrs[6],
+        );
+
+        // a 1inch swap of WETH for DAI, which routes through 2 Uniswap pairs
+        let input = calldata(
+            "swap(address,(address,address,address,address,uint256,uint256,uint256,bytes),bytes)",
+            &[
+                Token::Address(executor),
+                Token::Tuple(vec![
+                    Token::Address(*WETH),
+                    Token::Address(dai),
+                    Token::Address(executor),
+                    Token::Address(Address::zero()),
+                    Token::Uint(100.into()),
+                    Token::Uint(2900.into()),
+                    Token::Uint(0.into()),
+                    Token::Bytes(vec![]),
+                ]),
+                Token::Bytes(vec![]),
+            ],
+        );
+        let trace = mk_inspection(vec![
+            mk_call(usr, router, input, vec![]),
+            mk_call(
+                router,
+                *WETH,
+                transfer_from(usr, executor, 100.into()),
+                vec![0],
+            ),
+            mk_call(router, executor, Bytes::default(), vec![1]),
+            mk_call(executor, *WETH, transfer(pair1, 100.into()), vec![1, 0]),
+            mk_call(executor, pair1, pair_swap(0, 300, pair2), vec![1, 1]),
+            mk_call(pair1, usdc, transfer(pair2, 300.into()), vec![1, 1, 0]),
+            mk_call(executor, pair2, pair_swap(2950, 0, router), vec![1, 2]),
+            mk_call(pair2, dai, transfer(router, 2950.into()), vec![1, 2, 0]),
+            mk_call(router, dai, transfer(usr, 2950.into()), vec![2]),
+        ]);
+        let mut inspection = trace.clone();
+        ERC20::new().inspect(&mut inspection);
+        UniswapV2::new().inspect(&mut inspection);
+        let pools = inspection.clone();
+
+        // the aggregator's trade, as classified by an inspector which ran
+        // after the pools' and left them as they were
+        let mut aggregated = trace;
+        ERC20::new().inspect(&mut aggregated);
+        OneInch::new().inspect(&mut aggregated);
+        assert!(aggregated.actions[0].as_action().unwrap().trade().is_some());
+        inspection.actions[0] = aggregated.actions[0].clone();
+        AggregatorReducer::new().reduce(&mut inspection);
+        inspection.prune();
+        let trades = inspection
+            .known()
+            .iter()
+            .filter_map(|a| a.as_ref().trade().cloned())
+            .collect::<Vec<_>>();
+        assert_eq!(trades.len(), 1);
+        assert_eq!((trades[0].t1.from, trades[0].t2.to), (usr, usr));
+
+        // without the aggregator, the pools' trades are kept
+        let mut inspection = pools;
+        AggregatorReducer::new().reduce(&mut inspection);
+        let trades = inspection
+            .known()
+            .iter()
+            .filter_map(|a| a.as_ref().trade().cloned())
+            .collect::<Vec<_>>();
+        assert_eq!(trades.len(), 2);
+        let pairs = trades.iter().map(|t| t.t1.to).collect::<Vec<_>>();
+        assert_eq!(pairs, vec![pair1, pair2]);
+    }
+
+    #[test]
+    fn keeps_the_trades_of_flash_swaps() {
+        let addrs = addrs();
+        let (bot, pair, other, dai) = (addrs[0], addrs[1], addrs[2], addrs[3]);
+        let trade = |from: Address, to: Address, t1: (Address, u64), t2: (Address, u64)| Trade {
+            t1: Transfer {
+                from,
+                to,
+                amount: t1.1.into(),
+                token: t1.0,
+            },
+            t2: Transfer {
+                from: to,
+                to: from,
+                amount: t2.1.into(),
+                token: t2.0,
+            },
+        };
+
+        // borrows DAI from the pair and sells it on another one, before
+        // repaying the pair in WETH
+        let actions = vec![
+            Classification::new(trade(bot, pair, (*WETH, 100), (dai, 3000)), vec![0]),
+            Classification::new(trade(bot, other, (dai, 3000), (*WETH, 101)), vec![0, 0, 1]),
+        ];
+        let mut inspection = mk_inspection(actions.clone());
+        AggregatorReducer::new().reduce(&mut inspection);
+        assert_eq!(inspection.actions, actions);
+    }
+}
@@ -0,0 +1,170 @@
+use crate::{
+    types::{
+        actions::{AllowanceExploit, SpecificAction},
+        Classification, Inspection, Provenance,
+    },
+    Reducer,
+};
+use ethers::types::{Address, I256};
+
+#[derive(Clone, Debug)]
+/// Flags the transfers which pulled tokens with an allowance from an account
+/// which took no part in the transaction: the owner did not send the tx nor
+/// make any of its calls, the tokens were pulled by another account calling
+/// `transferFrom`, and the owner received nothing in return. This is what
+/// draining the users who approved an exploited or a malicious contract looks
+/// like.
+///
+/// It is a heuristic with false positives, e.g. the meta transactions and the
+/// subscriptions which owners sign off chain look the same, so it is not part
+/// of the default reducers. It must run after the `TradeReducer` and the
+/// `TransferReducer`, so that only the transfers which are not part of a trade
+/// are flagged. The transfers which it flags are replaced with their
+/// `AllowanceExploit`.
+pub struct AllowanceExploitReducer;
+
+impl Default for AllowanceExploitReducer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AllowanceExploitReducer {
+    /// Instantiates the reducer
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Reducer for AllowanceExploitReducer {
+    fn reduce(&self, inspection: &mut Inspection) {
+        let deltas = inspection.balance_deltas();
+        let transfers = inspection
+            .actions
+            .iter()
+            .enumerate()
+            .filter_map(|(i, a)| Some((i, a.as_action()?.transfer()?.clone())))
+            .collect::<Vec<_>>();
+
+        for (i, transfer) in transfers {
+            let owner = transfer.from;
+            // mints, and the tokens of the sender or its contract
+            if owner.is_zero() || owner == inspection.from || owner == inspection.contract {
+                continue;
+            }
+            let spender = match spender(inspection, inspection.actions[i].trace_address()) {
+                Some(inner) => inner,
+                None => continue,
+            };
+            // a `transfer` by the owner itself
+            if spender == owner || took_part(inspection, owner) {
+                continue;
+            }
+            // e.g. a maker whose order got filled
+            let received = deltas
+                .iter()
+                .any(|((account, _), delta)| *account == owner && *delta > I256::zero());
+            if received {
+                continue;
+            }
+
+            let exploit = AllowanceExploit { transfer, spender };
+            let trace_address = inspection.actions[i].trace_address().to_vec();
+            let provenance = Provenance::of([&inspection.actions[i]]);
+            inspection.actions[i] =
+                Classification::new(exploit, trace_address).with_provenance(provenance);
+        }
+    }
+}
+
+/// The account which made the call at `trace_address`, if its caller was not
+/// classified
+fn spender(inspection: &Inspection, trace_address: &[usize]) -> Option<Address> {
+    let parent = match trace_address.split_last() {
+        Some((_, parent)) => parent,
+        // called by the tx itself
+        None => return Some(inspection.from),
+    };
+    inspection.actions.iter().find_map(|action| match action {
+        Classification::Unknown(calltrace) if calltrace.trace_address.as_slice() == parent => {
+            Some(calltrace.as_ref().to)
+        }
+        _ => None,
+    })
+}
+
+/// Whether `account` made or received any of the calls of the tx which are
+/// still unknown, or approved a spender in it
+fn took_part(inspection: &Inspection, account: Address) -> bool {
+    inspection.actions.iter().any(|action| match action {
+        Classification::Unknown(calltrace) => {
+            let call = calltrace.as_ref();
+            call.from == account || call.to == account
+        }
+        Classification::Known(inner) => match inner.as_ref() {
+            SpecificAction::Approval(approval) => approval.owner == account,
+            _ => false,
+        },
+        Classification::Prune => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        inspectors::ERC20,
+        test_helpers::{addrs, mk_call, mk_inspection, transfer, transfer_from},
+        Inspector,
+    };
+    use ethers::types::Bytes;
+
+    /// The (owner, spender) of the exploits found in a tx which the attacker
+    /// sent to its drainer contract
+    fn exploits(
+        (attacker, drainer): (Address, Address),
+        calls: Vec<Classification>,
+    ) -> Vec<(Address, Address)> {
+        let mut actions = vec![mk_call(attacker, drainer, Bytes::default(), vec![])];
+        actions.extend(calls);
+        let mut inspection = mk_inspection(actions);
+        inspection.from = attacker;
+        inspection.contract = drainer;
+        ERC20::new().inspect(&mut inspection);
+        AllowanceExploitReducer::new().reduce(&mut inspection);
+        inspection
+            .known_actions()
+            .filter_map(|a| a.allowance_exploit())
+            .map(|exploit| (exploit.transfer.from, exploit.spender))
+            .collect()
+    }
+
+    #[test]
+    fn flags_the_tokens_pulled_from_bystanders() {
+        let addrs = addrs();
+        let (attacker, drainer, victim, token, other) =
+            (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4]);
+        let tx = (attacker, drainer);
+        let drain = |owner| {
+            mk_call(
+                drainer,
+                token,
+                transfer_from(owner, attacker, 100.into()),
+                vec![0],
+            )
+        };
+
+        assert_eq!(exploits(tx, vec![drain(victim)]), vec![(victim, drainer)]);
+        // the tokens of the attacker, or which its contract sent itself
+        assert!(exploits(tx, vec![drain(attacker)]).is_empty());
+        let sent = mk_call(drainer, token, transfer(attacker, 100.into()), vec![0]);
+        assert!(exploits(tx, vec![sent]).is_empty());
+        // the owner got something back, e.g. its order was filled
+        let paid = mk_call(drainer, other, transfer(victim, 1.into()), vec![1]);
+        assert!(exploits(tx, vec![drain(victim), paid]).is_empty());
+        // the owner's contract was called, and transferred its tokens itself
+        let called = mk_call(drainer, victim, Bytes::default(), vec![0]);
+        let sent = mk_call(victim, token, transfer(attacker, 100.into()), vec![0, 0]);
+        assert!(exploits(tx, vec![called, sent]).is_empty());
+    }
+}
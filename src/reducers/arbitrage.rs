@@ -1,84 +1,396 @@
 use crate::{
+    addresses::{canonical_token, ChainConfig, PROTOCOLS, UNIVERSAL_ROUTER, WETH},
     inspectors::find_matching,
+    is_nested,
+    prices::{net_profit, signed, usd_value, PriceOracle},
     types::{
-        actions::{Arbitrage, SpecificAction},
-        Classification, Inspection,
+        actions::{Arbitrage, OpenArbitrage, SpecificAction, Trade},
+        Classification, Inspection, Provenance,
     },
     Reducer,
 };
+use ethers::types::{Address, I256, U256};
+use std::collections::HashSet;
 
 #[derive(Clone, Debug)]
-pub struct ArbitrageReducer;
+pub struct ArbitrageReducer {
+    /// The chain's wrapped ETH, which native ETH is treated as
+    weth: Address,
+}
 
 impl ArbitrageReducer {
     pub fn new() -> Self {
-        Self
+        Self::with_weth(*WETH)
+    }
+
+    /// Instantiates a reducer for a chain whose wrapped ETH is `weth`
+    pub fn with_weth(weth: Address) -> Self {
+        Self { weth }
+    }
+
+    /// Instantiates a reducer for `chain`
+    pub fn with_chain(chain: &ChainConfig) -> Self {
+        Self::with_weth(chain.weth)
     }
 }
 
 impl Reducer for ArbitrageReducer {
     fn reduce(&self, inspection: &mut Inspection) {
-        let actions = inspection.actions.to_vec();
-        let mut prune = Vec::new();
-        inspection
-            .actions
-            .iter_mut()
-            .enumerate()
-            .for_each(|(i, action)| {
-                // check if we got a trade
-                let trade = if let Some(trade) = action.as_action().map(|x| x.trade()).flatten() {
-                    trade
-                } else {
-                    return;
-                };
-
-                let res = find_matching(
-                    actions.iter().enumerate().skip(i + 1),
-                    |t| t.trade(),
-                    |t| t.t2.token == trade.t1.token,
-                    true,
-                );
-                if let Some((j, trade2)) = res {
-                    if trade2.t2.amount > trade.t1.amount {
-                        *action = Classification::new(
-                            Arbitrage {
-                                profit: trade2.t2.amount.saturating_sub(trade.t1.amount),
-                                token: trade2.t2.token,
-                                to: trade2.t2.to,
-                            },
-                            // TODO!
-                            Vec::new(),
-                        );
-                        // prune everything in that range
-                        prune.push((i + 1, j + 1));
-                    }
+        let actions = &inspection.actions;
+        // the trades which are already part of an arbitrage
+        let mut used = vec![false; actions.len()];
+        let mut classified = Vec::new();
+
+        for i in 0..actions.len() {
+            let first = match actions[i].as_action().and_then(|a| a.trade()) {
+                Some(trade) if !used[i] => trade,
+                _ => continue,
+            };
+
+            let hops = match find_cycle(actions, &used, i, first, self.weth) {
+                Some(hops) => hops,
+                None => continue,
+            };
+            let last = actions[*hops.last().expect("a cycle has at least 2 hops")]
+                .as_action()
+                .and_then(|a| a.trade())
+                .expect("hops are trades");
+            if last.t2.amount <= first.t1.amount {
+                continue;
+            }
+
+            let trades = hops
+                .iter()
+                .filter_map(|j| actions[*j].as_action()?.trade().cloned())
+                .collect();
+            let recipient = recipient(
+                actions,
+                *hops.last().expect("a cycle has at least 2 hops"),
+                last.t2.to,
+                last.t2.token,
+                inspection.from,
+                self.weth,
+            );
+            let arbitrage = Classification::new(
+                Arbitrage {
+                    profit: last.t2.amount.saturating_sub(first.t1.amount),
+                    token: canonical_token(last.t2.token, self.weth),
+                    to: last.t2.to,
+                    capital: first.t1.amount.saturating_sub(borrowed(
+                        actions,
+                        i,
+                        first.t1.token,
+                        self.weth,
+                    )),
+                    trades,
+                    recipient,
+                    searcher: inspection.searcher(),
+                },
+                // TODO!
+                Vec::new(),
+            )
+            .with_provenance(Provenance::of(hops.iter().map(|j| &actions[*j])));
+            classified.push((i, arbitrage));
+
+            // prune the other hops, the calls between them and the transfers
+            // which were made with the traded tokens. The trades of other
+            // arbitrages may be interleaved with these, so they're kept.
+            let tokens = hops
+                .iter()
+                .filter_map(|j| actions[*j].as_action()?.trade())
+                .map(|t| canonical_token(t.t1.token, self.weth))
+                .collect::<Vec<_>>();
+            let end = *hops.last().expect("a cycle has at least 2 hops");
+            for (j, action) in actions.iter().enumerate().take(end + 1).skip(i + 1) {
+                let prune = hops.contains(&j)
+                    || match action {
+                        Classification::Known(c) => match c.action {
+                            SpecificAction::Transfer(ref t) => {
+                                tokens.contains(&canonical_token(t.token, self.weth))
+                            }
+                            _ => false,
+                        },
+                        Classification::Unknown(_) => true,
+                        Classification::Prune => false,
+                    };
+                if prune {
+                    classified.push((j, Classification::Prune));
+                }
+            }
+            hops.iter().for_each(|j| used[*j] = true);
+        }
+
+        for (i, action) in classified {
+            inspection.actions[i] = action;
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+/// Finds the open arbitrages of a transaction: the chains of trades, each
+/// selling what the previous one bought, which do not trade back into the
+/// token which the first one sold, but whose output is worth more than their
+/// input at the oracle's prices of the tx's block. E.g. a bot which takes on
+/// inventory in a token while it is cheap on a pool. They bear the risk of
+/// holding that token, so they are kept apart from the risk-free `Arbitrage`s.
+///
+/// It is not part of the default reducers since it needs prices, and must run
+/// after the `ArbitrageReducer` so that the trades of closed cycles are left to
+/// it, whether they were profitable or not. The chains whose input or output
+/// cannot be priced are skipped. The first trade of a chain is replaced with
+/// its `OpenArbitrage` and the other ones are pruned.
+///
+/// Any multi-hop swap which got a good price looks like one, so only the txs
+/// sent to a contract which is not a known router, like bots do, are reduced.
+pub struct OpenArbitrageReducer<O> {
+    oracle: O,
+    /// The chain's wrapped ETH, which native ETH is treated as
+    weth: Address,
+    /// The chain's known routers and pools, whose users are not searchers
+    known: HashSet<Address>,
+}
+
+impl<O: PriceOracle> OpenArbitrageReducer<O> {
+    /// Instantiates a reducer which prices the trades with `oracle`
+    pub fn new(oracle: O) -> Self {
+        let known = PROTOCOLS.keys().chain(UNIVERSAL_ROUTER.iter()).copied();
+        Self {
+            oracle,
+            weth: *WETH,
+            known: known.collect(),
+        }
+    }
+
+    /// Sets the chain's wrapped ETH
+    pub fn with_weth(mut self, weth: Address) -> Self {
+        self.weth = weth;
+        self
+    }
+
+    /// Sets the chain's wrapped ETH and its known routers
+    pub fn with_chain(mut self, chain: &ChainConfig) -> Self {
+        let known = chain.protocols.keys().chain(chain.universal_routers.iter());
+        self.weth = chain.weth;
+        self.known = known.copied().collect();
+        self
+    }
+}
+
+impl<O: PriceOracle> Reducer for OpenArbitrageReducer<O> {
+    fn reduce(&self, inspection: &mut Inspection) {
+        if self.known.contains(&inspection.contract) {
+            return;
+        }
+        let actions = &inspection.actions;
+        let mut used = vec![false; actions.len()];
+        let mut classified = Vec::new();
+
+        for i in 0..actions.len() {
+            let first = match actions[i].as_action().and_then(|a| a.trade()) {
+                Some(trade) if !used[i] => trade,
+                _ => continue,
+            };
+            let hops = match find_chain(actions, &used, i, first, self.weth) {
+                Some(hops) => hops,
+                None => continue,
+            };
+
+            let trades = hops
+                .iter()
+                .filter_map(|j| actions[*j].as_action()?.trade().cloned())
+                .collect::<Vec<_>>();
+            let (sold, held) = (&first.t1, &trades[trades.len() - 1].t2);
+            let block = inspection.block_number;
+            let deltas = match (signed(sold.amount), signed(held.amount)) {
+                (Some(sold_amount), Some(held_amount)) => {
+                    vec![(sold.token, -sold_amount), (held.token, held_amount)]
                 }
-            });
-
-        for range in prune {
-            inspection.actions[range.0..range.1]
-                .iter_mut()
-                .for_each(|a| match a {
-                    // Of the known actions, prune only the trades/transfers
-                    Classification::Known(c) => match c.action {
-                        SpecificAction::Arbitrage(_)
-                        | SpecificAction::Trade(_)
-                        | SpecificAction::Transfer(_) => {
-                            *a = Classification::Prune;
-                        }
-                        _ => {}
-                    },
-                    _ => *a = Classification::Prune,
-                })
+                _ => continue,
+            };
+            let eth_profit = match net_profit(&self.oracle, deltas, block) {
+                Some(profit) if profit > I256::zero() => profit,
+                _ => continue,
+            };
+
+            let open = OpenArbitrage {
+                trades,
+                eth_profit,
+                usd_profit: usd_value(&self.oracle, eth_profit, block),
+                searcher: inspection.searcher(),
+            };
+            let open = Classification::new(open, actions[i].trace_address())
+                .with_provenance(Provenance::of(hops.iter().map(|j| &actions[*j])));
+            classified.push((i, open));
+            for j in hops.iter().skip(1) {
+                classified.push((*j, Classification::Prune));
+            }
+            hops.iter().for_each(|j| used[*j] = true);
+        }
+
+        for (i, action) in classified {
+            inspection.actions[i] = action;
         }
     }
 }
 
+/// The amount of `token` which was flash loaned and not repaid yet when the
+/// `i`th action executes. A loan is repaid before the call which made it
+/// returns, so it is outstanding for the actions nested in that call, and
+/// nested loans add up. Loans of WETH count towards ETH and vice versa.
+pub(crate) fn borrowed(
+    actions: &[Classification],
+    i: usize,
+    token: Address,
+    weth: Address,
+) -> U256 {
+    let token = canonical_token(token, weth);
+    let at = actions[i].trace_address();
+    actions[..i]
+        .iter()
+        .filter_map(|a| Some((a.trace_address(), a.as_action()?.flash_loan()?)))
+        .filter(|(_, loan)| canonical_token(loan.token, weth) == token)
+        .filter(|(loaned_at, _)| {
+            // the loan replaced the lender's transfer out, within the loan call
+            let call = &loaned_at[..loaned_at.len().saturating_sub(1)];
+            call.is_empty() || is_nested(call, at)
+        })
+        .fold(U256::zero(), |sum, (_, loan)| {
+            sum.saturating_add(loan.amount)
+        })
+}
+
+/// Follows the profit in `token` out of `contract`, which received the output
+/// of the arbitrage's last hop at index `last`. The tx's `sender` is the one
+/// account known to be an EOA. Otherwise the profit went to the last recipient
+/// which did not make calls of its own, and if nothing was withdrawn it stayed
+/// in `contract`.
+fn recipient(
+    actions: &[Classification],
+    last: usize,
+    contract: Address,
+    token: Address,
+    sender: Address,
+    weth: Address,
+) -> Address {
+    let token = canonical_token(token, weth);
+    let withdrawals = actions
+        .iter()
+        .skip(last + 1)
+        .filter_map(|a| a.as_action()?.transfer())
+        .filter(|t| t.from == contract && t.to != contract)
+        .filter(|t| canonical_token(t.token, weth) == token)
+        .collect::<Vec<_>>();
+    if withdrawals.iter().any(|t| t.to == sender) {
+        return sender;
+    }
+
+    let makes_calls = |address: Address| {
+        actions
+            .iter()
+            .any(|a| matches!(a.as_call(), Some(call) if call.as_ref().from == address))
+    };
+    withdrawals
+        .iter()
+        .rev()
+        .map(|t| t.to)
+        .find(|to| !makes_calls(*to))
+        .unwrap_or(contract)
+}
+
+/// Stitches the trades after `first` whose input is the previous trade's output,
+/// until one of them trades back into the token which `first` sold. Returns the
+/// indices of the trades of the cycle. ETH and WETH are considered the same
+/// token, since wrapping them is not classified as a trade.
+fn find_cycle(
+    actions: &[Classification],
+    used: &[bool],
+    i: usize,
+    first: &Trade,
+    weth: Address,
+) -> Option<Vec<usize>> {
+    let same = |a: Address, b: Address| canonical_token(a, weth) == canonical_token(b, weth);
+    let mut hops = vec![i];
+    let mut current = first;
+    loop {
+        let last = *hops.last()?;
+        let trades = || {
+            actions
+                .iter()
+                .enumerate()
+                .skip(last + 1)
+                .filter(|(j, _)| !used[*j])
+        };
+        let (j, next) = find_matching(
+            trades(),
+            |t| t.trade(),
+            |t| same(t.t1.token, current.t2.token),
+            true,
+        )
+        // The tokens may have been converted between the hops in a way which
+        // we do not classify (e.g. between Synthetix's proxies and their
+        // targets), so we fall back to the first trade which closes the cycle
+        .or_else(|| {
+            find_matching(
+                trades(),
+                |t| t.trade(),
+                |t| same(t.t2.token, first.t1.token),
+                true,
+            )
+        })?;
+        hops.push(j);
+        if same(next.t2.token, first.t1.token) {
+            return Some(hops);
+        }
+        current = next;
+    }
+}
+
+/// Stitches the trades after `first` whose input is the previous trade's output,
+/// like `find_cycle`. Returns the indices of the trades of the chain, unless
+/// it has a single trade or it closes a cycle.
+fn find_chain(
+    actions: &[Classification],
+    used: &[bool],
+    i: usize,
+    first: &Trade,
+    weth: Address,
+) -> Option<Vec<usize>> {
+    let same = |a: Address, b: Address| canonical_token(a, weth) == canonical_token(b, weth);
+    let mut hops = vec![i];
+    let mut current = first;
+    while let Some((j, next)) = find_matching(
+        actions
+            .iter()
+            .enumerate()
+            .skip(hops[hops.len() - 1] + 1)
+            .filter(|(j, _)| !used[*j]),
+        |t| t.trade(),
+        |t| same(t.t1.token, current.t2.token),
+        true,
+    ) {
+        if same(next.t2.token, first.t1.token) {
+            return None;
+        }
+        hops.push(j);
+        current = next;
+    }
+    if hops.len() > 1 {
+        Some(hops)
+    } else {
+        None
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::addresses::NATIVE_ETH;
     use crate::test_helpers::*;
-    use crate::types::actions::{Arbitrage, Trade, Transfer};
+    use crate::types::{
+        actions::{Arbitrage, FlashLoan, Trade, Transfer},
+        Protocol,
+    };
+    use ethers::types::{Address, Bytes};
 
     fn test_trade_to_arbitrage(input: Vec<Classification>, expected: Vec<Classification>) {
         let uniswap = ArbitrageReducer::new();
@@ -138,6 +450,10 @@ mod tests {
                     profit: 10.into(),
                     token: token1,
                     to: usr,
+                    capital: 100.into(),
+                    trades: vec![t1, t2],
+                    recipient: usr,
+                    searcher: Default::default(),
                 },
                 Vec::new(),
             ),
@@ -146,4 +462,309 @@ mod tests {
 
         test_trade_to_arbitrage(input, expected);
     }
+
+    fn hop(usr: Address, pool: Address, t1: (Address, u64), t2: (Address, u64)) -> Trade {
+        Trade::new(
+            Transfer {
+                from: usr,
+                to: pool,
+                amount: t1.1.into(),
+                token: t1.0,
+            },
+            Transfer {
+                from: pool,
+                to: usr,
+                amount: t2.1.into(),
+                token: t2.0,
+            },
+        )
+    }
+
+    #[test]
+    fn multi_hop_arb_with_unrelated_transfers() {
+        let addrs = addrs();
+        let (weth, usdc, dai, other) = (addrs[0], addrs[1], addrs[2], addrs[3]);
+        let (usr, pool1, pool2, pool3) = (addrs[4], addrs[5], addrs[6], addrs[7]);
+
+        let hops = vec![
+            hop(usr, pool1, (weth, 100), (usdc, 300)),
+            hop(usr, pool2, (usdc, 300), (dai, 290)),
+            hop(usr, pool3, (dai, 290), (weth, 120)),
+        ];
+        let unrelated = Transfer {
+            from: usr,
+            to: addrs[8],
+            amount: 5.into(),
+            token: other,
+        };
+        let input = vec![
+            Classification::new(hops[0].clone(), Vec::new()),
+            Classification::new(unrelated.clone(), vec![1]),
+            Classification::new(hops[1].clone(), Vec::new()),
+            Classification::new(hops[2].clone(), Vec::new()),
+        ];
+        let expected = vec![
+            Classification::new(
+                Arbitrage {
+                    profit: 20.into(),
+                    token: weth,
+                    to: usr,
+                    capital: 100.into(),
+                    trades: hops,
+                    recipient: usr,
+                    searcher: Default::default(),
+                },
+                Vec::new(),
+            ),
+            Classification::new(unrelated, vec![1]),
+            Classification::Prune,
+            Classification::Prune,
+        ];
+
+        test_trade_to_arbitrage(input, expected);
+    }
+
+    #[test]
+    fn interleaved_arbs() {
+        let addrs = addrs();
+        let (weth, usdc, dai, wbtc) = (addrs[0], addrs[1], addrs[2], addrs[3]);
+        let (usr, pool1, pool2, pool3, pool4) = (addrs[4], addrs[5], addrs[6], addrs[7], addrs[8]);
+
+        let arb1 = vec![
+            hop(usr, pool1, (weth, 100), (usdc, 300)),
+            hop(usr, pool2, (usdc, 300), (weth, 110)),
+        ];
+        let arb2 = vec![
+            hop(usr, pool3, (dai, 1000), (wbtc, 3)),
+            hop(usr, pool4, (wbtc, 3), (dai, 1005)),
+        ];
+        let input = vec![
+            Classification::new(arb1[0].clone(), Vec::new()),
+            Classification::new(arb2[0].clone(), Vec::new()),
+            Classification::new(arb1[1].clone(), Vec::new()),
+            Classification::new(arb2[1].clone(), Vec::new()),
+        ];
+        let expected = vec![
+            Classification::new(
+                Arbitrage {
+                    profit: 10.into(),
+                    token: weth,
+                    to: usr,
+                    capital: 100.into(),
+                    trades: arb1,
+                    recipient: usr,
+                    searcher: Default::default(),
+                },
+                Vec::new(),
+            ),
+            Classification::new(
+                Arbitrage {
+                    profit: 5.into(),
+                    token: dai,
+                    to: usr,
+                    capital: 1000.into(),
+                    trades: arb2,
+                    recipient: usr,
+                    searcher: Default::default(),
+                },
+                Vec::new(),
+            ),
+            Classification::Prune,
+            Classification::Prune,
+        ];
+
+        test_trade_to_arbitrage(input, expected);
+    }
+
+    #[test]
+    fn flash_loaned_arb() {
+        let addrs = addrs();
+        let (weth, usdc, dai) = (addrs[0], addrs[1], addrs[2]);
+        let (usr, pool1, pool2) = (addrs[4], addrs[5], addrs[6]);
+
+        let loan = |provider, token, amount: u64| FlashLoan {
+            provider,
+            token,
+            amount: amount.into(),
+            fee: 0.into(),
+        };
+        let hops = [
+            hop(usr, pool1, (weth, 100), (usdc, 300)),
+            hop(usr, pool2, (usdc, 300), (weth, 110)),
+        ];
+        // a loan repaid before the arb, then a dYdX loan nested in an Aave
+        // one, along with a loan of another token
+        let input = vec![
+            Classification::new(loan(Protocol::Aave, weth, 50), vec![0, 0]),
+            Classification::new(loan(Protocol::Aave, weth, 60), vec![1, 0]),
+            Classification::new(loan(Protocol::DyDx, weth, 30), vec![1, 1, 0]),
+            Classification::new(loan(Protocol::DyDx, dai, 1000), vec![1, 1, 1]),
+            Classification::new(hops[0].clone(), vec![1, 1, 2]),
+            Classification::new(hops[1].clone(), vec![1, 1, 3]),
+        ];
+        let mut inspection = mk_inspection(input);
+        ArbitrageReducer::new().reduce(&mut inspection);
+
+        let arb = inspection.known()[4].as_ref().arbitrage().cloned().unwrap();
+        assert_eq!(arb.capital, 10.into());
+        assert_eq!(arb.profit, 10.into());
+        assert!(arb.is_atomic());
+    }
+
+    #[test]
+    fn follows_the_profit_to_its_recipient() {
+        let addrs = addrs();
+        let (weth, usdc, dai) = (addrs[0], addrs[1], addrs[2]);
+        let (eoa, bot, pool1, pool2, vault, other) =
+            (addrs[3], addrs[4], addrs[5], addrs[6], addrs[7], addrs[8]);
+        let hops = [
+            hop(bot, pool1, (weth, 100), (usdc, 300)),
+            hop(bot, pool2, (usdc, 300), (weth, 110)),
+        ];
+        let withdrawal = |to: Address, token: Address| {
+            Classification::new(
+                Transfer {
+                    from: bot,
+                    to,
+                    amount: 10.into(),
+                    token,
+                },
+                vec![2],
+            )
+        };
+        let recipient = |withdrawals: Vec<Classification>| {
+            let mut actions = vec![
+                Classification::new(hops[0].clone(), vec![0]),
+                Classification::new(hops[1].clone(), vec![1]),
+            ];
+            actions.extend(withdrawals);
+            let mut inspection = mk_inspection(actions);
+            inspection.from = eoa;
+            ArbitrageReducer::with_weth(weth).reduce(&mut inspection);
+            let arb = inspection.arbitrages().next().cloned().unwrap();
+            arb.recipient
+        };
+
+        // the profit is unwrapped and sent to the tx's sender
+        assert_eq!(recipient(vec![withdrawal(eoa, NATIVE_ETH)]), eoa);
+        // or to another EOA, rather than to a contract which forwards it
+        assert_eq!(
+            recipient(vec![
+                withdrawal(other, weth),
+                withdrawal(vault, weth),
+                mk_call(vault, weth, Bytes::default(), vec![3]),
+            ]),
+            other
+        );
+        // withdrawals of other tokens do not count
+        assert_eq!(recipient(vec![withdrawal(eoa, dai)]), bot);
+        // the profit stays in the contract
+        assert_eq!(recipient(vec![]), bot);
+    }
+
+    #[test]
+    fn eth_and_weth_are_netted() {
+        let addrs = addrs();
+        let (weth, usdc) = (addrs[0], addrs[1]);
+        let (usr, pool1, pool2) = (addrs[4], addrs[5], addrs[6]);
+
+        // ETH in, WETH out, with a loan of WETH
+        let hops = [
+            hop(usr, pool1, (NATIVE_ETH, 100), (usdc, 300)),
+            hop(usr, pool2, (usdc, 300), (weth, 110)),
+        ];
+        let input = vec![
+            Classification::new(
+                FlashLoan {
+                    provider: Protocol::Aave,
+                    token: weth,
+                    amount: 60.into(),
+                    fee: 0.into(),
+                },
+                vec![0],
+            ),
+            Classification::new(hops[0].clone(), vec![1]),
+            Classification::new(hops[1].clone(), vec![2]),
+        ];
+        let mut inspection = mk_inspection(input.clone());
+        ArbitrageReducer::with_weth(weth).reduce(&mut inspection);
+
+        let arb = inspection.known()[1].as_ref().arbitrage().cloned().unwrap();
+        assert_eq!(arb.token, weth);
+        assert_eq!(arb.profit, 10.into());
+        assert_eq!(arb.capital, 40.into());
+
+        // which is not the case for another chain's wrapped ETH
+        let mut inspection = mk_inspection(input);
+        ArbitrageReducer::new().reduce(&mut inspection);
+        assert!(inspection
+            .known()
+            .iter()
+            .all(|action| action.as_ref().arbitrage().is_none()));
+    }
+
+    #[test]
+    fn open_arbitrages_are_valued_with_the_oracle() {
+        let addrs = addrs();
+        let (bot, pool1, pool2, token) = (addrs[0], addrs[1], addrs[2], addrs[3]);
+        let usdc = *crate::addresses::USDC;
+        let eth = |n: u64| U256::from(n) * U256::exp10(18);
+        let usd = |n: u64| U256::from(n) * U256::exp10(6);
+        let oracle = crate::UniswapV2Oracle::new();
+        // 1 ETH = 100 tokens = 2000 USDC
+        oracle.insert(token, 0, eth(100), eth(1));
+        oracle.insert(usdc, 0, usd(2000), eth(1));
+
+        let trade = |pool, (sold, sold_amount), (bought, bought_amount)| {
+            Trade::new(
+                Transfer::new(bot, pool, sold_amount, sold),
+                Transfer::new(pool, bot, bought_amount, bought),
+            )
+        };
+        let open_through = |contract: Address, hops: Vec<Trade>| {
+            let mut inspection = mk_inspection(
+                hops.into_iter()
+                    .enumerate()
+                    .map(|(i, hop)| Classification::new(hop, vec![i]))
+                    .collect(),
+            );
+            inspection.contract = contract;
+            ArbitrageReducer::new().reduce(&mut inspection);
+            OpenArbitrageReducer::new(oracle.clone()).reduce(&mut inspection);
+            inspection.prune();
+            inspection
+                .known_actions()
+                .filter_map(|a| a.open_arbitrage())
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+
+        let open = |hops| open_through(bot, hops);
+
+        // 1 WETH for 110 tokens, which are sold for 2100 USDC: the bot is left
+        // with USDC worth 1.05 ETH
+        let buy = trade(pool1, (*WETH, eth(1)), (token, eth(110)));
+        let sell = trade(pool2, (token, eth(110)), (usdc, usd(2100)));
+        let found = open(vec![buy.clone(), sell.clone()]);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].trades.len(), 2);
+        assert_eq!(found[0].held().token, usdc);
+        assert_eq!(found[0].eth_profit, I256::from_raw(eth(1) / 20));
+        assert_eq!(found[0].usd_profit.map(|usd| usd.round()), Some(100.0));
+        // the same swap made through a router is a user's
+        let router = *PROTOCOLS.keys().next().unwrap();
+        assert!(open_through(router, vec![buy.clone(), sell]).is_empty());
+
+        // worth less than what was sold
+        let sell = trade(pool2, (token, eth(110)), (usdc, usd(1900)));
+        assert!(open(vec![buy.clone(), sell]).is_empty());
+        // a single trade, or a cycle which is left to the `ArbitrageReducer`
+        assert!(open(vec![buy.clone()]).is_empty());
+        let back = trade(pool2, (token, eth(110)), (*WETH, eth(1) / 2));
+        assert!(open(vec![buy.clone(), back]).is_empty());
+        // the output cannot be priced
+        let unknown = addrs[4];
+        let sell = trade(pool2, (token, eth(110)), (unknown, eth(1)));
+        assert!(open(vec![buy, sell]).is_empty());
+    }
 }
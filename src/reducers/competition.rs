@@ -0,0 +1,227 @@
+use crate::{
+    types::{actions::LiquidationCompetition, Classification, Inspection, Provenance, Status},
+    BlockReducer,
+};
+use ethers::types::{Address, U256};
+use indexmap::IndexMap;
+
+#[derive(Clone, Debug)]
+/// Finds the liquidations which several transactions of a block raced for, by
+/// grouping the liquidation attempts of the block by the borrower and the debt
+/// they repay. Only one attempt on a position usually succeeds, and the ones
+/// included after it revert, or are checked, since there is nothing left to
+/// liquidate.
+///
+/// The competition is attached to the winning transaction, right after its
+/// liquidation. The first successful attempt wins, and the positions which
+/// were only attempted by one transaction, or by none which succeeded, are
+/// left alone. Maker's takes whose vault is not known are not grouped.
+pub struct LiquidationCompetitionReducer;
+
+impl Default for LiquidationCompetitionReducer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LiquidationCompetitionReducer {
+    /// Instantiates the reducer
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+/// A transaction's attempt at liquidating a position
+struct Attempt {
+    tx: usize,
+    action: usize,
+    liquidator: Address,
+}
+
+impl BlockReducer for LiquidationCompetitionReducer {
+    fn reduce_block(&self, inspections: &mut [Inspection]) {
+        // (borrower, debt) -> the attempts, in the order of the block
+        let mut positions: IndexMap<(Address, Address), Vec<Attempt>> = IndexMap::new();
+        for (tx, inspection) in inspections.iter().enumerate() {
+            for (action, classification) in inspection.actions.iter().enumerate() {
+                let liquidation = match classification.as_action() {
+                    Some(action) => match action.liquidation() {
+                        Some(inner) => inner,
+                        None => match action.profitable_liquidation() {
+                            Some(inner) => inner.as_ref(),
+                            None => continue,
+                        },
+                    },
+                    None => continue,
+                };
+                if liquidation.liquidated_user == Address::zero() {
+                    continue;
+                }
+                let attempts = positions
+                    .entry((liquidation.liquidated_user, liquidation.sent_token))
+                    .or_default();
+                // a transaction which liquidates the position several times
+                // only attempts it once
+                if attempts.iter().all(|attempt| attempt.tx != tx) {
+                    attempts.push(Attempt {
+                        tx,
+                        action,
+                        liquidator: liquidation.from,
+                    });
+                }
+            }
+        }
+
+        // the actions which are inserted into each transaction, by position
+        let mut competitions: Vec<(usize, usize, LiquidationCompetition)> = Vec::new();
+        for ((liquidated_user, debt_token), attempts) in positions {
+            let winner = attempts
+                .iter()
+                .find(|attempt| inspections[attempt.tx].status == Status::Success);
+            let winner = match winner {
+                Some(inner) => inner,
+                None => continue,
+            };
+            let failed = attempts
+                .iter()
+                .map(|attempt| &inspections[attempt.tx])
+                .filter(|inspection| inspection.status != Status::Success)
+                .collect::<Vec<_>>();
+            if failed.is_empty() {
+                continue;
+            }
+
+            let gas = failed
+                .iter()
+                .filter_map(|inspection| inspection.gas.as_ref());
+            let competition = LiquidationCompetition {
+                liquidated_user,
+                debt_token,
+                winner: winner.liquidator,
+                winner_tx: inspections[winner.tx].hash,
+                failed_txs: failed.iter().map(|inspection| inspection.hash).collect(),
+                wasted_gas: gas.clone().fold(U256::zero(), |total, gas| {
+                    total.saturating_add(gas.gas_used)
+                }),
+                wasted_fees: gas.fold(U256::zero(), |total, gas| total.saturating_add(gas.cost())),
+            };
+            competitions.push((winner.tx, winner.action, competition));
+        }
+
+        // inserted from the last action, so that the positions of the others
+        // in the same transaction do not move
+        competitions.sort_by_key(|(tx, action, _)| std::cmp::Reverse((*tx, *action)));
+        for (tx, action, competition) in competitions {
+            let trace_address = inspections[tx].actions[action].trace_address();
+            let provenance = Provenance::of([&inspections[tx].actions[action]]);
+            inspections[tx].actions.insert(
+                action + 1,
+                Classification::new(competition, trace_address).with_provenance(provenance),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_helpers::*, types::actions::Liquidation};
+
+    fn attempt(
+        i: u64,
+        liquidator: Address,
+        user: Address,
+        debt: Address,
+        status: Status,
+    ) -> Inspection {
+        let liquidation = Liquidation {
+            sent_token: debt,
+            sent_amount: 100.into(),
+            received_token: Address::zero(),
+            received_amount: 0.into(),
+            from: liquidator,
+            liquidated_user: user,
+            auction: None,
+        };
+        let mut inspection = mk_tx(i, liquidator, vec![liquidation], Some(10));
+        inspection.status = status;
+        inspection
+    }
+
+    fn competitions(inspections: &[Inspection]) -> Vec<LiquidationCompetition> {
+        inspections
+            .iter()
+            .flat_map(|inspection| inspection.known_actions())
+            .filter_map(|action| action.liquidation_competition())
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn reports_the_winner_and_the_failed_competitors() {
+        let addrs = addrs();
+        let (keeper1, keeper2, keeper3, user, other, dai) =
+            (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4], addrs[5]);
+        let mut inspections = vec![
+            // fails before the winner, e.g. because it ran out of gas
+            attempt(0, keeper2, user, dai, Status::Reverted),
+            attempt(1, keeper1, user, dai, Status::Success),
+            attempt(2, keeper3, user, dai, Status::Checked),
+            // the only attempt on another position
+            attempt(3, keeper2, other, dai, Status::Reverted),
+        ];
+        inspections[2].gas = None;
+        let hashes = inspections.iter().map(|i| i.hash).collect::<Vec<_>>();
+        LiquidationCompetitionReducer::new().reduce_block(&mut inspections);
+
+        assert_eq!(
+            competitions(&inspections),
+            vec![LiquidationCompetition {
+                liquidated_user: user,
+                debt_token: dai,
+                winner: keeper1,
+                winner_tx: hashes[1],
+                failed_txs: vec![hashes[0], hashes[2]],
+                // the gas of the checked attempt is not known
+                wasted_gas: 100_000.into(),
+                wasted_fees: 1_000_000.into(),
+            }]
+        );
+        // attached right after the winning liquidation
+        let winner = &inspections[1];
+        assert_eq!(winner.actions.len(), 2);
+        assert!(winner.liquidations().next().is_some());
+        assert_eq!(winner.actions[1].trace_address(), vec![0]);
+    }
+
+    #[test]
+    fn needs_a_winner_and_a_failed_competitor() {
+        let addrs = addrs();
+        let (keeper1, keeper2, user, dai, usdc) =
+            (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4]);
+
+        // nobody succeeded
+        let mut inspections = vec![
+            attempt(0, keeper1, user, dai, Status::Reverted),
+            attempt(1, keeper2, user, dai, Status::Reverted),
+        ];
+        LiquidationCompetitionReducer::new().reduce_block(&mut inspections);
+        assert!(competitions(&inspections).is_empty());
+
+        // the position's debts in 2 tokens are not competed for
+        let mut inspections = vec![
+            attempt(2, keeper1, user, dai, Status::Success),
+            attempt(3, keeper2, user, usdc, Status::Reverted),
+        ];
+        LiquidationCompetitionReducer::new().reduce_block(&mut inspections);
+        assert!(competitions(&inspections).is_empty());
+
+        // both succeeded, e.g. each liquidating part of the position
+        let mut inspections = vec![
+            attempt(4, keeper1, user, dai, Status::Success),
+            attempt(5, keeper2, user, dai, Status::Success),
+        ];
+        LiquidationCompetitionReducer::new().reduce_block(&mut inspections);
+        assert!(competitions(&inspections).is_empty());
+    }
+}
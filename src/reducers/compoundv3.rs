@@ -0,0 +1,202 @@
+use crate::{
+    addresses::COMETS,
+    types::{
+        actions::{Liquidation, SpecificAction},
+        Inspection, Protocol,
+    },
+    BlockReducer,
+};
+use ethers::types::{Address, U256};
+use std::collections::HashSet;
+
+#[derive(Clone, Debug)]
+/// Links Compound V3's collateral purchases to the borrowers whose collateral
+/// was bought. The market pools the collateral of the accounts it absorbed, so
+/// that a purchase does not know its borrower: it is attributed to the first
+/// account absorbed before it in the block which has collateral of the bought
+/// asset left, and the amount bought is deducted from it. The accounts whose
+/// collateral is not known, since their logs were not fetched, match the
+/// purchases of any asset of their market.
+///
+/// The purchases of accounts absorbed in an earlier block are left with an
+/// unknown borrower. It must run before the `LiquidationCompetitionReducer`,
+/// which groups the liquidations by their borrower.
+pub struct CompoundV3Reducer {
+    /// The tokens lent by the markets, which the purchases are paid in
+    base_tokens: HashSet<Address>,
+}
+
+impl Default for CompoundV3Reducer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CompoundV3Reducer {
+    /// Instantiates the reducer
+    pub fn new() -> Self {
+        Self {
+            base_tokens: COMETS.values().cloned().collect(),
+        }
+    }
+}
+
+/// An absorbed account, and the collateral left to buy from it
+struct Absorbed {
+    base_token: Address,
+    borrower: Address,
+    collateral: Option<Vec<(Address, U256)>>,
+}
+
+impl BlockReducer for CompoundV3Reducer {
+    fn reduce_block(&self, inspections: &mut [Inspection]) {
+        let mut absorbed: Vec<Absorbed> = Vec::new();
+        for inspection in inspections.iter_mut() {
+            if !inspection.protocols.contains(&Protocol::CompoundV3) {
+                continue;
+            }
+            for action in inspection.actions.iter_mut() {
+                let purchase = match action.as_action_mut() {
+                    Some(SpecificAction::CollateralAbsorption(absorption)) => {
+                        absorbed.push(Absorbed {
+                            base_token: absorption.base_token,
+                            borrower: absorption.borrower,
+                            collateral: if absorption.collateral.is_empty() {
+                                None
+                            } else {
+                                Some(absorption.collateral.clone())
+                            },
+                        });
+                        continue;
+                    }
+                    Some(SpecificAction::Liquidation(liquidation)) => liquidation,
+                    Some(SpecificAction::ProfitableLiquidation(liquidation)) => {
+                        &mut liquidation.liquidation
+                    }
+                    _ => continue,
+                };
+                if self.is_purchase(purchase) {
+                    link(&mut absorbed, purchase);
+                }
+            }
+        }
+    }
+}
+
+impl CompoundV3Reducer {
+    fn is_purchase(&self, liquidation: &Liquidation) -> bool {
+        liquidation.auction.is_none()
+            && liquidation.liquidated_user == Address::zero()
+            && self.base_tokens.contains(&liquidation.sent_token)
+    }
+}
+
+/// Attributes the purchase to the first absorbed account which has the asset
+/// left, and deducts it from the account's collateral
+fn link(absorbed: &mut [Absorbed], purchase: &mut Liquidation) {
+    let account = absorbed.iter_mut().find(|account| {
+        account.base_token == purchase.sent_token
+            && match account.collateral {
+                Some(ref collateral) => collateral
+                    .iter()
+                    .any(|(asset, left)| *asset == purchase.received_token && !left.is_zero()),
+                None => true,
+            }
+    });
+    let account = match account {
+        Some(inner) => inner,
+        None => return,
+    };
+    purchase.liquidated_user = account.borrower;
+    if let Some(ref mut collateral) = account.collateral {
+        for (asset, left) in collateral.iter_mut() {
+            if *asset == purchase.received_token {
+                *left = left.saturating_sub(purchase.received_amount);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        addresses::{parse_address, WETH},
+        test_helpers::*,
+        types::{actions::CollateralAbsorption, Classification},
+    };
+
+    fn absorption(borrower: Address, collateral: Vec<(Address, U256)>) -> Classification {
+        Classification::new(
+            CollateralAbsorption {
+                comet: parse_address("A17581A9E3356d9A858b789D68B4d866e593aE94"),
+                absorber: Address::zero(),
+                borrower,
+                base_token: *WETH,
+                debt: 0.into(),
+                collateral,
+            },
+            vec![],
+        )
+    }
+
+    fn purchase(buyer: Address, asset: Address, amount: u64) -> Classification {
+        Classification::new(
+            Liquidation {
+                sent_token: *WETH,
+                sent_amount: amount.into(),
+                received_token: asset,
+                received_amount: amount.into(),
+                from: buyer,
+                liquidated_user: Address::zero(),
+                auction: None,
+            },
+            vec![],
+        )
+    }
+
+    fn inspection(actions: Vec<Classification>) -> Inspection {
+        let mut inspection = mk_inspection(actions);
+        inspection.protocols.insert(Protocol::CompoundV3);
+        inspection
+    }
+
+    fn borrowers(inspections: &[Inspection]) -> Vec<Address> {
+        inspections
+            .iter()
+            .flat_map(|inspection| inspection.liquidations())
+            .map(|liquidation| liquidation.liquidated_user)
+            .collect()
+    }
+
+    #[test]
+    fn links_purchases_to_absorbed_accounts() {
+        let addrs = addrs();
+        let (alice, bob, carol, keeper, wsteth, cbeth) =
+            (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4], addrs[5]);
+
+        let mut inspections = vec![
+            // bought before anything was absorbed in the block
+            inspection(vec![purchase(keeper, wsteth, 1)]),
+            inspection(vec![
+                absorption(alice, vec![(wsteth, 10.into())]),
+                absorption(bob, vec![(wsteth, 5.into()), (cbeth, 5.into())]),
+            ]),
+            // alice's collateral runs out, and then bob's is bought
+            inspection(vec![
+                purchase(keeper, wsteth, 10),
+                purchase(keeper, wsteth, 4),
+                purchase(keeper, cbeth, 5),
+            ]),
+            // carol's collateral is not known
+            inspection(vec![absorption(carol, vec![])]),
+            inspection(vec![purchase(keeper, cbeth, 3)]),
+        ];
+        CompoundV3Reducer::new().reduce_block(&mut inspections);
+
+        assert_eq!(
+            borrowers(&inspections),
+            vec![Address::zero(), alice, bob, bob, carol]
+        );
+    }
+}
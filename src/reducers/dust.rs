@@ -0,0 +1,219 @@
+use crate::{
+    prices::{net_profit, signed, usd_value, PriceOracle},
+    types::{actions::Sandwich, Classification, Inspection},
+    BlockReducer,
+};
+use ethers::types::I256;
+
+/// The default minimum profit of the `DustReducer`, in USD
+pub const DEFAULT_MIN_PROFIT: f64 = 0.01;
+
+#[derive(Clone, Debug)]
+/// Drops the arbitrages and the sandwiches which made less than a minimum
+/// profit in USD once their gas is paid for, e.g. the "arbitrages" worth a
+/// fraction of a cent which are only rounding, and the ones which made less
+/// than they paid for gas. The profit of an arbitrage is the net profit of its
+/// tx, and the profit of a sandwich is net of the gas of both of its txs.
+///
+/// It must run after all the other reducers and block reducers, since the
+/// sandwiches are only found across the txs of a block. The actions whose
+/// profit cannot be netted, because their gas is not set or their tokens or
+/// USDC cannot be priced, are kept. A minimum profit of 0 disables it.
+pub struct DustReducer<O> {
+    oracle: O,
+    min_profit: f64,
+}
+
+impl<O> DustReducer<O> {
+    /// Instantiates the reducer with the `DEFAULT_MIN_PROFIT`
+    pub fn new(oracle: O) -> Self {
+        Self {
+            oracle,
+            min_profit: DEFAULT_MIN_PROFIT,
+        }
+    }
+
+    /// Sets the minimum profit in USD, or disables the reducer with 0
+    pub fn with_min_profit(mut self, min_profit: f64) -> Self {
+        self.min_profit = min_profit;
+        self
+    }
+}
+
+impl<O: PriceOracle> DustReducer<O> {
+    /// Whether the ETH made at `block` is below the minimum profit
+    fn is_dust(&self, profit: Option<I256>, block: u64) -> bool {
+        match profit.and_then(|profit| usd_value(&self.oracle, profit, block)) {
+            Some(usd) => usd < self.min_profit,
+            None => false,
+        }
+    }
+
+    /// The ETH made by the sandwich, net of the costs of its txs
+    fn sandwich_profit(&self, inspections: &[Inspection], sandwich: &Sandwich) -> Option<I256> {
+        let tx_cost = |hash| {
+            inspections
+                .iter()
+                .find(|inspection| inspection.hash == hash)?
+                .total_cost()
+        };
+        let cost = tx_cost(sandwich.frontrun_tx)?.saturating_add(tx_cost(sandwich.backrun_tx)?);
+        let block = inspections
+            .iter()
+            .find(|inspection| inspection.hash == sandwich.frontrun_tx)?
+            .block_number;
+        net_profit(&self.oracle, vec![(sandwich.token, sandwich.profit)], block)?
+            .checked_sub(signed(cost)?)
+    }
+}
+
+impl<O: PriceOracle> BlockReducer for DustReducer<O> {
+    fn reduce_block(&self, inspections: &mut [Inspection]) {
+        if self.min_profit <= 0.0 {
+            return;
+        }
+        for i in 0..inspections.len() {
+            let block = inspections[i].block_number;
+            let dust_arbs = inspections[i].arbitrages().next().is_some()
+                && self.is_dust(inspections[i].net_profit_eth(&self.oracle), block);
+            for a in 0..inspections[i].actions.len() {
+                let action = match inspections[i].actions[a].as_action() {
+                    Some(inner) => inner,
+                    None => continue,
+                };
+                let dust = match action.sandwich() {
+                    Some(sandwich) => {
+                        self.is_dust(self.sandwich_profit(inspections, sandwich), block)
+                    }
+                    None => dust_arbs && action.arbitrage().is_some(),
+                };
+                if dust {
+                    inspections[i].actions[a] = Classification::Prune;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        addresses::{USDC, WETH},
+        test_helpers::{addrs, mk_tx},
+        types::actions::{Arbitrage, SpecificAction, Trade, Transfer},
+        UniswapV2Oracle,
+    };
+    use ethers::types::{Address, TxHash, U256};
+
+    const WEI: u64 = 1_000_000_000_000_000_000;
+
+    fn oracle() -> UniswapV2Oracle {
+        let oracle = UniswapV2Oracle::new();
+        // 1 ETH = 2000 USDC
+        oracle.insert(*USDC, 0, (2000 * 1_000_000u64).into(), WEI.into());
+        oracle
+    }
+
+    /// A tx which paid `cost` wei of gas for `action`
+    fn tx(i: u64, action: impl Into<SpecificAction>, cost: Option<u64>) -> Inspection {
+        mk_tx(
+            i,
+            Address::zero(),
+            vec![action.into()],
+            cost.map(|cost| cost / 100_000),
+        )
+    }
+
+    /// The number of arbitrages and sandwiches which are left
+    fn kept<O: PriceOracle>(reducer: &DustReducer<O>, mut inspections: Vec<Inspection>) -> usize {
+        reducer.reduce_block(&mut inspections);
+        inspections
+            .iter()
+            .flat_map(|inspection| inspection.known_actions())
+            .filter(|action| action.arbitrage().is_some() || action.sandwich().is_some())
+            .count()
+    }
+
+    #[test]
+    fn drops_what_made_less_than_the_minimum_after_gas() {
+        let addrs = addrs();
+        let arb = |profit: u64| Arbitrage {
+            profit: profit.into(),
+            token: *WETH,
+            to: addrs[0],
+            capital: 0.into(),
+            trades: vec![],
+            recipient: addrs[0],
+            searcher: Default::default(),
+        };
+        let reducer = DustReducer::new(oracle());
+
+        // $20 for $19.80 of gas leaves 20 cents
+        let profitable = tx(1, arb(WEI / 100), Some(WEI / 100 - WEI / 10_000));
+        assert_eq!(kept(&reducer, vec![profitable.clone()]), 1);
+        assert_eq!(
+            kept(&reducer.clone().with_min_profit(1.0), vec![profitable]),
+            0
+        );
+        // a fraction of a cent, or less than the gas
+        assert_eq!(
+            kept(&reducer, vec![tx(1, arb(WEI / 1_000_000_000), Some(0))]),
+            0
+        );
+        let unprofitable = tx(1, arb(WEI / 100), Some(WEI / 50));
+        assert_eq!(kept(&reducer, vec![unprofitable.clone()]), 0);
+        // unless disabled, or the gas is unknown
+        assert_eq!(
+            kept(&reducer.clone().with_min_profit(0.0), vec![unprofitable]),
+            1
+        );
+        assert_eq!(kept(&reducer, vec![tx(1, arb(WEI / 100), None)]), 1);
+        // or the prices
+        let unpriced = DustReducer::new(UniswapV2Oracle::new());
+        assert_eq!(kept(&unpriced, vec![tx(1, arb(0), Some(WEI))]), 1);
+    }
+
+    #[test]
+    fn nets_sandwiches_of_the_gas_of_both_legs() {
+        let addrs = addrs();
+        let (searcher, pool, token) = (addrs[0], addrs[1], addrs[2]);
+        let sandwich = Sandwich {
+            pool,
+            frontrun_tx: TxHash::from_low_u64_be(1),
+            backrun_tx: TxHash::from_low_u64_be(2),
+            frontrun: Trade::new(
+                Transfer::new(searcher, pool, WEI.into(), *WETH),
+                Transfer::new(pool, searcher, 100.into(), token),
+            ),
+            victims: vec![],
+            backrun: Trade::new(
+                Transfer::new(searcher, pool, 100.into(), token),
+                Transfer::new(pool, searcher, (WEI + WEI / 100).into(), *WETH),
+            ),
+            token: *WETH,
+            // $20
+            profit: I256::from_raw(U256::from(WEI / 100)),
+            capital: WEI.into(),
+            searcher: Default::default(),
+        };
+        let backrun = |cost: u64| {
+            mk_tx(
+                2,
+                Address::zero(),
+                Vec::<Trade>::new(),
+                Some(cost / 100_000),
+            )
+        };
+        let reducer = DustReducer::new(oracle());
+
+        // $10 of gas for the frontrun and $5 for the backrun leave $5, but $10
+        // for both leave nothing
+        let frontrun = tx(1, sandwich.clone(), Some(WEI / 200));
+        assert_eq!(
+            kept(&reducer, vec![frontrun.clone(), backrun(WEI / 400)]),
+            1
+        );
+        assert_eq!(kept(&reducer, vec![frontrun, backrun(WEI / 200)]), 0);
+    }
+}
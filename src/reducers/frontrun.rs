@@ -0,0 +1,181 @@
+use crate::{
+    types::{
+        actions::{Frontrun, Trade},
+        Classification, Inspection, Provenance,
+    },
+    BlockReducer,
+};
+use ethers::types::Address;
+
+#[derive(Clone, Debug)]
+/// Finds trades which were copied by a searcher and included ahead of the
+/// original by paying a higher gas price, across the transactions of a block.
+///
+/// Independent trades on a popular pool are common, so both transactions must
+/// take the same route, i.e. trade on the same pools in the same directions
+/// and order. The gas of both transactions must be known.
+pub struct FrontrunReducer;
+
+impl Default for FrontrunReducer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrontrunReducer {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl BlockReducer for FrontrunReducer {
+    fn reduce_block(&self, inspections: &mut [Inspection]) {
+        for i in 0..inspections.len() {
+            let route = route(&inspections[i]);
+            let gas_price = match inspections[i].gas {
+                Some(ref gas) if !route.is_empty() => gas.effective_gas_price(),
+                _ => continue,
+            };
+
+            let victim = inspections
+                .iter()
+                .skip(i + 1)
+                .filter(|victim| victim.from != inspections[i].from)
+                .find(|victim| {
+                    matches!(victim.gas, Some(ref gas) if gas.effective_gas_price() < gas_price)
+                        && route == self::route(victim)
+                });
+            let victim = match victim {
+                Some(victim) => victim,
+                None => continue,
+            };
+
+            let (a, frontrun) = first_trade(&inspections[i]).expect("the route is not empty");
+            let (v, victim_trade) = first_trade(victim).expect("the victim took the same route");
+            let provenance = Provenance::of([&inspections[i].actions[a], &victim.actions[v]]);
+            let frontrun = Frontrun {
+                frontrunner_tx: inspections[i].hash,
+                victim_tx: victim.hash,
+                pool: frontrun.t1.to,
+                frontrun: frontrun.clone(),
+                victim: victim_trade.clone(),
+            };
+            let trace_address = inspections[i].actions[a].trace_address();
+            inspections[i].actions[a] =
+                Classification::new(frontrun, trace_address).with_provenance(provenance);
+        }
+    }
+}
+
+/// The pools traded on by a tx along with the tokens sold and bought on each
+fn route(inspection: &Inspection) -> Vec<(Address, Address, Address)> {
+    inspection
+        .trades()
+        .map(|trade| (trade.t1.to, trade.t1.token, trade.t2.token))
+        .collect()
+}
+
+fn first_trade(inspection: &Inspection) -> Option<(usize, &Trade)> {
+    inspection
+        .actions
+        .iter()
+        .enumerate()
+        .find_map(|(a, action)| Some((a, action.as_action()?.trade()?)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_helpers::*, types::actions::Transfer};
+
+    fn trade(usr: Address, pool: Address, t1: (Address, u64), t2: (Address, u64)) -> Trade {
+        Trade::new(
+            Transfer {
+                from: usr,
+                to: pool,
+                amount: t1.1.into(),
+                token: t1.0,
+            },
+            Transfer {
+                from: pool,
+                to: usr,
+                amount: t2.1.into(),
+                token: t2.0,
+            },
+        )
+    }
+
+    #[test]
+    fn copied_trade_with_higher_gas_price() {
+        let addrs = addrs();
+        let (searcher, alice, bob, pool, other) =
+            (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4]);
+        let (weth, dai) = (addrs[5], addrs[6]);
+
+        let copy = trade(searcher, pool, (weth, 100), (dai, 1000));
+        let original = trade(alice, pool, (weth, 100), (dai, 990));
+        let mut inspections = vec![
+            mk_tx(0, searcher, vec![copy.clone()], Some(200)),
+            // an independent trade which also uses another pool
+            mk_tx(
+                1,
+                bob,
+                vec![original.clone(), trade(bob, other, (dai, 990), (weth, 99))],
+                Some(100),
+            ),
+            mk_tx(2, alice, vec![original.clone()], Some(100)),
+        ];
+        FrontrunReducer::new().reduce_block(&mut inspections);
+
+        let frontrun = inspections[0].known()[0]
+            .as_ref()
+            .frontrun()
+            .cloned()
+            .unwrap();
+        assert_eq!(frontrun.frontrunner_tx, inspections[0].hash);
+        assert_eq!(frontrun.victim_tx, inspections[2].hash);
+        assert_eq!(frontrun.pool, pool);
+        assert_eq!(frontrun.frontrun, copy);
+        assert_eq!(frontrun.victim, original);
+        // the victims' trades stay as they are
+        assert_eq!(inspections[2].trades().count(), 1);
+        assert_eq!(inspections[1].trades().count(), 2);
+    }
+
+    #[test]
+    fn needs_a_higher_gas_price_and_the_same_route() {
+        let addrs = addrs();
+        let (searcher, alice, pool, weth, dai) = (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4]);
+
+        let copy = trade(searcher, pool, (weth, 100), (dai, 1000));
+        let original = trade(alice, pool, (weth, 100), (dai, 990));
+        let reverse = trade(alice, pool, (dai, 990), (weth, 100));
+
+        for block in [
+            // outbid by the later tx
+            vec![
+                mk_tx(0, searcher, vec![copy.clone()], Some(100)),
+                mk_tx(1, alice, vec![original.clone()], Some(200)),
+            ],
+            // the gas of the victim is not known
+            vec![
+                mk_tx(2, searcher, vec![copy.clone()], Some(200)),
+                mk_tx(3, alice, vec![original.clone()], None),
+            ],
+            // opposite directions
+            vec![
+                mk_tx(4, searcher, vec![copy.clone()], Some(200)),
+                mk_tx(5, alice, vec![reverse], Some(100)),
+            ],
+            // the same sender
+            vec![
+                mk_tx(6, searcher, vec![copy.clone()], Some(200)),
+                mk_tx(7, searcher, vec![copy], Some(100)),
+            ],
+        ] {
+            let mut inspections = block.clone();
+            FrontrunReducer::new().reduce_block(&mut inspections);
+            assert_eq!(inspections, block);
+        }
+    }
+}
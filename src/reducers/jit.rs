@@ -0,0 +1,330 @@
+use crate::{
+    prices::signed_delta,
+    types::{
+        actions::{AddLiquidity, JitLiquidity, RemoveLiquidity, Trade},
+        Classification, Inspection, Provenance,
+    },
+    BlockReducer, Reducer,
+};
+use ethers::types::{Address, U256};
+
+#[derive(Clone, Debug)]
+/// Finds liquidity which was added right before a trade against the same pool
+/// and removed right after it, either in the same transaction or in the
+/// transactions around the trade's.
+pub struct JitReducer;
+
+impl Default for JitReducer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl JitReducer {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Reducer for JitReducer {
+    fn reduce(&self, inspection: &mut Inspection) {
+        let lp = inspection.from;
+        let actions = &inspection.actions;
+        let mut classified = Vec::new();
+        for (i, add) in actions.iter().enumerate() {
+            let add = match add.as_action().and_then(|a| a.add_liquidity()) {
+                Some(inner) => inner,
+                None => continue,
+            };
+
+            // the first removal from the same range after it
+            let res = actions.iter().enumerate().skip(i + 1).find_map(|(j, a)| {
+                let remove = a.as_action()?.remove_liquidity()?;
+                if same_range(add, remove) {
+                    Some((j, remove))
+                } else {
+                    None
+                }
+            });
+            let (j, remove) = match res {
+                Some(inner) => inner,
+                None => continue,
+            };
+
+            let trades = actions[i + 1..j]
+                .iter()
+                .filter_map(|a| a.as_action()?.trade())
+                .collect::<Vec<_>>();
+            if let Some(jit) = jit_liquidity(add, &trades, remove, lp) {
+                let provenance = Provenance::of([&actions[i], &actions[j]]);
+                let jit = Classification::new(jit, actions[i].trace_address())
+                    .with_provenance(provenance);
+                classified.push((i, jit));
+                classified.push((j, Classification::Prune));
+            }
+        }
+
+        for (i, action) in classified {
+            inspection.actions[i] = action;
+        }
+    }
+}
+
+impl BlockReducer for JitReducer {
+    fn reduce_block(&self, inspections: &mut [Inspection]) {
+        for i in 0..inspections.len().saturating_sub(2) {
+            let (front, rest) = inspections.split_at_mut(i + 1);
+            let (victim, back) = rest.split_at_mut(1);
+            let (front, victim, back) = (&mut front[i], &victim[0], &mut back[0]);
+
+            // the liquidity must be added and removed by the same account
+            if front.from != back.from {
+                continue;
+            }
+
+            let trades = victim
+                .actions
+                .iter()
+                .filter_map(|a| a.as_action()?.trade())
+                .collect::<Vec<_>>();
+            if trades.is_empty() {
+                continue;
+            }
+
+            for a in 0..front.actions.len() {
+                let add = match front.actions[a].as_action().and_then(|a| a.add_liquidity()) {
+                    Some(inner) => inner,
+                    None => continue,
+                };
+
+                let res = back.actions.iter().enumerate().find_map(|(r, action)| {
+                    let remove = action.as_action()?.remove_liquidity()?;
+                    if same_range(add, remove) {
+                        Some((r, remove))
+                    } else {
+                        None
+                    }
+                });
+
+                let (r, remove) = match res {
+                    Some(inner) => inner,
+                    None => continue,
+                };
+                if let Some(jit) = jit_liquidity(add, &trades, remove, front.from) {
+                    let trace_address = front.actions[a].trace_address();
+                    let provenance = Provenance::of([&front.actions[a], &back.actions[r]]);
+                    front.actions[a] =
+                        Classification::new(jit, trace_address).with_provenance(provenance);
+                    back.actions[r] = Classification::Prune;
+                }
+            }
+        }
+    }
+}
+
+/// Whether the liquidity was removed from the same pool and (part of) the same
+/// tick range as it was provided to
+fn same_range(add: &AddLiquidity, remove: &RemoveLiquidity) -> bool {
+    match (&add.position, &remove.position) {
+        (Some(added), Some(removed)) => added.pool == removed.pool && added.overlaps(removed),
+        _ => false,
+    }
+}
+
+fn jit_liquidity(
+    add: &AddLiquidity,
+    trades: &[&Trade],
+    remove: &RemoveLiquidity,
+    lp: Address,
+) -> Option<JitLiquidity> {
+    let pool = add.position.as_ref()?.pool;
+
+    // only count the trades against the pool which were not made by the LP
+    let trades = trades
+        .iter()
+        .filter(|t| t.t1.to == pool || t.t2.from == pool)
+        .filter(|t| t.t1.from != lp && t.t2.to != lp)
+        .map(|t| (*t).clone())
+        .collect::<Vec<_>>();
+    if trades.is_empty() {
+        return None;
+    }
+
+    let tokens = if add.tokens.is_empty() {
+        remove.tokens.clone()
+    } else {
+        add.tokens.clone()
+    };
+    let fees = if remove.fees.is_empty() {
+        vec![U256::zero(); remove.amounts.len()]
+    } else {
+        remove.fees.clone()
+    };
+    let deltas = add
+        .amounts
+        .iter()
+        .zip(&remove.amounts)
+        .zip(&fees)
+        .map(|((added, removed), fee)| signed_delta(removed.saturating_add(*fee), *added))
+        .collect::<Option<Vec<_>>>()?;
+
+    Some(JitLiquidity {
+        pool,
+        tokens,
+        added: add.amounts.clone(),
+        removed: remove.amounts.clone(),
+        fees,
+        trades,
+        deltas,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+    use crate::types::actions::{LiquidityPosition, Transfer};
+    use ethers::types::I256;
+
+    fn position(pool: Address, tick_lower: i32, tick_upper: i32) -> Option<LiquidityPosition> {
+        Some(LiquidityPosition {
+            pool,
+            token_id: 1.into(),
+            tick_lower,
+            tick_upper,
+        })
+    }
+
+    fn trade(usr: Address, pool: Address, token0: Address, token1: Address) -> Trade {
+        Trade::new(
+            Transfer {
+                from: usr,
+                to: pool,
+                amount: 1000.into(),
+                token: token0,
+            },
+            Transfer {
+                from: pool,
+                to: usr,
+                amount: 990.into(),
+                token: token1,
+            },
+        )
+    }
+
+    #[test]
+    fn jit_across_transactions() {
+        let addrs = addrs();
+        let (lp, usr, pool, token0, token1) = (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4]);
+
+        let add = AddLiquidity {
+            tokens: vec![token0, token1],
+            amounts: vec![500.into(), 500.into()],
+            position: position(pool, -60, 60),
+        };
+        let remove = RemoveLiquidity {
+            tokens: vec![token0, token1],
+            amounts: vec![1490.into(), 0.into()],
+            fees: vec![3.into(), 0.into()],
+            position: position(pool, -60, 60),
+        };
+        let trade = trade(usr, pool, token0, token1);
+
+        let mut front = mk_inspection(vec![Classification::new(add, vec![])]);
+        front.from = lp;
+        let victim = mk_inspection(vec![Classification::new(trade.clone(), vec![])]);
+        let mut back = mk_inspection(vec![Classification::new(remove, vec![])]);
+        back.from = lp;
+
+        let mut inspections = vec![front, victim, back];
+        JitReducer::new().reduce_block(&mut inspections);
+
+        let jit = inspections[0].known()[0]
+            .as_ref()
+            .jit_liquidity()
+            .cloned()
+            .unwrap();
+        assert_eq!(jit.pool, pool);
+        assert_eq!(jit.trades, vec![trade]);
+        assert_eq!(jit.fees, vec![3.into(), 0.into()]);
+        assert_eq!(
+            jit.deltas,
+            vec![I256::from(993), I256::from(-500)],
+            "the LP ends up with the token which was sold to the pool"
+        );
+        assert_eq!(inspections[2].actions, vec![Classification::Prune]);
+    }
+
+    #[test]
+    fn jit_in_one_transaction() {
+        let addrs = addrs();
+        let (usr, pool, token0, token1) = (addrs[1], addrs[2], addrs[3], addrs[4]);
+
+        let add = AddLiquidity {
+            tokens: vec![token0, token1],
+            amounts: vec![500.into(), 500.into()],
+            position: position(pool, -60, 60),
+        };
+        // only the remove's range needs to overlap with the add's
+        let remove = RemoveLiquidity {
+            tokens: vec![token0, token1],
+            amounts: vec![1490.into(), 0.into()],
+            fees: vec![],
+            position: position(pool, 0, 120),
+        };
+
+        let mut inspection = mk_inspection(vec![
+            Classification::new(add, vec![0]),
+            Classification::new(trade(usr, pool, token0, token1), vec![1]),
+            Classification::new(remove, vec![2]),
+        ]);
+        JitReducer::new().reduce(&mut inspection);
+
+        let jit = inspection.actions[0].as_action().unwrap();
+        assert_eq!(jit.jit_liquidity().unwrap().fees, vec![0.into(), 0.into()]);
+        assert!(inspection.actions[1].as_action().unwrap().trade().is_some());
+        assert_eq!(inspection.actions[2], Classification::Prune);
+    }
+
+    #[test]
+    fn ignores_other_pools_and_ranges() {
+        let addrs = addrs();
+        let (lp, usr, pool, token0, token1) = (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4]);
+
+        let add = AddLiquidity {
+            tokens: vec![token0, token1],
+            amounts: vec![500.into(), 500.into()],
+            position: position(pool, -60, 60),
+        };
+        let remove = |pool, tick_lower, tick_upper| RemoveLiquidity {
+            tokens: vec![token0, token1],
+            amounts: vec![1490.into(), 0.into()],
+            fees: vec![],
+            position: position(pool, tick_lower, tick_upper),
+        };
+        let trade = trade(usr, pool, token0, token1);
+
+        for remove in &[remove(addrs[5], -60, 60), remove(pool, 60, 120)] {
+            let mut inspection = mk_inspection(vec![
+                Classification::new(add.clone(), vec![0]),
+                Classification::new(trade.clone(), vec![1]),
+                Classification::new(remove.clone(), vec![2]),
+            ]);
+            inspection.from = lp;
+            let expected = inspection.actions.clone();
+            JitReducer::new().reduce(&mut inspection);
+            assert_eq!(inspection.actions, expected);
+        }
+
+        // the LP's own trades are not JIT
+        let mut inspection = mk_inspection(vec![
+            Classification::new(add, vec![0]),
+            Classification::new(trade, vec![1]),
+            Classification::new(remove(pool, -60, 60), vec![2]),
+        ]);
+        inspection.from = usr;
+        let expected = inspection.actions.clone();
+        JitReducer::new().reduce(&mut inspection);
+        assert_eq!(inspection.actions, expected);
+    }
+}
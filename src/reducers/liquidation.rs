@@ -1,92 +1,109 @@
 use crate::{
-    addresses::{ETH, WETH},
+    addresses::{ChainConfig, NATIVE_ETH, WETH},
     inspectors::find_matching,
     types::{
         actions::{ProfitableLiquidation, Transfer},
-        Classification, Inspection,
+        Classification, Inspection, Provenance,
     },
     Reducer,
 };
+use ethers::types::Address;
 
-pub struct LiquidationReducer;
+pub struct LiquidationReducer {
+    /// The chain's wrapped native token, which liquidators may be repaid in
+    /// instead of the native token
+    weth: Address,
+    native: Address,
+}
 
 impl LiquidationReducer {
     pub fn new() -> Self {
-        Self
+        Self {
+            weth: *WETH,
+            native: NATIVE_ETH,
+        }
+    }
+
+    /// Instantiates a reducer for `chain`
+    pub fn with_chain(chain: &ChainConfig) -> Self {
+        Self {
+            weth: chain.weth,
+            native: chain.native,
+        }
     }
 }
 
 impl Reducer for LiquidationReducer {
     fn reduce(&self, inspection: &mut Inspection) {
-        let actions = inspection.actions.clone();
+        let actions = &inspection.actions;
+        let mut classified = Vec::new();
         let mut prune = Vec::new();
 
         // 1. find all the liquidations and populate their received amount with
         // the transfer that was their subtrace
         // 2. find the tx right before the liquidation which has a matching token
         // as the received token and use that to calculate the profit
-        inspection
-            .actions
-            .iter_mut()
-            .enumerate()
-            .for_each(|(i, ref mut action)| {
-                let opt = action
-                    .as_action_mut()
-                    .map(|x| x.liquidation_mut())
-                    .flatten();
-                let liquidation = if let Some(liquidation) = opt {
-                    liquidation
-                } else {
-                    return;
-                };
-
-                // find the transfer which corresponds to this liquidation
-                let mut liq = liquidation.clone();
-                let check_fn = |t: &Transfer| {
-                    t.to == liq.from && (t.token == liq.received_token || t.token == *ETH)
-                };
-
-                // found the transfer after, which is the one that pays us
+        for (i, action) in actions.iter().enumerate() {
+            let liquidation = match action.as_action().and_then(|x| x.liquidation()) {
+                Some(liquidation) => liquidation,
+                None => continue,
+            };
+
+            // find the transfer which corresponds to this liquidation
+            let mut liq = liquidation.clone();
+            let check_fn = |t: &Transfer| {
+                t.to == liq.from && (t.token == liq.received_token || t.token == self.native)
+            };
+
+            // found the transfer after, which is the one that pays us
+            let res = find_matching(
+                actions.iter().enumerate().skip(i + 1),
+                |t| t.transfer(),
+                check_fn,
+                true,
+            );
+            if let Some((idx, received)) = res {
+                // prune the repayment subcall
+                prune.push(idx);
+
+                // there may be a DEX trade before the liquidation, allowing
+                // us to instantly determine if it's a profitable liquidation
+                // or not
                 let res = find_matching(
-                    actions.iter().enumerate().skip(i + 1),
-                    |t| t.transfer(),
-                    check_fn,
+                    actions.iter().enumerate(),
+                    |t| t.trade(),
+                    |t| t.t2.token == liq.sent_token,
                     true,
                 );
-                if let Some((idx, received)) = res {
-                    // prune the repayment subcall
-                    prune.push(idx);
-
-                    // there may be a DEX trade before the liquidation, allowing
-                    // us to instantly determine if it's a profitable liquidation
-                    // or not
-                    let res = find_matching(
-                        actions.iter().enumerate(),
-                        |t| t.trade(),
-                        |t| t.t2.token == liq.sent_token,
-                        true,
-                    );
-
-                    if let Some((_, paid)) = res {
-                        // prune.push(idx2);
-                        let tokens_match = (received.token == paid.t1.token)
-                            || ((received.token == *ETH && paid.t1.token == *WETH)
-                                || (received.token == *WETH && paid.t1.token == *ETH));
-                        if received.amount > paid.t1.amount && tokens_match {
-                            liq.received_amount = received.amount;
-                            let profitable_liq = ProfitableLiquidation {
-                                token: paid.t1.token,
-                                liquidation: liq.clone(),
-                                profit: received.amount - paid.t1.amount,
-                            };
-                            **action = Classification::new(profitable_liq, Vec::new());
-                            return;
-                        }
-                    }
 
-                    liquidation.received_amount = received.amount;
+                if let Some((_, paid)) = res {
+                    // prune.push(idx2);
+                    let tokens_match = (received.token == paid.t1.token)
+                        || ((received.token == self.native && paid.t1.token == self.weth)
+                            || (received.token == self.weth && paid.t1.token == self.native));
+                    if received.amount > paid.t1.amount && tokens_match {
+                        liq.received_amount = received.amount;
+                        let profitable_liq = ProfitableLiquidation {
+                            token: paid.t1.token,
+                            liquidation: liq.clone(),
+                            profit: received.amount - paid.t1.amount,
+                        };
+                        let profitable_liq = Classification::new(profitable_liq, Vec::new())
+                            .with_provenance(Provenance::TransferInferred);
+                        classified.push((i, profitable_liq));
+                        continue;
+                    }
                 }
-            });
+
+                liq.received_amount = received.amount;
+                let liquidation = Classification::new(liq, action.trace_address())
+                    .with_provenance(Provenance::TransferInferred);
+                classified.push((i, liquidation));
+            }
+        }
+        for (i, action) in classified {
+            inspection.actions[i] = action;
+        }
         for i in prune {
             inspection.actions[i] = Classification::Prune;
         }
@@ -169,6 +186,7 @@ mod tests {
 
             from: usr,
             liquidated_user: liquidated,
+            auction: None,
         };
 
         // gets paid out in ETH
@@ -200,7 +218,8 @@ mod tests {
             Classification::new(trade1, Vec::new()),
             Classification::new(trade2, Vec::new()),
             Classification::new(repayment, Vec::new()),
-            Classification::new(res, Vec::new()),
+            // its profit comes from matching the payout
+            Classification::new(res, Vec::new()).with_provenance(Provenance::TransferInferred),
             Classification::Prune,
         ];
 
@@ -0,0 +1,160 @@
+use crate::{
+    types::{actions::SpecificAction, Classification, Inspection},
+    BlockReducer,
+};
+use ethers::types::{Address, U256};
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, Default)]
+/// Aggregates the takes of Maker's collateral auctions across the transactions
+/// of a block, keyed by their auction id. The takes of each taker are kept
+/// apart, so that each one's liquidations still add up to what it paid and got.
+/// An aggregated take is attached to the first of its transactions.
+///
+/// Takes do not know the vault they liquidate unless their logs were fetched,
+/// in which case it is taken from the bark which kicked off the auction if it
+/// is in the same block.
+pub struct MakerAuctionReducer;
+
+impl MakerAuctionReducer {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl BlockReducer for MakerAuctionReducer {
+    fn reduce_block(&self, inspections: &mut [Inspection]) {
+        // the vaults of the auctions kicked off in this block
+        let mut urns = HashMap::new();
+        for inspection in inspections.iter() {
+            for liquidation in inspection.liquidations() {
+                if let Some(ref auction) = liquidation.auction {
+                    if liquidation.received_amount.is_zero()
+                        && liquidation.liquidated_user != Address::zero()
+                    {
+                        urns.insert((auction.house, auction.id), liquidation.liquidated_user);
+                    }
+                }
+            }
+        }
+
+        // (house, id, taker) -> the position of the first take
+        let mut takes: HashMap<(Address, U256, Address), (usize, usize)> = HashMap::new();
+        for i in 0..inspections.len() {
+            for a in 0..inspections[i].actions.len() {
+                let liquidation = match inspections[i].actions[a].as_action_mut() {
+                    Some(SpecificAction::Liquidation(liquidation)) => liquidation,
+                    _ => continue,
+                };
+                let key = match liquidation.auction {
+                    // kicks have not sold anything yet
+                    Some(ref auction) if !liquidation.received_amount.is_zero() => {
+                        (auction.house, auction.id, liquidation.from)
+                    }
+                    _ => continue,
+                };
+                if liquidation.liquidated_user == Address::zero() {
+                    if let Some(urn) = urns.get(&(key.0, key.1)) {
+                        liquidation.liquidated_user = *urn;
+                    }
+                }
+
+                match takes.get(&key) {
+                    Some(&(i0, a0)) => {
+                        let take = liquidation.clone();
+                        inspections[i].actions[a] = Classification::Prune;
+                        if let Some(SpecificAction::Liquidation(first)) =
+                            inspections[i0].actions[a0].as_action_mut()
+                        {
+                            first.sent_amount += take.sent_amount;
+                            first.received_amount += take.received_amount;
+                            if first.liquidated_user == Address::zero() {
+                                first.liquidated_user = take.liquidated_user;
+                            }
+                        }
+                    }
+                    None => {
+                        takes.insert(key, (i, a));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_helpers::*,
+        types::actions::{Auction, Liquidation},
+    };
+
+    fn liquidation(
+        house: Address,
+        from: Address,
+        urn: Address,
+        sent: u64,
+        received: u64,
+    ) -> Liquidation {
+        Liquidation {
+            sent_token: Address::zero(),
+            sent_amount: sent.into(),
+            received_token: Address::zero(),
+            received_amount: received.into(),
+            from,
+            liquidated_user: urn,
+            auction: Some(Auction {
+                house,
+                id: 1.into(),
+                ilk: "ETH-A".to_owned(),
+            }),
+        }
+    }
+
+    fn inspection(i: u64, liquidation: Liquidation) -> Inspection {
+        mk_tx(i, Address::zero(), vec![liquidation], None)
+    }
+
+    #[test]
+    fn aggregates_takes_of_each_taker() {
+        let addrs = addrs();
+        let (clip, keeper, other, urn) = (addrs[0], addrs[1], addrs[2], addrs[3]);
+        let mut inspections = vec![
+            inspection(0, liquidation(clip, keeper, urn, 0, 0)),
+            inspection(1, liquidation(clip, keeper, Address::zero(), 100, 1)),
+            inspection(2, liquidation(clip, other, Address::zero(), 50, 1)),
+            inspection(3, liquidation(clip, keeper, Address::zero(), 300, 2)),
+        ];
+        MakerAuctionReducer::new().reduce_block(&mut inspections);
+
+        // the kick is left alone
+        assert_eq!(
+            inspections[0].liquidations().next().unwrap(),
+            &liquidation(clip, keeper, urn, 0, 0)
+        );
+        assert_eq!(
+            inspections[1].liquidations().next().unwrap(),
+            &liquidation(clip, keeper, urn, 400, 3)
+        );
+        assert_eq!(
+            inspections[2].liquidations().next().unwrap(),
+            &liquidation(clip, other, urn, 50, 1)
+        );
+        assert_eq!(inspections[3].liquidations().count(), 0);
+        assert_eq!(inspections[3].actions[0], Classification::Prune);
+    }
+
+    #[test]
+    fn keeps_auctions_apart() {
+        let addrs = addrs();
+        let (clip, other_clip, keeper) = (addrs[0], addrs[1], addrs[2]);
+        let mut inspections = vec![
+            inspection(0, liquidation(clip, keeper, Address::zero(), 100, 1)),
+            inspection(1, liquidation(other_clip, keeper, Address::zero(), 100, 1)),
+        ];
+        MakerAuctionReducer::new().reduce_block(&mut inspections);
+        assert_eq!(inspections[0].liquidations().count(), 1);
+        assert_eq!(inspections[1].liquidations().count(), 1);
+    }
+}
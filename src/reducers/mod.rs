@@ -2,7 +2,46 @@ mod trade;
 pub use trade::TradeReducer;
 
 mod arbitrage;
-pub use arbitrage::ArbitrageReducer;
+pub use arbitrage::{ArbitrageReducer, OpenArbitrageReducer};
+
+mod statistical;
+pub use statistical::StatisticalArbitrageReducer;
 
 mod liquidation;
 pub use liquidation::LiquidationReducer;
+
+mod jit;
+pub use jit::JitReducer;
+
+mod sandwich;
+pub use sandwich::SandwichReducer;
+
+mod frontrun;
+pub use frontrun::FrontrunReducer;
+
+mod maker;
+pub use maker::MakerAuctionReducer;
+
+mod compoundv3;
+pub use compoundv3::CompoundV3Reducer;
+
+mod transfer;
+pub use transfer::TransferReducer;
+
+mod aggregator;
+pub use aggregator::AggregatorReducer;
+
+mod oracle;
+pub use oracle::OracleManipulationReducer;
+
+mod competition;
+pub use competition::LiquidationCompetitionReducer;
+
+mod nft;
+pub use nft::{NftArbitrageReducer, NftSweepReducer};
+
+mod allowance;
+pub use allowance::AllowanceExploitReducer;
+
+mod dust;
+pub use dust::{DustReducer, DEFAULT_MIN_PROFIT};
@@ -0,0 +1,317 @@
+use crate::{
+    addresses::{ChainConfig, NATIVE_ETH, WETH},
+    prices::signed_delta,
+    types::{
+        actions::{NftArbitrage, NftSweep, NftTrade},
+        Classification, Inspection, Provenance, TraceAddress,
+    },
+    Reducer,
+};
+use ethers::types::{Address, I256, U256};
+use indexmap::IndexMap;
+
+/// The NFT trades of the inspection, along with their index
+fn nft_trades(inspection: &Inspection) -> Vec<(usize, NftTrade)> {
+    inspection
+        .actions
+        .iter()
+        .enumerate()
+        .filter_map(|(i, a)| Some((i, a.as_action()?.nft_trade()?.clone())))
+        .collect()
+}
+
+#[derive(Clone, Debug)]
+/// Finds the NFTs which a transaction bought and then sold for more, e.g. on
+/// another marketplace. The matching key is the NFT's collection and token id,
+/// and both trades must be paid in the same token, counting ETH and WETH as
+/// the same one. The purchase is replaced with the `NftArbitrage` and the sale
+/// is pruned.
+///
+/// It must run before the `NftSweepReducer`, so that the NFTs which were
+/// flipped are not counted as swept.
+pub struct NftArbitrageReducer {
+    weth: Address,
+    native: Address,
+}
+
+impl Default for NftArbitrageReducer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NftArbitrageReducer {
+    /// Instantiates the reducer
+    pub fn new() -> Self {
+        Self {
+            weth: *WETH,
+            native: NATIVE_ETH,
+        }
+    }
+
+    /// Instantiates a reducer for `chain`
+    pub fn with_chain(chain: &ChainConfig) -> Self {
+        Self {
+            weth: chain.weth,
+            native: chain.native,
+        }
+    }
+}
+
+impl Reducer for NftArbitrageReducer {
+    fn reduce(&self, inspection: &mut Inspection) {
+        let currency = |trade: &NftTrade| canonical(trade.payment_token, self.native, self.weth);
+        let trades = nft_trades(inspection);
+        let mut sold = vec![false; trades.len()];
+
+        for (n, (i, bought)) in trades.iter().enumerate() {
+            if sold[n] {
+                continue;
+            }
+            let sale = trades.iter().enumerate().skip(n + 1).find(|(m, (_, t))| {
+                !sold[*m]
+                    && t.collection == bought.collection
+                    && t.token_id == bought.token_id
+                    && t.seller == bought.buyer
+                    && currency(t) == currency(bought)
+            });
+            let (m, (j, sale)) = match sale {
+                Some(inner) => inner,
+                None => continue,
+            };
+            // bought low and sold high
+            let profit = match signed_delta(sale.price, bought.price) {
+                Some(profit) if profit > I256::zero() => profit,
+                _ => continue,
+            };
+            sold[m] = true;
+
+            let arbitrage = NftArbitrage {
+                collection: bought.collection,
+                token_id: bought.token_id,
+                arbitrageur: bought.buyer,
+                payment_token: currency(bought),
+                bought: bought.clone(),
+                sold: sale.clone(),
+                profit,
+            };
+            let trace_address = TraceAddress::from(inspection.actions[*i].trace_address());
+            let provenance = Provenance::of([&inspection.actions[*i], &inspection.actions[*j]]);
+            inspection.actions[*i] =
+                Classification::new(arbitrage, trace_address).with_provenance(provenance);
+            inspection.actions[*j] = Classification::Prune;
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+/// Finds the sweeps of a transaction: the NFTs of the same collection which a
+/// buyer bought from several listings, which may be on several marketplaces.
+/// The purchases with ETH and WETH are summed together, and the ones with
+/// other tokens are swept separately. The first purchase is replaced with the
+/// `NftSweep` and the others are pruned.
+pub struct NftSweepReducer {
+    weth: Address,
+    native: Address,
+}
+
+impl Default for NftSweepReducer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NftSweepReducer {
+    /// Instantiates the reducer
+    pub fn new() -> Self {
+        Self {
+            weth: *WETH,
+            native: NATIVE_ETH,
+        }
+    }
+
+    /// Instantiates a reducer for `chain`
+    pub fn with_chain(chain: &ChainConfig) -> Self {
+        Self {
+            weth: chain.weth,
+            native: chain.native,
+        }
+    }
+}
+
+impl Reducer for NftSweepReducer {
+    fn reduce(&self, inspection: &mut Inspection) {
+        // (collection, buyer, currency) -> the purchases, in order
+        let mut sweeps: IndexMap<(Address, Address, Address), Vec<(usize, NftTrade)>> =
+            IndexMap::new();
+        for (i, trade) in nft_trades(inspection) {
+            let currency = canonical(trade.payment_token, self.native, self.weth);
+            sweeps
+                .entry((trade.collection, trade.buyer, currency))
+                .or_default()
+                .push((i, trade));
+        }
+
+        for ((collection, buyer, payment_token), purchases) in sweeps {
+            if purchases.len() < 2 {
+                continue;
+            }
+            let first = purchases[0].0;
+            let provenance = Provenance::of(purchases.iter().map(|(i, _)| &inspection.actions[*i]));
+            for (i, _) in purchases.iter().skip(1) {
+                inspection.actions[*i] = Classification::Prune;
+            }
+            let trades = purchases
+                .into_iter()
+                .map(|(_, trade)| trade)
+                .collect::<Vec<_>>();
+            let sweep = NftSweep {
+                collection,
+                buyer,
+                count: trades.len(),
+                payment_token,
+                total_paid: trades.iter().fold(U256::zero(), |total, trade| {
+                    total.saturating_add(trade.price)
+                }),
+                trades,
+            };
+            let trace_address = TraceAddress::from(inspection.actions[first].trace_address());
+            inspection.actions[first] =
+                Classification::new(sweep, trace_address).with_provenance(provenance);
+        }
+    }
+}
+
+/// Maps the native token to the wrapped one, so that the NFTs paid with either
+/// are priced in the same token
+fn canonical(token: Address, native: Address, weth: Address) -> Address {
+    if token == native {
+        weth
+    } else {
+        token
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+
+    fn trade(
+        (collection, token_id): (Address, u64),
+        (seller, buyer): (Address, Address),
+        (payment_token, price): (Address, u64),
+    ) -> NftTrade {
+        NftTrade {
+            collection,
+            token_id: token_id.into(),
+            payment_token,
+            price: price.into(),
+            buyer,
+            seller,
+        }
+    }
+
+    fn inspection(trades: &[NftTrade]) -> Inspection {
+        mk_inspection(
+            trades
+                .iter()
+                .enumerate()
+                .map(|(i, t)| Classification::new(t.clone(), vec![i]))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn sweeps_a_collection_with_eth_and_weth() {
+        let addrs = addrs();
+        let (bot, seller1, seller2, seller3, punks, apes, usdc) = (
+            addrs[0], addrs[1], addrs[2], addrs[3], addrs[4], addrs[5], addrs[6],
+        );
+        let mut inspection = inspection(&[
+            trade((punks, 1), (seller1, bot), (NATIVE_ETH, 100)),
+            // another collection
+            trade((apes, 1), (seller1, bot), (NATIVE_ETH, 500)),
+            // a listing on another marketplace, which takes WETH
+            trade((punks, 2), (seller2, bot), (*WETH, 110)),
+            trade((punks, 3), (seller3, bot), (NATIVE_ETH, 120)),
+            // bought with another token
+            trade((punks, 4), (seller3, bot), (usdc, 1000)),
+        ]);
+        NftSweepReducer::new().reduce(&mut inspection);
+        inspection.prune();
+
+        let sweeps = inspection
+            .known_actions()
+            .filter_map(|a| a.nft_sweep())
+            .collect::<Vec<_>>();
+        assert_eq!(sweeps.len(), 1);
+        assert_eq!(
+            (sweeps[0].collection, sweeps[0].buyer, sweeps[0].count),
+            (punks, bot, 3)
+        );
+        assert_eq!(sweeps[0].payment_token, *WETH);
+        assert_eq!(sweeps[0].total_paid, 330.into());
+        // attached to the first purchase, and the others are left alone
+        assert_eq!(inspection.actions[0].trace_address(), [0]);
+        let left = inspection
+            .known_actions()
+            .filter_map(|a| a.nft_trade())
+            .map(|t| t.collection)
+            .collect::<Vec<_>>();
+        assert_eq!(left, vec![apes, punks]);
+    }
+
+    #[test]
+    fn flips_an_nft_across_marketplaces() {
+        let addrs = addrs();
+        let (bot, lister, bidder, punks) = (addrs[0], addrs[1], addrs[2], addrs[3]);
+        let reduce = |trades: &[NftTrade]| {
+            let mut inspection = inspection(trades);
+            NftArbitrageReducer::new().reduce(&mut inspection);
+            NftSweepReducer::new().reduce(&mut inspection);
+            inspection.prune();
+            inspection
+        };
+
+        // bought with ETH from a listing, and sold to a WETH bid
+        let bought = trade((punks, 7), (lister, bot), (NATIVE_ETH, 100));
+        let sold = trade((punks, 7), (bot, bidder), (*WETH, 130));
+        let inspection = reduce(&[
+            bought.clone(),
+            trade((punks, 8), (lister, bot), (NATIVE_ETH, 100)),
+            sold.clone(),
+        ]);
+        let arbs = inspection
+            .known_actions()
+            .filter_map(|a| a.nft_arbitrage())
+            .cloned()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            arbs,
+            vec![NftArbitrage {
+                collection: punks,
+                token_id: 7.into(),
+                arbitrageur: bot,
+                payment_token: *WETH,
+                bought,
+                sold,
+                profit: 30.into(),
+            }]
+        );
+        // the flipped NFT is not part of a sweep with the other one
+        assert_eq!(inspection.known_actions().count(), 2);
+        assert!(inspection.known_actions().all(|a| a.nft_sweep().is_none()));
+
+        // sold at a loss, or for another token
+        for sale in [(*WETH, 90), (addrs[4], 130)].iter() {
+            let inspection = reduce(&[
+                trade((punks, 7), (lister, bot), (NATIVE_ETH, 100)),
+                trade((punks, 7), (bot, bidder), *sale),
+            ]);
+            assert!(inspection
+                .known_actions()
+                .all(|a| a.nft_arbitrage().is_none()));
+        }
+    }
+}
@@ -0,0 +1,329 @@
+use crate::{
+    addresses::PROTOCOLS,
+    is_nested,
+    types::{
+        actions::{OracleManipulation, SpecificAction},
+        Classification, Inspection, Protocol, Provenance,
+    },
+    Reducer,
+};
+use ethers::types::{Address, CallType};
+
+#[derive(Clone, Debug)]
+/// Finds the price oracle manipulations of a transaction: a flash loan funds a
+/// swap which moves a pool's spot price, then a victim contract which reads
+/// that price is borrowed from, minted from or liquidated on, before a swap in
+/// the opposite direction moves the price back.
+///
+/// Large round trips on a pool are otherwise common in arbitrages, so the
+/// victim interaction must happen between the 2 swaps. It is either a
+/// liquidation, or a call to a contract which then calls into the pool, e.g.
+/// to read its reserves. The swap is replaced with the `OracleManipulation`
+/// and the reverse swap is pruned, so that they are not also found to be an
+/// arbitrage.
+pub struct OracleManipulationReducer;
+
+impl Default for OracleManipulationReducer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OracleManipulationReducer {
+    /// Instantiates the reducer
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Reducer for OracleManipulationReducer {
+    fn reduce(&self, inspection: &mut Inspection) {
+        let actions = &inspection.actions;
+        let trades = actions
+            .iter()
+            .enumerate()
+            .filter_map(|(i, a)| Some((i, a.as_action()?.trade()?)))
+            .collect::<Vec<_>>();
+
+        let mut manipulations = Vec::new();
+        for (n, (i, swap)) in trades.iter().enumerate() {
+            // already used as the reverse swap of an earlier manipulation
+            if manipulations.iter().any(|(_, j, _)| j == i) {
+                continue;
+            }
+            // the flash loans precede the actions they funded
+            let flash_loan = actions[..*i]
+                .iter()
+                .filter_map(|a| a.as_action()?.flash_loan())
+                .last();
+            let flash_loan = match flash_loan {
+                Some(inner) => inner,
+                None => continue,
+            };
+
+            let pool = swap.t1.to;
+            let reverse = trades.iter().skip(n + 1).find(|(_, t)| {
+                t.t1.to == pool && t.t1.token == swap.t2.token && t.t2.token == swap.t1.token
+            });
+            let (j, reverse_swap) = match reverse {
+                Some(inner) => inner,
+                None => continue,
+            };
+
+            let reverse_address = actions[*j].trace_address();
+            let victim =
+                (*i + 1..*j).find_map(|k| victim(inspection, actions, k, pool, reverse_address));
+            let (victim, victim_protocol) = match victim {
+                Some(inner) => inner,
+                None => continue,
+            };
+
+            let manipulation = OracleManipulation {
+                pool,
+                flash_loan: flash_loan.clone(),
+                swap: (*swap).clone(),
+                reverse_swap: (*reverse_swap).clone(),
+                victim,
+                victim_protocol,
+            };
+            let provenance = Provenance::of([&actions[*i], &actions[*j]]);
+            let manipulation = Classification::new(manipulation, actions[*i].trace_address())
+                .with_provenance(provenance);
+            manipulations.push((*i, *j, manipulation));
+        }
+
+        for (i, j, manipulation) in manipulations {
+            inspection.actions[i] = manipulation;
+            inspection.actions[j] = Classification::Prune;
+        }
+    }
+}
+
+/// Returns the victim contract and protocol of the `k`th action, if it is an
+/// interaction with a contract which read the spot price of `pool`
+fn victim(
+    inspection: &Inspection,
+    actions: &[Classification],
+    k: usize,
+    pool: Address,
+    reverse_address: &[usize],
+) -> Option<(Option<Address>, Option<Protocol>)> {
+    match &actions[k] {
+        Classification::Known(action) => match action.as_ref() {
+            SpecificAction::Liquidation(_) | SpecificAction::ProfitableLiquidation(_) => {
+                let protocol = inspection.protocols.iter().find(|protocol| {
+                    matches!(
+                        protocol,
+                        Protocol::Aave
+                            | Protocol::Compound
+                            | Protocol::CompoundV3
+                            | Protocol::Cream
+                            | Protocol::IronBank
+                            | Protocol::MakerDAO
+                    )
+                });
+                Some((None, protocol.cloned()))
+            }
+            _ => None,
+        },
+        Classification::Unknown(calltrace) => {
+            let call = calltrace.as_ref();
+            // the call which made the reverse swap, e.g. a router's, reads the
+            // pool's reserves too
+            if call.call_type != CallType::Call
+                || call.to == pool
+                || is_nested(&calltrace.trace_address, reverse_address)
+            {
+                return None;
+            }
+            let reads_pool = actions.iter().skip(k + 1).any(|a| {
+                matches!(a.as_call(), Some(inner) if inner.as_ref().to == pool)
+                    && is_nested(&calltrace.trace_address, a.trace_address())
+            });
+            if reads_pool {
+                Some((Some(call.to), PROTOCOLS.get(&call.to).cloned()))
+            } else {
+                None
+            }
+        }
+        Classification::Prune => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_helpers::*,
+        types::actions::{FlashLoan, Liquidation, Trade, Transfer},
+    };
+    use ethers::{abi::Token, types::Bytes};
+
+    fn trade(usr: Address, pool: Address, t1: (Address, u64), t2: (Address, u64)) -> Trade {
+        Trade::new(
+            Transfer {
+                from: usr,
+                to: pool,
+                amount: t1.1.into(),
+                token: t1.0,
+            },
+            Transfer {
+                from: pool,
+                to: usr,
+                amount: t2.1.into(),
+                token: t2.0,
+            },
+        )
+    }
+
+    fn flash_loan(token: Address) -> Classification {
+        Classification::new(
+            FlashLoan {
+                provider: Protocol::Aave,
+                token,
+                amount: 1000.into(),
+                fee: 1.into(),
+            },
+            vec![0, 0],
+        )
+    }
+
+    /// A flash loan funded round trip on `pool`, with `victim` calls in between
+    fn attack(
+        bot: Address,
+        pool: Address,
+        (weth, dai): (Address, Address),
+        victim: Vec<Classification>,
+    ) -> Vec<Classification> {
+        let mut actions = vec![
+            flash_loan(weth),
+            Classification::new(
+                trade(bot, pool, (weth, 1000), (dai, 200_000)),
+                vec![0, 1, 0],
+            ),
+        ];
+        actions.extend(victim);
+        actions.push(Classification::new(
+            trade(bot, pool, (dai, 200_000), (weth, 990)),
+            vec![0, 1, 3],
+        ));
+        actions
+    }
+
+    fn manipulations(inspection: &Inspection) -> Vec<OracleManipulation> {
+        inspection
+            .known_actions()
+            .filter_map(|a| a.oracle_manipulation())
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn manipulates_a_lending_market() {
+        let addrs = addrs();
+        let (bot, pool, market, weth, dai) = (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4]);
+
+        // borrows against collateral priced at the pool's spot price
+        let mut inspection = mk_inspection(attack(
+            bot,
+            pool,
+            (weth, dai),
+            vec![
+                mk_call(
+                    bot,
+                    market,
+                    calldata("borrow(uint256)", &[Token::Uint(500.into())]),
+                    vec![0, 1, 1],
+                ),
+                mk_call(
+                    market,
+                    pool,
+                    calldata("getReserves()", &[]),
+                    vec![0, 1, 1, 0],
+                ),
+            ],
+        ));
+        OracleManipulationReducer::new().reduce(&mut inspection);
+        inspection.prune();
+
+        let found = manipulations(&inspection);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].pool, pool);
+        assert_eq!(found[0].flash_loan.token, weth);
+        assert_eq!(found[0].swap.t2.amount, 200_000.into());
+        assert_eq!(found[0].reverse_swap.t2.amount, 990.into());
+        assert_eq!(found[0].victim, Some(market));
+        assert_eq!(found[0].victim_protocol, None);
+        // the reverse swap is part of the manipulation
+        assert_eq!(inspection.trades().count(), 0);
+
+        // a liquidation of a position priced at the spot price
+        let mut inspection = mk_inspection(attack(
+            bot,
+            pool,
+            (weth, dai),
+            vec![Classification::new(
+                Liquidation {
+                    sent_token: dai,
+                    sent_amount: 1000.into(),
+                    received_token: weth,
+                    received_amount: 10.into(),
+                    from: bot,
+                    liquidated_user: addrs[5],
+                    auction: None,
+                },
+                vec![0, 1, 1],
+            )],
+        ));
+        inspection.protocols.insert(Protocol::Compound);
+        OracleManipulationReducer::new().reduce(&mut inspection);
+
+        let found = manipulations(&inspection);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].victim, None);
+        assert_eq!(found[0].victim_protocol, Some(Protocol::Compound));
+    }
+
+    #[test]
+    fn needs_the_victim_between_the_swaps() {
+        let addrs = addrs();
+        let (bot, pool, market, router, weth, dai) =
+            (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4], addrs[5]);
+        let borrow = |trace_address: Vec<usize>| {
+            let mut read = trace_address.clone();
+            read.push(0);
+            vec![
+                mk_call(bot, market, Bytes::default(), trace_address),
+                mk_call(market, pool, Bytes::default(), read),
+            ]
+        };
+
+        // the market is only called after the reverse swap
+        let mut actions = attack(bot, pool, (weth, dai), vec![]);
+        actions.extend(borrow(vec![0, 1, 4]));
+        let mut inspection = mk_inspection(actions.clone());
+        OracleManipulationReducer::new().reduce(&mut inspection);
+        assert_eq!(inspection.actions, actions);
+
+        // the reverse swap is routed, and the router reads the pool's reserves
+        let actions = attack(
+            bot,
+            pool,
+            (weth, dai),
+            vec![
+                mk_call(bot, router, Bytes::default(), vec![0, 1]),
+                mk_call(router, pool, Bytes::default(), vec![0, 1, 2]),
+            ],
+        );
+        let mut inspection = mk_inspection(actions.clone());
+        OracleManipulationReducer::new().reduce(&mut inspection);
+        assert_eq!(inspection.actions, actions);
+
+        // without a flash loan
+        let mut actions = attack(bot, pool, (weth, dai), borrow(vec![0, 1, 1]));
+        actions.remove(0);
+        let mut inspection = mk_inspection(actions.clone());
+        OracleManipulationReducer::new().reduce(&mut inspection);
+        assert_eq!(inspection.actions, actions);
+    }
+}
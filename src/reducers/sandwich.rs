@@ -0,0 +1,506 @@
+use super::arbitrage::borrowed;
+use crate::{
+    addresses::{ChainConfig, PROTOCOLS, UNIVERSAL_ROUTER, WETH},
+    prices::signed_delta,
+    types::{
+        actions::{PartialSandwich, Sandwich, Trade, VictimTrade},
+        Classification, Inspection, Provenance, Status,
+    },
+    BlockReducer,
+};
+use ethers::types::{Address, U256};
+use std::collections::HashSet;
+
+#[derive(Clone, Debug)]
+/// Finds trades which were front-run and back-run by the same searcher on the
+/// same pool, across the transactions of a block. All the trades made on the
+/// pool in the frontrun's direction between the two legs are its victims, while
+/// the trades on other pools or in the other direction are left alone.
+///
+/// Frontruns whose backrun reverted or is missing from the block are reported
+/// as partial sandwiches, since the searcher was left holding what it bought.
+pub struct SandwichReducer {
+    /// The chain's wrapped ETH, whose flash loans also fund frontruns in ETH
+    weth: Address,
+    /// The chain's known routers and pools, whose trades are not a bot's
+    known: HashSet<Address>,
+}
+
+impl Default for SandwichReducer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SandwichReducer {
+    pub fn new() -> Self {
+        Self::with_weth(*WETH)
+    }
+
+    /// Instantiates a reducer for a chain whose wrapped ETH is `weth`
+    pub fn with_weth(weth: Address) -> Self {
+        let known = PROTOCOLS.keys().chain(UNIVERSAL_ROUTER.iter()).copied();
+        Self {
+            weth,
+            known: known.collect(),
+        }
+    }
+
+    /// Instantiates a reducer for `chain`
+    pub fn with_chain(chain: &ChainConfig) -> Self {
+        let known = chain.protocols.keys().chain(chain.universal_routers.iter());
+        Self {
+            weth: chain.weth,
+            known: known.copied().collect(),
+        }
+    }
+}
+
+impl BlockReducer for SandwichReducer {
+    fn reduce_block(&self, inspections: &mut [Inspection]) {
+        for i in 0..inspections.len() {
+            for a in 0..inspections[i].actions.len() {
+                let frontrun = match inspections[i].actions[a]
+                    .as_action()
+                    .and_then(|a| a.trade())
+                {
+                    Some(inner) => inner.clone(),
+                    None => continue,
+                };
+
+                // the frontrun may be funded by flash loans made before it
+                let capital = frontrun.t1.amount.saturating_sub(borrowed(
+                    &inspections[i].actions,
+                    a,
+                    frontrun.t1.token,
+                    self.weth,
+                ));
+                let trace_address = inspections[i].actions[a].trace_address();
+                match find_sandwich(inspections, i, frontrun, capital, &self.known) {
+                    Some(Match::Sandwich(sandwich, (k, r))) => {
+                        let provenance = Provenance::of([
+                            &inspections[i].actions[a],
+                            &inspections[k].actions[r],
+                        ]);
+                        inspections[i].actions[a] = Classification::new(*sandwich, trace_address)
+                            .with_provenance(provenance);
+                        inspections[k].actions[r] = Classification::Prune;
+                    }
+                    Some(Match::Partial(partial)) => {
+                        let provenance = Provenance::of([&inspections[i].actions[a]]);
+                        inspections[i].actions[a] = Classification::new(*partial, trace_address)
+                            .with_provenance(provenance);
+                    }
+                    None => {}
+                }
+            }
+        }
+    }
+}
+
+/// What a frontrun turned out to be part of
+enum Match {
+    /// A sandwich, and the index of the backrun's action
+    Sandwich(Box<Sandwich>, (usize, usize)),
+    Partial(Box<PartialSandwich>),
+}
+
+/// Looks for the victims and the backrun of the frontrun made in the `i`th
+/// inspection. The victims may span several transactions before the backrun.
+///
+/// If the searcher's backrun reverted, or it never made one, the frontrun is
+/// a partial sandwich instead. Since any trade is followed by others in the
+/// same direction, a frontrun without a backrun is only reported if it was
+/// made through a contract which is not one of the `known` routers, like bots
+/// do, and its first victim is in the very next transaction.
+fn find_sandwich(
+    inspections: &[Inspection],
+    i: usize,
+    frontrun: Trade,
+    capital: U256,
+    known: &HashSet<Address>,
+) -> Option<Match> {
+    let pool = frontrun.t1.to;
+    let searcher = &inspections[i];
+    let is_searcher = |inspection: &Inspection, trader| {
+        inspection.from == searcher.from || trader == frontrun.t1.from
+    };
+    let partial = |victims, backrun_missing| {
+        Match::Partial(Box::new(PartialSandwich {
+            pool,
+            frontrun_tx: searcher.hash,
+            frontrun: frontrun.clone(),
+            victims,
+            backrun_missing,
+        }))
+    };
+
+    let mut victims = Vec::new();
+    let mut first_victim = None;
+    for (k, inspection) in inspections.iter().enumerate().skip(i + 1) {
+        let trades = inspection
+            .actions
+            .iter()
+            .enumerate()
+            .filter_map(|(r, a)| Some((r, a.as_action()?.trade()?)))
+            .filter(|(_, t)| t.t1.to == pool && t.t2.from == pool);
+
+        for (r, trade) in trades {
+            let same_direction =
+                trade.t1.token == frontrun.t1.token && trade.t2.token == frontrun.t2.token;
+            let reverse =
+                trade.t1.token == frontrun.t2.token && trade.t2.token == frontrun.t1.token;
+
+            if is_searcher(inspection, trade.t1.from) {
+                if reverse && !victims.is_empty() {
+                    if inspection.status == Status::Reverted {
+                        return Some(partial(victims, false));
+                    }
+                    let profit = signed_delta(trade.t2.amount, frontrun.t1.amount)?;
+                    let sandwich = Sandwich {
+                        pool,
+                        frontrun_tx: searcher.hash,
+                        backrun_tx: inspection.hash,
+                        token: frontrun.t1.token,
+                        frontrun,
+                        victims,
+                        backrun: trade.clone(),
+                        profit,
+                        capital,
+                        searcher: searcher.searcher(),
+                    };
+                    return Some(Match::Sandwich(Box::new(sandwich), (k, r)));
+                }
+            } else if same_direction {
+                first_victim.get_or_insert(k);
+                victims.push(VictimTrade {
+                    tx: inspection.hash,
+                    trade: trade.clone(),
+                });
+            }
+        }
+    }
+
+    let from_bot = frontrun.t1.from != searcher.from && !known.contains(&frontrun.t1.from);
+    if from_bot && searcher.status != Status::Reverted && first_victim == Some(i + 1) {
+        return Some(partial(victims, true));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+    use crate::{
+        prices::PriceOracle,
+        types::{
+            actions::{FlashLoan, Transfer},
+            Protocol,
+        },
+    };
+    use ethers::types::{Address, I256};
+
+    fn trade(usr: Address, pool: Address, t1: (Address, u64), t2: (Address, u64)) -> Trade {
+        Trade::new(
+            Transfer {
+                from: usr,
+                to: pool,
+                amount: t1.1.into(),
+                token: t1.0,
+            },
+            Transfer {
+                from: pool,
+                to: usr,
+                amount: t2.1.into(),
+                token: t2.0,
+            },
+        )
+    }
+
+    #[test]
+    fn sandwich_with_many_victims() {
+        let addrs = addrs();
+        let (searcher, bot, alice, bob, pool) = (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4]);
+        let (weth, dai, other) = (addrs[5], addrs[6], addrs[7]);
+
+        let frontrun = trade(bot, pool, (weth, 100), (dai, 1000));
+        let victim1 = trade(alice, pool, (weth, 50), (dai, 450));
+        // an unrelated trade in between
+        let unrelated = trade(alice, addrs[8], (weth, 50), (other, 450));
+        let victim2 = trade(bob, pool, (weth, 10), (dai, 85));
+        let backrun = trade(bot, pool, (dai, 1000), (weth, 104));
+
+        let mut inspections = vec![
+            mk_tx(0, searcher, vec![frontrun.clone()], None),
+            mk_tx(1, alice, vec![victim1.clone(), unrelated], None),
+            mk_tx(2, addrs[9], Vec::<Trade>::new(), None),
+            mk_tx(3, bob, vec![victim2.clone()], None),
+            mk_tx(4, searcher, vec![backrun.clone()], None),
+        ];
+        SandwichReducer::new().reduce_block(&mut inspections);
+
+        let sandwich = inspections[0].known()[0]
+            .as_ref()
+            .sandwich()
+            .cloned()
+            .unwrap();
+        assert_eq!(sandwich.pool, pool);
+        assert_eq!(sandwich.frontrun, frontrun);
+        let victims = sandwich
+            .victims
+            .iter()
+            .map(|victim| (victim.tx, victim.trade.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            victims,
+            vec![
+                (inspections[1].hash, victim1),
+                (inspections[3].hash, victim2)
+            ]
+        );
+        assert_eq!(sandwich.backrun, backrun);
+        assert_eq!(sandwich.token, weth);
+        assert_eq!(sandwich.profit, I256::from(4));
+        assert_eq!(sandwich.frontrun_tx, inspections[0].hash);
+        assert_eq!(sandwich.backrun_tx, inspections[4].hash);
+        assert_eq!(sandwich.searcher.eoa, searcher);
+        assert!(!sandwich.is_atomic());
+        let bundled = Sandwich {
+            backrun_tx: sandwich.frontrun_tx,
+            ..sandwich
+        };
+        assert!(bundled.is_atomic());
+        assert_eq!(inspections[4].actions, vec![Classification::Prune]);
+        // the victims' trades stay as they are
+        assert_eq!(inspections[1].known().len(), 2);
+    }
+
+    #[test]
+    fn needs_a_victim_and_a_backrun() {
+        let addrs = addrs();
+        let (searcher, alice, pool, weth, dai) = (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4]);
+
+        let frontrun = trade(searcher, pool, (weth, 100), (dai, 1000));
+        let victim = trade(alice, pool, (weth, 50), (dai, 450));
+        // the victim sells into the pool, which does not worsen the price
+        let reverse = trade(alice, pool, (dai, 450), (weth, 50));
+        let backrun = trade(searcher, pool, (dai, 1000), (weth, 104));
+        // backrun on another pool
+        let elsewhere = trade(searcher, addrs[5], (dai, 1000), (weth, 104));
+
+        for block in [
+            vec![
+                mk_tx(0, searcher, vec![frontrun.clone()], None),
+                mk_tx(1, searcher, vec![backrun.clone()], None),
+            ],
+            vec![
+                mk_tx(2, searcher, vec![frontrun.clone()], None),
+                mk_tx(3, alice, vec![reverse], None),
+                mk_tx(4, searcher, vec![backrun], None),
+            ],
+            vec![
+                mk_tx(5, searcher, vec![frontrun], None),
+                mk_tx(6, alice, vec![victim], None),
+                mk_tx(7, searcher, vec![elsewhere], None),
+            ],
+        ] {
+            let mut inspections = block.clone();
+            SandwichReducer::new().reduce_block(&mut inspections);
+            let actions = inspections.iter().map(|i| &i.actions).collect::<Vec<_>>();
+            let expected = block.iter().map(|i| &i.actions).collect::<Vec<_>>();
+            assert_eq!(actions, expected);
+        }
+    }
+
+    #[test]
+    fn extracts_value_from_every_victim() {
+        let addrs = addrs();
+        let (searcher, alice, bob, carol, pool, dai) =
+            (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4], addrs[5]);
+
+        // a pool with 1000 WETH and 1M DAI, whose trades follow x * y = k
+        let frontrun = trade(searcher, pool, (*WETH, 100), (dai, 90661));
+        let victim1 = trade(alice, pool, (*WETH, 10), (dai, 8167));
+        let victim2 = trade(bob, pool, (*WETH, 20), (dai, 15902));
+        // the same tokens on another pool, and the other direction on the
+        // sandwiched one
+        let elsewhere = trade(carol, addrs[6], (*WETH, 20), (dai, 19000));
+        let reverse = trade(carol, pool, (dai, 1000), (*WETH, 1));
+        let backrun = trade(searcher, pool, (dai, 90661), (*WETH, 104));
+        let expected = vec![victim1.clone(), victim2.clone()];
+        let mut inspections = vec![
+            mk_tx(0, searcher, vec![frontrun], None),
+            mk_tx(1, alice, vec![victim1], None),
+            mk_tx(2, carol, vec![elsewhere, reverse], None),
+            mk_tx(3, bob, vec![victim2], None),
+            mk_tx(4, searcher, vec![backrun], None),
+        ];
+        SandwichReducer::new().reduce_block(&mut inspections);
+
+        let sandwich = inspections[0].known()[0]
+            .as_ref()
+            .sandwich()
+            .cloned()
+            .unwrap();
+        let victims = sandwich
+            .victims
+            .iter()
+            .map(|victim| victim.trade.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(victims, expected);
+        assert_eq!(sandwich.profit, I256::from(4));
+
+        // without the frontrun, the victims would have gotten 9871 and 19169 DAI
+        let (weth_reserve, dai_reserve) = (U256::from(1000), U256::from(1_000_000));
+        assert_eq!(
+            sandwich.counterfactual_outputs(weth_reserve, dai_reserve),
+            Some(vec![9871.into(), 19169.into()])
+        );
+        assert_eq!(
+            sandwich.extracted_value(weth_reserve, dai_reserve),
+            Some((9871 - 8167 + 19169 - 15902).into())
+        );
+    }
+
+    #[test]
+    fn frontruns_without_a_backrun_are_partial_sandwiches() {
+        let addrs = addrs();
+        let (searcher, bot, alice, bob, pool, weth, dai) = (
+            addrs[0], addrs[1], addrs[2], addrs[3], addrs[4], addrs[5], addrs[6],
+        );
+
+        // the searcher trades through its contract
+        let frontrun = trade(bot, pool, (weth, 100), (dai, 1000));
+        let victim = trade(alice, pool, (weth, 50), (dai, 450));
+        let backrun = trade(bot, pool, (dai, 1000), (weth, 104));
+        let front = mk_tx(0, searcher, vec![frontrun.clone()], None);
+        let victims = mk_tx(1, alice, vec![victim], None);
+        let mut reverted = mk_tx(2, searcher, vec![backrun], None);
+        reverted.status = Status::Reverted;
+
+        for (mut inspections, backrun_missing) in [
+            (vec![front.clone(), victims.clone()], true),
+            (vec![front.clone(), victims.clone(), reverted], false),
+        ] {
+            SandwichReducer::new().reduce_block(&mut inspections);
+            let partial = inspections[0].known()[0]
+                .as_ref()
+                .partial_sandwich()
+                .cloned()
+                .unwrap();
+            assert_eq!(partial.pool, pool);
+            assert_eq!(partial.frontrun, frontrun);
+            assert_eq!(partial.frontrun_tx, inspections[0].hash);
+            assert_eq!(partial.victims.len(), 1);
+            assert_eq!(partial.victims[0].tx, inspections[1].hash);
+            assert_eq!(partial.backrun_missing, backrun_missing);
+            // the reverted backrun stays as it is
+            assert_eq!(inspections.last().unwrap().known().len(), 1);
+        }
+
+        // a trade from an EOA or through a known router, or one whose next tx
+        // is not a victim, is just followed by other trades
+        let mut from_eoa = front.clone();
+        from_eoa.from = bot;
+        let router = *PROTOCOLS.keys().next().unwrap();
+        let routed = mk_tx(
+            3,
+            searcher,
+            vec![trade(router, pool, (weth, 100), (dai, 1000))],
+            None,
+        );
+        for block in [
+            vec![from_eoa, victims.clone()],
+            vec![routed, victims.clone()],
+            vec![front, mk_tx(4, bob, Vec::<Trade>::new(), None), victims],
+        ] {
+            let mut inspections = block.clone();
+            SandwichReducer::new().reduce_block(&mut inspections);
+            let actions = inspections.iter().map(|i| &i.actions).collect::<Vec<_>>();
+            let expected = block.iter().map(|i| &i.actions).collect::<Vec<_>>();
+            assert_eq!(actions, expected);
+        }
+    }
+
+    /// Returns the same reserves for every pool, as `(WETH, DAI)`
+    struct Reserves(U256, U256);
+
+    impl PriceOracle for Reserves {
+        fn price_at(&self, _: Address, _: u64) -> Option<U256> {
+            None
+        }
+
+        fn reserves_at(
+            &self,
+            _: Address,
+            token_in: Address,
+            _: Address,
+            _: u64,
+        ) -> Option<(U256, U256)> {
+            if token_in == *WETH {
+                Some((self.0, self.1))
+            } else {
+                Some((self.1, self.0))
+            }
+        }
+    }
+
+    #[test]
+    fn victim_slippage_and_roi() {
+        let addrs = addrs();
+        let (searcher, alice, pool, dai) = (addrs[0], addrs[1], addrs[2], addrs[3]);
+
+        // a pool with 1000 WETH and 1M DAI, whose trades follow x * y = k
+        let frontrun = trade(searcher, pool, (*WETH, 100), (dai, 90661));
+        let victim = trade(alice, pool, (*WETH, 10), (dai, 8167));
+        let backrun = trade(searcher, pool, (dai, 90661), (*WETH, 101));
+        let block = vec![
+            mk_tx(0, searcher, vec![frontrun.clone()], None),
+            mk_tx(1, alice, vec![victim], None),
+            mk_tx(2, searcher, vec![backrun.clone()], None),
+        ];
+
+        let mut inspections = block.clone();
+        SandwichReducer::new().reduce_block(&mut inspections);
+        let sandwich = inspections[0].known()[0]
+            .as_ref()
+            .sandwich()
+            .cloned()
+            .unwrap();
+        assert_eq!(sandwich.capital, 100.into());
+        assert!((sandwich.roi().unwrap() - 0.01).abs() < 1e-9);
+
+        // without the frontrun, the victim would have gotten 9871 DAI
+        let (weth_reserve, dai_reserve) = (U256::from(1000), U256::from(1_000_000));
+        assert_eq!(
+            sandwich.counterfactual_outputs(weth_reserve, dai_reserve),
+            Some(vec![9871.into()])
+        );
+        let slippage = sandwich
+            .victim_slippage(&Reserves(weth_reserve, dai_reserve), 1)
+            .unwrap();
+        assert_eq!(slippage.len(), 1);
+        assert!((slippage[0] - (1.0 - 8167.0 / 9871.0)).abs() < 1e-9);
+
+        // a flash loaned frontrun puts nothing at risk
+        let mut inspections = block;
+        let loan = FlashLoan {
+            provider: Protocol::Aave,
+            token: *WETH,
+            amount: 100.into(),
+            fee: 0.into(),
+        };
+        inspections[0]
+            .actions
+            .insert(0, Classification::new(loan, vec![]));
+        SandwichReducer::new().reduce_block(&mut inspections);
+        let sandwich = inspections[0].known()[1]
+            .as_ref()
+            .sandwich()
+            .cloned()
+            .unwrap();
+        assert_eq!(sandwich.capital, 0.into());
+        assert!(sandwich.roi().is_none());
+    }
+}
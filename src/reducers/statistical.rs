@@ -0,0 +1,190 @@
+use crate::{
+    addresses::{parse_address, MARKET_MAKERS, USDC, WETH},
+    types::{
+        actions::{StatisticalArbitrageCandidate, Trade, Transfer},
+        Classification, Inspection, Provenance,
+    },
+    Reducer,
+};
+use ethers::types::{Address, U256};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Clone, Debug)]
+/// Flags the trades which are likely the on-chain leg of an arbitrage against a
+/// centralized exchange: the large trades of known market makers which only go
+/// one way in their transaction. Since the other leg is not visible on chain,
+/// this is a heuristic with false positives, e.g. a market maker rebalancing
+/// its inventory looks the same, and its candidates must not be counted as
+/// MEV without further evidence.
+///
+/// It is not part of the default reducers, and must run after the
+/// `ArbitrageReducer` so that the trades of atomic arbitrages are left alone.
+/// The trades which it flags are replaced with their candidate, so that they
+/// are no longer trades to the reducers which run after it.
+pub struct StatisticalArbitrageReducer {
+    market_makers: HashSet<Address>,
+    /// The amount of each token above which a trade is large. The trades of
+    /// the other tokens are never flagged.
+    min_amounts: HashMap<Address, U256>,
+}
+
+impl Default for StatisticalArbitrageReducer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StatisticalArbitrageReducer {
+    /// Instantiates the reducer with the known market makers, for the trades
+    /// of at least 10 WETH or 20k of a USD stablecoin
+    pub fn new() -> Self {
+        let usd = |decimals| U256::from(20_000) * U256::exp10(decimals);
+        let min_amounts = vec![
+            (*WETH, U256::exp10(19)),
+            (*USDC, usd(6)),
+            // USDT
+            (
+                parse_address("0xdac17f958d2ee523a2206206994597c13d831ec7"),
+                usd(6),
+            ),
+            // DAI
+            (
+                parse_address("0x6B175474E89094C44Da98b954EedeAC495271d0F"),
+                usd(18),
+            ),
+        ];
+        Self {
+            market_makers: MARKET_MAKERS.clone(),
+            min_amounts: min_amounts.into_iter().collect(),
+        }
+    }
+
+    /// Adds a market maker whose trades are flagged
+    pub fn with_market_maker(mut self, address: Address) -> Self {
+        self.market_makers.insert(address);
+        self
+    }
+
+    /// Sets the amount of `token` above which a trade is large
+    pub fn with_min_amount(mut self, token: Address, amount: U256) -> Self {
+        self.min_amounts.insert(token, amount);
+        self
+    }
+
+    fn is_large(&self, transfer: &Transfer) -> bool {
+        matches!(self.min_amounts.get(&transfer.token), Some(min) if transfer.amount >= *min)
+    }
+
+    /// The market maker which made the trade, which may have traded from its
+    /// contract or directly from its EOA
+    fn market_maker(&self, inspection: &Inspection, trade: &Trade) -> Option<Address> {
+        [
+            trade.t1.from,
+            trade.t2.to,
+            inspection.contract,
+            inspection.from,
+        ]
+        .iter()
+        .find(|address| self.market_makers.contains(address))
+        .cloned()
+    }
+}
+
+impl Reducer for StatisticalArbitrageReducer {
+    fn reduce(&self, inspection: &mut Inspection) {
+        let trades = inspection
+            .actions
+            .iter()
+            .enumerate()
+            .filter_map(|(i, a)| Some((i, a.as_action()?.trade()?.clone())))
+            .collect::<Vec<_>>();
+
+        for (i, trade) in trades.iter() {
+            if !self.is_large(&trade.t1) && !self.is_large(&trade.t2) {
+                continue;
+            }
+            let mm_address = match self.market_maker(inspection, trade) {
+                Some(inner) => inner,
+                None => continue,
+            };
+            // a trade which is sold back in the same transaction does not take
+            // a position, whether it was a profitable cycle or not
+            let directional = !trades.iter().any(|(_, other)| {
+                other.t1.token == trade.t2.token && other.t2.token == trade.t1.token
+            });
+            if !directional {
+                continue;
+            }
+
+            let candidate = StatisticalArbitrageCandidate {
+                trade: trade.clone(),
+                mm_address,
+            };
+            let trace_address = inspection.actions[*i].trace_address().to_vec();
+            let provenance = Provenance::of([&inspection.actions[*i]]);
+            inspection.actions[*i] =
+                Classification::new(candidate, trace_address).with_provenance(provenance);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+
+    fn trade(
+        trader: Address,
+        pool: Address,
+        (sold, sold_amount): (Address, U256),
+        (bought, bought_amount): (Address, U256),
+    ) -> Trade {
+        Trade::new(
+            Transfer::new(trader, pool, sold_amount, sold),
+            Transfer::new(pool, trader, bought_amount, bought),
+        )
+    }
+
+    fn candidates(reducer: &StatisticalArbitrageReducer, trades: Vec<Trade>) -> Vec<Address> {
+        let mut inspection = mk_inspection(
+            trades
+                .into_iter()
+                .enumerate()
+                .map(|(i, t)| Classification::new(t, vec![i]))
+                .collect(),
+        );
+        reducer.reduce(&mut inspection);
+        inspection
+            .known_actions()
+            .filter_map(|a| a.statistical_arbitrage_candidate())
+            .map(|candidate| candidate.mm_address)
+            .collect()
+    }
+
+    #[test]
+    fn flags_the_large_one_way_trades_of_market_makers() {
+        let addrs = addrs();
+        let (mm, trader, pool, token) = (addrs[0], addrs[1], addrs[2], addrs[3]);
+        let reducer = StatisticalArbitrageReducer::new().with_market_maker(mm);
+        let eth = |n: u64| U256::from(n) * U256::exp10(18);
+        let usdc = |n: u64| U256::from(n) * U256::exp10(6);
+
+        // 50 WETH sold for USDC
+        let sell = trade(mm, pool, (*WETH, eth(50)), (*USDC, usdc(100_000)));
+        assert_eq!(candidates(&reducer, vec![sell.clone()]), vec![mm]);
+        // too small, or not by a market maker
+        let small = trade(mm, pool, (*WETH, eth(1)), (*USDC, usdc(2000)));
+        assert!(candidates(&reducer, vec![small]).is_empty());
+        let other = trade(trader, pool, (*WETH, eth(50)), (*USDC, usdc(100_000)));
+        assert!(candidates(&reducer, vec![other]).is_empty());
+        // bought back in the same transaction
+        let buy = trade(mm, pool, (*USDC, usdc(100_000)), (*WETH, eth(49)));
+        assert!(candidates(&reducer, vec![sell, buy]).is_empty());
+
+        // the tokens without a minimum are never large
+        let unknown = trade(mm, pool, (token, eth(1_000_000)), (*WETH, 1.into()));
+        assert!(candidates(&reducer, vec![unknown.clone()]).is_empty());
+        let reducer = reducer.with_min_amount(token, eth(1000));
+        assert_eq!(candidates(&reducer, vec![unknown]), vec![mm]);
+    }
+}
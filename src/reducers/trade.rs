@@ -1,88 +1,170 @@
 use crate::{
+    addresses::{ChainConfig, REBASING},
     inspectors::find_matching,
-    types::{actions::Trade, Classification, Inspection},
+    types::{
+        actions::{Trade, Transfer},
+        CheckedReason, Classification, Inspection, Provenance, Status,
+    },
     Reducer,
 };
+use ethers::types::Address;
+use std::{borrow::Cow, collections::HashSet};
 
-pub struct TradeReducer;
+/// Stitches the transfers to and from the same account into trades.
+///
+/// Wrapping and unwrapping tokens which are minted and burned, e.g. stETH into
+/// wstETH, are trades with the wrapper's contract: a mint to the account which
+/// paid it is the other leg of its payment, and a burn is paid back by it. The
+/// `0x0` side of the mint or burn is then replaced by the contract.
+///
+/// The trades of a token for itself which the inspectors found, e.g. against
+/// the fake pair of a honeypot, do not exchange anything and are pruned so that
+/// they are not mistaken for profitable trades. Their transaction is then
+/// `Checked` as suspicious, unless it reverted. Rebasing tokens are exempt.
+pub struct TradeReducer {
+    rebasing: Cow<'static, HashSet<Address>>,
+}
 
 impl TradeReducer {
     /// Instantiates the reducer
     pub fn new() -> Self {
-        Self
+        Self {
+            rebasing: Cow::Borrowed(&REBASING),
+        }
+    }
+
+    /// Instantiates the reducer for `chain`, whose rebasing tokens are exempt
+    pub fn with_chain(chain: &ChainConfig) -> Self {
+        Self {
+            rebasing: Cow::Owned(chain.rebasing.clone()),
+        }
+    }
+
+    /// Whether the trade sells a token for itself, see `Trade::is_same_token`
+    fn is_same_token(&self, trade: &Trade) -> bool {
+        trade.t1.token == trade.t2.token && !self.rebasing.contains(&trade.t1.token)
     }
 }
 
 impl Reducer for TradeReducer {
     fn reduce(&self, inspection: &mut Inspection) {
-        let actions = inspection.actions.to_vec();
+        let mut suspicious = false;
+        for action in inspection.actions.iter_mut() {
+            if matches!(action.as_action().and_then(|a| a.trade()), Some(trade) if self.is_same_token(trade))
+            {
+                *action = Classification::Prune;
+                suspicious = true;
+            }
+        }
+        if suspicious && inspection.status != Status::Reverted {
+            inspection.mark_checked(CheckedReason::SuspiciousTrade);
+        }
+
+        let actions = &inspection.actions;
+        let mut trades = Vec::new();
         let mut prune = Vec::new();
-        inspection
-            .actions
-            .iter_mut()
-            .enumerate()
-            .for_each(|(i, action)| {
-                // check if we got a transfer
-                let transfer =
-                    if let Some(transfer) = action.as_action().map(|x| x.transfer()).flatten() {
-                        transfer
-                    } else {
-                        return;
-                    };
-
-                // find the first transfer after it
+        for (i, action) in actions.iter().enumerate() {
+            // check if we got a transfer
+            let transfer = if let Some(transfer) = action.as_action().and_then(|x| x.transfer()) {
+                transfer
+            } else {
+                continue;
+            };
+
+            // find the first transfer after it
+            let res = find_matching(
+                actions.iter().enumerate().skip(i + 1),
+                |t| t.transfer(),
+                |t| {
+                    t.to == transfer.from
+                        && counterparty(t) == recipient(transfer)
+                        && t.token != transfer.token
+                },
+                true,
+            );
+
+            if let Some((j, transfer2)) = res {
+                // only match transfers which were on the same rank of the trace
+                // trades across multiple trace levels are handled by their individual
+                // inspectors
+                if actions[i].trace_address().len() != actions[j].trace_address().len() {
+                    continue;
+                }
+
+                let trade = Classification::new(
+                    Trade {
+                        t1: Transfer {
+                            to: recipient(transfer),
+                            ..transfer.clone()
+                        },
+                        t2: Transfer {
+                            from: counterparty(transfer2),
+                            ..transfer2.clone()
+                        },
+                    },
+                    actions[i].trace_address(),
+                );
+                trades.push((i, trade.with_provenance(Provenance::TransferInferred)));
+
+                // If there is no follow-up transfer that uses `transfer2`, prune it:
                 let res = find_matching(
-                    actions.iter().enumerate().skip(i + 1),
+                    actions.iter().enumerate().skip(j + 1),
                     |t| t.transfer(),
-                    |t| t.to == transfer.from && t.from == transfer.to && t.token != transfer.token,
-                    true,
+                    |t| t.to == transfer2.from && t.from == transfer2.to,
+                    false,
                 );
-
-                if let Some((j, transfer2)) = res {
-                    // only match transfers which were on the same rank of the trace
-                    // trades across multiple trace levels are handled by their individual
-                    // inspectors
-                    if actions[i].trace_address().len() != actions[j].trace_address().len() {
-                        return;
-                    }
-
-                    *action = Classification::new(
-                        Trade {
-                            t1: transfer.clone(),
-                            t2: transfer2.clone(),
-                        },
-                        actions[i].trace_address(),
-                    );
-
-                    // If there is no follow-up transfer that uses `transfer2`, prune it:
-                    let res = find_matching(
-                        actions.iter().enumerate().skip(j + 1),
-                        |t| t.transfer(),
-                        |t| t.to == transfer2.from && t.from == transfer2.to,
-                        false,
-                    );
-                    if res.is_none() {
-                        prune.push(j);
-                    }
+                if res.is_none() {
+                    prune.push(j);
                 }
-            });
+            }
+        }
 
+        for (i, trade) in trades {
+            inspection.actions[i] = trade;
+        }
         prune
             .iter()
             .for_each(|p| inspection.actions[*p] = Classification::Prune);
     }
 }
 
+/// Who sent the tokens of `transfer`, which is the token's contract for mints,
+/// e.g. when a wrapper mints its tokens for the ones it was sent
+fn counterparty(transfer: &Transfer) -> Address {
+    if transfer.is_mint() {
+        transfer.token
+    } else {
+        transfer.from
+    }
+}
+
+/// Who received the tokens of `transfer`, which is the token's contract for
+/// burns, e.g. when a wrapper burns its tokens to redeem the wrapped ones
+fn recipient(transfer: &Transfer) -> Address {
+    if transfer.is_burn() {
+        transfer.token
+    } else {
+        transfer.to
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::test_helpers::*;
-    use crate::types::actions::Transfer;
+    use crate::{inspectors::ERC20, test_helpers::*, Inspector};
 
     fn test_transfer_to_trade(input: Vec<Classification>, expected: Vec<Classification>) {
         let uniswap = TradeReducer::new();
         let mut inspection = mk_inspection(input);
         uniswap.reduce(&mut inspection);
+        // the trades are pieced together from the transfers
+        let expected = expected
+            .into_iter()
+            .map(|action| match action.as_action().and_then(|a| a.trade()) {
+                Some(_) => action.with_provenance(Provenance::TransferInferred),
+                None => action,
+            })
+            .collect::<Vec<_>>();
         assert_eq!(inspection.actions, expected);
     }
 
@@ -157,4 +239,207 @@ mod tests {
 
         test_transfer_to_trade(input, expected);
     }
+
+    #[test]
+    fn fee_on_transfer_trades_are_net_of_fees() {
+        let addrs = addrs();
+        let (token1, token2, trader, pool, collector) =
+            (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4]);
+
+        // the token takes its 2% fee within its `transfer`, which only its
+        // logs show
+        let mut inspection = mk_inspection(vec![
+            mk_call(trader, token1, transfer(pool, 100.into()), vec![0]),
+            mk_call(pool, token2, transfer(trader, 50.into()), vec![1]),
+        ]);
+        inspection.logs = vec![
+            transfer_log(token1, trader, pool, 100),
+            transfer_log(token2, pool, trader, 49),
+            transfer_log(token2, pool, collector, 1),
+        ];
+        ERC20::new().inspect(&mut inspection);
+        TradeReducer::new().reduce(&mut inspection);
+        inspection.prune();
+
+        let known = inspection.known();
+        assert_eq!(known.len(), 1);
+        let trade = known[0].as_ref().trade().unwrap();
+        assert_eq!((trade.t1.token, trade.t1.amount), (token1, 100.into()));
+        assert_eq!((trade.t2.to, trade.t2.amount), (trader, 49.into()));
+    }
+
+    #[test]
+    fn mints_and_burns_of_wrappers_are_trades() {
+        use crate::{addresses::WETH, reducers::ArbitrageReducer};
+        use ethers::types::I256;
+
+        let addrs = addrs();
+        let (bot, pool1, pool2, steth, wsteth) = (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4]);
+        let transfer = |token, from, to, amount: u64| Transfer {
+            from,
+            to,
+            amount: amount.into(),
+            token,
+        };
+
+        // buys stETH, wraps it into wstETH, and sells the wstETH
+        let mut inspection = mk_inspection(vec![
+            Classification::new(transfer(*WETH, bot, pool1, 100), vec![0]),
+            Classification::new(transfer(steth, pool1, bot, 5), vec![1]),
+            Classification::new(transfer(steth, bot, wsteth, 5), vec![2]),
+            Classification::new(transfer(wsteth, Address::zero(), bot, 4), vec![3]),
+            Classification::new(transfer(wsteth, bot, pool2, 4), vec![4]),
+            Classification::new(transfer(*WETH, pool2, bot, 101), vec![5]),
+        ]);
+        TradeReducer::new().reduce(&mut inspection);
+        let trades = inspection.trades().cloned().collect::<Vec<_>>();
+        assert_eq!(trades.len(), 3);
+        assert_eq!(
+            trades[1],
+            Trade::new(
+                transfer(steth, bot, wsteth, 5),
+                transfer(wsteth, wsteth, bot, 4)
+            )
+        );
+
+        ArbitrageReducer::new().reduce(&mut inspection);
+        inspection.prune();
+        let arb = inspection.arbitrages().next().cloned().unwrap();
+        assert_eq!(arb.trades.len(), 3);
+        assert_eq!(arb.profit, 1.into());
+        let deltas = inspection.balance_deltas();
+        assert_eq!(deltas[&(bot, *WETH)], I256::from(1));
+
+        // unwrapping burns the wstETH, and the mint only credits the bot
+        let burn = transfer(wsteth, bot, Address::zero(), 4);
+        let redeemed = transfer(steth, wsteth, bot, 5);
+        let input = vec![
+            Classification::new(burn.clone(), vec![0]),
+            Classification::new(redeemed.clone(), vec![1]),
+        ];
+        let unwrapped = Trade::new(transfer(wsteth, bot, wsteth, 4), redeemed);
+        test_transfer_to_trade(
+            input,
+            vec![
+                Classification::new(unwrapped, vec![0]),
+                Classification::Prune,
+            ],
+        );
+        let deltas = mk_inspection(vec![Classification::new(burn, vec![0])]).balance_deltas();
+        assert_eq!(
+            deltas.into_iter().collect::<Vec<_>>(),
+            vec![((bot, wsteth), I256::from(-4))]
+        );
+    }
+
+    #[test]
+    fn custodian_mints_are_inflows() {
+        use crate::addresses::parse_address;
+        use ethers::types::I256;
+
+        // WBTC is minted by its custodians for BTC sent to them off-chain, so
+        // nothing is sent for the minted WBTC in the tx
+        let wbtc = parse_address("2260FAC5E5542a773Aa44fBCfeDf7C193bc2C599");
+        let addrs = addrs();
+        let (bot, pool, usdc) = (addrs[0], addrs[1], addrs[2]);
+        let mint = Transfer {
+            from: Address::zero(),
+            to: bot,
+            amount: 5.into(),
+            token: wbtc,
+        };
+        let sold = Transfer {
+            from: bot,
+            to: pool,
+            amount: 5.into(),
+            token: wbtc,
+        };
+        let bought = Transfer {
+            from: pool,
+            to: bot,
+            amount: 100.into(),
+            token: usdc,
+        };
+        let mut inspection = mk_inspection(vec![
+            Classification::new(mint.clone(), vec![0]),
+            Classification::new(sold.clone(), vec![1]),
+            Classification::new(bought.clone(), vec![2]),
+        ]);
+        TradeReducer::new().reduce(&mut inspection);
+        inspection.prune();
+        assert_eq!(inspection.transfers().collect::<Vec<_>>(), vec![&mint]);
+        assert_eq!(
+            inspection.trades().collect::<Vec<_>>(),
+            vec![&Trade::new(sold, bought)]
+        );
+
+        // the minted WBTC makes up for the sold one
+        let deltas = inspection.balance_deltas();
+        assert_eq!(
+            deltas.get(&(bot, wbtc)).copied().unwrap_or_default(),
+            I256::zero()
+        );
+        assert_eq!(deltas[&(bot, usdc)], I256::from(100));
+        assert!(deltas
+            .keys()
+            .all(|(account, _)| *account != Address::zero()));
+    }
+
+    #[test]
+    // a synthetic trace of a bot which sells a token for itself on a fake pair
+    fn same_token_trades_are_suspicious() {
+        use crate::{
+            addresses::REBASING,
+            inspectors::{UniswapV2, ERC20},
+            reducers::ArbitrageReducer,
+            Inspector,
+        };
+
+        let mut inspection = read_trace("same_token_bait.json");
+        ERC20::new().inspect(&mut inspection);
+        UniswapV2::new().inspect(&mut inspection);
+        // the pair "pays out" 110 tokens for 100 of the same token
+        assert!(inspection
+            .trades()
+            .any(|trade| trade.t1.token == trade.t2.token && trade.t2.amount > trade.t1.amount));
+
+        TradeReducer::new().reduce(&mut inspection);
+        ArbitrageReducer::new().reduce(&mut inspection);
+        inspection.prune();
+        assert_eq!(inspection.status, Status::Checked);
+        assert_eq!(
+            inspection.checked_reason,
+            Some(CheckedReason::SuspiciousTrade)
+        );
+        assert_eq!(inspection.trades().count(), 0);
+        assert_eq!(inspection.arbitrages().count(), 0);
+
+        // the balances of rebasing tokens change while they are in a pool
+        let addrs = addrs();
+        let steth = *REBASING.iter().next().unwrap();
+        let trade = Trade {
+            t1: Transfer {
+                from: addrs[0],
+                to: addrs[1],
+                amount: 100.into(),
+                token: steth,
+            },
+            t2: Transfer {
+                from: addrs[1],
+                to: addrs[0],
+                amount: 101.into(),
+                token: steth,
+            },
+        };
+        let input = vec![Classification::new(trade, Vec::new())];
+        let mut inspection = mk_inspection(input.clone());
+        TradeReducer::new().reduce(&mut inspection);
+        assert_eq!(inspection.actions, input);
+        assert_eq!(inspection.status, Status::Success);
+
+        // but mainnet's rebasing tokens are not known to rebase elsewhere
+        let mut inspection = mk_inspection(input);
+        TradeReducer::with_chain(&ChainConfig::arbitrum()).reduce(&mut inspection);
+        assert_eq!(inspection.status, Status::Checked);
+    }
 }
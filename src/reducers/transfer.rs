@@ -0,0 +1,142 @@
+use crate::{
+    types::{
+        actions::{SpecificAction, Transfer},
+        Classification, Inspection, Provenance,
+    },
+    Reducer,
+};
+use ethers::types::{Address, U256};
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, Default)]
+/// Collapses the transfers which pass through an intermediate address, e.g. a
+/// bot's helper contract, into a single transfer between the endpoints. An
+/// address is only passed through if it sends on the amount it received, and
+/// its balance of the token is the same at the end of the transaction.
+///
+/// The trader of an arbitrage passes through its intermediate tokens too, so
+/// this must run after the `TradeReducer` pairs those transfers into trades.
+pub struct TransferReducer;
+
+impl TransferReducer {
+    /// Instantiates the reducer
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Reducer for TransferReducer {
+    fn reduce(&self, inspection: &mut Inspection) {
+        // the amounts of each token received and sent by each address,
+        // including the legs of the trades
+        let mut flows: HashMap<(Address, Address), (U256, U256)> = HashMap::new();
+        let transfers = inspection
+            .actions
+            .iter()
+            .filter_map(|a| a.as_action())
+            .flat_map(|a| match a {
+                SpecificAction::Transfer(t) => vec![t],
+                SpecificAction::Trade(t) => vec![&t.t1, &t.t2],
+                _ => vec![],
+            });
+        for transfer in transfers {
+            let received = flows.entry((transfer.token, transfer.to)).or_default();
+            received.0 = received.0.saturating_add(transfer.amount);
+            let sent = flows.entry((transfer.token, transfer.from)).or_default();
+            sent.1 = sent.1.saturating_add(transfer.amount);
+        }
+        let balanced = |token: Address, address: Address| {
+            !address.is_zero()
+                && flows
+                    .get(&(token, address))
+                    .is_some_and(|(received, sent)| received == sent)
+        };
+
+        for i in 0..inspection.actions.len() {
+            loop {
+                let first = match inspection.actions[i].as_action().and_then(|a| a.transfer()) {
+                    Some(transfer) if balanced(transfer.token, transfer.to) => transfer.clone(),
+                    _ => break,
+                };
+                let next = inspection
+                    .actions
+                    .iter()
+                    .enumerate()
+                    .skip(i + 1)
+                    .filter_map(|(j, a)| Some((j, a.as_action()?.transfer()?)))
+                    .find(|(_, t)| {
+                        t.token == first.token
+                            && t.from == first.to
+                            && t.amount == first.amount
+                            // round trips are not passed through
+                            && t.to != first.from
+                    })
+                    .map(|(j, t)| (j, t.to));
+                let (j, to) = match next {
+                    Some(inner) => inner,
+                    None => break,
+                };
+
+                let trace_address = inspection.actions[i].trace_address();
+                let provenance = Provenance::of([&inspection.actions[i], &inspection.actions[j]]);
+                inspection.actions[i] =
+                    Classification::new(Transfer { to, ..first }, trace_address)
+                        .with_provenance(provenance);
+                inspection.actions[j] = Classification::Prune;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+
+    fn transfer(from: Address, to: Address, amount: u64, token: Address) -> Classification {
+        Classification::new(
+            Transfer {
+                from,
+                to,
+                amount: amount.into(),
+                token,
+            },
+            vec![],
+        )
+    }
+
+    #[test]
+    fn collapses_pass_through() {
+        let addrs = addrs();
+        let (a, b, c, d, token) = (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4]);
+        let mut inspection = mk_inspection(vec![
+            transfer(a, b, 100, token),
+            transfer(b, c, 100, token),
+            transfer(c, d, 100, token),
+        ]);
+        TransferReducer::new().reduce(&mut inspection);
+        inspection.prune();
+
+        assert_eq!(inspection.actions, vec![transfer(a, d, 100, token)]);
+    }
+
+    #[test]
+    fn keeps_what_is_not_passed_through() {
+        let addrs = addrs();
+        let (a, b, c, token, other) = (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4]);
+        let actions = vec![
+            // b keeps some of what it received
+            transfer(a, b, 100, token),
+            transfer(b, c, 100, token),
+            transfer(a, b, 50, token),
+            // another token
+            transfer(c, a, 100, other),
+            // a round trip
+            transfer(a, c, 10, other),
+            transfer(c, a, 10, other),
+        ];
+        let mut inspection = mk_inspection(actions.clone());
+        TransferReducer::new().reduce(&mut inspection);
+        assert_eq!(inspection.actions, actions);
+    }
+}
@@ -0,0 +1,250 @@
+//! Pending transactions are inspected by simulating them with Geth's
+//! `debug_traceCall` and the `callTracer`, whose frames are converted to
+//! Parity-style traces like the ones of mined transactions. The simulation
+//! runs against the pending block, with its header overridden with the values
+//! predicted for the next block, so that the base fee and the coinbase which
+//! the transaction sees are the ones it would be mined with.
+use crate::types::gas::next_base_fee;
+use ethers::types::{Address, U256};
+use serde_json::json;
+use thiserror::Error;
+
+/// Seconds between 2 blocks since the merge
+const BLOCK_TIME: u64 = 12;
+
+#[derive(Debug, Clone, PartialEq)]
+/// The block which a pending transaction is simulated in
+pub struct PendingBlock {
+    pub number: u64,
+    pub timestamp: u64,
+    /// The block's base fee, for post-London blocks
+    pub base_fee: Option<U256>,
+    /// The block's builder, who gets paid the coinbase payments
+    pub coinbase: Address,
+    /// Geth's `stateOverrides`, e.g. to fund the sender of the transaction
+    pub state_overrides: Option<serde_json::Value>,
+}
+
+impl PendingBlock {
+    /// Instantiates the block, without a base fee
+    pub fn new(number: u64, timestamp: u64, coinbase: Address) -> Self {
+        Self {
+            number,
+            timestamp,
+            base_fee: None,
+            coinbase,
+            state_overrides: None,
+        }
+    }
+
+    /// Predicts the block after `parent`, which used `gas_used` out of its
+    /// `gas_limit`. The base fee is only predicted if `parent` has one.
+    pub fn after(
+        parent: &crate::types::Block,
+        gas_used: U256,
+        gas_limit: U256,
+        coinbase: Address,
+    ) -> Self {
+        let timestamp = parent.timestamp.unwrap_or_default().low_u64() + BLOCK_TIME;
+        let mut block = Self::new(parent.number + 1, timestamp, coinbase);
+        block.base_fee = parent
+            .base_fee
+            .map(|base_fee| next_base_fee(base_fee, gas_used, gas_limit));
+        block
+    }
+
+    /// Sets the block's base fee
+    pub fn with_base_fee(mut self, base_fee: U256) -> Self {
+        self.base_fee = Some(base_fee);
+        self
+    }
+
+    /// Sets Geth's `stateOverrides` of the simulation
+    pub fn with_state_overrides(mut self, overrides: serde_json::Value) -> Self {
+        self.state_overrides = Some(overrides);
+        self
+    }
+
+    /// The options of `debug_traceCall` which trace the transaction's calls in
+    /// this block
+    pub(crate) fn options(&self) -> serde_json::Value {
+        // older versions of Geth name the coinbase `coinbase`, newer ones
+        // `feeRecipient`, and both ignore the fields they do not know
+        let mut overrides = json!({
+            "number": U256::from(self.number),
+            "time": U256::from(self.timestamp),
+            "coinbase": self.coinbase,
+            "feeRecipient": self.coinbase,
+        });
+        if let Some(base_fee) = self.base_fee {
+            overrides["baseFee"] = json!(base_fee);
+        }
+        let mut options = json!({
+            "tracer": "callTracer",
+            "blockOverrides": overrides,
+        });
+        if let Some(ref state) = self.state_overrides {
+            options["stateOverrides"] = state.clone();
+        }
+        options
+    }
+}
+
+#[derive(Debug, Error)]
+/// An error while simulating a pending transaction
+pub enum SimulationError<E: std::error::Error + 'static> {
+    /// The node failed to simulate the transaction
+    #[error(transparent)]
+    Provider(E),
+    /// The node's output was not the frames of the `callTracer`
+    #[error("could not parse the traced calls: {0}")]
+    Frames(#[from] serde_json::Error),
+    /// The transaction did not make any calls, e.g. because it is a plain
+    /// transfer to an EOA
+    #[error("the simulated transaction has nothing to inspect")]
+    NoActions,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        inspectors::ERC20,
+        reducers::TransferReducer,
+        test_helpers::addrs,
+        types::{Block, GasInfo},
+        BatchInspector, TraceProvider, Unsupported,
+    };
+    use async_trait::async_trait;
+    use ethers::types::{BlockNumber, Trace, TransactionRequest, TxHash};
+    use std::sync::Mutex;
+
+    /// A node which simulates every call with the same frames
+    struct Simulator {
+        frame: serde_json::Value,
+        requests: Mutex<Vec<(BlockNumber, serde_json::Value)>>,
+    }
+
+    #[async_trait]
+    impl TraceProvider for Simulator {
+        type Error = std::io::Error;
+
+        async fn trace_transaction(&self, _: TxHash) -> Result<Vec<Trace>, Self::Error> {
+            Err(Unsupported("trace_transaction").into())
+        }
+
+        async fn trace_block(&self, _: u64) -> Result<Vec<Trace>, Self::Error> {
+            Err(Unsupported("trace_block").into())
+        }
+
+        async fn debug_trace_call(
+            &self,
+            _: &TransactionRequest,
+            block: BlockNumber,
+            options: serde_json::Value,
+        ) -> Result<serde_json::Value, Self::Error> {
+            self.requests.lock().unwrap().push((block, options));
+            Ok(self.frame.clone())
+        }
+    }
+
+    #[test]
+    fn predicts_the_next_block() {
+        let addrs = addrs();
+        let parent = Block {
+            number: 100,
+            miner: addrs[0],
+            base_fee: Some(U256::from(80)),
+            timestamp: Some(1000.into()),
+            inspections: vec![],
+            reorged: false,
+        };
+        // the parent was full, so the base fee goes up by 12.5%
+        let block = PendingBlock::after(&parent, 30_000_000.into(), 30_000_000.into(), addrs[1]);
+        assert_eq!(
+            block,
+            PendingBlock::new(101, 1012, addrs[1]).with_base_fee(90.into())
+        );
+
+        let funded = json!({ format!("{:?}", addrs[2]): { "balance": "0x1" } });
+        let options = block.with_state_overrides(funded.clone()).options();
+        assert_eq!(options["tracer"], "callTracer");
+        assert_eq!(options["blockOverrides"]["number"], "0x65");
+        assert_eq!(options["blockOverrides"]["time"], "0x3f4");
+        assert_eq!(options["blockOverrides"]["baseFee"], "0x5a");
+        assert_eq!(options["blockOverrides"]["coinbase"], json!(addrs[1]));
+        assert_eq!(options["stateOverrides"], funded);
+
+        // there is no base fee to predict before London
+        let parent = Block {
+            base_fee: None,
+            ..parent
+        };
+        let block = PendingBlock::after(&parent, 0.into(), 30_000_000.into(), addrs[1]);
+        assert_eq!(block.base_fee, None);
+        assert!(block.options()["blockOverrides"].get("baseFee").is_none());
+    }
+
+    #[tokio::test]
+    async fn inspects_a_pending_tx() {
+        let addrs = addrs();
+        let (searcher, bot, coinbase) = (addrs[0], addrs[1], addrs[2]);
+        // a bot which pays the next block's builder
+        let simulator = Simulator {
+            frame: json!({
+                "type": "CALL",
+                "from": searcher,
+                "to": bot,
+                "gas": "0x30000",
+                "gasUsed": "0x10000",
+                "input": "0x12345678",
+                "calls": [{
+                    "type": "CALL",
+                    "from": bot,
+                    "to": coinbase,
+                    "value": "0x100",
+                    "gas": "0x1000",
+                    "gasUsed": "0x0",
+                    "input": "0x",
+                }],
+            }),
+            requests: Mutex::new(vec![]),
+        };
+        let block = PendingBlock::new(101, 1012, coinbase).with_base_fee(90.into());
+        let tx = TransactionRequest::new()
+            .from(searcher)
+            .to(bot)
+            .gas_price(100)
+            .data(vec![0x12, 0x34, 0x56, 0x78]);
+        let inspector = BatchInspector::new(
+            vec![Box::new(ERC20::new())],
+            vec![Box::new(TransferReducer::new())],
+        );
+
+        let inspection = inspector
+            .inspect_pending(&simulator, &tx, &block)
+            .await
+            .unwrap();
+        let requests = simulator.requests.lock().unwrap().clone();
+        assert_eq!(requests, vec![(BlockNumber::Pending, block.options())]);
+
+        assert_eq!(inspection.block_number, 101);
+        assert_eq!(inspection.from, searcher);
+        let payment = inspection
+            .known_actions()
+            .find_map(|action| action.miner_payment())
+            .cloned()
+            .unwrap();
+        assert_eq!((payment.from, payment.to), (bot, coinbase));
+        assert_eq!(payment.amount, 0x100.into());
+        assert_eq!(
+            inspection.gas,
+            Some(GasInfo {
+                gas_used: 0x10000.into(),
+                gas_price: 100.into(),
+                base_fee: Some(90.into()),
+                ..Default::default()
+            })
+        );
+    }
+}
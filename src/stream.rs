@@ -0,0 +1,353 @@
+//! Live inspection of the blocks at the head of the chain, e.g. for dashboards
+//! and alerts. Each new head is fetched along with its traces and receipts and
+//! inspected with the full pipeline.
+//!
+//! The stream is pulled, so that nothing is fetched until the consumer asks
+//! for the next block: a slow consumer pauses the fetching, and the heads
+//! which arrive meanwhile wait in the subscription. When a head does not build
+//! on the last streamed blocks, its ancestors are fetched by their hash until
+//! they do, and the ones which replaced streamed blocks are emitted again with
+//! `reorged` set. The same walk fills in the heads which the node skipped.
+use crate::{
+    types::{gas::BlockWithFees, Block, GasInfo},
+    BatchInspector,
+};
+use ethers::{
+    providers::{JsonRpcClient, Middleware, Provider, ProviderError, PubsubClient},
+    types::{Block as EthBlock, BlockNumber, TxHash, H256},
+};
+use futures::stream::{self, Stream, StreamExt};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use thiserror::Error;
+
+/// How many of the last streamed blocks are remembered, which bounds how deep
+/// a reorg can be detected
+const MAX_REORG_DEPTH: u64 = 64;
+
+#[derive(Debug, Error)]
+/// An error while streaming a block. The stream goes on with the next head.
+pub enum StreamError<E: std::error::Error + 'static> {
+    /// The node failed to serve the block
+    #[error(transparent)]
+    Provider(E),
+    /// The node failed to serve the block's header, which is requested
+    /// directly since ethers' `Block` misses its fees
+    #[error(transparent)]
+    Request(ProviderError),
+    /// The node does not know the block, e.g. because it got reorged again
+    #[error("block {0:?} not found")]
+    NotFound(H256),
+}
+
+/// Subscribes to the node's new heads with `eth_subscribe` and streams their
+/// inspections
+pub async fn stream_blocks<'a, P: PubsubClient + 'static>(
+    provider: &'a Provider<P>,
+    inspector: &'a BatchInspector,
+) -> Result<impl Stream<Item = Result<Block, StreamError<ProviderError>>> + 'a, ProviderError> {
+    let heads = provider.subscribe_blocks().await?;
+    Ok(inspect_heads(provider, inspector, heads))
+}
+
+/// Streams the inspections of the blocks of `heads`, along with the blocks
+/// which they reorged and the ones which they skipped
+pub fn inspect_heads<'a, M, S>(
+    provider: &'a M,
+    inspector: &'a BatchInspector,
+    heads: S,
+) -> impl Stream<Item = Result<Block, StreamError<M::Error>>> + 'a
+where
+    M: Middleware + 'static,
+    S: Stream<Item = EthBlock<TxHash>> + 'a,
+{
+    let chain = Chain {
+        heads: Box::pin(heads),
+        streamed: BTreeMap::new(),
+        queue: VecDeque::new(),
+    };
+    stream::unfold(chain, move |mut chain| async move {
+        loop {
+            if let Some((hash, reorged)) = chain.queue.pop_front() {
+                let block = inspect_block(provider, inspector, hash)
+                    .await
+                    .map(|mut block| {
+                        block.reorged = reorged;
+                        chain.streamed(block.number, hash);
+                        block
+                    });
+                if block.is_err() {
+                    // its descendants are fetched again by the next head
+                    chain.queue.clear();
+                }
+                return Some((block, chain));
+            }
+
+            let head = chain.heads.next().await?;
+            if let Err(err) = chain.extend(provider, head).await {
+                return Some((Err(err), chain));
+            }
+        }
+    })
+}
+
+/// The blocks streamed so far and the ones waiting to be
+struct Chain<'a> {
+    heads: std::pin::Pin<Box<dyn Stream<Item = EthBlock<TxHash>> + 'a>>,
+    /// The hashes of the last streamed blocks, by number
+    streamed: BTreeMap<u64, H256>,
+    /// The blocks to stream next, oldest first, and whether they reorged one
+    /// which was streamed
+    queue: VecDeque<(H256, bool)>,
+}
+
+impl<'a> Chain<'a> {
+    /// Queues the head, preceded by its ancestors which were not streamed
+    async fn extend<M: Middleware + 'static>(
+        &mut self,
+        provider: &M,
+        head: EthBlock<TxHash>,
+    ) -> Result<(), StreamError<M::Error>> {
+        let (hash, number) = match (head.hash, head.number) {
+            (Some(hash), Some(number)) => (hash, number.as_u64()),
+            // a pending block
+            _ => return Ok(()),
+        };
+        if self.streamed.get(&number) == Some(&hash) {
+            return Ok(());
+        }
+
+        let mut blocks = vec![(hash, number)];
+        let mut parent = head.parent_hash;
+        for number in (number.saturating_sub(MAX_REORG_DEPTH)..number).rev() {
+            match self.streamed.get(&number) {
+                Some(streamed) if *streamed == parent => break,
+                // nothing was streamed before it
+                None if self.streamed.range(..number).next().is_none() => break,
+                _ => (),
+            }
+            let ancestor = provider
+                .get_block(parent)
+                .await
+                .map_err(StreamError::Provider)?
+                .ok_or(StreamError::NotFound(parent))?;
+            blocks.push((parent, number));
+            parent = ancestor.parent_hash;
+        }
+
+        self.queue = blocks
+            .into_iter()
+            .rev()
+            .map(|(hash, number)| (hash, self.streamed.contains_key(&number)))
+            .collect();
+        Ok(())
+    }
+
+    /// Records that the block got streamed. The blocks after it were reorged
+    /// out of the chain if they were streamed before.
+    fn streamed(&mut self, number: u64, hash: H256) {
+        self.streamed.split_off(&number);
+        self.streamed.insert(number, hash);
+        if number >= MAX_REORG_DEPTH {
+            self.streamed = self.streamed.split_off(&(number - MAX_REORG_DEPTH));
+        }
+    }
+}
+
+/// Fetches the transactions, traces and receipts of block `hash`, and inspects
+/// them
+async fn inspect_block<M: Middleware + 'static>(
+    provider: &M,
+    inspector: &BatchInspector,
+    hash: H256,
+) -> Result<Block, StreamError<M::Error>> {
+    let header = block_with_fees(provider, hash)
+        .await
+        .map_err(StreamError::Request)?
+        .ok_or(StreamError::NotFound(hash))?;
+    let number = header.number.ok_or(StreamError::NotFound(hash))?.as_u64();
+    let traces = provider
+        .trace_block(BlockNumber::Number(number.into()))
+        .await
+        .map_err(StreamError::Provider)?;
+    let receipts = provider
+        .parity_block_receipts(number)
+        .await
+        .map_err(StreamError::Provider)?;
+    // The traces and receipts can only be fetched by number, which may have
+    // been reorged to another block since its header was
+    let other_block = traces.iter().any(|trace| trace.block_hash != hash)
+        || receipts
+            .iter()
+            .any(|receipt| matches!(receipt.block_hash, Some(block) if block != hash));
+    if other_block {
+        return Err(StreamError::NotFound(hash));
+    }
+
+    let mut block =
+        Block::from_traces(inspector, number, traces, &receipts).with_header(&header.header);
+    let receipts = receipts
+        .iter()
+        .map(|receipt| (receipt.transaction_hash, receipt))
+        .collect::<HashMap<_, _>>();
+    for tx in header.transactions.iter() {
+        if let Some(receipt) = receipts.get(&tx.hash) {
+            if let Some(inspection) = block.inspections.iter_mut().find(|i| i.hash == tx.hash) {
+                inspection.gas = Some(GasInfo::from_fees(receipt, tx, &header.header));
+            }
+        }
+    }
+    Ok(block)
+}
+
+/// Fetches block `hash` with the fees of its txs
+async fn block_with_fees<M: Middleware>(
+    provider: &M,
+    hash: H256,
+) -> Result<Option<BlockWithFees>, ProviderError> {
+    let response: serde_json::Value = provider
+        .provider()
+        .as_ref()
+        .request("eth_getBlockByHash", (hash, true))
+        .await
+        .map_err(Into::into)?;
+    Ok(serde_json::from_value(response)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{inspectors::ERC20, reducers::TransferReducer};
+    use ethers::{
+        providers::MockProvider,
+        types::{Trace, Transaction, TransactionReceipt, H160},
+    };
+
+    fn header(number: u64, hash: H256, parent_hash: H256) -> EthBlock<TxHash> {
+        EthBlock {
+            hash: Some(hash),
+            parent_hash,
+            number: Some(number.into()),
+            ..Default::default()
+        }
+    }
+
+    /// Mocks the responses to the requests which fetch and inspect the blocks,
+    /// in order
+    fn mock(responses: Vec<serde_json::Value>) -> Provider<MockProvider> {
+        let (provider, mock) = Provider::mocked();
+        // the mock serves its last response first
+        for response in responses.into_iter().rev() {
+            mock.push(response).unwrap();
+        }
+        provider
+    }
+
+    /// The responses which inspect a block
+    fn inspected(header: &EthBlock<TxHash>) -> Vec<serde_json::Value> {
+        let block = EthBlock::<Transaction> {
+            hash: header.hash,
+            parent_hash: header.parent_hash,
+            number: header.number,
+            ..Default::default()
+        };
+        let mut block = serde_json::to_value(block).unwrap();
+        // which ethers' `Block` does not have
+        block["baseFeePerGas"] = "0x7".into();
+        vec![
+            block,
+            serde_json::to_value(Vec::<Trace>::new()).unwrap(),
+            serde_json::to_value(Vec::<TransactionReceipt>::new()).unwrap(),
+        ]
+    }
+
+    #[tokio::test]
+    async fn skips_blocks_reorged_while_fetched() {
+        let inspector = BatchInspector::new(vec![], vec![]);
+        let (hash, other) = (H256::from_low_u64_be(1), H256::from_low_u64_be(10));
+        let head = header(1, hash, H256::zero());
+        let mut responses = inspected(&head);
+        // the traces of block 1 are the ones of the block which replaced it
+        let trace = serde_json::json!({
+            "action": {
+                "callType": "call",
+                "from": format!("{:?}", H160::zero()),
+                "gas": "0x0",
+                "input": "0x",
+                "to": format!("{:?}", H160::zero()),
+                "value": "0x0",
+            },
+            "blockHash": format!("{:?}", other),
+            "blockNumber": 1,
+            "result": { "gasUsed": "0x0", "output": "0x" },
+            "subtraces": 0,
+            "traceAddress": [],
+            "transactionHash": format!("{:?}", H256::zero()),
+            "transactionPosition": 0,
+            "type": "call",
+        });
+        responses[1] = serde_json::json!([trace]);
+        let provider = mock(responses);
+
+        let blocks = inspect_heads(&provider, &inspector, stream::iter(vec![head]))
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(blocks.len(), 1);
+        assert!(matches!(blocks[0], Err(StreamError::NotFound(h)) if h == hash));
+    }
+
+    #[tokio::test]
+    async fn reemits_the_reorged_blocks() {
+        let inspector = BatchInspector::new(
+            vec![Box::new(ERC20::new())],
+            vec![Box::new(TransferReducer::new())],
+        );
+        let hash = |n: u64| H256::from_low_u64_be(n);
+        let (b1, b2, b3) = (
+            header(1, hash(1), hash(0)),
+            header(2, hash(2), hash(1)),
+            header(3, hash(3), hash(2)),
+        );
+        // a reorg of depth 2, whose first block was not announced, and whose
+        // head is announced twice
+        let (b2_, b3_) = (header(2, hash(20), hash(1)), header(3, hash(30), hash(20)));
+        // a head which skipped block 4
+        let (b4, b5) = (header(4, hash(4), hash(30)), header(5, hash(5), hash(4)));
+
+        let mut responses = vec![];
+        for block in [&b1, &b2, &b3].iter() {
+            responses.extend(inspected(block));
+        }
+        // the ancestor fetched by its hash
+        responses.push(serde_json::to_value(&b2_).unwrap());
+        for block in [&b2_, &b3_].iter() {
+            responses.extend(inspected(block));
+        }
+        responses.push(serde_json::to_value(&b4).unwrap());
+        for block in [&b4, &b5].iter() {
+            responses.extend(inspected(block));
+        }
+        let provider = mock(responses);
+
+        let heads = stream::iter(vec![b1, b2, b3, b3_.clone(), b3_, b5]);
+        let blocks = inspect_heads(&provider, &inspector, heads)
+            .map(|block| {
+                let block = block.unwrap();
+                assert_eq!(block.base_fee, Some(7.into()));
+                (block.number, block.reorged)
+            })
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(
+            blocks,
+            vec![
+                (1, false),
+                (2, false),
+                (3, false),
+                (2, true),
+                (3, true),
+                (4, false),
+                (5, false),
+            ]
+        );
+    }
+}
@@ -1,7 +1,18 @@
-use crate::types::{inspection::TraceWrapper, Classification, Inspection, Status};
-use ethers::types::{Address, Trace, TxHash};
+use crate::{
+    inspectors::*,
+    reducers::*,
+    types::{
+        actions::SpecificAction, classification::CallTrace, inspection::TraceWrapper,
+        Classification, GasInfo, Inspection,
+    },
+};
+use ethers::{
+    abi::{self, Token},
+    types::{Address, Bytes, Call, CallType, Log, Trace, TxHash, H256, U256},
+    utils::{id, keccak256},
+};
 use once_cell::sync::Lazy;
-use std::{collections::HashSet, convert::TryInto};
+use std::convert::TryInto;
 
 pub const TRACE: &str = include_str!("../../res/11017338.trace.json");
 pub static TRACES: Lazy<Vec<Trace>> = Lazy::new(|| serde_json::from_str(TRACE).unwrap());
@@ -15,18 +26,143 @@ pub fn addrs() -> Vec<Address> {
 }
 
 pub fn mk_inspection(actions: Vec<Classification>) -> Inspection {
-    Inspection {
-        status: Status::Success,
-        actions,
-        protocols: HashSet::new(),
-        from: Address::zero(),
-        contract: Address::zero(),
-        proxy_impl: None,
-        hash: TxHash::zero(),
-        block_number: 0,
+    Inspection::from_actions(actions)
+}
+
+/// Creates the `i`th tx of a block, which `from` sent for the `actions` at
+/// the top of its trace and which used 100k gas at `gas_price`, if it is known
+pub fn mk_tx<T: Into<SpecificAction>>(
+    i: u64,
+    from: Address,
+    actions: Vec<T>,
+    gas_price: Option<u64>,
+) -> Inspection {
+    let mut inspection = mk_inspection(
+        actions
+            .into_iter()
+            .enumerate()
+            .map(|(j, action)| Classification::new(action, vec![j]))
+            .collect(),
+    );
+    inspection.hash = TxHash::from_low_u64_be(i);
+    inspection.from = from;
+    inspection.gas = gas_price.map(|gas_price| GasInfo {
+        gas_used: 100_000.into(),
+        gas_price: gas_price.into(),
+        ..Default::default()
+    });
+    inspection
+}
+
+/// Creates an unclassified `CALL` with the provided calldata
+pub fn mk_call(
+    from: Address,
+    to: Address,
+    input: Bytes,
+    trace_address: Vec<usize>,
+) -> Classification {
+    CallTrace {
+        call: Call {
+            from,
+            to,
+            input,
+            gas: 100_000.into(),
+            call_type: CallType::Call,
+            ..Default::default()
+        },
+        trace_address: trace_address.into(),
+        output: Bytes::default(),
+    }
+    .into()
+}
+
+/// Sets the return data of an unclassified call
+pub fn with_output(mut call: Classification, output: &[Token]) -> Classification {
+    if let Classification::Unknown(ref mut calltrace) = call {
+        calltrace.output = abi::encode(output).into();
+    }
+    call
+}
+
+/// Sets the ETH sent by an unclassified call
+pub fn with_value(mut call: Classification, value: U256) -> Classification {
+    if let Classification::Unknown(ref mut calltrace) = call {
+        calltrace.call.value = value;
+    }
+    call
+}
+
+/// ABI-encodes a call to `signature` with the provided arguments
+pub fn calldata(signature: &str, args: &[Token]) -> Bytes {
+    [&id(signature)[..], &abi::encode(args)].concat().into()
+}
+
+/// Calldata for an ERC20 `transfer`
+pub fn transfer(to: Address, amount: U256) -> Bytes {
+    calldata(
+        "transfer(address,uint256)",
+        &[Token::Address(to), Token::Uint(amount)],
+    )
+}
+
+/// Calldata for an ERC20 `transferFrom`
+pub fn transfer_from(from: Address, to: Address, amount: U256) -> Bytes {
+    calldata(
+        "transferFrom(address,address,uint256)",
+        &[
+            Token::Address(from),
+            Token::Address(to),
+            Token::Uint(amount),
+        ],
+    )
+}
+
+/// Creates a log emitted by `address`
+pub fn mk_log(address: Address, topics: Vec<H256>, data: Vec<u8>) -> Log {
+    Log {
+        address,
+        topics,
+        data: data.into(),
+        block_hash: None,
+        block_number: None,
+        transaction_hash: None,
+        transaction_index: None,
+        log_index: None,
+        transaction_log_index: None,
+        log_type: None,
+        removed: None,
     }
 }
 
+/// The `Transfer` log of an ERC20
+pub fn transfer_log(token: Address, from: Address, to: Address, amount: u64) -> Log {
+    mk_log(
+        token,
+        vec![
+            H256::from(keccak256("Transfer(address,address,uint256)")),
+            H256::from(from),
+            H256::from(to),
+        ],
+        abi::encode(&[Token::Uint(amount.into())]),
+    )
+}
+
+/// The `Transfer` log of an ERC721, which indexes the token id
+pub fn erc721_transfer_log(collection: Address, from: Address, to: Address, id: U256) -> Log {
+    let mut token_id = [0; 32];
+    id.to_big_endian(&mut token_id);
+    mk_log(
+        collection,
+        vec![
+            H256::from(keccak256("Transfer(address,address,uint256)")),
+            H256::from(from),
+            H256::from(to),
+            H256::from(token_id),
+        ],
+        vec![],
+    )
+}
+
 pub fn read_trace(path: &str) -> Inspection {
     let input = std::fs::read_to_string(format!("res/{}", path)).unwrap();
     let traces: Vec<Trace> = serde_json::from_str(&input).unwrap();
@@ -51,15 +187,106 @@ pub fn get_trace(hash: &str) -> Inspection {
     .unwrap()
 }
 
+/// The default inspectors and reducers, without the data which the Compound
+/// and Curve inspectors fetch from a node
+pub fn processor() -> BatchInspector {
+    BatchInspector::new(
+        vec![
+            Box::new(ERC721::new()),
+            Box::new(ERC1155::new()),
+            Box::new(Seaport::new()),
+            Box::new(LooksRare::new()),
+            Box::new(X2Y2::new()),
+            Box::new(ZeroEx::new()),
+            Box::new(Kyber::new()),
+            Box::new(Bancor::new()),
+            Box::new(WethInspector::new()),
+            Box::new(ERC20::new()),
+            Box::new(Permit2::new()),
+            Box::new(GasTokens::new()),
+            Box::new(DyDx::new()),
+            Box::new(FlashLoans::new()),
+            Box::new(OneInch::new()),
+            Box::new(UniversalRouter::new()),
+            Box::new(Balancer::new()),
+            Box::new(BalancerV2::new()),
+            Box::new(UniswapV2::new()),
+            Box::new(UniswapV3::new()),
+            Box::new(UniswapV3Positions::new()),
+            Box::new(Curve::new(vec![])),
+            Box::new(Synthetix::new()),
+            Box::new(Aave::new()),
+            Box::new(AaveV2::new()),
+            Box::new(Compound::new(vec![])),
+            Box::new(CompoundV3::new()),
+            Box::new(MakerDAO::new()),
+            Box::new(EventLogs::new()),
+        ],
+        vec![
+            Box::new(AggregatorReducer::new()),
+            Box::new(LiquidationReducer::new()),
+            Box::new(TradeReducer::new()),
+            Box::new(TransferReducer::new()),
+            Box::new(OracleManipulationReducer::new()),
+            Box::new(ArbitrageReducer::new()),
+            Box::new(JitReducer::new()),
+            Box::new(NftArbitrageReducer::new()),
+            Box::new(NftSweepReducer::new()),
+        ],
+    )
+}
+
+/// Inspects the tx `fixture_hash` of the fixture block with the default
+/// `processor`, and panics with the differences unless its snapshot is the
+/// `expected_json` one
+#[cfg(feature = "serde")]
+pub fn assert_inspection_matches(fixture_hash: &str, expected_json: &str) {
+    assert_processed_matches(get_trace(fixture_hash), expected_json);
+}
+
+/// Inspects the inspection with the default `processor`, and panics with the
+/// differences unless its snapshot is the `expected_json` one
+#[cfg(feature = "serde")]
+pub fn assert_processed_matches(mut inspection: Inspection, expected_json: &str) {
+    let processor = processor();
+    processor.inspect(&mut inspection);
+    processor.reduce(&mut inspection);
+    inspection.prune();
+    assert_snapshot_matches(&inspection, expected_json);
+}
+
+/// Panics with the differences unless the snapshot of the inspection is the
+/// `expected_json` one. The new snapshot is printed, to be checked in when
+/// the classification was meant to change.
+#[cfg(feature = "serde")]
+pub fn assert_snapshot_matches(inspection: &Inspection, expected_json: &str) {
+    let expected = serde_json::from_str(expected_json).expect("invalid expected snapshot");
+    let actual = inspection.snapshot().to_json();
+    let differences = crate::types::snapshot::diff(&expected, &actual);
+    if !differences.is_empty() {
+        let differences = differences
+            .iter()
+            .map(|difference| format!("  {}", difference))
+            .collect::<Vec<_>>()
+            .join("\n");
+        panic!(
+            "the inspection of {:?} differs from its snapshot:\n{}\nits new snapshot is:\n{}",
+            inspection.hash,
+            differences,
+            serde_json::to_string_pretty(&actual).unwrap()
+        );
+    }
+}
+
 #[macro_export]
 macro_rules! set {
     ( $( $x:expr ),* ) => {  // Match zero or more comma delimited items
         {
-            let mut temp_set = std::collections::HashSet::new();  // Create a mutable HashSet
+            let mut temp_set = indexmap::IndexSet::new();  // Create a mutable IndexSet
             $(
-                temp_set.insert($x); // Insert each item matched into the HashSet
+                temp_set.insert($x); // Insert each item matched into the IndexSet
              )*
-                temp_set // Return the populated HashSet
+                temp_set // Return the populated IndexSet
         }
     };
 }
@@ -0,0 +1,346 @@
+use crate::{
+    addresses::{parse_address, ETH, WETH},
+    cache::SharedCache,
+};
+use ethers::{
+    abi::{self, ParamType, Token},
+    providers::Middleware,
+    types::{Address, TransactionRequest, U256},
+    utils::id,
+};
+use once_cell::sync::Lazy;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// The symbol and the decimals of a token
+pub struct TokenInfo {
+    pub symbol: String,
+    pub decimals: u8,
+}
+
+impl TokenInfo {
+    pub fn new<S: Into<String>>(symbol: S, decimals: u8) -> Self {
+        Self {
+            symbol: symbol.into(),
+            decimals,
+        }
+    }
+
+    /// Formats an amount of the token in its units, e.g. "1.5 WETH"
+    pub fn format(&self, amount: U256) -> String {
+        format!("{} {}", format_units(amount, self.decimals), self.symbol)
+    }
+}
+
+/// Tokens which are known without querying a node
+static KNOWN_TOKENS: Lazy<HashMap<Address, TokenInfo>> = Lazy::new(|| {
+    let known = [
+        ("6B175474E89094C44Da98b954EedeAC495271d0F", "DAI", 18),
+        // SAI's `symbol()` is "DAI" as a `bytes32`
+        ("89d24A6b4CcB1B6fAA2625fE562bDD9a23260359", "SAI", 18),
+        ("A0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48", "USDC", 6),
+        ("dAC17F958D2ee523a2206206994597C13D831ec7", "USDT", 6),
+        ("2260FAC5E5542a773Aa44fBCfeDf7C193bc2C599", "WBTC", 8),
+        ("9f8F72aA9304c8B593d555F12eF6589cC3A579A2", "MKR", 18),
+    ];
+    let mut tokens = known
+        .iter()
+        .map(|(address, symbol, decimals)| {
+            (parse_address(address), TokenInfo::new(*symbol, *decimals))
+        })
+        .collect::<HashMap<_, _>>();
+    tokens.insert(*ETH, TokenInfo::new("ETH", 18));
+    tokens.insert(*WETH, TokenInfo::new("WETH", 18));
+    tokens
+});
+
+/// The tokens which have already been queried, `None` for contracts which
+/// do not implement `decimals()`. It is shared by all the threads, and only
+/// keeps the most recently used tokens.
+static TOKENS: Lazy<SharedCache<Address, Option<TokenInfo>>> = Lazy::new(SharedCache::default);
+
+/// Sets how many queried tokens are kept in the cache, which defaults to
+/// `cache::DEFAULT_MAX_ENTRIES`
+pub fn set_max_cached_tokens(max_entries: usize) {
+    TOKENS.set_max_entries(max_entries);
+}
+
+/// Returns the info of `token` if it is known or has already been queried
+pub fn cached_token_info(token: Address) -> Option<TokenInfo> {
+    if let Some(info) = KNOWN_TOKENS.get(&token) {
+        return Some(info.clone());
+    }
+    TOKENS.get(&token).flatten()
+}
+
+/// Returns the symbol and the decimals of `token`, or `None` if it does not
+/// implement `decimals()`, i.e. the call returns nothing or reverts. Tokens
+/// without a readable `symbol()` are named after their address.
+///
+/// Results are cached by address, and shared by all the threads, so the
+/// tokens of a block are only queried once. Errors of the node other than
+/// reverts are returned, and not cached.
+pub async fn token_info<M: Middleware>(
+    token: Address,
+    provider: &M,
+) -> Result<Option<TokenInfo>, M::Error> {
+    if let Some(info) = KNOWN_TOKENS.get(&token) {
+        return Ok(Some(info.clone()));
+    }
+    if let Some(info) = TOKENS.get(&token) {
+        return Ok(info);
+    }
+
+    let call = |signature: &'static str| async move {
+        let tx = TransactionRequest::new()
+            .to(token)
+            .data(id(signature).to_vec());
+        match provider.call(&tx, None).await {
+            Ok(output) => Ok(Some(output)),
+            Err(err) if is_revert(&err) => Ok(None),
+            Err(err) => Err(err),
+        }
+    };
+    let decimals = call("decimals()").await?;
+    let info = match decimals.and_then(|output| decode_decimals(output.as_ref())) {
+        Some(decimals) => {
+            let symbol = call("symbol()").await?;
+            let symbol = symbol
+                .and_then(|output| decode_symbol(output.as_ref()))
+                .unwrap_or_else(|| format!("{:?}", token));
+            Some(TokenInfo::new(symbol, decimals))
+        }
+        None => None,
+    };
+
+    TOKENS.insert(token, info.clone());
+    Ok(info)
+}
+
+/// Whether the node failed a call because it reverted, e.g. with Geth's
+/// "execution reverted" or OpenEthereum's "VM execution error", rather than
+/// because it could not be made
+fn is_revert<E: std::error::Error>(err: &E) -> bool {
+    let message = err.to_string().to_lowercase();
+    message.contains("revert") || message.contains("vm execution error")
+}
+
+/// Decodes the output of `decimals()`, which must fit in a `uint8`
+fn decode_decimals(output: &[u8]) -> Option<u8> {
+    if output.len() < 32 {
+        return None;
+    }
+    let decimals = U256::from_big_endian(&output[..32]);
+    if decimals > U256::from(u8::MAX) {
+        return None;
+    }
+    Some(decimals.low_u32() as u8)
+}
+
+/// Decodes the output of `symbol()`, which is a `bytes32` instead of a
+/// `string` for some old tokens, e.g. MKR and SAI
+fn decode_symbol(output: &[u8]) -> Option<String> {
+    let symbol = if output.len() == 32 {
+        let len = output.iter().position(|b| *b == 0).unwrap_or(32);
+        String::from_utf8(output[..len].to_vec()).ok()?
+    } else {
+        match abi::decode(&[ParamType::String], output).ok()?.as_slice() {
+            [Token::String(symbol)] => symbol.clone(),
+            _ => return None,
+        }
+    };
+
+    if symbol.is_empty() {
+        None
+    } else {
+        Some(symbol)
+    }
+}
+
+/// Formats `amount` with `decimals`, without trailing zeros
+fn format_units(amount: U256, decimals: u8) -> String {
+    let unit = U256::exp10(decimals as usize);
+    let (whole, fraction) = amount.div_mod(unit);
+    if fraction.is_zero() {
+        return whole.to_string();
+    }
+
+    let fraction = format!(
+        "{:0>width$}",
+        fraction.to_string(),
+        width = decimals as usize
+    );
+    format!("{}.{}", whole, fraction.trim_end_matches('0'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_helpers::addrs, types::actions::Transfer};
+    use async_trait::async_trait;
+    use ethers::{
+        providers::{MockProvider, Provider, ProviderError},
+        types::{BlockNumber, Bytes, NameOrAddress},
+    };
+
+    #[test]
+    fn formats_amounts() {
+        let weth = TokenInfo::new("WETH", 18);
+        assert_eq!(weth.format(U256::exp10(18) * 3 / 2), "1.5 WETH");
+        assert_eq!(weth.format(U256::exp10(18)), "1 WETH");
+        assert_eq!(weth.format(1.into()), "0.000000000000000001 WETH");
+        let usdc = TokenInfo::new("USDC", 6);
+        assert_eq!(usdc.format(1_234_500_000.into()), "1234.5 USDC");
+        assert_eq!(TokenInfo::new("X", 0).format(7.into()), "7 X");
+
+        let addrs = addrs();
+        let transfer = |token| Transfer {
+            from: addrs[0],
+            to: addrs[1],
+            amount: U256::exp10(18) * 3 / 2,
+            token,
+        };
+        assert_eq!(transfer(*WETH).readable_amount(), "1.5 WETH");
+        // unknown tokens keep their raw amounts
+        assert_eq!(
+            transfer(addrs[2]).readable_amount(),
+            format!("1500000000000000000 {:?}", addrs[2])
+        );
+    }
+
+    #[test]
+    fn decodes_string_and_bytes32_symbols() {
+        let string = abi::encode(&[Token::String("UNI".to_owned())]);
+        assert_eq!(decode_symbol(&string), Some("UNI".to_owned()));
+
+        let mut bytes32 = [0; 32];
+        bytes32[..3].copy_from_slice(b"MKR");
+        assert_eq!(decode_symbol(&bytes32), Some("MKR".to_owned()));
+
+        assert_eq!(decode_symbol(&[0; 32]), None);
+        assert_eq!(decode_symbol(&[]), None);
+    }
+
+    #[tokio::test]
+    async fn queries_and_caches_tokens() {
+        let addrs = addrs();
+        let (token, old_token, contract) = (addrs[0], addrs[1], addrs[2]);
+        let (provider, mock) = Provider::mocked();
+        let uint = |value: u64| Bytes::from(abi::encode(&[Token::Uint(value.into())]));
+        let mut bytes32 = [0; 32];
+        bytes32[..3].copy_from_slice(b"SAI");
+        // the mock's responses are popped from the back
+        let responses = [
+            // a contract without `decimals()`
+            Bytes::default(),
+            // a token whose symbol is a `bytes32`
+            Bytes::from(bytes32.to_vec()),
+            uint(18),
+            // a regular token
+            Bytes::from(abi::encode(&[Token::String("YFI".to_owned())])),
+            uint(6),
+        ];
+        for response in responses.iter() {
+            mock.push(response.clone()).unwrap();
+        }
+
+        for (address, expected) in [
+            (token, Some(TokenInfo::new("YFI", 6))),
+            (old_token, Some(TokenInfo::new("SAI", 18))),
+            (contract, None),
+        ]
+        .iter()
+        {
+            assert_eq!(token_info(*address, &provider).await.unwrap(), *expected);
+        }
+
+        // all the responses were consumed, so these come from the cache
+        assert_eq!(
+            token_info(token, &provider).await.unwrap(),
+            Some(TokenInfo::new("YFI", 6))
+        );
+        assert_eq!(cached_token_info(contract), None);
+        // the known tokens are never queried
+        assert_eq!(
+            token_info(*WETH, &provider).await.unwrap(),
+            Some(TokenInfo::new("WETH", 18))
+        );
+    }
+
+    /// Fails the calls to `fails`, as if the node could not be reached, and
+    /// reverts the listed calls
+    #[derive(Debug)]
+    struct Reverting {
+        inner: Provider<MockProvider>,
+        reverts: Vec<(Address, &'static str)>,
+        fails: Address,
+    }
+
+    #[async_trait]
+    impl Middleware for Reverting {
+        type Error = ProviderError;
+        type Provider = MockProvider;
+        type Inner = Provider<MockProvider>;
+
+        fn inner(&self) -> &Self::Inner {
+            &self.inner
+        }
+
+        async fn call(
+            &self,
+            tx: &TransactionRequest,
+            block: Option<BlockNumber>,
+        ) -> Result<Bytes, Self::Error> {
+            let to = match tx.to {
+                Some(NameOrAddress::Address(to)) => to,
+                _ => unreachable!(),
+            };
+            let error = |message: &str| {
+                let error = std::io::Error::new(std::io::ErrorKind::Other, message);
+                ProviderError::JsonRpcClientError(Box::new(error))
+            };
+            if to == self.fails {
+                return Err(error("connection refused"));
+            }
+            let input = tx.data.as_ref().unwrap().as_ref();
+            if self
+                .reverts
+                .iter()
+                .any(|(token, signature)| *token == to && input == id(signature))
+            {
+                return Err(error("(code: 3, message: execution reverted, data: None)"));
+            }
+            self.inner.call(tx, block).await
+        }
+    }
+
+    #[tokio::test]
+    async fn reverted_calls_are_not_implemented() {
+        let addrs = addrs();
+        let (contract, token, unreachable) = (addrs[3], addrs[4], addrs[5]);
+        let (inner, mock) = Provider::mocked();
+        // only the token's `decimals()` gets to the mock
+        mock.push(Bytes::from(abi::encode(&[Token::Uint(8.into())])))
+            .unwrap();
+        let provider = Reverting {
+            inner,
+            reverts: vec![(contract, "decimals()"), (token, "symbol()")],
+            fails: unreachable,
+        };
+
+        assert_eq!(token_info(contract, &provider).await.unwrap(), None);
+        assert_eq!(
+            token_info(token, &provider).await.unwrap(),
+            Some(TokenInfo::new(format!("{:?}", token), 8))
+        );
+        assert!(token_info(unreachable, &provider).await.is_err());
+
+        // the reverted calls were cached, but not the failed one
+        assert_eq!(TOKENS.get(&contract), Some(None));
+        assert!(TOKENS.get(&token).is_some());
+        assert_eq!(TOKENS.get(&unreachable), None);
+    }
+}
@@ -0,0 +1,618 @@
+use crate::types::actions::Transfer;
+use async_trait::async_trait;
+use ethers::{
+    providers::{JsonRpcClient, ProviderError},
+    types::{Address, BlockNumber, Trace, TransactionRequest, TxHash, U256, U64},
+};
+use futures::future;
+use serde::{Deserialize, Serialize};
+use std::{
+    convert::TryFrom,
+    fmt::Display,
+    io,
+    ops::Range,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+/// A method which the provider does not serve, e.g. the `debug_` namespace of
+/// a database of traces
+#[error("{0} is not supported by this provider")]
+pub struct Unsupported(pub &'static str);
+
+impl From<Unsupported> for ProviderError {
+    fn from(err: Unsupported) -> Self {
+        ProviderError::CustomError(err.to_string())
+    }
+}
+
+impl From<Unsupported> for io::Error {
+    fn from(err: Unsupported) -> Self {
+        io::Error::other(err)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "u8", into = "u8")]
+/// The kind of an internal operation, which Otterscan numbers 0 to 3
+pub enum OperationKind {
+    Transfer,
+    SelfDestruct,
+    Create,
+    Create2,
+}
+
+impl TryFrom<u8> for OperationKind {
+    type Error = String;
+
+    fn try_from(kind: u8) -> Result<Self, Self::Error> {
+        match kind {
+            0 => Ok(OperationKind::Transfer),
+            1 => Ok(OperationKind::SelfDestruct),
+            2 => Ok(OperationKind::Create),
+            3 => Ok(OperationKind::Create2),
+            _ => Err(format!("unknown internal operation type {}", kind)),
+        }
+    }
+}
+
+impl From<OperationKind> for u8 {
+    fn from(kind: OperationKind) -> Self {
+        kind as u8
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// An ETH transfer, self destruct or contract creation made by a contract
+/// during a transaction, as returned by Otterscan's `ots_getInternalOperations`.
+/// Unlike traces these have no calldata, so they only tell where the
+/// transaction's ETH went.
+pub struct InternalOperation {
+    #[serde(rename = "type")]
+    pub kind: OperationKind,
+    pub from: Address,
+    /// The created contract for creations, or the beneficiary of self destructs
+    pub to: Address,
+    pub value: U256,
+}
+
+impl InternalOperation {
+    /// The transfer of `value` to `to`, with `native` as the token, for the
+    /// operations which moved any ETH
+    pub fn transfer(&self, native: Address) -> Option<Transfer> {
+        if self.value.is_zero() {
+            return None;
+        }
+        Some(Transfer {
+            from: self.from,
+            to: self.to,
+            amount: self.value,
+            token: native,
+        })
+    }
+}
+
+/// A source of traces, e.g. a node's `trace_` and `debug_` namespaces, Erigon's
+/// `ots_` namespace or a local database of traces
+#[async_trait]
+pub trait TraceProvider: Send + Sync {
+    type Error: std::error::Error + From<Unsupported> + Send + Sync + 'static;
+
+    /// The Parity-style traces of a transaction
+    async fn trace_transaction(&self, hash: TxHash) -> Result<Vec<Trace>, Self::Error>;
+
+    /// The Parity-style traces of all the transactions of a block, along with
+    /// its reward traces
+    async fn trace_block(&self, number: u64) -> Result<Vec<Trace>, Self::Error>;
+
+    /// The output of Geth's `debug_traceTransaction` with the given tracer
+    /// options, e.g. `{"tracer": "callTracer"}`. Unsupported unless
+    /// implemented.
+    async fn debug_trace_transaction(
+        &self,
+        _hash: TxHash,
+        _options: serde_json::Value,
+    ) -> Result<serde_json::Value, Self::Error> {
+        Err(Unsupported("debug_traceTransaction").into())
+    }
+
+    /// The output of Geth's `debug_traceCall`, which simulates `tx` on top of
+    /// the state at `block`, with the given tracer options. Besides the tracer
+    /// they may override the block's header (`blockOverrides`) and accounts'
+    /// state (`stateOverrides`). Unsupported unless implemented.
+    async fn debug_trace_call(
+        &self,
+        _tx: &TransactionRequest,
+        _block: BlockNumber,
+        _options: serde_json::Value,
+    ) -> Result<serde_json::Value, Self::Error> {
+        Err(Unsupported("debug_traceCall").into())
+    }
+
+    /// The hashes of the transactions of block `number`, in order, which
+    /// `trace_block_or_transactions` traces one at a time if need be.
+    /// Unsupported unless implemented.
+    async fn block_transactions(&self, _number: u64) -> Result<Vec<TxHash>, Self::Error> {
+        Err(Unsupported("eth_getBlockByNumber").into())
+    }
+
+    /// The internal operations of a transaction, from Erigon's
+    /// `ots_getInternalOperations`. Unsupported unless implemented.
+    async fn internal_operations(
+        &self,
+        _hash: TxHash,
+    ) -> Result<Vec<InternalOperation>, Self::Error> {
+        Err(Unsupported("ots_getInternalOperations").into())
+    }
+
+    /// The traces of the transactions `txs` of block `number`, fetched with a
+    /// single `trace_block` if the provider serves it, or else one transaction
+    /// at a time, e.g. for nodes which only serve `trace_transaction`. Only the
+    /// traces fetched with `trace_block` include the block's reward traces.
+    async fn trace_block_or_transactions(
+        &self,
+        number: u64,
+        txs: &[TxHash],
+    ) -> Result<Vec<Trace>, Self::Error> {
+        match self.trace_block(number).await {
+            Ok(traces) => Ok(traces),
+            Err(err) => {
+                log::debug!("trace_block {} failed, tracing its txs: {}", number, err);
+                self.trace_transactions(txs).await
+            }
+        }
+    }
+
+    /// The traces of each of the transactions, in order
+    async fn trace_transactions(&self, txs: &[TxHash]) -> Result<Vec<Trace>, Self::Error> {
+        let mut traces = Vec::new();
+        for hash in txs {
+            traces.extend(self.trace_transaction(*hash).await?);
+        }
+        Ok(traces)
+    }
+
+    /// The traces of each block of the range, in order
+    async fn trace_blocks(&self, blocks: Range<u64>) -> Result<Vec<Vec<Trace>>, Self::Error> {
+        let mut traces = Vec::new();
+        for number in blocks {
+            traces.push(self.trace_block(number).await?);
+        }
+        Ok(traces)
+    }
+}
+
+/// Fetches traces over an `ethers` transport, retrying the requests which
+/// failed because of transient errors with an exponential backoff
+#[derive(Debug)]
+pub struct EthersTraceProvider<P: JsonRpcClient> {
+    client: P,
+    /// How many times a failed request is retried
+    retries: usize,
+    /// How long to wait before the first retry, doubled for each next one
+    backoff: Duration,
+    /// How many blocks `trace_blocks` fetches concurrently, and how many
+    /// transactions are traced concurrently when `trace_block` is not served
+    batch_size: usize,
+    /// Whether a failed request should be retried
+    retry_if: fn(&ProviderError) -> bool,
+    /// Set once `trace_block` failed with an error which is not retried, e.g.
+    /// because the node does not serve it, so that it is not tried again
+    no_trace_block: AtomicBool,
+}
+
+impl<P: JsonRpcClient> EthersTraceProvider<P> {
+    /// Instantiates a provider which retries requests 3 times, starting with
+    /// a 500ms backoff, and fetches 10 blocks at a time
+    pub fn new(client: P) -> Self {
+        Self {
+            client,
+            retries: 3,
+            backoff: Duration::from_millis(500),
+            batch_size: 10,
+            retry_if: is_transient,
+            no_trace_block: AtomicBool::new(false),
+        }
+    }
+
+    /// Sets how many times failed requests are retried, and the backoff before
+    /// the first retry
+    pub fn with_retries(mut self, retries: usize, backoff: Duration) -> Self {
+        self.retries = retries;
+        self.backoff = backoff;
+        self
+    }
+
+    /// Sets how many blocks `trace_blocks` fetches concurrently
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Sets which errors are retried, instead of `is_transient`
+    pub fn with_retry_if(mut self, retry_if: fn(&ProviderError) -> bool) -> Self {
+        self.retry_if = retry_if;
+        self
+    }
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, ProviderError>
+    where
+        T: std::fmt::Debug + serde::Serialize + Send + Sync + Clone,
+        R: serde::Serialize + serde::de::DeserializeOwned + Send,
+    {
+        let mut backoff = self.backoff;
+        let mut retries = 0;
+        loop {
+            match self
+                .client
+                .request(method, params.clone())
+                .await
+                .map_err(Into::into)
+            {
+                Ok(res) => return Ok(res),
+                Err(err) if retries < self.retries && (self.retry_if)(&err) => {
+                    log::warn!("{} failed, retrying in {:?}: {}", method, backoff, err);
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                    retries += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<P: JsonRpcClient> TraceProvider for EthersTraceProvider<P> {
+    type Error = ProviderError;
+
+    async fn trace_transaction(&self, hash: TxHash) -> Result<Vec<Trace>, Self::Error> {
+        self.request("trace_transaction", [hash]).await
+    }
+
+    async fn trace_block(&self, number: u64) -> Result<Vec<Trace>, Self::Error> {
+        self.request("trace_block", [U64::from(number)]).await
+    }
+
+    async fn debug_trace_transaction(
+        &self,
+        hash: TxHash,
+        options: serde_json::Value,
+    ) -> Result<serde_json::Value, Self::Error> {
+        let params = (hash, options);
+        self.request("debug_traceTransaction", params).await
+    }
+
+    async fn debug_trace_call(
+        &self,
+        tx: &TransactionRequest,
+        block: BlockNumber,
+        options: serde_json::Value,
+    ) -> Result<serde_json::Value, Self::Error> {
+        let params = (tx.clone(), block, options);
+        self.request("debug_traceCall", params).await
+    }
+
+    async fn block_transactions(&self, number: u64) -> Result<Vec<TxHash>, Self::Error> {
+        let block: Option<BlockHashes> = self
+            .request("eth_getBlockByNumber", (U64::from(number), false))
+            .await?;
+        block
+            .map(|block| block.transactions)
+            .ok_or_else(|| ProviderError::CustomError(format!("block {} not found", number)))
+    }
+
+    async fn internal_operations(
+        &self,
+        hash: TxHash,
+    ) -> Result<Vec<InternalOperation>, Self::Error> {
+        self.request("ots_getInternalOperations", [hash]).await
+    }
+
+    async fn trace_block_or_transactions(
+        &self,
+        number: u64,
+        txs: &[TxHash],
+    ) -> Result<Vec<Trace>, Self::Error> {
+        if !self.no_trace_block.load(Ordering::Relaxed) {
+            match self.trace_block(number).await {
+                Ok(traces) => return Ok(traces),
+                Err(err) => {
+                    if !(self.retry_if)(&err) {
+                        log::warn!("trace_block is not served, tracing each tx: {}", err);
+                        self.no_trace_block.store(true, Ordering::Relaxed);
+                    } else {
+                        log::debug!("trace_block {} failed, tracing its txs: {}", number, err);
+                    }
+                }
+            }
+        }
+        self.trace_transactions(txs).await
+    }
+
+    async fn trace_transactions(&self, txs: &[TxHash]) -> Result<Vec<Trace>, Self::Error> {
+        let mut traces = Vec::new();
+        for batch in txs.chunks(self.batch_size) {
+            let batch = batch.iter().map(|hash| self.trace_transaction(*hash));
+            traces.extend(future::try_join_all(batch).await?.into_iter().flatten());
+        }
+        Ok(traces)
+    }
+
+    async fn trace_blocks(&self, blocks: Range<u64>) -> Result<Vec<Vec<Trace>>, Self::Error> {
+        let blocks = blocks.collect::<Vec<_>>();
+        let mut traces = Vec::with_capacity(blocks.len());
+        for batch in blocks.chunks(self.batch_size) {
+            let batch = batch.iter().map(|number| self.trace_block(*number));
+            traces.extend(future::try_join_all(batch).await?);
+        }
+        Ok(traces)
+    }
+}
+
+/// The part of a block without its transactions' bodies which we read
+#[derive(Serialize, Deserialize)]
+struct BlockHashes {
+    transactions: Vec<TxHash>,
+}
+
+/// Whether a failed request is worth retrying. The node's JSON-RPC errors are
+/// final, e.g. for unsupported methods or unknown txs, unless they signal that
+/// the node is rate limiting, while transport errors such as timeouts are not.
+///
+/// `ethers` does not export the JSON-RPC error type, so they are told apart
+/// by the `(code: .., message: ..)` they are displayed as.
+pub fn is_transient<E: Display>(err: &E) -> bool {
+    let err = err.to_string();
+    match err.strip_prefix("(code: ") {
+        Some(rest) => {
+            let code = rest.split(',').next().unwrap_or_default();
+            // -32005 is the standard "limit exceeded" error
+            code == "-32005" || code == "429"
+        }
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::TRACES;
+    use ethers::providers::{MockError, MockProvider};
+    use serde::{de::DeserializeOwned, Serialize};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// A transport which fails its first requests
+    #[derive(Debug)]
+    struct Flaky {
+        failures: AtomicUsize,
+        inner: MockProvider,
+    }
+
+    #[async_trait]
+    impl JsonRpcClient for Flaky {
+        type Error = MockError;
+
+        async fn request<T, R>(&self, method: &str, params: T) -> Result<R, MockError>
+        where
+            T: std::fmt::Debug + Serialize + Send + Sync,
+            R: Serialize + DeserializeOwned,
+        {
+            if self.failures.load(Ordering::SeqCst) > 0 {
+                self.failures.fetch_sub(1, Ordering::SeqCst);
+                return Err(MockError::EmptyResponses);
+            }
+            self.inner.request(method, params).await
+        }
+    }
+
+    fn flaky(failures: usize) -> Flaky {
+        let inner = MockProvider::new();
+        inner.push::<Vec<Trace>, _>(TRACES.clone()).unwrap();
+        Flaky {
+            failures: AtomicUsize::new(failures),
+            inner,
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_with_backoff() {
+        let provider = EthersTraceProvider::new(flaky(2)).with_retries(2, Duration::from_millis(1));
+        let traces = provider.trace_block(11017338).await.unwrap();
+        assert_eq!(traces.len(), TRACES.len());
+        provider
+            .client
+            .inner
+            .assert_request("trace_block", [U64::from(11017338)])
+            .unwrap();
+
+        // gives up once it ran out of retries
+        let provider = EthersTraceProvider::new(flaky(3)).with_retries(2, Duration::from_millis(1));
+        assert!(provider.trace_block(11017338).await.is_err());
+
+        // and does not retry the errors which are not transient
+        let provider = EthersTraceProvider::new(flaky(1))
+            .with_retries(2, Duration::from_millis(1))
+            .with_retry_if(|_| false);
+        assert!(provider.trace_block(11017338).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_tracing_each_tx() {
+        let hashes = [
+            TRACES[0].transaction_hash.unwrap(),
+            TRACES[5].transaction_hash.unwrap(),
+        ];
+        let traces_of = |hash: TxHash| {
+            TRACES
+                .iter()
+                .filter(|t| t.transaction_hash == Some(hash))
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        // the node does not serve `trace_block`
+        let provider = EthersTraceProvider::new(Flaky {
+            failures: AtomicUsize::new(1),
+            inner: MockProvider::new(),
+        })
+        .with_retry_if(|_| false);
+
+        for _ in 0..2 {
+            // the responses are popped from the back
+            for hash in hashes.iter().rev() {
+                provider
+                    .client
+                    .inner
+                    .push::<Vec<Trace>, _>(traces_of(*hash))
+                    .unwrap();
+            }
+            let traces = provider
+                .trace_block_or_transactions(11017338, &hashes)
+                .await
+                .unwrap();
+            assert_eq!(
+                traces,
+                [traces_of(hashes[0]), traces_of(hashes[1])].concat()
+            );
+            // and is not asked for it again
+            for hash in hashes.iter() {
+                provider
+                    .client
+                    .inner
+                    .assert_request("trace_transaction", [*hash])
+                    .unwrap();
+            }
+        }
+
+        // a node which serves it traces the block in one request
+        let provider = EthersTraceProvider::new(flaky(0));
+        let traces = provider
+            .trace_block_or_transactions(11017338, &hashes)
+            .await
+            .unwrap();
+        assert_eq!(traces.len(), TRACES.len());
+        provider
+            .client
+            .inner
+            .assert_request("trace_block", [U64::from(11017338)])
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn traces_blocks_in_batches() {
+        let inner = MockProvider::new();
+        for i in 0..5u64 {
+            inner
+                .push::<Vec<Trace>, _>(TRACES[..i as usize].to_vec())
+                .unwrap();
+        }
+        let provider = EthersTraceProvider::new(inner).with_batch_size(2);
+        let traces = provider.trace_blocks(1..6).await.unwrap();
+        // the responses are popped from the back
+        let lens = traces.iter().map(|traces| traces.len()).collect::<Vec<_>>();
+        assert_eq!(lens, vec![4, 3, 2, 1, 0]);
+    }
+
+    #[tokio::test]
+    async fn debug_methods_are_unsupported_by_default() {
+        struct Traces;
+
+        #[async_trait]
+        impl TraceProvider for Traces {
+            type Error = io::Error;
+
+            async fn trace_transaction(&self, _: TxHash) -> Result<Vec<Trace>, Self::Error> {
+                Ok(vec![])
+            }
+
+            async fn trace_block(&self, _: u64) -> Result<Vec<Trace>, Self::Error> {
+                Ok(TRACES.clone())
+            }
+        }
+
+        let err = Traces
+            .debug_trace_call(
+                &TransactionRequest::new(),
+                BlockNumber::Latest,
+                serde_json::json!({}),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "debug_traceCall is not supported by this provider"
+        );
+        assert!(Traces
+            .debug_trace_transaction(TxHash::zero(), serde_json::json!({}))
+            .await
+            .is_err());
+        assert!(Traces.internal_operations(TxHash::zero()).await.is_err());
+        assert!(Traces.block_transactions(11017338).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn fetches_the_transactions_of_a_block() {
+        let inner = MockProvider::new();
+        let hashes = vec![TxHash::repeat_byte(1), TxHash::repeat_byte(2)];
+        inner
+            .push(serde_json::json!({ "number": "0xa81d3a", "transactions": hashes }))
+            .unwrap();
+        let provider = EthersTraceProvider::new(inner);
+        assert_eq!(provider.block_transactions(11017338).await.unwrap(), hashes);
+        provider
+            .client
+            .assert_request("eth_getBlockByNumber", (U64::from(11017338), false))
+            .unwrap();
+
+        // unknown blocks are an error rather than empty
+        provider.client.push(serde_json::Value::Null).unwrap();
+        assert!(provider.block_transactions(11017338).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn fetches_internal_operations() {
+        let inner = MockProvider::new();
+        let (alice, bob) = (Address::repeat_byte(1), Address::repeat_byte(2));
+        inner
+            .push(serde_json::json!([
+                { "type": 0, "from": alice, "to": bob, "value": "0xde0b6b3a7640000" },
+                { "type": 2, "from": alice, "to": bob, "value": "0x0" },
+            ]))
+            .unwrap();
+        let provider = EthersTraceProvider::new(inner);
+        let hash = TxHash::repeat_byte(3);
+        let operations = provider.internal_operations(hash).await.unwrap();
+        provider
+            .client
+            .assert_request("ots_getInternalOperations", [hash])
+            .unwrap();
+
+        assert_eq!(operations[0].kind, OperationKind::Transfer);
+        assert_eq!(operations[1].kind, OperationKind::Create);
+        let native = Address::repeat_byte(0xee);
+        assert_eq!(
+            operations[0].transfer(native),
+            Some(Transfer {
+                from: alice,
+                to: bob,
+                amount: U256::exp10(18),
+                token: native,
+            })
+        );
+        // creations which did not fund the contract moved no ETH
+        assert_eq!(operations[1].transfer(native), None);
+    }
+
+    #[test]
+    fn classifies_transient_errors() {
+        assert!(is_transient(&"error sending request: operation timed out"));
+        assert!(is_transient(
+            &"(code: -32005, message: rate limit exceeded, data: None)"
+        ));
+        assert!(!is_transient(
+            &"(code: -32601, message: the method trace_block does not exist, data: None)"
+        ));
+    }
+}
@@ -7,8 +7,26 @@ pub trait Reducer {
     fn reduce(&self, _: &mut Inspection);
 }
 
+/// Reducers which look for actions across the transactions of a block
+pub trait BlockReducer {
+    /// Reduces a block's inspections, which must be in the same order as
+    /// their transactions
+    fn reduce_block(&self, inspections: &mut [Inspection]);
+}
+
 /// Trait for defining an inspector for a specific DeFi protocol
+///
+/// Inspecting is done in two phases: each inspector classifies what it can,
+/// and may leave facts which depend on the other inspectors' classifications
+/// on the inspection, e.g. UniswapV2's preflight reads, which only tag their
+/// protocols if another inspector finds a trade after them. These are
+/// resolved by `InspectorRegistry::inspect_all` once all its inspectors ran.
+/// Inspectors which are run by hand must be followed by a call to
+/// `Inspection::resolve_preflights`.
 pub trait Inspector: core::fmt::Debug {
+    /// The name which the inspector is selected by, e.g. "uniswap"
+    fn name(&self) -> &'static str;
+
     /// Classifies an inspection's actions
     fn inspect(&self, inspection: &mut Inspection);
 }
@@ -1,24 +1,53 @@
-use crate::addresses::lookup;
+use crate::{
+    addresses::{lookup, REBASING},
+    prices::{amount_out, net_profit, signed, to_f64, usd_value, PriceOracle},
+    tokens::cached_token_info,
+    types::{gas::LONDON_BLOCK, Protocol},
+};
 
-use ethers::types::{Address, Bytes, U256};
+use ethers::types::{Address, Bytes, TxHash, I256, U256};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 use std::fmt;
 
 // https://github.com/flashbots/mev-inspect/blob/master/src/types.ts#L65-L87
 #[derive(Debug, Clone, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 /// The types of actions
 pub enum SpecificAction {
     WethDeposit(Deposit),
     WethWithdrawal(Withdrawal),
 
     Transfer(Transfer),
+    Approval(Approval),
+    NftTransfer(NftTransfer),
+    NftTrade(NftTrade),
+    NftSweep(NftSweep),
+    MinerPayment(MinerPayment),
+    Deployment(Deployment),
+    SelfDestruct(SelfDestruct),
+    GasToken(GasToken),
     Trade(Trade),
     Liquidation(Liquidation),
+    CollateralAbsorption(CollateralAbsorption),
+    FlashLoan(FlashLoan),
 
     AddLiquidity(AddLiquidity),
+    RemoveLiquidity(RemoveLiquidity),
+    JitLiquidity(JitLiquidity),
 
     Arbitrage(Arbitrage),
+    OpenArbitrage(OpenArbitrage),
+    Sandwich(Box<Sandwich>),
+    PartialSandwich(PartialSandwich),
+    Frontrun(Box<Frontrun>),
     ProfitableLiquidation(ProfitableLiquidation),
+    OracleManipulation(Box<OracleManipulation>),
+    LiquidationCompetition(LiquidationCompetition),
+    NftArbitrage(Box<NftArbitrage>),
+    StatisticalArbitrageCandidate(StatisticalArbitrageCandidate),
+    AllowanceExploit(AllowanceExploit),
 
     Unclassified(Bytes),
 
@@ -26,9 +55,12 @@ pub enum SpecificAction {
 }
 
 #[derive(Debug, Clone, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct AddLiquidity {
     pub tokens: Vec<Address>,
     pub amounts: Vec<U256>,
+    /// Set for concentrated liquidity positions, e.g. Uniswap V3
+    pub position: Option<LiquidityPosition>,
 }
 
 impl From<AddLiquidity> for SpecificAction {
@@ -37,7 +69,531 @@ impl From<AddLiquidity> for SpecificAction {
     }
 }
 
+impl AddLiquidity {
+    /// Liquidity added to a pool, with the amount of each token
+    pub fn new(tokens: Vec<Address>, amounts: Vec<U256>) -> Self {
+        Self {
+            tokens,
+            amounts,
+            position: None,
+        }
+    }
+
+    /// Sets the concentrated liquidity position which the liquidity was added to
+    pub fn with_position(mut self, position: LiquidityPosition) -> Self {
+        self.position = Some(position);
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RemoveLiquidity {
+    pub tokens: Vec<Address>,
+    /// The liquidity which was withdrawn
+    pub amounts: Vec<U256>,
+    /// Any fees which were collected along with the liquidity
+    pub fees: Vec<U256>,
+    /// Set for concentrated liquidity positions, e.g. Uniswap V3
+    pub position: Option<LiquidityPosition>,
+}
+
+impl From<RemoveLiquidity> for SpecificAction {
+    fn from(src: RemoveLiquidity) -> Self {
+        SpecificAction::RemoveLiquidity(src)
+    }
+}
+
+impl RemoveLiquidity {
+    /// Liquidity withdrawn from a pool, with the amount of each token and
+    /// without any fees
+    pub fn new(tokens: Vec<Address>, amounts: Vec<U256>) -> Self {
+        Self {
+            tokens,
+            amounts,
+            fees: Vec::new(),
+            position: None,
+        }
+    }
+
+    /// Sets the fees of each token which were collected along with the
+    /// liquidity
+    pub fn with_fees(mut self, fees: Vec<U256>) -> Self {
+        self.fees = fees;
+        self
+    }
+
+    /// Sets the concentrated liquidity position which the liquidity was
+    /// withdrawn from
+    pub fn with_position(mut self, position: LiquidityPosition) -> Self {
+        self.position = Some(position);
+        self
+    }
+}
+
+#[derive(Clone, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// Liquidity which was added right before some trades and removed right
+/// after them, in order to earn their fees
+pub struct JitLiquidity {
+    pub pool: Address,
+    pub tokens: Vec<Address>,
+    pub added: Vec<U256>,
+    pub removed: Vec<U256>,
+    pub fees: Vec<U256>,
+    /// The trades which the liquidity was provided for
+    pub trades: Vec<Trade>,
+    /// The LP's net change in each token, `removed + fees - added`
+    pub deltas: Vec<I256>,
+}
+
+impl From<JitLiquidity> for SpecificAction {
+    fn from(src: JitLiquidity) -> Self {
+        SpecificAction::JitLiquidity(src)
+    }
+}
+
+impl fmt::Debug for JitLiquidity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JitLiquidity")
+            .field("pool", &lookup(self.pool))
+            .field(
+                "tokens",
+                &self.tokens.iter().map(|t| lookup(*t)).collect::<Vec<_>>(),
+            )
+            .field("added", &self.added)
+            .field("removed", &self.removed)
+            .field("fees", &self.fees)
+            .field("trades", &self.trades)
+            .field("deltas", &self.deltas)
+            .finish()
+    }
+}
+
+#[derive(Clone, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// A trade which was front-run and back-run on the same pool by the same
+/// searcher, across the transactions of a block
+pub struct Sandwich {
+    pub pool: Address,
+    /// The tx of the frontrun, which the sandwich is attached to
+    pub frontrun_tx: TxHash,
+    /// The tx of the backrun
+    pub backrun_tx: TxHash,
+    /// The searcher's trade before the victims'
+    pub frontrun: Trade,
+    /// The trades on the pool in the same direction as the frontrun, made
+    /// between the two legs, in block order
+    pub victims: Vec<VictimTrade>,
+    /// The searcher's trade back into the token it sold in the frontrun
+    pub backrun: Trade,
+    /// The token which the searcher sold in the frontrun
+    pub token: Address,
+    /// The searcher's net change in `token`, before gas
+    pub profit: I256,
+    /// The amount of `token` which the searcher put up for the frontrun, i.e.
+    /// its input minus the flash loans which funded it
+    pub capital: U256,
+    /// The sender and the contract of the frontrun's tx
+    pub searcher: Searcher,
+}
+
+impl From<Sandwich> for SpecificAction {
+    fn from(src: Sandwich) -> Self {
+        SpecificAction::Sandwich(Box::new(src))
+    }
+}
+
+impl Sandwich {
+    /// Whether the frontrun and the backrun were made in the same transaction.
+    /// The victims' trades are in other transactions, so a sandwich is only
+    /// atomic if its legs surround trades which the searcher bundled in its own
+    /// tx. A frontrun and a backrun in consecutive transactions, i.e. with no
+    /// other tx in between, are still non-atomic. The `SandwichReducer` only
+    /// looks for backruns in the transactions after the frontrun's, so the
+    /// sandwiches it finds are always non-atomic.
+    pub fn is_atomic(&self) -> bool {
+        self.frontrun_tx == self.backrun_tx
+    }
+
+    /// The searcher's return on the capital it put at risk, e.g. 0.05 for a
+    /// profit of 5% of it. `None` if the frontrun was entirely flash loaned,
+    /// since nothing was at risk then.
+    pub fn roi(&self) -> Option<f64> {
+        if self.capital.is_zero() {
+            return None;
+        }
+        let profit = to_f64(self.profit.abs().into_raw());
+        let roi = profit / to_f64(self.capital);
+        Some(if self.profit.is_negative() { -roi } else { roi })
+    }
+
+    /// The amounts which the victims would have received, had they not been
+    /// front-run, given the reserves of `token` and of the bought token in the
+    /// pool before the frontrun. Each victim trades against the reserves left
+    /// by the previous ones, which assumes a constant product pool with a 0.3%
+    /// fee, see `prices::amount_out`.
+    pub fn counterfactual_outputs(&self, reserve_in: U256, reserve_out: U256) -> Option<Vec<U256>> {
+        let (mut reserve_in, mut reserve_out) = (reserve_in, reserve_out);
+        self.victims
+            .iter()
+            .map(|victim| {
+                let amount_in = victim.trade.t1.amount;
+                let out = amount_out(amount_in, reserve_in, reserve_out)?;
+                reserve_in = reserve_in.checked_add(amount_in)?;
+                reserve_out = reserve_out.checked_sub(out)?;
+                Some(out)
+            })
+            .collect()
+    }
+
+    /// The victims' realized slippage, as the fraction of their counterfactual
+    /// output which they lost to the frontrun, e.g. 0.02 if a victim received 2%
+    /// less than it would have without it. The reserves of the pool are the ones
+    /// returned by the oracle at `block`, which must be from before the frontrun.
+    pub fn victim_slippage<O: PriceOracle>(&self, oracle: &O, block: u64) -> Option<Vec<f64>> {
+        let (reserve_in, reserve_out) = oracle.reserves_at(
+            self.pool,
+            self.frontrun.t1.token,
+            self.frontrun.t2.token,
+            block,
+        )?;
+        let counterfactual = self.counterfactual_outputs(reserve_in, reserve_out)?;
+        self.victims
+            .iter()
+            .zip(counterfactual)
+            .map(|(victim, out)| {
+                if out.is_zero() {
+                    return None;
+                }
+                Some(1.0 - to_f64(victim.trade.t2.amount) / to_f64(out))
+            })
+            .collect()
+    }
+
+    /// The total value extracted from the victims, as the sum of what each one
+    /// received less than its counterfactual output, in the token bought by the
+    /// frontrun. The reserves are the pool's before the frontrun, as in
+    /// `counterfactual_outputs`.
+    pub fn extracted_value(&self, reserve_in: U256, reserve_out: U256) -> Option<U256> {
+        let counterfactual = self.counterfactual_outputs(reserve_in, reserve_out)?;
+        Some(
+            self.victims
+                .iter()
+                .zip(counterfactual)
+                .fold(U256::zero(), |sum, (victim, out)| {
+                    sum.saturating_add(out.saturating_sub(victim.trade.t2.amount))
+                }),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// Who made an action: the EOA which sent its tx, and the contract which the
+/// tx called. Searchers usually run their MEV through a bot contract, so the
+/// EOA is the account which funds and controls it.
+pub struct Searcher {
+    pub eoa: Address,
+    pub contract: Address,
+}
+
+#[derive(Debug, Clone, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// A victim's trade in a sandwich
+pub struct VictimTrade {
+    /// The victim's tx
+    pub tx: TxHash,
+    pub trade: Trade,
+}
+
+impl fmt::Debug for Sandwich {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sandwich")
+            .field("pool", &lookup(self.pool))
+            .field("frontrun_tx", &self.frontrun_tx)
+            .field("backrun_tx", &self.backrun_tx)
+            .field("frontrun", &self.frontrun)
+            .field("victims", &self.victims)
+            .field("backrun", &self.backrun)
+            .field("token", &lookup(self.token))
+            .field("profit", &self.profit)
+            .field("capital", &self.capital)
+            .field("searcher", &self.searcher)
+            .finish()
+    }
+}
+
+#[derive(Clone, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// A frontrun whose victims traded after it, but which was not backrun in the
+/// block, leaving the searcher with the tokens it bought
+pub struct PartialSandwich {
+    pub pool: Address,
+    /// The tx of the frontrun, which the partial sandwich is attached to
+    pub frontrun_tx: TxHash,
+    /// The searcher's trade before the victims'
+    pub frontrun: Trade,
+    /// The trades on the pool in the same direction as the frontrun, made
+    /// after it, in block order
+    pub victims: Vec<VictimTrade>,
+    /// Whether the searcher made no backrun at all, e.g. because it was
+    /// censored, rather than one which reverted
+    pub backrun_missing: bool,
+}
+
+impl From<PartialSandwich> for SpecificAction {
+    fn from(src: PartialSandwich) -> Self {
+        SpecificAction::PartialSandwich(src)
+    }
+}
+
+impl fmt::Debug for PartialSandwich {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PartialSandwich")
+            .field("pool", &lookup(self.pool))
+            .field("frontrun_tx", &self.frontrun_tx)
+            .field("frontrun", &self.frontrun)
+            .field("victims", &self.victims)
+            .field("backrun_missing", &self.backrun_missing)
+            .finish()
+    }
+}
+
+#[derive(Clone, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// A trade which was copied by a searcher and included before the original,
+/// by paying a higher gas price
+pub struct Frontrun {
+    /// The tx which made the copy
+    pub frontrunner_tx: TxHash,
+    /// The tx which made the original trade, later in the block
+    pub victim_tx: TxHash,
+    pub pool: Address,
+    /// The frontrunner's trade
+    pub frontrun: Trade,
+    /// The victim's trade on the same pool and in the same direction
+    pub victim: Trade,
+}
+
+impl From<Frontrun> for SpecificAction {
+    fn from(src: Frontrun) -> Self {
+        SpecificAction::Frontrun(Box::new(src))
+    }
+}
+
+impl fmt::Debug for Frontrun {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Frontrun")
+            .field("frontrunner_tx", &self.frontrunner_tx)
+            .field("victim_tx", &self.victim_tx)
+            .field("pool", &lookup(self.pool))
+            .field("frontrun", &self.frontrun)
+            .field("victim", &self.victim)
+            .finish()
+    }
+}
+
+#[derive(Clone, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// A flash loan funded swap which moved the spot price of a pool, which a
+/// victim contract read before the swap was reversed on the same pool
+pub struct OracleManipulation {
+    /// The pool whose spot price was manipulated
+    pub pool: Address,
+    /// The loan which funded the swap
+    pub flash_loan: FlashLoan,
+    /// The swap which moved the pool's price
+    pub swap: Trade,
+    /// The swap in the opposite direction, which moved the price back
+    pub reverse_swap: Trade,
+    /// The contract which read the manipulated price, when the attacker's
+    /// call to it was not classified, e.g. a lending market's borrow or mint
+    pub victim: Option<Address>,
+    /// The victim's protocol, when it is one which this crate classifies
+    pub victim_protocol: Option<Protocol>,
+}
+
+impl From<OracleManipulation> for SpecificAction {
+    fn from(src: OracleManipulation) -> Self {
+        SpecificAction::OracleManipulation(Box::new(src))
+    }
+}
+
+impl fmt::Debug for OracleManipulation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OracleManipulation")
+            .field("pool", &lookup(self.pool))
+            .field("flash_loan", &self.flash_loan)
+            .field("swap", &self.swap)
+            .field("reverse_swap", &self.reverse_swap)
+            .field("victim", &self.victim.map(lookup))
+            .field("victim_protocol", &self.victim_protocol)
+            .finish()
+    }
+}
+
+#[derive(Clone, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// A liquidation which several transactions of a block raced for, of which
+/// only the winner's succeeded
+pub struct LiquidationCompetition {
+    /// The borrower whose position was liquidated
+    pub liquidated_user: Address,
+    /// The debt which was repaid
+    pub debt_token: Address,
+    /// The liquidator whose transaction succeeded
+    pub winner: Address,
+    pub winner_tx: TxHash,
+    /// The transactions which attempted the same liquidation and failed
+    pub failed_txs: Vec<TxHash>,
+    /// The gas used by the failed transactions, whose gas is known
+    pub wasted_gas: U256,
+    /// What the failed transactions paid for that gas, in wei
+    pub wasted_fees: U256,
+}
+
+impl From<LiquidationCompetition> for SpecificAction {
+    fn from(src: LiquidationCompetition) -> Self {
+        SpecificAction::LiquidationCompetition(src)
+    }
+}
+
+impl fmt::Debug for LiquidationCompetition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LiquidationCompetition")
+            .field("liquidated_user", &lookup(self.liquidated_user))
+            .field("debt_token", &lookup(self.debt_token))
+            .field("winner", &lookup(self.winner))
+            .field("winner_tx", &self.winner_tx)
+            .field("failed_txs", &self.failed_txs)
+            .field("wasted_gas", &self.wasted_gas)
+            .field("wasted_fees", &self.wasted_fees)
+            .finish()
+    }
+}
+
+#[derive(Clone, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// A large trade of a known market maker which is likely the on-chain leg of
+/// an arbitrage against a centralized exchange, whose other leg is not visible
+/// on chain. This is a guess, which may as well be the market maker
+/// rebalancing its inventory or filling a client's order.
+pub struct StatisticalArbitrageCandidate {
+    pub trade: Trade,
+    /// The market maker which made the trade
+    pub mm_address: Address,
+}
+
+impl From<StatisticalArbitrageCandidate> for SpecificAction {
+    fn from(src: StatisticalArbitrageCandidate) -> Self {
+        SpecificAction::StatisticalArbitrageCandidate(src)
+    }
+}
+
+impl fmt::Debug for StatisticalArbitrageCandidate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StatisticalArbitrageCandidate")
+            .field("trade", &self.trade)
+            .field("mm_address", &lookup(self.mm_address))
+            .finish()
+    }
+}
+
+#[derive(Clone, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// Tokens pulled with an allowance from an account which took no part in the
+/// transaction, e.g. a user who approved a contract which got exploited or a
+/// malicious one. This is a guess, which may as well be a legitimate meta
+/// transaction or subscription which the owner signed off chain.
+pub struct AllowanceExploit {
+    /// The transfer out of the owner's account
+    pub transfer: Transfer,
+    /// The contract or the EOA which called `transferFrom`
+    pub spender: Address,
+}
+
+impl From<AllowanceExploit> for SpecificAction {
+    fn from(src: AllowanceExploit) -> Self {
+        SpecificAction::AllowanceExploit(src)
+    }
+}
+
+impl fmt::Debug for AllowanceExploit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AllowanceExploit")
+            .field("transfer", &self.transfer)
+            .field("spender", &lookup(self.spender))
+            .finish()
+    }
+}
+
+#[derive(Clone, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// A concentrated liquidity position over a tick range of a pool
+pub struct LiquidityPosition {
+    pub pool: Address,
+    pub token_id: U256,
+    pub tick_lower: i32,
+    pub tick_upper: i32,
+}
+
+impl LiquidityPosition {
+    /// Whether the tick ranges of the 2 positions intersect
+    pub fn overlaps(&self, other: &LiquidityPosition) -> bool {
+        self.tick_lower < other.tick_upper && other.tick_lower < self.tick_upper
+    }
+}
+
+impl fmt::Debug for LiquidityPosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LiquidityPosition")
+            .field("pool", &lookup(self.pool))
+            .field("token_id", &self.token_id)
+            .field("tick_lower", &self.tick_lower)
+            .field("tick_upper", &self.tick_upper)
+            .finish()
+    }
+}
+
 impl SpecificAction {
+    /// The name of the action's variant, e.g. "Trade"
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SpecificAction::WethDeposit(_) => "WethDeposit",
+            SpecificAction::WethWithdrawal(_) => "WethWithdrawal",
+            SpecificAction::Transfer(_) => "Transfer",
+            SpecificAction::Approval(_) => "Approval",
+            SpecificAction::NftTransfer(_) => "NftTransfer",
+            SpecificAction::NftTrade(_) => "NftTrade",
+            SpecificAction::NftSweep(_) => "NftSweep",
+            SpecificAction::MinerPayment(_) => "MinerPayment",
+            SpecificAction::Deployment(_) => "Deployment",
+            SpecificAction::SelfDestruct(_) => "SelfDestruct",
+            SpecificAction::GasToken(_) => "GasToken",
+            SpecificAction::Trade(_) => "Trade",
+            SpecificAction::Liquidation(_) => "Liquidation",
+            SpecificAction::CollateralAbsorption(_) => "CollateralAbsorption",
+            SpecificAction::FlashLoan(_) => "FlashLoan",
+            SpecificAction::AddLiquidity(_) => "AddLiquidity",
+            SpecificAction::RemoveLiquidity(_) => "RemoveLiquidity",
+            SpecificAction::JitLiquidity(_) => "JitLiquidity",
+            SpecificAction::Arbitrage(_) => "Arbitrage",
+            SpecificAction::OpenArbitrage(_) => "OpenArbitrage",
+            SpecificAction::Sandwich(_) => "Sandwich",
+            SpecificAction::PartialSandwich(_) => "PartialSandwich",
+            SpecificAction::Frontrun(_) => "Frontrun",
+            SpecificAction::ProfitableLiquidation(_) => "ProfitableLiquidation",
+            SpecificAction::OracleManipulation(_) => "OracleManipulation",
+            SpecificAction::LiquidationCompetition(_) => "LiquidationCompetition",
+            SpecificAction::NftArbitrage(_) => "NftArbitrage",
+            SpecificAction::StatisticalArbitrageCandidate(_) => "StatisticalArbitrageCandidate",
+            SpecificAction::AllowanceExploit(_) => "AllowanceExploit",
+            SpecificAction::Unclassified(_) => "Unclassified",
+            SpecificAction::LiquidationCheck => "LiquidationCheck",
+        }
+    }
+
     pub fn deposit(&self) -> Option<&Deposit> {
         match self {
             SpecificAction::WethDeposit(inner) => Some(inner),
@@ -59,6 +615,48 @@ impl SpecificAction {
         }
     }
 
+    pub fn approval(&self) -> Option<&Approval> {
+        match self {
+            SpecificAction::Approval(inner) => Some(inner),
+            _ => None,
+        }
+    }
+
+    pub fn nft_transfer(&self) -> Option<&NftTransfer> {
+        match self {
+            SpecificAction::NftTransfer(inner) => Some(inner),
+            _ => None,
+        }
+    }
+
+    pub fn nft_trade(&self) -> Option<&NftTrade> {
+        match self {
+            SpecificAction::NftTrade(inner) => Some(inner),
+            _ => None,
+        }
+    }
+
+    pub fn nft_sweep(&self) -> Option<&NftSweep> {
+        match self {
+            SpecificAction::NftSweep(inner) => Some(inner),
+            _ => None,
+        }
+    }
+
+    pub fn nft_arbitrage(&self) -> Option<&NftArbitrage> {
+        match self {
+            SpecificAction::NftArbitrage(inner) => Some(inner),
+            _ => None,
+        }
+    }
+
+    pub fn miner_payment(&self) -> Option<&MinerPayment> {
+        match self {
+            SpecificAction::MinerPayment(inner) => Some(inner),
+            _ => None,
+        }
+    }
+
     pub fn trade(&self) -> Option<&Trade> {
         match self {
             SpecificAction::Trade(inner) => Some(inner),
@@ -73,6 +671,13 @@ impl SpecificAction {
         }
     }
 
+    pub fn open_arbitrage(&self) -> Option<&OpenArbitrage> {
+        match self {
+            SpecificAction::OpenArbitrage(inner) => Some(inner),
+            _ => None,
+        }
+    }
+
     pub fn liquidation(&self) -> Option<&Liquidation> {
         match self {
             SpecificAction::Liquidation(inner) => Some(inner),
@@ -80,6 +685,34 @@ impl SpecificAction {
         }
     }
 
+    pub fn deployment(&self) -> Option<&Deployment> {
+        match self {
+            SpecificAction::Deployment(inner) => Some(inner),
+            _ => None,
+        }
+    }
+
+    pub fn self_destruct(&self) -> Option<&SelfDestruct> {
+        match self {
+            SpecificAction::SelfDestruct(inner) => Some(inner),
+            _ => None,
+        }
+    }
+
+    pub fn gas_token(&self) -> Option<&GasToken> {
+        match self {
+            SpecificAction::GasToken(inner) => Some(inner),
+            _ => None,
+        }
+    }
+
+    pub fn flash_loan(&self) -> Option<&FlashLoan> {
+        match self {
+            SpecificAction::FlashLoan(inner) => Some(inner),
+            _ => None,
+        }
+    }
+
     // TODO: Can we convert these to AsRef / AsMut Options somehow?
     pub fn liquidation_mut(&mut self) -> Option<&mut Liquidation> {
         match self {
@@ -88,6 +721,13 @@ impl SpecificAction {
         }
     }
 
+    pub fn collateral_absorption(&self) -> Option<&CollateralAbsorption> {
+        match self {
+            SpecificAction::CollateralAbsorption(inner) => Some(inner),
+            _ => None,
+        }
+    }
+
     pub fn profitable_liquidation(&self) -> Option<&ProfitableLiquidation> {
         match self {
             SpecificAction::ProfitableLiquidation(inner) => Some(inner),
@@ -101,9 +741,73 @@ impl SpecificAction {
             _ => None,
         }
     }
+
+    pub fn remove_liquidity(&self) -> Option<&RemoveLiquidity> {
+        match self {
+            SpecificAction::RemoveLiquidity(inner) => Some(inner),
+            _ => None,
+        }
+    }
+
+    pub fn jit_liquidity(&self) -> Option<&JitLiquidity> {
+        match self {
+            SpecificAction::JitLiquidity(inner) => Some(inner),
+            _ => None,
+        }
+    }
+
+    pub fn sandwich(&self) -> Option<&Sandwich> {
+        match self {
+            SpecificAction::Sandwich(inner) => Some(inner),
+            _ => None,
+        }
+    }
+
+    pub fn partial_sandwich(&self) -> Option<&PartialSandwich> {
+        match self {
+            SpecificAction::PartialSandwich(inner) => Some(inner),
+            _ => None,
+        }
+    }
+
+    pub fn frontrun(&self) -> Option<&Frontrun> {
+        match self {
+            SpecificAction::Frontrun(inner) => Some(inner),
+            _ => None,
+        }
+    }
+
+    pub fn oracle_manipulation(&self) -> Option<&OracleManipulation> {
+        match self {
+            SpecificAction::OracleManipulation(inner) => Some(inner),
+            _ => None,
+        }
+    }
+
+    pub fn liquidation_competition(&self) -> Option<&LiquidationCompetition> {
+        match self {
+            SpecificAction::LiquidationCompetition(inner) => Some(inner),
+            _ => None,
+        }
+    }
+
+    pub fn statistical_arbitrage_candidate(&self) -> Option<&StatisticalArbitrageCandidate> {
+        match self {
+            SpecificAction::StatisticalArbitrageCandidate(inner) => Some(inner),
+            _ => None,
+        }
+    }
+
+    pub fn allowance_exploit(&self) -> Option<&AllowanceExploit> {
+        match self {
+            SpecificAction::AllowanceExploit(inner) => Some(inner),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Clone, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 /// A token transfer
 pub struct Transfer {
     pub from: Address,
@@ -118,9 +822,41 @@ impl From<Transfer> for SpecificAction {
     }
 }
 
+impl Transfer {
+    /// A transfer of `amount` of `token`
+    pub fn new(from: Address, to: Address, amount: U256, token: Address) -> Self {
+        Self {
+            from,
+            to,
+            amount,
+            token,
+        }
+    }
+
+    /// Whether the tokens were minted, i.e. sent from `0x0`
+    pub fn is_mint(&self) -> bool {
+        self.from == Address::zero()
+    }
+
+    /// Whether the tokens were burned, i.e. sent to `0x0`
+    pub fn is_burn(&self) -> bool {
+        self.to == Address::zero()
+    }
+
+    /// The amount in the token's units, e.g. "1.5 WETH", if the token is known
+    /// or was queried with `tokens::token_info`. Otherwise the raw amount is
+    /// followed by the token's address.
+    pub fn readable_amount(&self) -> String {
+        match cached_token_info(self.token) {
+            Some(info) => info.format(self.amount),
+            None => format!("{} {}", self.amount, lookup(self.token)),
+        }
+    }
+}
+
 // Manually implemented Debug (and Display?) for datatypes so that we
-// can get their token names instead of using addresses. TODO: Could we
-// also normalize the decimals? What about tokens with non-18 decimals e.g. Tether?
+// can get their token names instead of using addresses. The amounts are
+// kept raw, `readable_amount` normalizes them with the token's decimals.
 impl fmt::Debug for Transfer {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Transfer")
@@ -133,6 +869,351 @@ impl fmt::Debug for Transfer {
 }
 
 #[derive(Clone, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// An ERC20 `approve`, or an `increaseAllowance` in which case the amount is
+/// the increase. Approvals move no tokens. ERC721's `approve` shares its
+/// selector, the amount of an NFT approval is the approved token id.
+pub struct Approval {
+    pub owner: Address,
+    pub spender: Address,
+    pub amount: U256,
+    pub token: Address,
+}
+
+impl From<Approval> for SpecificAction {
+    fn from(src: Approval) -> Self {
+        SpecificAction::Approval(src)
+    }
+}
+
+impl Approval {
+    /// An approval of `spender` to transfer `amount` of the owner's `token`
+    pub fn new(owner: Address, spender: Address, amount: U256, token: Address) -> Self {
+        Self {
+            owner,
+            spender,
+            amount,
+            token,
+        }
+    }
+}
+
+impl fmt::Debug for Approval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Approval")
+            .field("owner", &lookup(self.owner))
+            .field("spender", &lookup(self.spender))
+            .field("amount", &self.amount)
+            .field("token", &lookup(self.token))
+            .finish()
+    }
+}
+
+#[derive(Clone, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// An ERC721 or ERC1155 token transfer
+pub struct NftTransfer {
+    pub collection: Address,
+    pub token_id: U256,
+    /// How many of the token were sent, which is always 1 for ERC721s
+    pub amount: U256,
+    pub from: Address,
+    pub to: Address,
+}
+
+impl From<NftTransfer> for SpecificAction {
+    fn from(src: NftTransfer) -> Self {
+        SpecificAction::NftTransfer(src)
+    }
+}
+
+impl NftTransfer {
+    /// A transfer of one token of the collection, e.g. an ERC721
+    pub fn new(collection: Address, token_id: U256, from: Address, to: Address) -> Self {
+        Self {
+            collection,
+            token_id,
+            amount: 1.into(),
+            from,
+            to,
+        }
+    }
+
+    /// Sets how many of the token were sent, for ERC1155s
+    pub fn with_amount(mut self, amount: U256) -> Self {
+        self.amount = amount;
+        self
+    }
+}
+
+impl fmt::Debug for NftTransfer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NftTransfer")
+            .field("collection", &lookup(self.collection))
+            .field("token_id", &self.token_id)
+            .field("amount", &self.amount)
+            .field("from", &lookup(self.from))
+            .field("to", &lookup(self.to))
+            .finish()
+    }
+}
+
+#[derive(Clone, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// An NFT bought on a marketplace
+pub struct NftTrade {
+    pub collection: Address,
+    pub token_id: U256,
+    /// ETH or the ERC20 which the NFT was paid with
+    pub payment_token: Address,
+    /// The amount paid by the buyer, including the marketplace's and the
+    /// creator's fees
+    pub price: U256,
+    pub buyer: Address,
+    pub seller: Address,
+}
+
+impl From<NftTrade> for SpecificAction {
+    fn from(src: NftTrade) -> Self {
+        SpecificAction::NftTrade(src)
+    }
+}
+
+impl fmt::Debug for NftTrade {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NftTrade")
+            .field("collection", &lookup(self.collection))
+            .field("token_id", &self.token_id)
+            .field("payment_token", &lookup(self.payment_token))
+            .field("price", &self.price)
+            .field("buyer", &lookup(self.buyer))
+            .field("seller", &lookup(self.seller))
+            .finish()
+    }
+}
+
+#[derive(Clone, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// Several NFTs of a collection bought by the same buyer in a single
+/// transaction, e.g. from the cheapest listings of several marketplaces
+pub struct NftSweep {
+    pub collection: Address,
+    pub buyer: Address,
+    /// How many NFTs were bought
+    pub count: usize,
+    /// The token which the NFTs were paid with. ETH and WETH are both
+    /// reported as WETH, so that a sweep paid with both has a single total.
+    pub payment_token: Address,
+    /// The sum of the prices of the NFTs
+    pub total_paid: U256,
+    /// The purchases, in order
+    pub trades: Vec<NftTrade>,
+}
+
+impl From<NftSweep> for SpecificAction {
+    fn from(src: NftSweep) -> Self {
+        SpecificAction::NftSweep(src)
+    }
+}
+
+impl fmt::Debug for NftSweep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NftSweep")
+            .field("collection", &lookup(self.collection))
+            .field("buyer", &lookup(self.buyer))
+            .field("count", &self.count)
+            .field("payment_token", &lookup(self.payment_token))
+            .field("total_paid", &self.total_paid)
+            .field("trades", &self.trades)
+            .finish()
+    }
+}
+
+#[derive(Clone, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// An NFT bought and sold again in the same transaction, for more than it was
+/// bought for, e.g. a listing below the collection's best bid on another
+/// marketplace
+pub struct NftArbitrage {
+    pub collection: Address,
+    pub token_id: U256,
+    /// Who bought the NFT and sold it
+    pub arbitrageur: Address,
+    /// The token which both trades were paid with, with ETH and WETH both
+    /// reported as WETH
+    pub payment_token: Address,
+    pub bought: NftTrade,
+    pub sold: NftTrade,
+    /// The sale's price minus the purchase's
+    pub profit: I256,
+}
+
+impl From<NftArbitrage> for SpecificAction {
+    fn from(src: NftArbitrage) -> Self {
+        SpecificAction::NftArbitrage(Box::new(src))
+    }
+}
+
+impl fmt::Debug for NftArbitrage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NftArbitrage")
+            .field("collection", &lookup(self.collection))
+            .field("token_id", &self.token_id)
+            .field("arbitrageur", &lookup(self.arbitrageur))
+            .field("payment_token", &lookup(self.payment_token))
+            .field("bought", &self.bought)
+            .field("sold", &self.sold)
+            .field("profit", &self.profit)
+            .finish()
+    }
+}
+
+#[derive(Clone, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// A contract created with `CREATE` or `CREATE2`, either by a transaction or
+/// by another contract
+pub struct Deployment {
+    pub deployer: Address,
+    /// The address of the created contract
+    pub contract: Address,
+    /// The ETH sent to the contract's constructor
+    pub value: U256,
+}
+
+impl From<Deployment> for SpecificAction {
+    fn from(src: Deployment) -> Self {
+        SpecificAction::Deployment(src)
+    }
+}
+
+impl fmt::Debug for Deployment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Deployment")
+            .field("deployer", &lookup(self.deployer))
+            .field("contract", &lookup(self.contract))
+            .field("value", &self.value)
+            .finish()
+    }
+}
+
+#[derive(Clone, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// A contract which self-destructed, sending its ETH to the beneficiary. The
+/// ETH is also classified as a `Transfer` right after it, which is what the
+/// balances and the payments to the miner are counted from.
+pub struct SelfDestruct {
+    pub contract: Address,
+    /// The recipient of the contract's ETH, which may be `block.coinbase`
+    pub beneficiary: Address,
+    pub balance: U256,
+}
+
+impl From<SelfDestruct> for SpecificAction {
+    fn from(src: SelfDestruct) -> Self {
+        SpecificAction::SelfDestruct(src)
+    }
+}
+
+impl fmt::Debug for SelfDestruct {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SelfDestruct")
+            .field("contract", &lookup(self.contract))
+            .field("beneficiary", &lookup(self.beneficiary))
+            .field("balance", &self.balance)
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// Whether gas tokens were minted or burned
+pub enum GasTokenKind {
+    /// Minted while gas was cheap, by deploying or storing what gets cleared
+    /// when they are burned
+    Mint,
+    /// Burned with `free`, `freeUpTo`, `freeFrom` or `freeFromUpTo`
+    Free,
+}
+
+#[derive(Clone, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// Gas tokens, e.g. CHI or GST2, which were minted or burned
+pub struct GasToken {
+    pub token: Address,
+    /// The holder of the tokens, which is the caller unless it burned another
+    /// holder's tokens with `freeFrom`
+    pub account: Address,
+    pub amount: U256,
+    pub kind: GasTokenKind,
+}
+
+impl From<GasToken> for SpecificAction {
+    fn from(src: GasToken) -> Self {
+        SpecificAction::GasToken(src)
+    }
+}
+
+impl GasToken {
+    /// The gas refunded for burning each CHI or GST2 token, which self-destructs
+    /// one of the contracts created when it was minted
+    pub const REFUND_PER_TOKEN: u64 = 24_000;
+
+    /// The gas refunded by burning the tokens, before the refunds of the
+    /// transaction are capped to half of its gas. Nothing is refunded since
+    /// London, which removed the refund of `SELFDESTRUCT`.
+    pub fn refund(&self, block: u64) -> U256 {
+        if self.kind != GasTokenKind::Free || block >= LONDON_BLOCK {
+            return U256::zero();
+        }
+        self.amount.saturating_mul(Self::REFUND_PER_TOKEN.into())
+    }
+}
+
+impl fmt::Debug for GasToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GasToken")
+            .field("token", &lookup(self.token))
+            .field("account", &lookup(self.account))
+            .field("amount", &self.amount)
+            .field("kind", &self.kind)
+            .finish()
+    }
+}
+
+#[derive(Clone, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// ETH paid directly to the block's miner (`block.coinbase`)
+pub struct MinerPayment {
+    pub from: Address,
+    pub to: Address,
+    pub amount: U256,
+}
+
+impl From<MinerPayment> for SpecificAction {
+    fn from(src: MinerPayment) -> Self {
+        SpecificAction::MinerPayment(src)
+    }
+}
+
+impl MinerPayment {
+    /// A payment of `amount` of ETH to the block's miner
+    pub fn new(from: Address, to: Address, amount: U256) -> Self {
+        Self { from, to, amount }
+    }
+}
+
+impl fmt::Debug for MinerPayment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MinerPayment")
+            .field("from", &lookup(self.from))
+            .field("to", &lookup(self.to))
+            .field("amount", &self.amount)
+            .finish()
+    }
+}
+
+#[derive(Clone, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Deposit {
     pub from: Address,
     pub amount: U256,
@@ -144,6 +1225,13 @@ impl From<Deposit> for SpecificAction {
     }
 }
 
+impl Deposit {
+    /// A deposit of `amount` of ETH for WETH
+    pub fn new(from: Address, amount: U256) -> Self {
+        Self { from, amount }
+    }
+}
+
 impl fmt::Debug for Deposit {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Deposit")
@@ -154,6 +1242,7 @@ impl fmt::Debug for Deposit {
 }
 
 #[derive(Clone, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Withdrawal {
     pub to: Address,
     pub amount: U256,
@@ -165,6 +1254,13 @@ impl From<Withdrawal> for SpecificAction {
     }
 }
 
+impl Withdrawal {
+    /// A withdrawal of `amount` of WETH for ETH
+    pub fn new(to: Address, amount: U256) -> Self {
+        Self { to, amount }
+    }
+}
+
 impl fmt::Debug for Withdrawal {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Withdrawal")
@@ -175,6 +1271,7 @@ impl fmt::Debug for Withdrawal {
 }
 
 #[derive(Debug, Clone, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Trade {
     pub t1: Transfer,
     pub t2: Transfer,
@@ -195,13 +1292,104 @@ impl Trade {
         );
         Self { t1, t2 }
     }
+
+    /// Whether the trade sells a token for itself, which does not exchange
+    /// anything unless the token rebases
+    pub fn is_same_token(&self) -> bool {
+        self.t1.token == self.t2.token && !REBASING.contains(&self.t1.token)
+    }
+
+    /// The value of the bought tokens minus the value of the sold ones, in ETH
+    pub fn eth_profit<O: PriceOracle>(&self, oracle: &O, block: u64) -> Option<I256> {
+        net_profit(
+            oracle,
+            vec![
+                (self.t1.token, -signed(self.t1.amount)?),
+                (self.t2.token, signed(self.t2.amount)?),
+            ],
+            block,
+        )
+    }
+
+    /// The price which the trade was executed at, as the tokens bought per
+    /// token sold in their units, e.g. 2000 for 1 WETH sold for 2000 USDC.
+    /// `None` if the decimals of a token are unknown, see `tokens::token_info`.
+    pub fn implied_price(&self) -> Option<f64> {
+        if self.t1.amount.is_zero() {
+            return None;
+        }
+        let decimals_in = cached_token_info(self.t1.token)?.decimals;
+        let decimals_out = cached_token_info(self.t2.token)?.decimals;
+        let price = to_f64(self.t2.amount) / to_f64(self.t1.amount);
+        Some(price * 10f64.powi(decimals_in as i32 - decimals_out as i32))
+    }
+
+    /// How much worse the trade's price was than the spot price of its pool at
+    /// `block`, as a fraction of the spot price, e.g. 0.01 if it bought 1% less
+    /// than the reserves' ratio. This includes the pool's fee. The reserves
+    /// must be the ones before the trade, and the pool is the receiver of the
+    /// tokens sold. Since both prices are in the same tokens, this does not
+    /// depend on their decimals.
+    pub fn price_impact<O: PriceOracle>(&self, oracle: &O, block: u64) -> Option<f64> {
+        let (reserve_in, reserve_out) =
+            oracle.reserves_at(self.t1.to, self.t1.token, self.t2.token, block)?;
+        if reserve_in.is_zero() || self.t1.amount.is_zero() {
+            return None;
+        }
+        let spot = to_f64(reserve_out) / to_f64(reserve_in);
+        let price = to_f64(self.t2.amount) / to_f64(self.t1.amount);
+        Some(1.0 - price / spot)
+    }
 }
 
 #[derive(Clone, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// Tokens which were borrowed and repaid within the same transaction
+pub struct FlashLoan {
+    pub provider: Protocol,
+    pub token: Address,
+    pub amount: U256,
+    /// The amount repaid on top of the borrowed one
+    pub fee: U256,
+}
+
+impl From<FlashLoan> for SpecificAction {
+    fn from(src: FlashLoan) -> Self {
+        SpecificAction::FlashLoan(src)
+    }
+}
+
+impl fmt::Debug for FlashLoan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FlashLoan")
+            .field("provider", &self.provider)
+            .field("token", &lookup(self.token))
+            .field("amount", &self.amount)
+            .field("fee", &self.fee)
+            .finish()
+    }
+}
+
+#[derive(Clone, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// A cycle of trades made within one transaction, since the `ArbitrageReducer`
+/// reduces the trades of each transaction on their own. Arbitrages whose legs
+/// span transactions, e.g. against a centralized exchange, are only guessed by
+/// the `StatisticalArbitrageReducer`.
 pub struct Arbitrage {
     pub profit: U256,
     pub token: Address,
     pub to: Address,
+    /// The amount of `token` which the searcher put up for the first hop, i.e.
+    /// its input minus the flash loans which funded it
+    pub capital: U256,
+    /// The hops of the arbitrage, in order
+    pub trades: Vec<Trade>,
+    /// Where the profit landed: the EOA which `to` withdrew it to, or `to`
+    /// itself if it was kept in the contract
+    pub recipient: Address,
+    /// The sender and the contract of the arbitrage's tx
+    pub searcher: Searcher,
 }
 
 impl From<Arbitrage> for SpecificAction {
@@ -210,17 +1398,93 @@ impl From<Arbitrage> for SpecificAction {
     }
 }
 
+impl Arbitrage {
+    /// Whether all the hops of the arbitrage were made in one transaction,
+    /// which is the case for all the arbitrages, since the `ArbitrageReducer`
+    /// reduces the trades of each transaction on their own. Arbitrages whose
+    /// legs span transactions or blocks, e.g. against a centralized exchange,
+    /// are non-atomic and are reported as a `StatisticalArbitrageCandidate`
+    /// instead.
+    pub fn is_atomic(&self) -> bool {
+        true
+    }
+
+    /// The arbitrage's profit in ETH
+    pub fn eth_profit<O: PriceOracle>(&self, oracle: &O, block: u64) -> Option<I256> {
+        net_profit(oracle, vec![(self.token, signed(self.profit)?)], block)
+    }
+
+    /// The arbitrage's profit in USD. Its profit in the token which the cycle
+    /// started from is `profit`.
+    pub fn usd_profit<O: PriceOracle>(&self, oracle: &O, block: u64) -> Option<f64> {
+        usd_value(oracle, self.eth_profit(oracle, block)?, block)
+    }
+}
+
 impl fmt::Debug for Arbitrage {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Arbitrage")
             .field("profit", &self.profit)
             .field("to", &lookup(self.to))
             .field("token", &lookup(self.token))
+            .field("capital", &self.capital)
+            .field("trades", &self.trades)
+            .field("recipient", &lookup(self.recipient))
+            .field("searcher", &self.searcher)
+            .finish()
+    }
+}
+
+#[derive(Clone, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// A sequence of trades which does not trade back into the token it started
+/// from, but which left the searcher with more value than it sold at the
+/// oracle's prices. Unlike an `Arbitrage`, whose profit is locked in by closing
+/// its cycle, its profit is only realized if the bought token is sold at these
+/// prices later, so it bears the risk of holding that token.
+pub struct OpenArbitrage {
+    /// The hops, each one selling what the previous one bought
+    pub trades: Vec<Trade>,
+    /// The value of what the last hop bought minus that of what the first hop
+    /// sold, in ETH
+    pub eth_profit: I256,
+    /// The same in USD, if the oracle could price USDC
+    pub usd_profit: Option<f64>,
+    /// The sender and the contract of the tx
+    pub searcher: Searcher,
+}
+
+impl From<OpenArbitrage> for SpecificAction {
+    fn from(src: OpenArbitrage) -> Self {
+        SpecificAction::OpenArbitrage(src)
+    }
+}
+
+impl OpenArbitrage {
+    /// What the first hop sold
+    pub fn sold(&self) -> &Transfer {
+        &self.trades.first().expect("open arbitrages have hops").t1
+    }
+
+    /// What the last hop bought, which the searcher is left holding
+    pub fn held(&self) -> &Transfer {
+        &self.trades.last().expect("open arbitrages have hops").t2
+    }
+}
+
+impl fmt::Debug for OpenArbitrage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OpenArbitrage")
+            .field("trades", &self.trades)
+            .field("eth_profit", &self.eth_profit)
+            .field("usd_profit", &self.usd_profit)
+            .field("searcher", &self.searcher)
             .finish()
     }
 }
 
 #[derive(Default, Clone, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Liquidation {
     pub sent_token: Address,
     pub sent_amount: U256,
@@ -230,6 +1494,9 @@ pub struct Liquidation {
 
     pub from: Address,
     pub liquidated_user: Address,
+
+    /// The auction of the collateral, for protocols which sell it in auctions
+    pub auction: Option<Auction>,
 }
 
 impl From<Liquidation> for SpecificAction {
@@ -238,6 +1505,48 @@ impl From<Liquidation> for SpecificAction {
     }
 }
 
+impl Liquidation {
+    /// A liquidation of `liquidated_user`, in which `from` repaid its debt of
+    /// `sent_token` for its collateral of `received_token`
+    pub fn new(
+        sent_token: Address,
+        sent_amount: U256,
+        received_token: Address,
+        received_amount: U256,
+        from: Address,
+        liquidated_user: Address,
+    ) -> Self {
+        Self {
+            sent_token,
+            sent_amount,
+            received_token,
+            received_amount,
+            from,
+            liquidated_user,
+            auction: None,
+        }
+    }
+
+    /// Sets the auction which sold the collateral
+    pub fn with_auction(mut self, auction: Auction) -> Self {
+        self.auction = Some(auction);
+        self
+    }
+
+    /// The value of the seized collateral minus the value of the repaid debt,
+    /// in ETH
+    pub fn eth_profit<O: PriceOracle>(&self, oracle: &O, block: u64) -> Option<I256> {
+        net_profit(
+            oracle,
+            vec![
+                (self.sent_token, -signed(self.sent_amount)?),
+                (self.received_token, signed(self.received_amount)?),
+            ],
+            block,
+        )
+    }
+}
+
 impl fmt::Debug for Liquidation {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Liquidation")
@@ -247,11 +1556,67 @@ impl fmt::Debug for Liquidation {
             .field("received_amount", &self.received_amount)
             .field("liquidated_user", &lookup(self.liquidated_user))
             .field("from", &lookup(self.from))
+            .field("auction", &self.auction)
             .finish()
     }
 }
 
 #[derive(Clone, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// An underwater account of Compound V3 whose debt and collateral the
+/// protocol took over, for liquidators to buy the collateral from it with
+/// `buyCollateral` afterwards
+pub struct CollateralAbsorption {
+    /// The market which absorbed the account
+    pub comet: Address,
+    pub absorber: Address,
+    pub borrower: Address,
+    /// The token which the market lends
+    pub base_token: Address,
+    /// The debt which was written off, if the logs are known
+    pub debt: U256,
+    /// The (asset, amount) of the collateral which was absorbed, if the logs
+    /// are known
+    pub collateral: Vec<(Address, U256)>,
+}
+
+impl From<CollateralAbsorption> for SpecificAction {
+    fn from(src: CollateralAbsorption) -> Self {
+        SpecificAction::CollateralAbsorption(src)
+    }
+}
+
+impl fmt::Debug for CollateralAbsorption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let collateral = self
+            .collateral
+            .iter()
+            .map(|(asset, amount)| (lookup(*asset), *amount))
+            .collect::<Vec<_>>();
+        f.debug_struct("CollateralAbsorption")
+            .field("comet", &lookup(self.comet))
+            .field("absorber", &lookup(self.absorber))
+            .field("borrower", &lookup(self.borrower))
+            .field("base_token", &lookup(self.base_token))
+            .field("debt", &self.debt)
+            .field("collateral", &collateral)
+            .finish()
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// A Maker auction of the collateral of a liquidated vault
+pub struct Auction {
+    /// The contract which runs the auction, i.e. the ilk's Clipper or Flipper
+    pub house: Address,
+    pub id: U256,
+    /// The collateral type, e.g. "ETH-A"
+    pub ilk: String,
+}
+
+#[derive(Clone, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ProfitableLiquidation {
     pub liquidation: Liquidation,
     pub profit: U256,
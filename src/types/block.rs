@@ -0,0 +1,750 @@
+use crate::{
+    prices::{net_profit, signed, PriceOracle},
+    types::{
+        actions::{
+            AllowanceExploit, Arbitrage, Frontrun, Liquidation, OpenArbitrage, PartialSandwich,
+            Sandwich, SpecificAction,
+        },
+        gas::BlockFees,
+        GasInfo, Inspection, Protocol,
+    },
+    BatchInspector, TraceProvider,
+};
+use ethers::types::{Action, Address, RewardType, Trace, TransactionReceipt, TxHash, I256, U256};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// The inspections of all the transactions of a block, in block order
+pub struct Block {
+    /// The block's number
+    pub number: u64,
+    /// The block's miner, who got paid the coinbase payments
+    pub miner: Address,
+    /// The block's base fee, for post-London blocks
+    pub base_fee: Option<U256>,
+    /// The block's timestamp, if its header has been fetched
+    pub timestamp: Option<U256>,
+    /// The inspections of the block's transactions
+    pub inspections: Vec<Inspection>,
+    /// Whether the block replaced one at its height which was already
+    /// streamed by `stream_blocks`, i.e. it is part of a reorg
+    pub reorged: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// Where a transaction was included in its block and how much it bid for it
+pub struct BlockPosition {
+    /// The position of the transaction in the block
+    pub tx_index: usize,
+    /// The percentage of the block's transactions, from 0 to 100, which paid
+    /// at most the same priority fee as this one. `None` if its gas is not known
+    pub gas_price_rank: Option<f64>,
+    /// Whether the transaction is one of the first transactions of the block,
+    /// which made calls to other contracts and came before any plain transaction
+    pub is_top_of_block: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialOrd, PartialEq, Eq, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// The kinds of MEV which blocks are summarized by
+pub enum MevType {
+    Arbitrage,
+    Sandwich,
+    /// Whether their profit is known or not
+    Liquidation,
+    JitLiquidity,
+    NftArbitrage,
+}
+
+impl MevType {
+    /// All the types, in the order they are summarized in
+    pub const ALL: [MevType; 5] = [
+        MevType::Arbitrage,
+        MevType::Sandwich,
+        MevType::Liquidation,
+        MevType::JitLiquidity,
+        MevType::NftArbitrage,
+    ];
+
+    /// The type of MEV of the action, if it is one, with the deltas of its
+    /// profit in each token. The profit is left out, but the action still
+    /// counted, if it is unknown, e.g. a liquidation whose seized collateral
+    /// was not found, or if its amounts are too large to be signed.
+    fn of(action: &SpecificAction) -> Option<(Self, Vec<(Address, I256)>)> {
+        let profit = |token, amount| signed(amount).map(|profit| (token, profit));
+        Some(match action {
+            SpecificAction::Arbitrage(arb) => (
+                MevType::Arbitrage,
+                profit(arb.token, arb.profit).into_iter().collect(),
+            ),
+            SpecificAction::Sandwich(sandwich) => {
+                (MevType::Sandwich, vec![(sandwich.token, sandwich.profit)])
+            }
+            SpecificAction::Liquidation(liq) => {
+                let deltas = match (signed(liq.sent_amount), signed(liq.received_amount)) {
+                    (Some(sent), Some(received)) if !received.is_zero() => {
+                        vec![(liq.sent_token, -sent), (liq.received_token, received)]
+                    }
+                    _ => vec![],
+                };
+                (MevType::Liquidation, deltas)
+            }
+            SpecificAction::ProfitableLiquidation(liq) => (
+                MevType::Liquidation,
+                profit(liq.token, liq.profit).into_iter().collect(),
+            ),
+            SpecificAction::JitLiquidity(jit) => (
+                MevType::JitLiquidity,
+                jit.tokens.iter().cloned().zip(jit.deltas.clone()).collect(),
+            ),
+            SpecificAction::NftArbitrage(arb) => {
+                (MevType::NftArbitrage, vec![(arb.payment_token, arb.profit)])
+            }
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// How many MEV actions of a type or a protocol there were and what they made
+pub struct MevStats {
+    pub count: usize,
+    /// The gross profit in each token, in its base units
+    pub profits: BTreeMap<Address, I256>,
+    /// The gross profit in ETH, if the summary was made with a price oracle
+    /// which can price all its tokens
+    pub profit_eth: Option<I256>,
+}
+
+impl MevStats {
+    fn add(&mut self, deltas: &[(Address, I256)]) {
+        self.count += 1;
+        for (token, amount) in deltas {
+            let total = self.profits.entry(*token).or_insert_with(I256::zero);
+            *total = total.saturating_add(*amount);
+        }
+    }
+
+    fn price<O: PriceOracle>(&mut self, oracle: &O, block: u64) {
+        let profits = self.profits.iter().map(|(token, amount)| (*token, *amount));
+        self.profit_eth = net_profit(oracle, profits, block);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// The MEV of a block by type and by protocol, which is what most consumers
+/// store for each block. Profits are gross, before gas and payments to the
+/// miner.
+pub struct BlockSummary {
+    pub number: u64,
+    /// The MEV of each type, including the types which the block has none of
+    pub by_type: BTreeMap<MevType, MevStats>,
+    /// The MEV of the txs which touched each protocol. Each action counts
+    /// towards every protocol of its tx, so these overlap when txs touched
+    /// more than one protocol. Sandwiches are the frontrun's.
+    pub by_protocol: BTreeMap<Protocol, MevStats>,
+    /// The total ETH paid to the miner by the block's transactions
+    pub coinbase_payments: U256,
+}
+
+impl Block {
+    /// Inspects the traces of block `number` along with the logs of its
+    /// receipts. The miner is taken from the block's reward trace, and the
+    /// timestamp and base fee can be set from its header with `with_header`.
+    pub fn from_traces(
+        inspector: &BatchInspector,
+        number: u64,
+        traces: impl IntoIterator<Item = Trace>,
+        receipts: &[TransactionReceipt],
+    ) -> Self {
+        let traces = traces.into_iter().collect::<Vec<_>>();
+        let miner = traces
+            .iter()
+            .find_map(|trace| match trace.action {
+                Action::Reward(ref reward) if reward.reward_type == RewardType::Block => {
+                    Some(reward.author)
+                }
+                _ => None,
+            })
+            .unwrap_or_default();
+        let logs = receipts
+            .iter()
+            .map(|receipt| (receipt.transaction_hash, receipt.logs.clone()))
+            .collect::<HashMap<TxHash, _>>();
+
+        let inspections = inspector.inspect_block_with_logs(traces, Some(miner), &logs);
+        Self {
+            number,
+            miner,
+            base_fee: None,
+            timestamp: None,
+            inspections,
+            reorged: false,
+        }
+    }
+
+    /// Fetches the traces of block `number` and inspects them in one step. The
+    /// block is traced one transaction at a time if the provider does not
+    /// serve `trace_block`. The logs of its receipts are not fetched, use
+    /// `from_traces` for inspectors which need them.
+    pub async fn from_provider<P: TraceProvider>(
+        inspector: &BatchInspector,
+        provider: &P,
+        number: u64,
+    ) -> Result<Self, P::Error> {
+        let txs = provider.block_transactions(number).await?;
+        let traces = provider.trace_block_or_transactions(number, &txs).await?;
+        Ok(Self::from_traces(inspector, number, traces, &[]))
+    }
+
+    /// Sets the miner, i.e. the fee recipient after the merge, the timestamp
+    /// and the base fee from the block's header
+    pub fn with_header(mut self, header: &BlockFees) -> Self {
+        self.miner = header.miner;
+        self.timestamp = Some(header.timestamp);
+        self.base_fee = header.base_fee;
+        self
+    }
+
+    /// All the classified actions of the block, in block order
+    pub fn actions(&self) -> impl Iterator<Item = &SpecificAction> {
+        self.inspections
+            .iter()
+            .flat_map(|inspection| inspection.known_actions())
+    }
+
+    /// The arbitrages of the block
+    pub fn arbitrages(&self) -> impl Iterator<Item = &Arbitrage> {
+        self.actions().filter_map(|action| action.arbitrage())
+    }
+
+    /// The open arbitrages of the block, if the `OpenArbitrageReducer` ran,
+    /// which are not part of its `arbitrages`
+    pub fn open_arbitrages(&self) -> impl Iterator<Item = &OpenArbitrage> {
+        self.actions().filter_map(|action| action.open_arbitrage())
+    }
+
+    /// The sandwiches of the block, which are attached to the tx of their frontrun
+    pub fn sandwiches(&self) -> impl Iterator<Item = &Sandwich> {
+        self.actions().filter_map(|action| action.sandwich())
+    }
+
+    /// The frontruns of the block which were not backrun, which are attached to
+    /// the tx of their frontrun
+    pub fn partial_sandwiches(&self) -> impl Iterator<Item = &PartialSandwich> {
+        self.actions()
+            .filter_map(|action| action.partial_sandwich())
+    }
+
+    /// The candidate allowance exploits of the block, if the
+    /// `AllowanceExploitReducer` ran
+    pub fn allowance_exploits(&self) -> impl Iterator<Item = &AllowanceExploit> {
+        self.actions()
+            .filter_map(|action| action.allowance_exploit())
+    }
+
+    /// The frontruns of the block, which are attached to the frontrunner's tx
+    pub fn frontruns(&self) -> impl Iterator<Item = &Frontrun> {
+        self.actions().filter_map(|action| action.frontrun())
+    }
+
+    /// The liquidations of the block, whether their profit is known or not
+    pub fn liquidations(&self) -> impl Iterator<Item = &Liquidation> {
+        self.inspections
+            .iter()
+            .flat_map(|inspection| inspection.liquidations())
+    }
+
+    /// Counts the MEV of the block by type and by protocol from its classified
+    /// actions, with its profit in each token
+    pub fn summary(&self) -> BlockSummary {
+        let mut by_type = MevType::ALL
+            .iter()
+            .map(|mev_type| (*mev_type, MevStats::default()))
+            .collect::<BTreeMap<_, _>>();
+        let mut by_protocol: BTreeMap<Protocol, MevStats> = BTreeMap::new();
+        for inspection in &self.inspections {
+            for action in inspection.known_actions() {
+                let (mev_type, deltas) = match MevType::of(action) {
+                    Some(mev) => mev,
+                    None => continue,
+                };
+                by_type.entry(mev_type).or_default().add(&deltas);
+                for protocol in &inspection.protocols {
+                    by_protocol.entry(*protocol).or_default().add(&deltas);
+                }
+            }
+        }
+
+        BlockSummary {
+            number: self.number,
+            by_type,
+            by_protocol,
+            coinbase_payments: self.coinbase_payments(),
+        }
+    }
+
+    /// Summarizes the block, with the profits priced in ETH by `oracle` at the
+    /// block
+    pub fn summary_with_prices<O: PriceOracle>(&self, oracle: &O) -> BlockSummary {
+        let mut summary = self.summary();
+        for stats in summary
+            .by_type
+            .values_mut()
+            .chain(summary.by_protocol.values_mut())
+        {
+            stats.price(oracle, self.number);
+        }
+        summary
+    }
+
+    /// The ETH made by the arbitrages, sandwiches and liquidations of the block,
+    /// before gas and payments to the miner. Returns `None` if any of the tokens
+    /// cannot be priced or if an amount is too large to be signed.
+    pub fn total_mev<O: PriceOracle>(&self, oracle: &O) -> Option<I256> {
+        let mut deltas = Vec::new();
+        for action in self.actions() {
+            match action {
+                SpecificAction::Arbitrage(arb) => deltas.push((arb.token, signed(arb.profit)?)),
+                SpecificAction::Sandwich(sandwich) => {
+                    deltas.push((sandwich.token, sandwich.profit))
+                }
+                SpecificAction::Liquidation(liq) => {
+                    deltas.push((liq.sent_token, -signed(liq.sent_amount)?));
+                    deltas.push((liq.received_token, signed(liq.received_amount)?));
+                }
+                SpecificAction::ProfitableLiquidation(liq) => {
+                    deltas.push((liq.token, signed(liq.profit)?))
+                }
+                _ => {}
+            }
+        }
+        net_profit(oracle, deltas, self.number)
+    }
+
+    /// The position of each of the block's transactions and how it ranks by
+    /// gas price. Transactions are ranked by their priority fee, which is the
+    /// whole gas price before London.
+    ///
+    /// A transaction is at the top of the block if it is one of its first `k`
+    /// transactions and it and all the transactions before it made calls to other
+    /// contracts, i.e. none of them was a plain transfer or a single call from
+    /// an EOA. Bots compete for these positions to be the first to act on the
+    /// state left by the previous block.
+    pub fn positions(&self, k: usize) -> Vec<BlockPosition> {
+        let base_fee = self.base_fee.unwrap_or_default();
+        let fees = self
+            .inspections
+            .iter()
+            .map(|inspection| {
+                inspection
+                    .gas
+                    .as_ref()
+                    .map(|gas| gas.priority_fee_per_gas(base_fee))
+            })
+            .collect::<Vec<_>>();
+        let priced = fees.iter().flatten().collect::<Vec<_>>();
+
+        let mut is_top_of_block = true;
+        self.inspections
+            .iter()
+            .zip(&fees)
+            .enumerate()
+            .map(|(i, (inspection, fee))| {
+                // gaps are left by txs which could not be inspected
+                let tx_index = inspection.tx_index.unwrap_or(i);
+                // the txs after a plain one are not at the top either
+                is_top_of_block = is_top_of_block && tx_index < k && inspection.actions.len() > 1;
+                BlockPosition {
+                    tx_index,
+                    gas_price_rank: fee.map(|fee| {
+                        let outbid = priced.iter().filter(|other| ***other <= fee).count();
+                        outbid as f64 * 100.0 / priced.len() as f64
+                    }),
+                    is_top_of_block,
+                }
+            })
+            .collect()
+    }
+
+    /// The gas of each of the block's transactions whose gas is known, with the
+    /// block's base fee for those which do not have their own
+    fn gas(&self) -> impl Iterator<Item = GasInfo> + '_ {
+        self.inspections.iter().filter_map(move |inspection| {
+            let gas = inspection.gas.as_ref()?;
+            Some(GasInfo {
+                base_fee: gas.base_fee.or(self.base_fee),
+                ..gas.clone()
+            })
+        })
+    }
+
+    /// The ETH burned by the block's transactions paying the base fee
+    pub fn burned_fees(&self) -> U256 {
+        self.gas().fold(U256::zero(), |total, gas| {
+            total.saturating_add(gas.burned())
+        })
+    }
+
+    /// The ETH which the block's transactions paid to its miner, or to its fee
+    /// recipient after the merge: their gas net of the burned base fee, and
+    /// their coinbase payments. This is what searchers bid for the block,
+    /// which costs them the burned fees on top. Only the transactions whose
+    /// gas is known are counted.
+    pub fn miner_revenue(&self) -> U256 {
+        self.gas().fold(self.coinbase_payments(), |total, gas| {
+            total.saturating_add(gas.miner_fees())
+        })
+    }
+
+    /// The total ETH paid to the miner by the block's transactions
+    pub fn coinbase_payments(&self) -> U256 {
+        self.inspections
+            .iter()
+            .fold(U256::zero(), |total, inspection| {
+                total.saturating_add(inspection.coinbase_payment())
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        addresses::{parse_address, WETH},
+        inspectors::*,
+        reducers::*,
+        test_helpers::*,
+        types::{
+            actions::{JitLiquidity, MinerPayment, NftArbitrage, NftTrade, Trade, Transfer},
+            Classification,
+        },
+        EthersTraceProvider, UniswapV2Oracle,
+    };
+    use ethers::providers::MockProvider;
+
+    #[test]
+    fn aggregates_the_mev_of_a_block() {
+        let inspector = BatchInspector::new(
+            vec![
+                Box::new(ERC20::new()),
+                Box::new(UniswapV2::new()),
+                Box::new(Balancer::new()),
+                Box::new(Aave::new()),
+            ],
+            vec![
+                Box::new(LiquidationReducer::new()),
+                Box::new(TradeReducer::new()),
+                Box::new(ArbitrageReducer::new()),
+            ],
+        );
+        let block = Block::from_traces(&inspector, 11017338, TRACES.clone(), &[]);
+        assert_eq!(
+            block.miner,
+            parse_address("ea674fdde714fd979de3edf0f56aa9716b898ec8")
+        );
+        assert_eq!(block.inspections.len(), 124);
+        assert_eq!(block.arbitrages().count(), 4);
+        assert!(block.arbitrages().all(|arb| arb.token == *WETH));
+        assert_eq!(block.liquidations().count(), 1);
+        assert_eq!(block.sandwiches().count(), 0);
+        assert!(block.coinbase_payments().is_zero());
+        assert!(block
+            .positions(0)
+            .windows(2)
+            .all(|txs| txs[0].tx_index < txs[1].tx_index));
+
+        // all the profits are in WETH, which is priced without any reserves
+        let arbs =
+            626678385524850545u64 + 41108016724856778 + 23939671034095067 + 47597234528640869;
+        let liquidation = U256::from_dec_str("11050220339336811520").unwrap();
+        assert_eq!(
+            block.total_mev(&UniswapV2Oracle::new()).unwrap(),
+            I256::from_raw(liquidation + arbs)
+        );
+
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_value(&block).unwrap();
+            assert_eq!(json["number"], 11017338);
+            assert_eq!(json["inspections"].as_array().unwrap().len(), 124);
+        }
+    }
+
+    #[test]
+    fn summarizes_the_mev_of_a_block_by_type_and_protocol() {
+        let inspector = BatchInspector::new(
+            vec![
+                Box::new(ERC20::new()),
+                Box::new(UniswapV2::new()),
+                Box::new(Balancer::new()),
+                Box::new(Aave::new()),
+            ],
+            vec![
+                Box::new(LiquidationReducer::new()),
+                Box::new(TradeReducer::new()),
+                Box::new(ArbitrageReducer::new()),
+            ],
+        );
+        let mut block = Block::from_traces(&inspector, 11017338, TRACES.clone(), &[]);
+
+        // add a sandwich, some JIT liquidity, an NFT arbitrage and a bribe to
+        // its arbitrages and liquidation
+        let addrs = addrs();
+        let (searcher, pool, token, punks) = (addrs[0], addrs[1], addrs[2], addrs[3]);
+        let tx = |action: SpecificAction, protocol: Option<Protocol>| {
+            let mut inspection = mk_inspection(vec![Classification::new(action, vec![0])]);
+            inspection.protocols.extend(protocol);
+            inspection
+        };
+        let trade = |from, to, amount: u64, token| {
+            Trade::new(
+                Transfer::new(from, pool, amount.into(), token),
+                Transfer::new(pool, to, amount.into(), token),
+            )
+        };
+        let sandwich = Sandwich {
+            pool,
+            frontrun_tx: Default::default(),
+            backrun_tx: Default::default(),
+            frontrun: trade(searcher, searcher, 100, *WETH),
+            victims: vec![],
+            backrun: trade(searcher, searcher, 100, *WETH),
+            token: *WETH,
+            profit: I256::from(50),
+            capital: 100.into(),
+            searcher: Default::default(),
+        };
+        let jit = JitLiquidity {
+            pool,
+            tokens: vec![*WETH, token],
+            added: vec![100.into(), 100.into()],
+            removed: vec![90.into(), 115.into()],
+            fees: vec![20.into(), 0.into()],
+            trades: vec![],
+            deltas: vec![I256::from(10), I256::from(15)],
+        };
+        let nft_trade = |buyer, seller, price: u64| NftTrade {
+            collection: punks,
+            token_id: 7.into(),
+            payment_token: *WETH,
+            price: price.into(),
+            buyer,
+            seller,
+        };
+        let nft_arbitrage = NftArbitrage {
+            collection: punks,
+            token_id: 7.into(),
+            arbitrageur: searcher,
+            payment_token: *WETH,
+            bought: nft_trade(searcher, pool, 100),
+            sold: nft_trade(pool, searcher, 130),
+            profit: I256::from(30),
+        };
+        let bribe = MinerPayment {
+            from: searcher,
+            to: block.miner,
+            amount: 1000.into(),
+        };
+        // a liquidation whose seized collateral was not found, which would
+        // otherwise count its repayment as a loss
+        let unseized = Liquidation::new(*WETH, 500.into(), token, 0.into(), searcher, pool);
+        block.inspections.extend(vec![
+            tx(sandwich.into(), Some(Protocol::Uniswap)),
+            tx(jit.into(), Some(Protocol::UniswapV3)),
+            tx(nft_arbitrage.into(), Some(Protocol::Seaport)),
+            tx(bribe.into(), None),
+            tx(unseized.into(), None),
+        ]);
+
+        // WETH is priced without any reserves, but the JIT's other token is not
+        let summary = block.summary_with_prices(&UniswapV2Oracle::new());
+        assert_eq!(summary.number, 11017338);
+        assert_eq!(summary.coinbase_payments, 1000.into());
+        let by_type = summary
+            .by_type
+            .iter()
+            .map(|(mev_type, stats)| (*mev_type, stats.count, stats.profit_eth))
+            .collect::<Vec<_>>();
+        let arbs = I256::from(739323307812443259u64);
+        let liquidation = I256::from_dec_str("11050220339336811520").unwrap();
+        assert_eq!(
+            by_type,
+            vec![
+                (MevType::Arbitrage, 4, Some(arbs)),
+                (MevType::Sandwich, 1, Some(I256::from(50))),
+                (MevType::Liquidation, 2, Some(liquidation)),
+                (MevType::JitLiquidity, 1, None),
+                (MevType::NftArbitrage, 1, Some(I256::from(30))),
+            ]
+        );
+        let jit = &summary.by_type[&MevType::JitLiquidity];
+        assert_eq!(jit.profits[&*WETH], I256::from(10));
+        assert_eq!(jit.profits[&token], I256::from(15));
+
+        // the arbitrages went through several protocols each
+        let by_protocol = summary
+            .by_protocol
+            .iter()
+            .map(|(protocol, stats)| (*protocol, stats.count))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            by_protocol,
+            vec![
+                (Protocol::Uniswap, 6),
+                (Protocol::UniswapV3, 1),
+                (Protocol::Sushiswap, 2),
+                (Protocol::Balancer, 2),
+                (Protocol::Aave, 1),
+                (Protocol::Seaport, 1),
+            ]
+        );
+        assert_eq!(
+            summary.by_protocol[&Protocol::Aave].profit_eth,
+            Some(liquidation)
+        );
+
+        // without an oracle, only the profits in each token are known
+        let unpriced = block.summary();
+        assert_eq!(unpriced.by_type[&MevType::Arbitrage].profit_eth, None);
+        assert_eq!(unpriced.by_type[&MevType::Arbitrage].profits[&*WETH], arbs);
+
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_value(&summary).unwrap();
+            assert_eq!(json["by_type"]["Sandwich"]["count"], 1);
+            assert_eq!(json["by_protocol"]["Seaport"]["count"], 1);
+            let roundtrip: BlockSummary = serde_json::from_value(json).unwrap();
+            assert_eq!(roundtrip, summary);
+        }
+    }
+
+    #[test]
+    fn ranks_transactions_by_priority_fee() {
+        let addrs = addrs();
+        let (bot, pool) = (addrs[0], addrs[1]);
+        let bot_tx = |index, gas| {
+            let mut inspection = mk_inspection(vec![
+                mk_call(bot, pool, Default::default(), vec![]),
+                mk_call(pool, bot, Default::default(), vec![0]),
+            ]);
+            inspection.tx_index = Some(index);
+            inspection.gas = gas;
+            inspection
+        };
+        let legacy = |gas_price: u64| GasInfo {
+            gas_used: 21_000.into(),
+            gas_price: gas_price.into(),
+            ..Default::default()
+        };
+        let mut plain = mk_inspection(vec![mk_call(bot, pool, Default::default(), vec![])]);
+        plain.tx_index = Some(2);
+        plain.gas = Some(legacy(60));
+
+        let block = Block {
+            number: 1,
+            miner: Address::zero(),
+            base_fee: Some(50.into()),
+            timestamp: None,
+            inspections: vec![
+                bot_tx(0, Some(legacy(100))),
+                // reports a higher gas price, but only tips 2 per gas
+                bot_tx(
+                    1,
+                    Some(legacy(1000).with_fees(50.into(), 1000.into(), 2.into())),
+                ),
+                plain,
+                bot_tx(3, None),
+            ],
+            reorged: false,
+        };
+        let positions = block.positions(3);
+        let ranks = positions
+            .iter()
+            .map(|p| p.gas_price_rank.map(|rank| rank.round() as u64))
+            .collect::<Vec<_>>();
+        assert_eq!(ranks, vec![Some(100), Some(33), Some(67), None]);
+        let top = positions
+            .iter()
+            .map(|p| p.is_top_of_block)
+            .collect::<Vec<_>>();
+        // the plain tx ends the top of the block
+        assert_eq!(top, vec![true, true, false, false]);
+        assert_eq!(positions[3].tx_index, 3);
+    }
+
+    #[test]
+    fn splits_the_fees_between_the_miner_and_the_burn() {
+        let addrs = addrs();
+        let (bot, miner) = (addrs[0], addrs[1]);
+        let legacy = GasInfo {
+            gas_used: 21_000.into(),
+            gas_price: 100.into(),
+            ..Default::default()
+        };
+        let mut bribe = mk_inspection(vec![Classification::new(
+            MinerPayment {
+                from: bot,
+                to: miner,
+                amount: 1000.into(),
+            },
+            vec![0],
+        )]);
+        bribe.gas = Some(legacy.clone().with_fees(50.into(), 1000.into(), 2.into()));
+        let mut legacy_tx = mk_inspection(vec![]);
+        legacy_tx.gas = Some(legacy);
+
+        let block = Block {
+            number: 1,
+            miner,
+            base_fee: None,
+            timestamp: None,
+            inspections: vec![legacy_tx, bribe],
+            reorged: false,
+        };
+        // the legacy tx paid all its gas to the miner, but the EIP-1559 tx
+        // has its own base fee
+        assert_eq!(block.burned_fees(), 1_050_000.into());
+        assert_eq!(block.miner_revenue(), (2_100_000 + 42_000 + 1000).into());
+
+        // the base fee of the block is burned for legacy txs as well
+        let block = block.with_header(&BlockFees {
+            miner,
+            base_fee: Some(50.into()),
+            timestamp: 1_600_000_000.into(),
+        });
+        assert_eq!(block.timestamp, Some(1_600_000_000.into()));
+        assert_eq!(block.burned_fees(), 2_100_000.into());
+        assert_eq!(block.miner_revenue(), (1_050_000 + 42_000 + 1000).into());
+    }
+
+    #[tokio::test]
+    async fn fetches_and_inspects_a_block() {
+        let inspector = BatchInspector::new(
+            vec![Box::new(ERC20::new()), Box::new(UniswapV2::new())],
+            vec![
+                Box::new(TradeReducer::new()),
+                Box::new(ArbitrageReducer::new()),
+            ],
+        );
+        let mock = MockProvider::new();
+        mock.push::<Vec<Trace>, _>(TRACES.clone()).unwrap();
+        // the responses are popped from the back
+        mock.push(serde_json::json!({ "transactions": [] }))
+            .unwrap();
+        let provider = EthersTraceProvider::new(mock);
+
+        let block = Block::from_provider(&inspector, &provider, 11017338)
+            .await
+            .unwrap();
+        assert_eq!(block.number, 11017338);
+        assert_eq!(block.inspections.len(), 124);
+        assert_eq!(block.arbitrages().count(), 4);
+    }
+}
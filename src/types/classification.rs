@@ -1,8 +1,20 @@
 use crate::{addresses::lookup, is_subtrace, types::actions::SpecificAction};
-use ethers::types::Call;
+use ethers::types::{Bytes, Call};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use smallvec::SmallVec;
 use std::fmt;
 
+/// The position of a call in its transaction's call tree. Most calls are only
+/// a few levels deep, so their addresses are stored inline.
+///
+/// The `trace_address` fields of `ActionTrace` and `CallTrace` used to be
+/// `Vec<usize>`s. They deref to the same slice, but code which builds them
+/// directly from a `Vec` must now convert it with `.into()`.
+pub type TraceAddress = SmallVec<[usize; 4]>;
+
 #[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Classification {
     Known(ActionTrace),
     Unknown(CallTrace),
@@ -10,9 +22,40 @@ pub enum Classification {
 }
 
 #[derive(Debug, Clone, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ActionTrace {
     pub action: SpecificAction,
-    pub trace_address: Vec<usize>,
+    pub trace_address: TraceAddress,
+    /// How the action was classified
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub provenance: Provenance,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// How an action was classified, from the most to the least reliable, so
+/// that the classifications which relied on heuristics can be filtered out
+pub enum Provenance {
+    /// Decoded from the calldata and the output of the calls
+    #[default]
+    CalldataDecoded,
+    /// Decoded from the logs which the contracts emitted
+    EventDecoded,
+    /// Pieced together by matching the transfers made around a call, or built
+    /// from other actions which were
+    TransferInferred,
+}
+
+impl Provenance {
+    /// The provenance of an action built from `actions`, which is the least
+    /// reliable of theirs. The unknown calls and the pruned actions have none.
+    pub fn of<'a>(actions: impl IntoIterator<Item = &'a Classification>) -> Self {
+        actions
+            .into_iter()
+            .filter_map(|action| action.provenance())
+            .max()
+            .unwrap_or_default()
+    }
 }
 
 impl AsRef<SpecificAction> for ActionTrace {
@@ -28,9 +71,12 @@ impl From<ActionTrace> for Classification {
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct CallTrace {
     pub call: Call,
-    pub trace_address: Vec<usize>,
+    pub trace_address: TraceAddress,
+    /// The call's return data
+    pub output: Bytes,
 }
 
 impl AsRef<Call> for CallTrace {
@@ -46,19 +92,46 @@ impl From<CallTrace> for Classification {
 }
 
 impl Classification {
-    pub fn new<T: Into<SpecificAction>>(action: T, trace_address: Vec<usize>) -> Self {
+    pub fn new<T: Into<SpecificAction>>(action: T, trace_address: impl Into<TraceAddress>) -> Self {
         Classification::Known(ActionTrace {
             action: action.into(),
-            trace_address,
+            trace_address: trace_address.into(),
+            provenance: Provenance::default(),
         })
     }
 
+    /// Sets how the action was classified, unless this is not a known action
+    pub fn with_provenance(mut self, provenance: Provenance) -> Self {
+        if let Classification::Known(ref mut inner) = self {
+            inner.provenance = provenance;
+        }
+        self
+    }
+
+    /// How the action was classified, if this is a known action
+    pub fn provenance(&self) -> Option<Provenance> {
+        match self {
+            Classification::Known(ref inner) => Some(inner.provenance),
+            _ => None,
+        }
+    }
+
     /// Gets the trace address in this call (Empty if Prune)
-    pub fn trace_address(&self) -> Vec<usize> {
+    pub fn trace_address(&self) -> &[usize] {
         match &self {
-            Classification::Known(inner) => inner.trace_address.clone(),
-            Classification::Unknown(inner) => inner.trace_address.clone(),
-            Classification::Prune => vec![],
+            Classification::Known(inner) => &inner.trace_address,
+            Classification::Unknown(inner) => &inner.trace_address,
+            Classification::Prune => &[],
+        }
+    }
+
+    /// Sets the trace address of this call, unless it is a `Prune`
+    pub fn set_trace_address(&mut self, trace_address: impl Into<TraceAddress>) {
+        let trace_address = trace_address.into();
+        match self {
+            Classification::Known(inner) => inner.trace_address = trace_address,
+            Classification::Unknown(inner) => inner.trace_address = trace_address,
+            Classification::Prune => {}
         }
     }
 
@@ -71,7 +144,7 @@ impl Classification {
                 continue;
             }
 
-            if is_subtrace(&t1, &t2) {
+            if is_subtrace(t1, t2) {
                 *c = Classification::Prune;
             }
         }
@@ -84,7 +157,7 @@ impl Classification {
         for c in classifications.iter() {
             let t2 = c.trace_address();
 
-            if is_subtrace(&t1, &t2) {
+            if is_subtrace(t1, t2) {
                 v.push(c.clone());
             }
         }
@@ -120,6 +193,7 @@ impl fmt::Debug for Classification {
             Classification::Unknown(CallTrace {
                 call,
                 trace_address,
+                output,
             }) => f
                 .debug_struct("TraceCall")
                 .field("from", &lookup(call.from))
@@ -127,6 +201,7 @@ impl fmt::Debug for Classification {
                 .field("value", &call.value)
                 .field("gas", &call.gas)
                 .field("input", &hex::encode(&call.input))
+                .field("output", &hex::encode(output))
                 .field("call_type", &call.call_type)
                 .field("trace", trace_address)
                 .finish(),
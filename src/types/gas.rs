@@ -0,0 +1,318 @@
+use ethers::types::{Address, Transaction, TransactionReceipt, TxHash, U256, U64};
+use serde::Deserialize;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+/// The first block of the London hard fork, which introduced the base fee
+/// (EIP-1559) and removed most gas refunds (EIP-3529)
+pub const LONDON_BLOCK: u64 = 12_965_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The types of transactions, which price their gas differently
+pub enum TxType {
+    /// Pays its `gasPrice`
+    Legacy,
+    /// EIP-2930, a legacy transaction with an access list, which pays its
+    /// `gasPrice` as well
+    AccessList,
+    /// EIP-1559, which bids a `maxFeePerGas` and a `maxPriorityFeePerGas`
+    DynamicFee,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+/// The fees of a transaction as returned by the node, which ethers'
+/// `Transaction` does not have
+pub struct TxFees {
+    #[serde(default)]
+    pub hash: TxHash,
+    /// Missing until the transaction is mined
+    #[serde(default)]
+    pub block_number: Option<U64>,
+    /// Missing for the legacy transactions of nodes which predate EIP-2718
+    #[serde(rename = "type", default)]
+    pub tx_type: Option<U64>,
+    /// For EIP-1559 transactions, which nodes report it for, this is the price
+    /// which was paid once the transaction is mined
+    #[serde(default)]
+    pub gas_price: Option<U256>,
+    #[serde(default)]
+    pub max_fee_per_gas: Option<U256>,
+    #[serde(default)]
+    pub max_priority_fee_per_gas: Option<U256>,
+}
+
+impl TxFees {
+    /// The type of the transaction, legacy unless the node says otherwise
+    pub fn tx_type(&self) -> TxType {
+        match self.tx_type.map(|tx_type| tx_type.as_u64()) {
+            Some(1) => TxType::AccessList,
+            Some(2) => TxType::DynamicFee,
+            _ => TxType::Legacy,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+/// The fees of a block header as returned by the node, which ethers' `Block`
+/// does not have, along with its timestamp
+pub struct BlockFees {
+    /// Who the priority fees are paid to. After the merge this is the block's
+    /// fee recipient, which the validator or its builder set.
+    pub miner: Address,
+    /// Set for post-London blocks
+    #[serde(rename = "baseFeePerGas", default)]
+    pub base_fee: Option<U256>,
+    #[serde(default)]
+    pub timestamp: U256,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+/// A block with the fees of its txs, as returned by `eth_getBlockByNumber` and
+/// `eth_getBlockByHash` when asked for the full txs
+pub struct BlockWithFees {
+    #[serde(flatten)]
+    pub header: BlockFees,
+    /// Missing for pending blocks
+    #[serde(default)]
+    pub number: Option<U64>,
+    pub transactions: Vec<TxFees>,
+}
+
+/// The price which `tx` paid per gas in `block`, whatever its type. Legacy and
+/// EIP-2930 transactions pay their `gasPrice`, and EIP-1559 ones pay the base
+/// fee plus their priority fee, up to their max fee. Before London there is no
+/// base fee and all transactions pay their `gasPrice`.
+pub fn effective_gas_price(tx: &TxFees, block: &BlockFees) -> U256 {
+    let gas_price = tx.gas_price.unwrap_or_default();
+    match (tx.tx_type(), block.base_fee) {
+        (TxType::DynamicFee, Some(base_fee)) => {
+            match (tx.max_fee_per_gas, tx.max_priority_fee_per_gas) {
+                (Some(max_fee), Some(priority_fee)) => {
+                    std::cmp::min(max_fee, base_fee.saturating_add(priority_fee))
+                }
+                _ => gas_price,
+            }
+        }
+        _ => gas_price,
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// The gas paid by a transaction
+pub struct GasInfo {
+    /// The gas used by the transaction, from its receipt
+    pub gas_used: U256,
+    /// The transaction's `gasPrice`, which is what legacy and EIP-2930
+    /// transactions paid
+    pub gas_price: U256,
+    /// The block's base fee, for post-London blocks
+    pub base_fee: Option<U256>,
+    /// The transaction's `maxPriorityFeePerGas`, for EIP-1559 transactions
+    pub priority_fee: Option<U256>,
+    /// The transaction's `maxFeePerGas`, for EIP-1559 transactions
+    pub max_fee: Option<U256>,
+}
+
+impl GasInfo {
+    /// Creates the gas info of a legacy transaction from its receipt
+    pub fn new(tx: &Transaction, receipt: &TransactionReceipt) -> Self {
+        Self {
+            gas_used: receipt.gas_used.unwrap_or_default(),
+            gas_price: tx.gas_price,
+            ..Default::default()
+        }
+    }
+
+    /// Creates the gas info of a transaction of any type from its receipt and
+    /// the fees which the node returned for it and its block
+    pub fn from_fees(receipt: &TransactionReceipt, tx: &TxFees, block: &BlockFees) -> Self {
+        let gas = Self {
+            gas_used: receipt.gas_used.unwrap_or_default(),
+            gas_price: effective_gas_price(tx, block),
+            base_fee: block.base_fee,
+            ..Default::default()
+        };
+        match (
+            tx.tx_type(),
+            tx.max_fee_per_gas,
+            tx.max_priority_fee_per_gas,
+        ) {
+            (TxType::DynamicFee, Some(max_fee), Some(priority_fee)) => Self {
+                max_fee: Some(max_fee),
+                priority_fee: Some(priority_fee),
+                ..gas
+            },
+            _ => gas,
+        }
+    }
+
+    /// Sets the EIP-1559 fees of the transaction and the base fee of its block
+    pub fn with_fees(mut self, base_fee: U256, max_fee: U256, priority_fee: U256) -> Self {
+        self.base_fee = Some(base_fee);
+        self.max_fee = Some(max_fee);
+        self.priority_fee = Some(priority_fee);
+        self
+    }
+
+    /// The price which was actually paid per gas. For EIP-1559 transactions this
+    /// is `min(maxFee, baseFee + priorityFee)`, since the `gasPrice` which nodes
+    /// report for them is not what they paid.
+    pub fn effective_gas_price(&self) -> U256 {
+        match (self.base_fee, self.max_fee, self.priority_fee) {
+            (Some(base_fee), Some(max_fee), Some(priority_fee)) => {
+                std::cmp::min(max_fee, base_fee.saturating_add(priority_fee))
+            }
+            _ => self.gas_price,
+        }
+    }
+
+    /// The part of the effective gas price above the base fee, which is what
+    /// transactions are ordered by after London. `base_fee` is the block's, for
+    /// legacy transactions whose own base fee is not set.
+    pub fn priority_fee_per_gas(&self, base_fee: U256) -> U256 {
+        self.effective_gas_price()
+            .saturating_sub(self.base_fee.unwrap_or(base_fee))
+    }
+
+    /// The total paid for gas, in wei
+    pub fn cost(&self) -> U256 {
+        self.gas_used.saturating_mul(self.effective_gas_price())
+    }
+
+    /// The part of the cost which was burned by paying the base fee, which is
+    /// not revenue for the miner
+    pub fn burned(&self) -> U256 {
+        let base_fee = std::cmp::min(
+            self.base_fee.unwrap_or_default(),
+            self.effective_gas_price(),
+        );
+        self.gas_used.saturating_mul(base_fee)
+    }
+
+    /// The part of the cost which was paid to the miner, i.e. all of it before
+    /// London and the priority fees after
+    pub fn miner_fees(&self) -> U256 {
+        self.cost().saturating_sub(self.burned())
+    }
+}
+
+/// The base fee of the block after one with `base_fee`, which used `gas_used`
+/// out of its `gas_limit`, as set by EIP-1559. The base fee moves by up to
+/// 1/8th towards keeping blocks half full.
+pub fn next_base_fee(base_fee: U256, gas_used: U256, gas_limit: U256) -> U256 {
+    let target = gas_limit / 2;
+    if target.is_zero() || gas_used == target {
+        return base_fee;
+    }
+    if gas_used > target {
+        let delta = base_fee.saturating_mul(gas_used - target) / target / 8;
+        base_fee.saturating_add(std::cmp::max(delta, 1.into()))
+    } else {
+        let delta = base_fee.saturating_mul(target - gas_used) / target / 8;
+        base_fee.saturating_sub(delta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn predicts_the_next_base_fee() {
+        let gwei = |n: u64| U256::from(n) * 1_000_000_000u64;
+        let limit = U256::from(30_000_000);
+        // half full blocks keep the base fee
+        assert_eq!(next_base_fee(gwei(100), limit / 2, limit), gwei(100));
+        // full ones raise it by 12.5%, and empty ones lower it by as much
+        assert_eq!(
+            next_base_fee(gwei(100), limit, limit),
+            gwei(112) + gwei(1) / 2
+        );
+        assert_eq!(
+            next_base_fee(gwei(100), 0.into(), limit),
+            gwei(87) + gwei(1) / 2
+        );
+        // by at least 1 wei when the block is over its target
+        assert_eq!(next_base_fee(7.into(), limit / 2 + 1, limit), 8.into());
+    }
+
+    #[test]
+    fn effective_gas_price() {
+        let gas = GasInfo {
+            gas_used: 100_000.into(),
+            gas_price: 200.into(),
+            ..Default::default()
+        };
+        assert_eq!(gas.effective_gas_price(), 200.into());
+        assert_eq!(gas.cost(), 20_000_000.into());
+
+        // capped by the max fee
+        let capped = gas.clone().with_fees(90.into(), 100.into(), 20.into());
+        assert_eq!(capped.effective_gas_price(), 100.into());
+
+        // pays the base fee and the whole tip
+        let tipped = gas.with_fees(90.into(), 200.into(), 20.into());
+        assert_eq!(tipped.effective_gas_price(), 110.into());
+        assert_eq!(tipped.cost(), 11_000_000.into());
+        assert_eq!(tipped.priority_fee_per_gas(0.into()), 20.into());
+    }
+
+    #[test]
+    fn prices_each_type_of_transaction() {
+        let receipt = TransactionReceipt {
+            gas_used: Some(100_000.into()),
+            ..Default::default()
+        };
+        let gas = |tx: serde_json::Value, block: serde_json::Value| {
+            let tx: TxFees = serde_json::from_value(tx).unwrap();
+            let block: BlockFees = serde_json::from_value(block).unwrap();
+            GasInfo::from_fees(&receipt, &tx, &block)
+        };
+        let miner = "0x0000000000000000000000000000000000000001";
+        let berlin = serde_json::json!({ "miner": miner });
+        let london = serde_json::json!({ "miner": miner, "baseFeePerGas": "0x5a" });
+
+        // a legacy tx, from a node which does not report the type, pays its
+        // gas price to the miner before London
+        let legacy = gas(serde_json::json!({ "gasPrice": "0xc8" }), berlin.clone());
+        assert_eq!(legacy.effective_gas_price(), 200.into());
+        assert_eq!(legacy.burned(), 0.into());
+        assert_eq!(legacy.miner_fees(), 20_000_000.into());
+
+        // after, the base fee is burned
+        let legacy = gas(
+            serde_json::json!({ "type": "0x0", "gasPrice": "0xc8" }),
+            london.clone(),
+        );
+        assert_eq!(legacy.effective_gas_price(), 200.into());
+        assert_eq!(legacy.burned(), 9_000_000.into());
+        assert_eq!(legacy.miner_fees(), 11_000_000.into());
+
+        // EIP-2930 txs pay their gas price as well
+        let tx = serde_json::json!({ "type": "0x1", "gasPrice": "0xc8" });
+        let access_list = gas(tx.clone(), berlin);
+        assert_eq!(access_list.cost(), 20_000_000.into());
+        assert_eq!(gas(tx, london.clone()), legacy);
+
+        // EIP-1559 txs pay the base fee and their tip, up to their max fee,
+        // whatever the gas price the node reports
+        let tx = |max_fee: &str| {
+            serde_json::json!({
+                "type": "0x2",
+                "gasPrice": "0x1",
+                "maxFeePerGas": max_fee,
+                "maxPriorityFeePerGas": "0x14",
+            })
+        };
+        let tipped = gas(tx("0xc8"), london.clone());
+        assert_eq!(tipped.effective_gas_price(), 110.into());
+        assert_eq!(tipped.burned(), 9_000_000.into());
+        assert_eq!(tipped.miner_fees(), 2_000_000.into());
+        let capped = gas(tx("0x64"), london);
+        assert_eq!(capped.effective_gas_price(), 100.into());
+        assert_eq!(capped.miner_fees(), 1_000_000.into());
+    }
+}
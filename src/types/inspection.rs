@@ -1,26 +1,58 @@
+#[cfg(feature = "serde")]
+use crate::types::snapshot::{Difference, Snapshot};
 use crate::{
-    addresses::{DYDX, FILTER, ZEROX},
+    addresses::{DYDX, ETH, FILTER, PROTOCOLS, ZEROX},
+    is_subtrace,
+    prices::{net_profit, signed, PriceOracle},
     types::{
+        actions::{
+            Arbitrage, Deployment, Liquidation, MinerPayment, Searcher, SelfDestruct,
+            SpecificAction, Trade, Transfer,
+        },
         classification::{ActionTrace, CallTrace},
-        Classification, Protocol, Status,
+        CheckedReason, Classification, GasInfo, Protocol, Report, RevertReason, Status,
+        TraceAddress,
     },
 };
-use ethers::types::{Action, Address, CallType, Trace, TxHash};
-use std::{collections::HashSet, convert::TryFrom};
+use ethers::types::{
+    Action, Address, Bytes, CallType, Log, Res, Trace, Transaction, TransactionReceipt, TxHash,
+    I256, U256,
+};
+use indexmap::{IndexMap, IndexSet};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryFrom,
+};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 /// The result of an inspection of a trace along with its inspected subtraces
 pub struct Inspection {
     /// Success / failure
     pub status: Status,
+    /// Why the tx, or the first of its calls which failed, reverted. This is
+    /// only known for nodes which report the output of failed calls, e.g. Geth
+    pub revert_reason: Option<RevertReason>,
+    /// Why the tx was marked as `Checked`, if it was. This is kept apart from
+    /// the `status` so that the status is stored as it always was
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub checked_reason: Option<CheckedReason>,
 
     //////  What
     /// All the classified / unclassified actions that happened
     pub actions: Vec<Classification>,
 
     ///// Where
-    /// All the involved protocols
-    pub protocols: HashSet<Protocol>,
+    /// All the involved protocols, in the order in which they were found
+    pub protocols: IndexSet<Protocol>,
+    /// The reads of reserves or quotes which bots make before trading, to
+    /// check whether their trades are profitable, and the protocols they were
+    /// made on. They are resolved once all the inspectors ran, since the trade
+    /// which follows a read may be classified by another inspector.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) preflights: Vec<(TraceAddress, Protocol)>,
 
     // Who
     /// The sender of the transaction
@@ -38,9 +70,95 @@ pub struct Inspection {
 
     /// The block number of this tx
     pub block_number: u64,
+    /// The position of this tx in its block
+    pub tx_index: Option<usize>,
+
+    ///// How much
+    /// The gas paid by this tx, if its receipt has been fetched
+    pub gas: Option<GasInfo>,
+    /// The logs emitted by this tx, if its receipt has been fetched
+    pub logs: Vec<Log>,
 }
 
 impl Inspection {
+    /// Creates an inspection of a successful transaction made of `actions`,
+    /// e.g. for testing reducers without a node. The other fields can be set
+    /// afterwards.
+    ///
+    /// ```
+    /// use ethers::types::Address;
+    /// use mev_inspect::{
+    ///     reducers::TradeReducer,
+    ///     types::{
+    ///         actions::{Trade, Transfer},
+    ///         Classification, Inspection,
+    ///     },
+    ///     Reducer,
+    /// };
+    ///
+    /// let (trader, pair) = (Address::repeat_byte(1), Address::repeat_byte(2));
+    /// let (dai, weth) = (Address::repeat_byte(3), Address::repeat_byte(4));
+    /// let sold = Transfer::new(trader, pair, 100.into(), dai);
+    /// let bought = Transfer::new(pair, trader, 1.into(), weth);
+    ///
+    /// let mut inspection = Inspection::from_actions(vec![
+    ///     Classification::new(sold.clone(), vec![0]),
+    ///     Classification::new(bought.clone(), vec![1]),
+    /// ]);
+    /// TradeReducer::new().reduce(&mut inspection);
+    /// inspection.prune();
+    ///
+    /// assert_eq!(
+    ///     inspection.trades().collect::<Vec<_>>(),
+    ///     vec![&Trade::new(sold, bought)]
+    /// );
+    /// assert_eq!(inspection.transfers().count(), 0);
+    /// ```
+    pub fn from_actions(actions: Vec<Classification>) -> Self {
+        Inspection {
+            status: Status::Success,
+            revert_reason: None,
+            checked_reason: None,
+            actions,
+            protocols: IndexSet::new(),
+            preflights: Vec::new(),
+            from: Address::zero(),
+            contract: Address::zero(),
+            proxy_impl: None,
+            hash: TxHash::zero(),
+            block_number: 0,
+            tx_index: None,
+            gas: None,
+            logs: Vec::new(),
+        }
+    }
+
+    /// Creates an inspection without any actions from a transaction and its
+    /// receipt, for when its traces are not available. Its actions can then be
+    /// classified from its logs with the `EventLogs` inspector.
+    pub fn from_receipt(tx: &Transaction, receipt: &TransactionReceipt) -> Self {
+        Inspection {
+            status: if receipt.status == Some(0.into()) {
+                Status::Reverted
+            } else {
+                Status::Success
+            },
+            revert_reason: None,
+            checked_reason: None,
+            actions: Vec::new(),
+            protocols: IndexSet::new(),
+            preflights: Vec::new(),
+            from: tx.from,
+            contract: tx.to.unwrap_or_else(Address::zero),
+            proxy_impl: None,
+            hash: tx.hash,
+            block_number: tx.block_number.unwrap_or_default().as_u64(),
+            tx_index: Some(receipt.transaction_index.as_usize()),
+            gas: Some(GasInfo::new(tx, receipt)),
+            logs: receipt.logs.clone(),
+        }
+    }
+
     // TODO: Is there a better way to do this without re-allocating?
     // Maybe this? https://doc.rust-lang.org/std/vec/struct.DrainFilter.html
     pub fn prune(&mut self) {
@@ -60,10 +178,358 @@ impl Inspection {
             .collect();
     }
 
-    /// Returns: types of protocols, types of actions (arb, liq), bot addresses and profit
-    /// Bots that perform liq/arbs maybe for a profit that are not in the addressbook should be
-    /// added
-    pub fn summary(&self) {}
+    /// Returns the contract which `proxy` delegated to in this transaction, i.e.
+    /// the target of its first `DELEGATECALL`. Proxies which were not called
+    /// can be resolved with `proxy::resolve_implementation`.
+    pub fn implementation_of(&self, proxy: Address) -> Option<Address> {
+        self.actions.iter().find_map(|action| {
+            let call = action.as_call()?.as_ref();
+            if call.call_type == CallType::DelegateCall && call.from == proxy {
+                Some(call.to)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Folds the `DELEGATECALL` frames into the frames which made them, so that
+    /// their subcalls appear as made by the caller, e.g. by a proxy instead of
+    /// its implementation. The subcalls take the place of the `DELEGATECALL`
+    /// among the caller's subcalls, and the trace addresses of the calls after
+    /// it are shifted accordingly.
+    ///
+    /// This must run before the inspectors, since `implementation_of` can not
+    /// find the implementations anymore.
+    pub fn fold_delegatecalls(&mut self) {
+        let is_delegatecall = |action: &Classification| matches!(action.as_call(), Some(call) if call.as_ref().call_type == CallType::DelegateCall);
+        let delegatecalls = self
+            .actions
+            .iter()
+            .filter(|action| is_delegatecall(action))
+            .map(|action| action.trace_address().to_vec())
+            .collect::<HashSet<_>>();
+        if delegatecalls.is_empty() {
+            return;
+        }
+
+        // the number of subcalls of each `DELEGATECALL`, which replace it
+        let mut subcalls = HashMap::new();
+        for action in self.actions.iter() {
+            if let Some((index, caller)) = action.trace_address().split_last() {
+                if delegatecalls.contains(caller) {
+                    let count = subcalls.entry(caller.to_vec()).or_insert(0);
+                    *count = std::cmp::max(*count, index + 1);
+                }
+            }
+        }
+
+        // where the subcalls of each frame go: the new trace address of the
+        // frame they end up under, and by how much their indices get shifted
+        let mut frames: HashMap<Vec<usize>, (Vec<usize>, isize)> = HashMap::new();
+        frames.insert(Vec::new(), (Vec::new(), 0));
+
+        for mut action in std::mem::take(&mut self.actions) {
+            let trace_address = action.trace_address().to_vec();
+            let (index, caller) = match trace_address.split_last() {
+                Some((index, caller)) => (*index, caller),
+                // the top level call and `Prune`s
+                None => {
+                    self.actions.push(action);
+                    continue;
+                }
+            };
+            let (address, offset) = resolve(&mut frames, caller);
+            let index = (index as isize + offset) as usize;
+
+            if delegatecalls.contains(&trace_address) {
+                // the calls after the `DELEGATECALL` make room for its
+                // subcalls, up to the first caller which was not folded
+                let shift = *subcalls.get(&trace_address).unwrap_or(&0) as isize - 1;
+                let mut caller = caller.to_vec();
+                loop {
+                    if let Some((_, offset)) = frames.get_mut(&caller) {
+                        *offset += shift;
+                    }
+                    if !delegatecalls.contains(&caller) || caller.pop().is_none() {
+                        break;
+                    }
+                }
+                frames.insert(trace_address, (address, index as isize));
+            } else {
+                let mut new_address = address;
+                new_address.push(index);
+                frames.insert(trace_address, (new_address.clone(), 0));
+                action.set_trace_address(new_address);
+                self.actions.push(action);
+            }
+        }
+    }
+
+    /// Summarizes the protocols, the actions, the profit and the gas of this
+    /// inspection, e.g. to print it with `println!("{}", inspection.report())`.
+    /// The profit is in raw token amounts, see `priced_report`.
+    pub fn report(&self) -> Report {
+        Report::new(self)
+    }
+
+    /// Like `report`, with the profit priced in ETH and USD by `oracle`
+    pub fn priced_report<O: PriceOracle>(&self, oracle: &O) -> Report {
+        Report::with_prices(self, oracle)
+    }
+
+    /// The classified actions of this inspection in a stable serialized form,
+    /// e.g. to check them in as the expected classification of a fixture
+    #[cfg(feature = "serde")]
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot::new(self)
+    }
+
+    /// How the classification of this inspection differs from the `expected`
+    /// one, ignoring the gas, the logs and the fields which identify the tx
+    #[cfg(feature = "serde")]
+    pub fn diff(&self, expected: &Inspection) -> Vec<Difference> {
+        self.snapshot().diff(&expected.snapshot())
+    }
+
+    /// Classifies all the ETH which was sent to `coinbase`, either with a call
+    /// or by self-destructing to it, as payments to the miner. Self-destructs
+    /// send their ETH with the `Transfer` which follows them.
+    pub fn classify_miner_payments(&mut self, coinbase: Address) {
+        for action in self.actions.iter_mut() {
+            let payment = match action {
+                Classification::Unknown(calltrace) => {
+                    let call = calltrace.as_ref();
+                    if call.to != coinbase || call.value.is_zero() {
+                        continue;
+                    }
+                    MinerPayment {
+                        from: call.from,
+                        to: coinbase,
+                        amount: call.value,
+                    }
+                }
+                Classification::Known(inner) => match inner.as_ref() {
+                    SpecificAction::Transfer(t) if t.to == coinbase && t.token == *ETH => {
+                        MinerPayment {
+                            from: t.from,
+                            to: coinbase,
+                            amount: t.amount,
+                        }
+                    }
+                    _ => continue,
+                },
+                Classification::Prune => continue,
+            };
+            *action = Classification::new(payment, action.trace_address());
+        }
+    }
+
+    /// The EOA which sent this tx and the contract which it called, e.g. its
+    /// bot, which the MEV found in it is attributed to
+    pub fn searcher(&self) -> Searcher {
+        Searcher {
+            eoa: self.from,
+            contract: self.contract,
+        }
+    }
+
+    /// Marks the tx as `Checked`, recording why in its `checked_reason`
+    pub fn mark_checked(&mut self, reason: CheckedReason) {
+        self.status = Status::Checked;
+        self.checked_reason = Some(reason);
+    }
+
+    /// Tags the protocols of the preflight reads which were followed by a
+    /// trade, on any protocol. If none was, and the tx did not trade, it was
+    /// an arb check which reverted early. The reads are only resolved once.
+    pub fn resolve_preflights(&mut self) {
+        let preflights = std::mem::take(&mut self.preflights);
+        if preflights.is_empty() {
+            return;
+        }
+        let mut followed = false;
+        for (read, protocol) in preflights.iter() {
+            if self.trades_after(read) {
+                self.protocols.insert(*protocol);
+                followed = true;
+            }
+        }
+
+        // Approvals are not attempts, bots often make them along with their trades
+        if !followed
+            && self.trades().next().is_none()
+            && self
+                .known_actions()
+                .filter(|action| action.approval().is_none())
+                .count()
+                < 2
+        {
+            let reason =
+                CheckedReason::new(self.revert_reason.as_ref(), CheckedReason::ArbRevertedEarly);
+            self.mark_checked(reason);
+        }
+    }
+
+    /// Whether a trade was made after the call at `trace_address`
+    pub(crate) fn trades_after(&self, trace_address: &[usize]) -> bool {
+        self.actions.iter().any(|action| match action {
+            Classification::Known(inner) => {
+                inner.as_ref().trade().is_some() && inner.trace_address[..] > *trace_address
+            }
+            _ => false,
+        })
+    }
+
+    /// The total ETH paid to the miner by this tx
+    pub fn coinbase_payment(&self) -> U256 {
+        self.known_actions()
+            .filter_map(|action| action.miner_payment())
+            .fold(U256::zero(), |total, payment| {
+                total.saturating_add(payment.amount)
+            })
+    }
+
+    /// The net change of the balance of each (account, token) in this tx, from
+    /// its transfers, the legs of its trades and the ETH sent by its calls,
+    /// self-destructs and payments to the miner. ETH is keyed as `ETH`.
+    ///
+    /// The profit of an arbitrage or a sandwich's leg is then the delta of
+    /// the searcher's contract in the profit token. The transfers of the seized
+    /// collateral, which the `LiquidationReducer` prunes, are credited to the
+    /// liquidators, without debiting the protocol which sent them. Likewise,
+    /// mints only credit their recipient and burns only debit their sender.
+    /// The amounts which are too large to be signed are left out.
+    pub fn balance_deltas(&self) -> HashMap<(Address, Address), I256> {
+        // (sender, receiver, token, amount), without a sender for the credits
+        let mut flows: Vec<(Option<Address>, Address, Address, U256)> = Vec::new();
+        for action in &self.actions {
+            let action = match action {
+                Classification::Known(inner) => inner.as_ref(),
+                Classification::Unknown(calltrace) => {
+                    let call = calltrace.as_ref();
+                    if call.call_type == CallType::Call && !call.value.is_zero() {
+                        flows.push((Some(call.from), call.to, *ETH, call.value));
+                    }
+                    continue;
+                }
+                Classification::Prune => continue,
+            };
+            let trades = match action {
+                SpecificAction::Transfer(t) => {
+                    flows.push((Some(t.from), t.to, t.token, t.amount));
+                    continue;
+                }
+                SpecificAction::AllowanceExploit(exploit) => {
+                    let t = &exploit.transfer;
+                    flows.push((Some(t.from), t.to, t.token, t.amount));
+                    continue;
+                }
+                SpecificAction::MinerPayment(p) => {
+                    flows.push((Some(p.from), p.to, *ETH, p.amount));
+                    continue;
+                }
+                SpecificAction::Liquidation(liq) => {
+                    flows.push((None, liq.from, liq.received_token, liq.received_amount));
+                    continue;
+                }
+                SpecificAction::ProfitableLiquidation(liq) => {
+                    let liq = liq.as_ref();
+                    flows.push((None, liq.from, liq.received_token, liq.received_amount));
+                    continue;
+                }
+                SpecificAction::Trade(trade) => vec![trade],
+                SpecificAction::Arbitrage(arb) => arb.trades.iter().collect(),
+                SpecificAction::OpenArbitrage(arb) => arb.trades.iter().collect(),
+                SpecificAction::OracleManipulation(manipulation) => {
+                    vec![&manipulation.swap, &manipulation.reverse_swap]
+                }
+                _ => continue,
+            };
+            for trade in trades {
+                for t in &[&trade.t1, &trade.t2] {
+                    flows.push((Some(t.from), t.to, t.token, t.amount));
+                }
+            }
+        }
+
+        let mut deltas: HashMap<(Address, Address), I256> = HashMap::new();
+        for (from, to, token, amount) in flows {
+            // e.g. a mint of `U256::MAX` by a fake token, which cannot be signed
+            let amount = match signed(amount) {
+                Some(amount) => amount,
+                None => continue,
+            };
+            // mints and burns change the supply rather than the balance of `0x0`
+            if let Some(from) = from.filter(|from| !from.is_zero()) {
+                let delta = deltas.entry((from, token)).or_insert_with(I256::zero);
+                *delta = delta.saturating_sub(amount);
+            }
+            if !to.is_zero() {
+                let delta = deltas.entry((to, token)).or_insert_with(I256::zero);
+                *delta = delta.saturating_add(amount);
+            }
+        }
+        deltas
+    }
+
+    /// The gas refunded for the gas tokens burned by this tx. The refunds of a
+    /// tx are capped to half of its gas before them, i.e. to the gas used after
+    /// them, which is only known if its gas is set.
+    pub fn gas_token_refund(&self) -> U256 {
+        let refund = self
+            .known_actions()
+            .filter_map(|action| action.gas_token())
+            .fold(U256::zero(), |total, burn| {
+                total.saturating_add(burn.refund(self.block_number))
+            });
+        match self.gas {
+            Some(ref gas) => std::cmp::min(refund, gas.gas_used),
+            None => refund,
+        }
+    }
+
+    /// The ETH made by the arbitrages and liquidations of this tx, net of the
+    /// gas, the flash loan fees and the payments to the miner. Returns `None` if
+    /// the gas is not set or if any of the tokens cannot be priced.
+    ///
+    /// The gas tokens burned by the tx were paid for when they were minted, so
+    /// the gas they refunded is counted as paid at the tx's gas price.
+    pub fn net_profit_eth<O: PriceOracle>(&self, oracle: &O) -> Option<I256> {
+        let cost = self.total_cost()?;
+        let block = self.block_number;
+        let mut profit = I256::zero();
+        for action in self.known_actions() {
+            let gross = match action {
+                SpecificAction::Arbitrage(arb) => arb.eth_profit(oracle, block)?,
+                SpecificAction::Liquidation(liq) => liq.eth_profit(oracle, block)?,
+                SpecificAction::ProfitableLiquidation(liq) => {
+                    net_profit(oracle, vec![(liq.token, signed(liq.profit)?)], block)?
+                }
+                // the fees of flash loans are not part of the trades they fund
+                SpecificAction::FlashLoan(loan) => {
+                    net_profit(oracle, vec![(loan.token, -signed(loan.fee)?)], block)?
+                }
+                _ => continue,
+            };
+            profit = profit.checked_add(gross)?;
+        }
+        profit.checked_sub(signed(cost)?)
+    }
+
+    /// What the tx cost its sender in wei: its gas, the gas refunded by the gas
+    /// tokens it burned, and its payments to the miner. Returns `None` if the
+    /// gas is not set.
+    pub fn total_cost(&self) -> Option<U256> {
+        let gas = self.gas.as_ref()?;
+        let refund = self
+            .gas_token_refund()
+            .saturating_mul(gas.effective_gas_price());
+        Some(
+            gas.cost()
+                .saturating_add(refund)
+                .saturating_add(self.coinbase_payment()),
+        )
+    }
 
     /// Returns all the successfully classified calls in this Inspection
     pub fn known(&self) -> Vec<ActionTrace> {
@@ -77,6 +543,93 @@ impl Inspection {
             .collect()
     }
 
+    /// All the classified actions of this Inspection, in trace order
+    pub fn known_actions(&self) -> impl Iterator<Item = &SpecificAction> {
+        self.actions.iter().filter_map(|action| action.as_action())
+    }
+
+    /// The trades of this Inspection
+    ///
+    /// ```
+    /// use ethers::types::Address;
+    /// use mev_inspect::types::{
+    ///     actions::{Trade, Transfer},
+    ///     Classification, Inspection,
+    /// };
+    ///
+    /// let (trader, pair) = (Address::repeat_byte(1), Address::repeat_byte(2));
+    /// let (dai, weth) = (Address::repeat_byte(3), Address::repeat_byte(4));
+    /// let sold = Transfer { from: trader, to: pair, amount: 100.into(), token: dai };
+    /// let bought = Transfer { from: pair, to: trader, amount: 1.into(), token: weth };
+    ///
+    /// let inspection = Inspection::from_actions(vec![
+    ///     Classification::new(Trade::new(sold.clone(), bought), vec![0]),
+    ///     Classification::new(sold, vec![1]),
+    /// ]);
+    /// assert_eq!(inspection.trades().count(), 1);
+    /// assert_eq!(inspection.transfers().count(), 1);
+    /// ```
+    pub fn trades(&self) -> impl Iterator<Item = &Trade> {
+        self.known_actions().filter_map(|action| action.trade())
+    }
+
+    /// The transfers of this Inspection which are not part of a trade
+    pub fn transfers(&self) -> impl Iterator<Item = &Transfer> {
+        self.known_actions().filter_map(|action| action.transfer())
+    }
+
+    /// The liquidations of this Inspection, whether their profit is known or not
+    pub fn liquidations(&self) -> impl Iterator<Item = &Liquidation> {
+        self.known_actions().filter_map(|action| match action {
+            SpecificAction::Liquidation(liq) => Some(liq),
+            SpecificAction::ProfitableLiquidation(liq) => Some(liq.as_ref()),
+            _ => None,
+        })
+    }
+
+    /// The arbitrages of this Inspection
+    pub fn arbitrages(&self) -> impl Iterator<Item = &Arbitrage> {
+        self.known_actions().filter_map(|action| action.arbitrage())
+    }
+
+    /// Returns the protocol which most of this Inspection's trades, including
+    /// the hops of its arbitrages, were made on. Pools are attributed to the
+    /// forks we know their address of, so that e.g. Sushiswap and Uniswap
+    /// trades are told apart. Ties go to the protocol which was found first,
+    /// and if none of the pools is known, that is the first found protocol.
+    pub fn primary_protocol(&self) -> Option<Protocol> {
+        let mut counts = self
+            .protocols
+            .iter()
+            .map(|protocol| (*protocol, 0))
+            .collect::<IndexMap<_, _>>();
+        let trades = self.known_actions().flat_map(|action| match action {
+            SpecificAction::Trade(trade) => vec![trade],
+            SpecificAction::Arbitrage(arb) => arb.trades.iter().collect(),
+            SpecificAction::Frontrun(frontrun) => vec![&frontrun.frontrun],
+            SpecificAction::OracleManipulation(manipulation) => {
+                vec![&manipulation.swap, &manipulation.reverse_swap]
+            }
+            _ => vec![],
+        });
+        for trade in trades {
+            let pool = PROTOCOLS
+                .get(&trade.t1.to)
+                .or_else(|| PROTOCOLS.get(&trade.t2.from));
+            if let Some(protocol) = pool {
+                *counts.entry(*protocol).or_insert(0) += 1;
+            }
+        }
+
+        counts
+            .into_iter()
+            .fold(None, |primary, (protocol, count)| match primary {
+                Some((_, max)) if max >= count => primary,
+                _ => Some((protocol, count)),
+            })
+            .map(|(protocol, _)| protocol)
+    }
+
     /// Returns all the unsuccessfully classified calls in this Inspection
     pub fn unknown(&self) -> Vec<CallTrace> {
         self.actions
@@ -90,6 +643,25 @@ impl Inspection {
     }
 }
 
+/// Returns where the subcalls of the frame at `trace_address` go when folding
+/// `DELEGATECALL`s. Frames which are missing from the inspection, e.g. contract
+/// creations, keep their place under their caller.
+fn resolve(
+    frames: &mut HashMap<Vec<usize>, (Vec<usize>, isize)>,
+    trace_address: &[usize],
+) -> (Vec<usize>, isize) {
+    if let Some(frame) = frames.get(trace_address) {
+        return frame.clone();
+    }
+    let (index, caller) = trace_address
+        .split_last()
+        .expect("the top level frame is always known");
+    let (mut address, offset) = resolve(frames, caller);
+    address.push((*index as isize + offset) as usize);
+    frames.insert(trace_address.to_vec(), (address.clone(), 0));
+    (address, 0)
+}
+
 /// Helper type to bypass https://github.com/rust-lang/rust/issues/50133#issuecomment-646908391
 pub(crate) struct TraceWrapper<T>(pub(crate) T);
 impl<T: IntoIterator<Item = Trace>> TryFrom<TraceWrapper<T>> for Inspection {
@@ -103,38 +675,81 @@ impl<T: IntoIterator<Item = Trace>> TryFrom<TraceWrapper<T>> for Inspection {
             Some(inner) => inner,
             None => return Err(()),
         };
-        let call = match trace.action {
-            Action::Call(ref call) => call,
-            // the first action we care about must be a call. everything else
-            // is junk
+        let (from, contract) = match (&trace.action, &trace.result) {
+            (Action::Call(call), _) => (call.from, call.to),
+            // a transaction which deploys a contract
+            (Action::Create(create), Some(Res::Create(res))) => (create.from, res.address),
+            (Action::Create(create), _) => (create.from, Address::zero()),
+            // the first action we care about must be a call or a creation.
+            // everything else is junk
             _ => return Err(()),
         };
 
         // Filter out unwanted calls
-        if FILTER.get(&call.to).is_some() {
+        if FILTER.get(&contract).is_some() {
             return Err(());
         }
 
+        // the tx reverted if its top level call did, while the calls which
+        // failed within it may have been caught
+        let reverted = trace.error.is_some();
         let mut inspection = Inspection {
-            status: Status::Success,
+            status: if reverted {
+                Status::Reverted
+            } else {
+                Status::Success
+            },
+            revert_reason: None,
+            checked_reason: None,
             // all unclassified calls
             actions: Vec::new(),
             // start off with empty protocols since everything is unclassified
-            protocols: HashSet::new(),
-            from: call.from,
-            contract: call.to,
+            protocols: IndexSet::new(),
+            preflights: Vec::new(),
+            from,
+            contract,
             proxy_impl: None,
             hash: trace.transaction_hash.unwrap_or_else(TxHash::zero),
             block_number: trace.block_number,
+            tx_index: trace.transaction_position,
+            gas: None,
+            logs: Vec::new(),
         };
 
+        // the calls which failed within a tx which did not revert
+        let mut failed: Vec<Vec<usize>> = Vec::new();
         inspection.actions = traces
             .into_iter()
             .filter_map(|trace| {
-                // Revert if all subtraces revert? There are counterexamples
-                // e.g. when a low-level trace's revert is handled
-                if trace.error.is_some() {
-                    inspection.status = Status::Reverted;
+                let output = match trace.result {
+                    Some(Res::Call(ref res)) => res.output.clone(),
+                    _ => Bytes::default(),
+                };
+                // the output of a failed call is its revert data, which must
+                // not be decoded as its return value
+                let output = if trace.error.is_some() {
+                    if inspection.revert_reason.is_none() && trace.result.is_some() {
+                        inspection.revert_reason = Some(RevertReason::decode(output.as_ref()));
+                    }
+                    Bytes::default()
+                } else {
+                    output
+                };
+
+                // a failed call and its subcalls had no effect, e.g. a transfer
+                // within it never happened. The calls of a reverted tx are kept
+                // as what it attempted.
+                if !reverted {
+                    if failed
+                        .iter()
+                        .any(|frame| is_subtrace(frame, &trace.trace_address))
+                    {
+                        return None;
+                    }
+                    if trace.error.is_some() {
+                        failed.push(trace.trace_address);
+                        return None;
+                    }
                 }
 
                 match trace.action {
@@ -157,16 +772,403 @@ impl<T: IntoIterator<Item = Trace>> TryFrom<TraceWrapper<T>> for Inspection {
                         Some(
                             CallTrace {
                                 call,
-                                trace_address: trace.trace_address,
+                                trace_address: trace.trace_address.into(),
+                                output,
                             }
                             .into(),
                         )
                     }
+                    // the contracts created by failed frames are discarded
+                    Action::Create(create) if trace.error.is_none() => match trace.result {
+                        Some(Res::Create(res)) => Some(Classification::new(
+                            Deployment {
+                                deployer: create.from,
+                                contract: res.address,
+                                value: create.value,
+                            },
+                            trace.trace_address,
+                        )),
+                        _ => None,
+                    },
+                    // the ETH of a self-destruct is sent to its beneficiary with a
+                    // transfer, so that it is tracked as any other ETH
+                    Action::Suicide(suicide) => {
+                        let sent = if suicide.balance.is_zero() {
+                            None
+                        } else {
+                            Some(Classification::new(
+                                Transfer {
+                                    from: suicide.address,
+                                    to: suicide.refund_address,
+                                    amount: suicide.balance,
+                                    token: *ETH,
+                                },
+                                trace.trace_address.clone(),
+                            ))
+                        };
+                        let destruct = Classification::new(
+                            SelfDestruct {
+                                contract: suicide.address,
+                                beneficiary: suicide.refund_address,
+                                balance: suicide.balance,
+                            },
+                            trace.trace_address,
+                        );
+                        return Some((destruct, sent));
+                    }
                     _ => None,
                 }
+                .map(|action| (action, None))
             })
+            .flat_map(|(action, sent)| std::iter::once(action).chain(sent))
             .collect();
 
         Ok(inspection)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        addresses::parse_address, inspectors::*, reducers::*, test_helpers::*, BatchInspector,
+        Inspector,
+    };
+
+    fn delegatecall(from: Address, to: Address, trace_address: Vec<usize>) -> Classification {
+        let mut call = mk_call(from, to, Bytes::default(), trace_address);
+        if let Classification::Unknown(ref mut calltrace) = call {
+            calltrace.call.call_type = CallType::DelegateCall;
+        }
+        call
+    }
+
+    /// A Parity trace of a call which transfers `token` to `to`, or which
+    /// failed
+    fn transfer_trace(
+        token: Address,
+        to: Address,
+        trace_address: Vec<usize>,
+        failed: bool,
+    ) -> Trace {
+        let mut trace = serde_json::json!({
+            "action": {
+                "callType": "call",
+                "from": format!("{:?}", Address::zero()),
+                "gas": "0x0",
+                "input": transfer(to, 1.into()),
+                "to": format!("{:?}", token),
+                "value": "0x0",
+            },
+            "blockHash": format!("{:?}", TxHash::zero()),
+            "blockNumber": 1,
+            "result": { "gasUsed": "0x0", "output": "0x" },
+            "subtraces": 0,
+            "traceAddress": trace_address,
+            "transactionHash": format!("{:?}", TxHash::zero()),
+            "transactionPosition": 0,
+            "type": "call",
+        });
+        if failed {
+            trace["error"] = "Reverted".into();
+            trace["result"] = serde_json::Value::Null;
+        }
+        serde_json::from_value(trace).unwrap()
+    }
+
+    #[test]
+    fn prunes_the_calls_which_were_reverted() {
+        let addrs = addrs();
+        let (token, alice, bob, carol) = (addrs[0], addrs[1], addrs[2], addrs[3]);
+        let traces = |reverted: bool| {
+            vec![
+                transfer_trace(token, alice, vec![], reverted),
+                transfer_trace(token, alice, vec![0], false),
+                // a call which was caught when it reverted, whose subcalls
+                // succeeded but were reverted along with it
+                transfer_trace(token, bob, vec![1], true),
+                transfer_trace(token, bob, vec![1, 0], false),
+                transfer_trace(token, bob, vec![1, 0, 0], false),
+                transfer_trace(token, carol, vec![2], false),
+            ]
+        };
+        let inspect = |traces: Vec<Trace>| {
+            let mut inspection = Inspection::try_from(TraceWrapper(traces)).unwrap();
+            ERC20::new().inspect(&mut inspection);
+            inspection
+        };
+
+        let inspection = inspect(traces(false));
+        assert_eq!(inspection.status, Status::Success);
+        let receivers = inspection
+            .transfers()
+            .map(|transfer| transfer.to)
+            .collect::<Vec<_>>();
+        assert_eq!(receivers, vec![alice, alice, carol]);
+
+        // the calls of a reverted tx are kept as what it attempted
+        let inspection = inspect(traces(true));
+        assert_eq!(inspection.status, Status::Reverted);
+        assert_eq!(inspection.actions.len(), 6);
+    }
+
+    #[test]
+    fn folds_delegatecalls_into_their_callers() {
+        let addrs = addrs();
+        let (user, proxy, implementation, library) = (addrs[0], addrs[1], addrs[2], addrs[3]);
+        let (a, b, c, d, e) = (addrs[4], addrs[5], addrs[6], addrs[7], addrs[8]);
+        let call = |from, to, trace_address| mk_call(from, to, Bytes::default(), trace_address);
+
+        let mut inspection = mk_inspection(vec![
+            call(user, proxy, vec![]),
+            delegatecall(proxy, implementation, vec![0]),
+            call(proxy, a, vec![0, 0]),
+            // a library called by the implementation
+            delegatecall(proxy, library, vec![0, 1]),
+            call(proxy, b, vec![0, 1, 0]),
+            call(proxy, c, vec![0, 1, 1]),
+            call(proxy, d, vec![1]),
+            call(d, e, vec![1, 0]),
+        ]);
+        inspection.fold_delegatecalls();
+
+        let calls = inspection
+            .actions
+            .iter()
+            .map(|action| {
+                let call = action.as_call().unwrap();
+                (call.as_ref().to, call.trace_address.to_vec())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            calls,
+            vec![
+                (proxy, vec![]),
+                (a, vec![0]),
+                (b, vec![1]),
+                (c, vec![2]),
+                (d, vec![3]),
+                (e, vec![3, 0]),
+            ]
+        );
+    }
+
+    #[test]
+    fn primary_protocol_by_trade_count() {
+        let addrs = addrs();
+        let (usr, weth, usdc, unknown_pool) = (addrs[0], addrs[1], addrs[2], addrs[3]);
+        let uni_pool = parse_address("b4e16d0168e52d35cacd2c6185b44281ec28c9dc");
+        let sushi_pool = parse_address("088ee5007c98a9677165d78dd2109ae4a3d04d0c");
+        let trade = |pool| {
+            Trade::new(
+                Transfer {
+                    from: usr,
+                    to: pool,
+                    amount: 1.into(),
+                    token: weth,
+                },
+                Transfer {
+                    from: pool,
+                    to: usr,
+                    amount: 1.into(),
+                    token: usdc,
+                },
+            )
+        };
+        let inspection = |actions: Vec<SpecificAction>| {
+            let mut inspection = mk_inspection(
+                actions
+                    .into_iter()
+                    .map(|action| Classification::new(action, vec![]))
+                    .collect(),
+            );
+            for protocol in &[
+                Protocol::Balancer,
+                Protocol::Uniswap,
+                Protocol::Sushiswap,
+                Protocol::Uniswap,
+            ] {
+                inspection.protocols.insert(*protocol);
+            }
+            inspection
+        };
+
+        let multi = inspection(vec![
+            trade(uni_pool).into(),
+            trade(sushi_pool).into(),
+            trade(unknown_pool).into(),
+            trade(sushi_pool).into(),
+        ]);
+        // duplicates are only kept once, in the order they were found
+        assert_eq!(
+            multi.protocols.iter().collect::<Vec<_>>(),
+            vec![
+                &Protocol::Balancer,
+                &Protocol::Uniswap,
+                &Protocol::Sushiswap
+            ]
+        );
+        assert_eq!(multi.primary_protocol(), Some(Protocol::Sushiswap));
+
+        // the hops of arbitrages are counted
+        let arb = Arbitrage {
+            profit: 1.into(),
+            token: weth,
+            to: usr,
+            capital: 1.into(),
+            trades: vec![trade(uni_pool), trade(uni_pool)],
+            recipient: usr,
+            searcher: Default::default(),
+        };
+        let arb = inspection(vec![trade(sushi_pool).into(), arb.into()]);
+        assert_eq!(arb.primary_protocol(), Some(Protocol::Uniswap));
+
+        let tie = inspection(vec![trade(sushi_pool).into(), trade(uni_pool).into()]);
+        assert_eq!(tie.primary_protocol(), Some(Protocol::Uniswap));
+        let unknown = inspection(vec![trade(unknown_pool).into()]);
+        assert_eq!(unknown.primary_protocol(), Some(Protocol::Balancer));
+        assert_eq!(mk_inspection(vec![]).primary_protocol(), None);
+    }
+
+    #[test]
+    fn nets_the_balances_of_each_account() {
+        let addrs = addrs();
+        let (usr, bot, pool, miner, dai, weth) =
+            (addrs[0], addrs[1], addrs[2], addrs[3], addrs[4], addrs[5]);
+        let transfer = |from, to, amount: u64, token| Transfer {
+            from,
+            to,
+            amount: amount.into(),
+            token,
+        };
+        let inspection = mk_inspection(vec![
+            // tops up the bot with ETH and DAI
+            with_value(mk_call(usr, bot, Bytes::default(), vec![]), 50.into()),
+            Classification::new(transfer(usr, bot, 100, dai), vec![0]),
+            Classification::new(
+                Trade::new(transfer(bot, pool, 100, dai), transfer(pool, bot, 2, weth)),
+                vec![1],
+            ),
+            Classification::new(
+                MinerPayment {
+                    from: bot,
+                    to: miner,
+                    amount: 10.into(),
+                },
+                vec![2],
+            ),
+            // calls without value do not move any ETH
+            mk_call(bot, pool, Bytes::default(), vec![3]),
+            Classification::Prune,
+        ]);
+
+        let deltas = inspection.balance_deltas();
+        let delta = |account, token| deltas.get(&(account, token)).cloned();
+        assert_eq!(delta(usr, *ETH), Some(I256::from(-50)));
+        assert_eq!(delta(usr, dai), Some(I256::from(-100)));
+        assert_eq!(delta(bot, *ETH), Some(I256::from(40)));
+        assert_eq!(delta(bot, dai), Some(I256::zero()));
+        assert_eq!(delta(bot, weth), Some(I256::from(2)));
+        assert_eq!(delta(pool, dai), Some(I256::from(100)));
+        assert_eq!(delta(pool, weth), Some(I256::from(-2)));
+        assert_eq!(delta(miner, *ETH), Some(I256::from(10)));
+        assert_eq!(deltas.len(), 8);
+
+        // an amount too large to be signed would turn into a debit
+        let inspection = mk_inspection(vec![Classification::new(
+            Transfer {
+                amount: U256::MAX,
+                ..transfer(usr, bot, 0, dai)
+            },
+            vec![0],
+        )]);
+        assert!(inspection.balance_deltas().is_empty());
+    }
+
+    #[test]
+    fn arbitrage_profit_is_the_bots_delta() {
+        // https://etherscan.io/tx/0xd9306dc8c1230cc0faef22a8442d0994b8fc9a8f4c9faeab94a9a7eac8e59710
+        let mut inspection =
+            get_trace("0xd9306dc8c1230cc0faef22a8442d0994b8fc9a8f4c9faeab94a9a7eac8e59710");
+        let inspector = BatchInspector::new(
+            vec![Box::new(ERC20::new()), Box::new(UniswapV2::new())],
+            vec![
+                Box::new(TradeReducer::new()),
+                Box::new(ArbitrageReducer::new()),
+            ],
+        );
+        inspector.inspect(&mut inspection);
+        inspector.reduce(&mut inspection);
+        inspection.prune();
+
+        let arb = inspection.arbitrages().next().cloned().unwrap();
+        let deltas = inspection.balance_deltas();
+        assert_eq!(deltas[&(arb.to, arb.token)], I256::from_raw(arb.profit));
+        // the tokens only change hands
+        let mut totals: HashMap<Address, I256> = HashMap::new();
+        for ((_, token), delta) in deltas {
+            *totals.entry(token).or_insert_with(I256::zero) += delta;
+        }
+        assert!(totals.values().all(|total| total.is_zero()));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn round_trips_through_json() {
+        // https://etherscan.io/tx/0x46f4a4d409b44d85e64b1722b8b0f70e9713eb16d2c89da13cffd91486442627
+        let mut inspection =
+            get_trace("0x46f4a4d409b44d85e64b1722b8b0f70e9713eb16d2c89da13cffd91486442627");
+        let inspector = BatchInspector::new(
+            vec![
+                Box::new(ERC20::new()),
+                Box::new(UniswapV2::new()),
+                Box::new(Balancer::new()),
+            ],
+            vec![
+                Box::new(TradeReducer::new()),
+                Box::new(ArbitrageReducer::new()),
+            ],
+        );
+        inspector.inspect(&mut inspection);
+        inspector.reduce(&mut inspection);
+        inspection.actions.push(Classification::Prune);
+        inspection.gas = Some(GasInfo {
+            gas_used: 200_000.into(),
+            gas_price: 1.into(),
+            ..Default::default()
+        });
+        let addrs = addrs();
+        inspection.logs = vec![mk_log(addrs[0], vec![], vec![1, 2, 3])];
+        assert!(!inspection.known().is_empty());
+        assert!(!inspection.unknown().is_empty());
+
+        let json = serde_json::to_value(&inspection).unwrap();
+        // addresses and numbers are hex strings
+        assert_eq!(
+            json["from"],
+            serde_json::Value::String(format!("{:?}", inspection.from))
+        );
+        assert_eq!(json["gas"]["gas_used"], "0x30d40");
+
+        let decoded: Inspection = serde_json::from_value(json).unwrap();
+        assert_eq!(decoded, inspection);
+        assert_eq!(decoded.actions.last(), Some(&Classification::Prune));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn checked_txs_keep_their_status() {
+        let mut inspection = mk_inspection(vec![]);
+        inspection.mark_checked(CheckedReason::InsufficientOutput);
+        assert_eq!(format!("{:?}", inspection.status), "Checked");
+        let mut json = serde_json::to_value(&inspection).unwrap();
+        assert_eq!(json["status"], "Checked");
+        assert_eq!(json["checked_reason"], "InsufficientOutput");
+
+        // the inspections which were serialized before the reason was kept
+        json.as_object_mut().unwrap().remove("checked_reason");
+        let decoded: Inspection = serde_json::from_value(json).unwrap();
+        assert_eq!(decoded.status, Status::Checked);
+        assert_eq!(decoded.checked_reason, None);
+    }
+}
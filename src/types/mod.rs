@@ -5,27 +5,106 @@ pub mod evaluation;
 pub use evaluation::{EvalError, Evaluation};
 
 pub(crate) mod classification;
-pub use classification::Classification;
+pub use classification::{Classification, Provenance, TraceAddress};
 
 pub(crate) mod inspection;
 pub use inspection::Inspection;
 
+pub mod gas;
+pub use gas::GasInfo;
+
+pub mod block;
+pub use block::{Block, BlockPosition, BlockSummary, MevStats, MevType};
+
+pub mod revert;
+pub use revert::RevertReason;
+
+pub mod report;
+pub use report::Report;
+
+#[cfg(feature = "serde")]
+pub mod snapshot;
+#[cfg(feature = "serde")]
+pub use snapshot::{Difference, Snapshot};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
 #[derive(Debug, Clone, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Status {
     /// When a transaction reverts without touching any DeFi protocol
     Reverted,
-    /// When a transaction reverts early but it had touched a DeFi protocol
+    /// When a transaction reverts early but it had touched a DeFi protocol. Why
+    /// is in the inspection's `checked_reason`
     Checked,
     /// When a transaction succeeds
     Success,
+    /// When a transaction has more frames than the inspector's `max_frames`,
+    /// so that it was not inspected
+    TooLarge,
+}
+
+#[derive(Debug, Clone, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// Why a transaction was marked as `Checked`, to tell apart failed MEV attempts
+pub enum CheckedReason {
+    /// An arb which touched protocols but gave up before completing a trade
+    ArbRevertedEarly,
+    /// A trade which reverted because it would have received too little
+    /// output, e.g. Uniswap's "INSUFFICIENT_OUTPUT_AMOUNT"
+    InsufficientOutput,
+    /// A bot which reverted because it could not find a profitable trade
+    NoProfitableTrade,
+    /// A trade of a token for itself, e.g. the bait of a honeypot, which is
+    /// not a real trade
+    SuspiciousTrade,
+    /// The transaction was checked for a reason which could not be told
+    Unknown,
+}
+
+impl CheckedReason {
+    /// Tells why a transaction was checked from the reason it reverted with,
+    /// if that is one of the known failures of trades or bots
+    pub fn from_revert_reason(reason: &RevertReason) -> Option<Self> {
+        let msg = match reason {
+            RevertReason::Error(msg) => msg.to_lowercase(),
+            _ => return None,
+        };
+        if msg.contains("insufficient_output")
+            || msg.contains("insufficient output")
+            || msg.contains("too little received")
+        {
+            Some(CheckedReason::InsufficientOutput)
+        } else if msg.contains("profit") {
+            Some(CheckedReason::NoProfitableTrade)
+        } else {
+            None
+        }
+    }
+
+    /// Tells why a transaction was checked from its revert reason, falling back
+    /// to `default` if the reason is unknown
+    pub fn new(reason: Option<&RevertReason>, default: Self) -> Self {
+        reason.and_then(Self::from_revert_reason).unwrap_or(default)
+    }
+}
+
+impl Status {
+    /// Whether the transaction was marked as `Checked`, for any reason
+    pub fn is_checked(&self) -> bool {
+        matches!(self, Status::Checked)
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialOrd, PartialEq, Eq, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 /// The supported protocols
 pub enum Protocol {
     // Uniswap & Forks
     UniswapV1,
     Uniswap,
+    UniswapV3,
     Uniswappy,
     Sushiswap,
     SakeSwap,
@@ -33,13 +112,26 @@ pub enum Protocol {
     // Other AMMs
     Curve,
     Balancer,
+    Kyber,
+    Bancor,
+    Synthetix,
 
     // Lending / Liquidations
     Aave,
     Compound,
+    CompoundV3,
+    Cream,
+    IronBank,
+    MakerDAO,
 
     // Aggregators
     ZeroEx,
+    OneInch,
+
+    // NFT Marketplaces
+    Seaport,
+    LooksRare,
+    X2Y2,
 
     // Misc.
     Flashloan,
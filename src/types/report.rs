@@ -0,0 +1,283 @@
+use crate::{
+    addresses::lookup,
+    prices::{net_profit, signed, usd_value, PriceOracle},
+    tokens::{cached_token_info, TokenInfo},
+    types::{actions::SpecificAction, Inspection, Protocol, RevertReason, Status},
+};
+use ethers::types::{Address, TxHash, I256, U256};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt};
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// A summary of an inspection, which prints on one screen with `Display`.
+///
+/// The profit is the one of the arbitrages and liquidations of the tx, net of
+/// its flash loan fees, as in `Inspection::net_profit_eth`. It is always given
+/// in each token, and in ETH and USD if the report was made with a price
+/// oracle which can price all its tokens.
+pub struct Report {
+    pub hash: TxHash,
+    pub block_number: u64,
+    pub status: Status,
+    pub revert_reason: Option<RevertReason>,
+    /// The sender of the tx
+    pub from: Address,
+    /// The contract which it called
+    pub contract: Address,
+    /// The protocols which the tx touched, in the order they were found
+    pub protocols: Vec<Protocol>,
+    /// The number of known actions of each kind
+    pub actions: BTreeMap<String, usize>,
+    /// The gross profit in each token, in its base units
+    pub profits: Vec<(Address, I256)>,
+    /// What was paid for gas, in wei, if the gas is known
+    pub gas_cost: Option<U256>,
+    /// The ETH paid directly to the miner
+    pub coinbase_payment: U256,
+    /// The gross profit in ETH, if it could be priced
+    pub gross_profit_eth: Option<I256>,
+    /// The profit in ETH net of the gas and of the payments to the miner, if
+    /// the gas is known and the profit could be priced
+    pub net_profit_eth: Option<I256>,
+    /// The net profit in USD, if USDC could be priced as well
+    pub net_profit_usd: Option<f64>,
+}
+
+impl Report {
+    /// Summarizes the inspection, with its profit in raw token amounts
+    pub fn new(inspection: &Inspection) -> Self {
+        let mut actions = BTreeMap::new();
+        let mut profits: BTreeMap<Address, I256> = BTreeMap::new();
+        for action in inspection.known_actions() {
+            *actions.entry(action.kind().to_owned()).or_insert(0) += 1;
+            for (token, amount) in profit(action) {
+                let total = profits.entry(token).or_insert_with(I256::zero);
+                *total = total.saturating_add(amount);
+            }
+        }
+
+        Self {
+            hash: inspection.hash,
+            block_number: inspection.block_number,
+            status: inspection.status.clone(),
+            revert_reason: inspection.revert_reason.clone(),
+            from: inspection.from,
+            contract: inspection.contract,
+            protocols: inspection.protocols.iter().cloned().collect(),
+            actions,
+            profits: profits.into_iter().collect(),
+            gas_cost: inspection.gas.as_ref().map(|gas| gas.cost()),
+            coinbase_payment: inspection.coinbase_payment(),
+            gross_profit_eth: None,
+            net_profit_eth: None,
+            net_profit_usd: None,
+        }
+    }
+
+    /// Summarizes the inspection, with its profit priced in ETH and USD by
+    /// `oracle` at the inspection's block
+    pub fn with_prices<O: PriceOracle>(inspection: &Inspection, oracle: &O) -> Self {
+        let mut report = Self::new(inspection);
+        let block = inspection.block_number;
+        report.gross_profit_eth = net_profit(oracle, report.profits.iter().cloned(), block);
+        report.net_profit_eth = inspection.net_profit_eth(oracle);
+        report.net_profit_usd = report
+            .net_profit_eth
+            .and_then(|net| usd_value(oracle, net, block));
+        report
+    }
+}
+
+/// The deltas of the action towards the profit of its tx, which are left out
+/// if an amount is too large to be signed
+fn profit(action: &SpecificAction) -> Vec<(Address, I256)> {
+    let deltas = match action {
+        SpecificAction::Arbitrage(arb) => {
+            signed(arb.profit).map(|profit| vec![(arb.token, profit)])
+        }
+        SpecificAction::Liquidation(liq) => signed(liq.received_amount)
+            .zip(signed(liq.sent_amount))
+            .map(|(received, sent)| vec![(liq.received_token, received), (liq.sent_token, -sent)]),
+        SpecificAction::ProfitableLiquidation(liq) => {
+            signed(liq.profit).map(|profit| vec![(liq.token, profit)])
+        }
+        SpecificAction::FlashLoan(loan) => signed(loan.fee).map(|fee| vec![(loan.token, -fee)]),
+        _ => None,
+    };
+    deltas.unwrap_or_default()
+}
+
+/// Formats a signed amount of `token` in its units if it is known
+fn amount(token: Address, amount: I256) -> String {
+    let sign = if amount.is_negative() { "-" } else { "" };
+    let raw = amount.abs().into_raw();
+    match cached_token_info(token) {
+        Some(info) => format!("{}{}", sign, info.format(raw)),
+        None => format!("{}{} {}", sign, raw, lookup(token)),
+    }
+}
+
+/// Formats an amount of wei in ETH
+fn eth(wei: I256) -> String {
+    let sign = if wei.is_negative() { "-" } else { "" };
+    format!(
+        "{}{}",
+        sign,
+        TokenInfo::new("ETH", 18).format(wei.abs().into_raw())
+    )
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "tx {:?} (block {})", self.hash, self.block_number)?;
+        write!(f, "status: {:?}", self.status)?;
+        match self.revert_reason {
+            Some(ref reason) => writeln!(f, " ({})", reason)?,
+            None => writeln!(f)?,
+        }
+        writeln!(
+            f,
+            "from: {}, contract: {}",
+            lookup(self.from),
+            lookup(self.contract)
+        )?;
+
+        let protocols = self
+            .protocols
+            .iter()
+            .map(|protocol| format!("{:?}", protocol))
+            .collect::<Vec<_>>();
+        writeln!(f, "protocols: {}", list(protocols))?;
+        let actions = self
+            .actions
+            .iter()
+            .map(|(kind, count)| format!("{} x{}", kind, count))
+            .collect::<Vec<_>>();
+        writeln!(f, "actions: {}", list(actions))?;
+
+        let profits = self
+            .profits
+            .iter()
+            .map(|(token, profit)| amount(*token, *profit))
+            .collect::<Vec<_>>();
+        write!(f, "gross profit: {}", list(profits))?;
+        match self.gross_profit_eth {
+            Some(gross) => writeln!(f, " ({})", eth(gross))?,
+            None => writeln!(f)?,
+        }
+        match self.gas_cost {
+            Some(cost) => write!(f, "gas cost: {}", eth(I256::from_raw(cost)))?,
+            None => write!(f, "gas cost: unknown")?,
+        }
+        if self.coinbase_payment.is_zero() {
+            writeln!(f)?;
+        } else {
+            writeln!(
+                f,
+                ", paid to the miner: {}",
+                eth(I256::from_raw(self.coinbase_payment))
+            )?;
+        }
+        match (self.net_profit_eth, self.net_profit_usd) {
+            (Some(net), Some(usd)) => write!(f, "net profit: {} (${:.2})", eth(net), usd),
+            (Some(net), None) => write!(f, "net profit: {}", eth(net)),
+            _ => write!(f, "net profit: unpriced"),
+        }
+    }
+}
+
+fn list(items: Vec<String>) -> String {
+    if items.is_empty() {
+        "none".to_owned()
+    } else {
+        items.join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        addresses::WETH,
+        prices::UniswapV2Oracle,
+        test_helpers::*,
+        types::{
+            actions::{Arbitrage, FlashLoan, Trade, Transfer},
+            Classification, GasInfo,
+        },
+    };
+
+    #[test]
+    fn summarizes_an_arbitrage() {
+        let addrs = addrs();
+        let (bot, pool) = (addrs[0], addrs[1]);
+        let gwei = U256::exp10(9);
+        let trade = Trade::new(
+            Transfer::new(bot, pool, gwei, *WETH),
+            Transfer::new(pool, bot, gwei * 2, *WETH),
+        );
+        let arb = Arbitrage {
+            profit: gwei * 200_000,
+            token: *WETH,
+            to: bot,
+            capital: 0.into(),
+            trades: vec![trade.clone(), trade],
+            recipient: bot,
+            searcher: Default::default(),
+        };
+        let loan = FlashLoan {
+            provider: Protocol::Aave,
+            token: *WETH,
+            amount: gwei,
+            fee: gwei * 50_000,
+        };
+        let mut inspection = mk_inspection(vec![
+            Classification::new(loan, vec![0]),
+            Classification::new(arb, vec![1]),
+        ]);
+        inspection.protocols.insert(Protocol::Aave);
+        inspection.protocols.insert(Protocol::Uniswap);
+        inspection.gas = Some(GasInfo {
+            gas_used: 100_000.into(),
+            gas_price: gwei,
+            ..Default::default()
+        });
+
+        let report = inspection.report();
+        assert_eq!(report.protocols, vec![Protocol::Aave, Protocol::Uniswap]);
+        assert_eq!(
+            report.actions.into_iter().collect::<Vec<_>>(),
+            vec![("Arbitrage".to_owned(), 1), ("FlashLoan".to_owned(), 1)]
+        );
+        assert_eq!(
+            report.profits,
+            vec![(*WETH, I256::from_raw(gwei * 150_000))]
+        );
+        assert_eq!(report.gas_cost, Some(gwei * 100_000));
+        assert_eq!(report.net_profit_eth, None);
+
+        // WETH is always priced, but not USDC
+        let report = inspection.priced_report(&UniswapV2Oracle::new());
+        assert_eq!(
+            report.gross_profit_eth,
+            Some(I256::from_raw(gwei * 150_000))
+        );
+        assert_eq!(report.net_profit_eth, Some(I256::from_raw(gwei * 50_000)));
+        assert_eq!(report.net_profit_usd, None);
+        let printed = report.to_string();
+        assert!(printed.contains("status: Success\n"));
+        assert!(printed.contains("protocols: Aave, Uniswap\n"));
+        assert!(printed.contains("actions: Arbitrage x1, FlashLoan x1\n"));
+        assert!(printed.contains("gross profit: 0.00015 WETH (0.00015 ETH)\n"));
+        assert!(printed.contains("gas cost: 0.0001 ETH\n"));
+        assert!(printed.ends_with("net profit: 0.00005 ETH"));
+
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_string(&report).unwrap();
+            assert_eq!(serde_json::from_str::<Report>(&json).unwrap(), report);
+        }
+    }
+}
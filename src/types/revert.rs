@@ -0,0 +1,121 @@
+use ethers::{
+    abi::{self, ParamType, Token},
+    types::{Bytes, U256},
+};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// The selector of `Error(string)`, used by `require` and `revert("...")`
+const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+
+/// The selector of `Panic(uint256)`, used by failed asserts and arithmetic
+/// errors since Solidity 0.8
+const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// Why a call reverted, decoded from its output
+pub enum RevertReason {
+    /// A `require` or `revert` with a message, e.g. "UniswapV2Router: INSUFFICIENT_OUTPUT_AMOUNT"
+    Error(String),
+    /// A Solidity panic, with its error code
+    Panic(U256),
+    /// A revert without any data, e.g. running out of gas or a bare `revert()`
+    Empty,
+    /// A custom error, or any other output which could not be decoded
+    Custom(Bytes),
+}
+
+impl RevertReason {
+    /// Decodes the output of a reverted call
+    pub fn decode(output: &[u8]) -> Self {
+        if output.is_empty() {
+            return RevertReason::Empty;
+        }
+
+        let (selector, data) = output.split_at(std::cmp::min(4, output.len()));
+        let decoded = if selector == ERROR_SELECTOR {
+            abi::decode(&[ParamType::String], data)
+        } else if selector == PANIC_SELECTOR {
+            abi::decode(&[ParamType::Uint(256)], data)
+        } else {
+            return RevertReason::Custom(output.to_vec().into());
+        };
+
+        match decoded.as_deref() {
+            Ok([Token::String(msg)]) => RevertReason::Error(msg.clone()),
+            Ok([Token::Uint(code)]) => RevertReason::Panic(*code),
+            _ => RevertReason::Custom(output.to_vec().into()),
+        }
+    }
+}
+
+impl fmt::Display for RevertReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RevertReason::Error(msg) => write!(f, "{}", msg),
+            RevertReason::Panic(code) => {
+                let reason = match code.low_u64() {
+                    0x01 => "assertion failed",
+                    0x11 => "arithmetic overflow or underflow",
+                    0x12 => "division by zero",
+                    0x21 => "invalid enum value",
+                    0x31 => "pop on an empty array",
+                    0x32 => "array index out of bounds",
+                    0x41 => "out of memory",
+                    0x51 => "call to an uninitialized function",
+                    _ => "unknown panic",
+                };
+                write!(f, "panic {:#x}: {}", code, reason)
+            }
+            RevertReason::Empty => write!(f, "reverted without a reason"),
+            RevertReason::Custom(data) => write!(f, "custom error 0x{}", hex::encode(data)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_revert_reasons() {
+        let mut output = ERROR_SELECTOR.to_vec();
+        output.extend(abi::encode(&[Token::String(
+            "UniswapV2Router: INSUFFICIENT_OUTPUT_AMOUNT".to_owned(),
+        )]));
+        let reason = RevertReason::decode(&output);
+        assert_eq!(
+            reason,
+            RevertReason::Error("UniswapV2Router: INSUFFICIENT_OUTPUT_AMOUNT".to_owned())
+        );
+        assert_eq!(
+            reason.to_string(),
+            "UniswapV2Router: INSUFFICIENT_OUTPUT_AMOUNT"
+        );
+
+        let mut output = PANIC_SELECTOR.to_vec();
+        output.extend(abi::encode(&[Token::Uint(0x11.into())]));
+        let reason = RevertReason::decode(&output);
+        assert_eq!(reason, RevertReason::Panic(0x11.into()));
+        assert_eq!(
+            reason.to_string(),
+            "panic 0x11: arithmetic overflow or underflow"
+        );
+
+        assert_eq!(RevertReason::decode(&[]), RevertReason::Empty);
+
+        // custom errors and truncated messages are kept as they are
+        let custom = [0xde, 0xad, 0xbe, 0xef, 0x01];
+        assert_eq!(
+            RevertReason::decode(&custom),
+            RevertReason::Custom(custom.to_vec().into())
+        );
+        let truncated = [&ERROR_SELECTOR[..], &[0; 16]].concat();
+        assert_eq!(
+            RevertReason::decode(&truncated),
+            RevertReason::Custom(truncated.into())
+        );
+    }
+}
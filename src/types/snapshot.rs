@@ -0,0 +1,185 @@
+use crate::types::{classification::ActionTrace, Inspection, Protocol, Status};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// What the inspectors and the reducers made of a transaction, in a stable
+/// serialized form, so that expected classifications can be checked in and
+/// compared against. The fields which do not depend on the classification,
+/// e.g. the gas and the logs, are left out.
+pub struct Snapshot {
+    pub status: Status,
+    /// The protocols which the tx touched, sorted rather than in the order they
+    /// were found, which depends on the order the inspectors ran in
+    pub protocols: Vec<Protocol>,
+    /// The known actions, in the order of their calls
+    pub actions: Vec<ActionTrace>,
+}
+
+impl Snapshot {
+    /// Takes the snapshot of the inspection's classified actions
+    pub fn new(inspection: &Inspection) -> Self {
+        let mut protocols = inspection.protocols.iter().cloned().collect::<Vec<_>>();
+        protocols.sort();
+        Self {
+            status: inspection.status.clone(),
+            protocols,
+            actions: inspection.known(),
+        }
+    }
+
+    /// The snapshot as JSON, in which the fields of the objects are sorted
+    pub fn to_json(&self) -> Value {
+        serde_json::to_value(self).expect("snapshots always serialize")
+    }
+
+    /// The differences from the `expected` snapshot to this one
+    pub fn diff(&self, expected: &Snapshot) -> Vec<Difference> {
+        diff(&expected.to_json(), &self.to_json())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A value which differs between 2 serialized snapshots
+pub struct Difference {
+    /// Where the value is, e.g. `actions[1].action.Arbitrage.profit`
+    pub path: String,
+    /// The expected value, if it was there
+    pub expected: Option<Value>,
+    /// The actual value, if it was there
+    pub actual: Option<Value>,
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |value: &Option<Value>| match value {
+            Some(value) => value.to_string(),
+            None => "nothing".to_owned(),
+        };
+        write!(
+            f,
+            "{}: expected {}, got {}",
+            self.path,
+            show(&self.expected),
+            show(&self.actual)
+        )
+    }
+}
+
+/// The differences from `expected` to `actual`, down to the values which
+/// differ so that a single changed amount does not show as a whole new action
+pub fn diff(expected: &Value, actual: &Value) -> Vec<Difference> {
+    let mut differences = Vec::new();
+    diff_at(String::new(), expected, actual, &mut differences);
+    differences
+}
+
+fn diff_at(path: String, expected: &Value, actual: &Value, differences: &mut Vec<Difference>) {
+    let field = |key: &str| {
+        if path.is_empty() {
+            key.to_owned()
+        } else {
+            format!("{}.{}", path, key)
+        }
+    };
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => {
+            for (key, value) in expected {
+                match actual.get(key) {
+                    Some(other) => diff_at(field(key), value, other, differences),
+                    None => differences.push(Difference {
+                        path: field(key),
+                        expected: Some(value.clone()),
+                        actual: None,
+                    }),
+                }
+            }
+            for (key, value) in actual {
+                if !expected.contains_key(key) {
+                    differences.push(Difference {
+                        path: field(key),
+                        expected: None,
+                        actual: Some(value.clone()),
+                    });
+                }
+            }
+        }
+        (Value::Array(expected), Value::Array(actual)) => {
+            for i in 0..std::cmp::max(expected.len(), actual.len()) {
+                let path = format!("{}[{}]", path, i);
+                match (expected.get(i), actual.get(i)) {
+                    (Some(value), Some(other)) => diff_at(path, value, other, differences),
+                    (value, other) => differences.push(Difference {
+                        path,
+                        expected: value.cloned(),
+                        actual: other.cloned(),
+                    }),
+                }
+            }
+        }
+        _ if expected != actual => differences.push(Difference {
+            path,
+            expected: Some(expected.clone()),
+            actual: Some(actual.clone()),
+        }),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_helpers::{addrs, mk_inspection},
+        types::{actions::Transfer, Classification},
+    };
+    use ethers::types::TxHash;
+    use serde_json::json;
+
+    #[test]
+    fn diffs_down_to_the_values() {
+        let expected = json!({
+            "status": "Success",
+            "actions": [{"Trade": {"amount": "0x1"}}, {"Transfer": {}}],
+        });
+        let actual = json!({
+            "status": "Success",
+            "actions": [{"Trade": {"amount": "0x2"}}],
+            "protocols": [],
+        });
+        let differences = diff(&expected, &actual)
+            .iter()
+            .map(|d| d.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            differences,
+            vec![
+                r#"actions[0].Trade.amount: expected "0x1", got "0x2""#,
+                r#"actions[1]: expected {"Transfer":{}}, got nothing"#,
+                "protocols: expected nothing, got []",
+            ]
+        );
+        assert!(diff(&expected, &expected).is_empty());
+    }
+
+    #[test]
+    fn ignores_what_does_not_depend_on_the_classification() {
+        let addrs = addrs();
+        let transfer = |amount: u64| Transfer::new(addrs[0], addrs[1], amount.into(), addrs[2]);
+        let expected = mk_inspection(vec![Classification::new(transfer(1), vec![0])]);
+
+        let mut inspection = expected.clone();
+        inspection.hash = TxHash::repeat_byte(1);
+        inspection.block_number = 1;
+        assert!(inspection.diff(&expected).is_empty());
+
+        inspection.actions[0] = Classification::new(transfer(2), vec![0]);
+        let paths = inspection
+            .diff(&expected)
+            .into_iter()
+            .map(|difference| difference.path)
+            .collect::<Vec<_>>();
+        assert_eq!(paths, vec!["actions[0].action.Transfer.amount"]);
+    }
+}